@@ -0,0 +1,69 @@
+use std::hint::black_box;
+
+use axum::http::HeaderMap;
+use criterion::{Criterion, criterion_group, criterion_main};
+use fastrand::Rng;
+use hmac::{Hmac, Mac};
+use robochick_rs::{bench, config::AppConfig};
+use sha2::Sha256;
+
+const REDEMPTION: &str = include_str!("../resources/tests/reward_redemption_event.json");
+const CONFIG: &str = include_str!("../resources/config/message_components.json");
+
+/// Headers Twitch would send with `payload`, signed with the subscription secret.
+fn signed_headers(payload: &str, config: &AppConfig) -> HeaderMap {
+    let (message_id, timestamp) = (
+        "e76c6bd4-55c9-4987-8304-da1588d8988b",
+        "2026-10-16T12:00:00Z",
+    );
+    let mut hmac =
+        Hmac::<Sha256>::new_from_slice(config.twitch_eventsub_subscription_secret.as_bytes())
+            .unwrap();
+    hmac.update(format!("{message_id}{timestamp}{payload}").as_bytes());
+    let signature = format!("sha256={}", hex::encode(hmac.finalize().into_bytes()));
+
+    let mut headers = HeaderMap::new();
+    headers.insert("Twitch-Eventsub-Message-Id", message_id.parse().unwrap());
+    headers.insert(
+        "Twitch-Eventsub-Message-Timestamp",
+        timestamp.parse().unwrap(),
+    );
+    headers.insert(
+        "Twitch-Eventsub-Message-Signature",
+        signature.parse().unwrap(),
+    );
+    headers
+}
+
+fn verify_signature(c: &mut Criterion) {
+    dotenvy::from_filename(".env.test").unwrap();
+    let config = AppConfig::from_env();
+    let headers = signed_headers(REDEMPTION, &config);
+
+    c.bench_function("verify_signature", |b| {
+        b.iter(|| {
+            bench::verify_signature(black_box(REDEMPTION.as_bytes()), &headers, &config).unwrap()
+        })
+    });
+}
+
+fn parse_redemption(c: &mut Criterion) {
+    c.bench_function("parse_redemption", |b| {
+        b.iter(|| bench::parse_redemption(black_box(REDEMPTION)).unwrap())
+    });
+}
+
+fn build_message(c: &mut Criterion) {
+    let components = bench::parse_config(CONFIG).unwrap();
+    let mut rng = Rng::with_seed(7);
+
+    c.bench_function("parse_config", |b| {
+        b.iter(|| bench::parse_config(black_box(CONFIG)).unwrap())
+    });
+    c.bench_function("build_message", |b| {
+        b.iter(|| bench::build_message(&components, &mut rng).unwrap())
+    });
+}
+
+criterion_group!(hot_path, verify_signature, parse_redemption, build_message);
+criterion_main!(hot_path);
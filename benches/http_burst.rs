@@ -0,0 +1,45 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use robochick_rs::{client::http_client, config::AppConfig};
+
+/// Requests sent at once, like a raid of redemptions.
+const BURST: usize = 20;
+
+async fn burst(client: &reqwest::Client, url: &str) {
+    let mut requests = tokio::task::JoinSet::new();
+    for _ in 0..BURST {
+        let request = client.get(url).send();
+        requests.spawn(async move { request.await.unwrap() });
+    }
+    requests.join_all().await;
+}
+
+/// A burst of requests to a local server, with the connection pool kept warm between bursts
+/// and with pooling turned off, which opens a new connection for every request.
+fn http_burst(c: &mut Criterion) {
+    dotenvy::from_filename(".env.test").unwrap();
+    let config = AppConfig::from_env();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut server = runtime.block_on(mockito::Server::new_async());
+    let _mock = server
+        .mock("GET", "/")
+        .with_body("{}")
+        .expect_at_least(1)
+        .create();
+    let url = server.url();
+
+    let pooled = http_client(&config);
+    let unpooled = http_client(&AppConfig {
+        http_pool_max_idle_per_host: Some(0),
+        ..config.clone()
+    });
+
+    c.bench_function("http_burst_pooled", |b| {
+        b.iter(|| runtime.block_on(burst(&pooled, &url)))
+    });
+    c.bench_function("http_burst_unpooled", |b| {
+        b.iter(|| runtime.block_on(burst(&unpooled, &url)))
+    });
+}
+
+criterion_group!(benches, http_burst);
+criterion_main!(benches);
@@ -0,0 +1,4 @@
+/// Records the version, commit, build time and features for `/version` and the startup log.
+fn main() {
+    built::write_built_file().expect("Failed to acquire build-time information");
+}
@@ -0,0 +1,569 @@
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+#[cfg(feature = "long-delays")]
+use aws_sdk_scheduler::types::{
+    ActionAfterCompletion, FlexibleTimeWindow, FlexibleTimeWindowMode, Target,
+};
+use chrono::{TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audit,
+    client::{HelixCaller, StreamelementsCaller},
+    config::AppConfig,
+    robochick::twitch::FollowUpMessage,
+    trace,
+    types::twitch::SubscriptionRequest,
+};
+
+/// SQS refuses delays longer than 15 minutes.
+#[cfg(feature = "action-queue")]
+const MAX_SQS_DELAY_SECS: u64 = 900;
+
+/// Work handed off to run later, either on the action queue or a background task.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QueuedAction {
+    FollowUp(FollowUpMessage),
+    ResolvePoll(PollResolution),
+    SetRewardPaused(RewardPause),
+    /// Recreates an EventSub subscription Twitch revoked.
+    Resubscribe(SubscriptionRequest),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RewardPause {
+    pub reward_id: String,
+    pub paused: bool,
+}
+
+/// Everything needed to announce the result of a chat vote once its poll has closed. The
+/// outcomes are rendered up front so no other state has to be kept around.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PollResolution {
+    pub poll_id: String,
+    pub outcomes: Vec<PollOutcome>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PollOutcome {
+    pub choice: String,
+    pub message: String,
+}
+
+#[async_trait]
+pub trait ActionScheduler: Send + Sync {
+    async fn schedule(
+        &self,
+        action: QueuedAction,
+        delay_secs: u64,
+        config: &AppConfig,
+    ) -> Result<()>;
+}
+
+/// Runs a queued action now.
+pub async fn execute<C: StreamelementsCaller + HelixCaller>(
+    action: QueuedAction,
+    client: &C,
+    config: &AppConfig,
+) -> Result<()> {
+    let message = match action {
+        QueuedAction::FollowUp(follow_up) => follow_up.message,
+        QueuedAction::ResolvePoll(resolution) => {
+            let results = client.get_poll_results(&resolution.poll_id, config).await?;
+            match pick_poll_outcome(&resolution, &results)? {
+                Some(message) => message,
+                None => {
+                    println!(
+                        "Nobody voted in poll {}, not announcing an outcome",
+                        resolution.poll_id
+                    );
+                    return Ok(());
+                }
+            }
+        }
+        QueuedAction::SetRewardPaused(pause) => {
+            return client
+                .set_reward_paused(&pause.reward_id, pause.paused, config)
+                .await;
+        }
+        QueuedAction::Resubscribe(request) => {
+            return client.create_eventsub_subscription(&request, config).await;
+        }
+    };
+
+    client.say(&message, config).await.map(|_| ())
+}
+
+/// Runs actions received from the action queue as `(message id, body)` pairs. Returns the ids
+/// of messages that failed and should be retried. Messages that can't be parsed are dropped,
+/// since retrying them won't help.
+pub async fn run_queued<C: StreamelementsCaller + HelixCaller>(
+    messages: &[(String, String)],
+    client: &C,
+    config: &AppConfig,
+) -> Vec<String> {
+    let mut failed = vec![];
+    for (id, body) in messages {
+        let action: QueuedAction = match serde_json::from_str(body) {
+            Ok(a) => a,
+            Err(e) => {
+                println!("Dropping unreadable queued action {id}: {e}");
+                continue;
+            }
+        };
+
+        let run = audit::with_event_id(id.clone(), execute(action, client, config));
+        match trace::with_correlation_id(id.clone(), run).await {
+            Ok(_) => println!("Successfully ran queued action {id}"),
+            Err(e) => {
+                println!("Queued action {id} failed: {e}");
+                failed.push(id.clone());
+            }
+        }
+    }
+
+    failed
+}
+
+/// Finds the outcome for the choice with the most votes. Ties go to the choice listed first.
+/// Returns `None` when nobody voted, as no choice won then.
+fn pick_poll_outcome(
+    resolution: &PollResolution,
+    results: &[(String, u64)],
+) -> Result<Option<String>> {
+    let mut winner: Option<&(String, u64)> = None;
+    for result in results {
+        if winner.is_none_or(|w| result.1 > w.1) {
+            winner = Some(result);
+        }
+    }
+
+    let (choice, votes) = winner.ok_or(anyhow!("Poll {} has no choices", resolution.poll_id))?;
+    if *votes == 0 {
+        return Ok(None);
+    }
+    resolution
+        .outcomes
+        .iter()
+        .find(|o| &o.choice == choice)
+        .map(|o| Some(o.message.clone()))
+        .ok_or(anyhow!("No outcome configured for poll choice {choice}"))
+}
+
+/// Sends actions to the action queue with a delivery delay. Used on Lambda, where nothing
+/// keeps running once the response has been returned.
+#[cfg(feature = "action-queue")]
+pub struct SqsScheduler {
+    pub client: aws_sdk_sqs::Client,
+    pub queue_url: String,
+}
+
+#[cfg(feature = "action-queue")]
+#[async_trait]
+impl ActionScheduler for SqsScheduler {
+    async fn schedule(
+        &self,
+        action: QueuedAction,
+        delay_secs: u64,
+        config: &AppConfig,
+    ) -> Result<()> {
+        // Sending it early would run it at the wrong time, so longer delays have to go
+        // through the `LongDelayRouter`.
+        if delay_secs > MAX_SQS_DELAY_SECS {
+            return Err(anyhow!(
+                "Action delay of {delay_secs}s is longer than SQS allows ({MAX_SQS_DELAY_SECS}s), \
+                 set ACTION_QUEUE_ARN and SCHEDULER_ROLE_ARN to schedule it"
+            ));
+        }
+
+        let delay = delay_secs as i32;
+        let body = serde_json::to_string(&action)?;
+
+        match self
+            .client
+            .send_message()
+            .queue_url(&self.queue_url)
+            .message_body(body)
+            .delay_seconds(delay)
+            .send()
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow!("Failed to queue action: {e}")),
+        }
+    }
+}
+
+/// Creates a one-off EventBridge Scheduler schedule that drops the action on the action queue
+/// when it's due, for delays longer than SQS allows. The schedule deletes itself once it has
+/// run.
+#[cfg(feature = "long-delays")]
+pub struct EventBridgeScheduler {
+    pub client: aws_sdk_scheduler::Client,
+    pub queue_arn: String,
+    pub role_arn: String,
+    pub group_name: Option<String>,
+}
+
+#[cfg(feature = "long-delays")]
+#[async_trait]
+impl ActionScheduler for EventBridgeScheduler {
+    async fn schedule(
+        &self,
+        action: QueuedAction,
+        delay_secs: u64,
+        config: &AppConfig,
+    ) -> Result<()> {
+        let due = Utc::now() + TimeDelta::seconds(delay_secs as i64);
+        let name = format!("robochick-{}-{:08x}", due.timestamp(), fastrand::u32(..));
+
+        let target = Target::builder()
+            .arn(&self.queue_arn)
+            .role_arn(&self.role_arn)
+            .input(serde_json::to_string(&action)?)
+            .build()?;
+        let window = FlexibleTimeWindow::builder()
+            .mode(FlexibleTimeWindowMode::Off)
+            .build()?;
+
+        match self
+            .client
+            .create_schedule()
+            .name(&name)
+            .set_group_name(self.group_name.clone())
+            .schedule_expression(format!("at({})", due.format("%Y-%m-%dT%H:%M:%S")))
+            .schedule_expression_timezone("UTC")
+            .flexible_time_window(window)
+            .action_after_completion(ActionAfterCompletion::Delete)
+            .target(target)
+            .send()
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow!("Failed to schedule action: {e}")),
+        }
+    }
+}
+
+/// Sends actions straight to the queue when SQS can hold them for long enough, and through
+/// EventBridge Scheduler otherwise.
+#[cfg(feature = "long-delays")]
+pub struct LongDelayRouter {
+    pub queue: SqsScheduler,
+    pub scheduler: EventBridgeScheduler,
+}
+
+#[cfg(feature = "long-delays")]
+#[async_trait]
+impl ActionScheduler for LongDelayRouter {
+    async fn schedule(
+        &self,
+        action: QueuedAction,
+        delay_secs: u64,
+        config: &AppConfig,
+    ) -> Result<()> {
+        if delay_secs <= MAX_SQS_DELAY_SECS {
+            self.queue.schedule(action, delay_secs, config).await
+        } else {
+            self.scheduler.schedule(action, delay_secs, config).await
+        }
+    }
+}
+
+/// Sleeps on a background task before running the action. Only suitable for the long-lived
+/// standalone server.
+pub struct TokioScheduler<C: StreamelementsCaller + HelixCaller + Clone + 'static> {
+    pub client: C,
+}
+
+#[async_trait]
+impl<C: StreamelementsCaller + HelixCaller + Clone + 'static> ActionScheduler
+    for TokioScheduler<C>
+{
+    async fn schedule(
+        &self,
+        action: QueuedAction,
+        delay_secs: u64,
+        config: &AppConfig,
+    ) -> Result<()> {
+        let client = self.client.clone();
+        let config = config.clone();
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(delay_secs)).await;
+            match execute(action, &client, &config).await {
+                Ok(_) => println!("Successfully ran delayed action"),
+                Err(e) => println!("Delayed action failed: {e}"),
+            }
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use anyhow::Result;
+    #[cfg(feature = "long-delays")]
+    use aws_sdk_scheduler::{
+        operation::create_schedule::CreateScheduleOutput, types::ActionAfterCompletion,
+    };
+    #[cfg(feature = "action-queue")]
+    use aws_sdk_sqs::{Client, operation::send_message::SendMessageOutput};
+    use aws_smithy_mocks::{Rule, mock, mock_client};
+    use mockito::Server;
+
+    use crate::{
+        action::{
+            ActionScheduler, PollOutcome, PollResolution, QueuedAction, RewardPause,
+            TokioScheduler, pick_poll_outcome, run_queued,
+        },
+        client::WebClient,
+        config::AppConfig,
+        robochick::twitch::FollowUpMessage,
+    };
+
+    fn resolution() -> PollResolution {
+        PollResolution {
+            poll_id: "poll-1".into(),
+            outcomes: vec![
+                PollOutcome {
+                    choice: "Eat it".into(),
+                    message: "John ate the cracker.".into(),
+                },
+                PollOutcome {
+                    choice: "Share it".into(),
+                    message: "John shared the cracker with Jane.".into(),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn pick_poll_outcome_returns_message_for_most_voted_choice() -> Result<()> {
+        let results = vec![("Eat it".to_string(), 3), ("Share it".to_string(), 7)];
+
+        let message = pick_poll_outcome(&resolution(), &results)?;
+
+        assert_eq!(
+            message.as_deref(),
+            Some("John shared the cracker with Jane.")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn pick_poll_outcome_breaks_ties_with_the_first_choice() -> Result<()> {
+        let results = vec![("Eat it".to_string(), 4), ("Share it".to_string(), 4)];
+
+        let message = pick_poll_outcome(&resolution(), &results)?;
+
+        assert_eq!(message.as_deref(), Some("John ate the cracker."));
+        Ok(())
+    }
+
+    #[test]
+    fn pick_poll_outcome_has_no_winner_without_votes() -> Result<()> {
+        let results = vec![("Eat it".to_string(), 0), ("Share it".to_string(), 0)];
+
+        assert_eq!(pick_poll_outcome(&resolution(), &results)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn pick_poll_outcome_returns_err_for_unknown_choice() {
+        let results = vec![("Throw it".to_string(), 5)];
+
+        assert!(pick_poll_outcome(&resolution(), &results).is_err());
+    }
+
+    #[cfg(feature = "action-queue")]
+    #[tokio::test]
+    async fn sqs_scheduler_queues_action_with_its_delay() -> Result<()> {
+        use crate::action::SqsScheduler;
+
+        dotenvy::from_filename(".env.test")?;
+        let config = AppConfig::from_env();
+
+        let action = QueuedAction::FollowUp(FollowUpMessage {
+            message: "...and the chicken chose John.".into(),
+            delay_secs: 900,
+        });
+        let expected_body = serde_json::to_string(&action)?;
+
+        let send_rule: Rule = mock!(Client::send_message)
+            .match_requests(move |r| {
+                r.queue_url() == Some("https://sqs.test/actions")
+                    && r.delay_seconds() == Some(900)
+                    && r.message_body() == Some(expected_body.as_str())
+            })
+            .then_output(|| SendMessageOutput::builder().build());
+
+        let scheduler = SqsScheduler {
+            client: mock_client!(aws_sdk_sqs, [&send_rule]),
+            queue_url: "https://sqs.test/actions".into(),
+        };
+
+        let result = scheduler.schedule(action, 900, &config).await;
+
+        assert!(result.is_ok());
+        assert_eq!(send_rule.num_calls(), 1);
+        Ok(())
+    }
+
+    #[cfg(feature = "action-queue")]
+    #[tokio::test]
+    async fn sqs_scheduler_rejects_delays_longer_than_sqs_allows() -> Result<()> {
+        use crate::action::SqsScheduler;
+
+        dotenvy::from_filename(".env.test")?;
+        let config = AppConfig::from_env();
+
+        let send_rule: Rule =
+            mock!(Client::send_message).then_output(|| SendMessageOutput::builder().build());
+        let scheduler = SqsScheduler {
+            client: mock_client!(aws_sdk_sqs, [&send_rule]),
+            queue_url: "https://sqs.test/actions".into(),
+        };
+
+        let action = QueuedAction::FollowUp(FollowUpMessage {
+            message: "...and the chicken chose John.".into(),
+            delay_secs: 3600,
+        });
+        let result = scheduler.schedule(action, 3600, &config).await;
+
+        assert!(result.is_err());
+        assert_eq!(send_rule.num_calls(), 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn tokio_scheduler_posts_follow_up_after_delay() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        let mut mock_server = Server::new_async().await;
+        config = config.with_se_api_host(format!("http://{}", mock_server.host_with_port()));
+
+        let mock = mock_server
+            .mock("POST", "/kappa/v2/bot/test_channel_id/say")
+            .match_body(r#"{"message":"...and the chicken chose John."}"#)
+            .create_async()
+            .await;
+
+        let scheduler = TokioScheduler {
+            client: WebClient::new(reqwest::Client::new()),
+        };
+        let action = QueuedAction::FollowUp(FollowUpMessage {
+            message: "...and the chicken chose John.".into(),
+            delay_secs: 0,
+        });
+
+        scheduler.schedule(action, 0, &config).await?;
+
+        for _ in 0..50 {
+            if mock.matched_async().await {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        mock.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn run_queued_returns_failed_ids_and_drops_unreadable_messages() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        let mut mock_server = Server::new_async().await;
+        config = config.with_se_api_host(format!("http://{}", mock_server.host_with_port()));
+
+        let ok = mock_server
+            .mock("POST", "/kappa/v2/bot/test_channel_id/say")
+            .match_body(r#"{"message":"first"}"#)
+            .create_async()
+            .await;
+        let failing = mock_server
+            .mock("POST", "/kappa/v2/bot/test_channel_id/say")
+            .match_body(r#"{"message":"second"}"#)
+            .with_status(500)
+            .create_async()
+            .await;
+
+        let follow_up = |message: &str| {
+            serde_json::to_string(&QueuedAction::FollowUp(FollowUpMessage {
+                message: message.into(),
+                delay_secs: 0,
+            }))
+        };
+        let messages = vec![
+            ("m1".to_string(), follow_up("first")?),
+            ("m2".to_string(), follow_up("second")?),
+            ("m3".to_string(), "not json".to_string()),
+        ];
+
+        let failed = run_queued(&messages, &WebClient::new(reqwest::Client::new()), &config).await;
+
+        ok.assert_async().await;
+        failing.assert_async().await;
+        assert_eq!(failed, vec!["m2"]);
+        Ok(())
+    }
+
+    #[cfg(feature = "long-delays")]
+    #[tokio::test]
+    async fn long_delay_router_sends_long_delays_through_scheduler() -> Result<()> {
+        use crate::action::{EventBridgeScheduler, LongDelayRouter, SqsScheduler};
+
+        dotenvy::from_filename(".env.test")?;
+        let config = AppConfig::from_env();
+
+        let action = QueuedAction::SetRewardPaused(RewardPause {
+            reward_id: "reward-1".into(),
+            paused: false,
+        });
+        let expected_input = serde_json::to_string(&action)?;
+
+        let send_rule: Rule =
+            mock!(Client::send_message).then_output(|| SendMessageOutput::builder().build());
+        let schedule_rule: Rule = mock!(aws_sdk_scheduler::Client::create_schedule)
+            .match_requests(move |r| {
+                r.schedule_expression()
+                    .is_some_and(|e| e.starts_with("at("))
+                    && r.action_after_completion() == Some(&ActionAfterCompletion::Delete)
+                    && r.target().map(|t| t.arn()) == Some("arn:aws:sqs:eu-west-2:1:actions")
+                    && r.target().and_then(|t| t.input()) == Some(expected_input.as_str())
+            })
+            .then_output(|| {
+                CreateScheduleOutput::builder()
+                    .schedule_arn("arn:aws:scheduler:eu-west-2:1:schedule/default/x")
+                    .build()
+                    .unwrap()
+            });
+
+        let router = LongDelayRouter {
+            queue: SqsScheduler {
+                client: mock_client!(aws_sdk_sqs, [&send_rule]),
+                queue_url: "https://sqs.test/actions".into(),
+            },
+            scheduler: EventBridgeScheduler {
+                client: mock_client!(aws_sdk_scheduler, [&schedule_rule]),
+                queue_arn: "arn:aws:sqs:eu-west-2:1:actions".into(),
+                role_arn: "arn:aws:iam::1:role/scheduler".into(),
+                group_name: None,
+            },
+        };
+
+        router.schedule(action.clone(), 7200, &config).await?;
+        router.schedule(action, 60, &config).await?;
+
+        assert_eq!(schedule_rule.num_calls(), 1);
+        assert_eq!(send_rule.num_calls(), 1);
+        Ok(())
+    }
+}
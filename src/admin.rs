@@ -0,0 +1,380 @@
+use anyhow::{Result, anyhow};
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+};
+use chrono::Utc;
+use lambda_http::{Body, Response};
+use reqwest::{
+    StatusCode,
+    header::{CONTENT_TYPE, ETAG, IF_MATCH},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    AppState,
+    admin_tokens::AdminScope,
+    commands::{self, CustomCommand},
+    config_audit::{self, ChangeSource, ConfigAuditEntry},
+    config_diff::{ConfigChange, config_diff},
+    migrate,
+    reward::mod_feeder::read_config,
+    robochick::twitch::MessageComponents,
+    state::{ConfigWrite, StateStore, StoredConfig},
+};
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ConfigConflict {
+    pub version: u64,
+    pub changes: Vec<ConfigChange>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SaveOutcome {
+    Saved(u64),
+    Invalid(String),
+    Conflict(ConfigConflict),
+}
+
+/// Validates and saves a config submitted against `expected_version`. On a conflict the
+/// caller gets the current version and how their config differs from it.
+pub async fn save_config(
+    state: &dyn StateStore,
+    body: &str,
+    expected_version: u64,
+) -> Result<SaveOutcome> {
+    if let Err(e) = migrate::parse_config(body) {
+        return Ok(SaveOutcome::Invalid(format!("Invalid message config: {e}")));
+    }
+
+    match state.put_config(body, expected_version).await? {
+        ConfigWrite::Saved(version) => Ok(SaveOutcome::Saved(version)),
+        ConfigWrite::Conflict(current) => {
+            let current_json: Value = serde_json::from_str(&current.body)?;
+            let submitted_json: Value = serde_json::from_str(body)?;
+            Ok(SaveOutcome::Conflict(ConfigConflict {
+                version: current.version,
+                changes: config_diff(&current_json, &submitted_json),
+            }))
+        }
+    }
+}
+
+/// Reads the version out of an `If-Match: "3"` header.
+fn expected_version(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(IF_MATCH)
+        .and_then(|h| h.to_str().ok())
+        .map(|h| h.trim().trim_start_matches("W/").trim_matches('"'))
+        .and_then(|v| v.parse().ok())
+}
+
+fn etag(version: u64) -> String {
+    format!("\"{version}\"")
+}
+
+fn empty_response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::Empty)
+        .unwrap()
+}
+
+/// The version and body of the config in use: the stored one, or the file as version 0.
+async fn current_config(state: &AppState) -> Result<(u64, String)> {
+    let stored = state
+        .state
+        .get_config()
+        .await
+        .map_err(|e| anyhow!("Failed to read stored config: {e}"))?;
+    match stored {
+        Some(s) => Ok((s.version, s.body)),
+        None => state
+            .config_file
+            .get()
+            .and_then(|c| Ok(serde_json::to_string(&c)?))
+            .map(|body| (0, body))
+            .map_err(|e| anyhow!("Failed to read config file: {e}")),
+    }
+}
+
+/// `GET /admin/config` - the current message config, with its version as the `ETag`. Version 0
+/// is the bundled config file, before anything has been saved.
+#[utoipa::path(get, path = "/admin/config", tag = "admin",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "The message config, as described by `cargo run -- schema`", body = Object),
+        (status = 401, description = "Token missing or unknown"),
+        (status = 403, description = "Token not scoped for this"),
+    ),
+)]
+pub async fn get_config_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    if let Err(status) = state.admin_authorized(&headers, AdminScope::Read).await {
+        return empty_response(status);
+    }
+
+    let (version, body) = match current_config(&state).await {
+        Ok(current) => current,
+        Err(e) => {
+            println!("{e}");
+            return empty_response(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .header(ETAG, etag(version))
+        .body(Body::from(body))
+        .unwrap()
+}
+
+/// `PUT /admin/config` - replaces the message config. `If-Match` must carry the `ETag` the
+/// editor started from, otherwise someone else's changes could be overwritten. A stale
+/// version gets a 409 listing how the submitted config differs from the current one.
+#[utoipa::path(put, path = "/admin/config", tag = "admin",
+    security(("admin_token" = [])),
+    params(("If-Match" = String, Header, description = "`ETag` of the config the change was made to")),
+    request_body(content = Object, description = "The new message config"),
+    responses(
+        (status = 200, description = "Saved, with the new `ETag`"),
+        (status = 400, description = "Invalid config"),
+        (status = 401, description = "Token missing or unknown"),
+        (status = 403, description = "Token not scoped for this"),
+        (status = 409, description = "Someone else changed the config first, with the differences"),
+        (status = 428, description = "No `If-Match` header"),
+    ),
+)]
+pub async fn put_config_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: String,
+) -> Response<Body> {
+    let actor = match state.admin_actor(&headers, AdminScope::ConfigWrite).await {
+        Ok(actor) => actor,
+        Err(status) => return empty_response(status),
+    };
+
+    let expected = match expected_version(&headers) {
+        Some(v) => v,
+        None => return empty_response(StatusCode::PRECONDITION_REQUIRED),
+    };
+    let before = match current_config(&state).await {
+        Ok((_, body)) => serde_json::from_str(&body).unwrap_or(Value::Null),
+        Err(e) => {
+            println!("{e}");
+            Value::Null
+        }
+    };
+
+    let outcome = save_config(state.state.as_ref(), &body, expected).await;
+    save_response(&state, &actor, &before, &body, outcome).await
+}
+
+/// The response to a config save, recording it in the audit log when it was saved.
+async fn save_response(
+    state: &AppState,
+    actor: &str,
+    before: &Value,
+    body: &str,
+    outcome: Result<SaveOutcome>,
+) -> Response<Body> {
+    match outcome {
+        Ok(SaveOutcome::Saved(version)) => {
+            // Parsed for serving straight away, so what it skips is reported as it's saved.
+            let saved = StoredConfig {
+                version,
+                body: body.to_string(),
+            };
+            if let Err(e) = state.config_file.parse_saved(&saved) {
+                println!("{e}");
+            }
+            let after = serde_json::from_str(body).unwrap_or(Value::Null);
+            let entry = ConfigAuditEntry::new(
+                actor,
+                ChangeSource::AdminApi,
+                Some(version),
+                before,
+                &after,
+                Utc::now(),
+            );
+            config_audit::record_change(state, entry).await;
+            Response::builder()
+                .status(StatusCode::OK)
+                .header(ETAG, etag(version))
+                .body(Body::Empty)
+                .unwrap()
+        }
+        Ok(SaveOutcome::Invalid(reason)) => Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::from(reason))
+            .unwrap(),
+        Ok(SaveOutcome::Conflict(conflict)) => Response::builder()
+            .status(StatusCode::CONFLICT)
+            .header(CONTENT_TYPE, "application/json")
+            .header(ETAG, etag(conflict.version))
+            .body(Body::from(serde_json::to_string(&conflict).unwrap()))
+            .unwrap(),
+        Err(e) => {
+            println!("Failed to save config: {e}");
+            empty_response(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Saves the current config with a custom command added, replaced or removed.
+async fn save_command(
+    state: &AppState,
+    actor: &str,
+    name: &str,
+    command: Option<&CustomCommand>,
+) -> Response<Body> {
+    let (version, body) = match current_config(state).await {
+        Ok(current) => current,
+        Err(e) => {
+            println!("{e}");
+            return empty_response(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    let edited = match commands::edit_custom(&body, name, command) {
+        Ok(Some(edited)) => edited,
+        Ok(None) => return empty_response(StatusCode::NOT_FOUND),
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(e.to_string()))
+                .unwrap();
+        }
+    };
+
+    let before = serde_json::from_str(&body).unwrap_or(Value::Null);
+    let outcome = save_config(state.state.as_ref(), &edited, version).await;
+    save_response(state, actor, &before, &edited, outcome).await
+}
+
+/// `PUT /admin/commands/{name}` - adds or replaces a custom chat command in the current
+/// message config.
+#[utoipa::path(put, path = "/admin/commands/{name}", tag = "admin",
+    security(("admin_token" = [])),
+    params(("name" = String, Path, description = "The command, without the prefix")),
+    request_body(content = Object, description = "The command's `reply`, and optionally its `permission` and `cooldown_secs`"),
+    responses(
+        (status = 200, description = "Saved, with the config's new `ETag`"),
+        (status = 400, description = "Invalid command, or the name of a built-in one"),
+        (status = 401, description = "Token missing or unknown"),
+        (status = 403, description = "Token not scoped for this"),
+        (status = 409, description = "The config changed while saving"),
+    ),
+)]
+pub async fn put_command_handler(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    body: String,
+) -> Response<Body> {
+    let actor = match state.admin_actor(&headers, AdminScope::ConfigWrite).await {
+        Ok(actor) => actor,
+        Err(status) => return empty_response(status),
+    };
+    let command: CustomCommand = match serde_json::from_str(&body) {
+        Ok(c) => c,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(format!("Invalid command: {e}")))
+                .unwrap();
+        }
+    };
+
+    save_command(&state, &actor, &name, Some(&command)).await
+}
+
+/// `DELETE /admin/commands/{name}` - removes a custom chat command from the current message
+/// config.
+#[utoipa::path(delete, path = "/admin/commands/{name}", tag = "admin",
+    security(("admin_token" = [])),
+    params(("name" = String, Path, description = "The command, without the prefix")),
+    responses(
+        (status = 200, description = "Removed, with the config's new `ETag`"),
+        (status = 401, description = "Token missing or unknown"),
+        (status = 403, description = "Token not scoped for this"),
+        (status = 404, description = "No such custom command"),
+        (status = 409, description = "The config changed while saving"),
+    ),
+)]
+pub async fn delete_command_handler(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    let actor = match state.admin_actor(&headers, AdminScope::ConfigWrite).await {
+        Ok(actor) => actor,
+        Err(status) => return empty_response(status),
+    };
+
+    save_command(&state, &actor, &name, None).await
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use axum::http::HeaderMap;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use crate::{
+        admin::{ConfigConflict, SaveOutcome, expected_version, save_config},
+        config_diff::ConfigChange,
+        state::MemoryStore,
+    };
+
+    const CONFIG: &str = r#"{"scenarios":[],"mods":["John"]}"#;
+
+    #[test]
+    fn expected_version_reads_if_match_header() -> Result<()> {
+        let mut headers = HeaderMap::new();
+        assert_eq!(expected_version(&headers), None);
+
+        headers.insert("If-Match", "\"3\"".parse()?);
+        assert_eq!(expected_version(&headers), Some(3));
+
+        headers.insert("If-Match", "W/\"4\"".parse()?);
+        assert_eq!(expected_version(&headers), Some(4));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn save_config_rejects_invalid_config() -> Result<()> {
+        let store = MemoryStore::default();
+
+        let outcome = save_config(&store, r#"{"mods": 5}"#, 0).await?;
+
+        assert!(matches!(outcome, SaveOutcome::Invalid(_)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn save_config_reports_conflict_with_diff() -> Result<()> {
+        let store = MemoryStore::default();
+        assert_eq!(save_config(&store, CONFIG, 0).await?, SaveOutcome::Saved(1));
+
+        let outcome = save_config(&store, r#"{"scenarios":[],"mods":["Jane"]}"#, 0).await?;
+
+        assert_eq!(
+            outcome,
+            SaveOutcome::Conflict(ConfigConflict {
+                version: 1,
+                changes: vec![ConfigChange {
+                    path: "/mods/0".into(),
+                    current: json!("John"),
+                    submitted: json!("Jane"),
+                }],
+            })
+        );
+        Ok(())
+    }
+}
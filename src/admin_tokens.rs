@@ -0,0 +1,177 @@
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+use crate::{auth, cache::TtlCache, permissions::Principal};
+
+/// How long the token list is used before Secrets Manager is read again, so added or revoked
+/// tokens take effect without a redeploy.
+const TOKENS_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// How long a failed read is answered from memory, so requests during a Secrets Manager outage
+/// or with a broken secret don't each call it again.
+const FAILURE_CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// What an admin token may do. `ADMIN_API_TOKEN` can do all of it.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AdminScope {
+    /// Read the stored config and the message audit log.
+    Read,
+    /// Save configs, and take and restore backups.
+    ConfigWrite,
+    /// Manage EventSub subscriptions.
+    Subscriptions,
+    /// Delete a viewer's stored data.
+    UserData,
+}
+
+/// One entry of the token list, e.g. `{"name": "mods", "token": "...", "scopes": ["read"]}`.
+/// `name` is only used in logs.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AdminToken {
+    pub name: String,
+    pub token: String,
+    pub scopes: Vec<AdminScope>,
+}
+
+impl AdminToken {
+    pub fn principal(&self) -> Principal {
+        Principal::scoped_token(&self.scopes)
+    }
+}
+
+/// The token matching `token`, whatever it's scoped to. Every token is compared in constant
+/// time, so how long the lookup takes doesn't give away which one came close.
+pub fn find_token<'a>(tokens: &'a [AdminToken], token: &str) -> Option<&'a AdminToken> {
+    tokens.iter().fold(None, |found, t| {
+        match auth::secrets_match(token, &t.token) {
+            true => Some(t),
+            false => found,
+        }
+    })
+}
+
+/// Reads the scoped admin tokens from a Secrets Manager secret holding a JSON list of
+/// [`AdminToken`]s.
+pub struct AdminTokenStore {
+    client: aws_sdk_secretsmanager::Client,
+    secret_name: String,
+    cache: TtlCache<String, Vec<AdminToken>>,
+    /// The error of the last read, while it's recent.
+    failures: TtlCache<String, String>,
+}
+
+impl AdminTokenStore {
+    pub fn new(client: aws_sdk_secretsmanager::Client, secret_name: String) -> Self {
+        AdminTokenStore {
+            client,
+            secret_name,
+            cache: TtlCache::new(TOKENS_CACHE_TTL),
+            failures: TtlCache::new(FAILURE_CACHE_TTL),
+        }
+    }
+
+    pub async fn tokens(&self) -> Result<Vec<AdminToken>> {
+        if let Some(tokens) = self.cache.get(&self.secret_name) {
+            return Ok(tokens);
+        }
+        if let Some(failure) = self.failures.get(&self.secret_name) {
+            return Err(anyhow!(failure));
+        }
+
+        match self.read().await {
+            Ok(tokens) => {
+                self.cache.insert(self.secret_name.clone(), tokens.clone());
+                Ok(tokens)
+            }
+            Err(e) => {
+                self.failures
+                    .insert(self.secret_name.clone(), e.to_string());
+                Err(e)
+            }
+        }
+    }
+
+    async fn read(&self) -> Result<Vec<AdminToken>> {
+        let secret = self
+            .client
+            .get_secret_value()
+            .secret_id(&self.secret_name)
+            .send()
+            .await
+            .map_err(|e| anyhow!("Failed to read admin tokens from {}: {e}", self.secret_name))?;
+
+        match secret.secret_string() {
+            Some(s) => serde_json::from_str(s)
+                .map_err(|e| anyhow!("Invalid admin tokens in {}: {e}", self.secret_name)),
+            None => Err(anyhow!("Secret {} has no admin tokens", self.secret_name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use aws_sdk_secretsmanager::{Client, operation::get_secret_value::GetSecretValueOutput};
+    use aws_smithy_mocks::{RuleMode, mock, mock_client};
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        admin_tokens::{AdminScope, AdminTokenStore, find_token},
+        permissions::Principal,
+    };
+
+    #[tokio::test]
+    async fn tokens_are_read_once_and_checked_by_scope() -> Result<()> {
+        let rule = mock!(Client::get_secret_value)
+            .match_requests(|r| r.secret_id() == Some("robochick/admin-tokens"))
+            .then_output(|| {
+                GetSecretValueOutput::builder()
+                    .secret_string(
+                        r#"[
+                            {"name": "mods", "token": "mod-token", "scopes": ["read"]},
+                            {"name": "editor", "token": "editor-token", "scopes": ["read", "config_write"]}
+                        ]"#,
+                    )
+                    .build()
+            });
+        let client = mock_client!(aws_sdk_secretsmanager, RuleMode::MatchAny, [&rule]);
+        let store = AdminTokenStore::new(client, "robochick/admin-tokens".into());
+
+        let tokens = store.tokens().await?;
+        store.tokens().await?;
+
+        assert_eq!(rule.num_calls(), 1);
+        let moderator = find_token(&tokens, "mod-token").unwrap();
+        assert_eq!(moderator.name, "mods");
+        assert_eq!(
+            moderator.principal(),
+            Principal::scoped_token(&[AdminScope::Read])
+        );
+        assert_eq!(
+            find_token(&tokens, "editor-token").unwrap().principal(),
+            Principal::scoped_token(&[AdminScope::Read, AdminScope::ConfigWrite])
+        );
+        assert!(find_token(&tokens, "nope").is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failed_reads_are_remembered_briefly() -> Result<()> {
+        let rule = mock!(Client::get_secret_value).then_output(|| {
+            GetSecretValueOutput::builder()
+                .secret_string("nope")
+                .build()
+        });
+        let client = mock_client!(aws_sdk_secretsmanager, RuleMode::MatchAny, [&rule]);
+        let store = AdminTokenStore::new(client, "robochick/admin-tokens".into());
+
+        assert!(store.tokens().await.is_err());
+        assert!(store.tokens().await.is_err());
+
+        assert_eq!(rule.num_calls(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,271 @@
+use std::sync::Arc;
+
+use aws_config::SdkConfig;
+use aws_sdk_dynamodb::Client;
+use axum::Router;
+
+use crate::{
+    AppState,
+    action::{self, ActionScheduler, TokioScheduler},
+    admin_tokens::AdminTokenStore,
+    client::{Caller, WebClient},
+    config::AppConfig,
+    handler::event_handler::EventHandler,
+    load_aws_config,
+    reward::{ducks::DuckRedeemed, mod_feeder::ModFeed},
+    router,
+    secrets::SecretStore,
+    state::StateStore,
+    state_store, web_client_with_secrets,
+};
+
+/// The bot's parts wired together: the config, the secret backend, the state store, the chat
+/// sinks and Helix client, the scheduler for delayed actions and the EventSub handler. The
+/// HTTP server, the action consumer and tests all assemble it through [`App::builder`].
+#[derive(Clone)]
+pub struct App {
+    state: AppState,
+}
+
+impl App {
+    /// Starts wiring up the bot for `config`. Parts that aren't given are built from the config
+    /// and the ambient AWS config.
+    pub fn builder(config: AppConfig) -> AppBuilder {
+        AppBuilder {
+            config,
+            aws: None,
+            secrets: None,
+            state: None,
+            web_client: None,
+            caller: None,
+            actions: None,
+        }
+    }
+
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    pub fn config(&self) -> &AppConfig {
+        &self.state.config
+    }
+
+    /// The chat sinks and Helix, behind one client.
+    pub fn web_client(&self) -> &WebClient {
+        &self.state.web_client
+    }
+
+    pub fn router(&self) -> Router {
+        router(self.state.clone())
+    }
+}
+
+pub struct AppBuilder {
+    config: AppConfig,
+    aws: Option<SdkConfig>,
+    secrets: Option<Arc<SecretStore>>,
+    state: Option<Arc<dyn StateStore>>,
+    web_client: Option<WebClient>,
+    caller: Option<Arc<dyn Caller>>,
+    actions: Option<Arc<dyn ActionScheduler>>,
+}
+
+impl AppBuilder {
+    /// AWS config the AWS clients are built from, in place of the ambient one.
+    pub fn with_aws(self, aws: SdkConfig) -> AppBuilder {
+        AppBuilder {
+            aws: Some(aws),
+            ..self
+        }
+    }
+
+    /// Where StreamElements JWTs, EventSub secrets and the YouTube token are read from.
+    pub fn with_secrets(self, secrets: Arc<SecretStore>) -> AppBuilder {
+        AppBuilder {
+            secrets: Some(secrets),
+            ..self
+        }
+    }
+
+    pub fn with_state(self, state: Arc<dyn StateStore>) -> AppBuilder {
+        AppBuilder {
+            state: Some(state),
+            ..self
+        }
+    }
+
+    /// The client chat messages and Helix calls go through. Outbound messages are audited
+    /// to the state store either way.
+    pub fn with_web_client(self, web_client: WebClient) -> AppBuilder {
+        AppBuilder {
+            web_client: Some(web_client),
+            ..self
+        }
+    }
+
+    /// What redemptions, pipeline rules and delayed actions post and call Twitch through, in
+    /// place of the web client. Health checks and admin routes keep using the web client.
+    pub fn with_caller(self, caller: Arc<dyn Caller>) -> AppBuilder {
+        AppBuilder {
+            caller: Some(caller),
+            ..self
+        }
+    }
+
+    pub fn with_actions(self, actions: Arc<dyn ActionScheduler>) -> AppBuilder {
+        AppBuilder {
+            actions: Some(actions),
+            ..self
+        }
+    }
+
+    pub async fn build(self) -> App {
+        let config = self.config;
+        let aws = match self.aws {
+            Some(aws) => aws,
+            None => load_aws_config().await,
+        };
+
+        let dynamo_client = Client::new(&aws);
+        let state = self.state.unwrap_or_else(|| {
+            state_store(&config, &dynamo_client)
+                .unwrap_or_else(|e| panic!("Failed to set up the state store: {e}"))
+        });
+        let web_client = self
+            .web_client
+            .unwrap_or_else(|| {
+                let secrets = self.secrets.unwrap_or_else(|| {
+                    Arc::new(SecretStore::new(aws_sdk_secretsmanager::Client::new(&aws)))
+                });
+                web_client_with_secrets(&config, secrets)
+            })
+            .with_audit(state.clone());
+        let caller: Arc<dyn Caller> = self.caller.unwrap_or_else(|| Arc::new(web_client.clone()));
+        let actions = self
+            .actions
+            .unwrap_or_else(|| action_scheduler(&config, &aws, caller.clone()));
+        let admin_tokens = config.admin_tokens_secret.clone().map(|secret| {
+            Arc::new(AdminTokenStore::new(
+                aws_sdk_secretsmanager::Client::new(&aws),
+                secret,
+            ))
+        });
+
+        App {
+            state: AppState {
+                admin_tokens,
+                caller,
+                ..AppState::with_parts(config, aws, dynamo_client, actions, state, web_client)
+            },
+        }
+    }
+}
+
+/// Delayed actions go to the action queue when one is configured, with EventBridge Scheduler
+/// taking those delayed longer than SQS allows if it's set up too. Otherwise they run on
+/// background tasks.
+fn action_scheduler(
+    config: &AppConfig,
+    aws: &SdkConfig,
+    caller: Arc<dyn Caller>,
+) -> Arc<dyn ActionScheduler> {
+    match (
+        config.action_queue_url.clone(),
+        config.action_queue_arn.clone(),
+        config.scheduler_role_arn.clone(),
+    ) {
+        #[cfg(feature = "long-delays")]
+        (Some(queue_url), Some(queue_arn), Some(role_arn)) => Arc::new(action::LongDelayRouter {
+            queue: action::SqsScheduler {
+                client: aws_sdk_sqs::Client::new(aws),
+                queue_url,
+            },
+            scheduler: action::EventBridgeScheduler {
+                client: aws_sdk_scheduler::Client::new(aws),
+                queue_arn,
+                role_arn,
+                group_name: config.scheduler_group_name.clone(),
+            },
+        }),
+        #[cfg(feature = "action-queue")]
+        (Some(queue_url), _, _) => Arc::new(action::SqsScheduler {
+            client: aws_sdk_sqs::Client::new(aws),
+            queue_url,
+        }),
+        // Running them on background tasks instead would lose them on Lambda.
+        #[cfg(not(feature = "action-queue"))]
+        (Some(_), _, _) => panic!("ACTION_QUEUE_URL needs the action-queue feature"),
+        _ => Arc::new(TokioScheduler { client: caller }),
+    }
+}
+
+impl AppState {
+    /// The feed mods reward, wired to this state's parts.
+    pub(crate) fn mod_feed(&self) -> ModFeed<Arc<dyn Caller>> {
+        ModFeed {
+            client: self.caller.clone(),
+            chatters: self.chatters.clone(),
+            live: self.live.clone(),
+            users: self.users.clone(),
+            actions: self.actions.clone(),
+            state: self.state.clone(),
+            config_file: self.config_file.clone(),
+        }
+    }
+
+    /// The EventSub handler, with the feed mods and rubber duck rewards and the pipeline
+    /// wired to this state's parts.
+    pub(crate) fn event_handler(&self) -> EventHandler {
+        let mut event_handler = EventHandler::default();
+        event_handler.set_actions(self.actions.clone());
+        event_handler.set_challenge_cache(self.challenges.clone());
+        if let Some(secrets) = self.web_client.eventsub_secrets() {
+            event_handler.set_eventsub_secrets(secrets);
+        }
+        if let Some(notifier) = self.notifier.clone() {
+            event_handler.set_notifier(notifier);
+        }
+        event_handler.set_pipeline(self.mod_feed());
+        event_handler.register(self.config.feed_mods_rewards_id.clone(), self.mod_feed());
+        event_handler.register(
+            self.config.rubberduck_rewards_id.clone(),
+            DuckRedeemed {
+                dynamo_client: self.dynamo_client.clone(),
+            },
+        );
+        event_handler
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use std::sync::Arc;
+
+    use crate::{
+        app::App,
+        client::WebClient,
+        config::AppConfig,
+        state::{MemoryStore, StateStore},
+    };
+
+    #[tokio::test]
+    async fn given_parts_are_wired_in() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let state: Arc<dyn StateStore> = Arc::new(MemoryStore::default());
+
+        let app = App::builder(AppConfig::from_env())
+            .with_aws(
+                aws_config::SdkConfig::builder()
+                    .behavior_version(aws_config::BehaviorVersion::latest())
+                    .build(),
+            )
+            .with_state(state.clone())
+            .with_web_client(WebClient::new(reqwest::Client::new()))
+            .build()
+            .await;
+
+        assert!(Arc::ptr_eq(&app.state().state, &state));
+        Ok(())
+    }
+}
@@ -0,0 +1,209 @@
+use std::{collections::HashMap, future::Future};
+
+use anyhow::Result;
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use lambda_http::{Body, Response};
+use reqwest::{StatusCode, header::CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+
+use crate::{AppState, admin_tokens::AdminScope, sink::ChatBackend, state::StateStore};
+
+/// Longest stretch `GET /admin/audit` reads in one go.
+const MAX_AUDIT_DAYS: i64 = 31;
+
+tokio::task_local! {
+    /// Id of the event being handled, so outbound messages can be traced back to it.
+    static EVENT_ID: String;
+}
+
+/// Runs `fut` with `event_id` recorded against every chat message it sends.
+pub async fn with_event_id<F: Future>(event_id: String, fut: F) -> F::Output {
+    EVENT_ID.scope(event_id, fut).await
+}
+
+pub fn current_event_id() -> Option<String> {
+    EVENT_ID.try_with(String::clone).ok()
+}
+
+/// One outbound chat message and what became of it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    pub sent_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_id: Option<String>,
+    pub message: String,
+    pub backend: ChatBackend,
+    /// HTTP status of the send, if a response came back at all.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    pub latency_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+fn day_key(day: NaiveDate) -> String {
+    day.format("%Y-%m-%d").to_string()
+}
+
+/// Keys of the days from `since` to `now`, going back at most [`MAX_AUDIT_DAYS`].
+pub(crate) fn days_between(since: DateTime<Utc>, now: DateTime<Utc>) -> Vec<String> {
+    let since = since.max(now - Duration::days(MAX_AUDIT_DAYS));
+    let mut days = vec![];
+    let mut day = since.date_naive();
+    while day <= now.date_naive() {
+        days.push(day_key(day));
+        match day.succ_opt() {
+            Some(next) => day = next,
+            None => break,
+        }
+    }
+    days
+}
+
+/// Keys of today and the `days` days before it, i.e. every day still within a retention of
+/// `days` days.
+pub(crate) fn last_days(now: DateTime<Utc>, days: u64) -> Vec<String> {
+    (0..=days)
+        .filter_map(|back| now.date_naive().checked_sub_days(chrono::Days::new(back)))
+        .map(day_key)
+        .collect()
+}
+
+pub async fn record(state: &dyn StateStore, entry: &AuditEntry) -> Result<()> {
+    state
+        .append_audit(
+            &day_key(entry.sent_at.date_naive()),
+            &serde_json::to_string(entry)?,
+        )
+        .await
+}
+
+/// Entries sent at or after `since`, oldest first. Looks back at most [`MAX_AUDIT_DAYS`].
+pub async fn entries_since(
+    state: &dyn StateStore,
+    since: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> Result<Vec<AuditEntry>> {
+    let mut entries = vec![];
+    for day in days_between(since, now) {
+        for raw in state.audit_entries(&day).await? {
+            match serde_json::from_str::<AuditEntry>(&raw) {
+                Ok(e) if e.sent_at >= since => entries.push(e),
+                Ok(_) => {}
+                Err(e) => println!("Skipping unreadable audit entry: {e}"),
+            }
+        }
+    }
+
+    entries.sort_by_key(|e| e.sent_at);
+    Ok(entries)
+}
+
+/// `GET /admin/audit?since=2026-10-16T12:00:00Z` - outbound messages since then, defaulting
+/// to the last day.
+#[utoipa::path(get, path = "/admin/audit", tag = "admin",
+    security(("admin_token" = [])),
+    params(("since" = Option<String>, Query, description = "RFC 3339 timestamp, a day ago by default")),
+    responses(
+        (status = 200, description = "Outbound messages, oldest first", body = Vec<Object>),
+        (status = 400, description = "Invalid `since`"),
+        (status = 401, description = "Token missing or unknown"),
+        (status = 403, description = "Token not scoped for this"),
+    ),
+)]
+pub async fn audit_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response<Body> {
+    if let Err(status) = state.admin_authorized(&headers, AdminScope::Read).await {
+        return Response::builder()
+            .status(status)
+            .body(Body::Empty)
+            .unwrap();
+    }
+
+    let now = Utc::now();
+    let since = match params.get("since").map(|s| DateTime::parse_from_rfc3339(s)) {
+        Some(Ok(s)) => s.with_timezone(&Utc),
+        Some(Err(e)) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(format!("Invalid since: {e}")))
+                .unwrap();
+        }
+        None => now - Duration::days(1),
+    };
+
+    match entries_since(state.state.as_ref(), since, now).await {
+        Ok(entries) => Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_string(&entries).unwrap()))
+            .unwrap(),
+        Err(e) => {
+            println!("Failed to read audit log: {e}");
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::Empty)
+                .unwrap()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        audit::{AuditEntry, current_event_id, entries_since, record, with_event_id},
+        sink::ChatBackend,
+        state::MemoryStore,
+    };
+
+    fn entry(sent_at: DateTime<Utc>, message: &str) -> AuditEntry {
+        AuditEntry {
+            sent_at,
+            event_id: Some("event-1".into()),
+            message: message.into(),
+            backend: ChatBackend::StreamElements,
+            status: Some(200),
+            latency_ms: 120,
+            error: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn entries_since_reads_across_days_in_order() -> Result<()> {
+        let store = MemoryStore::default();
+        let now = Utc.with_ymd_and_hms(2026, 10, 16, 9, 0, 0).unwrap();
+        record(&store, &entry(now, "today")).await?;
+        record(&store, &entry(now - Duration::hours(12), "yesterday")).await?;
+        record(&store, &entry(now - Duration::days(3), "too old")).await?;
+
+        let entries = entries_since(&store, now - Duration::days(1), now).await?;
+
+        assert_eq!(
+            entries
+                .iter()
+                .map(|e| e.message.as_str())
+                .collect::<Vec<_>>(),
+            vec!["yesterday", "today"]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn event_id_is_visible_inside_scope_only() {
+        let inside = with_event_id("event-1".into(), async { current_event_id() }).await;
+
+        assert_eq!(inside, Some("event-1".to_string()));
+        assert_eq!(current_event_id(), None);
+    }
+}
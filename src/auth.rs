@@ -9,6 +9,142 @@ use aws_sdk_secretsmanager::{
         update_secret::{UpdateSecretError, UpdateSecretOutput},
     },
 };
+use axum::http::{HeaderMap, StatusCode};
+use reqwest::Url;
+use subtle::ConstantTimeEq;
+
+use crate::{
+    admin_tokens::{AdminScope, AdminTokenStore, find_token},
+    config::AppConfig,
+    permissions::{self, Capability, Principal},
+    signing::{self, RequestAuth},
+};
+
+/// Scopes the broadcaster has to grant for every feature to work.
+pub const REQUIRED_SCOPES: &[&str] = &[
+    "channel:read:redemptions",
+    "channel:manage:redemptions",
+    "channel:manage:polls",
+    "channel:read:subscriptions",
+    "channel:read:vips",
+    "moderation:read",
+    "moderator:read:chatters",
+    "user:write:chat",
+];
+
+/// Link the broadcaster can follow to (re-)authorize the bot. Twitch redirects back to
+/// `/twitch/oauth`.
+pub fn authorize_url(config: &AppConfig) -> anyhow::Result<Url> {
+    Ok(Url::parse_with_params(
+        &format!("{}/oauth2/authorize", config.twitch_host),
+        [
+            ("client_id", config.twitch_client_id.as_str()),
+            ("redirect_uri", config.redirect_uri.as_str()),
+            ("response_type", "code"),
+            ("scope", &REQUIRED_SCOPES.join(" ")),
+        ],
+    )?)
+}
+
+/// Actor recorded for requests made with `ADMIN_API_TOKEN`.
+pub const ADMIN_ACTOR: &str = "admin";
+
+/// Checks the bearer token on `/internal` routes. They stay closed when no token is configured.
+/// With `INTERNAL_AUTH` other than `bearer`, [`signing::internal_guard`] has checked the request
+/// already.
+pub fn internal_request_authorized(headers: &HeaderMap, config: &AppConfig) -> bool {
+    if config.internal_auth != RequestAuth::Bearer {
+        return signing::verified_actor(headers).is_some();
+    }
+    bearer_token_matches(headers, config.internal_api_token.as_deref())
+}
+
+/// Checks the bearer token on `/admin` routes. `ADMIN_API_TOKEN` is allowed everything, the
+/// tokens in `tokens` only what they're scoped to. They stay closed when no token is configured.
+/// Errors with the status to answer: 401 without a known token, 403 for a token that isn't
+/// scoped to `scope`.
+pub async fn admin_request_authorized(
+    headers: &HeaderMap,
+    config: &AppConfig,
+    tokens: Option<&AdminTokenStore>,
+    scope: AdminScope,
+) -> Result<(), StatusCode> {
+    admin_actor(headers, config, tokens, scope)
+        .await
+        .map(|_| ())
+}
+
+/// Who an allowed admin request comes from: [`ADMIN_ACTOR`] for `ADMIN_API_TOKEN`, otherwise
+/// the name of the scoped token. Errors like [`admin_request_authorized`] when the request
+/// isn't allowed `scope`. With `ADMIN_AUTH` other than `bearer`, the caller
+/// [`signing::admin_guard`] let through.
+pub async fn admin_actor(
+    headers: &HeaderMap,
+    config: &AppConfig,
+    tokens: Option<&AdminTokenStore>,
+    scope: AdminScope,
+) -> Result<String, StatusCode> {
+    let (actor, principal) = admin_principal(headers, config, tokens).await?;
+    if !permissions::allows(&principal, Capability::Admin(scope)) {
+        println!("Admin caller {actor} isn't allowed {scope:?}");
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(actor)
+}
+
+/// Who sent an admin request and what they may do. `ADMIN_API_TOKEN`, signed and
+/// certificate-authenticated callers are [`Principal::admin`], scoped tokens are limited to
+/// their scopes. 401 for anyone else.
+async fn admin_principal(
+    headers: &HeaderMap,
+    config: &AppConfig,
+    tokens: Option<&AdminTokenStore>,
+) -> Result<(String, Principal), StatusCode> {
+    if config.admin_auth != RequestAuth::Bearer {
+        let actor = signing::verified_actor(headers).ok_or(StatusCode::UNAUTHORIZED)?;
+        return Ok((actor, Principal::admin()));
+    }
+
+    if bearer_token_matches(headers, config.admin_api_token.as_deref()) {
+        return Ok((ADMIN_ACTOR.to_string(), Principal::admin()));
+    }
+
+    let (Some(store), Some(token)) = (tokens, bearer_token(headers)) else {
+        return Err(StatusCode::UNAUTHORIZED);
+    };
+
+    match store.tokens().await {
+        Ok(tokens) => find_token(&tokens, token)
+            .map(|t| (t.name.clone(), t.principal()))
+            .ok_or(StatusCode::UNAUTHORIZED),
+        Err(e) => {
+            println!("Failed to load admin tokens: {e}");
+            Err(StatusCode::UNAUTHORIZED)
+        }
+    }
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+}
+
+fn bearer_token_matches(headers: &HeaderMap, expected: Option<&str>) -> bool {
+    let expected = match expected {
+        Some(t) => t,
+        None => return false,
+    };
+
+    bearer_token(headers).is_some_and(|token| secrets_match(token, expected))
+}
+
+/// Whether `given` is the secret `expected`, compared in constant time so how long it takes
+/// doesn't give away how much of a guess was right.
+pub(crate) fn secrets_match(given: &str, expected: &str) -> bool {
+    given.as_bytes().ct_eq(expected.as_bytes()).into()
+}
 
 pub async fn securely_store_oauth_tokens(token_response: String) -> anyhow::Result<String> {
     let region = RegionProviderChain::default_provider().or_else("eu-west-2");
@@ -82,3 +218,115 @@ async fn create_new_secret(name: &str, val: &str, client: &Client) -> anyhow::Re
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use axum::http::{HeaderMap, StatusCode};
+
+    use aws_sdk_secretsmanager::{Client, operation::get_secret_value::GetSecretValueOutput};
+    use aws_smithy_mocks::{RuleMode, mock, mock_client};
+
+    use crate::{
+        admin_tokens::{AdminScope, AdminTokenStore},
+        auth::{
+            REQUIRED_SCOPES, admin_request_authorized, authorize_url, internal_request_authorized,
+            secrets_match,
+        },
+        config::AppConfig,
+    };
+
+    #[test]
+    fn secrets_match_only_the_whole_secret() {
+        assert!(secrets_match("scheduler-token", "scheduler-token"));
+        assert!(!secrets_match("scheduler-tokem", "scheduler-token"));
+        assert!(!secrets_match("scheduler", "scheduler-token"));
+        assert!(!secrets_match("", "scheduler-token"));
+    }
+
+    #[test]
+    fn internal_request_authorized_checks_bearer_token() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.internal_api_token = Some("scheduler-token".into());
+
+        let mut valid = HeaderMap::new();
+        valid.insert("Authorization", "Bearer scheduler-token".parse()?);
+        let mut invalid = HeaderMap::new();
+        invalid.insert("Authorization", "Bearer nope".parse()?);
+
+        assert!(internal_request_authorized(&valid, &config));
+        assert!(!internal_request_authorized(&invalid, &config));
+        assert!(!internal_request_authorized(&HeaderMap::new(), &config));
+        Ok(())
+    }
+
+    #[test]
+    fn internal_request_authorized_rejects_everything_without_token() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.internal_api_token = None;
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Authorization", "Bearer ".parse()?);
+
+        assert!(!internal_request_authorized(&headers, &config));
+        Ok(())
+    }
+
+    #[test]
+    fn authorize_url_requests_required_scopes() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.twitch_host = "https://id.twitch.tv".into();
+
+        let url = authorize_url(&config)?;
+        let scope = url
+            .query_pairs()
+            .find(|(k, _)| k == "scope")
+            .map(|(_, v)| v.into_owned());
+
+        assert!(url.path().ends_with("/oauth2/authorize"));
+        assert_eq!(scope, Some(REQUIRED_SCOPES.join(" ")));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn admin_request_authorized_enforces_token_scopes() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.admin_api_token = Some("owner-token".into());
+
+        let rule = mock!(Client::get_secret_value).then_output(|| {
+            GetSecretValueOutput::builder()
+                .secret_string(r#"[{"name": "mods", "token": "mod-token", "scopes": ["read"]}]"#)
+                .build()
+        });
+        let client = mock_client!(aws_sdk_secretsmanager, RuleMode::MatchAny, [&rule]);
+        let tokens = AdminTokenStore::new(client, "robochick/admin-tokens".into());
+
+        let mut owner = HeaderMap::new();
+        owner.insert("Authorization", "Bearer owner-token".parse()?);
+        let mut moderator = HeaderMap::new();
+        moderator.insert("Authorization", "Bearer mod-token".parse()?);
+
+        assert_eq!(
+            admin_request_authorized(&owner, &config, Some(&tokens), AdminScope::ConfigWrite).await,
+            Ok(())
+        );
+        assert_eq!(
+            admin_request_authorized(&moderator, &config, Some(&tokens), AdminScope::Read).await,
+            Ok(())
+        );
+        assert_eq!(
+            admin_request_authorized(&moderator, &config, Some(&tokens), AdminScope::ConfigWrite)
+                .await,
+            Err(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(
+            admin_request_authorized(&moderator, &config, None, AdminScope::Read).await,
+            Err(StatusCode::UNAUTHORIZED)
+        );
+        Ok(())
+    }
+}
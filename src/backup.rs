@@ -0,0 +1,506 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{Result, anyhow};
+use aws_sdk_dynamodb::{primitives::Blob, types::AttributeValue};
+use aws_sdk_s3::primitives::ByteStream;
+use axum::{extract::State, http::HeaderMap};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chrono::{DateTime, Utc};
+use lambda_http::{Body, Response};
+use reqwest::{StatusCode, header::CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+
+use crate::{AppState, admin_tokens::AdminScope, config::AppConfig};
+
+/// Every item of the bot's tables, keyed by table role rather than table name so a snapshot
+/// can be restored into differently named tables in another account. Items use DynamoDB's
+/// JSON format, e.g. `{"pk": {"S": "config"}}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub created_at: String,
+    pub tables: BTreeMap<String, Vec<Map<String, Value>>>,
+}
+
+/// Tables included in backups as `(role, table name)`. The state table holds the stored
+/// config and counters, the log table the audit logs and the duck rewards table the
+/// redemption history.
+pub fn backup_tables(config: &AppConfig) -> Vec<(String, String)> {
+    let mut tables = vec![(
+        "duck_rewards".to_string(),
+        config.duck_rewards_table_name.clone(),
+    )];
+    if let Some(state_table) = config.state_table_name.clone() {
+        tables.push(("state".to_string(), state_table));
+    }
+    if let Some(log_table) = config.log_table_name() {
+        tables.push(("state_log".to_string(), log_table));
+    }
+
+    tables
+}
+
+pub async fn snapshot(
+    client: &aws_sdk_dynamodb::Client,
+    tables: &[(String, String)],
+) -> Result<Snapshot> {
+    let mut snapshot = Snapshot {
+        created_at: Utc::now().to_rfc3339(),
+        tables: BTreeMap::new(),
+    };
+
+    for (role, table_name) in tables {
+        let mut items = vec![];
+        let mut start_key = None;
+        loop {
+            let output = match client
+                .scan()
+                .table_name(table_name)
+                .set_exclusive_start_key(start_key)
+                .send()
+                .await
+            {
+                Ok(o) => o,
+                Err(e) => return Err(anyhow!("Failed to scan {table_name}: {e}")),
+            };
+
+            for item in output.items() {
+                items.push(item_to_json(item)?);
+            }
+
+            start_key = output.last_evaluated_key;
+            if start_key.is_none() {
+                break;
+            }
+        }
+
+        snapshot.tables.insert(role.clone(), items);
+    }
+
+    Ok(snapshot)
+}
+
+/// Writes the snapshot's items back, overwriting items with the same key. Returns how many
+/// items were written.
+pub async fn restore(
+    client: &aws_sdk_dynamodb::Client,
+    snapshot: &Snapshot,
+    tables: &[(String, String)],
+) -> Result<usize> {
+    let mut restored = 0;
+    for (role, items) in &snapshot.tables {
+        let table_name = match tables.iter().find(|(r, _)| r == role) {
+            Some((_, t)) => t,
+            None => {
+                println!(
+                    "No table configured for {role}, skipping {} items",
+                    items.len()
+                );
+                continue;
+            }
+        };
+
+        for item in items {
+            if let Err(e) = client
+                .put_item()
+                .table_name(table_name)
+                .set_item(Some(item_from_json(item)?))
+                .send()
+                .await
+            {
+                return Err(anyhow!("Failed to restore item into {table_name}: {e}"));
+            }
+            restored += 1;
+        }
+    }
+
+    Ok(restored)
+}
+
+pub async fn upload(
+    client: &aws_sdk_s3::Client,
+    bucket: &str,
+    snapshot: &Snapshot,
+) -> Result<String> {
+    let key = format!(
+        "backups/robochick-{}.json",
+        key_timestamp(&snapshot.created_at)?
+    );
+    let body = serde_json::to_vec(snapshot)?;
+
+    match client
+        .put_object()
+        .bucket(bucket)
+        .key(&key)
+        .content_type("application/json")
+        .body(ByteStream::from(body))
+        .send()
+        .await
+    {
+        Ok(_) => Ok(key),
+        Err(e) => Err(anyhow!("Failed to upload backup: {e}")),
+    }
+}
+
+/// `created_at` as a timestamp that needs no escaping in a URL, e.g. `20261016T120000Z`.
+fn key_timestamp(created_at: &str) -> Result<String> {
+    match DateTime::parse_from_rfc3339(created_at) {
+        Ok(t) => Ok(t.with_timezone(&Utc).format("%Y%m%dT%H%M%SZ").to_string()),
+        Err(e) => Err(anyhow!("Invalid snapshot time {created_at}: {e}")),
+    }
+}
+
+pub async fn download(client: &aws_sdk_s3::Client, bucket: &str, key: &str) -> Result<Snapshot> {
+    let output = match client.get_object().bucket(bucket).key(key).send().await {
+        Ok(o) => o,
+        Err(e) => return Err(anyhow!("Failed to download backup {key}: {e}")),
+    };
+
+    let bytes = output.body.collect().await?.into_bytes();
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+/// Snapshots every table into the backup bucket and returns the archive's key.
+pub async fn run_backup(aws_cfg: &aws_config::SdkConfig, config: &AppConfig) -> Result<String> {
+    let bucket = backup_bucket(config)?;
+    let snapshot = snapshot(
+        &aws_sdk_dynamodb::Client::new(aws_cfg),
+        &backup_tables(config),
+    )
+    .await?;
+
+    upload(&aws_sdk_s3::Client::new(aws_cfg), bucket, &snapshot).await
+}
+
+/// Restores the archive at `key` in the backup bucket. Returns how many items were written.
+pub async fn run_restore(
+    aws_cfg: &aws_config::SdkConfig,
+    config: &AppConfig,
+    key: &str,
+) -> Result<usize> {
+    let bucket = backup_bucket(config)?;
+    let snapshot = download(&aws_sdk_s3::Client::new(aws_cfg), bucket, key).await?;
+
+    restore(
+        &aws_sdk_dynamodb::Client::new(aws_cfg),
+        &snapshot,
+        &backup_tables(config),
+    )
+    .await
+}
+
+fn backup_bucket(config: &AppConfig) -> Result<&str> {
+    config
+        .backup_bucket
+        .as_deref()
+        .ok_or(anyhow!("BACKUP_BUCKET is not set"))
+}
+
+fn item_to_json(item: &HashMap<String, AttributeValue>) -> Result<Map<String, Value>> {
+    item.iter()
+        .map(|(k, v)| Ok((k.clone(), attribute_to_json(v)?)))
+        .collect()
+}
+
+fn item_from_json(item: &Map<String, Value>) -> Result<HashMap<String, AttributeValue>> {
+    item.iter()
+        .map(|(k, v)| Ok((k.clone(), attribute_from_json(v)?)))
+        .collect()
+}
+
+fn attribute_to_json(value: &AttributeValue) -> Result<Value> {
+    Ok(match value {
+        AttributeValue::S(s) => json!({ "S": s }),
+        AttributeValue::N(n) => json!({ "N": n }),
+        AttributeValue::Bool(b) => json!({ "BOOL": b }),
+        AttributeValue::Null(_) => json!({ "NULL": true }),
+        AttributeValue::Ss(ss) => json!({ "SS": ss }),
+        AttributeValue::Ns(ns) => json!({ "NS": ns }),
+        AttributeValue::B(b) => json!({ "B": STANDARD.encode(b.as_ref()) }),
+        AttributeValue::Bs(bs) => json!({
+            "BS": bs.iter().map(|b| STANDARD.encode(b.as_ref())).collect::<Vec<_>>()
+        }),
+        AttributeValue::L(l) => json!({
+            "L": l.iter().map(attribute_to_json).collect::<Result<Vec<_>>>()?
+        }),
+        AttributeValue::M(m) => json!({ "M": item_to_json(m)? }),
+        other => return Err(anyhow!("Unsupported attribute type in backup: {other:?}")),
+    })
+}
+
+fn attribute_from_json(value: &Value) -> Result<AttributeValue> {
+    let (kind, inner) = value
+        .as_object()
+        .and_then(|o| o.iter().next())
+        .ok_or(anyhow!("Malformed attribute in backup: {value}"))?;
+
+    let strings = |v: &Value| -> Result<Vec<String>> {
+        Ok(serde_json::from_value::<Vec<String>>(v.clone())?)
+    };
+
+    Ok(match (kind.as_str(), inner) {
+        ("S", Value::String(s)) => AttributeValue::S(s.clone()),
+        ("N", Value::String(n)) => AttributeValue::N(n.clone()),
+        ("BOOL", Value::Bool(b)) => AttributeValue::Bool(*b),
+        ("NULL", _) => AttributeValue::Null(true),
+        ("SS", v) => AttributeValue::Ss(strings(v)?),
+        ("NS", v) => AttributeValue::Ns(strings(v)?),
+        ("B", Value::String(b)) => AttributeValue::B(Blob::new(STANDARD.decode(b)?)),
+        ("BS", v) => AttributeValue::Bs(
+            strings(v)?
+                .iter()
+                .map(|b| Ok(Blob::new(STANDARD.decode(b)?)))
+                .collect::<Result<_>>()?,
+        ),
+        ("L", Value::Array(l)) => {
+            AttributeValue::L(l.iter().map(attribute_from_json).collect::<Result<_>>()?)
+        }
+        ("M", Value::Object(m)) => AttributeValue::M(item_from_json(m)?),
+        _ => return Err(anyhow!("Malformed attribute in backup: {value}")),
+    })
+}
+
+#[derive(Deserialize)]
+pub struct RestoreRequest {
+    pub key: String,
+}
+
+/// `POST /admin/backup` - snapshots the bot's tables into the backup bucket.
+#[utoipa::path(post, path = "/admin/backup", tag = "admin",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Key of the snapshot in the backup bucket", body = Object),
+        (status = 401, description = "Token missing or unknown"),
+        (status = 403, description = "Token not scoped for this"),
+    ),
+)]
+pub async fn backup_handler(State(state): State<AppState>, headers: HeaderMap) -> Response<Body> {
+    if let Err(status) = state
+        .admin_authorized(&headers, AdminScope::ConfigWrite)
+        .await
+    {
+        return Response::builder()
+            .status(status)
+            .body(Body::Empty)
+            .unwrap();
+    }
+
+    match run_backup(&state.aws, &state.config).await {
+        Ok(key) => Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(json!({ "key": key }).to_string()))
+            .unwrap(),
+        Err(e) => {
+            println!("Backup failed: {e}");
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::Empty)
+                .unwrap()
+        }
+    }
+}
+
+/// `POST /admin/restore` - restores the archive named by `{"key": ...}` from the backup bucket.
+#[utoipa::path(post, path = "/admin/restore", tag = "admin",
+    security(("admin_token" = [])),
+    request_body(content = Object, description = "`{\"key\": ...}` of the snapshot"),
+    responses(
+        (status = 200, description = "Restored"),
+        (status = 400, description = "Invalid request or snapshot"),
+        (status = 401, description = "Token missing or unknown"),
+        (status = 403, description = "Token not scoped for this"),
+    ),
+)]
+pub async fn restore_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: String,
+) -> Response<Body> {
+    if let Err(status) = state
+        .admin_authorized(&headers, AdminScope::ConfigWrite)
+        .await
+    {
+        return Response::builder()
+            .status(status)
+            .body(Body::Empty)
+            .unwrap();
+    }
+
+    let request: RestoreRequest = match serde_json::from_str(&body) {
+        Ok(r) => r,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(format!("Invalid restore request: {e}")))
+                .unwrap();
+        }
+    };
+
+    match run_restore(&state.aws, &state.config, &request.key).await {
+        Ok(restored) => Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(json!({ "restored": restored }).to_string()))
+            .unwrap(),
+        Err(e) => {
+            println!("Restore failed: {e}");
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::Empty)
+                .unwrap()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use anyhow::Result;
+    use aws_sdk_dynamodb::{
+        Client,
+        operation::{put_item::PutItemOutput, scan::ScanOutput},
+        primitives::Blob,
+        types::AttributeValue,
+    };
+    use aws_sdk_s3::operation::put_object::PutObjectOutput;
+    use aws_smithy_mocks::{Rule, RuleMode, mock, mock_client};
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use crate::backup::{
+        Snapshot, attribute_from_json, attribute_to_json, restore, snapshot, upload,
+    };
+
+    fn tables() -> Vec<(String, String)> {
+        vec![
+            ("duck_rewards".into(), "ducks-table".into()),
+            ("state".into(), "state-table".into()),
+        ]
+    }
+
+    #[test]
+    fn attributes_round_trip_through_json() -> Result<()> {
+        let value = AttributeValue::M(HashMap::from([
+            ("name".to_string(), AttributeValue::S("John".into())),
+            (
+                "counts".to_string(),
+                AttributeValue::L(vec![
+                    AttributeValue::N("3".into()),
+                    AttributeValue::Bool(true),
+                ]),
+            ),
+            (
+                "digest".to_string(),
+                AttributeValue::B(Blob::new(vec![0, 159, 255])),
+            ),
+        ]));
+
+        let json = attribute_to_json(&value)?;
+
+        assert_eq!(json["M"]["name"], json!({"S": "John"}));
+        assert_eq!(json["M"]["digest"], json!({"B": "AJ//"}));
+        assert_eq!(attribute_from_json(&json)?, value);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn snapshot_scans_every_page_of_every_table() -> Result<()> {
+        let first_page: Rule = mock!(Client::scan)
+            .match_requests(|r| {
+                r.table_name() == Some("ducks-table") && r.exclusive_start_key().is_none()
+            })
+            .then_output(|| {
+                ScanOutput::builder()
+                    .items(HashMap::from([(
+                        "message_id".to_string(),
+                        AttributeValue::S("m1".into()),
+                    )]))
+                    .last_evaluated_key("message_id", AttributeValue::S("m1".into()))
+                    .build()
+            });
+        let second_page: Rule = mock!(Client::scan)
+            .match_requests(|r| {
+                r.table_name() == Some("ducks-table") && r.exclusive_start_key().is_some()
+            })
+            .then_output(|| {
+                ScanOutput::builder()
+                    .items(HashMap::from([(
+                        "message_id".to_string(),
+                        AttributeValue::S("m2".into()),
+                    )]))
+                    .build()
+            });
+        let state_scan: Rule = mock!(Client::scan)
+            .match_requests(|r| r.table_name() == Some("state-table"))
+            .then_output(|| ScanOutput::builder().build());
+
+        let client = mock_client!(
+            aws_sdk_dynamodb,
+            RuleMode::MatchAny,
+            [&first_page, &second_page, &state_scan]
+        );
+
+        let snapshot = snapshot(&client, &tables()).await?;
+
+        assert_eq!(snapshot.tables["duck_rewards"].len(), 2);
+        assert_eq!(snapshot.tables["state"].len(), 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn restore_writes_items_into_configured_tables_only() -> Result<()> {
+        let put_rule: Rule = mock!(Client::put_item)
+            .match_requests(|r| r.table_name() == Some("state-table"))
+            .then_output(|| PutItemOutput::builder().build());
+        let client = mock_client!(aws_sdk_dynamodb, [&put_rule]);
+
+        let snapshot = Snapshot {
+            created_at: "2026-10-16T12:00:00+00:00".into(),
+            tables: BTreeMap::from([
+                (
+                    "state".to_string(),
+                    vec![
+                        json!({"pk": {"S": "cursor#default"}, "cursor": {"N": "2"}})
+                            .as_object()
+                            .unwrap()
+                            .clone(),
+                    ],
+                ),
+                (
+                    "quotes".to_string(),
+                    vec![json!({"pk": {"S": "q1"}}).as_object().unwrap().clone()],
+                ),
+            ]),
+        };
+
+        let restored = restore(&client, &snapshot, &tables()).await?;
+
+        assert_eq!(restored, 1);
+        assert_eq!(put_rule.num_calls(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn upload_puts_snapshot_in_bucket() -> Result<()> {
+        let put_rule: Rule = mock!(aws_sdk_s3::Client::put_object)
+            .match_requests(|r| {
+                r.bucket() == Some("backups-bucket")
+                    && r.key() == Some("backups/robochick-20261016T120000Z.json")
+            })
+            .then_output(|| PutObjectOutput::builder().build());
+        let client = mock_client!(aws_sdk_s3, [&put_rule]);
+
+        let snapshot = Snapshot {
+            created_at: "2026-10-16T12:00:00+00:00".into(),
+            tables: BTreeMap::new(),
+        };
+
+        let key = upload(&client, "backups-bucket", &snapshot).await?;
+
+        assert_eq!(key, "backups/robochick-20261016T120000Z.json");
+        assert_eq!(put_rule.num_calls(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,164 @@
+use std::{sync::Mutex, time::Duration};
+
+use tokio::sync::oneshot;
+
+use crate::config::AppConfig;
+
+/// Longest chat message Twitch accepts, in characters.
+pub const MAX_MESSAGE_LEN: usize = 500;
+
+/// Result of sending a batch, shared by every message in it.
+pub type BatchResult = Result<String, String>;
+
+struct Pending {
+    msg: String,
+    /// Channel the message goes to. Only messages to the same channel are joined.
+    channel: String,
+    reply: oneshot::Sender<BatchResult>,
+}
+
+/// Collects the messages sent within a short window and joins them into as few chat messages
+/// as fit, so bursts of redemptions and follow-ups use fewer API calls.
+pub struct ChatBatcher {
+    window: Duration,
+    separator: String,
+    pending: Mutex<Vec<Pending>>,
+}
+
+impl ChatBatcher {
+    pub fn new(window: Duration, separator: String) -> Self {
+        ChatBatcher {
+            window,
+            separator,
+            pending: Mutex::new(vec![]),
+        }
+    }
+
+    /// Batching as set up in `config`, off unless `CHAT_BATCH_WINDOW_MS` is above 0.
+    pub fn from_config(config: &AppConfig) -> Option<ChatBatcher> {
+        match config.chat_batch_window_ms {
+            0 => None,
+            ms => Some(ChatBatcher::new(
+                Duration::from_millis(ms),
+                config.chat_batch_separator.clone(),
+            )),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Queues `msg` for `channel`, returning where its batch's result will arrive and whether
+    /// the caller started the channel's batch, in which case it sends the batch once the window
+    /// has passed.
+    pub fn enqueue(&self, msg: &str, channel: &str) -> (oneshot::Receiver<BatchResult>, bool) {
+        let (reply, result) = oneshot::channel();
+        let mut pending = self.pending.lock().unwrap();
+        let first = !pending.iter().any(|p| p.channel == channel);
+        pending.push(Pending {
+            msg: msg.to_string(),
+            channel: channel.to_string(),
+            reply,
+        });
+        (result, first)
+    }
+
+    /// Takes everything queued for `channel`, joined into messages, each with the replies
+    /// waiting on it.
+    pub fn take(&self, channel: &str) -> Vec<(String, Vec<oneshot::Sender<BatchResult>>)> {
+        let pending: Vec<Pending> = {
+            let mut queued = self.pending.lock().unwrap();
+            let (pending, rest) = std::mem::take(&mut *queued)
+                .into_iter()
+                .partition(|p| p.channel == channel);
+            *queued = rest;
+            pending
+        };
+        let msgs: Vec<&str> = pending.iter().map(|p| p.msg.as_str()).collect();
+        let sizes: Vec<usize> = join(&msgs, &self.separator, MAX_MESSAGE_LEN)
+            .iter()
+            .map(Vec::len)
+            .collect();
+
+        let mut pending = pending.into_iter();
+        sizes
+            .into_iter()
+            .map(|n| {
+                let batch: Vec<Pending> = pending.by_ref().take(n).collect();
+                let joined = batch
+                    .iter()
+                    .map(|p| p.msg.as_str())
+                    .collect::<Vec<_>>()
+                    .join(&self.separator);
+                (joined, batch.into_iter().map(|p| p.reply).collect())
+            })
+            .collect()
+    }
+}
+
+/// Groups consecutive messages so each group joined with `separator` stays within `max_len`
+/// characters. Messages already longer than that go on their own.
+pub fn join<'a>(msgs: &[&'a str], separator: &str, max_len: usize) -> Vec<Vec<&'a str>> {
+    let mut groups: Vec<Vec<&str>> = vec![];
+    let mut len = 0;
+    for msg in msgs {
+        let msg_len = msg.chars().count();
+        match groups.last_mut() {
+            Some(group) if len + separator.chars().count() + msg_len <= max_len => {
+                group.push(msg);
+                len += separator.chars().count() + msg_len;
+            }
+            _ => {
+                groups.push(vec![msg]);
+                len = msg_len;
+            }
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::batch::{ChatBatcher, join};
+
+    #[test]
+    fn join_fills_groups_up_to_max_len() {
+        let long = "x".repeat(12);
+
+        let groups = join(&["one", "two", "three", &long, "four"], " | ", 12);
+
+        assert_eq!(
+            groups,
+            vec![
+                vec!["one", "two"],
+                vec!["three"],
+                vec![long.as_str()],
+                vec!["four"]
+            ]
+        );
+    }
+
+    #[test]
+    fn take_joins_queued_messages_and_first_caller_flushes() {
+        let batcher = ChatBatcher::new(Duration::from_millis(50), " | ".into());
+
+        let (_, first) = batcher.enqueue("Bok", "chicken");
+        let (_, second) = batcher.enqueue("Bok bok", "chicken");
+        let (_, other_channel) = batcher.enqueue("Behave", "mods");
+        let batches = batcher.take("chicken");
+
+        assert!(first);
+        assert!(!second);
+        assert!(other_channel);
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].0, "Bok | Bok bok");
+        assert_eq!(batches[0].1.len(), 2);
+        assert!(batcher.enqueue("Again", "chicken").1);
+        assert_eq!(batcher.take("mods")[0].0, "Behave");
+    }
+}
@@ -0,0 +1,50 @@
+//! The per-event hot path, exposed for the criterion benches in `benches/`. Not part of the
+//! bot's API.
+
+use std::fmt::Debug;
+
+use anyhow::{Result, anyhow};
+use axum::http::HeaderMap;
+use fastrand::Rng;
+
+use crate::{
+    config::AppConfig,
+    handler::event_handler::EventHandler,
+    migrate,
+    robochick::twitch::{
+        MessageBuilder, MessageComponents, REWARD_COST, REWARD_PROMPT, REWARD_TITLE, Robochick,
+        TemplateContext,
+    },
+    types::twitch::RewardRedeemed,
+};
+
+/// A parsed message config, opaque outside the crate.
+pub struct Components(MessageComponents);
+
+/// Checks the EventSub signature headers of `payload`.
+pub fn verify_signature(payload: &[u8], headers: &HeaderMap, config: &AppConfig) -> Result<()> {
+    EventHandler::verify(payload, headers, config)
+}
+
+/// Deserializes a channel point redemption notification.
+pub fn parse_redemption(payload: &str) -> Result<impl Debug> {
+    Ok(serde_json::from_str::<RewardRedeemed>(payload)?)
+}
+
+/// Reads a message config of any schema version.
+pub fn parse_config(text: &str) -> Result<Components> {
+    Ok(Components(migrate::parse_config(text)?))
+}
+
+/// Builds a message from the config's default group, with the placeholders a redemption fills
+/// in.
+pub fn build_message(components: &Components, rng: &mut Rng) -> Result<String> {
+    let mut context = TemplateContext::default();
+    context.insert(REWARD_TITLE, "Feed the mods");
+    context.insert(REWARD_COST, "500");
+    context.insert(REWARD_PROMPT, "Bok bok");
+
+    Robochick::build_from_templates(&components.0, &context, rng)
+        .map(|built| built.message)
+        .map_err(|e| anyhow!("Failed to build message: {e}"))
+}
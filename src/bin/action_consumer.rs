@@ -0,0 +1,36 @@
+use aws_lambda_events::event::sqs::{SqsBatchResponse, SqsEvent};
+use lambda_runtime::{Error, LambdaEvent, service_fn};
+use robochick_rs::{action, app::App, client::WebClient, config::AppConfig, version};
+
+/// Consumes the action queue, reporting failed messages individually so the rest of the
+/// batch isn't retried with them. Needs `ReportBatchItemFailures` on the event source mapping.
+async fn handle(
+    event: LambdaEvent<SqsEvent>,
+    client: &WebClient,
+    config: &AppConfig,
+) -> Result<SqsBatchResponse, Error> {
+    let messages: Vec<(String, String)> = event
+        .payload
+        .records
+        .into_iter()
+        .map(|r| (r.message_id.unwrap_or_default(), r.body.unwrap_or_default()))
+        .collect();
+
+    let mut response = SqsBatchResponse::default();
+    for id in action::run_queued(&messages, client, config).await {
+        response.add_failure(id);
+    }
+
+    Ok(response)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    println!("Starting the action consumer of {}", version::build_info());
+    let app = App::builder(AppConfig::from_env()).build().await;
+
+    lambda_runtime::run(service_fn(|event| {
+        handle(event, app.web_client(), app.config())
+    }))
+    .await
+}
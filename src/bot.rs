@@ -0,0 +1,69 @@
+use anyhow::{Result, anyhow};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::config::AppConfig;
+
+/// A StreamElements bot a scenario group posts as, instead of the one set up in the env. Lets
+/// moderation-style messages come from a different account than the chicken.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct SeBot {
+    /// StreamElements channel id the bot speaks in.
+    pub(crate) channel: String,
+    /// Names the `SE_BOT_JWT_<NAME>` env var holding the bot's JWT. Without it the JWT is
+    /// found like the default bot's: `SE_JWT`, or the channel's secret under
+    /// `SE_JWT_SECRET_PREFIX`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) jwt: Option<String>,
+}
+
+impl SeBot {
+    /// `config` with the bot's channel and JWT swapped in. Errors when the named JWT isn't set.
+    pub fn apply(&self, config: &AppConfig) -> Result<AppConfig> {
+        let mut config = AppConfig {
+            twitch_channel_id: self.channel.clone(),
+            ..config.clone()
+        };
+        if let Some(name) = &self.jwt {
+            let jwt = config.se_bot_jwts.get(&name.to_lowercase()).ok_or(anyhow!(
+                "No JWT for bot {name}, set SE_BOT_JWT_{}",
+                name.to_uppercase()
+            ))?;
+            config.se_jwt = Some(jwt.clone());
+            config.se_jwt_secret_prefix = None;
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use pretty_assertions::assert_eq;
+
+    use crate::{bot::SeBot, config::AppConfig};
+
+    #[test]
+    fn apply_swaps_in_channel_and_named_jwt() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.se_jwt_secret_prefix = Some("robochick/se-jwt/".into());
+        config.se_bot_jwts.insert("mods".into(), "mods-jwt".into());
+        let bot = SeBot {
+            channel: "mod_channel".into(),
+            jwt: Some("Mods".into()),
+        };
+
+        let applied = bot.apply(&config)?;
+
+        assert_eq!(applied.twitch_channel_id, "mod_channel");
+        assert_eq!(applied.se_jwt.as_deref(), Some("mods-jwt"));
+        assert_eq!(applied.se_jwt_secret_prefix, None);
+        let unknown = SeBot {
+            jwt: Some("nope".into()),
+            ..bot
+        };
+        assert!(unknown.apply(&config).is_err());
+        Ok(())
+    }
+}
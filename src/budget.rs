@@ -0,0 +1,141 @@
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Caps how many redemptions of a reward get a message. The first redemption past the cap
+/// posts `full_message` and pauses the reward, later ones are ignored.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct Budget {
+    pub max_messages: u64,
+    #[serde(default)]
+    pub per: BudgetWindow,
+    #[serde(default = "default_full_message")]
+    pub full_message: String,
+    /// Unpauses the reward this long after it was paused. Hourly budgets default to the start
+    /// of the next hour, per-stream ones stay paused until unpaused by hand.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resume_after_secs: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetWindow {
+    #[default]
+    Hour,
+    /// Counted per broadcast, using the id of the stream that's live.
+    Stream,
+}
+
+fn default_full_message() -> String {
+    "The chicken is full! Come back later.".into()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BudgetCheck {
+    Within,
+    /// This redemption used the budget up.
+    JustFull,
+    AlreadyFull,
+}
+
+/// How long a stream's counter is kept, longer than any broadcast.
+const STREAM_BUCKET_TTL_SECS: u64 = 48 * 3600;
+
+impl Budget {
+    /// Name of the counter for the current window, e.g. `hour#2026-10-16T14` or
+    /// `stream#40952121085`. Per-stream budgets have no bucket without a live stream, so
+    /// offline redemptions and failed stream lookups aren't counted.
+    pub fn bucket(&self, now: DateTime<Utc>, stream_id: Option<&str>) -> Option<String> {
+        match self.per {
+            BudgetWindow::Hour => Some(format!("hour#{}", now.format("%Y-%m-%dT%H"))),
+            BudgetWindow::Stream => stream_id.map(|id| format!("stream#{id}")),
+        }
+    }
+
+    /// How long a bucket's counter is kept once it's created, so finished windows don't
+    /// pile up in the state store.
+    pub fn bucket_ttl_secs(&self) -> u64 {
+        match self.per {
+            BudgetWindow::Hour => 3600,
+            BudgetWindow::Stream => STREAM_BUCKET_TTL_SECS,
+        }
+    }
+
+    /// `count` includes the redemption being checked.
+    pub fn check(&self, count: u64) -> BudgetCheck {
+        if count <= self.max_messages {
+            BudgetCheck::Within
+        } else if count == self.max_messages + 1 {
+            BudgetCheck::JustFull
+        } else {
+            BudgetCheck::AlreadyFull
+        }
+    }
+
+    /// Seconds until a paused reward should be unpaused, if it should be at all.
+    pub fn resume_delay_secs(&self, now: DateTime<Utc>) -> Option<u64> {
+        if let Some(secs) = self.resume_after_secs {
+            return Some(secs);
+        }
+
+        match self.per {
+            BudgetWindow::Hour => {
+                let hour_start = now.duration_trunc(Duration::hours(1)).ok()?;
+                let next_hour = hour_start + Duration::hours(1);
+                Some((next_hour - now).num_seconds().max(0) as u64)
+            }
+            BudgetWindow::Stream => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use pretty_assertions::assert_eq;
+
+    use crate::budget::{Budget, BudgetCheck, BudgetWindow};
+
+    fn budget(per: BudgetWindow) -> Budget {
+        serde_json::from_value(serde_json::json!({"max_messages": 2, "per": per})).unwrap()
+    }
+
+    #[test]
+    fn check_reports_the_redemption_that_fills_the_budget() {
+        let budget = budget(BudgetWindow::Hour);
+
+        assert_eq!(budget.check(2), BudgetCheck::Within);
+        assert_eq!(budget.check(3), BudgetCheck::JustFull);
+        assert_eq!(budget.check(4), BudgetCheck::AlreadyFull);
+    }
+
+    #[test]
+    fn bucket_uses_hour_or_stream_and_skips_offline_streams() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 16, 14, 25, 0).unwrap();
+
+        assert_eq!(
+            budget(BudgetWindow::Hour).bucket(now, Some("123")),
+            Some("hour#2026-10-16T14".to_string())
+        );
+        assert_eq!(
+            budget(BudgetWindow::Stream).bucket(now, Some("123")),
+            Some("stream#123".to_string())
+        );
+        assert_eq!(budget(BudgetWindow::Stream).bucket(now, None), None);
+    }
+
+    #[test]
+    fn resume_delay_defaults_to_next_hour_for_hourly_budgets() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 16, 14, 25, 0).unwrap();
+
+        assert_eq!(
+            budget(BudgetWindow::Hour).resume_delay_secs(now),
+            Some(35 * 60)
+        );
+        assert_eq!(budget(BudgetWindow::Stream).resume_delay_secs(now), None);
+
+        let mut fixed = budget(BudgetWindow::Stream);
+        fixed.resume_after_secs = Some(7200);
+        assert_eq!(fixed.resume_delay_secs(now), Some(7200));
+    }
+}
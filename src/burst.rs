@@ -0,0 +1,125 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Catches a viewer, or the whole chat, redeeming a reward over and over within a few seconds.
+/// The redemption that goes past a limit posts `message` in place of the rest of the window's
+/// messages, and later ones in the window are ignored.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct BurstGuard {
+    #[serde(default = "default_window_secs")]
+    pub window_secs: u64,
+    /// Redemptions of a reward by one viewer within a window that get a message each.
+    #[serde(default = "default_per_user")]
+    pub per_user: u64,
+    /// Redemptions of a reward by everyone within a window that get a message each. No limit
+    /// when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    /// Posted once a limit is passed, with `{count}` and the redeemer as `{user_name}`.
+    #[serde(default = "default_message")]
+    pub message: String,
+    /// Pauses the reward for this long once a limit is passed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pause_secs: Option<u64>,
+}
+
+fn default_window_secs() -> u64 {
+    10
+}
+
+fn default_per_user() -> u64 {
+    3
+}
+
+fn default_message() -> String {
+    "x{count} feedings!".into()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BurstCheck {
+    Within,
+    /// This redemption went past a limit, the `count`th in the window.
+    Started {
+        count: u64,
+    },
+    Ongoing,
+}
+
+impl BurstGuard {
+    /// Names of the viewer's and everyone's counters for the current window of a reward.
+    pub fn counters(&self, reward_id: &str, user_id: &str, now: DateTime<Utc>) -> (String, String) {
+        let window = now.timestamp() / self.window_secs.max(1) as i64;
+        (
+            format!("burst#{reward_id}#{user_id}#{window}"),
+            format!("burst#{reward_id}#{window}"),
+        )
+    }
+
+    /// Both counts include the redemption being checked. A burst across chat is announced
+    /// once, even as single viewers go past their own limit during it.
+    pub fn check(&self, user_count: u64, total_count: u64) -> BurstCheck {
+        let over_total = self.total.is_some_and(|t| total_count > t);
+        if self.total.is_some_and(|t| total_count == t + 1) {
+            BurstCheck::Started { count: total_count }
+        } else if over_total {
+            BurstCheck::Ongoing
+        } else if user_count == self.per_user + 1 {
+            BurstCheck::Started { count: user_count }
+        } else if user_count > self.per_user {
+            BurstCheck::Ongoing
+        } else {
+            BurstCheck::Within
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use pretty_assertions::assert_eq;
+
+    use crate::burst::{BurstCheck, BurstGuard};
+
+    fn guard(total: Option<u64>) -> BurstGuard {
+        serde_json::from_value(serde_json::json!({"per_user": 2, "total": total})).unwrap()
+    }
+
+    #[test]
+    fn one_viewer_past_their_limit_starts_a_burst() {
+        let guard = guard(None);
+
+        assert_eq!(guard.check(2, 2), BurstCheck::Within);
+        assert_eq!(guard.check(3, 3), BurstCheck::Started { count: 3 });
+        assert_eq!(guard.check(4, 4), BurstCheck::Ongoing);
+    }
+
+    #[test]
+    fn chat_wide_bursts_are_announced_once() {
+        let guard = guard(Some(4));
+
+        assert_eq!(guard.check(1, 4), BurstCheck::Within);
+        assert_eq!(guard.check(1, 5), BurstCheck::Started { count: 5 });
+        assert_eq!(guard.check(3, 6), BurstCheck::Ongoing);
+    }
+
+    #[test]
+    fn counters_change_with_the_window() {
+        let guard = guard(None);
+        let start = Utc.with_ymd_and_hms(2026, 10, 16, 12, 0, 0).unwrap();
+
+        let (user, total) = guard.counters("reward-1", "9001", start);
+        assert_eq!(user, "burst#reward-1#9001#179215200");
+        assert_eq!(total, "burst#reward-1#179215200");
+        assert_eq!(
+            guard.counters("reward-1", "9001", start + chrono::Duration::seconds(9)),
+            (user.clone(), total)
+        );
+        assert_ne!(
+            guard
+                .counters("reward-1", "9001", start + chrono::Duration::seconds(10))
+                .0,
+            user
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// A small in-process cache whose entries expire after a fixed time-to-live.
+///
+/// Lambda keeps the process around between invocations while the container is warm, so this
+/// is enough to avoid repeating the same upstream call on every event.
+pub struct TtlCache<K, V> {
+    ttl: Duration,
+    entries: Mutex<HashMap<K, (Instant, V)>>,
+}
+
+impl<K: Eq + Hash, V: Clone> TtlCache<K, V> {
+    pub fn new(ttl: Duration) -> TtlCache<K, V> {
+        TtlCache {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        let mut entries = self.entries.lock().ok()?;
+
+        match entries.get(key) {
+            Some((inserted_at, val)) if inserted_at.elapsed() < self.ttl => Some(val.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Inserts `val`, dropping the entries that have expired so keys that are never read
+    /// again don't pile up.
+    pub fn insert(&self, key: K, val: V) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.retain(|_, (inserted_at, _)| inserted_at.elapsed() < self.ttl);
+            entries.insert(key, (Instant::now(), val));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::cache::TtlCache;
+
+    #[test]
+    fn get_returns_inserted_value_before_expiry() {
+        let cache: TtlCache<String, Vec<String>> = TtlCache::new(Duration::from_secs(60));
+        cache.insert("chatters".into(), vec!["John".into()]);
+
+        assert_eq!(cache.get(&"chatters".into()), Some(vec!["John".into()]));
+    }
+
+    #[test]
+    fn get_returns_none_after_expiry() {
+        let cache: TtlCache<String, String> = TtlCache::new(Duration::ZERO);
+        cache.insert("key".into(), "value".into());
+
+        assert_eq!(cache.get(&"key".into()), None);
+    }
+}
@@ -0,0 +1,183 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{client::HelixCaller, config::AppConfig, state::StateStore};
+
+/// Title and category of the channel as of the last `channel.update`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ChannelInfo {
+    pub title: String,
+    pub category_id: String,
+    pub category_name: String,
+}
+
+impl ChannelInfo {
+    fn from_event(event: &Value) -> ChannelInfo {
+        let field = |name: &str| event[name].as_str().unwrap_or_default().to_string();
+        ChannelInfo {
+            title: field("title"),
+            category_id: field("category_id"),
+            category_name: field("category_name"),
+        }
+    }
+}
+
+/// A `channel.update` event with what changed added for rules to check: `title_changed`,
+/// `category_changed` and the `previous_title`, `previous_category_id` and
+/// `previous_category_name`. The new title and category are remembered for the next update.
+/// Before the first update is remembered, both count as changed.
+pub async fn track_update(state: &dyn StateStore, event: &Value) -> Value {
+    let current = ChannelInfo::from_event(event);
+    let previous = match stored_info(state).await {
+        Ok(previous) => previous,
+        Err(e) => {
+            println!("Failed to read the last channel update: {e}");
+            None
+        }
+    };
+    if let Err(e) = save_info(state, &current).await {
+        println!("Failed to store the channel update: {e}");
+    }
+
+    let mut event = event.clone();
+    let Some(fields) = event.as_object_mut() else {
+        return event;
+    };
+    fields.insert(
+        "title_changed".into(),
+        previous
+            .as_ref()
+            .is_none_or(|p| p.title != current.title)
+            .into(),
+    );
+    fields.insert(
+        "category_changed".into(),
+        previous
+            .as_ref()
+            .is_none_or(|p| p.category_id != current.category_id)
+            .into(),
+    );
+    let previous = previous.unwrap_or_default();
+    fields.insert("previous_title".into(), previous.title.into());
+    fields.insert("previous_category_id".into(), previous.category_id.into());
+    fields.insert(
+        "previous_category_name".into(),
+        previous.category_name.into(),
+    );
+    event
+}
+
+/// Category id the stream is in as of the last `channel.update`. Until there's been one, it's
+/// read from Helix and remembered, so groups for particular games work straight after setup.
+pub async fn current_category(
+    state: &dyn StateStore,
+    helix: &impl HelixCaller,
+    config: &AppConfig,
+) -> Option<String> {
+    match stored_info(state).await {
+        Ok(Some(info)) => return Some(info.category_id),
+        Ok(None) => {}
+        Err(e) => {
+            println!("Failed to read the channel's category: {e}");
+            return None;
+        }
+    }
+
+    let info = match helix.get_channel_info(config).await {
+        Ok(info) => info,
+        Err(e) => {
+            println!("Failed to look up the channel's category: {e}");
+            return None;
+        }
+    };
+    if let Err(e) = save_info(state, &info).await {
+        println!("Failed to store the channel's category: {e}");
+    }
+    Some(info.category_id)
+}
+
+async fn stored_info(state: &dyn StateStore) -> Result<Option<ChannelInfo>> {
+    match state.channel_info().await? {
+        Some(entry) => Ok(Some(serde_json::from_str(&entry)?)),
+        None => Ok(None),
+    }
+}
+
+async fn save_info(state: &dyn StateStore, info: &ChannelInfo) -> Result<()> {
+    state.set_channel_info(&serde_json::to_string(info)?).await
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use mockito::Server;
+
+    use crate::{
+        channel::{current_category, track_update},
+        client::WebClient,
+        config::AppConfig,
+        state::MemoryStore,
+    };
+
+    #[tokio::test]
+    async fn track_update_compares_with_the_last_update() -> Result<()> {
+        let store = MemoryStore::default();
+        let update = |title: &str, id: &str, name: &str| json!({"broadcaster_user_id": "1337", "title": title, "category_id": id, "category_name": name});
+
+        let first = track_update(&store, &update("Chickens!", "509658", "Just Chatting")).await;
+        let retitled =
+            track_update(&store, &update("More chickens", "509658", "Just Chatting")).await;
+        let switched = track_update(&store, &update("More chickens", "27471", "Minecraft")).await;
+
+        assert_eq!(first["title_changed"], true);
+        assert_eq!(first["category_changed"], true);
+        assert_eq!(first["previous_category_name"], "");
+        assert_eq!(retitled["title_changed"], true);
+        assert_eq!(retitled["category_changed"], false);
+        assert_eq!(retitled["previous_title"], "Chickens!");
+        assert_eq!(switched["title_changed"], false);
+        assert_eq!(switched["category_changed"], true);
+        assert_eq!(switched["previous_category_name"], "Just Chatting");
+        assert_eq!(switched["category_name"], "Minecraft");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn current_category_is_seeded_from_helix_once() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut server = Server::new_async().await;
+        let config = AppConfig::from_env()
+            .with_twitch_api_host(format!("{}/helix/", server.url()))
+            .with_twitch_access_token("access-token".into());
+        let channel = server
+            .mock("GET", "/helix/channels?broadcaster_id=1337")
+            .with_body(r#"{"data": [{"broadcaster_id": "1337", "title": "Chickens!", "game_id": "27471", "game_name": "Minecraft"}]}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let store = MemoryStore::default();
+        let client = WebClient::new(reqwest::Client::new());
+
+        assert_eq!(
+            current_category(&store, &client, &config).await.as_deref(),
+            Some("27471")
+        );
+        assert_eq!(
+            current_category(&store, &client, &config).await.as_deref(),
+            Some("27471")
+        );
+
+        channel.assert_async().await;
+        let updated = track_update(
+            &store,
+            &json!({"title": "Chickens!", "category_id": "27471", "category_name": "Minecraft"}),
+        )
+        .await;
+        assert_eq!(updated["category_changed"], false);
+        Ok(())
+    }
+}
@@ -0,0 +1,545 @@
+use std::{fs, str::FromStr};
+
+use anyhow::{Result, anyhow};
+use schemars::schema_for;
+
+#[cfg(feature = "backups")]
+use crate::backup;
+use crate::{
+    client::{WebClient, http_client},
+    config::AppConfig,
+    config_tests,
+    convert::{self, BotFormat, Converted},
+    db, env_template, load_aws_config,
+    migrate::{self, SCHEMA_VERSION},
+    reward::mod_feeder::read_config,
+    robochick::twitch::MessageComponents,
+    simulate, state_store,
+    stats_export::{self, DEFAULT_RANGE, ExportFormat},
+    subscribe, web_client,
+};
+
+/// One-off commands run from the command line instead of starting the server.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    /// Prints the JSON Schema for the message components config.
+    Schema,
+    /// Converts a StreamElements or Nightbot command export into message components.
+    Import { format: BotFormat, path: String },
+    /// Converts a message components config into a StreamElements or Nightbot command export.
+    Export { format: BotFormat, path: String },
+    /// Snapshots the bot's tables into the backup bucket.
+    Backup,
+    /// Restores a snapshot from the backup bucket.
+    Restore { key: String },
+    /// Shows what the bot would post for an EventSub notification, without calling anything.
+    /// The config defaults to `MESSAGE_COMPONENTS_CONFIG_PATH`.
+    Simulate {
+        event: String,
+        config: Option<String>,
+    },
+    /// Rewrites a message config to the newest schema version, listing what changed. The
+    /// config defaults to `MESSAGE_COMPONENTS_CONFIG_PATH`.
+    MigrateConfig { path: Option<String> },
+    /// Creates, replaces and deletes EventSub subscriptions to match the config, or with
+    /// `dry_run` only prints what it would do. `rotate_secret` recreates all of them, to move
+    /// them over to a new secret.
+    Subscribe { dry_run: bool, rotate_secret: bool },
+    /// Prints every env var the bot reads, as a commented `.env` file or with `json` as JSON.
+    EnvTemplate { json: bool },
+    /// Checks a message config reads and runs the tests in its `tests` section. The config
+    /// defaults to `MESSAGE_COMPONENTS_CONFIG_PATH`.
+    ValidateConfig { path: Option<String> },
+    /// Prints redemption, mod and scenario stats over `range` (e.g. `30d`) as CSV or JSON,
+    /// like `GET /admin/stats/export`.
+    StatsExport { format: ExportFormat, range: String },
+    /// Creates the DynamoDB tables, TTLs and SQLite schema the config needs, or upgrades them
+    /// to this build's schema version.
+    DbMigrate,
+}
+
+impl Command {
+    /// Reads the command from the arguments after the binary name. No arguments means the
+    /// server should start as usual.
+    pub fn parse(args: &[String]) -> Result<Option<Command>> {
+        match args.first().map(String::as_str) {
+            None => Ok(None),
+            Some("schema") => Ok(Some(Command::Schema)),
+            Some("import") => {
+                let (format, path) = format_and_path(&args[1..])?;
+                Ok(Some(Command::Import { format, path }))
+            }
+            Some("export") => {
+                let (format, path) = format_and_path(&args[1..])?;
+                Ok(Some(Command::Export { format, path }))
+            }
+            Some("backup") => Ok(Some(Command::Backup)),
+            Some("restore") => match &args[1..] {
+                [key] => Ok(Some(Command::Restore { key: key.clone() })),
+                _ => Err(anyhow!("Usage: restore <backup key>")),
+            },
+            Some("simulate") => match &args[1..] {
+                [flag, event] if flag == "--event" => Ok(Some(Command::Simulate {
+                    event: event.clone(),
+                    config: None,
+                })),
+                [flag, event, config_flag, config]
+                    if flag == "--event" && config_flag == "--config" =>
+                {
+                    Ok(Some(Command::Simulate {
+                        event: event.clone(),
+                        config: Some(config.clone()),
+                    }))
+                }
+                _ => Err(anyhow!(
+                    "Usage: simulate --event <notification.json> [--config <config.json>]"
+                )),
+            },
+            Some("migrate-config") => match &args[1..] {
+                [] => Ok(Some(Command::MigrateConfig { path: None })),
+                [path] => Ok(Some(Command::MigrateConfig {
+                    path: Some(path.clone()),
+                })),
+                _ => Err(anyhow!("Usage: migrate-config [config.json]")),
+            },
+            Some("subscribe") => {
+                let (mut dry_run, mut rotate_secret) = (false, false);
+                for flag in &args[1..] {
+                    match flag.as_str() {
+                        "--dry-run" => dry_run = true,
+                        "--rotate-secret" => rotate_secret = true,
+                        _ => return Err(anyhow!("Usage: subscribe [--dry-run] [--rotate-secret]")),
+                    }
+                }
+                Ok(Some(Command::Subscribe {
+                    dry_run,
+                    rotate_secret,
+                }))
+            }
+            Some("env-template") => match &args[1..] {
+                [] => Ok(Some(Command::EnvTemplate { json: false })),
+                [flag] if flag == "--json" => Ok(Some(Command::EnvTemplate { json: true })),
+                _ => Err(anyhow!("Usage: env-template [--json]")),
+            },
+            Some("validate-config") => match &args[1..] {
+                [] => Ok(Some(Command::ValidateConfig { path: None })),
+                [path] => Ok(Some(Command::ValidateConfig {
+                    path: Some(path.clone()),
+                })),
+                _ => Err(anyhow!("Usage: validate-config [config.json]")),
+            },
+            Some("stats-export") => {
+                let usage = || anyhow!("Usage: stats-export [--format csv|json] [--range 30d]");
+                let mut format = ExportFormat::Csv;
+                let mut range = DEFAULT_RANGE.to_string();
+                for pair in args[1..].chunks(2) {
+                    match pair {
+                        [flag, value] if flag == "--format" => {
+                            format = value.parse().map_err(|_| usage())?
+                        }
+                        [flag, value] if flag == "--range" => range = value.clone(),
+                        _ => return Err(usage()),
+                    }
+                }
+                Ok(Some(Command::StatsExport { format, range }))
+            }
+            Some("db") => match &args[1..] {
+                [sub] if sub == "migrate" => Ok(Some(Command::DbMigrate)),
+                _ => Err(anyhow!("Usage: db migrate")),
+            },
+            Some(other) => Err(anyhow!("Unknown command: {other}")),
+        }
+    }
+}
+
+pub async fn run(command: Command) -> Result<()> {
+    match command {
+        Command::Schema => println!("{}", message_components_schema()?),
+        Command::Import { format, path } => {
+            let converted = convert::import(format, &fs::read_to_string(path)?)?;
+            print_converted(Converted {
+                output: serde_json::to_string_pretty(&converted.output)?,
+                skipped: converted.skipped,
+            });
+        }
+        Command::Export { format, path } => {
+            let components = migrate::parse_config(&fs::read_to_string(path)?)?;
+            print_converted(convert::export(format, &components)?);
+        }
+        #[cfg(feature = "backups")]
+        Command::Backup => {
+            let key = backup::run_backup(&load_aws_config().await, &AppConfig::from_env()).await?;
+            println!("{key}");
+        }
+        #[cfg(feature = "backups")]
+        Command::Restore { key } => {
+            let restored =
+                backup::run_restore(&load_aws_config().await, &AppConfig::from_env(), &key).await?;
+            println!("Restored {restored} items");
+        }
+        #[cfg(not(feature = "backups"))]
+        Command::Backup | Command::Restore { .. } => {
+            return Err(anyhow!("This build has no backups feature"));
+        }
+        Command::Simulate { event, config } => {
+            let config = config_path(config, "Pass --config")?;
+            let components = migrate::parse_config(&fs::read_to_string(config)?)?;
+            let notification = serde_json::from_str(&fs::read_to_string(event)?)?;
+            print!("{}", simulate::simulate(&components, &notification)?);
+        }
+        Command::MigrateConfig { path } => {
+            let path = config_path(path, "Pass the config file")?;
+            let original: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+            let migrated = migrate::migrate(original.clone())?;
+            if migrated.from == SCHEMA_VERSION {
+                println!("{path} is already schema version {SCHEMA_VERSION}");
+                return Ok(());
+            }
+
+            // Checks the result reads before replacing the file.
+            serde_json::from_value::<MessageComponents>(migrated.config.clone())?;
+            fs::write(
+                &path,
+                serde_json::to_string_pretty(&migrated.config)? + "\n",
+            )?;
+            println!(
+                "Migrated {path} from schema version {} to {SCHEMA_VERSION}",
+                migrated.from
+            );
+            for change in migrated.changes(&original) {
+                println!(
+                    "  {}: {} -> {}",
+                    change.path, change.current, change.submitted
+                );
+            }
+        }
+        Command::Subscribe {
+            dry_run,
+            rotate_secret,
+        } => {
+            let config = AppConfig::from_env();
+            let components = read_config(&config.message_components_config_path.clone().into())?;
+            let client = match config.eventsub_secret_prefix {
+                Some(_) => web_client(&config, &load_aws_config().await),
+                None => WebClient::new(http_client(&config)),
+            };
+            let plan =
+                subscribe::subscribe(&client, &config, &components, dry_run, rotate_secret).await?;
+            print!("{plan}");
+            if dry_run && !plan.steps.is_empty() {
+                println!("Dry run, nothing was changed. Run subscribe without --dry-run to apply.");
+            }
+        }
+        Command::EnvTemplate { json } => match json {
+            true => println!("{}", env_template::json()?),
+            false => print!("{}", env_template::dotenv()),
+        },
+        Command::ValidateConfig { path } => {
+            let path = config_path(path, "Pass the config file")?;
+            let components = migrate::parse_config(&fs::read_to_string(&path)?)?;
+            let results = config_tests::run_all(&components);
+            for result in &results {
+                println!("{result}");
+            }
+            let failed = results.iter().filter(|r| !r.passed()).count();
+            println!(
+                "{path} is valid, {} tests passed, {failed} failed",
+                results.len() - failed
+            );
+            if failed > 0 {
+                return Err(anyhow!("{failed} config tests failed"));
+            }
+        }
+        Command::StatsExport { format, range } => {
+            let config = AppConfig::from_env();
+            let client = aws_sdk_dynamodb::Client::new(&load_aws_config().await);
+            let state = state_store(&config, &client)?;
+            let components = read_config(&config.message_components_config_path.clone().into());
+            let export = stats_export::export(
+                &client,
+                &config.duck_rewards_table_name,
+                state.as_ref(),
+                components.as_ref().ok(),
+                stats_export::parse_range(&range)?,
+                chrono::Utc::now(),
+            )
+            .await?;
+            print!("{}", stats_export::render(&export, format)?);
+        }
+        Command::DbMigrate => {
+            let client = aws_sdk_dynamodb::Client::new(&load_aws_config().await);
+            for line in db::migrate(&AppConfig::from_env(), &client).await? {
+                println!("{line}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The config file given on the command line, or `MESSAGE_COMPONENTS_CONFIG_PATH`.
+fn config_path(path: Option<String>, hint: &str) -> Result<String> {
+    match path {
+        Some(path) => Ok(path),
+        None => std::env::var("MESSAGE_COMPONENTS_CONFIG_PATH")
+            .map_err(|_| anyhow!("{hint} or set MESSAGE_COMPONENTS_CONFIG_PATH")),
+    }
+}
+
+fn format_and_path(args: &[String]) -> Result<(BotFormat, String)> {
+    match args {
+        [format, path] => Ok((
+            BotFormat::from_str(format).map_err(|_| anyhow!("Unknown bot format: {format}"))?,
+            path.clone(),
+        )),
+        _ => Err(anyhow!("Usage: <streamelements|nightbot> <file>")),
+    }
+}
+
+/// The converted file goes to stdout so it can be redirected, notes about skipped entries go
+/// to stderr.
+fn print_converted(converted: Converted<String>) {
+    println!("{}", converted.output);
+    for note in converted.skipped {
+        eprintln!("Skipped {note}");
+    }
+}
+
+pub fn message_components_schema() -> Result<String> {
+    let schema = schema_for!(MessageComponents);
+    Ok(serde_json::to_string_pretty(&schema)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use serde_json::Value;
+
+    use crate::{
+        cli::{Command, message_components_schema},
+        convert::BotFormat,
+        stats_export::ExportFormat,
+    };
+
+    #[test]
+    fn parse_returns_none_without_args() -> Result<()> {
+        assert_eq!(Command::parse(&[])?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_reads_schema_command() -> Result<()> {
+        assert_eq!(Command::parse(&["schema".into()])?, Some(Command::Schema));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_reads_import_and_export_commands() -> Result<()> {
+        let import = Command::parse(&["import".into(), "nightbot".into(), "cmds.json".into()])?;
+        let export = Command::parse(&[
+            "export".into(),
+            "streamelements".into(),
+            "config.json".into(),
+        ])?;
+
+        assert_eq!(
+            import,
+            Some(Command::Import {
+                format: BotFormat::Nightbot,
+                path: "cmds.json".into()
+            })
+        );
+        assert_eq!(
+            export,
+            Some(Command::Export {
+                format: BotFormat::StreamElements,
+                path: "config.json".into()
+            })
+        );
+        assert!(Command::parse(&["import".into(), "moobot".into(), "x".into()]).is_err());
+        assert!(Command::parse(&["export".into()]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_reads_backup_and_restore_commands() -> Result<()> {
+        assert_eq!(Command::parse(&["backup".into()])?, Some(Command::Backup));
+        assert_eq!(
+            Command::parse(&["restore".into(), "backups/robochick.json".into()])?,
+            Some(Command::Restore {
+                key: "backups/robochick.json".into()
+            })
+        );
+        assert!(Command::parse(&["restore".into()]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_reads_simulate_command() -> Result<()> {
+        assert_eq!(
+            Command::parse(&["simulate".into(), "--event".into(), "cheer.json".into()])?,
+            Some(Command::Simulate {
+                event: "cheer.json".into(),
+                config: None
+            })
+        );
+        assert_eq!(
+            Command::parse(&[
+                "simulate".into(),
+                "--event".into(),
+                "cheer.json".into(),
+                "--config".into(),
+                "config.json".into()
+            ])?,
+            Some(Command::Simulate {
+                event: "cheer.json".into(),
+                config: Some("config.json".into())
+            })
+        );
+        assert!(Command::parse(&["simulate".into(), "cheer.json".into()]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_reads_migrate_config_command() -> Result<()> {
+        assert_eq!(
+            Command::parse(&["migrate-config".into()])?,
+            Some(Command::MigrateConfig { path: None })
+        );
+        assert_eq!(
+            Command::parse(&["migrate-config".into(), "config.json".into()])?,
+            Some(Command::MigrateConfig {
+                path: Some("config.json".into())
+            })
+        );
+        assert!(Command::parse(&["migrate-config".into(), "a".into(), "b".into()]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_reads_subscribe_command() -> Result<()> {
+        assert_eq!(
+            Command::parse(&["subscribe".into()])?,
+            Some(Command::Subscribe {
+                dry_run: false,
+                rotate_secret: false
+            })
+        );
+        assert_eq!(
+            Command::parse(&["subscribe".into(), "--dry-run".into()])?,
+            Some(Command::Subscribe {
+                dry_run: true,
+                rotate_secret: false
+            })
+        );
+        assert_eq!(
+            Command::parse(&[
+                "subscribe".into(),
+                "--rotate-secret".into(),
+                "--dry-run".into()
+            ])?,
+            Some(Command::Subscribe {
+                dry_run: true,
+                rotate_secret: true
+            })
+        );
+        assert!(Command::parse(&["subscribe".into(), "--plan".into()]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_reads_env_template_command() -> Result<()> {
+        assert_eq!(
+            Command::parse(&["env-template".into()])?,
+            Some(Command::EnvTemplate { json: false })
+        );
+        assert_eq!(
+            Command::parse(&["env-template".into(), "--json".into()])?,
+            Some(Command::EnvTemplate { json: true })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_reads_validate_config_command() -> Result<()> {
+        assert_eq!(
+            Command::parse(&["validate-config".into(), "config.json".into()])?,
+            Some(Command::ValidateConfig {
+                path: Some("config.json".into())
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_reads_stats_export_flags_in_any_order() -> Result<()> {
+        assert_eq!(
+            Command::parse(&["stats-export".into()])?,
+            Some(Command::StatsExport {
+                format: ExportFormat::Csv,
+                range: "30d".into()
+            })
+        );
+        assert_eq!(
+            Command::parse(&[
+                "stats-export".into(),
+                "--range".into(),
+                "12w".into(),
+                "--format".into(),
+                "json".into()
+            ])?,
+            Some(Command::StatsExport {
+                format: ExportFormat::Json,
+                range: "12w".into()
+            })
+        );
+        assert!(Command::parse(&["stats-export".into(), "--format".into(), "xls".into()]).is_err());
+        assert!(Command::parse(&["stats-export".into(), "--range".into()]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_reads_db_migrate_command() -> Result<()> {
+        assert_eq!(
+            Command::parse(&["db".into(), "migrate".into()])?,
+            Some(Command::DbMigrate)
+        );
+        assert!(Command::parse(&["db".into()]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_returns_err_for_unknown_command() {
+        assert!(Command::parse(&["hatch".into()]).is_err());
+    }
+
+    #[test]
+    fn message_components_schema_describes_config_fields() -> Result<()> {
+        let schema: Value = serde_json::from_str(&message_components_schema()?)?;
+
+        let properties = &schema["properties"];
+        assert!(properties["scenarios"].is_object());
+        assert!(properties["mods"].is_object());
+        assert!(properties["groups"].is_object());
+        assert!(properties["selection"].is_object());
+        assert!(schema["$defs"]["Scenario"]["properties"]["follow_up"].is_object());
+        assert!(schema["$defs"]["Scenario"]["properties"]["vote"].is_object());
+        Ok(())
+    }
+
+    #[test]
+    fn bundled_config_has_no_fields_outside_schema() -> Result<()> {
+        let schema: Value = serde_json::from_str(&message_components_schema()?)?;
+        let config: Value = serde_json::from_str(&std::fs::read_to_string(
+            "resources/config/message_components.json",
+        )?)?;
+
+        for key in config.as_object().unwrap().keys() {
+            assert!(
+                schema["properties"][key].is_object(),
+                "{key} is not in the schema"
+            );
+        }
+        Ok(())
+    }
+}
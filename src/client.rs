@@ -1,156 +1,2272 @@
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::HashMap,
+    ops::RangeInclusive,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use anyhow::{Result, anyhow};
-use reqwest::{Body, Client, Url, header::AUTHORIZATION};
+use async_trait::async_trait;
+use chrono::Utc;
+use reqwest::{
+    Body, Client, Method, RequestBuilder, Url,
+    header::{AUTHORIZATION, HeaderMap},
+};
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
 
-use crate::config::AppConfig;
+#[cfg(feature = "youtube")]
+use crate::youtube::YouTubeChatSink;
+use crate::{
+    audit::{self, AuditEntry},
+    batch::ChatBatcher,
+    cache::TtlCache,
+    channel::ChannelInfo,
+    config::AppConfig,
+    eventsub_secrets::EventsubSecretStore,
+    notify::{Notifier, NotifyEvent},
+    permissions::UserRoles,
+    ratelimit::RateLimiter,
+    run_detached,
+    schedule::ScheduleSegment,
+    se_jwt::SeJwtStore,
+    sink::{self, BackendStatus, ChatBackend},
+    state::StateStore,
+    trace,
+    types::twitch::{Subscription, SubscriptionRequest},
+    users::{LOGINS_PER_REQUEST, TwitchUser},
+};
 
+/// The HTTP client shared by every API the bot calls, with connection pooling as set up in
+/// `config`. Bursts of redemptions reuse warm connections instead of opening one each.
+pub fn http_client(config: &AppConfig) -> Client {
+    let mut builder = Client::builder()
+        .pool_idle_timeout(Duration::from_secs(config.http_pool_idle_timeout_secs));
+    if let Some(max_idle) = config.http_pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(max_idle);
+    }
+    if config.http2_prior_knowledge {
+        builder = builder.http2_prior_knowledge();
+    }
+    builder.build().unwrap_or_else(|e| {
+        println!("Failed to build tuned HTTP client, using the defaults: {e}");
+        Client::new()
+    })
+}
+
+/// Result of a chat send, along with the HTTP status if a response came back at all.
+type Sent = (Option<u16>, Result<String>);
+
+#[derive(Clone)]
 pub struct WebClient {
     client: Client,
+    se_jwts: Option<Arc<SeJwtStore>>,
+    eventsub_secrets: Option<Arc<EventsubSecretStore>>,
+    sink_health: Arc<TtlCache<ChatBackend, BackendStatus>>,
+    audit: Option<Arc<dyn StateStore>>,
+    #[cfg(feature = "youtube")]
+    youtube: Option<Arc<YouTubeChatSink>>,
+    notifier: Option<Arc<Notifier>>,
+    helix_limit: Arc<RateLimiter>,
+    se_limit: Arc<RateLimiter>,
+    batcher: Option<Arc<ChatBatcher>>,
+}
+
+impl WebClient {
+    pub fn new(client: Client) -> WebClient {
+        WebClient {
+            client,
+            helix_limit: Arc::new(RateLimiter::new("Helix")),
+            se_limit: Arc::new(RateLimiter::new("StreamElements")),
+            se_jwts: None,
+            eventsub_secrets: None,
+            sink_health: Arc::new(TtlCache::new(sink::PROBE_INTERVAL)),
+            audit: None,
+            #[cfg(feature = "youtube")]
+            youtube: None,
+            notifier: None,
+            batcher: None,
+        }
+    }
+
+    /// Tells the broadcaster privately when a chat message can't be sent.
+    pub fn with_notifier(self, notifier: Arc<Notifier>) -> WebClient {
+        WebClient {
+            notifier: Some(notifier),
+            ..self
+        }
+    }
+
+    pub fn notifier(&self) -> Option<Arc<Notifier>> {
+        self.notifier.clone()
+    }
+
+    /// The pooled HTTP client the APIs are called through, for other requests to share.
+    pub fn http(&self) -> &Client {
+        &self.client
+    }
+
+    /// Mirrors every chat message to the channel's YouTube live chat.
+    #[cfg(feature = "youtube")]
+    pub fn with_youtube(self, youtube: Arc<YouTubeChatSink>) -> WebClient {
+        WebClient {
+            youtube: Some(youtube),
+            ..self
+        }
+    }
+
+    /// Logs every chat message sent to the audit log in `state`.
+    pub fn with_audit(self, state: Arc<dyn StateStore>) -> WebClient {
+        WebClient {
+            audit: Some(state),
+            ..self
+        }
+    }
+
+    /// Joins messages sent close together into fewer chat messages, as set up by `batcher`.
+    pub fn with_batching(self, batcher: Option<ChatBatcher>) -> WebClient {
+        WebClient {
+            batcher: batcher.map(Arc::new),
+            ..self
+        }
+    }
+
+    /// Reads the StreamElements JWT for each channel from `se_jwts` instead of `SE_JWT`.
+    pub fn with_se_jwts(self, se_jwts: Arc<SeJwtStore>) -> WebClient {
+        WebClient {
+            se_jwts: Some(se_jwts),
+            ..self
+        }
+    }
+
+    /// Creates each EventSub subscription with a secret of its own, kept in `eventsub_secrets`.
+    pub fn with_eventsub_secrets(self, eventsub_secrets: Arc<EventsubSecretStore>) -> WebClient {
+        WebClient {
+            eventsub_secrets: Some(eventsub_secrets),
+            ..self
+        }
+    }
+
+    pub fn eventsub_secrets(&self) -> Option<Arc<EventsubSecretStore>> {
+        self.eventsub_secrets.clone()
+    }
+
+    /// The store is skipped when a group's bot brings its own JWT, see [`SeBot::apply`].
+    ///
+    /// [`SeBot::apply`]: crate::bot::SeBot::apply
+    pub(crate) async fn se_jwt(&self, config: &AppConfig) -> Result<String> {
+        let store = self
+            .se_jwts
+            .as_ref()
+            .filter(|_| config.se_jwt_secret_prefix.is_some());
+        match (store, config.se_jwt.as_ref()) {
+            (Some(store), _) => store.jwt(&config.twitch_channel_id).await,
+            (None, Some(jwt)) => Ok(jwt.clone()),
+            (None, None) => Err(anyhow!("Missing Streamelements JWT")),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct MessageRequest {
+    message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Chatter {
+    user_id: String,
+    user_login: String,
+    user_name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct HelixResponse<T> {
+    data: Vec<T>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct PollChoiceRequest {
+    title: String,
+}
+
+/// Longest poll title Helix accepts, in characters.
+const POLL_TITLE_MAX_CHARS: usize = 60;
+
+/// Longest poll choice Helix accepts, in characters.
+const POLL_CHOICE_MAX_CHARS: usize = 25;
+
+/// How many choices a poll can have.
+const POLL_CHOICES: RangeInclusive<usize> = 2..=5;
+
+/// How long a poll can run for, in seconds.
+const POLL_DURATION_SECS: RangeInclusive<u64> = 15..=1800;
+
+/// Checks a poll against Helix's limits, so a vote that can't be started fails with what's
+/// wrong with it rather than a bare 400.
+fn validate_poll(title: &str, choices: &[String], duration_secs: u64) -> Result<()> {
+    if title.trim().is_empty() || title.chars().count() > POLL_TITLE_MAX_CHARS {
+        return Err(anyhow!(
+            "Poll title must be 1 to {POLL_TITLE_MAX_CHARS} characters: {title}"
+        ));
+    }
+    if !POLL_CHOICES.contains(&choices.len()) {
+        return Err(anyhow!(
+            "Poll needs {} to {} choices, not {}",
+            POLL_CHOICES.start(),
+            POLL_CHOICES.end(),
+            choices.len()
+        ));
+    }
+    if let Some(choice) = choices
+        .iter()
+        .find(|c| c.trim().is_empty() || c.chars().count() > POLL_CHOICE_MAX_CHARS)
+    {
+        return Err(anyhow!(
+            "Poll choices must be 1 to {POLL_CHOICE_MAX_CHARS} characters: {choice}"
+        ));
+    }
+    if !POLL_DURATION_SECS.contains(&duration_secs) {
+        return Err(anyhow!(
+            "Poll must run for {} to {} seconds, not {duration_secs}",
+            POLL_DURATION_SECS.start(),
+            POLL_DURATION_SECS.end()
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CreatePollRequest {
+    broadcaster_id: String,
+    title: String,
+    choices: Vec<PollChoiceRequest>,
+    duration: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct PollChoice {
+    id: String,
+    title: String,
+    #[serde(default)]
+    votes: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Poll {
+    id: String,
+    choices: Vec<PollChoice>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Stream {
+    id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Channel {
+    title: String,
+    game_id: String,
+    game_name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ScheduleResponse {
+    data: Schedule,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Schedule {
+    #[serde(default)]
+    segments: Option<Vec<ScheduleSegment>>,
+}
+
+#[derive(Serialize, Debug)]
+struct UpdateRewardRequest {
+    is_paused: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Subscriber {
+    user_id: String,
+    tier: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ChannelUser {
+    user_id: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct AppAccessToken {
+    access_token: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct SubscriptionPage {
+    data: Vec<Subscription>,
+    #[serde(default)]
+    pagination: Pagination,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct Pagination {
+    cursor: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct WebhookTransport<'a> {
+    method: &'static str,
+    callback: &'a str,
+    secret: &'a str,
+}
+
+#[derive(Serialize, Debug)]
+struct CreateSubscriptionRequest<'a> {
+    r#type: &'a str,
+    version: &'a str,
+    condition: &'a crate::types::twitch::Condition,
+    transport: WebhookTransport<'a>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CreatedSubscription {
+    id: String,
+}
+
+#[derive(Serialize, Debug)]
+struct SendChatMessageRequest<'a> {
+    broadcaster_id: &'a str,
+    sender_id: &'a str,
+    message: &'a str,
+}
+
+#[derive(Deserialize, Debug)]
+struct DropReason {
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct SentChatMessage {
+    message_id: String,
+    is_sent: bool,
+    drop_reason: Option<DropReason>,
+}
+
+#[derive(Serialize, Debug)]
+struct UpdateRedemptionRequest {
+    status: &'static str,
+}
+
+#[async_trait]
+pub trait StreamelementsCaller: Send + Sync {
+    async fn say(&self, msg: &str, config: &AppConfig) -> Result<String>;
+
+    /// Adds `amount` StreamElements loyalty points to the viewer `user_login`, or takes them
+    /// away when it's negative.
+    async fn award_points(&self, user_login: &str, amount: i64, config: &AppConfig) -> Result<()>;
+}
+
+#[async_trait]
+pub trait HelixCaller: Send + Sync {
+    /// Display names of the users currently connected to the broadcaster's chat.
+    async fn get_chatters(&self, config: &AppConfig) -> Result<Vec<String>>;
+
+    /// Starts a poll in the broadcaster's channel, returning its id.
+    async fn create_poll(
+        &self,
+        title: &str,
+        choices: &[String],
+        duration_secs: u64,
+        config: &AppConfig,
+    ) -> Result<String>;
+
+    /// Vote counts for each choice of a poll, in the order the choices were created.
+    async fn get_poll_results(
+        &self,
+        poll_id: &str,
+        config: &AppConfig,
+    ) -> Result<Vec<(String, u64)>>;
+
+    /// Id of the broadcaster's live stream, or `None` while offline.
+    async fn get_stream_id(&self, config: &AppConfig) -> Result<Option<String>>;
+
+    /// The broadcaster's current stream title and category.
+    async fn get_channel_info(&self, config: &AppConfig) -> Result<ChannelInfo>;
+
+    /// Upcoming streams on the broadcaster's schedule, empty when there's no schedule.
+    async fn get_schedule(&self, config: &AppConfig) -> Result<Vec<ScheduleSegment>>;
+
+    /// Pauses or unpauses one of the broadcaster's channel point rewards.
+    async fn set_reward_paused(
+        &self,
+        reward_id: &str,
+        paused: bool,
+        config: &AppConfig,
+    ) -> Result<()>;
+
+    /// Subscription tier, VIP and moderator status of a user in the broadcaster's channel.
+    async fn get_user_roles(&self, user_id: &str, config: &AppConfig) -> Result<UserRoles>;
+
+    /// Users with the given logins, at most [`LOGINS_PER_REQUEST`]. Logins without a user are
+    /// left out.
+    async fn get_users(&self, logins: &[String], config: &AppConfig) -> Result<Vec<TwitchUser>>;
+
+    /// Cancels a redemption, refunding its points. Only works for rewards created by this
+    /// app's client id.
+    async fn cancel_redemption(
+        &self,
+        reward_id: &str,
+        redemption_id: &str,
+        config: &AppConfig,
+    ) -> Result<()>;
+
+    /// Creates an EventSub webhook subscription, signed with the shared secret or one of its
+    /// own when `EVENTSUB_SECRET_PREFIX` is set. Uses an app access token, as Twitch requires
+    /// for webhooks.
+    async fn create_eventsub_subscription(
+        &self,
+        request: &SubscriptionRequest,
+        config: &AppConfig,
+    ) -> Result<()>;
+
+    /// Every EventSub subscription of the app, across all pages.
+    async fn list_eventsub_subscriptions(&self, config: &AppConfig) -> Result<Vec<Subscription>>;
+
+    async fn delete_eventsub_subscription(&self, id: &str, config: &AppConfig) -> Result<()>;
+}
+
+/// Everything the bot calls out to for chat and Twitch, so a client can be kept behind an
+/// `Arc<dyn Caller>` and picked at runtime.
+pub trait Caller: StreamelementsCaller + HelixCaller {}
+
+impl<T: StreamelementsCaller + HelixCaller + ?Sized> Caller for T {}
+
+#[async_trait]
+impl<T: StreamelementsCaller + ?Sized> StreamelementsCaller for Arc<T> {
+    async fn say(&self, msg: &str, config: &AppConfig) -> Result<String> {
+        (**self).say(msg, config).await
+    }
+
+    async fn award_points(&self, user_login: &str, amount: i64, config: &AppConfig) -> Result<()> {
+        (**self).award_points(user_login, amount, config).await
+    }
+}
+
+#[async_trait]
+impl<T: HelixCaller + ?Sized> HelixCaller for Arc<T> {
+    async fn get_chatters(&self, config: &AppConfig) -> Result<Vec<String>> {
+        (**self).get_chatters(config).await
+    }
+
+    async fn create_poll(
+        &self,
+        title: &str,
+        choices: &[String],
+        duration_secs: u64,
+        config: &AppConfig,
+    ) -> Result<String> {
+        (**self)
+            .create_poll(title, choices, duration_secs, config)
+            .await
+    }
+
+    async fn get_poll_results(
+        &self,
+        poll_id: &str,
+        config: &AppConfig,
+    ) -> Result<Vec<(String, u64)>> {
+        (**self).get_poll_results(poll_id, config).await
+    }
+
+    async fn get_stream_id(&self, config: &AppConfig) -> Result<Option<String>> {
+        (**self).get_stream_id(config).await
+    }
+
+    async fn get_channel_info(&self, config: &AppConfig) -> Result<ChannelInfo> {
+        (**self).get_channel_info(config).await
+    }
+
+    async fn get_schedule(&self, config: &AppConfig) -> Result<Vec<ScheduleSegment>> {
+        (**self).get_schedule(config).await
+    }
+
+    async fn set_reward_paused(
+        &self,
+        reward_id: &str,
+        paused: bool,
+        config: &AppConfig,
+    ) -> Result<()> {
+        (**self).set_reward_paused(reward_id, paused, config).await
+    }
+
+    async fn get_user_roles(&self, user_id: &str, config: &AppConfig) -> Result<UserRoles> {
+        (**self).get_user_roles(user_id, config).await
+    }
+
+    async fn get_users(&self, logins: &[String], config: &AppConfig) -> Result<Vec<TwitchUser>> {
+        (**self).get_users(logins, config).await
+    }
+
+    async fn cancel_redemption(
+        &self,
+        reward_id: &str,
+        redemption_id: &str,
+        config: &AppConfig,
+    ) -> Result<()> {
+        (**self)
+            .cancel_redemption(reward_id, redemption_id, config)
+            .await
+    }
+
+    async fn create_eventsub_subscription(
+        &self,
+        request: &SubscriptionRequest,
+        config: &AppConfig,
+    ) -> Result<()> {
+        (**self).create_eventsub_subscription(request, config).await
+    }
+
+    async fn list_eventsub_subscriptions(&self, config: &AppConfig) -> Result<Vec<Subscription>> {
+        (**self).list_eventsub_subscriptions(config).await
+    }
+
+    async fn delete_eventsub_subscription(&self, id: &str, config: &AppConfig) -> Result<()> {
+        (**self).delete_eventsub_subscription(id, config).await
+    }
+}
+
+#[async_trait]
+impl StreamelementsCaller for WebClient {
+    /// Sends through the healthiest configured chat backend, batched with other messages when
+    /// batching is on.
+    async fn say(&self, msg: &str, config: &AppConfig) -> Result<String> {
+        let Some(batcher) = self.batcher.as_ref() else {
+            return self.say_now(msg, config).await;
+        };
+
+        let (result, first) = batcher.enqueue(msg, &config.twitch_channel_id);
+        if first {
+            // Flushed from a task of its own so the batch still goes out if this caller is
+            // dropped, e.g. by a handler timeout, rather than leaving later messages waiting.
+            let client = self.clone();
+            let batcher = batcher.clone();
+            let config = config.clone();
+            let event_id = audit::current_event_id();
+            let correlation_id = trace::current();
+            tokio::spawn(async move {
+                let flush = client.flush_batch(&batcher, &config);
+                let flush = async {
+                    match event_id {
+                        Some(id) => audit::with_event_id(id, flush).await,
+                        None => flush.await,
+                    }
+                };
+                match correlation_id {
+                    Some(id) => trace::with_correlation_id(id, flush).await,
+                    None => flush.await,
+                }
+            });
+        }
+        result
+            .await
+            .map_err(|_| anyhow!("Batched chat message was dropped"))?
+            .map_err(|e| anyhow!(e))
+    }
+
+    async fn award_points(&self, user_login: &str, amount: i64, config: &AppConfig) -> Result<()> {
+        let url = Url::parse(&config.se_api_host)?.join(&format!(
+            "kappa/v2/points/{}/{user_login}/{amount}",
+            config.twitch_channel_id
+        ))?;
+        let jwt = self.se_jwt(config).await?;
+        let request = self
+            .client
+            .put(url)
+            .bearer_auth(jwt)
+            .timeout(Duration::new(1, 0));
+
+        let _: serde_json::Value = WebClient::send_limited(&self.se_limit, request)
+            .await
+            .1
+            .map_err(|e| anyhow!("Failed to award {amount} points to {user_login}: {e}"))?;
+        Ok(())
+    }
+}
+
+impl WebClient {
+    /// Sends what's queued for the channel once the batch window has passed and answers every
+    /// message in each batch with how it went.
+    async fn flush_batch(&self, batcher: &ChatBatcher, config: &AppConfig) {
+        tokio::time::sleep(batcher.window()).await;
+        for (joined, replies) in batcher.take(&config.twitch_channel_id) {
+            let sent = self
+                .say_now(&joined, config)
+                .await
+                .map_err(|e| e.to_string());
+            for reply in replies {
+                let _ = reply.send(sent.clone());
+            }
+        }
+    }
+
+    async fn say_now(&self, msg: &str, config: &AppConfig) -> Result<String> {
+        let backend = self.chat_backend(config).await;
+        let sent_at = Utc::now();
+        let started = Instant::now();
+        let (status, result) = match backend {
+            ChatBackend::StreamElements => self.say_streamelements(msg, config).await,
+            ChatBackend::Helix => self.say_helix(msg, config).await,
+        };
+
+        self.audit(AuditEntry {
+            sent_at,
+            event_id: audit::current_event_id(),
+            message: msg.to_string(),
+            backend,
+            status,
+            latency_ms: started.elapsed().as_millis() as u64,
+            error: result.as_ref().err().map(|e| e.to_string()),
+        })
+        .await;
+
+        if let (Err(e), Some(notifier)) = (result.as_ref(), self.notifier.as_ref()) {
+            notifier
+                .notify(
+                    NotifyEvent::Error,
+                    &format!("Robochick couldn't send a chat message: {e}"),
+                )
+                .await;
+        }
+
+        // Mirrored from a task of its own so YouTube's latency isn't added to the Twitch post.
+        #[cfg(feature = "youtube")]
+        if let Some(youtube) = self.youtube.clone() {
+            let msg = msg.to_string();
+            tokio::spawn(async move {
+                if let Err(e) = youtube.say(&msg).await {
+                    println!("Failed to mirror message to YouTube: {e}");
+                }
+            });
+        }
+        result
+    }
 }
 
-impl WebClient {
-    pub fn new(client: Client) -> WebClient {
-        WebClient { client }
-    }
-}
+/// What the startup self-test posts.
+pub const SELF_TEST_MESSAGE: &str = "[test] Robochick self-test, please ignore";
+
+impl WebClient {
+    /// Posts [`SELF_TEST_MESSAGE`] to `channel_id` through the backend messages would go out
+    /// on, as its StreamElements channel id or Twitch user id depending on the backend. The
+    /// message isn't audited or mirrored.
+    pub async fn self_test(&self, channel_id: &str, config: &AppConfig) -> BackendStatus {
+        let backend = self.chat_backend(config).await;
+        let (_, result) = match backend {
+            ChatBackend::StreamElements => {
+                let config = AppConfig {
+                    twitch_channel_id: channel_id.to_string(),
+                    ..config.clone()
+                };
+                self.say_streamelements(SELF_TEST_MESSAGE, &config).await
+            }
+            ChatBackend::Helix => {
+                let config = AppConfig {
+                    broadcaster_user_id: channel_id.to_string(),
+                    ..config.clone()
+                };
+                self.say_helix(SELF_TEST_MESSAGE, &config).await
+            }
+        };
+
+        BackendStatus {
+            backend,
+            healthy: result.is_ok(),
+            checked_at: Utc::now(),
+            error: result.err().map(|e| e.to_string()),
+        }
+    }
+
+    /// A failed write is only logged, the message has already gone out.
+    async fn audit(&self, entry: AuditEntry) {
+        let Some(state) = self.audit.clone() else {
+            return;
+        };
+
+        let written =
+            run_detached(async move { audit::record(state.as_ref(), &entry).await }).await;
+        if let Err(e) = written.and_then(|written| written) {
+            println!("Failed to write audit entry: {e}");
+        }
+    }
+
+    async fn say_streamelements(&self, msg: &str, config: &AppConfig) -> Sent {
+        let url = match Url::parse(&config.se_api_host).and_then(|u| {
+            u.join(format!("kappa/v2/bot/{}/say", &config.twitch_channel_id).as_ref())
+        }) {
+            Ok(u) => u,
+            Err(e) => return (None, Err(e.into())),
+        };
+
+        let mut req_body: HashMap<String, String> = HashMap::new();
+        req_body.insert("message".to_string(), String::from(msg));
+
+        let jwt = match self.se_jwt(config).await {
+            Ok(jwt) => jwt,
+            Err(e) => return (None, Err(e)),
+        };
+
+        let request = self
+            .client
+            .post(url)
+            .bearer_auth(jwt)
+            .json(&req_body)
+            .timeout(Duration::new(1, 0));
+        match self.se_limit.send(request).await {
+            Ok(resp) => {
+                let status = Some(resp.status().as_u16());
+                if resp.status().is_success() {
+                    (
+                        status,
+                        resp.text()
+                            .await
+                            .map_err(|e| anyhow!("Failed to read response body")),
+                    )
+                } else {
+                    (
+                        status,
+                        Err(anyhow!(
+                            "Streamelemenst API returned error with status: {}",
+                            resp.status()
+                        )),
+                    )
+                }
+            }
+
+            Err(e) => (None, Err(e)),
+        }
+    }
+
+    async fn say_helix(&self, msg: &str, config: &AppConfig) -> Sent {
+        let sender_id = match config.helix_chat_sender_id.as_ref() {
+            Some(id) => id,
+            None => return (None, Err(anyhow!("Missing Helix chat sender id"))),
+        };
+        let request = match self.helix(Method::POST, "chat/messages", &[], config) {
+            Ok(r) => r.json(&SendChatMessageRequest {
+                broadcaster_id: &config.broadcaster_user_id,
+                sender_id,
+                message: msg,
+            }),
+            Err(e) => return (None, Err(e)),
+        };
+
+        let (status, sent) = self
+            .send_helix_with_status::<HelixResponse<SentChatMessage>>(request)
+            .await;
+        let result = sent.and_then(|sent| match sent.data.into_iter().next() {
+            Some(m) if m.is_sent => Ok(m.message_id),
+            Some(m) => Err(anyhow!(
+                "Twitch dropped the chat message: {}",
+                m.drop_reason.map(|r| r.message).unwrap_or_default()
+            )),
+            None => Err(anyhow!("Helix API returned no chat message")),
+        });
+        (status, result)
+    }
+
+    /// Chat backends set up in `config`, most preferred first.
+    fn chat_backends(config: &AppConfig) -> Vec<ChatBackend> {
+        let mut backends = vec![ChatBackend::StreamElements];
+        if config.helix_chat_sender_id.is_some() {
+            backends.push(ChatBackend::Helix);
+        }
+        backends
+    }
+
+    /// Only probes when there's more than one backend to choose from.
+    async fn chat_backend(&self, config: &AppConfig) -> ChatBackend {
+        let backends = WebClient::chat_backends(config);
+        if backends.len() == 1 {
+            return backends[0];
+        }
+
+        sink::choose(&self.backend_statuses(config).await).unwrap_or(backends[0])
+    }
+
+    /// Health of each configured chat backend, probing those not checked recently.
+    pub async fn backend_statuses(&self, config: &AppConfig) -> Vec<BackendStatus> {
+        let mut statuses = vec![];
+        for backend in WebClient::chat_backends(config) {
+            let status = match self.sink_health.get(&backend) {
+                Some(s) => s,
+                None => {
+                    let status = self.probe(backend, config).await;
+                    if !status.healthy {
+                        println!(
+                            "Chat backend {backend:?} is unhealthy: {}",
+                            status.error.as_deref().unwrap_or_default()
+                        );
+                    }
+                    self.sink_health.insert(backend, status.clone());
+                    status
+                }
+            };
+            statuses.push(status);
+        }
+        statuses
+    }
+
+    async fn probe(&self, backend: ChatBackend, config: &AppConfig) -> BackendStatus {
+        let result = match backend {
+            ChatBackend::StreamElements => self.probe_streamelements(config).await,
+            ChatBackend::Helix => self.probe_helix(config).await,
+        };
+
+        BackendStatus {
+            backend,
+            healthy: result.is_ok(),
+            checked_at: Utc::now(),
+            error: result.err().map(|e| e.to_string()),
+        }
+    }
+
+    /// Sends a `HEAD` request to `host` so a connection to it is waiting in the pool before
+    /// the first real request. Any response will do.
+    pub(crate) async fn open_connection(&self, host: &str) -> Result<()> {
+        self.client
+            .head(host)
+            .timeout(Duration::new(2, 0))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    /// Posts a probe notification to the bot's own EventSub endpoint, failing unless it's
+    /// answered with success.
+    pub(crate) async fn send_probe(
+        &self,
+        url: &str,
+        headers: HeaderMap,
+        body: String,
+    ) -> Result<()> {
+        let response = self
+            .client
+            .post(url)
+            .headers(headers)
+            .body(body)
+            .timeout(Duration::new(10, 0))
+            .send()
+            .await?;
+        let status = response.status();
+        match status.is_success() {
+            true => Ok(()),
+            false => Err(anyhow!(
+                "Probe was answered with {status}: {}",
+                response.text().await.unwrap_or_default()
+            )),
+        }
+    }
+
+    /// Checks the JWT still works by fetching the channel it belongs to.
+    async fn probe_streamelements(&self, config: &AppConfig) -> Result<()> {
+        let url = Url::parse(&config.se_api_host)?.join("kappa/v2/channels/me")?;
+        let jwt = self.se_jwt(config).await?;
+        let request = self
+            .client
+            .get(url)
+            .bearer_auth(jwt)
+            .timeout(Duration::new(1, 0));
+
+        let _: serde_json::Value = WebClient::send_limited(&self.se_limit, request).await.1?;
+        Ok(())
+    }
+
+    /// Checks the user access token with Twitch's validate endpoint.
+    async fn probe_helix(&self, config: &AppConfig) -> Result<()> {
+        let token = match config.twitch_access_token.as_ref() {
+            Some(t) => t,
+            None => return Err(anyhow!("Missing Twitch access token")),
+        };
+        let request = self
+            .client
+            .get(format!("{}/oauth2/validate", config.twitch_host))
+            .header(AUTHORIZATION, format!("OAuth {token}"))
+            .timeout(Duration::new(1, 0));
+
+        let _: serde_json::Value = self.send_helix(request).await?;
+        Ok(())
+    }
+}
+
+impl WebClient {
+    /// Starts a Helix request with the `Client-Id` and user access token headers set.
+    fn helix(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, &str)],
+        config: &AppConfig,
+    ) -> Result<RequestBuilder> {
+        let token = match config.twitch_access_token.as_ref() {
+            Some(t) => t,
+            None => return Err(anyhow!("Missing Twitch access token")),
+        };
+
+        let mut url = Url::parse(&config.twitch_api_host)?.join(path)?;
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+
+        Ok(self
+            .client
+            .request(method, url)
+            .bearer_auth(token)
+            .header("Client-Id", &config.twitch_client_id)
+            .timeout(Duration::new(1, 0)))
+    }
+
+    /// Fetches an app access token with the client credentials grant.
+    async fn app_access_token(&self, config: &AppConfig) -> Result<String> {
+        let secret = match config.twitch_client_secret.as_ref() {
+            Some(s) => s,
+            None => return Err(anyhow!("Missing Twitch client secret")),
+        };
+
+        let url = Url::parse_with_params(
+            &format!("{}/oauth2/token", config.twitch_host),
+            [
+                ("client_id", config.twitch_client_id.as_str()),
+                ("client_secret", secret.as_str()),
+                ("grant_type", "client_credentials"),
+            ],
+        )?;
+        let request = self.client.post(url).timeout(Duration::new(1, 0));
+
+        let token: AppAccessToken = self.send_helix(request).await?;
+        Ok(token.access_token)
+    }
+
+    async fn send_helix<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T> {
+        self.send_helix_with_status(request).await.1
+    }
+
+    async fn send_helix_with_status<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+    ) -> (Option<u16>, Result<T>) {
+        WebClient::send_limited(&self.helix_limit, request).await
+    }
+
+    /// Sends the request within `limiter`'s rate limit and deserializes the response.
+    async fn send_limited<T: DeserializeOwned>(
+        limiter: &RateLimiter,
+        request: RequestBuilder,
+    ) -> (Option<u16>, Result<T>) {
+        match limiter.send(request).await {
+            Ok(resp) => {
+                let status = Some(resp.status().as_u16());
+                if resp.status().is_success() {
+                    (
+                        status,
+                        resp.json::<T>().await.map_err(|e| {
+                            anyhow!("Failed to deserialize {} API response: {e}", limiter.api())
+                        }),
+                    )
+                } else {
+                    (
+                        status,
+                        Err(anyhow!(
+                            "{} API returned error with status: {}",
+                            limiter.api(),
+                            resp.status()
+                        )),
+                    )
+                }
+            }
+
+            Err(e) => (None, Err(e)),
+        }
+    }
+}
+
+#[async_trait]
+impl HelixCaller for WebClient {
+    async fn get_chatters(&self, config: &AppConfig) -> Result<Vec<String>> {
+        let request = self.helix(
+            Method::GET,
+            "chat/chatters",
+            &[
+                ("broadcaster_id", &config.broadcaster_user_id),
+                ("moderator_id", &config.broadcaster_user_id),
+                ("first", "1000"),
+            ],
+            config,
+        )?;
+
+        let chatters: HelixResponse<Chatter> = self.send_helix(request).await?;
+        Ok(chatters.data.into_iter().map(|c| c.user_name).collect())
+    }
+
+    async fn create_poll(
+        &self,
+        title: &str,
+        choices: &[String],
+        duration_secs: u64,
+        config: &AppConfig,
+    ) -> Result<String> {
+        validate_poll(title, choices, duration_secs)?;
+        let body = CreatePollRequest {
+            broadcaster_id: config.broadcaster_user_id.clone(),
+            title: title.to_string(),
+            choices: choices
+                .iter()
+                .map(|c| PollChoiceRequest { title: c.clone() })
+                .collect(),
+            duration: duration_secs,
+        };
+        let request = self.helix(Method::POST, "polls", &[], config)?.json(&body);
+
+        let polls: HelixResponse<Poll> = self.send_helix(request).await?;
+        match polls.data.into_iter().next() {
+            Some(poll) => Ok(poll.id),
+            None => Err(anyhow!("Helix API returned no poll")),
+        }
+    }
+
+    async fn get_poll_results(
+        &self,
+        poll_id: &str,
+        config: &AppConfig,
+    ) -> Result<Vec<(String, u64)>> {
+        let request = self.helix(
+            Method::GET,
+            "polls",
+            &[
+                ("broadcaster_id", &config.broadcaster_user_id),
+                ("id", poll_id),
+            ],
+            config,
+        )?;
+
+        let polls: HelixResponse<Poll> = self.send_helix(request).await?;
+        match polls.data.into_iter().next() {
+            Some(poll) => Ok(poll
+                .choices
+                .into_iter()
+                .map(|c| (c.title, c.votes))
+                .collect()),
+            None => Err(anyhow!("Poll {poll_id} not found")),
+        }
+    }
+
+    async fn get_stream_id(&self, config: &AppConfig) -> Result<Option<String>> {
+        let request = self.helix(
+            Method::GET,
+            "streams",
+            &[("user_id", &config.broadcaster_user_id)],
+            config,
+        )?;
+
+        let streams: HelixResponse<Stream> = self.send_helix(request).await?;
+        Ok(streams.data.into_iter().next().map(|s| s.id))
+    }
+
+    async fn get_channel_info(&self, config: &AppConfig) -> Result<ChannelInfo> {
+        let request = self.helix(
+            Method::GET,
+            "channels",
+            &[("broadcaster_id", &config.broadcaster_user_id)],
+            config,
+        )?;
+
+        let channels: HelixResponse<Channel> = self.send_helix(request).await?;
+        let channel = channels
+            .data
+            .into_iter()
+            .next()
+            .ok_or(anyhow!("Twitch returned no channel information"))?;
+        Ok(ChannelInfo {
+            title: channel.title,
+            category_id: channel.game_id,
+            category_name: channel.game_name,
+        })
+    }
+
+    async fn get_schedule(&self, config: &AppConfig) -> Result<Vec<ScheduleSegment>> {
+        let request = self.helix(
+            Method::GET,
+            "schedule",
+            &[("broadcaster_id", &config.broadcaster_user_id)],
+            config,
+        )?;
+
+        // Helix answers 404 for a channel that hasn't set up a schedule.
+        match self
+            .send_helix_with_status::<ScheduleResponse>(request)
+            .await
+        {
+            (Some(404), _) => Ok(vec![]),
+            (_, schedule) => Ok(schedule?.data.segments.unwrap_or_default()),
+        }
+    }
+
+    async fn set_reward_paused(
+        &self,
+        reward_id: &str,
+        paused: bool,
+        config: &AppConfig,
+    ) -> Result<()> {
+        let request = self
+            .helix(
+                Method::PATCH,
+                "channel_points/custom_rewards",
+                &[
+                    ("broadcaster_id", &config.broadcaster_user_id),
+                    ("id", reward_id),
+                ],
+                config,
+            )?
+            .json(&UpdateRewardRequest { is_paused: paused });
+
+        let _: HelixResponse<serde_json::Value> = self.send_helix(request).await?;
+        Ok(())
+    }
+
+    async fn get_user_roles(&self, user_id: &str, config: &AppConfig) -> Result<UserRoles> {
+        let query = [
+            ("broadcaster_id", config.broadcaster_user_id.as_str()),
+            ("user_id", user_id),
+        ];
+        let subscriptions = self.helix(Method::GET, "subscriptions", &query, config)?;
+        let vips = self.helix(Method::GET, "channels/vips", &query, config)?;
+        let moderators = self.helix(Method::GET, "moderation/moderators", &query, config)?;
+
+        let (subscriptions, vips, moderators) = tokio::try_join!(
+            self.send_helix::<HelixResponse<Subscriber>>(subscriptions),
+            self.send_helix::<HelixResponse<ChannelUser>>(vips),
+            self.send_helix::<HelixResponse<ChannelUser>>(moderators),
+        )?;
+
+        Ok(UserRoles {
+            sub_tier: subscriptions
+                .data
+                .into_iter()
+                .next()
+                .and_then(|s| s.tier.parse::<u16>().ok())
+                .map(|t| (t / 1000) as u8),
+            vip: !vips.data.is_empty(),
+            moderator: !moderators.data.is_empty(),
+            broadcaster: user_id == config.broadcaster_user_id,
+        })
+    }
+
+    async fn get_users(&self, logins: &[String], config: &AppConfig) -> Result<Vec<TwitchUser>> {
+        if logins.len() > LOGINS_PER_REQUEST {
+            return Err(anyhow!(
+                "Helix looks up at most {LOGINS_PER_REQUEST} users at once"
+            ));
+        }
+        let query: Vec<(&str, &str)> = logins.iter().map(|l| ("login", l.as_str())).collect();
+        let request = self.helix(Method::GET, "users", &query, config)?;
+
+        let users: HelixResponse<TwitchUser> = self.send_helix(request).await?;
+        Ok(users.data)
+    }
+
+    async fn cancel_redemption(
+        &self,
+        reward_id: &str,
+        redemption_id: &str,
+        config: &AppConfig,
+    ) -> Result<()> {
+        let request = self
+            .helix(
+                Method::PATCH,
+                "channel_points/custom_rewards/redemptions",
+                &[
+                    ("broadcaster_id", &config.broadcaster_user_id),
+                    ("reward_id", reward_id),
+                    ("id", redemption_id),
+                ],
+                config,
+            )?
+            .json(&UpdateRedemptionRequest { status: "CANCELED" });
+
+        let _: HelixResponse<serde_json::Value> = self.send_helix(request).await?;
+        Ok(())
+    }
+
+    async fn create_eventsub_subscription(
+        &self,
+        request: &SubscriptionRequest,
+        config: &AppConfig,
+    ) -> Result<()> {
+        let token = self.app_access_token(config).await?;
+        let url = Url::parse(&config.twitch_api_host)?.join("eventsub/subscriptions")?;
+        let secret = match self.eventsub_secrets.as_ref() {
+            Some(secrets) => secrets.generate().await?,
+            None => config.twitch_eventsub_subscription_secret.clone(),
+        };
+        let body = CreateSubscriptionRequest {
+            r#type: &request.r#type,
+            version: &request.version,
+            condition: &request.condition,
+            transport: WebhookTransport {
+                method: "webhook",
+                callback: &request.callback,
+                secret: &secret,
+            },
+        };
+        let request = self
+            .client
+            .post(url)
+            .bearer_auth(token)
+            .header("Client-Id", &config.twitch_client_id)
+            .timeout(Duration::new(1, 0))
+            .json(&body);
+
+        let created: HelixResponse<CreatedSubscription> = self.send_helix(request).await?;
+        if let Some(secrets) = self.eventsub_secrets.as_ref() {
+            let Some(subscription) = created.data.first() else {
+                return Err(anyhow!("Twitch didn't return the new subscription"));
+            };
+            secrets.save(&subscription.id, &secret).await?;
+        }
+        Ok(())
+    }
+
+    async fn list_eventsub_subscriptions(&self, config: &AppConfig) -> Result<Vec<Subscription>> {
+        let token = self.app_access_token(config).await?;
+        let url = Url::parse(&config.twitch_api_host)?.join("eventsub/subscriptions")?;
+        let mut subscriptions = vec![];
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut page_url = url.clone();
+            if let Some(after) = cursor.as_ref() {
+                page_url.query_pairs_mut().append_pair("after", after);
+            }
+            let request = self
+                .client
+                .get(page_url)
+                .bearer_auth(&token)
+                .header("Client-Id", &config.twitch_client_id)
+                .timeout(Duration::new(1, 0));
+
+            let page: SubscriptionPage = self.send_helix(request).await?;
+            subscriptions.extend(page.data);
+            match page.pagination.cursor {
+                Some(next) if !next.is_empty() => cursor = Some(next),
+                _ => return Ok(subscriptions),
+            }
+        }
+    }
+
+    async fn delete_eventsub_subscription(&self, id: &str, config: &AppConfig) -> Result<()> {
+        let token = self.app_access_token(config).await?;
+        let url = Url::parse_with_params(
+            Url::parse(&config.twitch_api_host)?
+                .join("eventsub/subscriptions")?
+                .as_str(),
+            [("id", id)],
+        )?;
+        let request = self
+            .client
+            .delete(url)
+            .bearer_auth(token)
+            .header("Client-Id", &config.twitch_client_id)
+            .timeout(Duration::new(1, 0));
+
+        let resp = self.helix_limit.send(request).await?;
+        if !resp.status().is_success() {
+            return Err(anyhow!(
+                "Helix API returned error with status: {}",
+                resp.status()
+            ));
+        }
+        if let Some(secrets) = self.eventsub_secrets.as_ref()
+            && let Err(e) = secrets.delete(id).await
+        {
+            println!("Failed to delete the secret of subscription {id}: {e}");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::needless_borrows_for_generic_args)]
+mod tests {
+    use anyhow::Result;
+    use dotenvy::dotenv;
+    use mockito::Server;
+    use reqwest::Client;
+    use std::path::PathBuf;
+
+    use crate::{
+        audit::{self, AuditEntry},
+        batch::ChatBatcher,
+        channel::ChannelInfo,
+        client::{Caller, HelixCaller, StreamelementsCaller, WebClient, validate_poll},
+        config::AppConfig,
+        eventsub_secrets::EventsubSecretStore,
+        permissions::{Role, UserRoles},
+        robochick::twitch::MessageComponents,
+        se_jwt::SeJwtStore,
+        secrets::SecretStore,
+        sink::ChatBackend,
+        state::{MemoryStore, StateStore},
+        types::twitch::SubscriptionRequest,
+        users::TwitchUser,
+    };
+    use aws_sdk_secretsmanager::operation::{
+        create_secret::CreateSecretOutput, get_random_password::GetRandomPasswordOutput,
+        get_secret_value::GetSecretValueOutput,
+    };
+    use aws_smithy_mocks::{RuleMode, mock, mock_client};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn say_makes_successful_request() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        let mut mock_server = Server::new_async().await;
+        config = config.with_se_api_host(format!("http://{}", mock_server.host_with_port()));
+
+        let response_body = r#"{
+            "status":200,
+            "channel":"example_channel_id",
+            "message":"Hello, World!",
+        }"#;
+
+        let expected_body = r#"{"message":"Hello, World!"}"#;
+        let mock = mock_server
+            .mock("POST", "/kappa/v2/bot/test_channel_id/say")
+            .match_header(
+                "Authorization",
+                mockito::Matcher::Exact(format!("Bearer {}", &config.se_jwt.as_ref().unwrap())),
+            )
+            .match_body(expected_body)
+            .with_body(&response_body)
+            .create_async()
+            .await;
+
+        let client = Client::new();
+        let webclient = WebClient::new(client);
+
+        let message: String = "Hello, World!".into();
+        let result = webclient.say(message.as_ref(), &config).await?;
+
+        mock.assert_async().await;
+        assert_eq!(result, response_body);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn award_points_adds_to_the_viewers_points() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        let mut mock_server = Server::new_async().await;
+        config = config.with_se_api_host(format!("http://{}", mock_server.host_with_port()));
+
+        let mock = mock_server
+            .mock("PUT", "/kappa/v2/points/test_channel_id/clucky/100")
+            .match_header(
+                "Authorization",
+                mockito::Matcher::Exact(format!("Bearer {}", &config.se_jwt.as_ref().unwrap())),
+            )
+            .with_body(r#"{"username":"clucky","amount":100,"newAmount":350}"#)
+            .create_async()
+            .await;
+
+        let webclient = WebClient::new(Client::new());
+        webclient.award_points("clucky", 100, &config).await?;
+
+        mock.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn say_returns_err_if_api_returns_4xx_error() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        let mut mock_server = Server::new_async().await;
+        config = config.with_se_api_host(format!("http://{}", mock_server.host_with_port()));
+
+        let response_body = r#"{
+            "status":200,
+            "channel":"example_channel_id",
+            "message":"Hello, World!",
+        }"#;
+
+        let expected_body = r#"{"message":"Hello, World!"}"#;
+        let mock = mock_server
+            .mock("POST", "/kappa/v2/bot/test_channel_id/say")
+            .match_header(
+                "Authorization",
+                mockito::Matcher::Exact(format!("Bearer {}", &config.se_jwt.as_ref().unwrap())),
+            )
+            .match_body(expected_body)
+            .with_status(400)
+            .create_async()
+            .await;
+
+        let client = Client::new();
+        let webclient = WebClient::new(client);
+
+        let message: String = "Hello, World!".into();
+        let result = webclient.say(message.as_ref(), &config).await;
+
+        mock.assert_async().await;
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_chatters_returns_display_names() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        let mut mock_server = Server::new_async().await;
+        config = config
+            .with_twitch_api_host(format!("http://{}/helix/", mock_server.host_with_port()))
+            .with_twitch_access_token("access-token".into());
+
+        let response_body = r#"{
+            "data": [
+                {"user_id": "128393656", "user_login": "smittysmithers", "user_name": "smittysmithers"},
+                {"user_id": "9001", "user_login": "cooler_user", "user_name": "Cooler_User"}
+            ],
+            "pagination": {},
+            "total": 2
+        }"#;
+
+        let mock = mock_server
+            .mock("GET", "/helix/chat/chatters")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("broadcaster_id".into(), "1337".into()),
+                mockito::Matcher::UrlEncoded("moderator_id".into(), "1337".into()),
+            ]))
+            .match_header("Authorization", "Bearer access-token")
+            .match_header("Client-Id", "client-id")
+            .with_body(response_body)
+            .create_async()
+            .await;
+
+        let webclient = WebClient::new(Client::new());
+        let result = webclient.get_chatters(&config).await?;
+
+        mock.assert_async().await;
+        assert_eq!(result, vec!["smittysmithers", "Cooler_User"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_chatters_returns_err_without_access_token() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.twitch_access_token = None;
+
+        let webclient = WebClient::new(Client::new());
+        let result = webclient.get_chatters(&config).await;
+
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn validate_poll_checks_helix_limits() {
+        let choices: Vec<String> = vec!["Eat it".into(), "Share it".into()];
+
+        assert!(validate_poll("What should John do?", &choices, 60).is_ok());
+        assert!(validate_poll(&"a".repeat(61), &choices, 60).is_err());
+        assert!(validate_poll("What should John do?", &choices[..1], 60).is_err());
+        assert!(
+            validate_poll(
+                "What should John do?",
+                &["Eat it".into(), "b".repeat(26)],
+                60
+            )
+            .is_err()
+        );
+        assert!(validate_poll("What should John do?", &choices, 10).is_err());
+        assert!(validate_poll("What should John do?", &choices, 1801).is_err());
+    }
+
+    #[tokio::test]
+    async fn create_poll_returns_poll_id() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        let mut mock_server = Server::new_async().await;
+        config = config
+            .with_twitch_api_host(format!("http://{}/helix/", mock_server.host_with_port()))
+            .with_twitch_access_token("access-token".into());
+
+        let expected_body = r#"{"broadcaster_id":"1337","title":"What should John do?","choices":[{"title":"Eat it"},{"title":"Share it"}],"duration":60}"#;
+        let response_body = r#"{
+            "data": [{
+                "id": "ed961efd-8a3f-4cf5-a9d0-e616c590cd2a",
+                "title": "What should John do?",
+                "choices": [
+                    {"id": "4c123012-1351-4f33-84b7-43856e7a0f47", "title": "Eat it", "votes": 0},
+                    {"id": "279087e3-54a7-467e-bcd0-c1393fcea4f0", "title": "Share it", "votes": 0}
+                ],
+                "status": "ACTIVE",
+                "duration": 60
+            }]
+        }"#;
+
+        let mock = mock_server
+            .mock("POST", "/helix/polls")
+            .match_header("Authorization", "Bearer access-token")
+            .match_body(expected_body)
+            .with_body(response_body)
+            .create_async()
+            .await;
+
+        let webclient = WebClient::new(Client::new());
+        let result = webclient
+            .create_poll(
+                "What should John do?",
+                &["Eat it".into(), "Share it".into()],
+                60,
+                &config,
+            )
+            .await?;
+
+        mock.assert_async().await;
+        assert_eq!(result, "ed961efd-8a3f-4cf5-a9d0-e616c590cd2a");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_poll_results_returns_votes_per_choice() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        let mut mock_server = Server::new_async().await;
+        config = config
+            .with_twitch_api_host(format!("http://{}/helix/", mock_server.host_with_port()))
+            .with_twitch_access_token("access-token".into());
+
+        let response_body = r#"{
+            "data": [{
+                "id": "poll-1",
+                "title": "What should John do?",
+                "choices": [
+                    {"id": "a", "title": "Eat it", "votes": 3},
+                    {"id": "b", "title": "Share it", "votes": 7}
+                ],
+                "status": "COMPLETED"
+            }]
+        }"#;
+
+        let mock = mock_server
+            .mock("GET", "/helix/polls")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("broadcaster_id".into(), "1337".into()),
+                mockito::Matcher::UrlEncoded("id".into(), "poll-1".into()),
+            ]))
+            .with_body(response_body)
+            .create_async()
+            .await;
+
+        let webclient = WebClient::new(Client::new());
+        let result = webclient.get_poll_results("poll-1", &config).await?;
+
+        mock.assert_async().await;
+        assert_eq!(
+            result,
+            vec![("Eat it".to_string(), 3), ("Share it".to_string(), 7)]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn set_reward_paused_patches_reward() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        let mut mock_server = Server::new_async().await;
+        config = config
+            .with_twitch_api_host(format!("http://{}/helix/", mock_server.host_with_port()))
+            .with_twitch_access_token("access-token".into());
+
+        let mock = mock_server
+            .mock("PATCH", "/helix/channel_points/custom_rewards")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("broadcaster_id".into(), "1337".into()),
+                mockito::Matcher::UrlEncoded("id".into(), "reward-1".into()),
+            ]))
+            .match_body(r#"{"is_paused":true}"#)
+            .with_body(r#"{"data": [{"id": "reward-1", "is_paused": true}]}"#)
+            .create_async()
+            .await;
+
+        let webclient = WebClient::new(Client::new());
+        let result = webclient.set_reward_paused("reward-1", true, &config).await;
+
+        mock.assert_async().await;
+        assert!(result.is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_stream_id_returns_none_when_offline() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        let mut mock_server = Server::new_async().await;
+        config = config
+            .with_twitch_api_host(format!("http://{}/helix/", mock_server.host_with_port()))
+            .with_twitch_access_token("access-token".into());
+
+        let live = mock_server
+            .mock("GET", "/helix/streams?user_id=1337")
+            .with_body(r#"{"data": [{"id": "40952121085", "user_id": "1337", "type": "live"}], "pagination": {}}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let webclient = WebClient::new(Client::new());
+        assert_eq!(
+            webclient.get_stream_id(&config).await?,
+            Some("40952121085".to_string())
+        );
+        live.remove_async().await;
+
+        mock_server
+            .mock("GET", "/helix/streams?user_id=1337")
+            .with_body(r#"{"data": [], "pagination": {}}"#)
+            .create_async()
+            .await;
+
+        assert_eq!(webclient.get_stream_id(&config).await?, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_channel_info_reads_title_and_category() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        let mut mock_server = Server::new_async().await;
+        config = config
+            .with_twitch_api_host(format!("http://{}/helix/", mock_server.host_with_port()))
+            .with_twitch_access_token("access-token".into());
+
+        let channel = mock_server
+            .mock("GET", "/helix/channels?broadcaster_id=1337")
+            .with_body(r#"{"data": [{"broadcaster_id": "1337", "title": "Chicken farming", "game_id": "31376", "game_name": "Terraria", "tags": []}]}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let webclient = WebClient::new(Client::new());
+        assert_eq!(
+            webclient.get_channel_info(&config).await?,
+            ChannelInfo {
+                title: "Chicken farming".into(),
+                category_id: "31376".into(),
+                category_name: "Terraria".into(),
+            }
+        );
+        channel.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_schedule_is_empty_without_a_schedule() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        let mut mock_server = Server::new_async().await;
+        config = config
+            .with_twitch_api_host(format!("http://{}/helix/", mock_server.host_with_port()))
+            .with_twitch_access_token("access-token".into());
+
+        let scheduled = mock_server
+            .mock("GET", "/helix/schedule?broadcaster_id=1337")
+            .with_body(r#"{"data": {"segments": [{"id": "a", "start_time": "2026-10-17T19:00:00Z", "end_time": "2026-10-17T22:00:00Z", "title": "Chicken farming", "canceled_until": null, "category": null, "is_recurring": true}], "broadcaster_id": "1337", "vacation": null}, "pagination": {}}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let webclient = WebClient::new(Client::new());
+        let segments = webclient.get_schedule(&config).await?;
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].title, "Chicken farming");
+        scheduled.remove_async().await;
 
-#[derive(Serialize, Deserialize, Debug)]
-struct MessageRequest {
-    message: String,
-}
+        mock_server
+            .mock("GET", "/helix/schedule?broadcaster_id=1337")
+            .with_status(404)
+            .create_async()
+            .await;
 
-pub trait StreamelementsCaller: Send + Sync {
-    fn say(
-        &self,
-        msg: &str,
-        config: &AppConfig,
-    ) -> impl std::future::Future<Output = Result<String>> + Send + Sync;
-}
+        assert_eq!(webclient.get_schedule(&config).await?, vec![]);
+        Ok(())
+    }
 
-impl StreamelementsCaller for WebClient {
-    async fn say(&self, msg: &str, config: &AppConfig) -> Result<String> {
-        let host = config.se_api_host.clone();
-        let mut url = Url::parse(&host)?;
-        url = url.join(format!("kappa/v2/bot/{}/say", &config.twitch_channel_id).as_ref())?;
+    #[tokio::test]
+    async fn get_user_roles_combines_subscription_vip_and_moderator() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        let mut mock_server = Server::new_async().await;
+        config = config
+            .with_twitch_api_host(format!("http://{}/helix/", mock_server.host_with_port()))
+            .with_twitch_access_token("access-token".into());
 
-        let mut req_body: HashMap<String, String> = HashMap::new();
-        req_body.insert("message".to_string(), String::from(msg));
+        let query = mockito::Matcher::AllOf(vec![
+            mockito::Matcher::UrlEncoded("broadcaster_id".into(), "1337".into()),
+            mockito::Matcher::UrlEncoded("user_id".into(), "9001".into()),
+        ]);
+        let subscriptions = mock_server
+            .mock("GET", "/helix/subscriptions")
+            .match_query(query.clone())
+            .with_body(r#"{"data": [{"user_id": "9001", "tier": "2000", "is_gift": false}]}"#)
+            .create_async()
+            .await;
+        let vips = mock_server
+            .mock("GET", "/helix/channels/vips")
+            .match_query(query.clone())
+            .with_body(r#"{"data": [], "pagination": {}}"#)
+            .create_async()
+            .await;
+        let moderators = mock_server
+            .mock("GET", "/helix/moderation/moderators")
+            .match_query(query)
+            .with_body(r#"{"data": [{"user_id": "9001", "user_login": "cooler_user", "user_name": "Cooler_User"}], "pagination": {}}"#)
+            .create_async()
+            .await;
 
-        match self
-            .client
-            .post(url)
-            .bearer_auth(config.se_jwt.as_ref().expect("Missing Streamelements JWT"))
-            .json(&req_body)
-            .timeout(Duration::new(1, 0))
-            .send()
-            .await
-        {
-            Ok(resp) => {
-                if resp.status().is_success() {
-                    resp.text()
-                        .await
-                        .map_err(|e| anyhow!("Failed to read response body"))
-                } else {
-                    Err(anyhow!(
-                        "Streamelemenst API returned error with status: {}",
-                        resp.status()
-                    ))
-                }
+        let webclient = WebClient::new(Client::new());
+        let result = webclient.get_user_roles("9001", &config).await?;
+
+        subscriptions.assert_async().await;
+        vips.assert_async().await;
+        moderators.assert_async().await;
+        assert_eq!(
+            result,
+            UserRoles {
+                sub_tier: Some(2),
+                vip: false,
+                moderator: true,
+                broadcaster: false,
             }
+        );
+        Ok(())
+    }
 
-            Err(e) => Err(anyhow!(
-                "Failed to make request to Streamelements API: {}",
-                e.without_url()
-            )),
+    #[tokio::test]
+    async fn get_user_roles_knows_the_broadcaster() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        let mut mock_server = Server::new_async().await;
+        config = config
+            .with_twitch_api_host(format!("http://{}/helix/", mock_server.host_with_port()))
+            .with_twitch_access_token("access-token".into());
+        for path in [
+            "/helix/subscriptions",
+            "/helix/channels/vips",
+            "/helix/moderation/moderators",
+        ] {
+            mock_server
+                .mock("GET", path)
+                .match_query(mockito::Matcher::Any)
+                .with_body(r#"{"data": [], "pagination": {}}"#)
+                .create_async()
+                .await;
         }
+
+        let webclient = WebClient::new(Client::new());
+        let roles = webclient.get_user_roles("1337", &config).await?;
+
+        assert!(roles.broadcaster);
+        assert!(roles.has(Role::Broadcaster));
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use anyhow::Result;
-    use dotenvy::dotenv;
-    use mockito::Server;
-    use reqwest::Client;
-    use std::path::PathBuf;
+    #[tokio::test]
+    async fn get_users_looks_up_logins() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        let mut mock_server = Server::new_async().await;
+        config = config
+            .with_twitch_api_host(format!("http://{}/helix/", mock_server.host_with_port()))
+            .with_twitch_access_token("access-token".into());
 
-    use crate::{
-        client::{StreamelementsCaller, WebClient},
-        config::AppConfig,
-        robochick::twitch::MessageComponents,
-    };
+        let mock = mock_server
+            .mock("GET", "/helix/users")
+            .match_query(mockito::Matcher::Regex(
+                "^login=cooler_user&login=nobody$".into(),
+            ))
+            .with_body(r#"{"data": [{"id": "9001", "login": "cooler_user", "display_name": "Cooler_Üser", "type": "", "profile_image_url": "https://example.com/9001.png"}]}"#)
+            .create_async()
+            .await;
+
+        let webclient = WebClient::new(Client::new());
+        let users = webclient
+            .get_users(&["cooler_user".into(), "nobody".into()], &config)
+            .await?;
+
+        mock.assert_async().await;
+        assert_eq!(
+            users,
+            vec![TwitchUser {
+                id: "9001".into(),
+                login: "cooler_user".into(),
+                display_name: "Cooler_Üser".into(),
+                profile_image_url: "https://example.com/9001.png".into(),
+            }]
+        );
+        Ok(())
+    }
 
     #[tokio::test]
-    async fn say_makes_successful_request() -> Result<()> {
+    async fn cancel_redemption_patches_status() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        let mut mock_server = Server::new_async().await;
+        config = config
+            .with_twitch_api_host(format!("http://{}/helix/", mock_server.host_with_port()))
+            .with_twitch_access_token("access-token".into());
+
+        let mock = mock_server
+            .mock("PATCH", "/helix/channel_points/custom_rewards/redemptions")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("broadcaster_id".into(), "1337".into()),
+                mockito::Matcher::UrlEncoded("reward_id".into(), "reward-1".into()),
+                mockito::Matcher::UrlEncoded("id".into(), "redemption-1".into()),
+            ]))
+            .match_body(r#"{"status":"CANCELED"}"#)
+            .with_body(r#"{"data": [{"id": "redemption-1", "status": "CANCELED"}]}"#)
+            .create_async()
+            .await;
+
+        let webclient = WebClient::new(Client::new());
+        let result = webclient
+            .cancel_redemption("reward-1", "redemption-1", &config)
+            .await;
+
+        mock.assert_async().await;
+        assert!(result.is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_eventsub_subscription_uses_app_access_token() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        let mut mock_server = Server::new_async().await;
+        config =
+            config.with_twitch_api_host(format!("http://{}/helix/", mock_server.host_with_port()));
+        config.twitch_host = format!("http://{}", mock_server.host_with_port());
+
+        let token = mock_server
+            .mock("POST", "/oauth2/token")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("client_id".into(), "client-id".into()),
+                mockito::Matcher::UrlEncoded("client_secret".into(), "test-secret".into()),
+                mockito::Matcher::UrlEncoded("grant_type".into(), "client_credentials".into()),
+            ]))
+            .with_body(
+                r#"{"access_token": "app-token", "expires_in": 5011271, "token_type": "bearer"}"#,
+            )
+            .create_async()
+            .await;
+        let subscribe = mock_server
+            .mock("POST", "/helix/eventsub/subscriptions")
+            .match_header("Authorization", "Bearer app-token")
+            .match_body(mockito::Matcher::Json(serde_json::json!({
+                "type": "channel.follow",
+                "version": "1",
+                "condition": {"broadcaster_user_id": "1337"},
+                "transport": {
+                    "method": "webhook",
+                    "callback": "https://example.com/webhooks/callback",
+                    "secret": config.twitch_eventsub_subscription_secret,
+                },
+            })))
+            .with_status(202)
+            .with_body(
+                r#"{"data": [{"id": "sub-1", "status": "webhook_callback_verification_pending"}]}"#,
+            )
+            .create_async()
+            .await;
+
+        let request: SubscriptionRequest = serde_json::from_value(serde_json::json!({
+            "type": "channel.follow",
+            "version": "1",
+            "condition": {"broadcaster_user_id": "1337"},
+            "callback": "https://example.com/webhooks/callback",
+        }))?;
+        let webclient = WebClient::new(Client::new());
+        let result = webclient
+            .create_eventsub_subscription(&request, &config)
+            .await;
+
+        token.assert_async().await;
+        subscribe.assert_async().await;
+        assert!(result.is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_eventsub_subscription_saves_its_own_secret() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        let mut mock_server = Server::new_async().await;
+        config =
+            config.with_twitch_api_host(format!("http://{}/helix/", mock_server.host_with_port()));
+        config.twitch_host = format!("http://{}", mock_server.host_with_port());
+
+        mock_server
+            .mock("POST", "/oauth2/token")
+            .match_query(mockito::Matcher::Any)
+            .with_body(
+                r#"{"access_token": "app-token", "expires_in": 5011271, "token_type": "bearer"}"#,
+            )
+            .create_async()
+            .await;
+        let subscribe = mock_server
+            .mock("POST", "/helix/eventsub/subscriptions")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "transport": {"secret": "generated-secret"},
+            })))
+            .with_status(202)
+            .with_body(
+                r#"{"data": [{"id": "sub-1", "status": "webhook_callback_verification_pending"}]}"#,
+            )
+            .create_async()
+            .await;
+        let random = mock!(aws_sdk_secretsmanager::Client::get_random_password).then_output(|| {
+            GetRandomPasswordOutput::builder()
+                .random_password("generated-secret")
+                .build()
+        });
+        let save = mock!(aws_sdk_secretsmanager::Client::create_secret)
+            .match_requests(|r| {
+                r.name() == Some("eventsub/sub-1") && r.secret_string() == Some("generated-secret")
+            })
+            .then_output(|| CreateSecretOutput::builder().build());
+        let secrets = mock_client!(aws_sdk_secretsmanager, RuleMode::MatchAny, [&random, &save]);
+
+        let request: SubscriptionRequest = serde_json::from_value(serde_json::json!({
+            "type": "channel.follow",
+            "version": "1",
+            "condition": {"broadcaster_user_id": "1337"},
+            "callback": "https://example.com/webhooks/callback",
+        }))?;
+        let webclient = WebClient::new(Client::new()).with_eventsub_secrets(Arc::new(
+            EventsubSecretStore::new(Arc::new(SecretStore::new(secrets)), "eventsub/".into()),
+        ));
+        webclient
+            .create_eventsub_subscription(&request, &config)
+            .await?;
+
+        subscribe.assert_async().await;
+        assert_eq!(save.num_calls(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn list_eventsub_subscriptions_follows_pagination() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        let mut mock_server = Server::new_async().await;
+        config =
+            config.with_twitch_api_host(format!("http://{}/helix/", mock_server.host_with_port()));
+        config.twitch_host = format!("http://{}", mock_server.host_with_port());
+
+        mock_server
+            .mock("POST", "/oauth2/token")
+            .match_query(mockito::Matcher::Any)
+            .with_body(r#"{"access_token": "app-token"}"#)
+            .create_async()
+            .await;
+        let subscription = |id: &str| {
+            serde_json::json!({
+                "id": id,
+                "status": "enabled",
+                "type": "channel.cheer",
+                "version": "1",
+                "cost": 0,
+                "condition": {"broadcaster_user_id": "1337"},
+                "transport": {"method": "webhook", "callback": "https://example.com/eventsub"},
+                "created_at": "2026-10-01T00:00:00Z"
+            })
+        };
+        let first = mock_server
+            .mock("GET", "/helix/eventsub/subscriptions")
+            .match_query(mockito::Matcher::Missing)
+            .with_body(
+                serde_json::json!({"data": [subscription("sub-1")], "pagination": {"cursor": "next"}})
+                    .to_string(),
+            )
+            .create_async()
+            .await;
+        let second = mock_server
+            .mock("GET", "/helix/eventsub/subscriptions")
+            .match_query(mockito::Matcher::UrlEncoded("after".into(), "next".into()))
+            .with_body(
+                serde_json::json!({"data": [subscription("sub-2")], "pagination": {}}).to_string(),
+            )
+            .create_async()
+            .await;
+
+        let subscriptions = WebClient::new(Client::new())
+            .list_eventsub_subscriptions(&config)
+            .await?;
+
+        first.assert_async().await;
+        second.assert_async().await;
+        let ids: Vec<&str> = subscriptions.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, ["sub-1", "sub-2"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn say_uses_channel_jwt_from_secrets_manager() -> Result<()> {
         dotenvy::from_filename(".env.test")?;
         let mut config = AppConfig::from_env();
         let mut mock_server = Server::new_async().await;
         config = config.with_se_api_host(format!("http://{}", mock_server.host_with_port()));
+        config.se_jwt_secret_prefix = Some("se-jwt/".into());
 
-        let response_body = r#"{
-            "status":200,
-            "channel":"example_channel_id",
-            "message":"Hello, World!",
-        }"#;
+        let rule = mock!(aws_sdk_secretsmanager::Client::get_secret_value)
+            .match_requests(|r| r.secret_id() == Some("se-jwt/test_channel_id"))
+            .then_output(|| {
+                GetSecretValueOutput::builder()
+                    .secret_string("channel-jwt")
+                    .build()
+            });
+        let secrets = mock_client!(aws_sdk_secretsmanager, [&rule]);
 
-        let expected_body = r#"{"message":"Hello, World!"}"#;
         let mock = mock_server
             .mock("POST", "/kappa/v2/bot/test_channel_id/say")
-            .match_header(
-                "Authorization",
-                mockito::Matcher::Exact(format!("Bearer {}", &config.se_jwt.as_ref().unwrap())),
-            )
-            .match_body(expected_body)
-            .with_body(&response_body)
+            .match_header("Authorization", "Bearer channel-jwt")
+            .with_body("{}")
             .create_async()
             .await;
 
-        let client = Client::new();
-        let webclient = WebClient::new(client);
-
-        let message: String = "Hello, World!".into();
-        let result = webclient.say(message.as_ref(), &config).await?;
+        let webclient = WebClient::new(Client::new()).with_se_jwts(Arc::new(SeJwtStore::new(
+            Arc::new(SecretStore::new(secrets)),
+            "se-jwt/".into(),
+        )));
+        webclient.say("Hello, World!", &config).await?;
 
         mock.assert_async().await;
-        assert_eq!(result, response_body);
         Ok(())
     }
 
     #[tokio::test]
-    async fn say_returns_err_if_api_returns_4xx_error() -> Result<()> {
+    async fn say_falls_back_to_helix_when_streamelements_is_unhealthy() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        let mut mock_server = Server::new_async().await;
+        let host = format!("http://{}", mock_server.host_with_port());
+        config = config
+            .with_se_api_host(format!("{host}/"))
+            .with_twitch_api_host(format!("{host}/helix/"))
+            .with_twitch_access_token("access-token".into());
+        config.twitch_host = host;
+        config.helix_chat_sender_id = Some("4242".into());
+
+        let se_probe = mock_server
+            .mock("GET", "/kappa/v2/channels/me")
+            .with_status(401)
+            .expect(1)
+            .create_async()
+            .await;
+        let helix_probe = mock_server
+            .mock("GET", "/oauth2/validate")
+            .match_header("Authorization", "OAuth access-token")
+            .with_body(r#"{"client_id": "client-id", "login": "robochick", "expires_in": 3600}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let send = mock_server
+            .mock("POST", "/helix/chat/messages")
+            .match_body(r#"{"broadcaster_id":"1337","sender_id":"4242","message":"Hello, World!"}"#)
+            .with_body(r#"{"data": [{"message_id": "msg-1", "is_sent": true}]}"#)
+            .expect(2)
+            .create_async()
+            .await;
+
+        let webclient = WebClient::new(Client::new());
+        assert_eq!(webclient.say("Hello, World!", &config).await?, "msg-1");
+        assert_eq!(webclient.say("Hello, World!", &config).await?, "msg-1");
+
+        se_probe.assert_async().await;
+        helix_probe.assert_async().await;
+        send.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn say_records_audit_entry_with_event_id() -> Result<()> {
         dotenvy::from_filename(".env.test")?;
         let mut config = AppConfig::from_env();
         let mut mock_server = Server::new_async().await;
         config = config.with_se_api_host(format!("http://{}", mock_server.host_with_port()));
+        mock_server
+            .mock("POST", "/kappa/v2/bot/test_channel_id/say")
+            .with_status(401)
+            .create_async()
+            .await;
 
-        let response_body = r#"{
-            "status":200,
-            "channel":"example_channel_id",
-            "message":"Hello, World!",
-        }"#;
+        let store = Arc::new(MemoryStore::default());
+        let webclient = WebClient::new(Client::new()).with_audit(store.clone());
 
-        let expected_body = r#"{"message":"Hello, World!"}"#;
-        let mock = mock_server
+        let result =
+            audit::with_event_id("event-1".into(), webclient.say("Bok bok", &config)).await;
+
+        assert!(result.is_err());
+        let day = chrono::Utc::now().format("%Y-%m-%d").to_string();
+        let entries = store.audit_entries(&day).await?;
+        assert_eq!(entries.len(), 1);
+        let entry: AuditEntry = serde_json::from_str(&entries[0])?;
+        assert_eq!(entry.event_id, Some("event-1".into()));
+        assert_eq!(entry.message, "Bok bok");
+        assert_eq!(entry.backend, ChatBackend::StreamElements);
+        assert_eq!(entry.status, Some(401));
+        assert!(entry.error.is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn self_test_posts_to_test_channel_without_auditing() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut server = Server::new_async().await;
+        let config =
+            AppConfig::from_env().with_se_api_host(format!("http://{}", server.host_with_port()));
+        let mock = server
+            .mock("POST", "/kappa/v2/bot/test-channel/say")
+            .match_body(r#"{"message":"[test] Robochick self-test, please ignore"}"#)
+            .with_body("{}")
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/kappa/v2/bot/broken-channel/say")
+            .with_status(403)
+            .create_async()
+            .await;
+
+        let state = Arc::new(MemoryStore::default());
+        let webclient = WebClient::new(Client::new()).with_audit(state.clone());
+
+        let passed = webclient.self_test("test-channel", &config).await;
+        let failed = webclient.self_test("broken-channel", &config).await;
+
+        mock.assert_async().await;
+        assert!(passed.healthy);
+        assert_eq!(passed.backend, ChatBackend::StreamElements);
+        assert!(!failed.healthy);
+        assert!(failed.error.is_some());
+        let now = chrono::Utc::now();
+        let audited = audit::entries_since(state.as_ref(), now - chrono::Duration::hours(1), now);
+        assert!(audited.await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn say_retries_once_after_rate_limit_resets() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut server = Server::new_async().await;
+        let config =
+            AppConfig::from_env().with_se_api_host(format!("http://{}", server.host_with_port()));
+        let limited = server
             .mock("POST", "/kappa/v2/bot/test_channel_id/say")
-            .match_header(
-                "Authorization",
-                mockito::Matcher::Exact(format!("Bearer {}", &config.se_jwt.as_ref().unwrap())),
-            )
-            .match_body(expected_body)
-            .with_status(400)
+            .with_status(429)
+            .with_header("Retry-After", "1")
+            .expect(1)
+            .create_async()
+            .await;
+        let sent = server
+            .mock("POST", "/kappa/v2/bot/test_channel_id/say")
+            .with_body("{}")
+            .expect(1)
             .create_async()
             .await;
 
-        let client = Client::new();
-        let webclient = WebClient::new(client);
+        let result = WebClient::new(Client::new()).say("Bok", &config).await?;
 
-        let message: String = "Hello, World!".into();
-        let result = webclient.say(message.as_ref(), &config).await;
+        limited.assert_async().await;
+        sent.assert_async().await;
+        assert_eq!(result, "{}");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn boxed_callers_forward_to_the_client() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut server = Server::new_async().await;
+        let config =
+            AppConfig::from_env().with_se_api_host(format!("http://{}", server.host_with_port()));
+        let mock = server
+            .mock("POST", "/kappa/v2/bot/test_channel_id/say")
+            .with_body("{}")
+            .expect(1)
+            .create_async()
+            .await;
+
+        let caller: Arc<dyn Caller> = Arc::new(WebClient::new(Client::new()));
+        let result = caller.say("Bok", &config).await?;
 
         mock.assert_async().await;
-        assert!(result.is_err());
+        assert_eq!(result, "{}");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn say_joins_messages_sent_within_batch_window() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut server = Server::new_async().await;
+        let config =
+            AppConfig::from_env().with_se_api_host(format!("http://{}", server.host_with_port()));
+        let mock = server
+            .mock("POST", "/kappa/v2/bot/test_channel_id/say")
+            .match_body(r#"{"message":"Bok | Bok bok"}"#)
+            .with_body("{}")
+            .expect(1)
+            .create_async()
+            .await;
+        let webclient = WebClient::new(Client::new()).with_batching(Some(ChatBatcher::new(
+            std::time::Duration::from_millis(50),
+            " | ".into(),
+        )));
+
+        let (first, second) = tokio::join!(
+            webclient.say("Bok", &config),
+            webclient.say("Bok bok", &config)
+        );
+
+        mock.assert_async().await;
+        assert_eq!(first?, "{}");
+        assert_eq!(second?, "{}");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn say_still_flushes_batch_when_the_first_caller_is_dropped() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut server = Server::new_async().await;
+        let config =
+            AppConfig::from_env().with_se_api_host(format!("http://{}", server.host_with_port()));
+        let mock = server
+            .mock("POST", "/kappa/v2/bot/test_channel_id/say")
+            .match_body(r#"{"message":"Bok | Bok bok"}"#)
+            .with_body("{}")
+            .expect(1)
+            .create_async()
+            .await;
+        let webclient = WebClient::new(Client::new()).with_batching(Some(ChatBatcher::new(
+            std::time::Duration::from_millis(50),
+            " | ".into(),
+        )));
+
+        let dropped = tokio::time::timeout(
+            std::time::Duration::from_millis(10),
+            webclient.say("Bok", &config),
+        )
+        .await;
+        let second = webclient.say("Bok bok", &config).await;
+
+        mock.assert_async().await;
+        assert!(dropped.is_err());
+        assert_eq!(second?, "{}");
         Ok(())
     }
 }
@@ -0,0 +1,686 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use chrono::Utc;
+use fastrand::Rng;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    config::AppConfig,
+    permissions::{self, Capability, Principal, Role},
+    robochick::twitch::{MessageComponents, TemplateContext, USER_LOGIN, USER_NAME},
+    state::StateStore,
+    stats::month_key,
+};
+
+const FEDBOARD: &str = "fedboard";
+const OPT_OUT: &str = "chickenoptout";
+const OPT_IN: &str = "chickenoptin";
+
+/// Names of the bot's own commands, which custom ones can't take.
+pub const BUILT_IN: &[&str] = &[FEDBOARD, OPT_OUT, OPT_IN];
+
+/// Most mods `!fedboard` lists.
+const FEDBOARD_SIZE: usize = 3;
+
+/// Placeholder in custom command replies filled with what was typed after the command.
+pub const ARGS: &str = "args";
+
+/// Chat commands, read from `channel.chat.message` notifications.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct CommandConfig {
+    /// What messages start with to be commands.
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+    /// Changes to who can use each command and its cooldown, by command name.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub settings: HashMap<String, CommandSettings>,
+    /// Commands that reply with a template, by name, e.g. `!discord`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub custom: HashMap<String, CustomCommand>,
+}
+
+fn default_prefix() -> String {
+    "!".into()
+}
+
+impl Default for CommandConfig {
+    fn default() -> Self {
+        CommandConfig {
+            prefix: default_prefix(),
+            settings: HashMap::new(),
+            custom: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq)]
+pub struct CommandSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permission: Option<Role>,
+    /// Seconds before the command can be used again. Moderators and the broadcaster aren't
+    /// held back by it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cooldown_secs: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct CustomCommand {
+    /// Template of the reply, with `{user_name}`, `{user_login}`, `{args}` and the template
+    /// variables.
+    pub reply: String,
+    #[serde(flatten)]
+    pub settings: CommandSettings,
+}
+
+/// Adds or replaces the custom command `name` in a config, or removes it with `None`. Returns
+/// the changed config, or `None` when there was no such command to remove.
+pub fn edit_custom(
+    body: &str,
+    name: &str,
+    command: Option<&CustomCommand>,
+) -> Result<Option<String>> {
+    let name = name.to_lowercase();
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(anyhow!("Command names can't be empty or contain spaces"));
+    }
+    if BUILT_IN.contains(&name.as_str()) {
+        return Err(anyhow!("{name} is a built-in command"));
+    }
+
+    let mut config: Value = serde_json::from_str(body)?;
+    let root = config
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("The config isn't an object"))?;
+    let commands = root
+        .entry("commands")
+        .or_insert_with(|| Value::Object(Map::new()));
+    if commands.is_null() {
+        *commands = Value::Object(Map::new());
+    }
+    let custom = commands
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("commands isn't an object"))?
+        .entry("custom")
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("commands.custom isn't an object"))?;
+
+    match command {
+        Some(command) => {
+            custom.insert(name, serde_json::to_value(command)?);
+        }
+        None => {
+            if custom.remove(&name).is_none() {
+                return Ok(None);
+            }
+        }
+    }
+    Ok(Some(serde_json::to_string(&config)?))
+}
+
+/// The parts of a `channel.chat.message` event commands use.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatMessage {
+    pub message_id: String,
+    pub chatter_user_id: String,
+    pub chatter_user_login: String,
+    pub chatter_user_name: String,
+    pub message: MessageText,
+    #[serde(default)]
+    pub badges: Vec<Badge>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct MessageText {
+    pub text: String,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Badge {
+    pub set_id: String,
+    #[serde(default)]
+    pub id: String,
+}
+
+impl ChatMessage {
+    pub fn from_event(event: &Value) -> Result<ChatMessage> {
+        Ok(serde_json::from_value(event.clone())?)
+    }
+
+    /// Whether the bot sent the message itself, through Helix or StreamElements. Replies are
+    /// never read as commands, so a reply starting with the prefix can't trigger itself.
+    pub fn is_from_bot(&self, config: &AppConfig) -> bool {
+        config.helix_chat_sender_id.as_deref() == Some(self.chatter_user_id.as_str())
+            || self
+                .chatter_user_login
+                .eq_ignore_ascii_case(&config.se_bot_login)
+    }
+
+    pub fn role(&self) -> Role {
+        Role::from_badges(
+            self.badges
+                .iter()
+                .map(|b| (b.set_id.as_str(), b.id.as_str())),
+        )
+    }
+}
+
+/// A command as typed in chat.
+#[derive(Debug, Clone, Copy)]
+pub struct Invocation<'a> {
+    /// Lowercase, without the prefix.
+    pub name: &'a str,
+    /// Everything after the name, trimmed.
+    pub args: &'a str,
+    pub message: &'a ChatMessage,
+}
+
+/// Splits `{prefix}name args` into the lowercase name and the arguments. `None` for messages
+/// that aren't commands.
+pub fn parse<'a>(prefix: &str, text: &'a str) -> Option<(String, &'a str)> {
+    let rest = text.trim().strip_prefix(prefix)?;
+    let (name, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    match name.is_empty() {
+        true => None,
+        false => Some((name.to_lowercase(), args.trim())),
+    }
+}
+
+#[async_trait]
+pub trait Command: Send + Sync {
+    /// What it's typed as, lowercase and without the prefix.
+    fn name(&self) -> &str;
+
+    /// The least role that can use it.
+    fn permission(&self) -> Role {
+        Role::Viewer
+    }
+
+    /// Seconds before the command can be used again, across chat. 0 for none.
+    fn cooldown_secs(&self) -> u64 {
+        0
+    }
+
+    /// Runs the command, returning the reply to post, if any.
+    async fn run(&self, invocation: Invocation<'_>, config: &AppConfig) -> Result<Option<String>>;
+}
+
+/// What happened to a chat message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Dispatch {
+    NotACommand,
+    Unknown(String),
+    NotAllowed(String),
+    CoolingDown(String),
+    Ran { name: String, reply: Option<String> },
+}
+
+/// The commands chat can use, run with the permissions and cooldowns each declares unless the
+/// config changes them.
+pub struct Commands {
+    config: CommandConfig,
+    commands: HashMap<String, Box<dyn Command>>,
+    state: Arc<dyn StateStore>,
+}
+
+impl Commands {
+    pub fn new(config: CommandConfig, state: Arc<dyn StateStore>) -> Self {
+        Commands {
+            config,
+            commands: HashMap::new(),
+            state,
+        }
+    }
+
+    /// The bot's own commands. `mods` are the configured ones, and `display_names` how they're
+    /// shown, by name.
+    pub fn built_in(
+        config: CommandConfig,
+        state: Arc<dyn StateStore>,
+        mods: &[String],
+        display_names: HashMap<String, String>,
+    ) -> Self {
+        let mut commands = Commands::new(config, state.clone());
+        for opted_out in [true, false] {
+            commands.register(OptOut {
+                state: state.clone(),
+                mods: mods.to_vec(),
+                opted_out,
+            });
+        }
+        commands.register(Fedboard {
+            state,
+            display_names,
+        });
+        commands
+    }
+
+    pub fn register(&mut self, command: impl Command + 'static) {
+        self.commands
+            .insert(command.name().to_lowercase(), Box::new(command));
+    }
+
+    /// Adds the config's `custom` commands. They can't replace the ones already registered.
+    pub fn add_custom(&mut self, components: Arc<MessageComponents>) {
+        for (name, command) in self.config.custom.clone() {
+            let name = name.to_lowercase();
+            if self.commands.contains_key(&name) {
+                println!("Custom command {name} has the name of a built-in one, ignoring it");
+                continue;
+            }
+            self.register(Reply {
+                name,
+                command,
+                components: components.clone(),
+            });
+        }
+    }
+
+    /// Whether `text` invokes one of the commands, without checking who may run it.
+    pub fn is_command(&self, text: &str) -> bool {
+        parse(&self.config.prefix, text).is_some_and(|(name, _)| self.commands.contains_key(&name))
+    }
+
+    pub async fn dispatch(&self, message: &ChatMessage, config: &AppConfig) -> Result<Dispatch> {
+        let Some((name, args)) = parse(&self.config.prefix, &message.message.text) else {
+            return Ok(Dispatch::NotACommand);
+        };
+        let Some(command) = self.commands.get(&name) else {
+            return Ok(Dispatch::Unknown(name));
+        };
+        let settings = self.config.settings.get(&name);
+
+        let chatter = Principal::chatter(message.role());
+        let required = settings
+            .and_then(|s| s.permission)
+            .unwrap_or_else(|| command.permission());
+        if !permissions::allows(&chatter, Capability::RunCommand(required)) {
+            return Ok(Dispatch::NotAllowed(name));
+        }
+
+        let cooldown = settings
+            .and_then(|s| s.cooldown_secs)
+            .unwrap_or_else(|| command.cooldown_secs());
+        if cooldown > 0
+            && !permissions::allows(&chatter, Capability::SkipCooldowns)
+            && !self
+                .state
+                .start_cooldown(&format!("command#{name}"), cooldown)
+                .await?
+        {
+            return Ok(Dispatch::CoolingDown(name));
+        }
+
+        let invocation = Invocation {
+            name: &name,
+            args,
+            message,
+        };
+        let reply = command.run(invocation, config).await?;
+        Ok(Dispatch::Ran { name, reply })
+    }
+}
+
+/// `!fedboard` - the mods fed most this month.
+struct Fedboard {
+    state: Arc<dyn StateStore>,
+    display_names: HashMap<String, String>,
+}
+
+#[async_trait]
+impl Command for Fedboard {
+    fn name(&self) -> &str {
+        FEDBOARD
+    }
+
+    fn cooldown_secs(&self) -> u64 {
+        30
+    }
+
+    async fn run(&self, _: Invocation<'_>, _: &AppConfig) -> Result<Option<String>> {
+        let mut wins: Vec<(String, u64)> = self
+            .state
+            .month_win_counts(&month_key(Utc::now()))
+            .await?
+            .into_iter()
+            .collect();
+        if wins.is_empty() {
+            return Ok(Some("Nobody has been fed yet this month.".into()));
+        }
+        wins.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        let top: Vec<String> = wins
+            .iter()
+            .take(FEDBOARD_SIZE)
+            .map(|(name, count)| {
+                let name = self.display_names.get(name).unwrap_or(name);
+                format!("{name} ({count})")
+            })
+            .collect();
+        Ok(Some(format!("Most fed this month: {}", top.join(", "))))
+    }
+}
+
+/// `!chickenoptout` and `!chickenoptin` - a mod on the list leaving scenarios or coming back.
+/// The mod is matched by login or display name, ignoring case. Anyone else gets no reply, so
+/// viewers can't make the bot spam chat with it.
+struct OptOut {
+    state: Arc<dyn StateStore>,
+    mods: Vec<String>,
+    opted_out: bool,
+}
+
+#[async_trait]
+impl Command for OptOut {
+    fn name(&self) -> &str {
+        match self.opted_out {
+            true => OPT_OUT,
+            false => OPT_IN,
+        }
+    }
+
+    async fn run(&self, invocation: Invocation<'_>, _: &AppConfig) -> Result<Option<String>> {
+        let chatter = invocation.message;
+        let Some(name) = self.mods.iter().find(|m| {
+            m.eq_ignore_ascii_case(&chatter.chatter_user_login)
+                || m.eq_ignore_ascii_case(&chatter.chatter_user_name)
+        }) else {
+            return Ok(None);
+        };
+
+        self.state
+            .set_opted_out(&name.to_lowercase(), self.opted_out)
+            .await?;
+        println!(
+            "{name} opted {} of scenarios",
+            if self.opted_out { "out" } else { "in" }
+        );
+        Ok(Some(match self.opted_out {
+            true => format!(
+                "{}, you're out of scenarios until you opt back in.",
+                chatter.chatter_user_name
+            ),
+            false => format!(
+                "{}, you're back in the scenarios.",
+                chatter.chatter_user_name
+            ),
+        }))
+    }
+}
+
+/// A custom command, replying with its template.
+struct Reply {
+    name: String,
+    command: CustomCommand,
+    /// For the template variables.
+    components: Arc<MessageComponents>,
+}
+
+#[async_trait]
+impl Command for Reply {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn permission(&self) -> Role {
+        self.command.settings.permission.unwrap_or_default()
+    }
+
+    fn cooldown_secs(&self) -> u64 {
+        self.command.settings.cooldown_secs.unwrap_or(0)
+    }
+
+    async fn run(&self, invocation: Invocation<'_>, _: &AppConfig) -> Result<Option<String>> {
+        let mut context = TemplateContext::default();
+        context.insert(USER_NAME, &invocation.message.chatter_user_name);
+        context.insert(USER_LOGIN, &invocation.message.chatter_user_login);
+        context.insert(ARGS, invocation.args);
+        self.components
+            .resolve_variables(&mut context, &mut Rng::new())?;
+        Ok(Some(context.format(&self.command.reply)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use anyhow::Result;
+    use chrono::Utc;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use crate::{
+        commands::{
+            ChatMessage, CommandConfig, CommandSettings, Commands, CustomCommand, Dispatch,
+            edit_custom, parse,
+        },
+        config::AppConfig,
+        permissions::Role,
+        robochick::twitch::MessageComponents,
+        state::{MemoryStore, StateStore},
+        stats::month_key,
+    };
+
+    fn message(text: &str, badges: &[&str]) -> Result<ChatMessage> {
+        let badges: Vec<_> = badges.iter().map(|b| json!({"set_id": b})).collect();
+        ChatMessage::from_event(&json!({
+            "message_id": "m-1",
+            "chatter_user_id": "9001",
+            "chatter_user_login": "clucky",
+            "chatter_user_name": "Clucky",
+            "message": {"text": text, "fragments": []},
+            "badges": badges
+        }))
+    }
+
+    #[test]
+    fn parse_splits_name_and_arguments() {
+        assert_eq!(
+            parse("!", "  !Quote add  the coop  "),
+            Some(("quote".into(), "add  the coop"))
+        );
+        assert_eq!(parse("!", "!fedboard"), Some(("fedboard".into(), "")));
+        assert_eq!(parse("!", "hi !fedboard"), None);
+        assert_eq!(parse("!", "! fedboard"), None);
+        assert_eq!(parse("?", "!fedboard"), None);
+    }
+
+    #[tokio::test]
+    async fn dispatch_checks_permissions_and_cooldowns() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let config = AppConfig::from_env();
+        let state = Arc::new(MemoryStore::default());
+        state
+            .record_month_wins(
+                &month_key(Utc::now()),
+                &["John".into(), "Jane".into(), "Jane".into()],
+            )
+            .await?;
+        let settings = CommandConfig {
+            settings: [(
+                "fedboard".to_string(),
+                CommandSettings {
+                    permission: Some(Role::Sub),
+                    cooldown_secs: None,
+                },
+            )]
+            .into(),
+            ..Default::default()
+        };
+        let display_names = HashMap::from([("Jane".to_string(), "JaneDoe".to_string())]);
+        let commands = Commands::built_in(settings, state, &[], display_names);
+
+        assert_eq!(
+            commands
+                .dispatch(&message("!fedboard", &[])?, &config)
+                .await?,
+            Dispatch::NotAllowed("fedboard".into())
+        );
+        assert_eq!(
+            commands
+                .dispatch(&message("!FedBoard", &["subscriber"])?, &config)
+                .await?,
+            Dispatch::Ran {
+                name: "fedboard".into(),
+                reply: Some("Most fed this month: JaneDoe (2), John (1)".into())
+            }
+        );
+        assert_eq!(
+            commands
+                .dispatch(&message("!fedboard", &["subscriber"])?, &config)
+                .await?,
+            Dispatch::CoolingDown("fedboard".into())
+        );
+        assert!(matches!(
+            commands
+                .dispatch(&message("!fedboard", &["moderator"])?, &config)
+                .await?,
+            Dispatch::Ran { .. }
+        ));
+        assert_eq!(
+            commands.dispatch(&message("!lurk", &[])?, &config).await?,
+            Dispatch::Unknown("lurk".into())
+        );
+        assert_eq!(
+            commands.dispatch(&message("hi", &[])?, &config).await?,
+            Dispatch::NotACommand
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn mods_on_the_list_opt_themselves_out_and_in() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let config = AppConfig::from_env();
+        let state = Arc::new(MemoryStore::default());
+        let mods = ["CLUCKY".to_string(), "John".to_string()];
+        let commands = Commands::built_in(
+            CommandConfig::default(),
+            state.clone(),
+            &mods,
+            HashMap::new(),
+        );
+
+        assert_eq!(
+            commands
+                .dispatch(&message("!chickenoptout", &[])?, &config)
+                .await?,
+            Dispatch::Ran {
+                name: "chickenoptout".into(),
+                reply: Some("Clucky, you're out of scenarios until you opt back in.".into())
+            }
+        );
+        assert_eq!(state.opted_out_mods().await?, vec!["clucky"]);
+        assert!(matches!(
+            commands
+                .dispatch(&message("!ChickenOptIn", &[])?, &config)
+                .await?,
+            Dispatch::Ran { .. }
+        ));
+        assert!(state.opted_out_mods().await?.is_empty());
+
+        let others = Commands::built_in(
+            CommandConfig::default(),
+            state.clone(),
+            &mods[1..],
+            HashMap::new(),
+        );
+        assert_eq!(
+            others
+                .dispatch(&message("!chickenoptout", &[])?, &config)
+                .await?,
+            Dispatch::Ran {
+                name: "chickenoptout".into(),
+                reply: None
+            }
+        );
+        assert!(state.opted_out_mods().await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn custom_commands_reply_with_their_template() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let config = AppConfig::from_env();
+        let discord = CustomCommand {
+            reply: "{user_name}, {args}: join the {place} at https://discord.gg/coop".into(),
+            settings: CommandSettings::default(),
+        };
+        let fedboard = CustomCommand {
+            reply: "Not the real one".into(),
+            settings: CommandSettings::default(),
+        };
+        let settings = CommandConfig {
+            custom: [
+                ("Discord".to_string(), discord),
+                ("fedboard".to_string(), fedboard),
+            ]
+            .into(),
+            ..Default::default()
+        };
+        let components = MessageComponents {
+            variables: [("place".to_string(), vec!["coop".to_string()])].into(),
+            commands: Some(settings.clone()),
+            ..Default::default()
+        };
+        let mut commands = Commands::built_in(
+            settings,
+            Arc::new(MemoryStore::default()),
+            &[],
+            HashMap::new(),
+        );
+        commands.add_custom(Arc::new(components));
+
+        assert_eq!(
+            commands
+                .dispatch(&message("!discord friends", &[])?, &config)
+                .await?,
+            Dispatch::Ran {
+                name: "discord".into(),
+                reply: Some("Clucky, friends: join the coop at https://discord.gg/coop".into())
+            }
+        );
+        assert_eq!(
+            commands
+                .dispatch(&message("!fedboard", &[])?, &config)
+                .await?,
+            Dispatch::Ran {
+                name: "fedboard".into(),
+                reply: Some("Nobody has been fed yet this month.".into())
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn edit_custom_adds_and_removes_commands() -> Result<()> {
+        let config = r#"{"scenarios":[],"mods":["John"]}"#;
+        let lurk = CustomCommand {
+            reply: "Enjoy the lurk, {user_name}!".into(),
+            settings: CommandSettings {
+                permission: None,
+                cooldown_secs: Some(10),
+            },
+        };
+
+        let added = edit_custom(config, "Lurk", Some(&lurk))?.unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&added)?["commands"]["custom"]["lurk"],
+            json!({"reply": "Enjoy the lurk, {user_name}!", "cooldown_secs": 10})
+        );
+        let removed = edit_custom(&added, "lurk", None)?.unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&removed)?["commands"]["custom"],
+            json!({})
+        );
+        assert_eq!(edit_custom(&removed, "lurk", None)?, None);
+        assert!(edit_custom(config, "fedboard", Some(&lurk)).is_err());
+        assert!(edit_custom(config, "two words", Some(&lurk)).is_err());
+        Ok(())
+    }
+}
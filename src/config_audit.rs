@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+};
+use chrono::{DateTime, Duration, Utc};
+use lambda_http::{Body, Response};
+use reqwest::{StatusCode, header::CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use strum::AsRefStr;
+
+use crate::{
+    AppState,
+    admin_tokens::AdminScope,
+    audit::days_between,
+    config_diff::{ConfigChange, config_diff},
+    notify::NotifyEvent,
+    state::StateStore,
+};
+
+/// Most changed paths named in a summary.
+const SUMMARY_PATHS: usize = 3;
+
+/// Largest old or new value, serialized, kept in an entry. A change replacing a whole section
+/// (or the whole config, on the first save) would otherwise copy it into the log.
+const MAX_KEPT_VALUE_BYTES: usize = 1024;
+
+/// What swapped the message config in.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, AsRefStr)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ChangeSource {
+    /// `PUT /admin/config` and the `/admin/commands` routes.
+    AdminApi,
+    /// `POST /admin/reload`.
+    Reload,
+    /// SIGHUP to the standalone server.
+    Sighup,
+}
+
+/// One change to the message config and who made it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConfigAuditEntry {
+    pub changed_at: DateTime<Utc>,
+    /// Name of the admin token used, `admin` for `ADMIN_API_TOKEN`, or `signal` for SIGHUP.
+    pub actor: String,
+    pub source: ChangeSource,
+    /// Version saved through the admin API. Not set for reloads of the file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u64>,
+    pub summary: String,
+    pub changes: Vec<ConfigChange>,
+}
+
+impl ConfigAuditEntry {
+    pub fn new(
+        actor: &str,
+        source: ChangeSource,
+        version: Option<u64>,
+        before: &Value,
+        after: &Value,
+        changed_at: DateTime<Utc>,
+    ) -> Self {
+        let changes: Vec<ConfigChange> = config_diff(before, after)
+            .into_iter()
+            .map(|c| ConfigChange {
+                current: shorten(c.current),
+                submitted: shorten(c.submitted),
+                ..c
+            })
+            .collect();
+        ConfigAuditEntry {
+            changed_at,
+            actor: actor.to_string(),
+            source,
+            version,
+            summary: summarize(&changes),
+            changes,
+        }
+    }
+}
+
+/// `value`, or a note of its size when it's over [`MAX_KEPT_VALUE_BYTES`].
+fn shorten(value: Value) -> Value {
+    match serde_json::to_string(&value).map(|s| s.len()) {
+        Ok(len) if len > MAX_KEPT_VALUE_BYTES => Value::String(format!("({len} bytes, not kept)")),
+        _ => value,
+    }
+}
+
+/// e.g. `4 changes: /mods/2, /scenarios/0/template, /selection and 1 more`.
+pub fn summarize(changes: &[ConfigChange]) -> String {
+    let paths: Vec<&str> = changes
+        .iter()
+        .take(SUMMARY_PATHS)
+        .map(|c| match c.path.as_str() {
+            "" => "/",
+            path => path,
+        })
+        .collect();
+    let count = match changes.len() {
+        0 => return "no changes".into(),
+        1 => "1 change".to_string(),
+        n => format!("{n} changes"),
+    };
+    match changes.len().saturating_sub(SUMMARY_PATHS) {
+        0 => format!("{count}: {}", paths.join(", ")),
+        more => format!("{count}: {} and {more} more", paths.join(", ")),
+    }
+}
+
+pub async fn record(state: &dyn StateStore, entry: &ConfigAuditEntry) -> Result<()> {
+    state
+        .append_config_audit(
+            &entry.changed_at.format("%Y-%m-%d").to_string(),
+            &serde_json::to_string(entry)?,
+        )
+        .await
+}
+
+/// Records a config change, logging rather than failing when it can't be, and tells the
+/// broadcaster about it.
+pub async fn record_change(app: &AppState, entry: ConfigAuditEntry) {
+    let text = format!(
+        "Message config changed by {} through {}: {}",
+        entry.actor,
+        entry.source.as_ref(),
+        entry.summary
+    );
+    println!("{text}");
+    if let Err(e) = record(app.state.as_ref(), &entry).await {
+        println!("Failed to record config change: {e}");
+    }
+    if let Some(notifier) = app.notifier.as_ref() {
+        notifier.notify(NotifyEvent::ConfigChange, &text).await;
+    }
+}
+
+/// Changes made at or after `since`, oldest first.
+pub async fn entries_since(
+    state: &dyn StateStore,
+    since: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> Result<Vec<ConfigAuditEntry>> {
+    let mut entries = vec![];
+    for day in days_between(since, now) {
+        for raw in state.config_audit_entries(&day).await? {
+            match serde_json::from_str::<ConfigAuditEntry>(&raw) {
+                Ok(e) if e.changed_at >= since => entries.push(e),
+                Ok(_) => {}
+                Err(e) => println!("Skipping unreadable config change: {e}"),
+            }
+        }
+    }
+
+    entries.sort_by_key(|e| e.changed_at);
+    Ok(entries)
+}
+
+/// `GET /admin/config/history?since=2026-10-16T12:00:00Z` - config changes since then,
+/// defaulting to the last week.
+#[utoipa::path(get, path = "/admin/config/history", tag = "admin",
+    security(("admin_token" = [])),
+    params(("since" = Option<String>, Query, description = "RFC 3339 timestamp, a week ago by default")),
+    responses(
+        (status = 200, description = "Config changes, oldest first", body = Vec<Object>),
+        (status = 400, description = "Invalid `since`"),
+        (status = 401, description = "Token missing or unknown"),
+        (status = 403, description = "Token not scoped for this"),
+    ),
+)]
+pub async fn config_history_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response<Body> {
+    if let Err(status) = state.admin_authorized(&headers, AdminScope::Read).await {
+        return Response::builder()
+            .status(status)
+            .body(Body::Empty)
+            .unwrap();
+    }
+
+    let now = Utc::now();
+    let since = match params.get("since").map(|s| DateTime::parse_from_rfc3339(s)) {
+        Some(Ok(s)) => s.with_timezone(&Utc),
+        Some(Err(e)) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(format!("Invalid since: {e}")))
+                .unwrap();
+        }
+        None => now - Duration::days(7),
+    };
+
+    match entries_since(state.state.as_ref(), since, now).await {
+        Ok(entries) => Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_string(&entries).unwrap()))
+            .unwrap(),
+        Err(e) => {
+            println!("Failed to read config changes: {e}");
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::Empty)
+                .unwrap()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use chrono::{Duration, TimeZone, Utc};
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use crate::{
+        config_audit::{ChangeSource, ConfigAuditEntry, entries_since, record},
+        state::MemoryStore,
+    };
+
+    #[tokio::test]
+    async fn changes_are_recorded_with_actor_and_summary() -> Result<()> {
+        let store = MemoryStore::default();
+        let now = Utc.with_ymd_and_hms(2026, 10, 16, 20, 0, 0).unwrap();
+        let before = json!({"mods": ["John"], "scenarios": [], "selection": "random"});
+        let after =
+            json!({"mods": ["John", "Jane", "Jo"], "scenarios": [], "selection": "round_robin"});
+        let entry = ConfigAuditEntry::new(
+            "mods",
+            ChangeSource::AdminApi,
+            Some(3),
+            &before,
+            &after,
+            now,
+        );
+        record(&store, &entry).await?;
+        let old = ConfigAuditEntry::new(
+            "admin",
+            ChangeSource::Reload,
+            None,
+            &after,
+            &before,
+            now - Duration::days(8),
+        );
+        record(&store, &old).await?;
+
+        let entries = entries_since(&store, now - Duration::days(7), now).await?;
+
+        assert_eq!(entries, vec![entry]);
+        assert_eq!(entries[0].actor, "mods");
+        assert_eq!(
+            entries[0].summary,
+            "3 changes: /mods/1, /mods/2, /selection"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn summary_counts_paths_beyond_the_first_few() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 16, 20, 0, 0).unwrap();
+        let same = json!({"mods": ["John"]});
+        let entry = ConfigAuditEntry::new("signal", ChangeSource::Sighup, None, &same, &same, now);
+        assert_eq!(entry.summary, "no changes");
+
+        let entry = ConfigAuditEntry::new(
+            "signal",
+            ChangeSource::Sighup,
+            None,
+            &json!({"mods": []}),
+            &json!({"mods": ["a", "b", "c", "d", "e"]}),
+            now,
+        );
+        assert_eq!(
+            entry.summary,
+            "5 changes: /mods/0, /mods/1, /mods/2 and 2 more"
+        );
+    }
+
+    #[test]
+    fn large_values_are_not_copied_into_the_log() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 16, 20, 0, 0).unwrap();
+        let config = json!({"mods": vec!["a mod with a long name"; 100]});
+
+        let entry = ConfigAuditEntry::new(
+            "admin",
+            ChangeSource::AdminApi,
+            Some(1),
+            &serde_json::Value::Null,
+            &config,
+            now,
+        );
+
+        assert_eq!(entry.changes.len(), 1);
+        assert_eq!(entry.changes[0].current, serde_json::Value::Null);
+        assert_eq!(entry.changes[0].submitted, json!("(2510 bytes, not kept)"));
+    }
+}
@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// One place where a submitted config differs from the stored one. `path` is a JSON pointer.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConfigChange {
+    pub path: String,
+    pub current: Value,
+    pub submitted: Value,
+}
+
+/// Every place where `submitted` differs from `current`, in path order.
+pub fn config_diff(current: &Value, submitted: &Value) -> Vec<ConfigChange> {
+    let mut changes = vec![];
+    collect_changes("", current, submitted, &mut changes);
+    changes
+}
+
+fn collect_changes(path: &str, current: &Value, submitted: &Value, out: &mut Vec<ConfigChange>) {
+    match (current, submitted) {
+        (Value::Object(a), Value::Object(b)) => {
+            let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let escaped = key.replace('~', "~0").replace('/', "~1");
+                collect_changes(
+                    &format!("{path}/{escaped}"),
+                    a.get(key).unwrap_or(&Value::Null),
+                    b.get(key).unwrap_or(&Value::Null),
+                    out,
+                );
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            for i in 0..a.len().max(b.len()) {
+                collect_changes(
+                    &format!("{path}/{i}"),
+                    a.get(i).unwrap_or(&Value::Null),
+                    b.get(i).unwrap_or(&Value::Null),
+                    out,
+                );
+            }
+        }
+        (a, b) if a != b => out.push(ConfigChange {
+            path: path.to_string(),
+            current: a.clone(),
+            submitted: b.clone(),
+        }),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use crate::config_diff::{ConfigChange, config_diff};
+
+    #[test]
+    fn config_diff_lists_changed_paths() {
+        let current = json!({"mods": ["John", "Jane"], "anti_repeat_window": 1});
+        let submitted = json!({"mods": ["John"], "anti_repeat_window": 2, "selection": "random"});
+
+        let changes = config_diff(&current, &submitted);
+
+        assert_eq!(
+            changes,
+            vec![
+                ConfigChange {
+                    path: "/anti_repeat_window".into(),
+                    current: json!(1),
+                    submitted: json!(2),
+                },
+                ConfigChange {
+                    path: "/mods/1".into(),
+                    current: json!("Jane"),
+                    submitted: json!(null),
+                },
+                ConfigChange {
+                    path: "/selection".into(),
+                    current: json!(null),
+                    submitted: json!("random"),
+                },
+            ]
+        );
+    }
+}
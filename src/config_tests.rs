@@ -0,0 +1,165 @@
+use std::{collections::HashMap, fmt};
+
+use chrono::{DateTime, Utc};
+use fastrand::Rng;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::robochick::twitch::{
+    MessageBuilder, MessageComponents, RandomPicker, Robochick, TemplateContext,
+};
+
+/// A regression test for the config's templates, from its `tests` section. Mods are always
+/// picked at random, since the other selections depend on earlier posts.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct ConfigTest {
+    pub name: String,
+    /// Seeds the picks, so the same scenario and mods come up every run.
+    pub seed: u64,
+    /// Group to post from. The default group when unset.
+    #[serde(default)]
+    pub group: Option<String>,
+    /// When the message is built, for testing seasonal scenarios. Now when unset.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    pub at: Option<DateTime<Utc>>,
+    /// Placeholder values, e.g. `random_viewer` or `reward_title`.
+    #[serde(default)]
+    pub context: HashMap<String, String>,
+    /// Mods expected to be picked as winners, in order. Not checked when unset.
+    #[serde(default)]
+    pub winners: Option<Vec<String>>,
+    /// Expected message. Not checked when unset.
+    #[serde(default)]
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestResult {
+    pub name: String,
+    /// Why the test failed. Empty when it passed.
+    pub failures: Vec<String>,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Runs one test against the config.
+pub fn run(test: &ConfigTest, components: &MessageComponents) -> TestResult {
+    let mut result = TestResult {
+        name: test.name.clone(),
+        failures: vec![],
+    };
+
+    let group = match test.group.as_deref() {
+        Some(name) => match components.group_named(name) {
+            Some(group) => group,
+            None => {
+                result
+                    .failures
+                    .push(format!("no scenario group named {name}"));
+                return result;
+            }
+        },
+        None => components.default_group(),
+    }
+    .active_at(test.at.unwrap_or_else(Utc::now));
+    let mut context = TemplateContext::default();
+    for (key, val) in &test.context {
+        context.insert(key, val);
+    }
+
+    let mut rng = Rng::with_seed(test.seed);
+    if let Err(e) = components.resolve_variables(&mut context, &mut rng) {
+        result
+            .failures
+            .push(format!("filling in variables failed: {e}"));
+        return result;
+    }
+    let built = match Robochick::build_from_group(
+        &group,
+        components.get_mods(),
+        &mut RandomPicker,
+        &context,
+        &mut rng,
+    ) {
+        Ok(built) => built,
+        Err(e) => {
+            result.failures.push(format!("building failed: {e}"));
+            return result;
+        }
+    };
+
+    if let Some(winners) = test.winners.as_ref()
+        && *winners != built.winners
+    {
+        result.failures.push(format!(
+            "expected winners {winners:?}, got {:?}",
+            built.winners
+        ));
+    }
+    if let Some(output) = test.output.as_ref()
+        && *output != built.message
+    {
+        result.failures.push(format!(
+            "expected output {output:?}, got {:?}",
+            built.message
+        ));
+    }
+    result
+}
+
+/// Runs every test in the config's `tests` section.
+pub fn run_all(components: &MessageComponents) -> Vec<TestResult> {
+    components
+        .get_tests()
+        .iter()
+        .map(|test| run(test, components))
+        .collect()
+}
+
+impl fmt::Display for TestResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.passed() {
+            return write!(f, "ok {}", self.name);
+        }
+        write!(f, "FAILED {}", self.name)?;
+        for failure in &self.failures {
+            write!(f, "\n  {failure}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use pretty_assertions::assert_eq;
+
+    use crate::{config_tests::run_all, reward::mod_feeder::read_config};
+
+    #[test]
+    fn run_all_checks_winners_and_output_for_seed() -> Result<()> {
+        let components = read_config(&"resources/tests/message_components_tests.json".into())?;
+
+        let results = run_all(&components);
+
+        assert_eq!(results.len(), 3);
+        assert!(results[0].passed(), "{}", results[0]);
+        assert_eq!(
+            results[1].failures,
+            vec![
+                "expected winners [\"Nobody\"], got [\"John\"]",
+                "expected output \"Nobody ate the chicken\", got \"John fed the chicken\"",
+            ]
+        );
+        assert_eq!(
+            results[2].to_string(),
+            "FAILED missing group\n  no scenario group named nope"
+        );
+        Ok(())
+    }
+}
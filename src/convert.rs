@@ -0,0 +1,342 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use strum::EnumString;
+
+use crate::robochick::twitch::{MessageComponents, RANDOM_VIEWER, Scenario, ScenarioGroup};
+
+/// Chat bots whose custom command exports can be converted to and from robochick config.
+#[derive(Debug, Clone, Copy, PartialEq, EnumString)]
+pub enum BotFormat {
+    #[strum(serialize = "streamelements")]
+    StreamElements,
+    #[strum(serialize = "nightbot")]
+    Nightbot,
+}
+
+/// A StreamElements custom command, as returned by `GET /kappa/v2/bot/commands/{channel}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SeCommand {
+    pub command: String,
+    pub reply: String,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+}
+
+/// Nightbot's `GET /1/commands` response. Only the fields robochick has a use for are kept.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NightbotExport {
+    pub commands: Vec<NightbotCommand>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NightbotCommand {
+    pub name: String,
+    pub message: String,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// Result of a conversion, along with a note for every command or scenario that couldn't be
+/// carried over.
+#[derive(Debug)]
+pub struct Converted<T> {
+    pub output: T,
+    pub skipped: Vec<String>,
+}
+
+/// Bot variables with a robochick placeholder equivalent, as `(variable, placeholder)`.
+const SE_VARIABLES: &[(&str, &str)] = &[("random.chatter", RANDOM_VIEWER)];
+const NIGHTBOT_VARIABLES: &[(&str, &str)] = &[];
+
+impl BotFormat {
+    fn delimiters(&self) -> (&'static str, char) {
+        match self {
+            BotFormat::StreamElements => ("${", '}'),
+            BotFormat::Nightbot => ("$(", ')'),
+        }
+    }
+
+    fn variables(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            BotFormat::StreamElements => SE_VARIABLES,
+            BotFormat::Nightbot => NIGHTBOT_VARIABLES,
+        }
+    }
+
+    fn command_prefix(&self) -> &'static str {
+        match self {
+            BotFormat::StreamElements => "",
+            BotFormat::Nightbot => "!",
+        }
+    }
+}
+
+/// Turns a bot's command export into message components with one group per command. The
+/// groups have no reward ids, so they only take effect once they're assigned to a reward.
+pub fn import(format: BotFormat, export: &str) -> Result<Converted<MessageComponents>> {
+    let commands: Vec<(String, String)> = match format {
+        BotFormat::StreamElements => serde_json::from_str::<Vec<SeCommand>>(export)?
+            .into_iter()
+            .filter(|c| c.enabled)
+            .map(|c| (c.command, c.reply))
+            .collect(),
+        BotFormat::Nightbot => serde_json::from_str::<NightbotExport>(export)?
+            .commands
+            .into_iter()
+            .map(|c| (c.name, c.message))
+            .collect(),
+    };
+
+    let mut groups = vec![];
+    let mut skipped = vec![];
+    for (name, reply) in commands {
+        let name = name.trim_start_matches('!').to_string();
+        match import_template(format, &reply) {
+            Ok(template) => groups.push(ScenarioGroup {
+                name: name.clone(),
+                scenarios: vec![Scenario {
+                    id: Some(name),
+                    template,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            Err(e) => skipped.push(format!("{name}: {e}")),
+        }
+    }
+
+    Ok(Converted {
+        output: MessageComponents {
+            groups,
+            ..Default::default()
+        },
+        skipped,
+    })
+}
+
+/// Turns message components into a bot command export with one command per scenario.
+/// Scenarios that need mods filled in are skipped since the bots have no way to do that.
+pub fn export(format: BotFormat, components: &MessageComponents) -> Result<Converted<String>> {
+    let mut groups = vec![components.default_group()];
+    groups.extend(components.groups.iter().cloned());
+
+    let mut commands = vec![];
+    let mut skipped = vec![];
+    for group in groups.iter().filter(|g| !g.scenarios.is_empty()) {
+        for (i, scenario) in group.scenarios.iter().enumerate() {
+            let name = match (scenario.id.as_ref(), i) {
+                (Some(id), _) => id.clone(),
+                (None, 0) => group.name.clone(),
+                (None, i) => format!("{}{}", group.name, i + 1),
+            };
+
+            if !scenario.winners.is_empty() || !scenario.others.is_empty() {
+                skipped.push(format!("{name}: picks mods, which {format:?} can't do"));
+                continue;
+            }
+
+            match export_template(format, &scenario.template) {
+                Ok(reply) => commands.push((name, reply)),
+                Err(e) => skipped.push(format!("{name}: {e}")),
+            }
+        }
+    }
+
+    let prefix = format.command_prefix();
+    let output = match format {
+        BotFormat::StreamElements => serde_json::to_string_pretty(
+            &commands
+                .into_iter()
+                .map(|(command, reply)| SeCommand {
+                    command,
+                    reply,
+                    enabled: true,
+                })
+                .collect::<Vec<_>>(),
+        )?,
+        BotFormat::Nightbot => serde_json::to_string_pretty(&NightbotExport {
+            commands: commands
+                .into_iter()
+                .map(|(name, message)| NightbotCommand {
+                    name: format!("{prefix}{name}"),
+                    message,
+                })
+                .collect(),
+        })?,
+    };
+
+    Ok(Converted { output, skipped })
+}
+
+/// Rewrites bot variables as robochick placeholders and escapes any other braces.
+fn import_template(format: BotFormat, reply: &str) -> Result<String> {
+    let (open, close) = format.delimiters();
+    let mut template = String::new();
+    let mut rest = reply;
+
+    while let Some(start) = rest.find(open) {
+        template.push_str(&escape_braces(&rest[..start]));
+        let after = &rest[start + open.len()..];
+        let end = after
+            .find(close)
+            .ok_or(anyhow!("unclosed variable in {reply:?}"))?;
+        let variable = after[..end].trim();
+
+        let placeholder = format
+            .variables()
+            .iter()
+            .find(|(v, _)| *v == variable)
+            .map(|(_, p)| *p)
+            .ok_or(anyhow!(
+                "{open}{variable}{close} has no robochick equivalent"
+            ))?;
+        template.push_str(&format!("{{{placeholder}}}"));
+        rest = &after[end + 1..];
+    }
+
+    template.push_str(&escape_braces(rest));
+    Ok(template)
+}
+
+/// Rewrites robochick placeholders as bot variables and unescapes braces.
+fn export_template(format: BotFormat, template: &str) -> Result<String> {
+    let (open, close) = format.delimiters();
+    let mut reply = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                reply.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                reply.push('}');
+            }
+            '{' => {
+                let placeholder: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                let variable = format
+                    .variables()
+                    .iter()
+                    .find(|(_, p)| *p == placeholder)
+                    .map(|(v, _)| *v)
+                    .ok_or(anyhow!("{{{placeholder}}} has no {format:?} equivalent"))?;
+                reply.push_str(&format!("{open}{variable}{close}"));
+            }
+            c => reply.push(c),
+        }
+    }
+
+    Ok(reply)
+}
+
+fn escape_braces(text: &str) -> String {
+    text.replace('{', "{{").replace('}', "}}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use anyhow::Result;
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+
+    use crate::{
+        convert::{BotFormat, export, import},
+        robochick::twitch::{MessageComponents, Scenario, TemplateContext},
+    };
+
+    #[test]
+    fn bot_format_parses_from_cli_name() -> Result<()> {
+        assert_eq!(
+            BotFormat::from_str("streamelements")?,
+            BotFormat::StreamElements
+        );
+        assert_eq!(BotFormat::from_str("nightbot")?, BotFormat::Nightbot);
+        assert!(BotFormat::from_str("moobot").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn import_streamelements_converts_variables_and_skips_unknown_ones() -> Result<()> {
+        let export = r#"[
+            {"command": "cracker", "reply": "${random.chatter} found a {dry} cracker", "enabled": true, "cooldown": {"user": 15, "global": 5}},
+            {"command": "hug", "reply": "${user} hugs ${1}", "enabled": true},
+            {"command": "old", "reply": "gone", "enabled": false}
+        ]"#;
+
+        let converted = import(BotFormat::StreamElements, export)?;
+
+        let groups = &converted.output.groups;
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].get_name(), "cracker");
+
+        let mut context = TemplateContext::default();
+        context.insert("random_viewer", "john");
+        let message = groups[0].scenarios[0].build(&[], &[], &context)?;
+        assert_eq!(message, "john found a {dry} cracker");
+
+        assert_eq!(converted.skipped.len(), 1);
+        assert!(converted.skipped[0].starts_with("hug:"));
+        Ok(())
+    }
+
+    #[test]
+    fn import_nightbot_strips_command_prefix() -> Result<()> {
+        let export = r#"{"commands": [{"name": "!chicken", "message": "bawk", "coolDown": 5, "userLevel": "everyone"}]}"#;
+
+        let converted = import(BotFormat::Nightbot, export)?;
+
+        assert_eq!(converted.output.groups[0].get_name(), "chicken");
+        assert_eq!(converted.output.groups[0].scenarios[0].template, "bawk");
+        assert!(converted.skipped.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn export_round_trips_imported_commands() -> Result<()> {
+        let export_json =
+            r#"[{"command": "cracker", "reply": "${random.chatter} found a {dry} cracker"}]"#;
+
+        let imported = import(BotFormat::StreamElements, export_json)?;
+        let exported = export(BotFormat::StreamElements, &imported.output)?;
+
+        let commands: Value = serde_json::from_str(&exported.output)?;
+        assert_eq!(commands[0]["command"], "cracker");
+        assert_eq!(
+            commands[0]["reply"],
+            "${random.chatter} found a {dry} cracker"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn export_skips_scenarios_that_pick_mods() -> Result<()> {
+        let components = MessageComponents {
+            scenarios: vec![
+                Scenario {
+                    template: "{win_1} gets the cracker".into(),
+                    winners: vec!["win_1".into()],
+                    ..Default::default()
+                },
+                Scenario {
+                    template: "Nobody gets a cracker".into(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let exported = export(BotFormat::Nightbot, &components)?;
+
+        let commands: Value = serde_json::from_str(&exported.output)?;
+        assert_eq!(commands["commands"][0]["name"], "!default2");
+        assert_eq!(commands["commands"][0]["message"], "Nobody gets a cracker");
+        assert_eq!(exported.skipped.len(), 1);
+        Ok(())
+    }
+}
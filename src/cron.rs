@@ -0,0 +1,192 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{Result, anyhow};
+use chrono::{Datelike, Timelike};
+use serde::{Deserialize, Serialize};
+
+/// A five-field cron expression (`minute hour day-of-month month day-of-week`) used to match
+/// points in time rather than to schedule anything. Fields take `*`, numbers, ranges (`1-5`),
+/// lists (`1,3`) and steps (`*/15`, `8-18/2`). Day-of-week runs from 0 (Sunday) to 6, with 7
+/// also meaning Sunday.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub struct CronExpr {
+    source: String,
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    /// Cron matches either day field when both are restricted, and both otherwise. Like Vixie
+    /// cron, a field starting with `*` (e.g. `*/2`) doesn't count as restricted.
+    day_of_month_any: bool,
+    day_of_week_any: bool,
+}
+
+impl CronExpr {
+    pub fn matches<T: Datelike + Timelike>(&self, at: &T) -> bool {
+        let day_of_month = bit(self.days_of_month, at.day());
+        let day_of_week = bit(self.days_of_week, at.weekday().num_days_from_sunday());
+        let day = match self.day_of_month_any || self.day_of_week_any {
+            true => day_of_month && day_of_week,
+            false => day_of_month || day_of_week,
+        };
+
+        bit(self.minutes, at.minute())
+            && bit(self.hours, at.hour())
+            && bit(self.months, at.month())
+            && day
+    }
+}
+
+fn bit(mask: u64, value: u32) -> bool {
+    mask & (1 << value) != 0
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64> {
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((r, s)) => (r, s.parse::<u32>()?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(anyhow!("Step of 0 in {field}"));
+        }
+
+        let (start, end) = match range {
+            "*" => (min, max),
+            r => match r.split_once('-') {
+                Some((a, b)) => (a.parse()?, b.parse()?),
+                None => {
+                    let n = r.parse()?;
+                    (n, if step > 1 { max } else { n })
+                }
+            },
+        };
+        if start < min || end > max || start > end {
+            return Err(anyhow!("{part} is outside {min}-{max}"));
+        }
+
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+
+    Ok(mask)
+}
+
+impl FromStr for CronExpr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [minute, hour, dom, month, dow] = fields[..] else {
+            return Err(anyhow!("Expected 5 fields in cron expression {s:?}"));
+        };
+
+        let mut days_of_week = parse_field(dow, 0, 7)?;
+        if bit(days_of_week, 7) {
+            days_of_week |= 1;
+        }
+
+        Ok(CronExpr {
+            source: s.to_string(),
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)?,
+            days_of_month: parse_field(dom, 1, 31)?,
+            months: parse_field(month, 1, 12)?,
+            days_of_week,
+            day_of_month_any: dom.starts_with('*'),
+            day_of_week_any: dow.starts_with('*'),
+        })
+    }
+}
+
+impl TryFrom<String> for CronExpr {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<CronExpr> for String {
+    fn from(expr: CronExpr) -> Self {
+        expr.source
+    }
+}
+
+impl fmt::Display for CronExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use chrono::{TimeZone, Utc};
+
+    use crate::cron::CronExpr;
+
+    #[test]
+    fn matches_ranges_and_steps() -> Result<()> {
+        let expr: CronExpr = "*/15 0-6 * * *".parse()?;
+
+        assert!(expr.matches(&Utc.with_ymd_and_hms(2026, 10, 16, 3, 45, 0).unwrap()));
+        assert!(!expr.matches(&Utc.with_ymd_and_hms(2026, 10, 16, 3, 44, 0).unwrap()));
+        assert!(!expr.matches(&Utc.with_ymd_and_hms(2026, 10, 16, 7, 0, 0).unwrap()));
+        Ok(())
+    }
+
+    #[test]
+    fn matches_day_of_week_with_sunday_as_seven() -> Result<()> {
+        let expr: CronExpr = "* * * * 6,7".parse()?;
+
+        // 2026-10-17 is a Saturday, 2026-10-18 a Sunday.
+        assert!(expr.matches(&Utc.with_ymd_and_hms(2026, 10, 17, 12, 0, 0).unwrap()));
+        assert!(expr.matches(&Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap()));
+        assert!(!expr.matches(&Utc.with_ymd_and_hms(2026, 10, 16, 12, 0, 0).unwrap()));
+        Ok(())
+    }
+
+    #[test]
+    fn matches_either_day_field_when_both_are_set() -> Result<()> {
+        let expr: CronExpr = "* * 1 * 1".parse()?;
+
+        // The 1st of the month, and a Monday.
+        assert!(expr.matches(&Utc.with_ymd_and_hms(2026, 10, 1, 12, 0, 0).unwrap()));
+        assert!(expr.matches(&Utc.with_ymd_and_hms(2026, 10, 19, 12, 0, 0).unwrap()));
+        assert!(!expr.matches(&Utc.with_ymd_and_hms(2026, 10, 20, 12, 0, 0).unwrap()));
+        Ok(())
+    }
+
+    #[test]
+    fn day_fields_with_a_star_step_dont_widen_the_other() -> Result<()> {
+        let expr: CronExpr = "* * */2 * 1".parse()?;
+
+        // Only Mondays on odd days: the 19th, but neither the 26th (a Monday) nor the 17th.
+        assert!(expr.matches(&Utc.with_ymd_and_hms(2026, 10, 19, 12, 0, 0).unwrap()));
+        assert!(!expr.matches(&Utc.with_ymd_and_hms(2026, 10, 26, 12, 0, 0).unwrap()));
+        assert!(!expr.matches(&Utc.with_ymd_and_hms(2026, 10, 17, 12, 0, 0).unwrap()));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_rejects_malformed_expressions() {
+        assert!("* * * *".parse::<CronExpr>().is_err());
+        assert!("60 * * * *".parse::<CronExpr>().is_err());
+        assert!("*/0 * * * *".parse::<CronExpr>().is_err());
+        assert!("5-1 * * * *".parse::<CronExpr>().is_err());
+    }
+
+    #[test]
+    fn deserializes_from_string() -> Result<()> {
+        let expr: CronExpr = serde_json::from_str(r#""0 9 * * 1-5""#)?;
+
+        assert_eq!(expr.to_string(), "0 9 * * 1-5");
+        assert!(serde_json::from_str::<CronExpr>(r#""nope""#).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,478 @@
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use aws_sdk_dynamodb::{
+    Client,
+    types::{
+        AttributeDefinition, AttributeValue, BillingMode, KeySchemaElement, KeyType,
+        ScalarAttributeType, TableStatus, TimeToLiveSpecification, TimeToLiveStatus,
+    },
+};
+
+use crate::{
+    config::AppConfig,
+    state::{EXPIRES_AT, StateBackend},
+};
+
+/// Version of the DynamoDB layout this build expects. It's kept in the state table's
+/// `schema_version` item so an older build can refuse tables set up by a newer one.
+pub const DYNAMO_SCHEMA_VERSION: u64 = 2;
+
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// How often, and how many times, a new table is checked before giving up on it turning active.
+const ACTIVE_POLL: Duration = Duration::from_secs(2);
+const ACTIVE_POLL_ATTEMPTS: u32 = 60;
+
+/// A DynamoDB table the bot needs, keyed by a string attribute and, for tables holding several
+/// items under one key, a string sort key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableSpec {
+    pub name: String,
+    pub key: &'static str,
+    pub sort_key: Option<&'static str>,
+    /// Attribute holding the epoch second an item expires at.
+    pub ttl: Option<&'static str>,
+}
+
+/// The duck rewards table, keyed by the redemption's message id, and the state and log tables
+/// when DynamoDB keeps the state.
+pub fn dynamo_tables(config: &AppConfig) -> Vec<TableSpec> {
+    let mut tables = vec![TableSpec {
+        name: config.duck_rewards_table_name.clone(),
+        key: "message_id",
+        sort_key: None,
+        ttl: None,
+    }];
+    if let (Some(name), Some(log_name)) = (config.state_table_name.clone(), config.log_table_name())
+        && StateBackend::for_config(config) == StateBackend::Dynamodb
+    {
+        tables.push(TableSpec {
+            name,
+            key: "pk",
+            sort_key: None,
+            ttl: Some(EXPIRES_AT),
+        });
+        tables.push(TableSpec {
+            name: log_name,
+            key: "pk",
+            sort_key: Some("sk"),
+            ttl: Some(EXPIRES_AT),
+        });
+    }
+    tables
+}
+
+/// How a table is keyed, for reports and errors.
+fn describe_keys(key: &str, sort_key: Option<&str>) -> String {
+    match sort_key {
+        Some(sort_key) => format!("{key} and {sort_key}"),
+        None => key.to_string(),
+    }
+}
+
+/// Creates the tables that don't exist yet, enables their TTL and records the schema version.
+/// Existing tables are checked, never changed beyond that. Returns a line for each step.
+pub async fn migrate_dynamo(client: &Client, tables: &[TableSpec]) -> Result<Vec<String>> {
+    let mut report = vec![];
+    for table in tables {
+        report.push(ensure_table(client, table).await?);
+        if let Some(attribute) = table.ttl {
+            report.push(ensure_ttl(client, &table.name, attribute).await?);
+        }
+    }
+
+    // The version goes in the state table, the only one keyed by `pk` alone.
+    if let Some(state) = tables
+        .iter()
+        .find(|t| t.key == "pk" && t.sort_key.is_none())
+    {
+        report.push(record_version(client, &state.name).await?);
+    }
+    Ok(report)
+}
+
+async fn ensure_table(client: &Client, table: &TableSpec) -> Result<String> {
+    let name = &table.name;
+    match client.describe_table().table_name(name).send().await {
+        Ok(output) => {
+            let schema = output.table().map(|t| t.key_schema()).unwrap_or_default();
+            let key = |kind: KeyType| {
+                schema
+                    .iter()
+                    .find(|k| k.key_type() == &kind)
+                    .map(|k| k.attribute_name())
+            };
+            let expected = describe_keys(table.key, table.sort_key);
+            match (key(KeyType::Hash), key(KeyType::Range)) {
+                (Some(k), sort_key) if k == table.key && sort_key == table.sort_key => {
+                    Ok(format!("{name}: exists"))
+                }
+                (Some(k), sort_key) => Err(anyhow!(
+                    "{name} is keyed by {}, expected {expected}",
+                    describe_keys(k, sort_key)
+                )),
+                (None, _) => Err(anyhow!("{name} is keyed by nothing, expected {expected}")),
+            }
+        }
+        Err(e)
+            if e.as_service_error()
+                .is_some_and(|e| e.is_resource_not_found_exception()) =>
+        {
+            let mut request = client
+                .create_table()
+                .table_name(name)
+                .billing_mode(BillingMode::PayPerRequest);
+            let keys = [
+                Some((table.key, KeyType::Hash)),
+                table.sort_key.map(|k| (k, KeyType::Range)),
+            ];
+            for (attribute, kind) in keys.into_iter().flatten() {
+                request = request
+                    .attribute_definitions(
+                        AttributeDefinition::builder()
+                            .attribute_name(attribute)
+                            .attribute_type(ScalarAttributeType::S)
+                            .build()?,
+                    )
+                    .key_schema(
+                        KeySchemaElement::builder()
+                            .attribute_name(attribute)
+                            .key_type(kind)
+                            .build()?,
+                    );
+            }
+            let output = request
+                .send()
+                .await
+                .map_err(|e| anyhow!("Failed to create {name}: {e}"))?;
+
+            let status = output.table_description().and_then(|t| t.table_status());
+            if status != Some(&TableStatus::Active) {
+                wait_until_active(client, name).await?;
+            }
+            Ok(format!(
+                "{name}: created, keyed by {}",
+                describe_keys(table.key, table.sort_key)
+            ))
+        }
+        Err(e) => Err(anyhow!("Failed to describe {name}: {e}")),
+    }
+}
+
+async fn wait_until_active(client: &Client, name: &str) -> Result<()> {
+    for _ in 0..ACTIVE_POLL_ATTEMPTS {
+        tokio::time::sleep(ACTIVE_POLL).await;
+        let output = client
+            .describe_table()
+            .table_name(name)
+            .send()
+            .await
+            .map_err(|e| anyhow!("Failed to describe {name}: {e}"))?;
+        if output.table().and_then(|t| t.table_status()) == Some(&TableStatus::Active) {
+            return Ok(());
+        }
+    }
+    Err(anyhow!("{name} did not become active"))
+}
+
+async fn ensure_ttl(client: &Client, name: &str, attribute: &str) -> Result<String> {
+    let current = client
+        .describe_time_to_live()
+        .table_name(name)
+        .send()
+        .await
+        .map_err(|e| anyhow!("Failed to describe the TTL of {name}: {e}"))?;
+    let description = current.time_to_live_description();
+    let status = description.and_then(|d| d.time_to_live_status());
+    if matches!(
+        status,
+        Some(TimeToLiveStatus::Enabled | TimeToLiveStatus::Enabling)
+    ) {
+        return match description.and_then(|d| d.attribute_name()) {
+            Some(a) if a == attribute => Ok(format!("{name}: TTL on {attribute} already enabled")),
+            other => Err(anyhow!(
+                "{name} has TTL on {}, expected {attribute}",
+                other.unwrap_or("nothing")
+            )),
+        };
+    }
+
+    client
+        .update_time_to_live()
+        .table_name(name)
+        .time_to_live_specification(
+            TimeToLiveSpecification::builder()
+                .attribute_name(attribute)
+                .enabled(true)
+                .build()?,
+        )
+        .send()
+        .await
+        .map_err(|e| anyhow!("Failed to enable the TTL of {name}: {e}"))?;
+    Ok(format!("{name}: enabled TTL on {attribute}"))
+}
+
+async fn record_version(client: &Client, name: &str) -> Result<String> {
+    let output = client
+        .get_item()
+        .table_name(name)
+        .key("pk", AttributeValue::S(SCHEMA_VERSION_KEY.into()))
+        .consistent_read(true)
+        .send()
+        .await
+        .map_err(|e| anyhow!("Failed to read the schema version from {name}: {e}"))?;
+    let stored = output
+        .item()
+        .and_then(|i| i.get("version"))
+        .and_then(|v| v.as_n().ok())
+        .and_then(|n| n.parse::<u64>().ok())
+        .unwrap_or(0);
+
+    if stored > DYNAMO_SCHEMA_VERSION {
+        return Err(anyhow!(
+            "{name} is schema version {stored}, newer than this build's {DYNAMO_SCHEMA_VERSION}"
+        ));
+    }
+    if stored == DYNAMO_SCHEMA_VERSION {
+        return Ok(format!(
+            "{name}: already schema version {DYNAMO_SCHEMA_VERSION}"
+        ));
+    }
+
+    client
+        .put_item()
+        .table_name(name)
+        .item("pk", AttributeValue::S(SCHEMA_VERSION_KEY.into()))
+        .item(
+            "version",
+            AttributeValue::N(DYNAMO_SCHEMA_VERSION.to_string()),
+        )
+        .send()
+        .await
+        .map_err(|e| anyhow!("Failed to record the schema version in {name}: {e}"))?;
+    Ok(format!(
+        "{name}: schema version {stored} -> {DYNAMO_SCHEMA_VERSION}"
+    ))
+}
+
+/// Sets up whatever the configured backends need: the DynamoDB tables, or the SQLite schema
+/// when that's where the state is kept. Redis and memory need nothing.
+pub async fn migrate(config: &AppConfig, client: &Client) -> Result<Vec<String>> {
+    let mut report = migrate_dynamo(client, &dynamo_tables(config)).await?;
+    match StateBackend::for_config(config) {
+        #[cfg(feature = "sqlite")]
+        StateBackend::Sqlite => {
+            let path = &config.sqlite_path;
+            let mut conn = rusqlite::Connection::open(path)
+                .map_err(|e| anyhow!("Failed to open SQLite at {path}: {e}"))?;
+            let (from, to) = crate::state::sqlite_store::migrate(&mut conn)?;
+            report.push(match from == to {
+                true => format!("{path}: already schema version {to}"),
+                false => format!("{path}: schema version {from} -> {to}"),
+            });
+        }
+        #[cfg(not(feature = "sqlite"))]
+        StateBackend::Sqlite => return Err(anyhow!("This build has no sqlite feature")),
+        backend @ (StateBackend::Redis | StateBackend::Memory) => {
+            report.push(format!("{}: nothing to set up", backend.as_ref()));
+        }
+        StateBackend::Dynamodb => {}
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use aws_sdk_dynamodb::{
+        Client,
+        operation::{
+            create_table::CreateTableOutput,
+            describe_table::{DescribeTableError, DescribeTableOutput},
+            describe_time_to_live::DescribeTimeToLiveOutput,
+            get_item::GetItemOutput,
+            put_item::PutItemOutput,
+            update_time_to_live::UpdateTimeToLiveOutput,
+        },
+        types::{
+            AttributeValue, KeySchemaElement, KeyType, TableDescription, TableStatus,
+            TimeToLiveDescription, TimeToLiveStatus, error::ResourceNotFoundException,
+        },
+    };
+    use aws_smithy_mocks::{Rule, RuleMode, mock, mock_client};
+    use pretty_assertions::assert_eq;
+
+    use crate::db::{TableSpec, migrate_dynamo};
+
+    fn tables() -> Vec<TableSpec> {
+        vec![
+            TableSpec {
+                name: "ducks".into(),
+                key: "message_id",
+                sort_key: None,
+                ttl: None,
+            },
+            TableSpec {
+                name: "state".into(),
+                key: "pk",
+                sort_key: None,
+                ttl: Some("expires_at"),
+            },
+            TableSpec {
+                name: "state-log".into(),
+                key: "pk",
+                sort_key: Some("sk"),
+                ttl: Some("expires_at"),
+            },
+        ]
+    }
+
+    #[tokio::test]
+    async fn migrate_creates_missing_tables_enables_ttl_and_records_version() -> Result<()> {
+        let missing: Rule = mock!(Client::describe_table).then_error(|| {
+            DescribeTableError::ResourceNotFoundException(
+                ResourceNotFoundException::builder().build(),
+            )
+        });
+        let created = || {
+            CreateTableOutput::builder()
+                .table_description(
+                    TableDescription::builder()
+                        .table_status(TableStatus::Active)
+                        .build(),
+                )
+                .build()
+        };
+        let create: Rule = mock!(Client::create_table)
+            .match_requests(|r| {
+                r.key_schema().len() == 1
+                    && (r.key_schema()[0].attribute_name() == "message_id"
+                        || r.key_schema()[0].attribute_name() == "pk")
+            })
+            .then_output(created);
+        let create_log: Rule = mock!(Client::create_table)
+            .match_requests(|r| {
+                r.key_schema().len() == 2
+                    && r.key_schema()[1].attribute_name() == "sk"
+                    && r.key_schema()[1].key_type() == &KeyType::Range
+            })
+            .then_output(created);
+        let ttl_off: Rule = mock!(Client::describe_time_to_live).then_output(|| {
+            DescribeTimeToLiveOutput::builder()
+                .time_to_live_description(
+                    TimeToLiveDescription::builder()
+                        .time_to_live_status(TimeToLiveStatus::Disabled)
+                        .build(),
+                )
+                .build()
+        });
+        let enable_ttl: Rule = mock!(Client::update_time_to_live)
+            .match_requests(|r| {
+                r.time_to_live_specification()
+                    .is_some_and(|s| s.attribute_name() == "expires_at" && s.enabled())
+            })
+            .then_output(|| UpdateTimeToLiveOutput::builder().build());
+        let no_version: Rule =
+            mock!(Client::get_item).then_output(|| GetItemOutput::builder().build());
+        let put_version: Rule = mock!(Client::put_item)
+            .match_requests(|r| {
+                r.item().and_then(|i| i.get("version")) == Some(&AttributeValue::N("2".into()))
+            })
+            .then_output(|| PutItemOutput::builder().build());
+        let client = mock_client!(
+            aws_sdk_dynamodb,
+            RuleMode::MatchAny,
+            [
+                &missing,
+                &create,
+                &create_log,
+                &ttl_off,
+                &enable_ttl,
+                &no_version,
+                &put_version
+            ]
+        );
+
+        let report = migrate_dynamo(&client, &tables()).await?;
+
+        assert_eq!(
+            report,
+            vec![
+                "ducks: created, keyed by message_id",
+                "state: created, keyed by pk",
+                "state: enabled TTL on expires_at",
+                "state-log: created, keyed by pk and sk",
+                "state-log: enabled TTL on expires_at",
+                "state: schema version 0 -> 2",
+            ]
+        );
+        assert_eq!(create.num_calls(), 2);
+        assert_eq!(create_log.num_calls(), 1);
+        assert_eq!(put_version.num_calls(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn migrate_refuses_tables_keyed_differently_or_from_a_newer_build() -> Result<()> {
+        let wrong_key: Rule = mock!(Client::describe_table).then_output(|| {
+            DescribeTableOutput::builder()
+                .table(
+                    TableDescription::builder()
+                        .key_schema(
+                            KeySchemaElement::builder()
+                                .attribute_name("id")
+                                .key_type(KeyType::Hash)
+                                .build()
+                                .unwrap(),
+                        )
+                        .build(),
+                )
+                .build()
+        });
+        let client = mock_client!(aws_sdk_dynamodb, [&wrong_key]);
+
+        assert!(migrate_dynamo(&client, &tables()[..1]).await.is_err());
+
+        let exists: Rule = mock!(Client::describe_table).then_output(|| {
+            DescribeTableOutput::builder()
+                .table(
+                    TableDescription::builder()
+                        .key_schema(
+                            KeySchemaElement::builder()
+                                .attribute_name("pk")
+                                .key_type(KeyType::Hash)
+                                .build()
+                                .unwrap(),
+                        )
+                        .build(),
+                )
+                .build()
+        });
+        let ttl_on: Rule = mock!(Client::describe_time_to_live).then_output(|| {
+            DescribeTimeToLiveOutput::builder()
+                .time_to_live_description(
+                    TimeToLiveDescription::builder()
+                        .time_to_live_status(TimeToLiveStatus::Enabled)
+                        .attribute_name("expires_at")
+                        .build(),
+                )
+                .build()
+        });
+        let newer: Rule = mock!(Client::get_item).then_output(|| {
+            GetItemOutput::builder()
+                .item("version", AttributeValue::N("3".into()))
+                .build()
+        });
+        let client = mock_client!(
+            aws_sdk_dynamodb,
+            RuleMode::MatchAny,
+            [&exists, &ttl_on, &newer]
+        );
+
+        let err = migrate_dynamo(&client, &tables()[1..2]).await.unwrap_err();
+
+        assert!(err.to_string().contains("newer than this build's 2"));
+        Ok(())
+    }
+}
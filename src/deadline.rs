@@ -0,0 +1,513 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow};
+use axum::{
+    Extension,
+    extract::State,
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode},
+};
+use chrono::{DateTime, Utc};
+use lambda_http::{Body, Context, Response};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppState, audit, auth,
+    handler::event_handler::{EventHandler, HandleOutcome},
+    types::twitch::{EventsubHeader, MessageType},
+};
+
+/// Time kept back from the invocation's deadline to queue what's left and answer Twitch.
+const MARGIN: Duration = Duration::from_millis(1000);
+
+/// Appended to the message id of a replayed notification, so the first attempt having been
+/// marked as seen doesn't drop it.
+const REPLAY_SUFFIX: &str = ":replay";
+
+/// How often the standalone server replays the outbox.
+const REPLAY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Replays of an entry before it's given up on.
+const MAX_REPLAY_ATTEMPTS: u32 = 5;
+
+/// How long an event can take before the Lambda invocation's deadline, less [`MARGIN`].
+/// `None` outside Lambda, where nothing cuts a request off.
+pub fn time_left(context: Option<&Context>, now: DateTime<Utc>) -> Option<Duration> {
+    let deadline = context.map(|c| c.deadline).filter(|d| *d > 0)?;
+    let left = (deadline as i64 - now.timestamp_millis()).max(0) as u64;
+    Some(Duration::from_millis(left).saturating_sub(MARGIN))
+}
+
+/// Work that ran out of time, kept in the outbox until it's replayed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum OutboxEntry {
+    /// A notification that was verified, but not handled before the deadline.
+    Notification {
+        message_id: String,
+        subscription_type: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timestamp: Option<String>,
+        body: String,
+        queued_at: DateTime<Utc>,
+        /// Replays that failed so far.
+        #[serde(default, skip_serializing_if = "is_zero")]
+        attempts: u32,
+    },
+    /// The stream went live, so the redemptions queued while it was offline are to be posted.
+    OfflineQueue {
+        queued_at: DateTime<Utc>,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        attempts: u32,
+    },
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+impl OutboxEntry {
+    pub fn notification(body: &[u8], headers: &HeaderMap, now: DateTime<Utc>) -> Result<Self> {
+        let header = |name: EventsubHeader| {
+            headers
+                .get(name.as_ref())
+                .and_then(|h| h.to_str().ok())
+                .map(String::from)
+        };
+        Ok(OutboxEntry::Notification {
+            message_id: header(EventsubHeader::MessageId)
+                .ok_or(anyhow!("Notification has no message id"))?,
+            subscription_type: header(EventsubHeader::SubscriptionType)
+                .ok_or(anyhow!("Notification has no subscription type"))?,
+            timestamp: header(EventsubHeader::MessageTimestamp),
+            body: String::from_utf8(body.to_vec())?,
+            queued_at: now,
+            attempts: 0,
+        })
+    }
+
+    /// What the entry is reported as, a notification by its message id.
+    fn id(&self) -> &str {
+        match self {
+            OutboxEntry::Notification { message_id, .. } => message_id,
+            OutboxEntry::OfflineQueue { .. } => "offline_queue",
+        }
+    }
+
+    fn attempts_mut(&mut self) -> &mut u32 {
+        match self {
+            OutboxEntry::Notification { attempts, .. }
+            | OutboxEntry::OfflineQueue { attempts, .. } => attempts,
+        }
+    }
+}
+
+/// What replaying the outbox did with each entry.
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct ReplayReport {
+    pub replayed: Vec<String>,
+    /// Notifications that had already reached chat before they ran out of time.
+    pub already_posted: Vec<String>,
+    /// Entries that failed again and were queued for the next replay.
+    pub failed: Vec<String>,
+    /// Entries dropped after failing [`MAX_REPLAY_ATTEMPTS`] times.
+    pub given_up: Vec<String>,
+    /// Entries left in the outbox for the next replay, as there was no time to get to them.
+    pub deferred: usize,
+}
+
+impl AppState {
+    /// Queues a notification that ran out of time to the outbox, answering 204 so Twitch
+    /// doesn't send it again as well. Anything else, or a notification that couldn't be
+    /// queued, is answered with 500 for Twitch to retry.
+    pub(crate) async fn queue_unfinished(
+        &self,
+        event_handler: &EventHandler,
+        body: &[u8],
+        headers: &HeaderMap,
+        after: Duration,
+    ) -> (Response<Body>, HandleOutcome) {
+        let notification = headers
+            .get(EventsubHeader::MessageType.as_ref())
+            .is_some_and(|t| t == MessageType::Notification.as_ref());
+        // Verifying again could mean a Secrets Manager lookup, which there's no time left for.
+        let queued = match notification && event_handler.was_verified() {
+            true => match OutboxEntry::notification(body, headers, Utc::now()) {
+                Ok(entry) => {
+                    self.state
+                        .push_outbox(&serde_json::to_string(&entry).unwrap())
+                        .await
+                }
+                Err(e) => Err(e),
+            },
+            false => Err(anyhow!("Only verified notifications are queued")),
+        };
+
+        let ms = after.as_millis();
+        let request = event_handler
+            .request_id()
+            .map(|id| format!(" in Lambda request {id}"))
+            .unwrap_or_default();
+        let status = match queued {
+            Ok(()) => {
+                println!(
+                    "Ran out of time after {ms}ms{request}, queued the notification to the outbox"
+                );
+                StatusCode::NO_CONTENT
+            }
+            Err(e) => {
+                println!(
+                    "Ran out of time after {ms}ms{request}, leaving it to Twitch to retry: {e}"
+                );
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        (
+            Response::builder()
+                .status(status)
+                .body(Body::Empty)
+                .unwrap(),
+            HandleOutcome::TimedOut {
+                queued: status == StatusCode::NO_CONTENT,
+            },
+        )
+    }
+
+    /// Handles the outbox's entries, except notifications that got as far as posting before
+    /// they ran out of time, stopping once `time_left` has run out. Each entry leaves the outbox
+    /// once it's been dealt with, and one that fails again is queued for the next replay.
+    /// Entries that can't be read are dropped.
+    pub async fn replay_outbox(&self, time_left: Option<Duration>) -> Result<ReplayReport> {
+        let mut report = ReplayReport::default();
+        let deadline = time_left.map(|left| Instant::now() + left);
+        let now = Utc::now();
+        let outbox = self.state.outbox().await?;
+        let total = outbox.len();
+        for (i, queued) in outbox.into_iter().enumerate() {
+            let left = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+            if left.is_some_and(|left| left.is_zero()) {
+                report.deferred = total - i;
+                break;
+            }
+
+            let entry = match serde_json::from_str::<OutboxEntry>(&queued.entry) {
+                Ok(e) => e,
+                Err(e) => {
+                    println!("Dropping unreadable outbox entry: {e}");
+                    self.state.remove_outbox(&queued.key).await?;
+                    continue;
+                }
+            };
+            let replayed = self.replay_entry(&entry, now);
+            let replayed = match left {
+                Some(left) => match tokio::time::timeout(left, replayed).await {
+                    Ok(replayed) => replayed,
+                    // Left in the outbox, a replay that got as far as posting is recognized by
+                    // its audit entry next time.
+                    Err(_) => {
+                        report.deferred = total - i;
+                        break;
+                    }
+                },
+                None => replayed.await,
+            };
+
+            let id = entry.id().to_string();
+            match replayed {
+                Ok(true) => report.replayed.push(id),
+                Ok(false) => report.already_posted.push(id),
+                Err(e) => {
+                    let mut retry = entry.clone();
+                    *retry.attempts_mut() += 1;
+                    if *retry.attempts_mut() < MAX_REPLAY_ATTEMPTS {
+                        println!("Failed to replay {id}, queueing it again: {e}");
+                        self.state
+                            .push_outbox(&serde_json::to_string(&retry)?)
+                            .await?;
+                        report.failed.push(id);
+                    } else {
+                        println!("Giving up on {id} after {MAX_REPLAY_ATTEMPTS} replays: {e}");
+                        report.given_up.push(id);
+                    }
+                }
+            }
+            self.state.remove_outbox(&queued.key).await?;
+        }
+        Ok(report)
+    }
+
+    /// Replays the outbox every minute, for the long-running standalone server. Instances
+    /// sharing a state store take turns.
+    pub fn replay_outbox_on_schedule(&self) {
+        let app = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(REPLAY_INTERVAL).await;
+                match app.state.start_cooldown("outbox_replay", 50).await {
+                    Ok(true) => {}
+                    Ok(false) => continue,
+                    Err(e) => println!("Failed to claim the outbox replay, replaying anyway: {e}"),
+                }
+                if let Err(e) = app.replay_outbox(None).await {
+                    println!("Failed to replay the outbox: {e}");
+                }
+            }
+        });
+    }
+
+    /// Handles an entry from the outbox, returning false when a notification's message had
+    /// already been posted.
+    async fn replay_entry(&self, entry: &OutboxEntry, now: DateTime<Utc>) -> Result<bool> {
+        let (message_id, subscription_type, timestamp, body, queued_at) = match entry {
+            OutboxEntry::Notification {
+                message_id,
+                subscription_type,
+                timestamp,
+                body,
+                queued_at,
+                ..
+            } => (message_id, subscription_type, timestamp, body, queued_at),
+            OutboxEntry::OfflineQueue { .. } => {
+                return self
+                    .mod_feed()
+                    .flush_offline_queue(&self.config)
+                    .await
+                    .map(|_| true);
+            }
+        };
+
+        let since = *queued_at - chrono::Duration::minutes(15);
+        let posted = audit::entries_since(self.state.as_ref(), since, now)
+            .await
+            .map(|entries| {
+                entries
+                    .iter()
+                    .any(|e| e.event_id.as_deref() == Some(message_id))
+            })
+            .unwrap_or(false);
+        if posted {
+            println!("Not replaying {message_id}, it was posted before running out of time");
+            return Ok(false);
+        }
+
+        let mut headers = HeaderMap::new();
+        for (name, value) in [
+            (
+                EventsubHeader::MessageId,
+                Some(format!("{message_id}{REPLAY_SUFFIX}")),
+            ),
+            (
+                EventsubHeader::SubscriptionType,
+                Some(subscription_type.clone()),
+            ),
+            (EventsubHeader::MessageTimestamp, timestamp.clone()),
+        ] {
+            if let Some(value) = value.and_then(|v| HeaderValue::from_str(&v).ok()) {
+                headers.insert(HeaderName::try_from(name.as_ref())?, value);
+            }
+        }
+        self.event_handler()
+            .replay(body, &headers, &self.config)
+            .await
+            .map(|_| true)
+    }
+}
+
+/// `POST /internal/replay-outbox` - handles notifications that ran out of Lambda time, for a
+/// schedule (e.g. an EventBridge rule every minute) to pick up.
+#[utoipa::path(post, path = "/internal/replay-outbox", tag = "internal",
+    security(("internal_token" = [])),
+    responses(
+        (status = 200, description = "What became of each queued notification", body = Object),
+        (status = 401, description = "Missing or wrong `INTERNAL_API_TOKEN`"),
+        (status = 500, description = "The outbox couldn't be read"),
+    ),
+)]
+pub async fn replay_outbox_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    context: Option<Extension<Context>>,
+) -> Response<Body> {
+    if !auth::internal_request_authorized(&headers, &state.config) {
+        return Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .body(Body::Empty)
+            .unwrap();
+    }
+
+    let left = time_left(context.as_ref().map(|Extension(c)| c), Utc::now());
+    match state.replay_outbox(left).await {
+        Ok(report) => Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(Body::from(serde_json::to_string(&report).unwrap()))
+            .unwrap(),
+        Err(e) => {
+            println!("Failed to replay the outbox: {e}");
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::Empty)
+                .unwrap()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use axum::http::{HeaderMap, StatusCode};
+    use chrono::{TimeZone, Utc};
+    use lambda_http::Context;
+    use pretty_assertions::assert_eq;
+    use std::sync::Arc;
+
+    use crate::{
+        app::App,
+        client::WebClient,
+        config::AppConfig,
+        deadline::{MAX_REPLAY_ATTEMPTS, OutboxEntry, time_left},
+        handler::event_handler::HandleOutcome,
+        probe,
+        state::{MemoryStore, StateStore},
+        types::twitch::EventsubHeader,
+    };
+
+    #[test]
+    fn time_left_keeps_a_margin_before_the_deadline() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 17, 18, 0, 0).unwrap();
+        let context = |deadline: i64| {
+            let mut context = Context::default();
+            context.deadline = deadline as u64;
+            context
+        };
+
+        assert_eq!(time_left(None, now), None);
+        assert_eq!(time_left(Some(&Context::default()), now), None);
+        assert_eq!(
+            time_left(Some(&context(now.timestamp_millis() + 3000)), now),
+            Some(Duration::from_millis(2000))
+        );
+        assert_eq!(
+            time_left(Some(&context(now.timestamp_millis() + 200)), now),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn outbox_entries_keep_what_is_needed_to_replay() -> Result<()> {
+        let now = Utc.with_ymd_and_hms(2026, 10, 17, 18, 0, 0).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(EventsubHeader::MessageId.as_ref(), "message-1".parse()?);
+        headers.insert(
+            EventsubHeader::SubscriptionType.as_ref(),
+            "channel.cheer".parse()?,
+        );
+
+        let entry = OutboxEntry::notification(b"{}", &headers, now)?;
+        let json = serde_json::to_value(&entry)?;
+
+        assert_eq!(json["kind"], "notification");
+        assert_eq!(json["message_id"], "message-1");
+        assert_eq!(serde_json::from_value::<OutboxEntry>(json)?, entry);
+        assert!(OutboxEntry::notification(b"{}", &HeaderMap::new(), now).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn only_verified_notifications_are_queued_when_out_of_time() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let store = Arc::new(MemoryStore::default());
+        let app = App::builder(AppConfig::from_env())
+            .with_aws(
+                aws_config::SdkConfig::builder()
+                    .behavior_version(aws_config::BehaviorVersion::latest())
+                    .build(),
+            )
+            .with_state(store.clone())
+            .with_web_client(WebClient::new(reqwest::Client::new()))
+            .build()
+            .await;
+        let state = app.state();
+        let event_handler = state.event_handler();
+        let (headers, body) = probe::notification(app.config(), Utc::now())?;
+        event_handler
+            .handle(body.as_bytes(), &headers, app.config())
+            .await?;
+
+        let (response, outcome) = state
+            .queue_unfinished(
+                &event_handler,
+                body.as_bytes(),
+                &headers,
+                Duration::from_secs(2),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(outcome, HandleOutcome::TimedOut { queued: true });
+
+        let (response, outcome) = state
+            .queue_unfinished(
+                &state.event_handler(),
+                body.as_bytes(),
+                &headers,
+                Duration::from_secs(2),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(outcome, HandleOutcome::TimedOut { queued: false });
+
+        let outbox = store.outbox().await?;
+        assert_eq!(outbox.len(), 1);
+        let Ok(OutboxEntry::Notification { body: queued, .. }) =
+            serde_json::from_str(&outbox[0].entry)
+        else {
+            panic!("Expected a queued notification");
+        };
+        assert_eq!(queued, body);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replays_that_fail_are_queued_again_until_given_up() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let store = Arc::new(MemoryStore::default());
+        let app = App::builder(AppConfig::from_env())
+            .with_aws(
+                aws_config::SdkConfig::builder()
+                    .behavior_version(aws_config::BehaviorVersion::latest())
+                    .build(),
+            )
+            .with_state(store.clone())
+            .with_web_client(WebClient::new(reqwest::Client::new()))
+            .build()
+            .await;
+        let state = app.state();
+        let mut headers = HeaderMap::new();
+        headers.insert(EventsubHeader::MessageId.as_ref(), "message-1".parse()?);
+        headers.insert(
+            EventsubHeader::SubscriptionType.as_ref(),
+            "channel.cheer".parse()?,
+        );
+        let entry = OutboxEntry::notification(b"not json", &headers, Utc::now())?;
+        store.push_outbox(&serde_json::to_string(&entry)?).await?;
+
+        let report = state.replay_outbox(Some(Duration::ZERO)).await?;
+        assert_eq!(report.deferred, 1);
+        assert_eq!(store.outbox().await?.len(), 1);
+
+        for attempt in 1..MAX_REPLAY_ATTEMPTS {
+            let report = state.replay_outbox(None).await?;
+            assert_eq!(report.failed, vec!["message-1"]);
+            let outbox = store.outbox().await?;
+            assert_eq!(outbox.len(), 1);
+            let mut queued: OutboxEntry = serde_json::from_str(&outbox[0].entry)?;
+            assert_eq!(*queued.attempts_mut(), attempt);
+        }
+
+        let report = state.replay_outbox(None).await?;
+        assert_eq!(report.given_up, vec!["message-1"]);
+        assert!(store.outbox().await?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+use strum::AsRefStr;
+
+use crate::robochick::twitch::{TemplateContext, USER_LOGIN, USER_NAME};
+
+/// Group that messages for declined redemptions and commands are picked from. Without it,
+/// they're declined silently.
+pub const DECLINES_GROUP: &str = "declines";
+
+/// Placeholder filled with why the bot didn't act, e.g. `budget`.
+pub const REASON: &str = "reason";
+
+/// Shortest time between two decline messages to the same viewer, so spamming a paused reward
+/// doesn't spam chat back.
+pub const DECLINE_COOLDOWN_SECS: u64 = 60;
+
+/// Why a redemption or command was declined, as `{reason}`.
+#[derive(AsRefStr, Debug, Clone, Copy, PartialEq)]
+#[strum(serialize_all = "snake_case")]
+pub enum DeclineReason {
+    /// The reward's budget is used up.
+    Budget,
+    /// The redemption was part of a burst that's already been answered.
+    Burst,
+    /// The chat command is cooling down.
+    Cooldown,
+    /// The chatter's role can't use the chat command.
+    NotAllowed,
+}
+
+/// The placeholders a decline message can use.
+pub fn context(reason: DeclineReason, user_name: &str, user_login: &str) -> TemplateContext {
+    let mut context = TemplateContext::default();
+    context.insert(USER_NAME, user_name);
+    context.insert(USER_LOGIN, user_login);
+    context.insert(REASON, reason.as_ref());
+    context
+}
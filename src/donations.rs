@@ -0,0 +1,110 @@
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+
+/// Hook name Ko-fi's webhook is pointed at, i.e. `/hooks/kofi`.
+pub const KOFI_HOOK: &str = "kofi";
+
+/// Shown instead of the donor's name when they asked for the donation to be private.
+const ANONYMOUS_DONOR: &str = "Someone";
+
+/// A donation, as it's announced in chat.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Donation {
+    pub donor: String,
+    /// Amount with its currency, e.g. `5.00 GBP`.
+    pub amount: String,
+}
+
+#[derive(Deserialize)]
+struct KofiForm {
+    data: String,
+}
+
+/// What Ko-fi sends, as JSON in the `data` field of a form-encoded body.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct KofiPayload {
+    pub verification_token: String,
+    /// `Donation`, `Subscription`, `Commission` or `Shop Order`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub from_name: String,
+    pub amount: String,
+    pub currency: String,
+    #[serde(default = "default_true")]
+    pub is_public: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl KofiPayload {
+    pub fn parse(body: &str) -> Result<KofiPayload> {
+        let form: KofiForm = serde_urlencoded::from_str(body)
+            .map_err(|e| anyhow!("Invalid Ko-fi form body: {e}"))?;
+        serde_json::from_str(&form.data).map_err(|e| anyhow!("Invalid Ko-fi payload: {e}"))
+    }
+
+    /// The donation to announce. Subscriptions count too, shop orders and commissions don't.
+    pub fn donation(&self) -> Option<Donation> {
+        if self.kind != "Donation" && self.kind != "Subscription" {
+            return None;
+        }
+
+        Some(Donation {
+            donor: match self.is_public {
+                true => self.from_name.clone(),
+                false => ANONYMOUS_DONOR.to_string(),
+            },
+            amount: format!("{} {}", self.amount, self.currency),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use pretty_assertions::assert_eq;
+
+    use crate::donations::{Donation, KofiPayload};
+
+    fn form(data: &str) -> String {
+        serde_urlencoded::to_string([("data", data)]).unwrap()
+    }
+
+    #[test]
+    fn kofi_donation_is_parsed_from_form_body() -> Result<()> {
+        let body = form(
+            r#"{"verification_token":"token","type":"Donation","from_name":"Clucky","amount":"5.00","currency":"GBP","is_public":true,"message":"bok"}"#,
+        );
+
+        let payload = KofiPayload::parse(&body)?;
+
+        assert_eq!(payload.verification_token, "token");
+        assert_eq!(
+            payload.donation(),
+            Some(Donation {
+                donor: "Clucky".into(),
+                amount: "5.00 GBP".into()
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn kofi_private_donations_hide_donor_and_shop_orders_are_skipped() -> Result<()> {
+        let private = KofiPayload::parse(&form(
+            r#"{"verification_token":"token","type":"Donation","from_name":"Clucky","amount":"3.00","currency":"USD","is_public":false}"#,
+        ))?;
+        let order = KofiPayload::parse(&form(
+            r#"{"verification_token":"token","type":"Shop Order","from_name":"Clucky","amount":"20.00","currency":"USD"}"#,
+        ))?;
+
+        assert_eq!(
+            private.donation().map(|d| d.donor),
+            Some("Someone".to_string())
+        );
+        assert_eq!(order.donation(), None);
+        Ok(())
+    }
+}
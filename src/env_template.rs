@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::config::{AppConfig, EnvSource};
+
+/// An env var [`AppConfig::from_env`](crate::config::AppConfig::from_env) reads.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct EnvVar {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// The bot won't start without it.
+    pub required: bool,
+    /// Used when the var is unset.
+    pub default: Option<&'static str>,
+    /// Holds a credential, so infrastructure code should source it from a secret store.
+    pub secret: bool,
+    /// What needs it.
+    pub feature: &'static str,
+}
+
+pub(crate) const fn required(
+    name: &'static str,
+    feature: &'static str,
+    description: &'static str,
+) -> EnvVar {
+    EnvVar {
+        name,
+        description,
+        required: true,
+        default: None,
+        secret: false,
+        feature,
+    }
+}
+
+pub(crate) const fn optional(
+    name: &'static str,
+    feature: &'static str,
+    description: &'static str,
+) -> EnvVar {
+    EnvVar {
+        name,
+        description,
+        required: false,
+        default: None,
+        secret: false,
+        feature,
+    }
+}
+
+impl EnvVar {
+    /// Marks the var as holding a credential.
+    pub(crate) const fn secret(self) -> EnvVar {
+        EnvVar {
+            secret: true,
+            ..self
+        }
+    }
+
+    pub(crate) const fn with_default(self, default: &'static str) -> EnvVar {
+        EnvVar {
+            default: Some(default),
+            ..self
+        }
+    }
+}
+
+/// Records the vars the config is read from, in the order it reads them.
+#[derive(Default)]
+struct Recorder(Vec<EnvVar>);
+
+impl EnvSource for Recorder {
+    /// Required vars read as empty, so reading the config doesn't stop at the first one.
+    fn get(&mut self, var: &EnvVar) -> Option<String> {
+        self.0.push(var.clone());
+        var.required.then(String::new)
+    }
+
+    fn prefixed(&mut self, var: EnvVar) -> HashMap<String, String> {
+        self.0.push(var);
+        HashMap::new()
+    }
+}
+
+/// Every env var of the config, in the order [`AppConfig::read`] reads them.
+pub fn env_vars() -> Vec<EnvVar> {
+    let mut recorder = Recorder::default();
+    AppConfig::read(&mut recorder);
+    recorder.0
+}
+
+/// The env vars as a commented `.env` file, required ones first.
+pub fn dotenv() -> String {
+    let mut vars = env_vars();
+    vars.sort_by_key(|v| !v.required);
+
+    let mut out = String::new();
+    for var in vars {
+        let mut notes = vec![match var.required {
+            true => "Required".to_string(),
+            false => "Optional".to_string(),
+        }];
+        if var.secret {
+            notes.push("secret".into());
+        }
+        notes.push(format!("needed for {}", var.feature));
+        out.push_str(&format!("# {}\n# {}.\n", var.description, notes.join(", ")));
+        match (var.required, var.default) {
+            (true, _) => out.push_str(&format!("{}=\n", var.name)),
+            (false, Some(default)) => out.push_str(&format!("# {}=\"{default}\"\n", var.name)),
+            (false, None) => out.push_str(&format!("# {}=\n", var.name)),
+        }
+    }
+    out
+}
+
+/// The env vars as JSON, for infrastructure code to read.
+pub fn json() -> Result<String> {
+    Ok(serde_json::to_string_pretty(&env_vars())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use anyhow::Result;
+    use pretty_assertions::assert_eq;
+
+    use crate::env_template::{dotenv, env_vars};
+
+    #[test]
+    fn env_vars_are_the_ones_the_config_reads() {
+        let vars = env_vars();
+        let names: HashSet<&str> = vars.iter().map(|v| v.name).collect();
+
+        assert_eq!(names.len(), vars.len());
+        assert_eq!(vars[0].name, "TWITCH_CLIENT_ID");
+        assert!(names.contains("HOOK_SECRET_<NAME>"));
+        assert!(names.contains("USER_CACHE_TTL_SECS"));
+    }
+
+    #[test]
+    fn dotenv_leaves_required_vars_uncommented() -> Result<()> {
+        let template = dotenv();
+
+        assert!(template.contains("\nTWITCH_EVENTSUB_SUBSCRIPTION_SECRET=\n"));
+        assert!(template.contains("\n# CHATTERS_CACHE_TTL_SECS=\"60\"\n"));
+        assert!(template.contains("# Secret EventSub notifications are signed with.\n# Required, secret, needed for core.\n"));
+        Ok(())
+    }
+}
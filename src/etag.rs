@@ -0,0 +1,193 @@
+use anyhow::Result;
+use axum::http::HeaderMap;
+use lambda_http::{Body, Response};
+use reqwest::{
+    StatusCode,
+    header::{CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+};
+use sha2::{Digest, Sha256};
+
+use crate::{reload::ConfigFile, state::StateStore, version};
+
+/// Strong validator of `body`, quoted the way the `ETag` header wants it.
+pub fn etag(body: &str) -> String {
+    format!("\"{}\"", hex::encode(&Sha256::digest(body)[..16]))
+}
+
+/// Whether the request's `If-None-Match` lists `etag` or is `*`, meaning the client's copy is
+/// still current. Compared weakly, so a `W/` prefix added by a proxy still matches.
+pub fn not_modified(headers: &HeaderMap, etag: &str) -> bool {
+    let Some(value) = headers.get(IF_NONE_MATCH).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    value
+        .split(',')
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+/// Data the public views are built from. Every write to one bumps its version counter, so a
+/// view's ETag can be worked out from the versions before any of the data itself is read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dataset {
+    Scenarios,
+    Wins,
+    Experiments,
+    RecentWinners,
+}
+
+impl Dataset {
+    fn counter(self) -> &'static str {
+        match self {
+            Dataset::Scenarios => "version#scenarios",
+            Dataset::Wins => "version#wins",
+            Dataset::Experiments => "version#experiments",
+            Dataset::RecentWinners => "version#recent_winners",
+        }
+    }
+}
+
+/// Marks `dataset` as changed, so the views built from it stop answering `304`. Failures are
+/// only logged, as the write itself already went through.
+pub async fn touch(state: &dyn StateStore, dataset: Dataset) {
+    if let Err(e) = state.increment_counter(dataset.counter(), None).await {
+        println!("Failed to bump the version of {dataset:?}: {e}");
+    }
+}
+
+/// ETag of a view built from `datasets` and the message config, from their versions alone.
+/// `inputs` are whatever else the view depends on, like its query or the current week. The
+/// versions are bumped after their writes, so a write racing the read shows up in the body
+/// early rather than being hidden behind a `304`.
+pub async fn version_etag(
+    state: &dyn StateStore,
+    file: &ConfigFile,
+    datasets: &[Dataset],
+    inputs: &[&str],
+) -> Result<String> {
+    let versions = async {
+        let mut versions = Vec::with_capacity(datasets.len());
+        for dataset in datasets {
+            versions.push(state.counter(dataset.counter()).await?);
+        }
+        Ok(versions)
+    };
+    let (stored, versions): (_, Vec<u64>) = tokio::try_join!(state.get_config(), versions)?;
+    let config = match stored {
+        Some(stored) => format!("saved {}", stored.version),
+        None => match file.get() {
+            Ok(components) => format!("file {}", etag(&serde_json::to_string(&*components)?)),
+            Err(_) => "missing".to_string(),
+        },
+    };
+
+    Ok(etag(&format!(
+        "{} {config} {versions:?} {inputs:?}",
+        version::build_info().built_at
+    )))
+}
+
+/// An empty 304 when the request's `If-None-Match` still lists `etag`, checked before the view
+/// is built.
+pub fn revalidate(headers: &HeaderMap, etag: &str, cache_control: &str) -> Option<Response<Body>> {
+    not_modified(headers, etag).then(|| {
+        Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header(ETAG, etag)
+            .header(CACHE_CONTROL, cache_control)
+            .body(Body::Empty)
+            .unwrap()
+    })
+}
+
+/// `body` tagged `etag`. Both this and [`revalidate`]'s 304 carry `cache_control`, so browsers
+/// polling a page revalidate instead of downloading it again.
+pub fn tagged_response(
+    etag: &str,
+    content_type: &str,
+    cache_control: &str,
+    body: String,
+) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(ETAG, etag)
+        .header(CACHE_CONTROL, cache_control)
+        .header(CONTENT_TYPE, content_type)
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use axum::http::HeaderMap;
+    use lambda_http::Body;
+    use pretty_assertions::assert_eq;
+    use reqwest::{
+        StatusCode,
+        header::{ETAG, IF_NONE_MATCH},
+    };
+
+    use crate::{
+        etag::{Dataset, etag, not_modified, revalidate, tagged_response, touch, version_etag},
+        reload::ConfigFile,
+        state::{MemoryStore, StateStore},
+    };
+
+    fn if_none_match(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(IF_NONE_MATCH, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn not_modified_matches_any_listed_tag() {
+        let tag = etag("[]");
+
+        assert!(not_modified(&if_none_match(&tag), &tag));
+        assert!(not_modified(
+            &if_none_match(&format!("\"old\", W/{tag}")),
+            &tag
+        ));
+        assert!(not_modified(&if_none_match("*"), &tag));
+        assert!(!not_modified(&if_none_match("\"old\""), &tag));
+        assert!(!not_modified(&HeaderMap::new(), &tag));
+    }
+
+    #[test]
+    fn revalidate_skips_the_body_for_a_current_copy() {
+        let tag = etag("[]");
+        let fresh = tagged_response(&tag, "application/json", "public, max-age=60", "[]".into());
+
+        let revalidated = revalidate(&if_none_match(&tag), &tag, "public, max-age=60").unwrap();
+
+        assert_eq!(fresh.status(), StatusCode::OK);
+        assert_eq!(revalidated.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(revalidated.headers()[ETAG], tag.as_str());
+        assert_eq!(revalidated.body(), &Body::Empty);
+        assert!(revalidate(&if_none_match("\"old\""), &tag, "public, max-age=60").is_none());
+    }
+
+    #[tokio::test]
+    async fn version_etag_changes_with_the_datasets_it_covers() -> Result<()> {
+        let store = MemoryStore::default();
+        let file = ConfigFile::new("resources/config/message_components.json");
+        let tag = || version_etag(&store, &file, &[Dataset::Scenarios], &["2026-W42"]);
+
+        let first = tag().await?;
+        touch(&store, Dataset::Wins).await;
+        assert_eq!(tag().await?, first);
+
+        touch(&store, Dataset::Scenarios).await;
+        let touched = tag().await?;
+        assert_ne!(touched, first);
+
+        store.put_config("{}", 0).await?;
+        assert_ne!(tag().await?, touched);
+        assert_ne!(
+            version_etag(&store, &file, &[Dataset::Scenarios], &["2026-W43"]).await?,
+            tag().await?
+        );
+        Ok(())
+    }
+}
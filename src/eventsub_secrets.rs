@@ -0,0 +1,198 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::{cache::TtlCache, secrets::SecretStore};
+
+/// Length of generated secrets. Twitch takes 10 to 100 characters.
+const SECRET_LENGTH: i64 = 64;
+
+/// How long a subscription without its own secret isn't looked up again.
+const MISSING_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// Lookups made for a challenge, which can arrive before the new secret is saved.
+const CHALLENGE_ATTEMPTS: u32 = 3;
+const CHALLENGE_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Most Secrets Manager reads of uncached secrets per [`LOOKUP_WINDOW`]. The subscription id
+/// comes from the unverified payload, so this caps what made-up ids can cost.
+const MAX_LOOKUPS: u32 = 20;
+const LOOKUP_WINDOW: Duration = Duration::from_secs(60);
+
+/// EventSub secrets of individual subscriptions, one secret per subscription named
+/// `{prefix}{subscription id}`, so a leaked secret only lets someone forge the events of one
+/// subscription. Subscriptions made before this was set up keep the shared
+/// `TWITCH_EVENTSUB_SUBSCRIPTION_SECRET` until they're recreated.
+pub struct EventsubSecretStore {
+    secrets: Arc<SecretStore>,
+    prefix: String,
+    missing: TtlCache<String, ()>,
+    /// Start of the current lookup window and the lookups made in it.
+    lookups: Mutex<(Instant, u32)>,
+}
+
+#[derive(Deserialize)]
+struct Payload {
+    subscription: PayloadSubscription,
+}
+
+#[derive(Deserialize)]
+struct PayloadSubscription {
+    id: String,
+}
+
+impl EventsubSecretStore {
+    pub fn new(secrets: Arc<SecretStore>, prefix: String) -> Self {
+        EventsubSecretStore {
+            secrets,
+            prefix,
+            missing: TtlCache::new(MISSING_CACHE_TTL),
+            lookups: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    fn name(&self, subscription_id: &str) -> String {
+        format!("{}{subscription_id}", self.prefix)
+    }
+
+    /// A fresh secret for a subscription about to be created.
+    pub async fn generate(&self) -> Result<String> {
+        self.secrets.random(SECRET_LENGTH).await
+    }
+
+    pub async fn save(&self, subscription_id: &str, secret: &str) -> Result<()> {
+        self.secrets.put(&self.name(subscription_id), secret).await
+    }
+
+    pub async fn delete(&self, subscription_id: &str) -> Result<()> {
+        self.secrets.delete(&self.name(subscription_id)).await
+    }
+
+    /// Counts a lookup against the window's budget, returning false once it's used up.
+    fn take_lookup(&self) -> bool {
+        let Ok(mut lookups) = self.lookups.lock() else {
+            return false;
+        };
+        if lookups.0.elapsed() >= LOOKUP_WINDOW {
+            *lookups = (Instant::now(), 0);
+        }
+        lookups.1 += 1;
+        lookups.1 <= MAX_LOOKUPS
+    }
+
+    /// The secret of subscription `subscription_id`, or `None` when it has none of its own or
+    /// too many lookups were made lately. With `challenge`, a missing secret is looked up a few
+    /// times before giving up, since Twitch may send the challenge before
+    /// [`save`](Self::save) is done.
+    pub async fn get(&self, subscription_id: &str, challenge: bool) -> Option<String> {
+        let key = subscription_id.to_string();
+        let name = self.name(subscription_id);
+        if let Some(secret) = self.secrets.cached(&name) {
+            return Some(secret);
+        }
+        if !challenge && self.missing.get(&key).is_some() {
+            return None;
+        }
+
+        let attempts = if challenge { CHALLENGE_ATTEMPTS } else { 1 };
+        for attempt in 1..=attempts {
+            if !self.take_lookup() {
+                println!("Too many EventSub secret lookups, not looking up {subscription_id}");
+                return None;
+            }
+            match self.secrets.get(&name).await {
+                Ok(secret) => return Some(secret),
+                Err(e) if attempt == attempts => {
+                    println!("No secret of its own for subscription {subscription_id}: {e}")
+                }
+                Err(_) => tokio::time::sleep(CHALLENGE_RETRY_DELAY).await,
+            }
+        }
+        self.missing.insert(key, ());
+        None
+    }
+}
+
+/// Id of the subscription an EventSub message is for, from its body.
+pub fn subscription_id(payload: &[u8]) -> Option<String> {
+    serde_json::from_slice::<Payload>(payload)
+        .ok()
+        .map(|p| p.subscription.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use aws_sdk_secretsmanager::{
+        Client,
+        operation::get_secret_value::{GetSecretValueError, GetSecretValueOutput},
+        types::error::ResourceNotFoundException,
+    };
+    use aws_smithy_mocks::{RuleMode, mock, mock_client};
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        eventsub_secrets::{EventsubSecretStore, MAX_LOOKUPS, subscription_id},
+        secrets::SecretStore,
+    };
+
+    #[test]
+    fn subscription_id_reads_the_payload() {
+        let payload = br#"{"subscription": {"id": "sub-1", "type": "stream.online"}, "event": {}}"#;
+
+        assert_eq!(subscription_id(payload), Some("sub-1".into()));
+        assert_eq!(subscription_id(b"{}"), None);
+    }
+
+    #[tokio::test]
+    async fn get_remembers_subscriptions_without_a_secret() {
+        let found = mock!(Client::get_secret_value)
+            .match_requests(|r| r.secret_id() == Some("eventsub/sub-1"))
+            .then_output(|| {
+                GetSecretValueOutput::builder()
+                    .secret_string("sub-1-secret")
+                    .build()
+            });
+        let missing = mock!(Client::get_secret_value)
+            .match_requests(|r| r.secret_id() == Some("eventsub/sub-2"))
+            .then_error(|| {
+                GetSecretValueError::ResourceNotFoundException(
+                    ResourceNotFoundException::builder().build(),
+                )
+            });
+        let client = mock_client!(
+            aws_sdk_secretsmanager,
+            RuleMode::MatchAny,
+            [&found, &missing]
+        );
+        let store =
+            EventsubSecretStore::new(Arc::new(SecretStore::new(client)), "eventsub/".into());
+
+        assert_eq!(store.get("sub-1", false).await, Some("sub-1-secret".into()));
+        assert_eq!(store.get("sub-2", false).await, None);
+        assert_eq!(store.get("sub-2", false).await, None);
+        assert_eq!(missing.num_calls(), 1);
+    }
+
+    #[tokio::test]
+    async fn get_stops_looking_up_once_the_budget_is_used() {
+        let missing = mock!(Client::get_secret_value).then_error(|| {
+            GetSecretValueError::ResourceNotFoundException(
+                ResourceNotFoundException::builder().build(),
+            )
+        });
+        let client = mock_client!(aws_sdk_secretsmanager, RuleMode::MatchAny, [&missing]);
+        let store =
+            EventsubSecretStore::new(Arc::new(SecretStore::new(client)), "eventsub/".into());
+
+        for i in 0..MAX_LOOKUPS + 5 {
+            assert_eq!(store.get(&format!("made-up-{i}"), false).await, None);
+        }
+        assert_eq!(missing.num_calls(), MAX_LOOKUPS as usize);
+    }
+}
@@ -0,0 +1,201 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
+
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    etag::{self, Dataset},
+    robochick::twitch::MessageComponents,
+    state::StateStore,
+};
+
+/// How long after a variant is posted the viewer it was for has their chat messages credited
+/// to it.
+pub const ENGAGEMENT_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+const SHOWN: &str = "shown";
+const CHAT_MESSAGES: &str = "chat_messages";
+
+/// Marks a scenario as one variant of an experiment. Viewers are split between the variants
+/// by user id, so a viewer keeps getting the same one.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub(crate) experiment: String,
+    pub(crate) variant: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct VariantStats {
+    pub shown: u64,
+    pub chat_messages: u64,
+    /// Chat messages per post, 0 when the variant wasn't posted.
+    pub chat_per_post: f64,
+}
+
+/// Which of `variants` `user_id` gets in `experiment`, the same every time.
+pub fn assign<'a>(
+    experiment: &str,
+    variants: &BTreeSet<&'a str>,
+    user_id: &str,
+) -> Option<&'a str> {
+    if variants.is_empty() {
+        return None;
+    }
+    let hash = Sha256::digest(format!("{experiment}:{user_id}"));
+    let bucket = u64::from_be_bytes(hash[..8].try_into().ok()?) % variants.len() as u64;
+    variants.iter().nth(bucket as usize).copied()
+}
+
+/// Counts a post of `variant`, crediting the chat messages of `viewer` to it for the
+/// [`ENGAGEMENT_WINDOW`]. Posts without a viewer, e.g. for hooks, are only counted.
+pub async fn record_shown(
+    state: &dyn StateStore,
+    variant: &Variant,
+    viewer: Option<&str>,
+) -> Result<()> {
+    state
+        .record_variant(&variant.experiment, &variant.variant, SHOWN)
+        .await?;
+    etag::touch(state, Dataset::Experiments).await;
+    if let Some(viewer) = viewer {
+        state
+            .set_engagement(
+                viewer,
+                &serde_json::to_string(variant)?,
+                ENGAGEMENT_WINDOW.as_secs(),
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// Credits a chat message from `chatter` to the variant posted for them within the
+/// [`ENGAGEMENT_WINDOW`]. Returns whether it was credited.
+pub async fn record_chat(state: &dyn StateStore, chatter: &str) -> Result<bool> {
+    let Some(entry) = state.engagement(chatter).await? else {
+        return Ok(false);
+    };
+    let variant: Variant = serde_json::from_str(&entry)?;
+    state
+        .record_variant(&variant.experiment, &variant.variant, CHAT_MESSAGES)
+        .await?;
+    etag::touch(state, Dataset::Experiments).await;
+    Ok(true)
+}
+
+/// Stats of every experiment in `components`, by experiment and variant.
+pub async fn report(
+    state: &dyn StateStore,
+    components: &MessageComponents,
+) -> Result<BTreeMap<String, BTreeMap<String, VariantStats>>> {
+    let mut experiments: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for variant in components
+        .all_scenarios()
+        .filter_map(|s| s.get_experiment())
+    {
+        experiments
+            .entry(&variant.experiment)
+            .or_default()
+            .insert(&variant.variant);
+    }
+
+    let mut report = BTreeMap::new();
+    for (experiment, variants) in experiments {
+        let counts = state.variant_counts(experiment).await?;
+        let count = |variant: &str, metric: &str| {
+            counts
+                .get(&format!("{variant}/{metric}"))
+                .copied()
+                .unwrap_or(0)
+        };
+        let stats = variants
+            .into_iter()
+            .map(|variant| {
+                let shown = count(variant, SHOWN);
+                let chat_messages = count(variant, CHAT_MESSAGES);
+                let chat_per_post = match shown {
+                    0 => 0.0,
+                    n => chat_messages as f64 / n as f64,
+                };
+                let stats = VariantStats {
+                    shown,
+                    chat_messages,
+                    chat_per_post,
+                };
+                (variant.to_string(), stats)
+            })
+            .collect();
+        report.insert(experiment.to_string(), stats);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use anyhow::Result;
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        experiment::{Variant, assign, record_chat, record_shown},
+        state::{MemoryStore, StateStore},
+    };
+
+    #[test]
+    fn assign_is_stable_and_uses_every_variant() {
+        let variants = BTreeSet::from(["a", "b"]);
+
+        let picks: BTreeSet<&str> = (0..50)
+            .filter_map(|id| assign("greeting", &variants, &id.to_string()))
+            .collect();
+
+        assert_eq!(picks, variants);
+        assert_eq!(
+            assign("greeting", &variants, "4242"),
+            assign("greeting", &variants, "4242")
+        );
+        assert_eq!(assign("greeting", &BTreeSet::new(), "4242"), None);
+    }
+
+    #[tokio::test]
+    async fn chat_is_credited_to_the_variant_its_sender_was_shown() -> Result<()> {
+        let store = MemoryStore::default();
+        let variant = Variant {
+            experiment: "greeting".into(),
+            variant: "b".into(),
+        };
+
+        assert!(!record_chat(&store, "42").await?);
+        record_shown(&store, &variant, Some("42")).await?;
+        record_shown(&store, &variant, None).await?;
+        assert!(record_chat(&store, "42").await?);
+        assert!(!record_chat(&store, "43").await?);
+
+        let counts = store.variant_counts("greeting").await?;
+        assert_eq!(counts.get("b/shown"), Some(&2));
+        assert_eq!(counts.get("b/chat_messages"), Some(&1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn chat_after_the_window_isnt_credited() -> Result<()> {
+        let store = MemoryStore::default();
+        let variant = Variant {
+            experiment: "greeting".into(),
+            variant: "b".into(),
+        };
+
+        store
+            .set_engagement("42", &serde_json::to_string(&variant)?, 0)
+            .await?;
+
+        assert!(!record_chat(&store, "42").await?);
+        Ok(())
+    }
+}
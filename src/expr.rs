@@ -0,0 +1,406 @@
+use std::cmp::Ordering;
+
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+
+/// A boolean expression over the fields of a JSON event, e.g.
+/// `reward.cost > 1000 && (user.is_sub || user_name == "Clucky")`.
+///
+/// Operands are dotted field paths, numbers, `"text"`, `true` and `false`. Comparisons take
+/// `==`, `!=`, `>`, `>=`, `<` and `<=`, and combine with `&&`, `||`, `!` and parentheses. A field
+/// on its own is true when it's `true`, a non-zero number or non-empty text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Operand, Comparison, Operand),
+    Truthy(Operand),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Field(String),
+    Literal(Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Comparison {
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Eq => ordering.is_eq(),
+            Comparison::Ne => ordering.is_ne(),
+            Comparison::Gt => ordering.is_gt(),
+            Comparison::Ge => ordering.is_ge(),
+            Comparison::Lt => ordering.is_lt(),
+            Comparison::Le => ordering.is_le(),
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(source: &str) -> Result<Expr> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            source,
+            tokens: &tokens,
+            pos: 0,
+        };
+        let expr = parser.or()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(t) => Err(parser.error(t, "Unexpected")),
+        }
+    }
+
+    /// Comparisons involving fields the event doesn't have are false.
+    pub fn evaluate(&self, event: &Value) -> bool {
+        match self {
+            Expr::Or(a, b) => a.evaluate(event) || b.evaluate(event),
+            Expr::And(a, b) => a.evaluate(event) && b.evaluate(event),
+            Expr::Not(e) => !e.evaluate(event),
+            Expr::Compare(a, op, b) => match (a.resolve(event), b.resolve(event)) {
+                (Some(a), Some(b)) => compare(a, b).is_some_and(|o| op.holds(o)),
+                _ => false,
+            },
+            Expr::Truthy(operand) => match operand.resolve(event) {
+                Some(Value::Bool(b)) => *b,
+                Some(Value::Number(n)) => n.as_f64().is_some_and(|n| n != 0.0),
+                Some(Value::String(s)) => !s.is_empty(),
+                _ => false,
+            },
+        }
+    }
+}
+
+impl Operand {
+    fn resolve<'a>(&'a self, event: &'a Value) -> Option<&'a Value> {
+        match self {
+            Operand::Field(path) => path
+                .split('.')
+                .try_fold(event, |val, key| val.get(key))
+                .filter(|v| !v.is_null()),
+            Operand::Literal(val) => Some(val),
+        }
+    }
+}
+
+/// Numbers, and text that reads as a number, compare as numbers. Bools only compare with bools,
+/// and anything else compares as text.
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (Value::Bool(_), _) | (_, Value::Bool(_)) => None,
+        _ => match (as_number(a), as_number(b)) {
+            (Some(a), Some(b)) => a.partial_cmp(&b),
+            _ => Some(as_text(a).cmp(&as_text(b))),
+        },
+    }
+}
+
+fn as_number(val: &Value) -> Option<f64> {
+    match val {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+fn as_text(val: &Value) -> String {
+    match val {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Field(String),
+    Literal(Value),
+    Compare(Comparison),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    kind: TokenKind,
+    /// 1-based column the token starts at, for error messages.
+    column: usize,
+    text: String,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let kind = match c {
+            '(' => {
+                i += 1;
+                TokenKind::Open
+            }
+            ')' => {
+                i += 1;
+                TokenKind::Close
+            }
+            '"' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&c| c == '"')
+                    .map(|p| i + 1 + p)
+                    .ok_or(anyhow!(
+                        "Unterminated text at column {} in {source:?}",
+                        start + 1
+                    ))?;
+                i = end + 1;
+                TokenKind::Literal(Value::String(chars[start + 1..end].iter().collect()))
+            }
+            '&' | '|' | '=' if chars.get(i + 1) == Some(&c) => {
+                i += 2;
+                match c {
+                    '&' => TokenKind::And,
+                    '|' => TokenKind::Or,
+                    _ => TokenKind::Compare(Comparison::Eq),
+                }
+            }
+            '!' | '>' | '<' => {
+                let eq = chars.get(i + 1) == Some(&'=');
+                i += if eq { 2 } else { 1 };
+                match (c, eq) {
+                    ('!', true) => TokenKind::Compare(Comparison::Ne),
+                    ('!', false) => TokenKind::Not,
+                    ('>', true) => TokenKind::Compare(Comparison::Ge),
+                    ('>', false) => TokenKind::Compare(Comparison::Gt),
+                    ('<', true) => TokenKind::Compare(Comparison::Le),
+                    _ => TokenKind::Compare(Comparison::Lt),
+                }
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                let number = text
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(serde_json::Number::from_f64)
+                    .ok_or(anyhow!(
+                        "Invalid number {text} at column {} in {source:?}",
+                        start + 1
+                    ))?;
+                TokenKind::Literal(Value::Number(number))
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                while i < chars.len()
+                    && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+                {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                match text.as_str() {
+                    "true" => TokenKind::Literal(Value::Bool(true)),
+                    "false" => TokenKind::Literal(Value::Bool(false)),
+                    _ if text.ends_with('.') || text.contains("..") => {
+                        return Err(anyhow!(
+                            "Invalid field {text} at column {} in {source:?}",
+                            start + 1
+                        ));
+                    }
+                    _ => TokenKind::Field(text),
+                }
+            }
+            other => {
+                return Err(anyhow!(
+                    "Unexpected character {other:?} at column {} in {source:?}",
+                    start + 1
+                ));
+            }
+        };
+
+        tokens.push(Token {
+            kind,
+            column: start + 1,
+            text: chars[start..i].iter().collect(),
+        });
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next_if(&mut self, kind: &TokenKind) -> bool {
+        let matched = self.peek().is_some_and(|t| &t.kind == kind);
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn error(&self, token: &Token, what: &str) -> anyhow::Error {
+        anyhow!(
+            "{what} {} at column {} in {:?}",
+            token.text,
+            token.column,
+            self.source
+        )
+    }
+
+    fn end_error(&self, expected: &str) -> anyhow::Error {
+        anyhow!("Expected {expected} at the end of {:?}", self.source)
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+        while self.next_if(&TokenKind::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.unary()?;
+        while self.next_if(&TokenKind::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.next_if(&TokenKind::Not) {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+
+        if self.next_if(&TokenKind::Open) {
+            let expr = self.or()?;
+            return match self.peek() {
+                Some(t) if t.kind == TokenKind::Close => {
+                    self.pos += 1;
+                    Ok(expr)
+                }
+                Some(t) => Err(self.error(t, "Expected ) but found")),
+                None => Err(self.end_error(")")),
+            };
+        }
+
+        let left = self.operand()?;
+        match self.peek().map(|t| t.kind.clone()) {
+            Some(TokenKind::Compare(op)) => {
+                self.pos += 1;
+                Ok(Expr::Compare(left, op, self.operand()?))
+            }
+            _ => Ok(Expr::Truthy(left)),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand> {
+        let token = match self.peek() {
+            Some(t) => t,
+            None => return Err(self.end_error("a field or value")),
+        };
+
+        let operand = match &token.kind {
+            TokenKind::Field(path) => Operand::Field(path.clone()),
+            TokenKind::Literal(val) => Operand::Literal(val.clone()),
+            _ => return Err(self.error(token, "Expected a field or value but found")),
+        };
+        self.pos += 1;
+        Ok(operand)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use serde_json::json;
+
+    use crate::expr::Expr;
+
+    #[test]
+    fn evaluates_comparisons_combined_with_boolean_logic() -> Result<()> {
+        let event = json!({"reward": {"cost": 1500}, "user": {"is_sub": true, "name": "Clucky"}});
+
+        assert!(Expr::parse("reward.cost > 1000 && user.is_sub")?.evaluate(&event));
+        assert!(Expr::parse("reward.cost > 2000 || user.name == \"Clucky\"")?.evaluate(&event));
+        assert!(!Expr::parse("!user.is_sub")?.evaluate(&event));
+        assert!(Expr::parse("!(reward.cost < 1000 || user.is_sub == false)")?.evaluate(&event));
+        Ok(())
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() -> Result<()> {
+        let event = json!({"a": true, "b": false, "c": false});
+
+        assert!(Expr::parse("a || b && c")?.evaluate(&event));
+        assert!(!Expr::parse("(a || b) && c")?.evaluate(&event));
+        Ok(())
+    }
+
+    #[test]
+    fn missing_fields_are_false_and_text_numbers_compare_as_numbers() -> Result<()> {
+        let event = json!({"tier": "2000", "bits": 0, "message": ""});
+
+        assert!(Expr::parse("tier == 2000")?.evaluate(&event));
+        assert!(Expr::parse("tier > 1000")?.evaluate(&event));
+        assert!(!Expr::parse("missing == 1")?.evaluate(&event));
+        assert!(!Expr::parse("missing != 1")?.evaluate(&event));
+        assert!(!Expr::parse("bits")?.evaluate(&event));
+        assert!(!Expr::parse("message")?.evaluate(&event));
+        assert!(Expr::parse("bits >= -1")?.evaluate(&event));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_problem() {
+        let errors = [
+            ("bits >=", "Expected a field or value at the end"),
+            ("bits >= 500 &&", "Expected a field or value at the end"),
+            ("(bits > 1", "Expected ) at the end"),
+            ("bits > 1)", "Unexpected ) at column 9"),
+            ("bits = 1", "Unexpected character '=' at column 6"),
+            ("user_name == \"Clucky", "Unterminated text at column 14"),
+            ("bits 500", "Unexpected 500 at column 6"),
+            (
+                "&& bits",
+                "Expected a field or value but found && at column 1",
+            ),
+            ("reward. > 1", "Invalid field reward. at column 1"),
+        ];
+
+        for (source, expected) in errors {
+            let err = Expr::parse(source).unwrap_err().to_string();
+            assert!(err.starts_with(expected), "{source}: {err}");
+        }
+    }
+}
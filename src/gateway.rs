@@ -0,0 +1,190 @@
+use axum::{
+    body::Bytes,
+    extract::OriginalUri,
+    http::{Request, Uri},
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use lambda_http::{RequestExt, request::RequestContext};
+
+/// Makes a request converted from any Lambda integration look like one sent to the bot
+/// directly, so the router sees the same paths behind API Gateway REST and HTTP APIs, ALBs
+/// and Function URLs.
+///
+/// `lambda_http` already decodes base64 bodies and folds multi-value headers into the header
+/// map, but it leaves a named API Gateway stage at the front of the path (`/prod/twitch/eventsub`),
+/// which the routes don't expect. That prefix is stripped here, and the path as the client sent
+/// it is kept as the request's [`OriginalUri`], which signed requests are checked against.
+pub fn normalize<B>(mut request: Request<B>) -> Request<B> {
+    let stage = match request.request_context_ref() {
+        Some(RequestContext::ApiGatewayV1(context)) => context.stage.clone(),
+        Some(RequestContext::ApiGatewayV2(context)) => context.stage.clone(),
+        _ => None,
+    };
+    let Some(stage) = stage.filter(|s| !s.is_empty() && s != "$default") else {
+        return request;
+    };
+
+    let uri = request.uri();
+    let Some(path) = uri
+        .path()
+        .strip_prefix(&format!("/{stage}"))
+        .filter(|p| p.is_empty() || p.starts_with('/'))
+    else {
+        return request;
+    };
+    let path = match path {
+        "" => "/",
+        path => path,
+    };
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{path}?{query}"),
+        None => path.to_string(),
+    };
+
+    let mut parts = uri.clone().into_parts();
+    match path_and_query.parse() {
+        Ok(path_and_query) => parts.path_and_query = Some(path_and_query),
+        Err(e) => {
+            println!("Failed to strip stage {stage} from {uri}: {e}");
+            return request;
+        }
+    }
+    if let Ok(stripped) = Uri::from_parts(parts) {
+        let original = OriginalUri(std::mem::replace(request.uri_mut(), stripped));
+        request.extensions_mut().insert(original);
+    }
+    request
+}
+
+/// The body Twitch signed. Integrations that mark a base64 body as such have it decoded by
+/// `lambda_http`, but some (REST APIs with binary media types, custom proxies) pass it on still
+/// encoded. The HMAC is over the original bytes, so those are decoded here before the signature
+/// is checked. EventSub bodies are always JSON objects, which is how an encoded one is told apart.
+pub(crate) fn original_body(body: Bytes) -> Bytes {
+    if body.trim_ascii_start().starts_with(b"{") {
+        return body;
+    }
+    match STANDARD.decode(body.trim_ascii()) {
+        Ok(decoded) if decoded.trim_ascii_start().starts_with(b"{") => Bytes::from(decoded),
+        _ => body,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use axum::{Router, body::Bytes, extract::OriginalUri, http::HeaderMap, routing::post};
+    use base64::{Engine, engine::general_purpose::STANDARD};
+    use hmac::{Hmac, Mac};
+    use pretty_assertions::assert_eq;
+    use sha2::Sha256;
+    use tower::ServiceExt;
+
+    use crate::{
+        config::AppConfig,
+        gateway::{normalize, original_body},
+        handler::event_handler::EventHandler,
+    };
+
+    const PAYLOAD: &str = r#"{"challenge":"pogchamp-kappa-360noscope-vohiyo"}"#;
+
+    /// Runs a recorded Lambda event through a router echoing the EventSub message id and the
+    /// body, as the eventsub handler would see them.
+    async fn deliver(integration: &str) -> Result<(u16, String)> {
+        let event = std::fs::read_to_string(format!("resources/tests/gateway/{integration}.json"))?;
+        let request = lambda_http::request::from_str(&event)?;
+        let app: Router = Router::new().route(
+            "/twitch/eventsub",
+            post(|headers: HeaderMap, body: Bytes| async move {
+                let id = headers["twitch-eventsub-message-id"]
+                    .to_str()
+                    .unwrap()
+                    .to_string();
+                format!("{id} {}", String::from_utf8_lossy(&body))
+            }),
+        );
+
+        let response = app.oneshot(normalize(request)).await?;
+        let status = response.status().as_u16();
+        let body = axum::body::to_bytes(response.into_body(), 1024).await?;
+        Ok((status, String::from_utf8(body.to_vec())?))
+    }
+
+    #[tokio::test]
+    async fn every_integration_reaches_the_eventsub_route() -> Result<()> {
+        for integration in [
+            "apigw_rest",
+            "apigw_http",
+            "function_url",
+            "alb",
+            "alb_multi_value",
+        ] {
+            let delivered = deliver(integration).await?;
+
+            assert_eq!(
+                delivered,
+                (200, format!("message-1 {PAYLOAD}")),
+                "{integration}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn normalize_leaves_requests_without_a_stage_alone() {
+        let request = axum::http::Request::post("/prod/twitch/eventsub?a=1")
+            .body(())
+            .unwrap();
+
+        assert_eq!(normalize(request).uri(), "/prod/twitch/eventsub?a=1");
+    }
+
+    #[test]
+    fn normalize_keeps_the_path_with_the_stage_as_the_original_uri() -> Result<()> {
+        let event = std::fs::read_to_string("resources/tests/gateway/apigw_http.json")?;
+        let request = normalize(lambda_http::request::from_str(&event)?);
+
+        assert_eq!(request.uri().path(), "/twitch/eventsub");
+        assert_eq!(
+            request.extensions().get::<OriginalUri>().map(|o| o.path()),
+            Some("/prod/twitch/eventsub")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn signatures_verify_over_decoded_base64_bodies() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let config = AppConfig::from_env();
+        let payload = "{\"user_name\": \"Cooler_Üser\"}\r\n";
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(config.twitch_eventsub_subscription_secret.as_bytes())?;
+        mac.update(format!("message-1{}{payload}", "2025-09-14T00:00:00Z").as_bytes());
+        let signature = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+        let mut headers = HeaderMap::new();
+        headers.insert("twitch-eventsub-message-id", "message-1".parse()?);
+        headers.insert(
+            "twitch-eventsub-message-timestamp",
+            "2025-09-14T00:00:00Z".parse()?,
+        );
+        headers.insert("twitch-eventsub-message-signature", signature.parse()?);
+
+        let encoded = Bytes::from(STANDARD.encode(payload));
+        assert!(EventHandler::verify(&encoded, &headers, &config).is_err());
+
+        let body = original_body(encoded);
+        assert_eq!(body, payload.as_bytes());
+        assert!(EventHandler::verify(&body, &headers, &config).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn original_body_keeps_json_and_non_json_bodies() {
+        let json = Bytes::from_static(b"  {\"a\": 1}");
+        assert_eq!(original_body(json.clone()), json);
+
+        // Valid base64, but not of a JSON object.
+        let text = Bytes::from_static(b"pogchamp");
+        assert_eq!(original_body(text.clone()), text);
+    }
+}
@@ -0,0 +1,372 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::locale::Locale;
+
+/// Pronouns used by the `they`/`them`/`their`/`theirs` template helpers. Mods without any set
+/// get they/them.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq)]
+pub enum Pronouns {
+    #[serde(rename = "he/him")]
+    HeHim,
+    #[serde(rename = "she/her")]
+    SheHer,
+    #[default]
+    #[serde(rename = "they/them")]
+    TheyThem,
+}
+
+impl Pronouns {
+    fn subject(&self) -> &'static str {
+        match self {
+            Pronouns::HeHim => "he",
+            Pronouns::SheHer => "she",
+            Pronouns::TheyThem => "they",
+        }
+    }
+
+    fn object(&self) -> &'static str {
+        match self {
+            Pronouns::HeHim => "him",
+            Pronouns::SheHer => "her",
+            Pronouns::TheyThem => "them",
+        }
+    }
+
+    fn possessive(&self) -> &'static str {
+        match self {
+            Pronouns::HeHim => "his",
+            Pronouns::SheHer => "her",
+            Pronouns::TheyThem => "their",
+        }
+    }
+
+    fn possessive_pronoun(&self) -> &'static str {
+        match self {
+            Pronouns::HeHim => "his",
+            Pronouns::SheHer => "hers",
+            Pronouns::TheyThem => "theirs",
+        }
+    }
+}
+
+/// Replaces helper calls such as `{possessive(win_1)}` or `{Their(win_1)}` with their result,
+/// leaving plain placeholders for strfmt. Results are brace-escaped so a name containing `{`
+/// can't be mistaken for a placeholder.
+///
+/// Helpers:
+/// - `possessive(key)`: `Anna's`, `James'`
+/// - `a(key)`: `a duck`, `an egg`
+/// - `plural(word, key_or_number)`: `cracker` for 1, `crackers` otherwise
+/// - `they(key)`, `them(key)`, `their(key)`, `theirs(key)`: pronouns of the mod in `key`
+/// - `number(key)`: `1,214`, with the separators of `locale`
+/// - `ago(key)`: `3 days ago`, `in 2 hours`, for an RFC 3339 time, in `locale`
+///
+/// Capitalising the helper name capitalises its result, e.g. `{They(win_1)}`.
+pub fn apply_helpers(
+    template: &str,
+    values: &HashMap<String, String>,
+    pronouns: &HashMap<String, Pronouns>,
+    locale: Locale,
+) -> Result<String, String> {
+    let mut output = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        if let Some(escaped) = after.strip_prefix('{') {
+            output.push_str("{{");
+            rest = escaped;
+            continue;
+        }
+
+        let end = match after.find('}') {
+            Some(end) => end,
+            None => {
+                output.push_str(&rest[start..]);
+                return Ok(output);
+            }
+        };
+
+        let inner = &after[..end];
+        match parse_call(inner) {
+            Some((name, args)) => {
+                let result = call_helper(name, &args, values, pronouns, locale)?;
+                output.push_str(&result.replace('{', "{{").replace('}', "}}"));
+            }
+            None => {
+                output.push('{');
+                output.push_str(inner);
+                output.push('}');
+            }
+        }
+        rest = &after[end + 1..];
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Placeholder keys passed to helper calls in `template`, e.g. `win_1` in `{their(win_1)}`.
+pub fn helper_keys(template: &str) -> Vec<&str> {
+    let mut keys = vec![];
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        let after = &rest[start + 1..];
+        let end = match after.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+
+        if let Some((_, args)) = parse_call(&after[..end]) {
+            keys.extend(args);
+        }
+        rest = &after[end + 1..];
+    }
+
+    keys
+}
+
+fn parse_call(inner: &str) -> Option<(&str, Vec<&str>)> {
+    let (name, args) = inner.strip_suffix(')')?.split_once('(')?;
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    Some((name, args.split(',').map(str::trim).collect()))
+}
+
+fn call_helper(
+    name: &str,
+    args: &[&str],
+    values: &HashMap<String, String>,
+    pronouns: &HashMap<String, Pronouns>,
+    locale: Locale,
+) -> Result<String, String> {
+    let lookup = |key: &str| {
+        values
+            .get(key)
+            .map(String::as_str)
+            .ok_or(format!("{name}({key}): no value for {key}"))
+    };
+    let pronouns_of = |key: &str| -> Result<Pronouns, String> {
+        let value = lookup(key)?;
+        Ok(pronouns.get(value).copied().unwrap_or_default())
+    };
+
+    let result = match (name.to_ascii_lowercase().as_str(), args) {
+        ("possessive", [key]) => possessive(lookup(key)?),
+        ("a", [key]) => article(lookup(key)?),
+        ("plural", [word, count]) => {
+            let count = match count.parse::<i64>() {
+                Ok(n) => n,
+                Err(_) => lookup(count)?
+                    .parse::<i64>()
+                    .map_err(|_| format!("plural: {count} is not a number"))?,
+            };
+            plural(word, count)
+        }
+        ("they", [key]) => pronouns_of(key)?.subject().to_string(),
+        ("them", [key]) => pronouns_of(key)?.object().to_string(),
+        ("their", [key]) => pronouns_of(key)?.possessive().to_string(),
+        ("theirs", [key]) => pronouns_of(key)?.possessive_pronoun().to_string(),
+        ("number", [key]) => locale
+            .format_number(lookup(key)?)
+            .ok_or(format!("number: {key} is not a number"))?,
+        ("ago", [key]) => {
+            let then = DateTime::parse_from_rfc3339(lookup(key)?)
+                .map_err(|_| format!("ago: {key} is not an RFC 3339 time"))?;
+            locale.relative_time(then.with_timezone(&Utc), Utc::now())
+        }
+        _ => {
+            return Err(format!(
+                "Unknown template helper {name}({})",
+                args.join(", ")
+            ));
+        }
+    };
+
+    if name.starts_with(|c: char| c.is_ascii_uppercase()) {
+        Ok(capitalize(&result))
+    } else {
+        Ok(result)
+    }
+}
+
+fn possessive(name: &str) -> String {
+    if name.ends_with(['s', 'S']) {
+        format!("{name}'")
+    } else {
+        format!("{name}'s")
+    }
+}
+
+fn article(word: &str) -> String {
+    if word.starts_with(['a', 'e', 'i', 'o', 'u', 'A', 'E', 'I', 'O', 'U']) {
+        format!("an {word}")
+    } else {
+        format!("a {word}")
+    }
+}
+
+fn plural(word: &str, count: i64) -> String {
+    if count == 1 {
+        return word.to_string();
+    }
+
+    if word.ends_with(['s', 'x', 'z']) || word.ends_with("ch") || word.ends_with("sh") {
+        format!("{word}es")
+    } else if let Some(stem) = word.strip_suffix('y')
+        && !stem.ends_with(['a', 'e', 'i', 'o', 'u'])
+    {
+        format!("{stem}ies")
+    } else {
+        format!("{word}s")
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        grammar::{Pronouns, apply_helpers, helper_keys},
+        locale::Locale,
+    };
+
+    fn values() -> HashMap<String, String> {
+        HashMap::from([
+            ("win_1".to_string(), "James".to_string()),
+            ("win_2".to_string(), "Anna".to_string()),
+            ("count".to_string(), "3".to_string()),
+        ])
+    }
+
+    #[test]
+    fn possessive_handles_names_ending_in_s() {
+        let result = apply_helpers(
+            "{possessive(win_1)} and {possessive(win_2)} crackers",
+            &values(),
+            &HashMap::new(),
+            Locale::default(),
+        );
+
+        assert_eq!(result, Ok("James' and Anna's crackers".to_string()));
+    }
+
+    #[test]
+    fn article_and_plural_pick_the_right_form() {
+        let result = apply_helpers(
+            "{win_1} found {a(win_2)} and {count} {plural(cracker, count)}, then {plural(box, 1)}",
+            &values(),
+            &HashMap::new(),
+            Locale::default(),
+        );
+
+        assert_eq!(
+            result,
+            Ok("{win_1} found an Anna and {count} crackers, then box".to_string())
+        );
+    }
+
+    #[test]
+    fn pronoun_helpers_use_configured_pronouns_and_default_to_they() {
+        let pronouns = HashMap::from([("Anna".to_string(), Pronouns::SheHer)]);
+
+        let result = apply_helpers(
+            "{win_2} ate {their(win_2)} cracker. {They(win_1)} kept {theirs(win_1)}.",
+            &values(),
+            &pronouns,
+            Locale::default(),
+        );
+
+        assert_eq!(
+            result,
+            Ok("{win_2} ate her cracker. They kept theirs.".to_string())
+        );
+    }
+
+    #[test]
+    fn apply_helpers_leaves_escaped_braces_alone() {
+        let result = apply_helpers(
+            "{{not(a_call)}} {them(win_1)}",
+            &values(),
+            &HashMap::new(),
+            Locale::default(),
+        );
+
+        assert_eq!(result, Ok("{{not(a_call)}} them".to_string()));
+    }
+
+    #[test]
+    fn apply_helpers_returns_err_for_unknown_helper_or_key() {
+        assert!(
+            apply_helpers(
+                "{shout(win_1)}",
+                &values(),
+                &HashMap::new(),
+                Locale::default()
+            )
+            .is_err()
+        );
+        assert!(
+            apply_helpers(
+                "{their(win_9)}",
+                &values(),
+                &HashMap::new(),
+                Locale::default()
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn helper_keys_lists_helper_arguments() {
+        assert_eq!(
+            helper_keys("{win_1} hands {their(random_viewer)} a {plural(cracker, count)}"),
+            vec!["random_viewer", "cracker", "count"]
+        );
+    }
+
+    #[test]
+    fn number_and_ago_follow_the_locale() {
+        let three_days_ago = (chrono::Utc::now() - chrono::Duration::days(3)).to_rfc3339();
+        let values = HashMap::from([
+            ("count".to_string(), "1214".to_string()),
+            ("fed_at".to_string(), three_days_ago),
+        ]);
+
+        let english = apply_helpers(
+            "Fed {number(count)} times, last {ago(fed_at)}",
+            &values,
+            &HashMap::new(),
+            Locale::En,
+        );
+        let german = apply_helpers(
+            "{number(count)} Mal, zuletzt {ago(fed_at)}",
+            &values,
+            &HashMap::new(),
+            Locale::De,
+        );
+
+        assert_eq!(english, Ok("Fed 1,214 times, last 3 days ago".to_string()));
+        assert_eq!(german, Ok("1.214 Mal, zuletzt vor 3 Tagen".to_string()));
+        assert!(apply_helpers("{ago(count)}", &values, &HashMap::new(), Locale::En).is_err());
+    }
+}
@@ -1,12 +1,22 @@
 pub mod event_handler {
-    use std::{collections::HashMap, path::PathBuf, str::FromStr};
+    use std::{
+        collections::HashMap,
+        path::PathBuf,
+        str::FromStr,
+        sync::{
+            Arc,
+            atomic::{AtomicBool, Ordering},
+        },
+        time::Duration,
+    };
 
     use anyhow::{Context, Result, anyhow};
     use axum::http::{HeaderMap, HeaderName};
+    use chrono::{DateTime, Utc};
     use fastrand::Rng;
     use hex::decode;
     use hmac::{Hmac, Mac};
-    use lambda_http::{Body, Response, tracing};
+    use lambda_http::{Body, Context as LambdaContext, Response, tracing};
     use reqwest::{
         StatusCode,
         header::{self, CONTENT_TYPE},
@@ -15,21 +25,100 @@ pub mod event_handler {
     use sha2::Sha256;
 
     use crate::{
+        action::{ActionScheduler, QueuedAction},
+        audit, auth,
+        cache::TtlCache,
         client::StreamelementsCaller,
         config::AppConfig,
-        reward::{RewardHandler, mod_feeder::ModFeed},
+        deadline,
+        eventsub_secrets::{self, EventsubSecretStore},
+        metrics,
+        notify::{Notifier, NotifyEvent},
+        probe,
+        reward::{EventPipeline, RewardHandler, mod_feeder::ModFeed},
         robochick::twitch::{MessageBuilder, MessageComponents, Robochick},
+        twitch_cli,
         types::twitch::{
-            EventsubHeader, MessageType, RevocationEvent, RewardRedeemed, SubscriptionType,
-            VerificationEvent,
+            EventsubHeader, MessageType, RevocationEvent, RevocationReason, RewardRedeemed,
+            SubscriptionType, VerificationEvent,
         },
     };
 
     type HmacSha256 = Hmac<Sha256>;
 
+    /// What handling an EventSub request did, for callers to act on without reading the
+    /// response.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum HandleOutcome {
+        /// The signature didn't check out, nothing was done.
+        Unverified {
+            reason: String,
+        },
+        ChallengeAnswered,
+        ChallengeRejected {
+            reason: String,
+        },
+        /// Something handled the notification. `scenario` is the one a reward posted, if any,
+        /// and `failures` the actions that failed while others went through.
+        NotificationProcessed {
+            scenario: Option<String>,
+            message_id: String,
+            failures: Vec<String>,
+        },
+        NotificationSkipped {
+            reason: String,
+        },
+        Revoked {
+            reason: String,
+        },
+        /// Handling ran past the Lambda deadline. `queued` when the notification went to the
+        /// outbox to be replayed, otherwise Twitch is asked to retry it.
+        TimedOut {
+            queued: bool,
+        },
+    }
+
+    impl HandleOutcome {
+        /// Short name of the outcome, for metric dimensions.
+        pub fn name(&self) -> &'static str {
+            match self {
+                HandleOutcome::Unverified { .. } => "Unverified",
+                HandleOutcome::ChallengeAnswered => "ChallengeAnswered",
+                HandleOutcome::ChallengeRejected { .. } => "ChallengeRejected",
+                HandleOutcome::NotificationProcessed { .. } => "NotificationProcessed",
+                HandleOutcome::NotificationSkipped { .. } => "NotificationSkipped",
+                HandleOutcome::Revoked { .. } => "Revoked",
+                HandleOutcome::TimedOut { .. } => "TimedOut",
+            }
+        }
+    }
+
+    /// What became of a redemption notification.
+    enum RedemptionOutcome {
+        /// A handler is registered for the reward and handled it, posting `scenario` if any.
+        Handled { scenario: Option<String> },
+        /// The notification isn't a redemption, or no handler is registered for its reward.
+        Unhandled,
+    }
+
     #[derive(Default)]
     pub struct EventHandler {
         handlers: HashMap<String, Box<dyn RewardHandler>>,
+        /// Used to recreate subscriptions Twitch revoked. Without it they're only logged.
+        actions: Option<Arc<dyn ActionScheduler>>,
+        /// Challenge responses by message id, for answering retried verification requests.
+        challenges: Option<Arc<TtlCache<String, String>>>,
+        /// Tells the broadcaster about revocations.
+        notifier: Option<Arc<Notifier>>,
+        /// Runs the configured rules for every notification.
+        pipeline: Option<Box<dyn EventPipeline>>,
+        /// Secrets of subscriptions that have their own, checked before the shared one.
+        eventsub_secrets: Option<Arc<EventsubSecretStore>>,
+        /// The invocation being handled, on Lambda.
+        lambda_context: Option<LambdaContext>,
+        /// Set once `handle` has verified the request, so a handler cut off by the deadline
+        /// can queue it without verifying again.
+        verified: AtomicBool,
     }
 
     impl EventHandler {
@@ -37,6 +126,136 @@ pub mod event_handler {
             self.handlers.insert(id.into(), Box::new(handler));
         }
 
+        pub fn set_actions(&mut self, actions: Arc<dyn ActionScheduler>) {
+            self.actions = Some(actions);
+        }
+
+        pub fn set_challenge_cache(&mut self, challenges: Arc<TtlCache<String, String>>) {
+            self.challenges = Some(challenges);
+        }
+
+        pub fn set_notifier(&mut self, notifier: Arc<Notifier>) {
+            self.notifier = Some(notifier);
+        }
+
+        pub fn set_pipeline(&mut self, pipeline: impl EventPipeline + 'static) {
+            self.pipeline = Some(Box::new(pipeline));
+        }
+
+        pub fn set_eventsub_secrets(&mut self, secrets: Arc<EventsubSecretStore>) {
+            self.eventsub_secrets = Some(secrets);
+        }
+
+        pub fn set_lambda_context(&mut self, context: LambdaContext) {
+            self.lambda_context = Some(context);
+        }
+
+        /// AWS request id of the Lambda invocation, for tying logs to it.
+        pub fn request_id(&self) -> Option<&str> {
+            self.lambda_context
+                .as_ref()
+                .map(|c| c.request_id.as_str())
+                .filter(|id| !id.is_empty())
+        }
+
+        /// How long handling can take before the invocation's deadline. `None` outside Lambda.
+        pub fn time_left(&self, now: DateTime<Utc>) -> Option<Duration> {
+            deadline::time_left(self.lambda_context.as_ref(), now)
+        }
+
+        /// Whether the request is signed with its subscription's secret or the shared one.
+        pub(crate) async fn is_verified(
+            &self,
+            request: &[u8],
+            headers: &HeaderMap,
+            config: &AppConfig,
+        ) -> bool {
+            self.verify_request(request, headers, config).await.is_ok()
+        }
+
+        /// Whether `handle` got as far as verifying the request.
+        pub(crate) fn was_verified(&self) -> bool {
+            self.verified.load(Ordering::Relaxed)
+        }
+
+        /// Checks the signature against the message's secret, then against
+        /// `TWITCH_EVENTSUB_PREVIOUS_SECRET` while that's accepted. During a rotation the secret
+        /// that matched is logged and counted, to tell when Twitch has moved over.
+        async fn verify_request(
+            &self,
+            request: &[u8],
+            headers: &HeaderMap,
+            config: &AppConfig,
+        ) -> Result<()> {
+            let secret = self.signing_secret(request, headers, config).await?;
+            let verified = EventHandler::verify_with_secret(request, headers, secret.as_bytes());
+            let Some(previous) = config.previous_eventsub_secret(Utc::now()) else {
+                return verified;
+            };
+
+            let matched = match verified {
+                Ok(()) => "current",
+                Err(e) => {
+                    match EventHandler::verify_with_secret(request, headers, previous.as_bytes()) {
+                        Ok(()) => "previous",
+                        Err(_) => return Err(e),
+                    }
+                }
+            };
+            let message_id = headers
+                .get(EventsubHeader::MessageId.as_ref())
+                .and_then(|h| h.to_str().ok())
+                .unwrap_or_default();
+            println!("Message {message_id} was signed with the {matched} EventSub secret");
+            metrics::count("EventsubSecretMatched", &[("Secret", matched)]);
+            Ok(())
+        }
+
+        /// Handles a notification that was verified when it first came in, e.g. one replayed
+        /// from the outbox.
+        pub(crate) async fn replay(
+            &self,
+            payload: &str,
+            headers: &HeaderMap,
+            config: &AppConfig,
+        ) -> Result<HandleOutcome> {
+            self.handle_notification(payload, headers, config).await
+        }
+
+        /// The secret the message was signed with: that of its subscription. The shared
+        /// `TWITCH_EVENTSUB_SUBSCRIPTION_SECRET` is only used without per-subscription secrets,
+        /// for Twitch CLI events and for the subscriptions in
+        /// `EVENTSUB_LEGACY_SUBSCRIPTION_IDS`, since the id comes from the unverified payload.
+        async fn signing_secret(
+            &self,
+            request: &[u8],
+            headers: &HeaderMap,
+            config: &AppConfig,
+        ) -> Result<String> {
+            let shared = || Ok(config.twitch_eventsub_subscription_secret.clone());
+            // The CLI makes up a new subscription id for every event, none of which have a
+            // secret of their own.
+            let cli = config.twitch_cli_events && twitch_cli::is_cli_event(request);
+            let Some(secrets) = self.eventsub_secrets.as_ref().filter(|_| !cli) else {
+                return shared();
+            };
+            let id = eventsub_secrets::subscription_id(request)
+                .ok_or(anyhow!("The message has no subscription id"))?;
+            if config.eventsub_legacy_subscription_ids.contains(&id) {
+                return shared();
+            }
+
+            let challenge = headers
+                .get(EventsubHeader::MessageType.as_ref())
+                .and_then(|h| h.to_str().ok())
+                .and_then(|h| MessageType::from_str(h).ok())
+                .is_some_and(|t| matches!(t, MessageType::WebhookCallbackVerification));
+            secrets
+                .get(&id, challenge)
+                .await
+                .ok_or(anyhow!("No secret for subscription {id}"))
+        }
+
         fn handle_challenge(
             payload: &str,
             headers: &HeaderMap,
@@ -51,80 +270,273 @@ pub mod event_handler {
                 }
             };
 
+            let subscription_type = challenge_event.subscription_type();
+            if SubscriptionType::from_str(subscription_type).is_err() {
+                return Err(anyhow!(
+                    "Challenge for unsupported subscription type {subscription_type}"
+                ));
+            }
+
             Ok(challenge_event.challenge().to_string())
         }
 
-        fn handle_revocation(payload: &str, headers: &HeaderMap, config: &AppConfig) {
-            if let Ok(event) = serde_json::from_str::<RevocationEvent>(payload) {
-                println!(
-                    "Subscription revoked for {} with reason: {}",
-                    event.subscription_type(),
+        /// Answers retried verification requests from the cache, keyed by message id, so only
+        /// the first one is parsed.
+        fn cached_challenge(
+            &self,
+            payload: &str,
+            headers: &HeaderMap,
+            config: &AppConfig,
+        ) -> Result<String> {
+            let message_id = headers
+                .get(EventsubHeader::MessageId.as_ref())
+                .and_then(|h| h.to_str().ok())
+                .map(str::to_string);
+
+            if let (Some(cache), Some(id)) = (self.challenges.as_ref(), message_id.as_ref())
+                && let Some(challenge) = cache.get(id)
+            {
+                return Ok(challenge);
+            }
+
+            let challenge = EventHandler::handle_challenge(payload, headers, config)?;
+            if let (Some(cache), Some(id)) = (self.challenges.as_ref(), message_id) {
+                cache.insert(id, challenge.clone());
+            }
+
+            Ok(challenge)
+        }
+
+        /// Logs the revocation, counts it by reason and starts whatever fixes it: re-authorizing,
+        /// re-subscribing, or a config change only a human can make. Returns the revocation's
+        /// reason.
+        async fn handle_revocation(&self, payload: &str, config: &AppConfig) -> String {
+            let event = match serde_json::from_str::<RevocationEvent>(payload) {
+                Ok(e) => e,
+                Err(e) => {
+                    println!("Failed to parse revocation payload: {e}");
+                    return format!("unparseable revocation: {e}");
+                }
+            };
+
+            println!(
+                "Subscription revoked for {} with reason: {}",
+                event.subscription_type(),
+                event.subscription_status()
+            );
+            metrics::count(
+                "SubscriptionRevoked",
+                &[
+                    ("Reason", event.subscription_status()),
+                    ("SubscriptionType", event.subscription_type()),
+                ],
+            );
+            if let Some(notifier) = self.notifier.as_ref() {
+                notifier
+                    .notify(
+                        NotifyEvent::Revocation,
+                        &format!(
+                            "Twitch revoked Robochick's {} subscription: {}",
+                            event.subscription_type(),
+                            event.subscription_status()
+                        ),
+                    )
+                    .await;
+            }
+
+            match event.reason() {
+                Some(RevocationReason::AuthorizationRevoked) => match auth::authorize_url(config) {
+                    Ok(url) => println!(
+                        "ALERT: the broadcaster revoked the bot's access. Re-authorize at {url}"
+                    ),
+                    Err(e) => println!(
+                        "ALERT: the broadcaster revoked the bot's access, and building the authorize link failed: {e}"
+                    ),
+                },
+                Some(RevocationReason::UserRemoved) => println!(
+                    "ALERT: Twitch user {} no longer exists. Update BROADCASTER_USER_ID",
+                    config.broadcaster_user_id
+                ),
+                Some(RevocationReason::VersionRemoved) => println!(
+                    "ALERT: version {} of {} is no longer supported. Update the subscription to a newer version",
+                    event.subscription_version(),
+                    event.subscription_type()
+                ),
+                Some(RevocationReason::NotificationFailuresExceeded) => {
+                    self.resubscribe(&event, config).await
+                }
+                None => println!(
+                    "ALERT: unknown revocation status {}",
                     event.subscription_status()
-                );
-            } else {
-                println!("Failed to parse payload");
+                ),
+            }
+            event.subscription_status().to_string()
+        }
+
+        async fn resubscribe(&self, event: &RevocationEvent, config: &AppConfig) {
+            let actions = match self.actions.as_ref() {
+                Some(a) => a,
+                None => {
+                    println!(
+                        "ALERT: {} was revoked after too many failed notifications. Recreate it by hand",
+                        event.subscription_type()
+                    );
+                    return;
+                }
+            };
+
+            let action = QueuedAction::Resubscribe(event.resubscribe_request());
+            match actions.schedule(action, 0, config).await {
+                Ok(_) => println!("Queued re-subscribing to {}", event.subscription_type()),
+                Err(e) => println!(
+                    "ALERT: failed to queue re-subscribing to {}: {e}",
+                    event.subscription_type()
+                ),
             }
         }
 
+        /// Runs the notification through its reward handler and the pipeline at the same time.
+        /// Errors when nothing handled it.
         async fn handle_notification(
             &self,
             payload: &str,
             headers: &HeaderMap,
             config: &AppConfig,
-        ) -> Result<()> {
-            if let Some(header) = headers.get(EventsubHeader::SubscriptionType.as_ref()) {
-                if header.to_str().map(SubscriptionType::from_str).is_err() {
-                    return Err(anyhow!("Unknown Subscription-Type header: {:?}", header));
+        ) -> Result<HandleOutcome> {
+            let header = match headers.get(EventsubHeader::SubscriptionType.as_ref()) {
+                Some(h) => h,
+                None => {
+                    return Err(anyhow!(
+                        "Missing {} header",
+                        EventsubHeader::SubscriptionType.as_ref()
+                    ));
                 }
+            };
+            let (event_type, subscription_type) = match header
+                .to_str()
+                .ok()
+                .and_then(|h| Some((h, SubscriptionType::from_str(h).ok()?)))
+            {
+                Some(t) => t,
+                None => return Err(anyhow!("Unknown Subscription-Type header: {:?}", header)),
+            };
 
-                let event = match serde_json::from_str::<RewardRedeemed>(payload) {
-                    Ok(s) => s,
-                    Err(e) => {
-                        println!("Failed to deserialize event to RewardRedeemed type: {e}");
-                        return Err(anyhow!("{e}"));
+            let notification: Value = serde_json::from_str(payload)?;
+            let event = &notification["event"];
+            let broadcaster_user_id = event
+                .get("broadcaster_user_id")
+                .or_else(|| event.get("to_broadcaster_user_id"))
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            if broadcaster_user_id != config.broadcaster_user_id {
+                println!("Invalid notification: unknown broadcaster user id {broadcaster_user_id}");
+                return Err(anyhow!("Unknown broadcaster user id {broadcaster_user_id}"));
+            }
+
+            let msg_id = headers
+                .get(EventsubHeader::MessageId.as_ref())
+                .expect("MessageId should be sent by Twitch")
+                .to_str()?;
+
+            // Probes only go through the reward, pipeline rules are free to post.
+            let probing = probe::is_probe(msg_id);
+            let redemption = async {
+                match subscription_type {
+                    SubscriptionType::CustomRewardRedemption => {
+                        let handled = self.handle_redemption(payload, headers, msg_id, config);
+                        probe::scope(probing, handled).await
+                    }
+                    _ => Ok(RedemptionOutcome::Unhandled),
+                }
+            };
+            let pipeline = async {
+                match self.pipeline.as_ref().filter(|_| !probing) {
+                    Some(p) => {
+                        audit::with_event_id(msg_id.to_string(), p.run(event_type, event, config))
+                            .await
                     }
-                };
+                    None => Ok(0),
+                }
+            };
+            let (handled, matched) = tokio::join!(redemption, pipeline);
+
+            let mut failures = vec![];
+            let handled = handled.unwrap_or_else(|e| {
+                println!("Failed to handle redemption: {e}");
+                failures.push(format!("redemption: {e}"));
+                RedemptionOutcome::Unhandled
+            });
+            let matched = matched.unwrap_or_else(|e| {
+                println!("Failed to run pipeline for {event_type}: {e}");
+                failures.push(format!("pipeline: {e}"));
+                0
+            });
+
+            let scenario = match handled {
+                RedemptionOutcome::Handled { scenario } => scenario,
+                RedemptionOutcome::Unhandled if matched > 0 => None,
+                RedemptionOutcome::Unhandled if !failures.is_empty() => {
+                    return Err(anyhow!(failures.join("; ")));
+                }
+                RedemptionOutcome::Unhandled => {
+                    println!("Invalid notification: nothing handles this {event_type}");
+                    return Err(anyhow!("Nothing handles this {event_type}"));
+                }
+            };
+            Ok(HandleOutcome::NotificationProcessed {
+                scenario,
+                message_id: msg_id.to_string(),
+                failures,
+            })
+        }
 
-                if event.broadcaster_user_id() != config.broadcaster_user_id {
-                    println!(
-                        "Invalid notification: unknown broadcaster user id {}",
-                        event.broadcaster_user_id(),
-                    );
-                    return Err(anyhow!("Unknown notification"));
+        /// Passes the redemption to the handler registered for its reward, returning the
+        /// scenario it posted.
+        async fn handle_redemption(
+            &self,
+            payload: &str,
+            headers: &HeaderMap,
+            msg_id: &str,
+            config: &AppConfig,
+        ) -> Result<RedemptionOutcome> {
+            let mut event = match serde_json::from_str::<RewardRedeemed>(payload) {
+                Ok(s) => s,
+                Err(e) => {
+                    println!("Failed to deserialize event to RewardRedeemed type: {e}");
+                    return Err(anyhow!("{e}"));
                 }
+            };
 
-                let msg_id = headers
-                    .get(EventsubHeader::MessageId.as_ref())
-                    .expect("MessageId should be sent by Twitch")
-                    .to_str()?;
-
-                match self.handlers.get(event.reward_id()) {
-                    Some(h) => h.handle(msg_id.to_string(), &event, config).await,
-                    None => {
-                        println!(
-                            "Invalid notification: unknown reward id {}",
-                            event.reward_id(),
-                        );
-                        Err(anyhow!("Unknown notification"))
-                    }
+            event.sent_at = Self::message_timestamp(headers);
+
+            match self.handlers.get(event.reward_id()) {
+                Some(h) => {
+                    let scenario = audit::with_event_id(
+                        msg_id.to_string(),
+                        h.handle(msg_id.to_string(), &event, config),
+                    )
+                    .await?;
+                    Ok(RedemptionOutcome::Handled { scenario })
+                }
+                None => {
+                    println!("No handler for reward id {}", event.reward_id());
+                    Ok(RedemptionOutcome::Unhandled)
                 }
-            } else {
-                Err(anyhow!(
-                    "Missing {} header",
-                    EventsubHeader::SubscriptionType.as_ref()
-                ))
             }
         }
 
+        /// Answers an EventSub request, returning the response for Twitch along with what
+        /// was done. The signature is checked against the body exactly as it arrived, before
+        /// anything reads it.
         pub async fn handle(
             &self,
-            request: String,
+            request: &[u8],
             headers: &HeaderMap,
             config: &AppConfig,
-        ) -> Result<Response<Body>> {
+        ) -> Result<(Response<Body>, HandleOutcome)> {
             // bail early if we cannot verify that the event is from twitch
-            match EventHandler::verify(&request, headers, config) {
-                Ok(_) => (),
+            match self.verify_request(request, headers, config).await {
+                Ok(_) => self.verified.store(true, Ordering::Relaxed),
                 Err(e) => {
                     eprintln!("Unverified event. Error: {e}");
                     let resp = Response::builder()
@@ -132,7 +544,12 @@ pub mod event_handler {
                         .body(Body::Empty)
                         .map_err(Box::new)?;
 
-                    return Ok(resp);
+                    return Ok((
+                        resp,
+                        HandleOutcome::Unverified {
+                            reason: e.to_string(),
+                        },
+                    ));
                 }
             }
 
@@ -147,62 +564,124 @@ pub mod event_handler {
                 },
                 None => return Err(anyhow!("Missing MessageType header")),
             };
+            let request = std::str::from_utf8(request).context("EventSub body isn't UTF-8")?;
+            let adapted;
+            let request = match config.twitch_cli_events
+                && twitch_cli::is_cli_event(request.as_bytes())
+            {
+                true => {
+                    adapted =
+                        twitch_cli::adapt(request, config, |id| self.handlers.contains_key(id))?;
+                    &adapted
+                }
+                false => request,
+            };
 
             let message_type = match MessageType::from_str(message_type_val) {
                 Ok(s) => s,
                 Err(_) => return Err(anyhow!("Invalid MessageType received")),
             };
+            if let Some(request_id) = self.request_id() {
+                let message_id = headers
+                    .get(EventsubHeader::MessageId.as_ref())
+                    .and_then(|h| h.to_str().ok())
+                    .unwrap_or_default();
+                println!("Handling {message_type_val} {message_id} in Lambda request {request_id}");
+            }
 
-            let resp: Response<Body> = match message_type {
+            let resp = match message_type {
                 MessageType::WebhookCallbackVerification => {
-                    if let Ok(challenge) = EventHandler::handle_challenge(&request, headers, config)
-                    {
-                        println!("Responding to challenge request with: {challenge}");
-
-                        Response::builder()
-                            .status(StatusCode::OK)
-                            .header(CONTENT_TYPE, "text/plain")
-                            .body(Body::from(challenge))
-                            .map_err(Box::new)?
-                    } else {
-                        Response::builder()
-                            .status(StatusCode::BAD_REQUEST)
-                            .body(Body::Empty)
-                            .map_err(Box::new)?
+                    match self.cached_challenge(request, headers, config) {
+                        Ok(challenge) => {
+                            println!("Responding to challenge request with: {challenge}");
+
+                            (
+                                Response::builder()
+                                    .status(StatusCode::OK)
+                                    .header(CONTENT_TYPE, "text/plain")
+                                    .body(Body::from(challenge))
+                                    .map_err(Box::new)?,
+                                HandleOutcome::ChallengeAnswered,
+                            )
+                        }
+                        Err(e) => {
+                            println!("Not answering challenge request: {e}");
+
+                            (
+                                Response::builder()
+                                    .status(StatusCode::BAD_REQUEST)
+                                    .body(Body::Empty)
+                                    .map_err(Box::new)?,
+                                HandleOutcome::ChallengeRejected {
+                                    reason: e.to_string(),
+                                },
+                            )
+                        }
                     }
                 }
 
                 MessageType::Notification => {
-                    if self
-                        .handle_notification(&request, headers, config)
-                        .await
-                        .is_ok()
-                    {
-                        Response::builder()
-                            .status(StatusCode::NO_CONTENT)
-                            .body(Body::Empty)
-                            .map_err(Box::new)?
-                    } else {
-                        Response::builder()
-                            .status(StatusCode::BAD_REQUEST)
-                            .body(Body::Empty)
-                            .map_err(Box::new)?
+                    match self.handle_notification(request, headers, config).await {
+                        Ok(outcome) => (
+                            Response::builder()
+                                .status(StatusCode::NO_CONTENT)
+                                .body(Body::Empty)
+                                .map_err(Box::new)?,
+                            outcome,
+                        ),
+                        Err(e) => (
+                            Response::builder()
+                                .status(StatusCode::BAD_REQUEST)
+                                .body(Body::Empty)
+                                .map_err(Box::new)?,
+                            HandleOutcome::NotificationSkipped {
+                                reason: e.to_string(),
+                            },
+                        ),
                     }
                 }
                 MessageType::Revocation => {
-                    EventHandler::handle_revocation(&request, headers, config);
+                    let reason = self.handle_revocation(request, config).await;
 
-                    Response::builder()
-                        .status(StatusCode::NO_CONTENT)
-                        .body(Body::Empty)
-                        .map_err(Box::new)?
+                    (
+                        Response::builder()
+                            .status(StatusCode::NO_CONTENT)
+                            .body(Body::Empty)
+                            .map_err(Box::new)?,
+                        HandleOutcome::Revoked { reason },
+                    )
                 }
             };
 
             Ok(resp)
         }
 
-        fn verify(payload: &str, headers: &HeaderMap, config: &AppConfig) -> Result<()> {
+        /// When Twitch sent the message, from the `Twitch-Eventsub-Message-Timestamp` header.
+        pub(crate) fn message_timestamp(headers: &HeaderMap) -> Option<DateTime<Utc>> {
+            headers
+                .get(EventsubHeader::MessageTimestamp.as_ref())
+                .and_then(|h| h.to_str().ok())
+                .and_then(|h| DateTime::parse_from_rfc3339(h).ok())
+                .map(|t| t.with_timezone(&Utc))
+        }
+
+        pub(crate) fn verify(
+            payload: &[u8],
+            headers: &HeaderMap,
+            config: &AppConfig,
+        ) -> Result<()> {
+            Self::verify_with_secret(
+                payload,
+                headers,
+                config.twitch_eventsub_subscription_secret.as_bytes(),
+            )
+        }
+
+        pub(crate) fn verify_with_secret(
+            payload: &[u8],
+            headers: &HeaderMap,
+            key: &[u8],
+        ) -> Result<()> {
             if let (Some(message_id), Some(timestamp), Some(signature_val)) = (
                 headers.get(EventsubHeader::MessageId.as_ref()),
                 headers.get(EventsubHeader::MessageTimestamp.as_ref()),
@@ -213,11 +692,10 @@ pub mod event_handler {
                     timestamp.to_str(),
                     signature_val.to_str(),
                 ) {
-                    let input = format!("{}{}{}", message_id_val, timestamp_val, payload);
-
-                    let key = config.twitch_eventsub_subscription_secret.as_bytes();
                     let mut hmac = HmacSha256::new_from_slice(key)?;
-                    hmac.update(input.as_bytes());
+                    hmac.update(message_id_val.as_bytes());
+                    hmac.update(timestamp_val.as_bytes());
+                    hmac.update(payload);
 
                     let signature = match String::from(signature_val).strip_prefix("sha256=") {
                         Some(s) => hex::decode(s)?,
@@ -258,6 +736,7 @@ pub mod event_handler {
     }
 
     #[cfg(test)]
+    #[allow(clippy::to_string_in_format_args)]
     mod tests {
         use anyhow::Result;
         use axum::http::{HeaderMap, Request};
@@ -265,25 +744,81 @@ pub mod event_handler {
         use dotenvy::dotenv;
         use pretty_assertions::assert_eq;
         use reqwest::header::CONTENT_TYPE;
-        use std::path::PathBuf;
+        use std::{path::PathBuf, sync::Arc};
 
+        use async_trait::async_trait;
         use hmac::{Hmac, Mac};
         use lambda_http::{Body, Response};
         use mockall::{mock, predicate};
         use reqwest::StatusCode;
         use sha2::Sha256;
 
+        use crate::action::{ActionScheduler, QueuedAction};
+        use crate::cache::TtlCache;
         use crate::client::StreamelementsCaller;
         use crate::config::AppConfig;
-        use crate::handler::event_handler::{self, EventHandler, HmacSha256};
+        use crate::eventsub_secrets::EventsubSecretStore;
+        use crate::handler::event_handler::{self, EventHandler, HandleOutcome, HmacSha256};
+        use crate::probe;
+        use crate::reward::{EventPipeline, RewardHandler};
         use crate::robochick::twitch::{MessageComponents, Scenario};
+        use crate::secrets::SecretStore;
         use crate::types::twitch;
+        use aws_sdk_secretsmanager::{
+            operation::get_secret_value::{GetSecretValueError, GetSecretValueOutput},
+            types::error::ResourceNotFoundException,
+        };
+        use aws_smithy_mocks::{RuleMode, mock as mock_rule, mock_client};
 
         mock! {
             pub Caller {}
 
+            #[async_trait]
             impl StreamelementsCaller for Caller {
                 async fn say(&self, msg: &str, config: &AppConfig) -> Result<String>;
+                async fn award_points(&self, user_login: &str, amount: i64, config: &AppConfig) -> Result<()>;
+            }
+        }
+
+        mock! {
+            pub Pipeline {}
+
+            #[async_trait]
+            impl EventPipeline for Pipeline {
+                async fn run(
+                    &self,
+                    event_type: &str,
+                    event: &serde_json::Value,
+                    config: &AppConfig,
+                ) -> Result<usize>;
+            }
+        }
+
+        mock! {
+            pub Handler {}
+
+            #[async_trait]
+            impl RewardHandler for Handler {
+                async fn handle(
+                    &self,
+                    msg_id: String,
+                    redeem: &twitch::RewardRedeemed,
+                    config: &AppConfig,
+                ) -> Result<Option<String>>;
+            }
+        }
+
+        mock! {
+            pub Scheduler {}
+
+            #[async_trait]
+            impl ActionScheduler for Scheduler {
+                async fn schedule(
+                    &self,
+                    action: QueuedAction,
+                    delay_secs: u64,
+                    config: &AppConfig,
+                ) -> Result<()>;
             }
         }
 
@@ -320,7 +855,7 @@ pub mod event_handler {
             let mock_caller = MockCaller::new();
             let event_handler = EventHandler::default();
 
-            let result = EventHandler::verify(payload, &headers, &config);
+            let result = EventHandler::verify(payload.as_bytes(), &headers, &config);
 
             assert!(result.is_ok());
             Ok(())
@@ -349,7 +884,7 @@ pub mod event_handler {
             let mock_caller = MockCaller::new();
             let event_handler = EventHandler::default();
 
-            let result = EventHandler::verify(payload, &headers_without_msg_id, &config);
+            let result = EventHandler::verify(payload.as_bytes(), &headers_without_msg_id, &config);
 
             assert!(result.is_err());
             Ok(())
@@ -383,12 +918,230 @@ pub mod event_handler {
             let mock_caller = MockCaller::new();
             let event_handler = EventHandler::default();
 
-            let result = EventHandler::verify(payload, &headers, &config);
+            let result = EventHandler::verify(payload.as_bytes(), &headers, &config);
 
             assert!(result.is_err());
             Ok(())
         }
 
+        #[test]
+        fn verify_checks_body_bytes_as_sent() -> Result<()> {
+            dotenvy::from_filename(".env.test")?;
+            let config = AppConfig::from_env();
+            let message_id = "message-1";
+            let timestamp = "2025-09-14T00:00:00.123456789";
+            let payload = "{\"user_name\": \"Cooler_Üser\"}\r\n";
+
+            let input = format!("{}{}{}", message_id, timestamp, payload);
+            let signature = generate_hmac(&input, &config.twitch_eventsub_subscription_secret)?;
+
+            let mut headers = HeaderMap::new();
+            headers.append(
+                twitch::EventsubHeader::MessageId.as_ref(),
+                message_id.parse().unwrap(),
+            );
+            headers.append(
+                twitch::EventsubHeader::MessageTimestamp.as_ref(),
+                timestamp.parse().unwrap(),
+            );
+            headers.append(
+                twitch::EventsubHeader::MessageSignature.as_ref(),
+                signature.parse().unwrap(),
+            );
+
+            assert!(EventHandler::verify(payload.as_bytes(), &headers, &config).is_ok());
+            assert!(
+                EventHandler::verify(payload.trim_end().as_bytes(), &headers, &config).is_err()
+            );
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn handle_verifies_with_the_subscriptions_own_secret() -> Result<()> {
+            dotenvy::from_filename(".env.test")?;
+            let config = AppConfig::from_env();
+            let payload =
+                r#"{"subscription": {"id": "sub-1", "type": "stream.online"}, "event": {}}"#;
+            let rule = mock_rule!(aws_sdk_secretsmanager::Client::get_secret_value)
+                .match_requests(|r| r.secret_id() == Some("eventsub/sub-1"))
+                .then_output(|| {
+                    GetSecretValueOutput::builder()
+                        .secret_string("sub-1-secret")
+                        .build()
+                });
+            let secrets = mock_client!(aws_sdk_secretsmanager, RuleMode::MatchAny, [&rule]);
+            let mut event_handler = EventHandler::default();
+            event_handler.set_eventsub_secrets(Arc::new(EventsubSecretStore::new(
+                Arc::new(SecretStore::new(secrets)),
+                "eventsub/".into(),
+            )));
+
+            let signed_with = |secret: &str| -> Result<HeaderMap> {
+                let mut headers = HeaderMap::new();
+                headers.append(
+                    twitch::EventsubHeader::MessageId.as_ref(),
+                    "message-1".parse()?,
+                );
+                headers.append(
+                    twitch::EventsubHeader::MessageTimestamp.as_ref(),
+                    "2025-09-14T00:00:00Z".parse()?,
+                );
+                headers.append(
+                    twitch::EventsubHeader::MessageType.as_ref(),
+                    "notification".parse()?,
+                );
+                let signature =
+                    generate_hmac(&format!("message-12025-09-14T00:00:00Z{payload}"), secret)?;
+                headers.append(
+                    twitch::EventsubHeader::MessageSignature.as_ref(),
+                    signature.parse()?,
+                );
+                Ok(headers)
+            };
+
+            let (_, own) = event_handler
+                .handle(payload.as_bytes(), &signed_with("sub-1-secret")?, &config)
+                .await?;
+            let (resp, shared) = event_handler
+                .handle(
+                    payload.as_bytes(),
+                    &signed_with(&config.twitch_eventsub_subscription_secret)?,
+                    &config,
+                )
+                .await?;
+
+            assert!(!matches!(own, HandleOutcome::Unverified { .. }));
+            assert!(matches!(shared, HandleOutcome::Unverified { .. }));
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn shared_secret_only_verifies_legacy_subscriptions() -> Result<()> {
+            dotenvy::from_filename(".env.test")?;
+            let mut config = AppConfig::from_env();
+            config.eventsub_legacy_subscription_ids = vec!["sub-old".into()];
+            let rule =
+                mock_rule!(aws_sdk_secretsmanager::Client::get_secret_value).then_error(|| {
+                    GetSecretValueError::ResourceNotFoundException(
+                        ResourceNotFoundException::builder().build(),
+                    )
+                });
+            let secrets = mock_client!(aws_sdk_secretsmanager, RuleMode::MatchAny, [&rule]);
+            let mut event_handler = EventHandler::default();
+            event_handler.set_eventsub_secrets(Arc::new(EventsubSecretStore::new(
+                Arc::new(SecretStore::new(secrets)),
+                "eventsub/".into(),
+            )));
+
+            let verified = |id: &str| {
+                let payload =
+                    format!(r#"{{"subscription": {{"id": "{id}", "type": "stream.online"}}}}"#);
+                let mut headers = HeaderMap::new();
+                headers.append(
+                    twitch::EventsubHeader::MessageId.as_ref(),
+                    "message-1".parse().unwrap(),
+                );
+                headers.append(
+                    twitch::EventsubHeader::MessageTimestamp.as_ref(),
+                    "2025-09-14T00:00:00Z".parse().unwrap(),
+                );
+                let signature = generate_hmac(
+                    &format!("message-12025-09-14T00:00:00Z{payload}"),
+                    &config.twitch_eventsub_subscription_secret,
+                )
+                .unwrap();
+                headers.append(
+                    twitch::EventsubHeader::MessageSignature.as_ref(),
+                    signature.parse().unwrap(),
+                );
+                let event_handler = &event_handler;
+                let config = &config;
+                async move {
+                    event_handler
+                        .is_verified(payload.as_bytes(), &headers, config)
+                        .await
+                }
+            };
+
+            assert!(verified("sub-old").await);
+            assert!(!verified("made-up").await);
+            assert_eq!(rule.num_calls(), 1);
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn handle_accepts_twitch_cli_events_when_enabled() -> Result<()> {
+            dotenvy::from_filename(".env.test")?;
+            let mut config = AppConfig::from_env();
+            let payload = std::fs::read_to_string("resources/tests/twitch_cli_redemption.json")?;
+            // Header names as `twitch event trigger` sends them.
+            let timestamp = "2026-10-17T09:12:44.418213902Z";
+            let signature = generate_hmac(
+                &format!("cli-message-1{timestamp}{payload}"),
+                &config.twitch_eventsub_subscription_secret,
+            )?;
+            let mut headers = HeaderMap::new();
+            for (name, value) in [
+                ("Twitch-Eventsub-Message-Id", "cli-message-1"),
+                ("Twitch-Eventsub-Message-Retry", "0"),
+                ("Twitch-Eventsub-Message-Type", "notification"),
+                ("Twitch-Eventsub-Message-Signature", signature.as_str()),
+                ("Twitch-Eventsub-Message-Timestamp", timestamp),
+                (
+                    "Twitch-Eventsub-Subscription-Type",
+                    "channel.channel_points_custom_reward_redemption.add",
+                ),
+                ("Twitch-Eventsub-Subscription-Version", "1"),
+            ] {
+                headers.append(name, value.parse()?);
+            }
+            let rule =
+                mock_rule!(aws_sdk_secretsmanager::Client::get_secret_value).then_error(|| {
+                    GetSecretValueError::ResourceNotFoundException(
+                        ResourceNotFoundException::builder().build(),
+                    )
+                });
+            let secrets = mock_client!(aws_sdk_secretsmanager, RuleMode::MatchAny, [&rule]);
+            let mut handler = MockHandler::new();
+            handler
+                .expect_handle()
+                .withf(|_, redeem, _| {
+                    redeem.broadcaster_user_id() == "1337"
+                        && redeem.user_input() == "Test Input From CLI"
+                })
+                .return_once(|_, _, _| Ok(Some("cracker-trip".into())))
+                .once();
+            let mut event_handler = EventHandler::default();
+            event_handler.register(config.feed_mods_rewards_id.clone(), handler);
+            event_handler.set_eventsub_secrets(Arc::new(EventsubSecretStore::new(
+                Arc::new(SecretStore::new(secrets)),
+                "eventsub/".into(),
+            )));
+
+            config.twitch_cli_events = true;
+            let (resp, handled) = event_handler
+                .handle(payload.as_bytes(), &headers, &config)
+                .await?;
+            assert_eq!(rule.num_calls(), 0);
+            config.twitch_cli_events = false;
+            let (_, rejected) = event_handler
+                .handle(payload.as_bytes(), &headers, &config)
+                .await?;
+
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+            assert_eq!(
+                handled,
+                HandleOutcome::NotificationProcessed {
+                    scenario: Some("cracker-trip".into()),
+                    message_id: "cli-message-1".into(),
+                    failures: vec![],
+                }
+            );
+            assert!(matches!(rejected, HandleOutcome::Unverified { .. }));
+            Ok(())
+        }
+
         #[tokio::test]
         async fn handle_returns_challenge_string_in_plaintext() -> Result<()> {
             dotenvy::from_filename(".env.test")?;
@@ -434,10 +1187,11 @@ pub mod event_handler {
             let mock_caller = MockCaller::new();
             let event_handler = EventHandler::default();
 
-            let response: Response<Body> = event_handler
-                .handle(payload.to_string(), &headers, &config)
+            let (response, outcome) = event_handler
+                .handle(payload.as_bytes(), &headers, &config)
                 .await?;
 
+            assert_eq!(outcome, HandleOutcome::ChallengeAnswered);
             assert_eq!(StatusCode::OK, response.status());
             assert_eq!("text/plain", response.headers().get(CONTENT_TYPE).unwrap());
 
@@ -491,11 +1245,17 @@ pub mod event_handler {
             let mock_caller = MockCaller::new();
             let event_handler = EventHandler::default();
 
-            let response: Response<Body> = event_handler
-                .handle(payload.to_string(), &headers, &config)
+            let (response, outcome) = event_handler
+                .handle(payload.as_bytes(), &headers, &config)
                 .await?;
 
             assert_eq!(StatusCode::NO_CONTENT, response.status());
+            assert_eq!(
+                outcome,
+                HandleOutcome::Revoked {
+                    reason: "authorization_revoked".into()
+                }
+            );
             Ok(())
         }
 
@@ -507,5 +1267,305 @@ pub mod event_handler {
             let encoded_hmac = hex::encode(hmac.into_bytes());
             Ok(format!("sha256={}", &encoded_hmac))
         }
+
+        #[tokio::test]
+        async fn revocation_after_failed_notifications_queues_resubscribe() -> Result<()> {
+            dotenvy::from_filename(".env.test")?;
+            let config = AppConfig::from_env();
+
+            let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            payload_path.push("resources/tests/subscription_revoked.json");
+            let payload = std::fs::read_to_string(payload_path)?
+                .replace("authorization_revoked", "notification_failures_exceeded");
+
+            let mut scheduler = MockScheduler::new();
+            scheduler
+                .expect_schedule()
+                .withf(|action, delay, _| {
+                    *delay == 0
+                        && matches!(action, QueuedAction::Resubscribe(r)
+                            if r.r#type == "channel.follow"
+                                && r.callback == "https://example.com/webhooks/callback")
+                })
+                .returning(|_, _, _| Ok(()))
+                .once();
+
+            let mut event_handler = EventHandler::default();
+            event_handler.set_actions(Arc::new(scheduler));
+
+            event_handler.handle_revocation(&payload, &config).await;
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn revocation_for_revoked_authorization_does_not_resubscribe() -> Result<()> {
+            dotenvy::from_filename(".env.test")?;
+            let config = AppConfig::from_env();
+
+            let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            payload_path.push("resources/tests/subscription_revoked.json");
+            let payload = std::fs::read_to_string(payload_path)?;
+
+            let mut scheduler = MockScheduler::new();
+            scheduler.expect_schedule().never();
+
+            let mut event_handler = EventHandler::default();
+            event_handler.set_actions(Arc::new(scheduler));
+
+            event_handler.handle_revocation(&payload, &config).await;
+            Ok(())
+        }
+
+        #[test]
+        fn cached_challenge_answers_retries_from_cache() -> Result<()> {
+            dotenvy::from_filename(".env.test")?;
+            let config = AppConfig::from_env();
+
+            let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            payload_path.push("resources/tests/challenge_request.json");
+            let payload = std::fs::read_to_string(payload_path)?;
+
+            let mut headers = HeaderMap::new();
+            headers.append(
+                twitch::EventsubHeader::MessageId.as_ref(),
+                "message-1".parse().unwrap(),
+            );
+
+            let mut event_handler = EventHandler::default();
+            event_handler
+                .set_challenge_cache(Arc::new(TtlCache::new(time::Duration::from_secs(60))));
+
+            let first = event_handler.cached_challenge(&payload, &headers, &config)?;
+            let retry = event_handler.cached_challenge("not even json", &headers, &config)?;
+
+            assert_eq!(first, "pogchamp-kappa-360noscope-vohiyo");
+            assert_eq!(retry, first);
+            Ok(())
+        }
+
+        #[test]
+        fn handle_challenge_rejects_unknown_subscription_type() -> Result<()> {
+            dotenvy::from_filename(".env.test")?;
+            let config = AppConfig::from_env();
+
+            let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            payload_path.push("resources/tests/challenge_request.json");
+            let payload = std::fs::read_to_string(payload_path)?.replace(
+                "channel.channel_points_custom_reward_redemption.add",
+                "channel.ban",
+            );
+
+            let result = EventHandler::handle_challenge(&payload, &HeaderMap::new(), &config);
+
+            assert!(result.is_err());
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn handle_notification_runs_pipeline_for_other_event_types() -> Result<()> {
+            dotenvy::from_filename(".env.test")?;
+            let config = AppConfig::from_env();
+
+            let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            payload_path.push("resources/tests/channel_cheer_event.json");
+            let payload = std::fs::read_to_string(payload_path)?;
+
+            let mut headers = HeaderMap::new();
+            headers.append(
+                twitch::EventsubHeader::MessageId.as_ref(),
+                "message-1".parse().unwrap(),
+            );
+            headers.append(
+                twitch::EventsubHeader::SubscriptionType.as_ref(),
+                "channel.cheer".parse().unwrap(),
+            );
+
+            let mut pipeline = MockPipeline::new();
+            pipeline
+                .expect_run()
+                .withf(|event_type, event, _| {
+                    event_type == "channel.cheer" && event["bits"] == 1000
+                })
+                .return_once(|_, _, _| Ok(1))
+                .once();
+            let mut event_handler = EventHandler::default();
+            event_handler.set_pipeline(pipeline);
+
+            assert_eq!(
+                event_handler
+                    .handle_notification(&payload, &headers, &config)
+                    .await?,
+                HandleOutcome::NotificationProcessed {
+                    scenario: None,
+                    message_id: "message-1".into(),
+                    failures: vec![],
+                }
+            );
+
+            let mut unmatched = MockPipeline::new();
+            unmatched.expect_run().return_once(|_, _, _| Ok(0));
+            let mut event_handler = EventHandler::default();
+            event_handler.set_pipeline(unmatched);
+
+            assert!(
+                event_handler
+                    .handle_notification(&payload, &headers, &config)
+                    .await
+                    .is_err()
+            );
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn probes_skip_the_pipeline() -> Result<()> {
+            dotenvy::from_filename(".env.test")?;
+            let config = AppConfig::from_env();
+            let (headers, payload) = probe::notification(&config, chrono::Utc::now())?;
+
+            let mut handler = MockHandler::new();
+            handler
+                .expect_handle()
+                .returning(|_, _, _| match probe::active() {
+                    true => Ok(Some("probed".into())),
+                    false => Err(anyhow::anyhow!("Not handled as a probe")),
+                })
+                .once();
+            let mut pipeline = MockPipeline::new();
+            pipeline.expect_run().never();
+            let mut event_handler = EventHandler::default();
+            event_handler.register(config.feed_mods_rewards_id.clone(), handler);
+            event_handler.set_pipeline(pipeline);
+
+            let outcome = event_handler
+                .handle_notification(&payload, &headers, &config)
+                .await?;
+            assert!(matches!(
+                outcome,
+                HandleOutcome::NotificationProcessed { scenario: Some(s), .. } if s == "probed"
+            ));
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn handle_notification_reports_failed_reward_alongside_pipeline() -> Result<()> {
+            dotenvy::from_filename(".env.test")?;
+            let config = AppConfig::from_env();
+
+            let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            payload_path.push("resources/tests/reward_redemption_event.json");
+            let payload = std::fs::read_to_string(payload_path)?;
+
+            let mut headers = HeaderMap::new();
+            headers.append(
+                twitch::EventsubHeader::MessageId.as_ref(),
+                "message-1".parse().unwrap(),
+            );
+            headers.append(
+                twitch::EventsubHeader::SubscriptionType.as_ref(),
+                "channel.channel_points_custom_reward_redemption.add"
+                    .parse()
+                    .unwrap(),
+            );
+
+            let mut handler = MockHandler::new();
+            handler
+                .expect_handle()
+                .return_once(|_, _, _| Err(anyhow::anyhow!("Dynamo is down")));
+            let mut pipeline = MockPipeline::new();
+            pipeline.expect_run().return_once(|_, _, _| Ok(1));
+            let mut event_handler = EventHandler::default();
+            event_handler.register(config.feed_mods_rewards_id.clone(), handler);
+            event_handler.set_pipeline(pipeline);
+
+            assert_eq!(
+                event_handler
+                    .handle_notification(&payload, &headers, &config)
+                    .await?,
+                HandleOutcome::NotificationProcessed {
+                    scenario: None,
+                    message_id: "message-1".into(),
+                    failures: vec!["redemption: Dynamo is down".into()],
+                }
+            );
+            Ok(())
+        }
+
+        #[test]
+        fn lambda_context_gives_the_request_id_and_time_left() {
+            let now = chrono::Utc::now();
+            let mut event_handler = EventHandler::default();
+            assert_eq!(event_handler.request_id(), None);
+            assert_eq!(event_handler.time_left(now), None);
+
+            let mut context = lambda_http::Context::default();
+            context.request_id = "aws-request-1".into();
+            context.deadline = (now.timestamp_millis() + 5000) as u64;
+            event_handler.set_lambda_context(context);
+
+            assert_eq!(event_handler.request_id(), Some("aws-request-1"));
+            assert_eq!(
+                event_handler.time_left(now),
+                Some(time::Duration::from_millis(4000))
+            );
+        }
+
+        #[tokio::test]
+        async fn previous_secret_verifies_until_its_window_ends() -> Result<()> {
+            dotenvy::from_filename(".env.test")?;
+            let now = chrono::Utc::now();
+            let config = AppConfig {
+                twitch_eventsub_previous_secret: Some("old-secret".into()),
+                twitch_eventsub_previous_secret_until: Some(now + chrono::Duration::hours(1)),
+                ..AppConfig::from_env()
+            };
+            let expired = AppConfig {
+                twitch_eventsub_previous_secret_until: Some(now - chrono::Duration::hours(1)),
+                ..config.clone()
+            };
+            let timestamp = "2025-09-14T00:00:00.123456789";
+            let payload = r#"{"message":"Hello, World!"}"#;
+            let signed_with = |secret: &str| -> Result<HeaderMap> {
+                let mut headers = HeaderMap::new();
+                headers.append(
+                    twitch::EventsubHeader::MessageId.as_ref(),
+                    "message-1".parse()?,
+                );
+                headers.append(
+                    twitch::EventsubHeader::MessageTimestamp.as_ref(),
+                    timestamp.parse()?,
+                );
+                headers.append(
+                    twitch::EventsubHeader::MessageSignature.as_ref(),
+                    generate_hmac(&format!("message-1{timestamp}{payload}"), secret)?.parse()?,
+                );
+                Ok(headers)
+            };
+            let event_handler = EventHandler::default();
+
+            let current = signed_with(&config.twitch_eventsub_subscription_secret)?;
+            let previous = signed_with("old-secret")?;
+            let other = signed_with("someone-elses-secret")?;
+            assert!(
+                event_handler
+                    .is_verified(payload.as_bytes(), &current, &config)
+                    .await
+            );
+            assert!(
+                event_handler
+                    .is_verified(payload.as_bytes(), &previous, &config)
+                    .await
+            );
+            assert!(
+                !event_handler
+                    .is_verified(payload.as_bytes(), &other, &config)
+                    .await
+            );
+            assert!(
+                !event_handler
+                    .is_verified(payload.as_bytes(), &previous, &expired)
+                    .await
+            );
+            Ok(())
+        }
     }
 }
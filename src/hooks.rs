@@ -0,0 +1,289 @@
+use anyhow::{Result, anyhow};
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use lambda_http::{Body, Response};
+use reqwest::StatusCode;
+use serde_json::Value;
+use sha2::Sha256;
+
+use crate::{
+    AppState, audit, auth,
+    client::{HelixCaller, StreamelementsCaller},
+    config::AppConfig,
+    donations::{KOFI_HOOK, KofiPayload},
+    notify::{Notifier, NotifyEvent},
+    reward::mod_feeder::ModFeed,
+    robochick::twitch::TemplateContext,
+};
+
+/// Header carrying `sha256=<hex HMAC-SHA256 of "{timestamp}.{body}">`, keyed with the hook's
+/// secret.
+pub const HOOK_SIGNATURE_HEADER: &str = "X-Hook-Signature";
+/// Unix time the call was signed at.
+pub const HOOK_TIMESTAMP_HEADER: &str = "X-Hook-Timestamp";
+
+/// How far a signature's timestamp may be from now. A signature is only accepted once, and
+/// remembered for twice this long.
+const MAX_HOOK_SKEW_SECS: u64 = 300;
+
+/// Checks the signature of the timestamp and body against the hook's secret, for a call made
+/// within a few minutes of `now`. Returns the signature, for the caller to accept only once.
+pub fn verify(body: &str, headers: &HeaderMap, secret: &str, now: DateTime<Utc>) -> Result<String> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|h| h.to_str().ok())
+            .ok_or(anyhow!("Missing {name} header"))
+    };
+    let timestamp: i64 = header(HOOK_TIMESTAMP_HEADER)?
+        .parse()
+        .map_err(|_| anyhow!("Invalid {HOOK_TIMESTAMP_HEADER} header"))?;
+    if now.timestamp().abs_diff(timestamp) > MAX_HOOK_SKEW_SECS {
+        return Err(anyhow!(
+            "Signature timestamp {timestamp} is too far from now"
+        ));
+    }
+    let given = header(HOOK_SIGNATURE_HEADER)?
+        .strip_prefix("sha256=")
+        .ok_or(anyhow!("Malformed {HOOK_SIGNATURE_HEADER} header"))?;
+
+    let mut hmac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())?;
+    hmac.update(format!("{timestamp}.").as_bytes());
+    hmac.update(body.as_bytes());
+    hmac.verify_slice(&hex::decode(given)?)
+        .map_err(|e| anyhow!("Signature verification failed: {e}"))?;
+    Ok(given.to_lowercase())
+}
+
+/// Template values from a hook's JSON body: each top-level string, number or bool as
+/// `{hook_<field>}`.
+pub fn hook_context(payload: &Value) -> TemplateContext {
+    let mut context = TemplateContext::default();
+    context.insert_fields("hook", payload);
+    context
+}
+
+/// `POST /hooks/{name}` - lets other tools trigger a post from the scenario group listing the
+/// hook in its `hooks`. `/hooks/kofi` takes Ko-fi's own payload instead and announces
+/// donations from the `donations` group.
+#[utoipa::path(post, path = "/hooks/{name}", tag = "hooks",
+    params(
+        ("name" = String, Path, description = "The hook, e.g. `kofi`"),
+        ("X-Hook-Timestamp" = Option<i64>, Header, description = "Unix time the call was signed at, except for Ko-fi"),
+        ("X-Hook-Signature" = Option<String>, Header, description = "`sha256=<hex HMAC-SHA256 of \"{timestamp}.{body}\">` with `HOOK_SECRET_<NAME>`, except for Ko-fi"),
+    ),
+    request_body(content = Object, description = "Any JSON object, or Ko-fi's form payload"),
+    responses(
+        (status = 200, description = "Ko-fi event handled or ignored"),
+        (status = 204, description = "Posted"),
+        (status = 400, description = "Invalid payload"),
+        (status = 403, description = "Unsigned, stale or replayed, or no secret for the hook"),
+        (status = 404, description = "No group lists the hook"),
+        (status = 502, description = "Posting failed"),
+    ),
+)]
+pub async fn hook_handler(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    body: String,
+) -> Response<Body> {
+    let name = name.to_lowercase();
+    let secret = match state.config.hook_secrets.get(&name) {
+        Some(s) => s,
+        None => {
+            println!("Rejected call to hook {name}: no secret configured");
+            return empty_response(StatusCode::FORBIDDEN);
+        }
+    };
+
+    let feed = state.mod_feed();
+    let event_id = format!("hook:{name}:{}", Utc::now().timestamp_millis());
+    match name.as_str() {
+        KOFI_HOOK => {
+            audit::with_event_id(
+                event_id,
+                kofi(
+                    &feed,
+                    &body,
+                    secret,
+                    state.notifier.as_deref(),
+                    &state.config,
+                ),
+            )
+            .await
+        }
+        _ => {
+            audit::with_event_id(
+                event_id,
+                generic(&feed, &name, &body, &headers, secret, &state.config),
+            )
+            .await
+        }
+    }
+}
+
+async fn generic<C: StreamelementsCaller + HelixCaller>(
+    feed: &ModFeed<C>,
+    name: &str,
+    body: &str,
+    headers: &HeaderMap,
+    secret: &str,
+    config: &AppConfig,
+) -> Response<Body> {
+    let signature = match verify(body, headers, secret, Utc::now()) {
+        Ok(s) => s,
+        Err(e) => {
+            println!("Rejected call to hook {name}: {e}");
+            return empty_response(StatusCode::FORBIDDEN);
+        }
+    };
+    let nonce = format!("hook#{name}#{signature}");
+    match feed
+        .state
+        .start_cooldown(&nonce, 2 * MAX_HOOK_SKEW_SECS)
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => {
+            println!("Rejected call to hook {name}: its signature was already used");
+            return empty_response(StatusCode::FORBIDDEN);
+        }
+        Err(e) => {
+            println!("Rejected call to hook {name}, couldn't check for a replay: {e}");
+            return empty_response(StatusCode::SERVICE_UNAVAILABLE);
+        }
+    }
+
+    let payload: Value = match serde_json::from_str(body) {
+        Ok(p) => p,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(format!("Invalid hook payload: {e}")))
+                .unwrap();
+        }
+    };
+
+    match feed.handle_hook(name, hook_context(&payload), config).await {
+        Ok(true) => empty_response(StatusCode::NO_CONTENT),
+        Ok(false) => {
+            println!("Hook {name} failed: no scenario group handles it");
+            empty_response(StatusCode::NOT_FOUND)
+        }
+        Err(e) => {
+            println!("Hook {name} failed: {e}");
+            empty_response(StatusCode::BAD_GATEWAY)
+        }
+    }
+}
+
+/// Ko-fi doesn't sign its webhooks, it sends the verification token from its settings in the
+/// payload, so that's what `HOOK_SECRET_KOFI` holds.
+async fn kofi<C: StreamelementsCaller + HelixCaller>(
+    feed: &ModFeed<C>,
+    body: &str,
+    verification_token: &str,
+    notifier: Option<&Notifier>,
+    config: &AppConfig,
+) -> Response<Body> {
+    let payload = match KofiPayload::parse(body) {
+        Ok(p) => p,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(e.to_string()))
+                .unwrap();
+        }
+    };
+
+    if !auth::secrets_match(&payload.verification_token, verification_token) {
+        println!("Rejected Ko-fi webhook with the wrong verification token");
+        return empty_response(StatusCode::FORBIDDEN);
+    }
+
+    // Ko-fi retries anything but a 200.
+    let Some(donation) = payload.donation() else {
+        println!("Ignoring Ko-fi {} from {}", payload.kind, payload.from_name);
+        return empty_response(StatusCode::OK);
+    };
+
+    if let Some(notifier) = notifier
+        && notifier
+            .donation_minimums
+            .reached(payload.amount.parse().unwrap_or(0.0), &payload.currency)
+    {
+        notifier
+            .notify(
+                NotifyEvent::Donation,
+                &format!("{} donated {} on Ko-fi", payload.from_name, donation.amount),
+            )
+            .await;
+    }
+
+    if let Err(e) = feed.handle_donation(&donation, config).await {
+        println!("Failed to announce Ko-fi donation: {e}");
+    }
+    empty_response(StatusCode::OK)
+}
+
+fn empty_response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::Empty)
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use axum::http::HeaderMap;
+    use chrono::{TimeDelta, Utc};
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    use crate::hooks::{HOOK_SIGNATURE_HEADER, HOOK_TIMESTAMP_HEADER, verify};
+
+    fn signed(body: &str, secret: &str, timestamp: i64) -> Result<HeaderMap> {
+        let mut hmac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())?;
+        hmac.update(format!("{timestamp}.{body}").as_bytes());
+        let mut headers = HeaderMap::new();
+        headers.insert(HOOK_TIMESTAMP_HEADER, timestamp.to_string().parse()?);
+        headers.insert(
+            HOOK_SIGNATURE_HEADER,
+            format!("sha256={}", hex::encode(hmac.finalize().into_bytes())).parse()?,
+        );
+        Ok(headers)
+    }
+
+    #[test]
+    fn verify_accepts_signed_body_only() -> Result<()> {
+        let body = r#"{"from_name":"Clucky","amount":"5.00"}"#;
+        let now = Utc::now();
+        let headers = signed(body, "kofi-secret", now.timestamp())?;
+
+        assert!(verify(body, &headers, "kofi-secret", now).is_ok());
+        assert!(verify(body, &headers, "other-secret", now).is_err());
+        assert!(verify(r#"{"amount":"500.00"}"#, &headers, "kofi-secret", now).is_err());
+        assert!(verify(body, &HeaderMap::new(), "kofi-secret", now).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn verify_rejects_stale_signatures() -> Result<()> {
+        let body = r#"{"from_name":"Clucky","amount":"5.00"}"#;
+        let now = Utc::now();
+        let headers = signed(body, "kofi-secret", now.timestamp())?;
+
+        assert!(verify(body, &headers, "kofi-secret", now + TimeDelta::minutes(4)).is_ok());
+        assert!(verify(body, &headers, "kofi-secret", now + TimeDelta::minutes(6)).is_err());
+
+        let overflowing = signed(body, "kofi-secret", i64::MIN)?;
+        assert!(verify(body, &overflowing, "kofi-secret", now).is_err());
+        Ok(())
+    }
+}
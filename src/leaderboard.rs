@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use axum::{extract::State, http::HeaderMap};
+use chrono::{DateTime, Utc};
+use lambda_http::{Body, Response};
+use reqwest::StatusCode;
+
+use crate::{
+    AppState,
+    etag::{self, Dataset},
+    reward::mod_feeder::load_message_components,
+    robochick::twitch::MessageComponents,
+    state::StateStore,
+    stats::{ScenarioCount, mod_fairness, month_key, recent_weeks, scenario_counts, week_key},
+};
+
+/// Weeks of scenarios shown, this one and the last.
+const RECENT_WEEKS: u64 = 2;
+
+/// Most scenarios listed.
+const TOP_SCENARIOS: usize = 10;
+
+const PAGE_KEY: &str = "/leaderboard";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeaderboardRow {
+    pub name: String,
+    pub month_wins: u64,
+    pub wins: u64,
+}
+
+/// What the public page shows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Leaderboard {
+    pub updated_at: DateTime<Utc>,
+    /// Current mods by wins this month, then all time.
+    pub mods: Vec<LeaderboardRow>,
+    /// Scenarios that fired in the last couple of weeks, most common first.
+    pub scenarios: Vec<ScenarioCount>,
+}
+
+impl Leaderboard {
+    /// Shows mods under their display names, by name in the mods list.
+    pub fn show_display_names(&mut self, display_names: &HashMap<String, String>) {
+        for row in &mut self.mods {
+            if let Some(shown) = display_names.get(&row.name) {
+                row.name = shown.clone();
+            }
+        }
+    }
+}
+
+pub async fn build(
+    state: &dyn StateStore,
+    components: Option<&MessageComponents>,
+    now: DateTime<Utc>,
+) -> Result<Leaderboard> {
+    let month_wins = state.month_win_counts(&month_key(now)).await?;
+    let mods = match components {
+        Some(components) => mod_fairness(state, components.get_mods(), now).await?,
+        None => vec![],
+    };
+    let mut mods: Vec<LeaderboardRow> = mods
+        .into_iter()
+        .map(|m| LeaderboardRow {
+            month_wins: month_wins.get(&m.name).copied().unwrap_or(0),
+            wins: m.wins,
+            name: m.name,
+        })
+        .collect();
+    mods.sort_by(|a, b| {
+        b.month_wins
+            .cmp(&a.month_wins)
+            .then(b.wins.cmp(&a.wins))
+            .then(a.name.cmp(&b.name))
+    });
+
+    let scenarios = scenario_counts(state, None, &recent_weeks(now, RECENT_WEEKS))
+        .await?
+        .into_iter()
+        .filter(|s| s.count > 0)
+        .take(TOP_SCENARIOS)
+        .collect();
+
+    Ok(Leaderboard {
+        updated_at: now,
+        mods,
+        scenarios,
+    })
+}
+
+/// The page as a self-contained HTML document.
+pub fn render(board: &Leaderboard) -> String {
+    let mods = match board.mods.is_empty() {
+        true => "<tr><td colspan=\"4\">No mods yet</td></tr>".to_string(),
+        false => board
+            .mods
+            .iter()
+            .enumerate()
+            .map(|(i, m)| {
+                format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    i + 1,
+                    escape(&m.name),
+                    m.month_wins,
+                    m.wins
+                )
+            })
+            .collect(),
+    };
+    let scenarios = match board.scenarios.is_empty() {
+        true => "<li>Nothing yet</li>".to_string(),
+        false => board
+            .scenarios
+            .iter()
+            .map(|s| format!("<li>{} ({}x)</li>", escape(&s.scenario), s.count))
+            .collect(),
+    };
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Fed mods leaderboard</title>
+<style>
+body {{ font-family: sans-serif; max-width: 40em; margin: 2em auto; padding: 0 1em; }}
+table {{ border-collapse: collapse; width: 100%; }}
+th, td {{ text-align: left; padding: 0.3em 0.6em; border-bottom: 1px solid #ddd; }}
+footer {{ color: #777; font-size: 0.8em; }}
+</style>
+</head>
+<body>
+<h1>Fed mods leaderboard</h1>
+<table>
+<thead><tr><th>#</th><th>Mod</th><th>This month</th><th>All time</th></tr></thead>
+<tbody>{mods}</tbody>
+</table>
+<h2>Recent scenarios</h2>
+<ol>{scenarios}</ol>
+<footer>Updated {updated}</footer>
+</body>
+</html>
+"#,
+        updated = board.updated_at.format("%Y-%m-%d %H:%M UTC"),
+    )
+}
+
+/// Escapes text for HTML element content and attribute values.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// `GET /leaderboard` - public, read-only page of the mods' wins and recent scenarios, e.g.
+/// for a link in the channel panels. Rendered at most once per `LEADERBOARD_CACHE_TTL_SECS`.
+#[utoipa::path(get, path = "/leaderboard", tag = "stats", responses(
+    (status = 200, description = "The leaderboard page", body = String, content_type = "text/html"),
+    (status = 304, description = "Unchanged since the `If-None-Match` ETag"),
+))]
+pub async fn leaderboard_handler(
+    State(app): State<AppState>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    let now = Utc::now();
+    let ttl_secs = app.config.leaderboard_cache_ttl_secs;
+    let cache_control = format!("public, max-age={ttl_secs}");
+    // Display names come from Twitch rather than the store, so the version also rolls over
+    // once per cache TTL to pick them up.
+    let window = (now.timestamp() as u64 / ttl_secs.max(1)).to_string();
+    let tag = match etag::version_etag(
+        app.state.as_ref(),
+        &app.config_file,
+        &[Dataset::Scenarios, Dataset::Wins],
+        &[PAGE_KEY, &window, &week_key(now), &month_key(now)],
+    )
+    .await
+    {
+        Ok(tag) => tag,
+        Err(e) => return build_error(e),
+    };
+    if let Some(response) = etag::revalidate(&headers, &tag, &cache_control) {
+        return response;
+    }
+
+    let page = match app.pages.get(&tag) {
+        Some(page) => page,
+        None => {
+            let components = load_message_components(app.state.as_ref(), &app.config_file)
+                .await
+                .ok();
+            match build(app.state.as_ref(), components.as_deref(), now).await {
+                Ok(mut board) => {
+                    if let Some(components) =
+                        components.as_ref().filter(|c| c.shows_display_names())
+                    {
+                        let names = app
+                            .users
+                            .display_names(&app.web_client, components.get_mods(), &app.config)
+                            .await;
+                        board.show_display_names(&names);
+                    }
+                    let page = render(&board);
+                    app.pages.insert(tag.clone(), page.clone());
+                    page
+                }
+                Err(e) => return build_error(e),
+            }
+        }
+    };
+
+    etag::tagged_response(&tag, "text/html; charset=utf-8", &cache_control, page)
+}
+
+fn build_error(e: anyhow::Error) -> Response<Body> {
+    println!("Failed to build the leaderboard: {e}");
+    Response::builder()
+        .status(StatusCode::INTERNAL_SERVER_ERROR)
+        .body(Body::Empty)
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use chrono::{TimeZone, Utc};
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        leaderboard::{LeaderboardRow, build, render},
+        robochick::twitch::MessageComponents,
+        state::{MemoryStore, StateStore},
+        stats::ScenarioCount,
+    };
+
+    #[tokio::test]
+    async fn build_ranks_mods_by_this_months_wins() -> Result<()> {
+        let now = Utc.with_ymd_and_hms(2026, 10, 16, 12, 0, 0).unwrap();
+        let store = MemoryStore::default();
+        store
+            .record_wins(&["John".into(), "John".into(), "Jane".into()])
+            .await?;
+        store.record_month_wins("2026-10", &["Jane".into()]).await?;
+        store.record_scenario("2026-W41", "cracker-trip").await?;
+        store.record_scenario("2026-W38", "guard-duty").await?;
+        let components = MessageComponents {
+            mods: vec!["Alex".into(), "Jane".into(), "John".into()],
+            ..Default::default()
+        };
+
+        let board = build(&store, Some(&components), now).await?;
+
+        let row = |name: &str, month_wins, wins| LeaderboardRow {
+            name: name.into(),
+            month_wins,
+            wins,
+        };
+        assert_eq!(
+            board.mods,
+            vec![row("Jane", 1, 1), row("John", 0, 2), row("Alex", 0, 0)]
+        );
+        assert_eq!(
+            board.scenarios,
+            vec![ScenarioCount {
+                scenario: "cracker-trip".into(),
+                count: 1
+            }]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn render_escapes_names() -> Result<()> {
+        let now = Utc.with_ymd_and_hms(2026, 10, 16, 12, 0, 0).unwrap();
+        let store = MemoryStore::default();
+        store
+            .record_scenario("2026-W42", "<script>alert('hi')</script>")
+            .await?;
+        let components = MessageComponents {
+            mods: vec!["Tom & Jerry".into()],
+            ..Default::default()
+        };
+
+        let page = render(&build(&store, Some(&components), now).await?);
+
+        assert!(page.contains("<td>Tom &amp; Jerry</td>"));
+        assert!(page.contains("<li>&lt;script&gt;alert(&#39;hi&#39;)&lt;/script&gt; (1x)</li>"));
+        assert!(page.contains("Updated 2026-10-16 12:00 UTC"));
+        Ok(())
+    }
+}
@@ -0,0 +1,1571 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use aws_config::{BehaviorVersion, meta::region::RegionProviderChain};
+use aws_sdk_dynamodb::Client;
+use axum::{
+    Extension, Router,
+    body::Bytes,
+    extract::{Query, Request, State},
+    http::HeaderMap,
+    middleware,
+    routing::{delete, get, post, put},
+};
+use chrono::Utc;
+use lambda_http::{Body, Error, Response};
+use reqwest::{StatusCode, Url};
+use tokio::sync::OnceCell;
+
+use crate::{
+    action::ActionScheduler,
+    admin_tokens::{AdminScope, AdminTokenStore},
+    batch::ChatBatcher,
+    cache::TtlCache,
+    client::{Caller, HelixCaller, WebClient},
+    config::AppConfig,
+    eventsub_secrets::EventsubSecretStore,
+    metrics::Unit,
+    notify::{Notifier, NotifyEvent},
+    reload::ConfigFile,
+    se_jwt::SeJwtStore,
+    secrets::SecretStore,
+    sink::BackendStatus,
+    state::{DynamoStore, MemoryStore, Retention, StateBackend, StateStore},
+    types::twitch::{EventsubHeader, Subscription},
+    users::UserCache,
+};
+
+#[cfg(feature = "redis")]
+use crate::state::RedisStore;
+#[cfg(feature = "sqlite")]
+use crate::state::SqliteStore;
+
+pub mod action;
+mod admin;
+mod admin_tokens;
+pub mod app;
+mod audit;
+mod auth;
+#[cfg(feature = "backups")]
+mod backup;
+mod batch;
+#[doc(hidden)]
+pub mod bench;
+mod bot;
+mod budget;
+mod burst;
+mod cache;
+mod channel;
+pub mod cli;
+pub mod client;
+mod commands;
+mod config_audit;
+mod config_diff;
+mod config_tests;
+mod convert;
+mod cron;
+mod db;
+mod deadline;
+mod decline;
+mod donations;
+mod env_template;
+mod etag;
+mod eventsub_secrets;
+mod experiment;
+mod expr;
+pub mod gateway;
+mod grammar;
+mod handler;
+mod hooks;
+mod leaderboard;
+mod locale;
+mod metrics;
+mod migrate;
+mod notify;
+mod offline;
+mod openapi;
+mod overlay;
+mod permissions;
+mod pipeline;
+mod probe;
+mod purge;
+mod quiet;
+mod ratelimit;
+mod recap;
+mod reload;
+mod reward;
+mod robochick;
+mod schedule;
+mod se_jwt;
+mod season;
+mod secrets;
+pub mod signing;
+mod simulate;
+mod sink;
+pub mod state;
+mod stats;
+mod stats_export;
+mod subscribe;
+mod text;
+mod trace;
+mod twitch_cli;
+mod types;
+mod users;
+mod variables;
+pub mod version;
+mod warm;
+#[cfg(feature = "youtube")]
+mod youtube;
+
+pub mod config {
+    use std::{collections::HashMap, env, str::FromStr};
+
+    use chrono::{DateTime, Utc};
+
+    use crate::env_template::{EnvVar, optional, required};
+
+    #[derive(Clone, PartialEq, Debug)]
+    pub struct AppConfig {
+        pub twitch_client_id: String,
+        pub twitch_client_secret: Option<String>,
+        pub twitch_eventsub_subscription_secret: String,
+        pub twitch_channel_id: String,
+        pub twitch_host: String,
+        pub se_jwt: Option<String>,
+        pub se_api_host: String,
+        pub feed_mods_rewards_id: String,
+        pub broadcaster_user_id: String,
+        pub redirect_uri: String,
+        pub message_components_config_path: String,
+        pub rubberduck_rewards_id: String,
+        pub duck_rewards_table_name: String,
+        pub twitch_api_host: String,
+        pub twitch_access_token: Option<String>,
+        pub chatters_cache_ttl_secs: u64,
+        /// How long whether the stream is live is cached for.
+        pub stream_state_cache_ttl_secs: u64,
+        /// How long the rendered `/leaderboard` page is cached for, here and by browsers.
+        pub leaderboard_cache_ttl_secs: u64,
+        pub action_queue_url: Option<String>,
+        pub state_table_name: Option<String>,
+        /// Table of the audit logs, the offline queue and the outbox, one item per entry sorted
+        /// by time. The state table's name with `-log` appended when unset.
+        pub state_log_table_name: Option<String>,
+        /// Where the state is kept: `dynamodb`, `redis`, `sqlite` or `memory`. Picked from
+        /// `STATE_TABLE_NAME` when unset.
+        pub state_backend: Option<crate::state::StateBackend>,
+        pub redis_url: Option<String>,
+        /// Goes in front of every Redis key, so several bots can share a server.
+        pub redis_key_prefix: String,
+        pub sqlite_path: String,
+        /// How long handled EventSub message ids are kept to recognize retries.
+        pub seen_message_retention_secs: u64,
+        /// How long each day's message and config audit log is kept.
+        pub audit_retention_days: u64,
+        /// How often expired rows are deleted from the SQLite file.
+        pub sqlite_cleanup_interval_secs: u64,
+        pub internal_api_token: Option<String>,
+        pub admin_api_token: Option<String>,
+        /// How callers of the `/admin` and `/internal` routes authenticate: a bearer token, an
+        /// HMAC signature or a client certificate.
+        pub admin_auth: crate::signing::RequestAuth,
+        pub internal_auth: crate::signing::RequestAuth,
+        pub admin_signing_secret: Option<String>,
+        pub internal_signing_secret: Option<String>,
+        /// SHA-256 fingerprints of the client certificates allowed, by name, from
+        /// `name=fingerprint` pairs separated by commas.
+        pub admin_client_certs: HashMap<String, String>,
+        pub internal_client_certs: HashMap<String, String>,
+        /// Header the TLS terminator passes the client certificate in.
+        pub client_cert_header: String,
+        pub backup_bucket: Option<String>,
+        pub action_queue_arn: Option<String>,
+        pub scheduler_role_arn: Option<String>,
+        pub scheduler_group_name: Option<String>,
+        /// User the bot sends chat messages as through Helix. Setting it makes Helix a fallback
+        /// for StreamElements.
+        pub helix_chat_sender_id: Option<String>,
+        /// Login of the bot StreamElements sends chat messages as, whose messages are ignored.
+        pub se_bot_login: String,
+        /// Prefix of the per-channel Secrets Manager secrets holding StreamElements JWTs. When
+        /// set, `SE_JWT` is ignored.
+        pub se_jwt_secret_prefix: Option<String>,
+        /// Prefix of the Secrets Manager secrets holding each EventSub subscription's own
+        /// secret. When set, new subscriptions get one instead of the shared secret.
+        pub eventsub_secret_prefix: Option<String>,
+        /// Subscriptions made before `EVENTSUB_SECRET_PREFIX` was set, which are still signed
+        /// with the shared secret. With the prefix set, no other subscription is.
+        pub eventsub_legacy_subscription_ids: Vec<String>,
+        /// Accept events made up by `twitch event trigger` as if they were for this channel.
+        /// For local testing only.
+        pub twitch_cli_events: bool,
+        /// Redemptions whose message takes longer than this to reach chat are logged.
+        pub latency_budget_ms: u64,
+        /// Secrets Manager secret holding the scoped admin tokens, on top of `ADMIN_API_TOKEN`.
+        pub admin_tokens_secret: Option<String>,
+        /// HMAC secrets of the `/hooks/{name}` webhooks, keyed by hook name, from
+        /// `HOOK_SECRET_<NAME>` env vars. Hooks without a secret are rejected.
+        pub hook_secrets: HashMap<String, String>,
+        /// JWTs of the StreamElements bots scenario groups can post as, keyed by lowercase name,
+        /// from `SE_BOT_JWT_<NAME>` env vars.
+        pub se_bot_jwts: HashMap<String, String>,
+        /// Secrets Manager secret with the OAuth client and refresh token of the YouTube channel
+        /// messages are mirrored to. Unset, nothing goes to YouTube.
+        pub youtube_token_secret: Option<String>,
+        pub youtube_api_host: String,
+        pub google_oauth_host: String,
+        /// Private notifications to the broadcaster go to Telegram when the bot token and chat
+        /// are set, otherwise to Matrix when the homeserver, room and token are.
+        pub telegram_api_host: String,
+        pub telegram_bot_token: Option<String>,
+        pub telegram_chat_id: Option<String>,
+        pub matrix_homeserver: Option<String>,
+        pub matrix_room_id: Option<String>,
+        pub matrix_access_token: Option<String>,
+        /// Discord webhook the monthly recap can be posted to.
+        pub discord_webhook_url: Option<String>,
+        /// Comma-separated events to notify about: `revocation`, `donation`, `error`,
+        /// `jwt_expiry`. All of them when unset.
+        pub notify_events: Option<String>,
+        /// Smallest donation the broadcaster is notified about, per currency.
+        pub notify_donation_min: crate::notify::DonationMinimums,
+        /// Channel the startup self-test posts to. No self-test runs without it.
+        pub self_test_channel_id: Option<String>,
+        /// How long to collect chat messages for before joining them into as few messages as
+        /// fit. 0 sends each message right away.
+        pub chat_batch_window_ms: u64,
+        /// Goes between messages joined into one.
+        pub chat_batch_separator: String,
+        /// Public URL of the `/eventsub` endpoint, which `subscribe` points subscriptions at.
+        pub eventsub_callback_url: Option<String>,
+        /// How long idle connections to the APIs are kept for reuse.
+        pub http_pool_idle_timeout_secs: u64,
+        /// Most idle connections kept per host, no limit when unset.
+        pub http_pool_max_idle_per_host: Option<usize>,
+        /// Speak HTTP/2 without negotiating it, for hosts known to support it.
+        pub http2_prior_knowledge: bool,
+        /// How often the standalone server sends itself a probe notification. Off when unset.
+        pub probe_interval_secs: Option<u64>,
+        /// Secret EventSub notifications were signed with before the current one, still
+        /// accepted while subscriptions move over to the new one.
+        pub twitch_eventsub_previous_secret: Option<String>,
+        /// When the previous secret stops being accepted. Until it's unset, when this is.
+        pub twitch_eventsub_previous_secret_until: Option<DateTime<Utc>>,
+        /// How long Twitch users looked up for their display names are cached for.
+        pub user_cache_ttl_secs: u64,
+    }
+
+    impl AppConfig {
+        /// Reads the config from env vars.
+        pub fn from_env() -> AppConfig {
+            AppConfig::read(&mut ProcessEnv)
+        }
+
+        /// Reads the config from `src`. This is the one definition of the config's env vars,
+        /// `env_template` lists them by reading the config into a recorder.
+        pub(crate) fn read(src: &mut impl EnvSource) -> AppConfig {
+            AppConfig {
+                twitch_client_id: src.string(required(
+                    "TWITCH_CLIENT_ID",
+                    "core",
+                    "Client id of the Twitch app.",
+                )),
+                twitch_client_secret: src.opt_string(
+                    optional(
+                        "TWITCH_CLIENT_SECRET",
+                        "subscriptions",
+                        "Client secret of the Twitch app, for app access tokens.",
+                    )
+                    .secret(),
+                ),
+                twitch_eventsub_subscription_secret: src.string(
+                    required(
+                        "TWITCH_EVENTSUB_SUBSCRIPTION_SECRET",
+                        "core",
+                        "Secret EventSub notifications are signed with.",
+                    )
+                    .secret(),
+                ),
+                twitch_channel_id: src.string(required(
+                    "TWITCH_CHANNEL_ID",
+                    "core",
+                    "StreamElements channel id messages are posted to.",
+                )),
+                twitch_host: src.string(required(
+                    "TWITCH_HOST",
+                    "core",
+                    "Twitch OAuth host, e.g. https://id.twitch.tv.",
+                )),
+                se_jwt: src.opt_string(
+                    optional(
+                        "SE_JWT",
+                        "core",
+                        "StreamElements JWT, unless SE_JWT_SECRET_PREFIX is set.",
+                    )
+                    .secret(),
+                ),
+                se_api_host: src.string(required(
+                    "SE_API_HOST",
+                    "core",
+                    "StreamElements API host.",
+                )),
+                feed_mods_rewards_id: src.string(required(
+                    "FEED_MODS_REWARD_ID",
+                    "core",
+                    "Channel point reward that posts a scenario.",
+                )),
+                broadcaster_user_id: src.string(required(
+                    "BROADCASTER_USER_ID",
+                    "core",
+                    "Twitch user id of the broadcaster.",
+                )),
+                redirect_uri: src.string(required(
+                    "REDIRECT_URI",
+                    "core",
+                    "OAuth redirect URI of the Twitch app.",
+                )),
+                message_components_config_path: src.string(required(
+                    "MESSAGE_COMPONENTS_CONFIG_PATH",
+                    "core",
+                    "Path of the message config file.",
+                )),
+                rubberduck_rewards_id: src.string(required(
+                    "RUBBERDUCK_REWARD_ID",
+                    "ducks",
+                    "Channel point reward recorded in the duck rewards table.",
+                )),
+                duck_rewards_table_name: src.string(required(
+                    "DUCK_REWARDS_TABLE_NAME",
+                    "ducks",
+                    "DynamoDB table of duck redemptions.",
+                )),
+                twitch_api_host: src.string(
+                    optional("TWITCH_API_HOST", "helix", "Helix API base URL.")
+                        .with_default("https://api.twitch.tv/helix/"),
+                ),
+                twitch_access_token: src.opt_string(
+                    optional(
+                        "TWITCH_ACCESS_TOKEN",
+                        "helix",
+                        "User access token for Helix calls like chatters, polls and roles.",
+                    )
+                    .secret(),
+                ),
+                chatters_cache_ttl_secs: src.parse_or_default(
+                    optional(
+                        "CHATTERS_CACHE_TTL_SECS",
+                        "random viewer",
+                        "How long the chatters list is cached for.",
+                    )
+                    .with_default("60"),
+                ),
+                stream_state_cache_ttl_secs: src.parse_or_default(
+                    optional(
+                        "STREAM_STATE_CACHE_TTL_SECS",
+                        "offline guard",
+                        "How long whether the stream is live is cached for.",
+                    )
+                    .with_default("60"),
+                ),
+                leaderboard_cache_ttl_secs: src.parse_or_default(
+                    optional(
+                        "LEADERBOARD_CACHE_TTL_SECS",
+                        "leaderboard",
+                        "How long the rendered /leaderboard page is cached for.",
+                    )
+                    .with_default("300"),
+                ),
+                action_queue_url: src.opt_string(optional(
+                    "ACTION_QUEUE_URL",
+                    "action queue",
+                    "SQS queue for delayed follow-ups and poll results.",
+                )),
+                state_table_name: src.opt_string(optional(
+                    "STATE_TABLE_NAME",
+                    "state",
+                    "DynamoDB table for state. Kept in memory when unset.",
+                )),
+                state_log_table_name: src.opt_string(optional(
+                    "STATE_LOG_TABLE_NAME",
+                    "state",
+                    "DynamoDB table for the audit logs, the offline queue and the outbox, \
+                        keyed by pk and sk. STATE_TABLE_NAME with -log appended when unset.",
+                )),
+                state_backend: src.parse(optional(
+                    "STATE_BACKEND",
+                    "state",
+                    "Where state is kept: dynamodb, redis, sqlite or memory. Picked from \
+                        STATE_TABLE_NAME when unset.",
+                )),
+                redis_url: src.opt_string(
+                    optional(
+                        "REDIS_URL",
+                        "state",
+                        "Redis server for the redis state backend, e.g. \
+                        redis://cache.example:6379.",
+                    )
+                    .secret(),
+                ),
+                redis_key_prefix: src.string(
+                    optional(
+                        "REDIS_KEY_PREFIX",
+                        "state",
+                        "Goes in front of every Redis key, so several bots can share a server.",
+                    )
+                    .with_default("robochick:"),
+                ),
+                sqlite_path: src.string(
+                    optional(
+                        "SQLITE_PATH",
+                        "state",
+                        "SQLite file for the sqlite state backend, created when missing.",
+                    )
+                    .with_default("robochick.db"),
+                ),
+                seen_message_retention_secs: src.parse_or_default(
+                    optional(
+                        "SEEN_MESSAGE_RETENTION_SECS",
+                        "state",
+                        "How long handled EventSub message ids are kept to recognize retries.",
+                    )
+                    .with_default("86400"),
+                ),
+                audit_retention_days: src.parse_or_default(
+                    optional(
+                        "AUDIT_RETENTION_DAYS",
+                        "state",
+                        "How long each day's message and config audit log is kept.",
+                    )
+                    .with_default("31"),
+                ),
+                sqlite_cleanup_interval_secs: src.parse_or_default(
+                    optional(
+                        "SQLITE_CLEANUP_INTERVAL_SECS",
+                        "state",
+                        "How often expired rows are deleted from the SQLite file.",
+                    )
+                    .with_default("3600"),
+                ),
+                internal_api_token: src.opt_string(
+                    optional(
+                        "INTERNAL_API_TOKEN",
+                        "action queue",
+                        "Bearer token of the /internal routes.",
+                    )
+                    .secret(),
+                ),
+                admin_api_token: src.opt_string(
+                    optional(
+                        "ADMIN_API_TOKEN",
+                        "admin API",
+                        "Bearer token allowed the whole admin API.",
+                    )
+                    .secret(),
+                ),
+                admin_auth: src.parse_or_default(
+                    optional(
+                        "ADMIN_AUTH",
+                        "admin API",
+                        "How /admin callers authenticate: bearer, hmac or mtls.",
+                    )
+                    .with_default("bearer"),
+                ),
+                internal_auth: src.parse_or_default(
+                    optional(
+                        "INTERNAL_AUTH",
+                        "action queue",
+                        "How /internal callers authenticate: bearer, hmac or mtls.",
+                    )
+                    .with_default("bearer"),
+                ),
+                admin_signing_secret: src.opt_string(
+                    optional(
+                        "ADMIN_SIGNING_SECRET",
+                        "admin API",
+                        "HMAC secret of signed /admin requests.",
+                    )
+                    .secret(),
+                ),
+                internal_signing_secret: src.opt_string(
+                    optional(
+                        "INTERNAL_SIGNING_SECRET",
+                        "action queue",
+                        "HMAC secret of signed /internal requests.",
+                    )
+                    .secret(),
+                ),
+                admin_client_certs: named_values(src.opt_string(optional(
+                    "ADMIN_CLIENT_CERTS",
+                    "admin API",
+                    "name=sha256-fingerprint pairs of the client certificates allowed on \
+                            /admin.",
+                ))),
+                internal_client_certs: named_values(src.opt_string(optional(
+                    "INTERNAL_CLIENT_CERTS",
+                    "action queue",
+                    "name=sha256-fingerprint pairs of the client certificates allowed on \
+                            /internal.",
+                ))),
+                client_cert_header: src.string(
+                    optional(
+                        "CLIENT_CERT_HEADER",
+                        "admin API",
+                        "Header the TLS terminator passes the client certificate in.",
+                    )
+                    .with_default("X-Amzn-Mtls-Clientcert"),
+                ),
+                backup_bucket: src.opt_string(optional(
+                    "BACKUP_BUCKET",
+                    "backups",
+                    "S3 bucket backups are written to.",
+                )),
+                action_queue_arn: src.opt_string(optional(
+                    "ACTION_QUEUE_ARN",
+                    "long delays",
+                    "ARN of the action queue, for EventBridge Scheduler.",
+                )),
+                scheduler_role_arn: src.opt_string(optional(
+                    "SCHEDULER_ROLE_ARN",
+                    "long delays",
+                    "Role EventBridge Scheduler assumes to send to the queue.",
+                )),
+                scheduler_group_name: src.opt_string(optional(
+                    "SCHEDULER_GROUP_NAME",
+                    "long delays",
+                    "EventBridge Scheduler group for the schedules.",
+                )),
+                helix_chat_sender_id: src.opt_string(optional(
+                    "HELIX_CHAT_SENDER_ID",
+                    "helix chat fallback",
+                    "User messages are sent as through Helix.",
+                )),
+                se_bot_login: src.string(
+                    optional(
+                        "SE_BOT_LOGIN",
+                        "chat commands",
+                        "Login of the StreamElements bot, whose chat messages are ignored.",
+                    )
+                    .with_default("streamelements"),
+                ),
+                se_jwt_secret_prefix: src.opt_string(optional(
+                    "SE_JWT_SECRET_PREFIX",
+                    "per-channel JWTs",
+                    "Prefix of the Secrets Manager secrets holding StreamElements JWTs.",
+                )),
+                eventsub_secret_prefix: src.opt_string(optional(
+                    "EVENTSUB_SECRET_PREFIX",
+                    "per-subscription secrets",
+                    "Prefix of the Secrets Manager secrets holding each EventSub \
+                        subscription's secret.",
+                )),
+                eventsub_legacy_subscription_ids: src.list(optional(
+                    "EVENTSUB_LEGACY_SUBSCRIPTION_IDS",
+                    "per-subscription secrets",
+                    "Comma-separated ids of subscriptions still signed with the shared secret.",
+                )),
+                twitch_cli_events: src.flag(
+                    optional(
+                        "TWITCH_CLI_EVENTS",
+                        "local testing",
+                        "Set to true to accept events from `twitch event trigger` for any \
+                        broadcaster and reward.",
+                    )
+                    .with_default("false"),
+                ),
+                latency_budget_ms: src.parse_or_default(
+                    optional(
+                        "LATENCY_BUDGET_MS",
+                        "core",
+                        "Chat latency above which redemptions are logged.",
+                    )
+                    .with_default("3000"),
+                ),
+                admin_tokens_secret: src.opt_string(optional(
+                    "ADMIN_TOKENS_SECRET",
+                    "admin API",
+                    "Secrets Manager secret holding scoped admin tokens.",
+                )),
+                hook_secrets: src.prefixed(
+                    optional(
+                        "HOOK_SECRET_<NAME>",
+                        "hooks",
+                        "HMAC secret of the /hooks/<name> webhook.",
+                    )
+                    .secret(),
+                ),
+                se_bot_jwts: src.prefixed(
+                    optional(
+                        "SE_BOT_JWT_<NAME>",
+                        "group bots",
+                        "JWT of a StreamElements bot scenario groups can post as.",
+                    )
+                    .secret(),
+                ),
+                youtube_token_secret: src.opt_string(optional(
+                    "YOUTUBE_TOKEN_SECRET",
+                    "YouTube",
+                    "Secrets Manager secret with the YouTube OAuth client and refresh token.",
+                )),
+                youtube_api_host: src.string(
+                    optional("YOUTUBE_API_HOST", "YouTube", "YouTube Data API base URL.")
+                        .with_default("https://www.googleapis.com/youtube/v3/"),
+                ),
+                google_oauth_host: src.string(
+                    optional("GOOGLE_OAUTH_HOST", "YouTube", "Google OAuth base URL.")
+                        .with_default("https://oauth2.googleapis.com/"),
+                ),
+                telegram_api_host: src.string(
+                    optional(
+                        "TELEGRAM_API_HOST",
+                        "notifications",
+                        "Telegram Bot API base URL.",
+                    )
+                    .with_default("https://api.telegram.org/"),
+                ),
+                telegram_bot_token: src.opt_string(
+                    optional(
+                        "TELEGRAM_BOT_TOKEN",
+                        "notifications",
+                        "Telegram bot notifications are sent with.",
+                    )
+                    .secret(),
+                ),
+                telegram_chat_id: src.opt_string(optional(
+                    "TELEGRAM_CHAT_ID",
+                    "notifications",
+                    "Telegram chat notifications go to.",
+                )),
+                matrix_homeserver: src.opt_string(optional(
+                    "MATRIX_HOMESERVER",
+                    "notifications",
+                    "Matrix homeserver notifications go through.",
+                )),
+                matrix_room_id: src.opt_string(optional(
+                    "MATRIX_ROOM_ID",
+                    "notifications",
+                    "Matrix room notifications go to.",
+                )),
+                matrix_access_token: src.opt_string(
+                    optional(
+                        "MATRIX_ACCESS_TOKEN",
+                        "notifications",
+                        "Matrix access token of the bot user.",
+                    )
+                    .secret(),
+                ),
+                discord_webhook_url: src.opt_string(
+                    optional(
+                        "DISCORD_WEBHOOK_URL",
+                        "monthly recap",
+                        "Discord webhook the monthly recap is posted to with post_to discord.",
+                    )
+                    .secret(),
+                ),
+                notify_events: src.opt_string(optional(
+                    "NOTIFY_EVENTS",
+                    "notifications",
+                    "Comma-separated events to notify about. All of them when unset.",
+                )),
+                notify_donation_min: src.parse_or_default(
+                    optional(
+                        "NOTIFY_DONATION_MIN",
+                        "notifications",
+                        "Smallest donation the broadcaster is notified about, as CURRENCY=amount \
+                        pairs.",
+                    )
+                    .with_default("USD=20"),
+                ),
+                self_test_channel_id: src.opt_string(optional(
+                    "SELF_TEST_CHANNEL_ID",
+                    "self-test",
+                    "Channel the startup self-test posts to.",
+                )),
+                chat_batch_window_ms: src.parse_or_default(
+                    optional(
+                        "CHAT_BATCH_WINDOW_MS",
+                        "batching",
+                        "How long chat messages are collected before joining them. 0 turns it off.",
+                    )
+                    .with_default("0"),
+                ),
+                chat_batch_separator: src.string(
+                    optional(
+                        "CHAT_BATCH_SEPARATOR",
+                        "batching",
+                        "Goes between messages joined into one.",
+                    )
+                    .with_default(" | "),
+                ),
+                eventsub_callback_url: src.opt_string(optional(
+                    "EVENTSUB_CALLBACK_URL",
+                    "subscriptions",
+                    "Public URL of the /eventsub endpoint.",
+                )),
+                http_pool_idle_timeout_secs: src.parse_or_default(
+                    optional(
+                        "HTTP_POOL_IDLE_TIMEOUT_SECS",
+                        "core",
+                        "How long idle API connections are kept open for reuse.",
+                    )
+                    .with_default("90"),
+                ),
+                http_pool_max_idle_per_host: src.parse(optional(
+                    "HTTP_POOL_MAX_IDLE_PER_HOST",
+                    "core",
+                    "Most idle connections kept per API host. No limit when unset.",
+                )),
+                http2_prior_knowledge: src.flag(
+                    optional(
+                        "HTTP2_PRIOR_KNOWLEDGE",
+                        "core",
+                        "Set to true to speak HTTP/2 without negotiating it first.",
+                    )
+                    .with_default("false"),
+                ),
+                probe_interval_secs: src.parse(optional(
+                    "PROBE_INTERVAL_SECS",
+                    "probes",
+                    "How often the standalone server sends itself a probe notification. Off \
+                        when unset.",
+                )),
+                twitch_eventsub_previous_secret: src
+                    .opt_string(
+                        optional(
+                            "TWITCH_EVENTSUB_PREVIOUS_SECRET",
+                            "secret rotation",
+                            "Shared EventSub secret before the current one, accepted too while \
+                            subscriptions are recreated.",
+                        )
+                        .secret(),
+                    )
+                    .filter(|v| !v.is_empty()),
+                twitch_eventsub_previous_secret_until: src.parse(optional(
+                    "TWITCH_EVENTSUB_PREVIOUS_SECRET_UNTIL",
+                    "secret rotation",
+                    "RFC 3339 time the previous EventSub secret stops being accepted. Accepted \
+                        until unset otherwise.",
+                )),
+                user_cache_ttl_secs: src.parse_or_default(
+                    optional(
+                        "USER_CACHE_TTL_SECS",
+                        "display names",
+                        "How long Twitch users looked up for their display names are cached for.",
+                    )
+                    .with_default("3600"),
+                ),
+            }
+        }
+
+        /// The table the audit logs, the offline queue and the outbox go in, when the state is
+        /// kept in DynamoDB.
+        pub fn log_table_name(&self) -> Option<String> {
+            self.state_log_table_name
+                .clone()
+                .or(self.state_table_name.as_ref().map(|t| format!("{t}-log")))
+        }
+
+        /// The previous EventSub secret, while it's still accepted at `now`.
+        pub fn previous_eventsub_secret(&self, now: DateTime<Utc>) -> Option<&str> {
+            self.twitch_eventsub_previous_secret.as_deref().filter(|_| {
+                self.twitch_eventsub_previous_secret_until
+                    .is_none_or(|until| now < until)
+            })
+        }
+
+        pub(crate) fn with_se_api_host(&self, new: String) -> Self {
+            AppConfig {
+                se_api_host: new.clone(),
+                ..self.clone()
+            }
+        }
+
+        pub(crate) fn with_se_jwt(&self, new: String) -> Self {
+            AppConfig {
+                se_jwt: Some(new),
+                ..self.clone()
+            }
+        }
+
+        pub(crate) fn with_twitch_api_host(&self, new: String) -> Self {
+            AppConfig {
+                twitch_api_host: new,
+                ..self.clone()
+            }
+        }
+
+        pub(crate) fn with_twitch_access_token(&self, new: String) -> Self {
+            AppConfig {
+                twitch_access_token: Some(new),
+                ..self.clone()
+            }
+        }
+
+        pub(crate) fn with_twitch_client_secret(&self, new: String) -> Self {
+            AppConfig {
+                twitch_client_secret: Some(new),
+                ..self.clone()
+            }
+        }
+    }
+
+    /// Where the config's env vars come from, the process env or, for listing them, a
+    /// recorder.
+    pub(crate) trait EnvSource {
+        /// The value of `var`, when it's set.
+        fn get(&mut self, var: &EnvVar) -> Option<String>;
+
+        /// The vars named like `var`, whose name ends in `<NAME>`, keyed by that part of their
+        /// name in lowercase.
+        fn prefixed(&mut self, var: EnvVar) -> HashMap<String, String>;
+
+        fn opt_string(&mut self, var: EnvVar) -> Option<String> {
+            self.get(&var).or(var.default.map(String::from))
+        }
+
+        /// The value of a required var or one with a default.
+        fn string(&mut self, var: EnvVar) -> String {
+            let name = var.name;
+            self.opt_string(var)
+                .unwrap_or_else(|| panic!("Missing {name} env var"))
+        }
+
+        /// The var parsed, when it's set. Panics on a value that doesn't parse, so a typo in a
+        /// setting stops the bot from starting instead of falling back to the default.
+        fn parse<T: FromStr>(&mut self, var: EnvVar) -> Option<T>
+        where
+            Self: Sized,
+        {
+            let name = var.name;
+            let value = self.opt_string(var)?;
+            match value.parse() {
+                Ok(v) => Some(v),
+                Err(_) => panic!("Invalid {name} env var: {value}"),
+            }
+        }
+
+        fn parse_or_default<T: FromStr + Default>(&mut self, var: EnvVar) -> T
+        where
+            Self: Sized,
+        {
+            self.parse(var).unwrap_or_default()
+        }
+
+        /// Whether the var is `true`.
+        fn flag(&mut self, var: EnvVar) -> bool {
+            self.opt_string(var).is_some_and(|v| v == "true")
+        }
+
+        /// The var's comma-separated values.
+        fn list(&mut self, var: EnvVar) -> Vec<String> {
+            self.opt_string(var)
+                .iter()
+                .flat_map(|v| v.split(','))
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+                .collect()
+        }
+    }
+
+    /// The env vars of the process.
+    struct ProcessEnv;
+
+    impl EnvSource for ProcessEnv {
+        fn get(&mut self, var: &EnvVar) -> Option<String> {
+            env::var(var.name).ok()
+        }
+
+        fn prefixed(&mut self, var: EnvVar) -> HashMap<String, String> {
+            let prefix = var.name.trim_end_matches("<NAME>");
+            env::vars()
+                .filter_map(|(k, v)| k.strip_prefix(prefix).map(|name| (name.to_lowercase(), v)))
+                .collect()
+        }
+    }
+
+    /// `name=value` pairs separated by commas.
+    fn named_values(value: Option<String>) -> HashMap<String, String> {
+        value
+            .iter()
+            .flat_map(|v| v.split(','))
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .filter(|(name, value)| !name.is_empty() && !value.is_empty())
+            .collect()
+    }
+}
+
+/// How long challenge responses are kept for retried verification requests.
+const CHALLENGE_CACHE_TTL: Duration = Duration::from_secs(600);
+
+/// A JWT expiry notification goes out at most this often per bot.
+const JWT_WARNING_INTERVAL_SECS: u64 = 24 * 60 * 60;
+
+#[derive(Clone)]
+pub struct AppState {
+    config: AppConfig,
+    /// Loaded once at startup, for clients only some routes need.
+    aws: aws_config::SdkConfig,
+    dynamo_client: Client,
+    chatters: Arc<TtlCache<String, Vec<String>>>,
+    live: Arc<TtlCache<String, bool>>,
+    users: Arc<UserCache>,
+    challenges: Arc<TtlCache<String, String>>,
+    /// Rendered public pages, by ETag.
+    pages: Arc<TtlCache<String, String>>,
+    web_client: WebClient,
+    /// What event handling posts and calls Twitch through, `web_client` unless swapped out.
+    caller: Arc<dyn Caller>,
+    actions: Arc<dyn ActionScheduler>,
+    state: Arc<dyn StateStore>,
+    admin_tokens: Option<Arc<AdminTokenStore>>,
+    notifier: Option<Arc<Notifier>>,
+    config_file: Arc<ConfigFile>,
+    /// Outcome of the startup self-test, once it has run.
+    self_test: Arc<RwLock<Option<BackendStatus>>>,
+}
+
+impl AppState {
+    fn with_parts(
+        config: AppConfig,
+        aws: aws_config::SdkConfig,
+        dynamo_client: Client,
+        actions: Arc<dyn ActionScheduler>,
+        state: Arc<dyn StateStore>,
+        web_client: WebClient,
+    ) -> Self {
+        let chatters_ttl = Duration::from_secs(config.chatters_cache_ttl_secs);
+        let live_ttl = Duration::from_secs(config.stream_state_cache_ttl_secs);
+        let pages_ttl = Duration::from_secs(config.leaderboard_cache_ttl_secs);
+        AppState {
+            aws,
+            dynamo_client,
+            chatters: Arc::new(TtlCache::new(chatters_ttl)),
+            live: Arc::new(TtlCache::new(live_ttl)),
+            users: Arc::new(UserCache::new(Duration::from_secs(
+                config.user_cache_ttl_secs,
+            ))),
+            challenges: Arc::new(TtlCache::new(CHALLENGE_CACHE_TTL)),
+            pages: Arc::new(TtlCache::new(pages_ttl)),
+            notifier: web_client.notifier(),
+            caller: Arc::new(web_client.clone()),
+            web_client,
+            actions,
+            state,
+            admin_tokens: None,
+            config_file: Arc::new(ConfigFile::new(&config.message_components_config_path)),
+            self_test: Arc::new(RwLock::new(None)),
+            config,
+        }
+    }
+
+    /// Posts a test message to `SELF_TEST_CHANNEL_ID` if it's set, failing the deep health
+    /// check until the next restart when that doesn't work.
+    pub async fn run_self_test(&self) {
+        let Some(channel_id) = self.config.self_test_channel_id.as_ref() else {
+            return;
+        };
+
+        let status = self.web_client.self_test(channel_id, &self.config).await;
+        match status.error.as_ref() {
+            Some(e) => println!("ALERT: self-test through {:?} failed: {e}", status.backend),
+            None => println!("Self-test through {:?} passed", status.backend),
+        }
+        *self.self_test.write().unwrap() = Some(status);
+    }
+
+    /// Warns the broadcaster about StreamElements JWTs, the default one and every group
+    /// bot's, that expire within a week, and emits how long each has left.
+    pub async fn check_se_jwts(&self) {
+        let mut jwts = vec![(
+            "default".to_string(),
+            "the default bot".to_string(),
+            self.web_client.se_jwt(&self.config).await,
+        )];
+        for (name, jwt) in &self.config.se_bot_jwts {
+            jwts.push((name.clone(), format!("bot {name}"), Ok(jwt.clone())));
+        }
+
+        let now = Utc::now();
+        for (name, bot, jwt) in jwts {
+            let expires_at = match jwt.and_then(|jwt| se_jwt::expires_at(&jwt)) {
+                Ok(at) => at,
+                Err(e) => {
+                    println!("Can't tell when the StreamElements JWT of {bot} expires: {e}");
+                    continue;
+                }
+            };
+            metrics::emit(
+                "SeJwtSecondsLeft",
+                (expires_at - now).num_seconds() as f64,
+                Unit::Seconds,
+                &[("Bot", &name)],
+            );
+            if let Some(warning) = se_jwt::expiry_warning(&bot, expires_at, now) {
+                println!("ALERT: {warning}");
+                if let Some(notifier) = self.notifier.as_ref()
+                    && self.first_jwt_warning_today(&name).await
+                {
+                    notifier.notify(NotifyEvent::JwtExpiry, &warning).await;
+                }
+            }
+        }
+    }
+
+    /// Whether no instance has notified about `bot`'s JWT in the last day, claiming the
+    /// notification if so. Cold starts would otherwise repeat it on every new instance.
+    async fn first_jwt_warning_today(&self, bot: &str) -> bool {
+        match self
+            .state
+            .start_cooldown(&format!("jwt_expiry#{bot}"), JWT_WARNING_INTERVAL_SECS)
+            .await
+        {
+            Ok(first) => first,
+            Err(e) => {
+                println!("Failed to claim the JWT expiry notification, sending it anyway: {e}");
+                true
+            }
+        }
+    }
+
+    /// Alerts about EventSub subscriptions that stopped delivering notifications, returning
+    /// them. Meant to run between streams, when nothing else would notice.
+    pub async fn check_subscriptions(&self) -> anyhow::Result<Vec<Subscription>> {
+        let existing = self
+            .web_client
+            .list_eventsub_subscriptions(&self.config)
+            .await?;
+        let unhealthy: Vec<Subscription> = subscribe::unhealthy(&existing)
+            .into_iter()
+            .cloned()
+            .collect();
+        metrics::emit(
+            "UnhealthySubscriptions",
+            unhealthy.len() as f64,
+            Unit::Count,
+            &[],
+        );
+
+        for subscription in &unhealthy {
+            let alert = format!(
+                "EventSub subscription {} ({}) is {}",
+                subscription.subscription_type(),
+                subscription.id(),
+                subscription.status()
+            );
+            println!("ALERT: {alert}");
+            if let Some(notifier) = self.notifier.as_ref() {
+                notifier
+                    .notify(NotifyEvent::SubscriptionFailure, &alert)
+                    .await;
+            }
+        }
+        Ok(unhealthy)
+    }
+
+    /// Reloads the message config file on SIGHUP, for the long-running standalone server.
+    #[cfg(unix)]
+    pub fn reload_on_sighup(&self) -> anyhow::Result<()> {
+        reload::reload_on_sighup(self.clone())
+    }
+
+    /// Posts the monthly recap on its cron, for the long-running standalone server.
+    pub fn post_recaps_on_schedule(&self) {
+        recap::spawn_schedule(self.clone())
+    }
+
+    /// Whether the request's bearer token may do `scope` on the admin API, or the status to
+    /// answer when it may not.
+    pub(crate) async fn admin_authorized(
+        &self,
+        headers: &HeaderMap,
+        scope: AdminScope,
+    ) -> Result<(), StatusCode> {
+        auth::admin_request_authorized(headers, &self.config, self.admin_tokens.as_deref(), scope)
+            .await
+    }
+
+    /// Who the request comes from, if its bearer token may do `scope` on the admin API, or the
+    /// status to answer when it may not.
+    pub(crate) async fn admin_actor(
+        &self,
+        headers: &HeaderMap,
+        scope: AdminScope,
+    ) -> Result<String, StatusCode> {
+        auth::admin_actor(headers, &self.config, self.admin_tokens.as_deref(), scope).await
+    }
+}
+
+/// Runs `fut` on a task of its own and waits for it. The futures of the AWS SDKs and the state
+/// store aren't `Sync`, which the callers' futures have to be, so they're awaited through this.
+pub(crate) async fn run_detached<F>(fut: F) -> anyhow::Result<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    Ok(tokio::spawn(fut).await?)
+}
+
+/// The state store picked by `STATE_BACKEND`. Without it, DynamoDB-backed state when
+/// `STATE_TABLE_NAME` is set, otherwise in memory. Errors when the backend that was picked
+/// can't be set up, rather than quietly keeping the state in memory.
+pub fn state_store(
+    config: &AppConfig,
+    dynamo_client: &Client,
+) -> anyhow::Result<Arc<dyn StateStore>> {
+    let retention = Retention::from_config(config);
+    match (
+        StateBackend::for_config(config),
+        config.state_table_name.clone(),
+    ) {
+        (StateBackend::Dynamodb, Some(table_name)) => {
+            return Ok(Arc::new(DynamoStore {
+                client: dynamo_client.clone(),
+                log_table_name: config
+                    .log_table_name()
+                    .unwrap_or(format!("{table_name}-log")),
+                table_name,
+                retention,
+            }));
+        }
+        (StateBackend::Dynamodb, None) => {
+            return Err(anyhow!("STATE_BACKEND=dynamodb needs STATE_TABLE_NAME"));
+        }
+        #[cfg(feature = "redis")]
+        (StateBackend::Redis, _) => {
+            let url = config
+                .redis_url
+                .as_deref()
+                .ok_or(anyhow!("STATE_BACKEND=redis needs REDIS_URL"))?;
+            return Ok(Arc::new(
+                RedisStore::new(url, &config.redis_key_prefix)?.with_retention(retention),
+            ));
+        }
+        #[cfg(not(feature = "redis"))]
+        (StateBackend::Redis, _) => {
+            return Err(anyhow!("STATE_BACKEND=redis needs the redis feature"));
+        }
+        #[cfg(feature = "sqlite")]
+        (StateBackend::Sqlite, _) => {
+            let store = Arc::new(SqliteStore::open(&config.sqlite_path)?.with_retention(retention));
+            store.spawn_cleanup(Duration::from_secs(config.sqlite_cleanup_interval_secs));
+            return Ok(store);
+        }
+        #[cfg(not(feature = "sqlite"))]
+        (StateBackend::Sqlite, _) => {
+            return Err(anyhow!("STATE_BACKEND=sqlite needs the sqlite feature"));
+        }
+        (StateBackend::Memory, _) => {}
+    }
+
+    println!("Keeping state in memory, it will not survive restarts");
+    Ok(Arc::new(MemoryStore::default().with_retention(retention)))
+}
+
+/// The HTTP client for StreamElements and Helix, reading StreamElements JWTs from Secrets
+/// Manager when `SE_JWT_SECRET_PREFIX` is set, keeping a secret per EventSub subscription
+/// there when `EVENTSUB_SECRET_PREFIX` is, and mirroring messages to YouTube when
+/// `YOUTUBE_TOKEN_SECRET` is.
+pub fn web_client(config: &AppConfig, aws_cfg: &aws_config::SdkConfig) -> WebClient {
+    let secrets = Arc::new(SecretStore::new(aws_sdk_secretsmanager::Client::new(
+        aws_cfg,
+    )));
+    web_client_with_secrets(config, secrets)
+}
+
+/// The client for `config`, reading its secrets from `secrets`.
+pub fn web_client_with_secrets(config: &AppConfig, secrets: Arc<SecretStore>) -> WebClient {
+    let mut client =
+        WebClient::new(client::http_client(config)).with_batching(ChatBatcher::from_config(config));
+    if let Some(prefix) = config.se_jwt_secret_prefix.clone() {
+        client = client.with_se_jwts(Arc::new(SeJwtStore::new(secrets.clone(), prefix)));
+    }
+    if let Some(prefix) = config.eventsub_secret_prefix.clone() {
+        client = client
+            .with_eventsub_secrets(Arc::new(EventsubSecretStore::new(secrets.clone(), prefix)));
+    }
+    #[cfg(feature = "youtube")]
+    if let Some(secret_name) = config.youtube_token_secret.clone() {
+        client = client.with_youtube(Arc::new(youtube::YouTubeChatSink::new(
+            client::http_client(config),
+            secrets,
+            secret_name,
+            config.youtube_api_host.clone(),
+            config.google_oauth_host.clone(),
+        )));
+    }
+    #[cfg(not(feature = "youtube"))]
+    if config.youtube_token_secret.is_some() {
+        println!("YOUTUBE_TOKEN_SECRET is set, but this build has no youtube feature");
+    }
+    if let Some(notifier) = Notifier::from_config(config) {
+        client = client.with_notifier(Arc::new(notifier));
+    }
+    client
+}
+
+pub async fn load_aws_config() -> aws_config::SdkConfig {
+    let region = RegionProviderChain::default_provider().or_else("eu-west-2");
+    aws_config::from_env().region(region).load().await
+}
+
+pub fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/health", get(healthcheck))
+        .route("/health/deep", get(deep_healthcheck))
+        .route("/version", get(version::version_handler))
+        .route("/openapi.json", get(openapi::openapi_handler))
+        .route("/twitch/oauth", get(oauth_handler))
+        .route("/twitch/eventsub", post(eventsub_handler))
+        .route("/hooks/{name}", post(hooks::hook_handler))
+        .route("/stats/scenarios", get(stats::scenario_stats_handler))
+        .route("/stats/experiments", get(stats::experiment_stats_handler))
+        .route("/stats/mods", get(stats::mod_stats_handler))
+        .route("/leaderboard", get(leaderboard::leaderboard_handler))
+        .route("/overlay/winners", get(overlay::winners_handler))
+        .merge(admin_routes(&state))
+        .merge(internal_routes(&state))
+        .layer(middleware::from_fn(trace::correlate))
+        .with_state(state)
+}
+
+/// The `/admin` routes, checked by [`signing::admin_guard`] before their handlers.
+fn admin_routes(state: &AppState) -> Router<AppState> {
+    let router = Router::new()
+        .route(
+            "/admin/config",
+            get(admin::get_config_handler).put(admin::put_config_handler),
+        )
+        .route(
+            "/admin/commands/{name}",
+            put(admin::put_command_handler).delete(admin::delete_command_handler),
+        )
+        .route(
+            "/admin/config/history",
+            get(config_audit::config_history_handler),
+        )
+        .route("/admin/audit", get(audit::audit_handler))
+        .route("/admin/stats/export", get(stats_export::export_handler))
+        .route("/admin/reload", post(reload::reload_handler))
+        .route(
+            "/admin/users/{user_id}/data",
+            delete(purge::purge_user_handler),
+        );
+    #[cfg(feature = "backups")]
+    let router = router
+        .route("/admin/backup", post(backup::backup_handler))
+        .route("/admin/restore", post(backup::restore_handler));
+    router.route_layer(middleware::from_fn_with_state(
+        state.config.clone(),
+        signing::admin_guard,
+    ))
+}
+
+/// The `/internal` routes, checked by [`signing::internal_guard`] before their handlers.
+fn internal_routes(state: &AppState) -> Router<AppState> {
+    Router::new()
+        .route(
+            "/internal/scenario-summary",
+            post(stats::scenario_summary_handler),
+        )
+        .route("/internal/monthly-recap", post(recap::recap_handler))
+        .route("/internal/check-jwt", post(check_jwt_handler))
+        .route(
+            "/internal/check-subscriptions",
+            post(check_subscriptions_handler),
+        )
+        .route("/internal/warm", post(warm::warm_handler))
+        .route("/internal/probe", post(probe::probe_handler))
+        .route(
+            "/internal/replay-outbox",
+            post(deadline::replay_outbox_handler),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            state.config.clone(),
+            signing::internal_guard,
+        ))
+}
+
+#[utoipa::path(get, path = "/health", tag = "health", responses(
+    (status = 200, description = "The bot is up", body = String, content_type = "text/plain"),
+))]
+async fn healthcheck() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from("bokbokbok"))
+        .unwrap()
+}
+
+/// Health of each chat backend, and which one messages are going to. 503 when none of them
+/// are healthy.
+#[utoipa::path(get, path = "/health/deep", tag = "health", responses(
+    (status = 200, description = "Each chat backend's status", body = Object),
+    (status = 503, description = "No chat backend is healthy", body = Object),
+))]
+async fn deep_healthcheck(State(state): State<AppState>) -> Response<Body> {
+    let statuses = state.web_client.backend_statuses(&state.config).await;
+    let self_test = state.self_test.read().unwrap().clone();
+    let body = serde_json::json!({
+        "chat_backend": sink::choose(&statuses),
+        "chat_backends": statuses,
+        "self_test": self_test,
+    });
+    let status = if statuses.iter().any(|s| s.healthy) && self_test.is_none_or(|t| t.healthy) {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// `POST /internal/check-jwt` - warns about StreamElements JWTs close to expiring. Runs on
+/// every cold start, this is for a daily schedule to catch long-running instances.
+#[utoipa::path(post, path = "/internal/check-jwt", tag = "internal",
+    security(("internal_token" = [])),
+    responses(
+        (status = 204, description = "Checked"),
+        (status = 401, description = "Missing or wrong `INTERNAL_API_TOKEN`"),
+    ),
+)]
+async fn check_jwt_handler(State(state): State<AppState>, headers: HeaderMap) -> Response<Body> {
+    if !auth::internal_request_authorized(&headers, &state.config) {
+        return Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .body(Body::Empty)
+            .unwrap();
+    }
+
+    state.check_se_jwts().await;
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .body(Body::Empty)
+        .unwrap()
+}
+
+/// `POST /internal/check-subscriptions` - alerts about EventSub subscriptions that failed
+/// verification or were disabled, responding with them. For a schedule (e.g. an EventBridge
+/// rule) to catch breakages between streams.
+#[utoipa::path(post, path = "/internal/check-subscriptions", tag = "internal",
+    security(("internal_token" = [])),
+    responses(
+        (status = 200, description = "The unhealthy subscriptions", body = Vec<Object>),
+        (status = 401, description = "Missing or wrong `INTERNAL_API_TOKEN`"),
+        (status = 502, description = "Twitch couldn't be asked"),
+    ),
+)]
+async fn check_subscriptions_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    if !auth::internal_request_authorized(&headers, &state.config) {
+        return Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .body(Body::Empty)
+            .unwrap();
+    }
+
+    match state.check_subscriptions().await {
+        Ok(unhealthy) => Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(Body::from(serde_json::to_string(&unhealthy).unwrap()))
+            .unwrap(),
+        Err(e) => {
+            println!("Failed to check EventSub subscriptions: {e}");
+            Response::builder()
+                .status(StatusCode::BAD_GATEWAY)
+                .body(Body::Empty)
+                .unwrap()
+        }
+    }
+}
+
+#[utoipa::path(get, path = "/twitch/oauth", tag = "twitch",
+    params(
+        ("code" = Option<String>, Query, description = "Authorization code from Twitch"),
+        ("scope" = Option<String>, Query, description = "Scopes that were granted"),
+        ("error" = Option<String>, Query, description = "Set when the broadcaster denied access"),
+        ("error_description" = Option<String>, Query),
+    ),
+    responses(
+        (status = 200, description = "Authorized, or access was denied", body = String, content_type = "text/html"),
+        (status = 400, description = "Neither a code nor an error"),
+        (status = 500, description = "The code couldn't be exchanged for a token"),
+    ),
+)]
+async fn oauth_handler(
+    State(state): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response<Body> {
+    if let (Some(code), Some(scope)) = (params.get("code"), params.get("scope")) {
+        let url_base = format!("{}/oauth2/token", state.config.twitch_host);
+        let req_params = [
+            ("client_id", state.config.twitch_client_id),
+            ("client_secret", state.config.twitch_client_secret.unwrap()),
+            ("code", code.to_string()),
+            ("grant_type", "authorization_code".to_string()),
+            ("redirect_uri", state.config.redirect_uri),
+        ];
+        let url = Url::parse_with_params(&url_base, req_params.iter());
+
+        let resp = match reqwest::Client::new().post(url.unwrap()).send().await {
+            Ok(resp) => resp,
+            Err(e) => {
+                println!(
+                    "Failed to create auth token, attempted to call /oauth2/token API. Caused by: {}",
+                    e.without_url()
+                );
+                return Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::Empty)
+                    .unwrap();
+            }
+        };
+
+        let oauth_response = match resp.text().await {
+            Ok(response) => response,
+            Err(e) => {
+                println!("Error decoding data from oauth API response");
+                return Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::Empty)
+                    .unwrap();
+            }
+        };
+
+        match auth::securely_store_oauth_tokens(oauth_response).await {
+            Ok(secret_name) => println!("Successfully stored in {secret_name}"),
+            Err(e) => println!("Failed to store oauth response: {e}"),
+        }
+
+        return Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "text/html")
+            .body(Body::from("Authorized! Have a nice day!"))
+            .unwrap();
+    }
+
+    if let (Some(error), Some(error_desc)) = (params.get("error"), params.get("error_description"))
+    {
+        println!("Authorization denied by user :( Error: {error} and description: {error_desc}");
+
+        return Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "text/html")
+            .body(Body::from("Authorization denied :("))
+            .unwrap();
+    }
+
+    println!("Authorization request from Twitch is missing code and/or scopes param");
+
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .body(Body::from("Malformed auth request."))
+        .unwrap()
+}
+
+#[utoipa::path(post, path = "/twitch/eventsub", tag = "twitch",
+    request_body(content = Object, description = "An EventSub message, signed in the `Twitch-Eventsub-Message-Signature` header"),
+    responses(
+        (status = 200, description = "The challenge of a verification request", body = String, content_type = "text/plain"),
+        (status = 204, description = "Notification or revocation handled"),
+        (status = 400, description = "Nothing handled the notification, or the challenge was refused"),
+        (status = 403, description = "The signature didn't check out"),
+    ),
+)]
+async fn eventsub_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    context: Option<Extension<lambda_http::Context>>,
+    body: Bytes,
+) -> Response<Body> {
+    let body = gateway::original_body(body);
+    let mut event_handler = state.event_handler();
+    if let Some(Extension(context)) = context {
+        event_handler.set_lambda_context(context);
+    }
+
+    let handled = event_handler.handle(&body, &headers, &state.config);
+    let handled = match event_handler.time_left(Utc::now()) {
+        Some(left) => match tokio::time::timeout(left, handled).await {
+            Ok(handled) => handled,
+            Err(_) => Ok(state
+                .queue_unfinished(&event_handler, &body, &headers, left)
+                .await),
+        },
+        None => handled.await,
+    };
+    match handled {
+        Ok((resp, outcome)) => {
+            metrics::count("EventsubRequests", &[("Outcome", outcome.name())]);
+            let probing = headers
+                .get(EventsubHeader::MessageId.as_ref())
+                .and_then(|h| h.to_str().ok())
+                .is_some_and(probe::is_probe);
+            match probing {
+                true => probe::answer(&outcome, resp),
+                false => resp,
+            }
+        }
+        Err(e) => {
+            println!("Event handling failed with error: {}", e);
+
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::Empty)
+                .unwrap()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use dotenvy::dotenv;
+
+    use crate::{config::AppConfig, state::StateBackend, state_store};
+
+    #[test]
+    fn from_env_creates_config() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let _result = AppConfig::from_env();
+        Ok(())
+    }
+
+    #[test]
+    fn state_store_fails_when_the_chosen_backend_cant_be_set_up() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let client = aws_sdk_dynamodb::Client::from_conf(
+            aws_sdk_dynamodb::Config::builder()
+                .behavior_version(aws_config::BehaviorVersion::latest())
+                .build(),
+        );
+        let mut config = AppConfig::from_env();
+        config.state_table_name = None;
+
+        config.state_backend = Some(StateBackend::Dynamodb);
+        assert!(state_store(&config, &client).is_err());
+        config.state_backend = Some(StateBackend::Redis);
+        config.redis_url = None;
+        assert!(state_store(&config, &client).is_err());
+        config.redis_url = Some("http://not-redis".into());
+        assert!(state_store(&config, &client).is_err());
+        #[cfg(feature = "sqlite")]
+        {
+            config.state_backend = Some(StateBackend::Sqlite);
+            config.sqlite_path = "resources/tests/no-such-dir/robochick.db".into();
+            assert!(state_store(&config, &client).is_err());
+        }
+        config.state_backend = None;
+        assert!(state_store(&config, &client).is_ok());
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Language numbers, dates and times are written in by the template helpers and the recap.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+    Fr,
+    Es,
+}
+
+/// Units of relative times, longest first, with their length in seconds.
+const UNITS: [(Unit, i64); 6] = [
+    (Unit::Year, 365 * 86400),
+    (Unit::Month, 30 * 86400),
+    (Unit::Week, 7 * 86400),
+    (Unit::Day, 86400),
+    (Unit::Hour, 3600),
+    (Unit::Minute, 60),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Unit {
+    Year,
+    Month,
+    Week,
+    Day,
+    Hour,
+    Minute,
+}
+
+impl Locale {
+    fn separators(self) -> (&'static str, &'static str) {
+        match self {
+            Locale::En => (",", "."),
+            Locale::De | Locale::Es => (".", ","),
+            Locale::Fr => ("\u{202f}", ","),
+        }
+    }
+
+    /// Groups the thousands of a number written like `1234567.5`, e.g. `1,234,567.5` in
+    /// English or `1.234.567,5` in German. `None` when it isn't a number.
+    pub fn format_number(self, number: &str) -> Option<String> {
+        let number = number.trim();
+        number.parse::<f64>().ok()?;
+        let (sign, digits) = match number.strip_prefix('-') {
+            Some(rest) => ("-", rest),
+            None => ("", number),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if !whole.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+
+        let (thousands, decimal) = self.separators();
+        let mut grouped = String::new();
+        for (i, c) in whole.chars().enumerate() {
+            if i > 0 && (whole.len() - i) % 3 == 0 {
+                grouped.push_str(thousands);
+            }
+            grouped.push(c);
+        }
+        match fraction.is_empty() {
+            true => Some(format!("{sign}{grouped}")),
+            false => Some(format!("{sign}{grouped}{decimal}{fraction}")),
+        }
+    }
+
+    /// How long ago `then` was, or how long until it, in the largest whole unit, e.g. `3 days
+    /// ago` or `in 2 hours`. Under a minute is `just now`.
+    pub fn relative_time(self, then: DateTime<Utc>, now: DateTime<Utc>) -> String {
+        let secs = (now - then).num_seconds();
+        let Some((unit, count)) = UNITS
+            .iter()
+            .map(|(unit, length)| (*unit, secs.abs() / length))
+            .find(|(_, count)| *count > 0)
+        else {
+            return self.just_now().to_string();
+        };
+
+        let amount = format!("{count} {}", self.unit_name(unit, count == 1));
+        match (self, secs > 0) {
+            (Locale::En, true) => format!("{amount} ago"),
+            (Locale::De, true) => format!("vor {amount}"),
+            (Locale::Fr, true) => format!("il y a {amount}"),
+            (Locale::Es, true) => format!("hace {amount}"),
+            (Locale::En | Locale::De, false) => format!("in {amount}"),
+            (Locale::Fr, false) => format!("dans {amount}"),
+            (Locale::Es, false) => format!("dentro de {amount}"),
+        }
+    }
+
+    fn just_now(self) -> &'static str {
+        match self {
+            Locale::En => "just now",
+            Locale::De => "gerade eben",
+            Locale::Fr => "à l'instant",
+            Locale::Es => "ahora mismo",
+        }
+    }
+
+    /// German uses the dative, as the unit always follows `vor` or `in`.
+    fn unit_name(self, unit: Unit, one: bool) -> &'static str {
+        let (singular, plural) = match (self, unit) {
+            (Locale::En, Unit::Year) => ("year", "years"),
+            (Locale::En, Unit::Month) => ("month", "months"),
+            (Locale::En, Unit::Week) => ("week", "weeks"),
+            (Locale::En, Unit::Day) => ("day", "days"),
+            (Locale::En, Unit::Hour) => ("hour", "hours"),
+            (Locale::En, Unit::Minute) => ("minute", "minutes"),
+            (Locale::De, Unit::Year) => ("Jahr", "Jahren"),
+            (Locale::De, Unit::Month) => ("Monat", "Monaten"),
+            (Locale::De, Unit::Week) => ("Woche", "Wochen"),
+            (Locale::De, Unit::Day) => ("Tag", "Tagen"),
+            (Locale::De, Unit::Hour) => ("Stunde", "Stunden"),
+            (Locale::De, Unit::Minute) => ("Minute", "Minuten"),
+            (Locale::Fr, Unit::Year) => ("an", "ans"),
+            (Locale::Fr, Unit::Month) => ("mois", "mois"),
+            (Locale::Fr, Unit::Week) => ("semaine", "semaines"),
+            (Locale::Fr, Unit::Day) => ("jour", "jours"),
+            (Locale::Fr, Unit::Hour) => ("heure", "heures"),
+            (Locale::Fr, Unit::Minute) => ("minute", "minutes"),
+            (Locale::Es, Unit::Year) => ("año", "años"),
+            (Locale::Es, Unit::Month) => ("mes", "meses"),
+            (Locale::Es, Unit::Week) => ("semana", "semanas"),
+            (Locale::Es, Unit::Day) => ("día", "días"),
+            (Locale::Es, Unit::Hour) => ("hora", "horas"),
+            (Locale::Es, Unit::Minute) => ("minuto", "minutos"),
+        };
+        match one {
+            true => singular,
+            false => plural,
+        }
+    }
+
+    /// Name of the month, 1 for January.
+    pub fn month_name(self, month: u32) -> &'static str {
+        let names = match self {
+            Locale::En => [
+                "January",
+                "February",
+                "March",
+                "April",
+                "May",
+                "June",
+                "July",
+                "August",
+                "September",
+                "October",
+                "November",
+                "December",
+            ],
+            Locale::De => [
+                "Januar",
+                "Februar",
+                "März",
+                "April",
+                "Mai",
+                "Juni",
+                "Juli",
+                "August",
+                "September",
+                "Oktober",
+                "November",
+                "Dezember",
+            ],
+            Locale::Fr => [
+                "janvier",
+                "février",
+                "mars",
+                "avril",
+                "mai",
+                "juin",
+                "juillet",
+                "août",
+                "septembre",
+                "octobre",
+                "novembre",
+                "décembre",
+            ],
+            Locale::Es => [
+                "enero",
+                "febrero",
+                "marzo",
+                "abril",
+                "mayo",
+                "junio",
+                "julio",
+                "agosto",
+                "septiembre",
+                "octubre",
+                "noviembre",
+                "diciembre",
+            ],
+        };
+        names[(month.clamp(1, 12) - 1) as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use pretty_assertions::assert_eq;
+
+    use crate::locale::Locale;
+
+    #[test]
+    fn numbers_get_the_locales_separators() {
+        assert_eq!(
+            Locale::En.format_number("1234567.5").as_deref(),
+            Some("1,234,567.5")
+        );
+        assert_eq!(Locale::De.format_number("-1234").as_deref(), Some("-1.234"));
+        assert_eq!(Locale::Fr.format_number("999").as_deref(), Some("999"));
+        assert_eq!(Locale::En.format_number("lots"), None);
+        assert_eq!(Locale::En.format_number("1e5"), None);
+    }
+
+    #[test]
+    fn relative_times_use_the_largest_whole_unit() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 16, 12, 0, 0).unwrap();
+
+        assert_eq!(
+            Locale::En.relative_time(now - Duration::days(3), now),
+            "3 days ago"
+        );
+        assert_eq!(
+            Locale::En.relative_time(now - Duration::minutes(61), now),
+            "1 hour ago"
+        );
+        assert_eq!(
+            Locale::En.relative_time(now + Duration::days(14), now),
+            "in 2 weeks"
+        );
+        assert_eq!(
+            Locale::En.relative_time(now - Duration::seconds(20), now),
+            "just now"
+        );
+        assert_eq!(
+            Locale::De.relative_time(now - Duration::days(3), now),
+            "vor 3 Tagen"
+        );
+        assert_eq!(
+            Locale::Es.relative_time(now - Duration::days(400), now),
+            "hace 1 año"
+        );
+        assert_eq!(Locale::Fr.month_name(8), "août");
+    }
+}
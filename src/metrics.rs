@@ -0,0 +1,116 @@
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value, json};
+
+use crate::trace;
+
+/// CloudWatch namespace all metrics are published under.
+const NAMESPACE: &str = "Robochick";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Unit {
+    Count,
+    Milliseconds,
+    Seconds,
+}
+
+impl Unit {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Unit::Count => "Count",
+            Unit::Milliseconds => "Milliseconds",
+            Unit::Seconds => "Seconds",
+        }
+    }
+}
+
+/// Builds a log line in CloudWatch's embedded metric format. Lambda ships stdout to
+/// CloudWatch Logs, which turns these lines into metrics without any API calls.
+pub fn embedded_metric(name: &str, value: f64, unit: Unit, dimensions: &[(&str, &str)]) -> String {
+    let mut line = Map::new();
+    line.insert(
+        "_aws".into(),
+        json!({
+            "Timestamp": Utc::now().timestamp_millis(),
+            "CloudWatchMetrics": [{
+                "Namespace": NAMESPACE,
+                "Dimensions": [dimensions.iter().map(|(k, _)| *k).collect::<Vec<_>>()],
+                "Metrics": [{"Name": name, "Unit": unit.as_str()}],
+            }],
+        }),
+    );
+    for (key, val) in dimensions {
+        line.insert(key.to_string(), Value::from(*val));
+    }
+    // Logged with the metric but not a dimension, so it doesn't split the metric up.
+    if let Some(id) = trace::current() {
+        line.insert("CorrelationId".into(), Value::from(id));
+    }
+    line.insert(name.into(), Value::from(value));
+
+    Value::Object(line).to_string()
+}
+
+pub fn emit(name: &str, value: f64, unit: Unit, dimensions: &[(&str, &str)]) {
+    println!("{}", embedded_metric(name, value, unit, dimensions));
+}
+
+pub fn count(name: &str, dimensions: &[(&str, &str)]) {
+    emit(name, 1.0, Unit::Count, dimensions);
+}
+
+/// Records how long a redemption took to reach chat, from when Twitch sent the notification
+/// to when the message was accepted, and warns when it took longer than `budget_ms`.
+pub fn record_chat_latency(
+    sent_at: DateTime<Utc>,
+    posted_at: DateTime<Utc>,
+    budget_ms: u64,
+) -> i64 {
+    let latency_ms = (posted_at - sent_at).num_milliseconds();
+    emit("ChatLatency", latency_ms as f64, Unit::Milliseconds, &[]);
+    if latency_ms > budget_ms as i64 {
+        println!("Message reached chat after {latency_ms}ms, over the {budget_ms}ms budget");
+    }
+
+    latency_ms
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use pretty_assertions::assert_eq;
+    use serde_json::{Value, json};
+
+    use crate::metrics::{Unit, embedded_metric, record_chat_latency};
+
+    #[test]
+    fn embedded_metric_declares_dimensions_and_value() {
+        let line = embedded_metric(
+            "SubscriptionRevoked",
+            1.0,
+            Unit::Count,
+            &[("Reason", "user_removed")],
+        );
+        let parsed: Value = serde_json::from_str(&line).unwrap();
+
+        assert_eq!(
+            parsed["_aws"]["CloudWatchMetrics"],
+            json!([{
+                "Namespace": "Robochick",
+                "Dimensions": [["Reason"]],
+                "Metrics": [{"Name": "SubscriptionRevoked", "Unit": "Count"}],
+            }])
+        );
+        assert_eq!(parsed["Reason"], json!("user_removed"));
+        assert_eq!(parsed["SubscriptionRevoked"], json!(1.0));
+    }
+
+    #[test]
+    fn record_chat_latency_returns_elapsed_millis() {
+        let sent_at = Utc.with_ymd_and_hms(2026, 10, 16, 14, 0, 0).unwrap();
+
+        assert_eq!(
+            record_chat_latency(sent_at, sent_at + Duration::milliseconds(1250), 3000),
+            1250
+        );
+    }
+}
@@ -0,0 +1,360 @@
+use std::sync::Once;
+
+use anyhow::{Result, anyhow};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    config_diff::{ConfigChange, config_diff},
+    metrics::{self, Unit},
+    robochick::twitch::{MessageComponents, Scenario, ScenarioGroup},
+};
+
+/// Version of the message config format this build writes.
+pub const SCHEMA_VERSION: u64 = 3;
+
+/// A change to the config format, rewriting a file of one version into the next.
+type Migration = fn(&mut Map<String, Value>);
+
+/// `MIGRATIONS[n]` upgrades a version `n` config to version `n + 1`.
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [
+    // Version 0 is every config from before `schema_version`, which version 1 reads as is.
+    |_| {},
+    rename_command_permissions,
+    rename_gate_roles,
+];
+
+/// Version 2 names command permissions after the roles the admin API shares, e.g. `sub`
+/// rather than `subscriber`.
+fn rename_command_permissions(config: &mut Map<String, Value>) {
+    let Some(commands) = config.get_mut("commands").and_then(Value::as_object_mut) else {
+        return;
+    };
+    let sections = commands
+        .iter_mut()
+        .filter(|(name, _)| *name == "settings" || *name == "custom")
+        .filter_map(|(_, section)| section.as_object_mut());
+    for command in sections.flat_map(|s| s.values_mut()) {
+        let Some(permission) = command.get_mut("permission") else {
+            continue;
+        };
+        rename_role(permission);
+    }
+}
+
+/// Version 3 names the roles of reward gates the same way, e.g. `mod` rather than `moderator`.
+fn rename_gate_roles(config: &mut Map<String, Value>) {
+    let Some(groups) = config.get_mut("groups").and_then(Value::as_array_mut) else {
+        return;
+    };
+    let roles = groups
+        .iter_mut()
+        .filter_map(|g| g.pointer_mut("/requires/any_of"))
+        .filter_map(Value::as_array_mut)
+        .flatten();
+    for role in roles {
+        rename_role(role);
+    }
+}
+
+/// Renames a role from before version 2, leaving anything else as it is.
+fn rename_role(role: &mut Value) {
+    let renamed = match role.as_str() {
+        Some("everyone") => "viewer",
+        Some("subscriber") => "sub",
+        Some("moderator") => "mod",
+        _ => return,
+    };
+    *role = renamed.into();
+}
+
+/// The `schema_version` of a config, defaulting to the newest for configs built in code.
+/// Files without one are version 0, see [`migrate`].
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq)]
+#[serde(transparent)]
+pub struct SchemaVersion(pub u64);
+
+impl Default for SchemaVersion {
+    fn default() -> Self {
+        SchemaVersion(SCHEMA_VERSION)
+    }
+}
+
+/// A config rewritten to the newest format.
+#[derive(Debug)]
+pub struct Migrated {
+    pub from: u64,
+    pub config: Value,
+}
+
+impl Migrated {
+    /// What the migration changed, as JSON pointers into the config.
+    pub fn changes(&self, original: &Value) -> Vec<ConfigChange> {
+        config_diff(original, &self.config)
+    }
+}
+
+/// Runs every migration from the config's `schema_version` up to [`SCHEMA_VERSION`]. Errors
+/// for configs written by a newer version of the bot.
+pub fn migrate(mut config: Value) -> Result<Migrated> {
+    let fields = config
+        .as_object_mut()
+        .ok_or(anyhow!("Message config must be a JSON object"))?;
+    let from = match fields.get("schema_version") {
+        None => 0,
+        Some(v) => v
+            .as_u64()
+            .ok_or(anyhow!("schema_version must be a whole number, got {v}"))?,
+    };
+    if from > SCHEMA_VERSION {
+        return Err(anyhow!(
+            "Message config is schema version {from}, newer than the {SCHEMA_VERSION} this build reads"
+        ));
+    }
+
+    for migration in &MIGRATIONS[from as usize..] {
+        migration(fields);
+    }
+    fields.insert("schema_version".into(), SCHEMA_VERSION.into());
+
+    Ok(Migrated { from, config })
+}
+
+/// Parses a message config of any schema version.
+pub fn parse_config(text: &str) -> Result<MessageComponents> {
+    Ok(serde_json::from_value(migrate_text(text)?)?)
+}
+
+/// The config in `text` rewritten to the newest format. Warns the first time it was older,
+/// rather than on every read of an outdated stored config.
+fn migrate_text(text: &str) -> Result<Value> {
+    static OUTDATED_WARNING: Once = Once::new();
+
+    let migrated = migrate(serde_json::from_str(text)?)?;
+    if migrated.from < SCHEMA_VERSION {
+        OUTDATED_WARNING.call_once(|| {
+            println!(
+                "Message config is schema version {}, read as version {SCHEMA_VERSION}. Run migrate-config to rewrite it",
+                migrated.from
+            )
+        });
+    }
+    Ok(migrated.config)
+}
+
+/// A scenario or group left out of the config because it didn't parse.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Skipped {
+    /// JSON pointer to it, e.g. `/groups/1/scenarios/0`.
+    pub path: String,
+    /// The scenario's `id` or the group's `name`, when it has one.
+    pub name: Option<String>,
+    pub error: String,
+}
+
+/// Parses a message config for serving, leaving out scenarios and groups that don't parse
+/// rather than failing over a typo in one of them. The rest of the config still has to parse.
+/// [`parse_config`] stays strict, for checking configs before they're saved. Logs what it left
+/// out, so it's run once per config: when the file is (re)loaded and per saved version.
+pub fn parse_config_lenient(text: &str) -> Result<(MessageComponents, Vec<Skipped>)> {
+    let mut config = migrate_text(text)?;
+    let mut skipped = vec![];
+    skip_broken::<Scenario>(&mut config, "/scenarios", "id", &mut skipped);
+    if let Some(groups) = config.get_mut("groups").and_then(Value::as_array_mut) {
+        for (i, group) in groups.iter_mut().enumerate() {
+            skip_broken::<Scenario>(group, &format!("/groups/{i}/scenarios"), "id", &mut skipped);
+        }
+    }
+    skip_broken::<ScenarioGroup>(&mut config, "/groups", "name", &mut skipped);
+
+    let components = serde_json::from_value(config)?;
+    for entry in &skipped {
+        println!(
+            "{}",
+            serde_json::json!({"level": "warn", "message": "Skipped broken message config entry", "skipped": entry})
+        );
+    }
+    if !skipped.is_empty() {
+        metrics::emit(
+            "SkippedConfigEntries",
+            skipped.len() as f64,
+            Unit::Count,
+            &[],
+        );
+    }
+    Ok((components, skipped))
+}
+
+/// Removes the items of the array at `pointer` that don't parse as `T`, recording them.
+fn skip_broken<T: for<'de> Deserialize<'de>>(
+    config: &mut Value,
+    pointer: &str,
+    name_field: &str,
+    skipped: &mut Vec<Skipped>,
+) {
+    let field = pointer.rsplit('/').next().unwrap_or_default();
+    let Some(items) = config.get_mut(field).and_then(Value::as_array_mut) else {
+        return;
+    };
+
+    let mut i = 0;
+    items.retain(|item| {
+        let path = format!("{pointer}/{i}");
+        i += 1;
+        match serde_json::from_value::<T>(item.clone()) {
+            Ok(_) => true,
+            Err(e) => {
+                skipped.push(Skipped {
+                    path,
+                    name: item[name_field].as_str().map(str::to_string),
+                    error: e.to_string(),
+                });
+                false
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use crate::{
+        migrate::{SCHEMA_VERSION, migrate, parse_config, parse_config_lenient},
+        permissions::Role,
+    };
+
+    #[test]
+    fn migrate_stamps_unversioned_configs_with_newest_version() -> Result<()> {
+        let original = json!({"scenarios": [], "mods": ["John"]});
+
+        let migrated = migrate(original.clone())?;
+
+        assert_eq!(migrated.from, 0);
+        assert_eq!(migrated.config["schema_version"], SCHEMA_VERSION);
+        assert_eq!(migrated.config["mods"], json!(["John"]));
+        let changes = migrated.changes(&original);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "/schema_version");
+        Ok(())
+    }
+
+    #[test]
+    fn migrate_rejects_configs_from_newer_versions() {
+        let err = migrate(json!({"schema_version": SCHEMA_VERSION + 1}))
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.contains("newer than"), "{err}");
+        assert!(migrate(json!({"schema_version": "one"})).is_err());
+    }
+
+    #[test]
+    fn migrate_renames_version_1_command_permissions() -> Result<()> {
+        let original = json!({
+            "schema_version": 1,
+            "scenarios": [],
+            "mods": ["John"],
+            "commands": {
+                "settings": {"fedboard": {"permission": "subscriber"}, "fed": {"permission": "vip"}},
+                "custom": {"so": {"reply": "Go follow {args}!", "permission": "moderator"}}
+            }
+        });
+
+        let migrated = migrate(original.clone())?;
+
+        let paths: Vec<String> = migrated
+            .changes(&original)
+            .into_iter()
+            .map(|c| c.path)
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                "/commands/custom/so/permission",
+                "/commands/settings/fedboard/permission",
+                "/schema_version",
+            ]
+        );
+        let commands = parse_config(&original.to_string())?.commands.unwrap();
+        assert_eq!(commands.settings["fedboard"].permission, Some(Role::Sub));
+        assert_eq!(commands.custom["so"].settings.permission, Some(Role::Mod));
+        Ok(())
+    }
+
+    #[test]
+    fn migrate_renames_version_2_gate_roles() -> Result<()> {
+        let original = json!({
+            "schema_version": 2,
+            "scenarios": [],
+            "mods": ["John"],
+            "groups": [{
+                "name": "subs",
+                "reward_ids": ["r"],
+                "scenarios": [],
+                "requires": {"any_of": ["subscriber", "tier2", "moderator"]}
+            }]
+        });
+
+        let components = parse_config(&original.to_string())?;
+
+        assert_eq!(
+            components.groups[0].get_requires().unwrap().any_of,
+            vec![Role::Sub, Role::Tier2, Role::Mod]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_config_reads_bundled_config() -> Result<()> {
+        let text = std::fs::read_to_string("resources/config/message_components.json")?;
+
+        let components = parse_config(&text)?;
+
+        assert_eq!(components.schema_version.0, SCHEMA_VERSION);
+        Ok(())
+    }
+
+    #[test]
+    fn lenient_parse_skips_broken_scenarios_and_groups() -> Result<()> {
+        let config = json!({
+            "mods": ["John"],
+            "scenarios": [
+                {"template": "{win_1} wins", "winners": ["win_1"], "others": []},
+                {"id": "typo", "template": "{win_1} wins", "winers": ["win_1"], "others": []}
+            ],
+            "groups": [
+                {"name": "raids", "reward_ids": ["r"], "scenarios": [
+                    {"template": 42, "winners": [], "others": []},
+                    {"template": "raid", "winners": [], "others": []}
+                ]},
+                {"name": "broken", "selection": "sideways", "scenarios": []}
+            ]
+        });
+
+        let (components, skipped) = parse_config_lenient(&config.to_string())?;
+
+        assert_eq!(components.schema_version.0, SCHEMA_VERSION);
+        assert_eq!(components.scenarios.len(), 1);
+        assert_eq!(components.groups.len(), 1);
+        assert_eq!(components.groups[0].scenarios.len(), 1);
+        let paths: Vec<(&str, Option<&str>)> = skipped
+            .iter()
+            .map(|s| (s.path.as_str(), s.name.as_deref()))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                ("/scenarios/1", Some("typo")),
+                ("/groups/0/scenarios/0", None),
+                ("/groups/1", Some("broken")),
+            ]
+        );
+        assert!(parse_config(&config.to_string()).is_err());
+        assert!(parse_config_lenient(r#"{"scenarios": []}"#).is_err());
+        Ok(())
+    }
+}
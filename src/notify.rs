@@ -0,0 +1,343 @@
+use std::{collections::HashMap, str::FromStr, time::Duration};
+
+use anyhow::{Result, anyhow};
+use reqwest::{Client, Url};
+use serde::Serialize;
+use serde_json::json;
+use strum::{AsRefStr, EnumString};
+
+use crate::{cache::TtlCache, config::AppConfig};
+
+/// The same notification isn't sent again within this long, so a failing backend doesn't
+/// flood the broadcaster's phone.
+const REPEAT_SUPPRESSION: Duration = Duration::from_secs(600);
+
+/// Events the broadcaster can be told about privately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, AsRefStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum NotifyEvent {
+    /// Twitch revoked one of the bot's EventSub subscriptions.
+    Revocation,
+    /// A donation at or above the `NOTIFY_DONATION_MIN` of its currency.
+    Donation,
+    /// A chat message couldn't be sent.
+    Error,
+    /// A StreamElements JWT expires within a week.
+    JwtExpiry,
+    /// The message config was saved through the admin API or reloaded.
+    ConfigChange,
+    /// An EventSub subscription stopped delivering notifications.
+    SubscriptionFailure,
+}
+
+/// Where private notifications go.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NotifySink {
+    /// A Telegram bot messaging a chat with the broadcaster, through the Bot API at
+    /// `api_host`.
+    Telegram {
+        api_host: String,
+        bot_token: String,
+        chat_id: String,
+    },
+    /// A Matrix room, posted to as the user the access token belongs to.
+    Matrix {
+        homeserver: String,
+        room_id: String,
+        access_token: String,
+    },
+}
+
+impl NotifySink {
+    /// Telegram when `TELEGRAM_BOT_TOKEN` and `TELEGRAM_CHAT_ID` are set, otherwise Matrix
+    /// when `MATRIX_HOMESERVER`, `MATRIX_ROOM_ID` and `MATRIX_ACCESS_TOKEN` are.
+    pub fn from_config(config: &AppConfig) -> Option<NotifySink> {
+        if let (Some(bot_token), Some(chat_id)) = (
+            config.telegram_bot_token.clone(),
+            config.telegram_chat_id.clone(),
+        ) {
+            return Some(NotifySink::Telegram {
+                api_host: config.telegram_api_host.clone(),
+                bot_token,
+                chat_id,
+            });
+        }
+
+        match (
+            config.matrix_homeserver.clone(),
+            config.matrix_room_id.clone(),
+            config.matrix_access_token.clone(),
+        ) {
+            (Some(homeserver), Some(room_id), Some(access_token)) => Some(NotifySink::Matrix {
+                homeserver,
+                room_id,
+                access_token,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Smallest donations worth a notification, by currency code, from e.g. `USD=20,EUR=18`.
+/// Amounts are only compared within a currency, so a donation in a currency without a
+/// minimum is always notified about.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DonationMinimums(HashMap<String, f64>);
+
+impl DonationMinimums {
+    pub fn reached(&self, amount: f64, currency: &str) -> bool {
+        match self.0.get(&currency.to_uppercase()) {
+            Some(min) => amount >= *min,
+            None => true,
+        }
+    }
+}
+
+impl Default for DonationMinimums {
+    fn default() -> Self {
+        DonationMinimums(HashMap::from([("USD".to_string(), 20.0)]))
+    }
+}
+
+impl FromStr for DonationMinimums {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (currency, min) = pair
+                    .split_once('=')
+                    .ok_or(anyhow!("Donation minimum {pair} has no currency"))?;
+                match min.trim().parse::<f64>() {
+                    Ok(min) if min.is_finite() && min >= 0.0 => {
+                        Ok((currency.trim().to_uppercase(), min))
+                    }
+                    _ => Err(anyhow!("Invalid donation minimum {pair}")),
+                }
+            })
+            .collect::<Result<_>>()
+            .map(DonationMinimums)
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct TelegramMessage<'a> {
+    chat_id: &'a str,
+    text: &'a str,
+}
+
+/// Sends private notifications to the broadcaster, separately from the public chat.
+pub struct Notifier {
+    client: Client,
+    sink: NotifySink,
+    events: Vec<NotifyEvent>,
+    pub donation_minimums: DonationMinimums,
+    sent: TtlCache<String, ()>,
+}
+
+impl Notifier {
+    pub fn new(client: Client, sink: NotifySink, events: Vec<NotifyEvent>) -> Self {
+        Notifier {
+            client,
+            sink,
+            events,
+            donation_minimums: DonationMinimums(HashMap::new()),
+            sent: TtlCache::new(REPEAT_SUPPRESSION),
+        }
+    }
+
+    /// The notifier set up in `config`, if any. `NOTIFY_EVENTS` picks the events, all of them
+    /// when it's unset.
+    pub fn from_config(config: &AppConfig) -> Option<Notifier> {
+        let sink = NotifySink::from_config(config)?;
+        let events = match config.notify_events.as_ref() {
+            Some(events) => events
+                .split(',')
+                .filter_map(|e| match NotifyEvent::from_str(e.trim()) {
+                    Ok(e) => Some(e),
+                    Err(_) => {
+                        println!("Ignoring unknown notification event {e}");
+                        None
+                    }
+                })
+                .collect(),
+            None => vec![
+                NotifyEvent::Revocation,
+                NotifyEvent::Donation,
+                NotifyEvent::Error,
+                NotifyEvent::JwtExpiry,
+                NotifyEvent::ConfigChange,
+                NotifyEvent::SubscriptionFailure,
+            ],
+        };
+
+        Some(Notifier {
+            donation_minimums: config.notify_donation_min.clone(),
+            ..Notifier::new(Client::new(), sink, events)
+        })
+    }
+
+    /// Sends `text` if the broadcaster wants to hear about `event`. Failures are only logged.
+    pub async fn notify(&self, event: NotifyEvent, text: &str) {
+        if !self.events.contains(&event) {
+            return;
+        }
+
+        let key = format!("{}:{text}", event.as_ref());
+        if self.sent.get(&key).is_some() {
+            return;
+        }
+
+        match self.send(text).await {
+            Ok(_) => self.sent.insert(key, ()),
+            Err(e) => println!("Failed to send {} notification: {e}", event.as_ref()),
+        }
+    }
+
+    async fn send(&self, text: &str) -> Result<()> {
+        let request = match &self.sink {
+            NotifySink::Telegram {
+                api_host,
+                bot_token,
+                chat_id,
+            } => self
+                .client
+                .post(format!(
+                    "{}/bot{bot_token}/sendMessage",
+                    api_host.trim_end_matches('/')
+                ))
+                .json(&TelegramMessage { chat_id, text }),
+            NotifySink::Matrix {
+                homeserver,
+                room_id,
+                access_token,
+            } => {
+                let mut url = Url::parse(homeserver)?;
+                url.path_segments_mut()
+                    .map_err(|_| anyhow!("Invalid Matrix homeserver {homeserver}"))?
+                    .pop_if_empty()
+                    .extend([
+                        "_matrix",
+                        "client",
+                        "v3",
+                        "rooms",
+                        room_id,
+                        "send",
+                        "m.room.message",
+                        &chrono::Utc::now().timestamp_micros().to_string(),
+                    ]);
+                self.client
+                    .put(url)
+                    .bearer_auth(access_token)
+                    .json(&json!({ "msgtype": "m.text", "body": text }))
+            }
+        };
+
+        match request.timeout(Duration::new(2, 0)).send().await {
+            Ok(resp) if resp.status().is_success() => Ok(()),
+            Ok(resp) => Err(anyhow!(
+                "Notification API returned error with status: {}",
+                resp.status()
+            )),
+            Err(e) => Err(anyhow!(
+                "Failed to make request to notification API: {}",
+                e.without_url()
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::{Matcher, Server};
+    use reqwest::Client;
+
+    use crate::notify::{DonationMinimums, Notifier, NotifyEvent, NotifySink};
+
+    #[tokio::test]
+    async fn notify_posts_to_telegram_chat() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/botbot-token/sendMessage")
+            .match_body(Matcher::Json(serde_json::json!({
+                "chat_id": "1234",
+                "text": "Clucky donated 25.00 EUR on Ko-fi"
+            })))
+            .with_body(r#"{"ok":true}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let notifier = Notifier::new(
+            Client::new(),
+            NotifySink::Telegram {
+                api_host: format!("http://{}/", server.host_with_port()),
+                bot_token: "bot-token".into(),
+                chat_id: "1234".into(),
+            },
+            vec![NotifyEvent::Donation],
+        );
+
+        notifier
+            .notify(NotifyEvent::Donation, "Clucky donated 25.00 EUR on Ko-fi")
+            .await;
+
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn donation_minimums_only_compare_within_a_currency() {
+        let minimums: DonationMinimums = "usd=20, JPY=3000".parse().unwrap();
+
+        assert!(minimums.reached(20.0, "USD"));
+        assert!(!minimums.reached(500.0, "JPY"));
+        assert!(minimums.reached(5.0, "EUR"));
+        assert!("20".parse::<DonationMinimums>().is_err());
+        assert!("USD=-1".parse::<DonationMinimums>().is_err());
+    }
+
+    #[tokio::test]
+    async fn notify_posts_to_matrix_room_once_per_message() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock(
+                "PUT",
+                Matcher::Regex(
+                    r"^/_matrix/client/v3/rooms/!room:example.org/send/m.room.message/\d+$".into(),
+                ),
+            )
+            .match_header("Authorization", "Bearer matrix-token")
+            .match_body(Matcher::Json(serde_json::json!({
+                "msgtype": "m.text",
+                "body": "Twitch revoked a subscription"
+            })))
+            .with_body(r#"{"event_id":"$1"}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let notifier = Notifier::new(
+            Client::new(),
+            NotifySink::Matrix {
+                homeserver: format!("http://{}", server.host_with_port()),
+                room_id: "!room:example.org".into(),
+                access_token: "matrix-token".into(),
+            },
+            vec![NotifyEvent::Revocation],
+        );
+
+        notifier
+            .notify(NotifyEvent::Revocation, "Twitch revoked a subscription")
+            .await;
+        notifier
+            .notify(NotifyEvent::Revocation, "Twitch revoked a subscription")
+            .await;
+        notifier
+            .notify(NotifyEvent::Error, "Failed to send a message")
+            .await;
+
+        mock.assert_async().await;
+    }
+}
@@ -0,0 +1,134 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    robochick::twitch::{
+        REWARD_COST, REWARD_PROMPT, REWARD_TITLE, TemplateContext, USER_LOGIN, USER_NAME,
+    },
+    types::twitch::RewardRedeemed,
+};
+
+/// Placeholders of `offline_summary`: who redeemed while the stream was offline, e.g.
+/// `Jane, Bob and Kim`, and how many redemptions there were.
+pub const USERS: &str = "users";
+pub const COUNT: &str = "count";
+
+/// What happens to redemptions made while the stream is offline.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OfflineBehavior {
+    /// Post as usual, the stream state isn't checked.
+    #[default]
+    Post,
+    /// Acknowledge the redemption without posting anything.
+    Drop,
+    /// Keep the redemption until the stream goes live.
+    Queue,
+}
+
+impl OfflineBehavior {
+    pub fn is_post(&self) -> bool {
+        *self == OfflineBehavior::Post
+    }
+}
+
+/// A redemption held back while the stream was offline, with what's needed to post it later.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QueuedRedemption {
+    pub reward_id: String,
+    pub user_id: String,
+    pub user_login: String,
+    pub user_name: String,
+    pub reward_title: String,
+    pub reward_cost: u64,
+    pub reward_prompt: String,
+    pub redeemed_at: DateTime<Utc>,
+}
+
+impl QueuedRedemption {
+    pub fn from_redemption(redeem: &RewardRedeemed, now: DateTime<Utc>) -> QueuedRedemption {
+        QueuedRedemption {
+            reward_id: redeem.reward_id().to_string(),
+            user_id: redeem.user_id().to_string(),
+            user_login: redeem.user_login().to_string(),
+            user_name: redeem.user_name().to_string(),
+            reward_title: redeem.reward_title().to_string(),
+            reward_cost: redeem.reward_cost(),
+            reward_prompt: redeem.reward_prompt().to_string(),
+            redeemed_at: redeem.sent_at.unwrap_or(now),
+        }
+    }
+
+    /// The placeholders the redemption fills, as they would have been when it was made.
+    pub fn context(&self) -> TemplateContext {
+        let mut context = TemplateContext::default();
+        context.insert(REWARD_TITLE, &self.reward_title);
+        context.insert(REWARD_COST, self.reward_cost.to_string());
+        context.insert(REWARD_PROMPT, &self.reward_prompt);
+        context.insert(USER_NAME, &self.user_name);
+        context.insert(USER_LOGIN, &self.user_login);
+        context
+    }
+}
+
+/// Fills `{users}` and `{count}` of the summary posted for `queued` once the stream is live.
+pub fn summary_context(queued: &[QueuedRedemption]) -> TemplateContext {
+    let mut users: Vec<&str> = vec![];
+    for q in queued {
+        if !users.contains(&q.user_name.as_str()) {
+            users.push(&q.user_name);
+        }
+    }
+
+    let mut context = TemplateContext::default();
+    context.insert(USERS, join_names(&users));
+    context.insert(COUNT, queued.len().to_string());
+    context
+}
+
+/// `Jane`, `Jane and Bob`, `Jane, Bob and Kim`.
+fn join_names(names: &[&str]) -> String {
+    match names {
+        [] => String::new(),
+        [only] => only.to_string(),
+        [rest @ .., last] => format!("{} and {last}", rest.join(", ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use pretty_assertions::assert_eq;
+
+    use crate::offline::{COUNT, QueuedRedemption, USERS, join_names, summary_context};
+
+    fn queued(user_name: &str) -> QueuedRedemption {
+        QueuedRedemption {
+            reward_id: "reward-1".into(),
+            user_id: user_name.to_lowercase(),
+            user_login: user_name.to_lowercase(),
+            user_name: user_name.into(),
+            reward_title: "Feed the mods".into(),
+            reward_cost: 100,
+            reward_prompt: "".into(),
+            redeemed_at: Utc.with_ymd_and_hms(2026, 10, 16, 3, 0, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn join_names_lists_names_in_a_sentence() {
+        assert_eq!(join_names(&[]), "");
+        assert_eq!(join_names(&["Jane"]), "Jane");
+        assert_eq!(join_names(&["Jane", "Bob"]), "Jane and Bob");
+        assert_eq!(join_names(&["Jane", "Bob", "Kim"]), "Jane, Bob and Kim");
+    }
+
+    #[test]
+    fn summary_names_each_redeemer_once_and_counts_every_redemption() {
+        let context = summary_context(&[queued("Jane"), queued("Bob"), queued("Jane")]);
+
+        assert_eq!(context.get(USERS), Some("Jane and Bob"));
+        assert_eq!(context.get(COUNT), Some("3"));
+    }
+}
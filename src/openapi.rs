@@ -0,0 +1,124 @@
+use lambda_http::{Body, Response};
+use reqwest::{StatusCode, header::CONTENT_TYPE};
+use utoipa::{
+    Modify, OpenApi,
+    openapi::security::{Http, HttpAuthScheme, SecurityScheme},
+};
+
+use crate::{
+    admin, audit, config_audit, deadline, hooks, leaderboard, overlay, probe, purge, recap, reload,
+    stats, stats_export, version, warm,
+};
+
+/// Every route of the HTTP server. Admin routes take `ADMIN_API_TOKEN` or a scoped admin
+/// token, internal ones `INTERNAL_API_TOKEN`, both as bearer tokens unless `ADMIN_AUTH` or
+/// `INTERNAL_AUTH` asks for signed requests or client certificates instead.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "robochick"),
+    paths(
+        crate::healthcheck,
+        crate::deep_healthcheck,
+        version::version_handler,
+        openapi_handler,
+        crate::oauth_handler,
+        crate::eventsub_handler,
+        hooks::hook_handler,
+        stats::scenario_stats_handler,
+        stats::experiment_stats_handler,
+        stats::mod_stats_handler,
+        stats_export::export_handler,
+        leaderboard::leaderboard_handler,
+        overlay::winners_handler,
+        admin::get_config_handler,
+        admin::put_config_handler,
+        admin::put_command_handler,
+        admin::delete_command_handler,
+        config_audit::config_history_handler,
+        audit::audit_handler,
+        reload::reload_handler,
+        purge::purge_user_handler,
+        stats::scenario_summary_handler,
+        recap::recap_handler,
+        crate::check_jwt_handler,
+        crate::check_subscriptions_handler,
+        warm::warm_handler,
+        probe::probe_handler,
+        deadline::replay_outbox_handler,
+    ),
+    modifiers(&Tokens),
+)]
+struct Api;
+
+#[cfg(feature = "backups")]
+#[derive(OpenApi)]
+#[openapi(paths(crate::backup::backup_handler, crate::backup::restore_handler))]
+struct BackupApi;
+
+struct Tokens;
+
+impl Modify for Tokens {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        for name in ["admin_token", "internal_token"] {
+            components.add_security_scheme(
+                name,
+                SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+            );
+        }
+    }
+}
+
+/// The document for the routes this build serves.
+pub fn spec() -> utoipa::openapi::OpenApi {
+    #[allow(unused_mut)]
+    let mut spec = Api::openapi();
+    spec.info.version = version::build_info().version.to_string();
+    #[cfg(feature = "backups")]
+    spec.merge(BackupApi::openapi());
+    spec
+}
+
+/// `GET /openapi.json` - this document.
+#[utoipa::path(get, path = "/openapi.json", tag = "health", responses(
+    (status = 200, description = "The OpenAPI document", body = Object),
+))]
+pub async fn openapi_handler() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(spec().to_json().unwrap()))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::openapi::spec;
+
+    #[test]
+    fn spec_documents_every_route() {
+        let spec = spec();
+
+        let paths: Vec<&str> = spec.paths.paths.keys().map(String::as_str).collect();
+        for path in [
+            "/health",
+            "/twitch/eventsub",
+            "/hooks/{name}",
+            "/stats/scenarios",
+            "/leaderboard",
+            "/overlay/winners",
+            "/admin/config",
+            "/admin/commands/{name}",
+            "/admin/users/{user_id}/data",
+            "/internal/monthly-recap",
+            "/openapi.json",
+        ] {
+            assert!(paths.contains(&path), "{path} is missing");
+        }
+        #[cfg(feature = "backups")]
+        assert!(paths.contains(&"/admin/backup"));
+        let schemes = spec.components.unwrap().security_schemes;
+        assert!(schemes.contains_key("admin_token"));
+        assert!(schemes.contains_key("internal_token"));
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+};
+use lambda_http::{Body, Response};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    AppState,
+    etag::{self, Dataset},
+};
+
+/// How long a stream overlay may show the winners before revalidating them with their ETag.
+const OVERLAY_CACHE_CONTROL: &str = "public, max-age=5";
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct RecentWinners {
+    pub group: String,
+    /// Most recent first.
+    pub winners: Vec<String>,
+}
+
+/// `GET /overlay/winners?group=NAME` - recent winners of a scenario group (default `default`),
+/// for a browser source in OBS to poll.
+#[utoipa::path(get, path = "/overlay/winners", tag = "overlay",
+    params(("group" = Option<String>, Query, description = "Scenario group, `default` if unset")),
+    responses(
+        (status = 200, description = "The group's recent winners", body = RecentWinners),
+        (status = 304, description = "Unchanged since the `If-None-Match` ETag"),
+    ),
+)]
+pub async fn winners_handler(
+    State(state): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    let group = params.get("group").map(String::as_str).unwrap_or("default");
+
+    let tag = match etag::version_etag(
+        state.state.as_ref(),
+        &state.config_file,
+        &[Dataset::RecentWinners],
+        &[group],
+    )
+    .await
+    {
+        Ok(tag) => tag,
+        Err(e) => return overlay_error(e),
+    };
+    if let Some(response) = etag::revalidate(&headers, &tag, OVERLAY_CACHE_CONTROL) {
+        return response;
+    }
+
+    match state.state.recent_winners(group).await {
+        Ok(winners) => etag::tagged_response(
+            &tag,
+            "application/json",
+            OVERLAY_CACHE_CONTROL,
+            serde_json::to_string(&RecentWinners {
+                group: group.to_string(),
+                winners,
+            })
+            .unwrap(),
+        ),
+        Err(e) => overlay_error(e),
+    }
+}
+
+fn overlay_error(e: anyhow::Error) -> Response<Body> {
+    println!("Failed to read recent winners: {e}");
+    Response::builder()
+        .status(StatusCode::INTERNAL_SERVER_ERROR)
+        .body(Body::Empty)
+        .unwrap()
+}
@@ -0,0 +1,318 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::admin_tokens::AdminScope;
+
+/// Who a caller is, from a viewer in chat up to a holder of an admin token. Each role has the
+/// capabilities of the ones before it. `sub` is a subscriber at any tier, `tier2` and `tier3`
+/// at that tier or higher.
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    #[default]
+    Viewer,
+    Sub,
+    Tier2,
+    Tier3,
+    Vip,
+    Mod,
+    Broadcaster,
+    AdminToken,
+}
+
+impl Role {
+    /// The highest role a chatter's Twitch badges show, from their set ids and ids. Subscriber
+    /// badge ids are the months subscribed, plus 2000 at tier 2 and 3000 at tier 3.
+    pub fn from_badges<'a>(badges: impl IntoIterator<Item = (&'a str, &'a str)>) -> Role {
+        badges
+            .into_iter()
+            .map(|(set_id, id)| match set_id {
+                "broadcaster" => Role::Broadcaster,
+                "moderator" | "lead_moderator" => Role::Mod,
+                "vip" => Role::Vip,
+                "subscriber" => match id.parse::<u32>().unwrap_or_default() / 1000 {
+                    3.. => Role::Tier3,
+                    2 => Role::Tier2,
+                    _ => Role::Sub,
+                },
+                "founder" => Role::Sub,
+                _ => Role::Viewer,
+            })
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+/// Something a caller can be allowed to do, in chat or on the admin API.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capability {
+    /// Run a chat command limited to the role and above.
+    RunCommand(Role),
+    /// Use chat commands while they're cooling down.
+    SkipCooldowns,
+    Admin(AdminScope),
+}
+
+impl Capability {
+    /// The least role with the capability.
+    fn required_role(self) -> Role {
+        match self {
+            Capability::RunCommand(role) => role,
+            Capability::SkipCooldowns => Role::Mod,
+            Capability::Admin(_) => Role::AdminToken,
+        }
+    }
+}
+
+/// A caller's role, and the scopes they're limited to on the admin API if they hold a scoped
+/// admin token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Principal {
+    pub role: Role,
+    pub scopes: Option<Vec<AdminScope>>,
+}
+
+impl Principal {
+    pub fn chatter(role: Role) -> Self {
+        Principal { role, scopes: None }
+    }
+
+    /// `ADMIN_API_TOKEN`, or a signed or certificate-authenticated admin request.
+    pub fn admin() -> Self {
+        Principal::chatter(Role::AdminToken)
+    }
+
+    pub fn scoped_token(scopes: &[AdminScope]) -> Self {
+        Principal {
+            role: Role::AdminToken,
+            scopes: Some(scopes.to_vec()),
+        }
+    }
+}
+
+/// What Helix reports about a redeemer. `sub_tier` is 1, 2 or 3. `broadcaster` is whether
+/// the redeemer is the broadcaster themselves.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UserRoles {
+    pub sub_tier: Option<u8>,
+    pub vip: bool,
+    pub moderator: bool,
+    pub broadcaster: bool,
+}
+
+impl UserRoles {
+    /// Whether the redeemer holds `role` itself. Unlike [`allows`], holding a role doesn't
+    /// give the ones before it, so a VIP-only reward stays closed to subscribers. Nobody
+    /// redeeming a reward holds the admin role.
+    pub fn has(&self, role: Role) -> bool {
+        match role {
+            Role::Viewer => true,
+            Role::Sub => self.sub_tier.is_some(),
+            Role::Tier2 => self.sub_tier.is_some_and(|t| t >= 2),
+            Role::Tier3 => self.sub_tier.is_some_and(|t| t >= 3),
+            Role::Vip => self.vip,
+            Role::Mod => self.moderator,
+            Role::Broadcaster => self.broadcaster,
+            Role::AdminToken => false,
+        }
+    }
+}
+
+/// Restricts a group's rewards to redeemers holding any of `any_of`. Other redeemers get
+/// `decline_message` and their points back. `{user}` in the message is the redeemer's name.
+/// Without a message, the decline names the roles in `any_of`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct RoleGate {
+    pub any_of: Vec<Role>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decline_message: Option<String>,
+}
+
+impl RoleGate {
+    pub fn allows(&self, roles: &UserRoles) -> bool {
+        self.any_of.iter().any(|r| roles.has(*r))
+    }
+
+    pub fn decline_message_for(&self, user: &str) -> String {
+        match &self.decline_message {
+            Some(message) => message.replace("{user}", user),
+            None => format!(
+                "Sorry {user}, this one is for {} only. Your points have been refunded.",
+                self.audience()
+            ),
+        }
+    }
+
+    /// Who the gate lets in, as a list like "subscribers, VIPs or mods".
+    fn audience(&self) -> String {
+        let names: Vec<&str> = self
+            .any_of
+            .iter()
+            .map(|role| match role {
+                Role::Viewer => "viewers",
+                Role::Sub => "subscribers",
+                Role::Tier2 => "tier 2 subscribers",
+                Role::Tier3 => "tier 3 subscribers",
+                Role::Vip => "VIPs",
+                Role::Mod => "mods",
+                Role::Broadcaster => "the broadcaster",
+                Role::AdminToken => "admins",
+            })
+            .collect();
+        match names.split_last() {
+            None => "nobody".into(),
+            Some((last, [])) => last.to_string(),
+            Some((last, rest)) => format!("{} or {last}", rest.join(", ")),
+        }
+    }
+}
+
+/// Whether the caller may do `capability`. The one check behind chat command permissions and
+/// admin routes.
+pub fn allows(principal: &Principal, capability: Capability) -> bool {
+    if principal.role < capability.required_role() {
+        return false;
+    }
+    match (capability, &principal.scopes) {
+        (Capability::Admin(scope), Some(scopes)) => scopes.contains(&scope),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        admin_tokens::AdminScope,
+        permissions::{Capability, Principal, Role, RoleGate, UserRoles, allows},
+    };
+
+    #[test]
+    fn roles_have_the_capabilities_of_the_ones_before() {
+        let vip = Principal::chatter(Role::Vip);
+        let moderator = Principal::chatter(Role::Mod);
+
+        assert!(allows(&vip, Capability::RunCommand(Role::Sub)));
+        assert!(!allows(&vip, Capability::RunCommand(Role::Mod)));
+        assert!(!allows(&vip, Capability::SkipCooldowns));
+        assert!(allows(&moderator, Capability::SkipCooldowns));
+        assert!(!allows(
+            &Principal::chatter(Role::Broadcaster),
+            Capability::Admin(AdminScope::Read)
+        ));
+        assert!(allows(
+            &Principal::admin(),
+            Capability::RunCommand(Role::Broadcaster)
+        ));
+    }
+
+    #[test]
+    fn scoped_tokens_only_get_their_scopes() {
+        let token = Principal::scoped_token(&[AdminScope::Read]);
+
+        assert!(allows(&token, Capability::Admin(AdminScope::Read)));
+        assert!(!allows(&token, Capability::Admin(AdminScope::ConfigWrite)));
+        assert!(allows(
+            &Principal::admin(),
+            Capability::Admin(AdminScope::ConfigWrite)
+        ));
+    }
+
+    #[test]
+    fn badges_give_the_highest_role() {
+        assert_eq!(Role::from_badges([]), Role::Viewer);
+        assert_eq!(
+            Role::from_badges([("founder", "0"), ("vip", "1")]),
+            Role::Vip
+        );
+        assert_eq!(
+            Role::from_badges([("subscriber", "12"), ("moderator", "1")]),
+            Role::Mod
+        );
+        assert_eq!(Role::from_badges([("broadcaster", "1")]), Role::Broadcaster);
+    }
+
+    #[test]
+    fn subscriber_badges_give_the_tier() {
+        assert_eq!(Role::from_badges([("subscriber", "6")]), Role::Sub);
+        assert_eq!(Role::from_badges([("subscriber", "2003")]), Role::Tier2);
+        assert_eq!(Role::from_badges([("subscriber", "3024")]), Role::Tier3);
+    }
+
+    #[test]
+    fn higher_tiers_count_as_lower_ones() {
+        let roles = UserRoles {
+            sub_tier: Some(2),
+            ..Default::default()
+        };
+
+        assert!(roles.has(Role::Sub));
+        assert!(roles.has(Role::Tier2));
+        assert!(!roles.has(Role::Tier3));
+        assert!(!roles.has(Role::Vip));
+    }
+
+    #[test]
+    fn gates_allow_any_listed_role() {
+        let gate: RoleGate =
+            serde_json::from_value(serde_json::json!({"any_of": ["vip", "mod"]})).unwrap();
+        let vip = UserRoles {
+            vip: true,
+            ..Default::default()
+        };
+        let subscriber = UserRoles {
+            sub_tier: Some(1),
+            ..Default::default()
+        };
+
+        assert!(gate.allows(&vip));
+        assert!(!gate.allows(&subscriber));
+        assert!(!gate.allows(&UserRoles::default()));
+    }
+
+    #[test]
+    fn broadcaster_gates_only_let_the_broadcaster_in() {
+        let gate = RoleGate {
+            any_of: vec![Role::Broadcaster],
+            decline_message: None,
+        };
+        let broadcaster = UserRoles {
+            broadcaster: true,
+            ..Default::default()
+        };
+        let moderator = UserRoles {
+            moderator: true,
+            ..Default::default()
+        };
+
+        assert!(gate.allows(&broadcaster));
+        assert!(!gate.allows(&moderator));
+        assert!(!broadcaster.has(Role::AdminToken));
+    }
+
+    #[test]
+    fn decline_message_names_the_redeemer() {
+        let gate = RoleGate {
+            any_of: vec![Role::Sub],
+            decline_message: Some("Subs only, {user}!".into()),
+        };
+
+        assert_eq!(gate.decline_message_for("Anna"), "Subs only, Anna!");
+    }
+
+    #[test]
+    fn default_decline_message_names_the_gated_roles() {
+        let gate = RoleGate {
+            any_of: vec![Role::Tier2, Role::Vip, Role::Mod],
+            decline_message: None,
+        };
+
+        assert_eq!(
+            gate.decline_message_for("Anna"),
+            "Sorry Anna, this one is for tier 2 subscribers, VIPs or mods only. Your points have been refunded."
+        );
+    }
+}
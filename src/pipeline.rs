@@ -0,0 +1,240 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{Result, anyhow};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{expr::Expr, robochick::twitch::TemplateContext};
+
+/// A rule run for every EventSub notification of type `on` whose event passes `if`, e.g.
+/// `{"on": "channel.cheer", "if": "bits >= 500", "do": ["post scenario_group=big_cheer"]}`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub on: String,
+    #[serde(rename = "if", default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub condition: Option<Condition>,
+    #[serde(rename = "do")]
+    #[schemars(with = "Vec<String>")]
+    pub steps: Vec<Step>,
+}
+
+impl Rule {
+    pub fn matches(&self, event_type: &str, event: &Value) -> bool {
+        self.on == event_type && self.condition.as_ref().is_none_or(|c| c.evaluate(event))
+    }
+}
+
+/// When a rule runs, as an [`Expr`] over the event's fields, e.g. `bits >= 500 && !is_anonymous`.
+/// Malformed conditions fail the whole config when it's loaded.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn evaluate(&self, event: &Value) -> bool {
+        self.expr.evaluate(event)
+    }
+}
+
+impl FromStr for Condition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(Condition {
+            source: s.to_string(),
+            expr: Expr::parse(s)?,
+        })
+    }
+}
+
+impl TryFrom<String> for Condition {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<Condition> for String {
+    fn from(condition: Condition) -> Self {
+        condition.source
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+/// What a rule does, written as a command with its arguments.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub enum Step {
+    /// `post scenario_group=<name>` - posts a message from the named scenario group.
+    Post { group: String },
+    /// `say <text>` - posts the text as it is, after filling in its placeholders.
+    Say { text: String },
+    /// `award_points <amount>` - gives the event's viewer (`user_login`) that many
+    /// StreamElements loyalty points, or takes them away when negative.
+    AwardPoints { amount: i64 },
+}
+
+impl FromStr for Step {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (command, args) = s.trim().split_once(' ').unwrap_or((s.trim(), ""));
+        match command {
+            "post" => match args.trim().strip_prefix("scenario_group=") {
+                Some(group) if !group.is_empty() => Ok(Step::Post {
+                    group: group.to_string(),
+                }),
+                _ => Err(anyhow!("Expected `post scenario_group=<name>` in {s:?}")),
+            },
+            "say" if !args.trim().is_empty() => Ok(Step::Say {
+                text: args.trim().to_string(),
+            }),
+            "say" => Err(anyhow!("Nothing to say in {s:?}")),
+            "award_points" => match args.trim().parse() {
+                Ok(amount) if amount != 0 => Ok(Step::AwardPoints { amount }),
+                _ => Err(anyhow!("Expected `award_points <amount>` in {s:?}")),
+            },
+            other => Err(anyhow!("Unknown pipeline step {other} in {s:?}")),
+        }
+    }
+}
+
+impl TryFrom<String> for Step {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<Step> for String {
+    fn from(step: Step) -> Self {
+        step.to_string()
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Post { group } => write!(f, "post scenario_group={group}"),
+            Step::Say { text } => write!(f, "say {text}"),
+            Step::AwardPoints { amount } => write!(f, "award_points {amount}"),
+        }
+    }
+}
+
+/// Template values from an EventSub event: each top-level string, number or bool as
+/// `{event_<field>}`, e.g. `{event_user_name}` and `{event_bits}` for a cheer.
+pub fn event_context(event: &Value) -> TemplateContext {
+    let mut context = TemplateContext::default();
+    context.insert_fields("event", event);
+    context
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use crate::pipeline::{Condition, Rule, Step, event_context};
+
+    #[test]
+    fn rule_is_parsed_into_condition_and_steps() -> Result<()> {
+        let rule: Rule = serde_json::from_value(json!({
+            "on": "channel.cheer",
+            "if": "bits >= 500",
+            "do": [
+                "post scenario_group=big_cheer",
+                "say Thanks {event_user_name}!",
+                "award_points 100"
+            ]
+        }))?;
+
+        assert_eq!(
+            rule.steps,
+            vec![
+                Step::Post {
+                    group: "big_cheer".into()
+                },
+                Step::Say {
+                    text: "Thanks {event_user_name}!".into()
+                },
+                Step::AwardPoints { amount: 100 }
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(&rule)?,
+            json!({
+                "on": "channel.cheer",
+                "if": "bits >= 500",
+                "do": [
+                    "post scenario_group=big_cheer",
+                    "say Thanks {event_user_name}!",
+                    "award_points 100"
+                ]
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rule_matches_event_type_and_condition() -> Result<()> {
+        let rule: Rule = serde_json::from_value(json!({
+            "on": "channel.cheer",
+            "if": "bits >= 500",
+            "do": ["post scenario_group=big_cheer"]
+        }))?;
+
+        assert!(rule.matches("channel.cheer", &json!({"bits": 500})));
+        assert!(!rule.matches("channel.cheer", &json!({"bits": 100})));
+        assert!(!rule.matches("channel.cheer", &json!({"user_name": "Clucky"})));
+        assert!(!rule.matches("channel.follow", &json!({"bits": 500})));
+        Ok(())
+    }
+
+    #[test]
+    fn condition_compares_nested_fields_and_text() -> Result<()> {
+        let event = json!({"tier": "2000", "reward": {"title": "Feed the mods", "cost": 300}});
+
+        assert!("reward.cost < 1000".parse::<Condition>()?.evaluate(&event));
+        assert!(
+            "reward.title == \"Feed the mods\""
+                .parse::<Condition>()?
+                .evaluate(&event)
+        );
+        assert!("tier != 1000".parse::<Condition>()?.evaluate(&event));
+        assert!(!"tier > 2000".parse::<Condition>()?.evaluate(&event));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_malformed_conditions_and_unknown_steps() {
+        assert!("bits >=".parse::<Condition>().is_err());
+        assert!("bits ~ 5".parse::<Condition>().is_err());
+        assert!("award_points".parse::<Step>().is_err());
+        assert!("award_points lots".parse::<Step>().is_err());
+        assert!("award_points 0".parse::<Step>().is_err());
+        assert!("post big_cheer".parse::<Step>().is_err());
+    }
+
+    #[test]
+    fn event_context_prefixes_fields() {
+        let context = event_context(&json!({"user_name": "Clucky", "bits": 500, "nested": {}}));
+
+        assert_eq!(context.get("event_user_name"), Some("Clucky"));
+        assert_eq!(context.get("event_bits"), Some("500"));
+        assert_eq!(context.get("event_nested"), None);
+    }
+}
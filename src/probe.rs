@@ -0,0 +1,288 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow};
+use axum::{
+    extract::State,
+    http::{HeaderMap, HeaderName, HeaderValue},
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use hmac::{Hmac, Mac};
+use lambda_http::{Body, Response};
+use reqwest::{StatusCode, header::CONTENT_TYPE};
+use serde::Serialize;
+use serde_json::json;
+use sha2::Sha256;
+
+use crate::{
+    AppState, auth,
+    config::AppConfig,
+    handler::event_handler::HandleOutcome,
+    metrics::{self, Unit},
+    types::twitch::{EventsubHeader, MessageType, SubscriptionType},
+};
+
+/// Message ids of probe notifications start with this. The id is part of what's signed, so
+/// only something holding the EventSub secret can send one.
+pub const MESSAGE_ID_PREFIX: &str = "probe-";
+
+tokio::task_local! {
+    /// Whether the notification being handled is a probe.
+    static PROBING: bool;
+}
+
+/// Runs `fut` as the handling of a probe notification when `probing`.
+pub async fn scope<F: Future>(probing: bool, fut: F) -> F::Output {
+    PROBING.scope(probing, fut).await
+}
+
+/// Whether a probe is being handled. Probes go through everything up to building the message,
+/// but don't post it or count towards stats, budgets or bursts.
+pub fn active() -> bool {
+    PROBING.try_with(|p| *p).unwrap_or(false)
+}
+
+pub fn is_probe(message_id: &str) -> bool {
+    message_id.starts_with(MESSAGE_ID_PREFIX)
+}
+
+/// A redemption of the feed mods reward by the broadcaster, with the headers and signature
+/// Twitch would send it with.
+pub fn notification(config: &AppConfig, now: DateTime<Utc>) -> Result<(HeaderMap, String)> {
+    let message_id = format!("{MESSAGE_ID_PREFIX}{}", now.timestamp_millis());
+    let timestamp = now.to_rfc3339_opts(SecondsFormat::Millis, true);
+    let body = json!({
+        "subscription": {
+            "id": "probe",
+            "type": SubscriptionType::CustomRewardRedemption.as_ref(),
+            "version": "1",
+            "status": "enabled",
+            "cost": 0,
+            "condition": {
+                "broadcaster_user_id": config.broadcaster_user_id,
+                "reward_id": config.feed_mods_rewards_id,
+            },
+            "transport": {
+                "method": "webhook",
+                "callback": config.eventsub_callback_url.clone().unwrap_or_default(),
+            },
+            "created_at": timestamp,
+        },
+        "event": {
+            "id": message_id,
+            "broadcaster_user_id": config.broadcaster_user_id,
+            "broadcaster_user_login": "probe",
+            "broadcaster_user_name": "probe",
+            "user_id": config.broadcaster_user_id,
+            "user_login": "probe",
+            "user_name": "probe",
+            "user_input": "",
+            "status": "unfulfilled",
+            "reward": {
+                "id": config.feed_mods_rewards_id,
+                "title": "probe",
+                "cost": 0,
+                "prompt": "",
+            },
+            "redeemed_at": timestamp,
+        },
+    })
+    .to_string();
+
+    let mut hmac =
+        Hmac::<Sha256>::new_from_slice(config.twitch_eventsub_subscription_secret.as_bytes())?;
+    hmac.update(message_id.as_bytes());
+    hmac.update(timestamp.as_bytes());
+    hmac.update(body.as_bytes());
+    let signature = format!("sha256={}", hex::encode(hmac.finalize().into_bytes()));
+
+    let mut headers = HeaderMap::new();
+    for (header, value) in [
+        (EventsubHeader::MessageId, message_id.as_str()),
+        (EventsubHeader::MessageTimestamp, &timestamp),
+        (EventsubHeader::MessageSignature, &signature),
+        (
+            EventsubHeader::MessageType,
+            MessageType::Notification.as_ref(),
+        ),
+        (
+            EventsubHeader::SubscriptionType,
+            SubscriptionType::CustomRewardRedemption.as_ref(),
+        ),
+        (EventsubHeader::SubscriptionVersion, "1"),
+    ] {
+        headers.insert(
+            HeaderName::try_from(header.as_ref())?,
+            HeaderValue::from_str(value)?,
+        );
+    }
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    Ok((headers, body))
+}
+
+/// Answers a probe notification with 500 unless a message was built for it, so the sender
+/// can tell. Other outcomes keep their response.
+pub fn answer(outcome: &HandleOutcome, response: Response<Body>) -> Response<Body> {
+    let reason = match outcome {
+        HandleOutcome::NotificationProcessed {
+            scenario: Some(_),
+            failures,
+            ..
+        } if failures.is_empty() => return response,
+        HandleOutcome::NotificationProcessed { failures, .. } if !failures.is_empty() => {
+            failures.join("; ")
+        }
+        HandleOutcome::NotificationProcessed { .. } => "No message was built".to_string(),
+        HandleOutcome::NotificationSkipped { reason } => reason.clone(),
+        _ => return response,
+    };
+    Response::builder()
+        .status(StatusCode::INTERNAL_SERVER_ERROR)
+        .body(Body::from(reason))
+        .unwrap()
+}
+
+/// How one probe went.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ProbeResult {
+    pub passed: bool,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl AppState {
+    /// Sends a probe notification to `EVENTSUB_CALLBACK_URL`, the way Twitch would, and
+    /// emits whether it went through as `ProbeSuccess` and how long it took as `ProbeLatency`.
+    pub async fn probe(&self) -> ProbeResult {
+        let started = Instant::now();
+        let sent = match self.config.eventsub_callback_url.as_ref() {
+            Some(url) => match notification(&self.config, Utc::now()) {
+                Ok((headers, body)) => self.web_client.send_probe(url, headers, body).await,
+                Err(e) => Err(e),
+            },
+            None => Err(anyhow!("Missing EVENTSUB_CALLBACK_URL")),
+        };
+        let latency_ms = started.elapsed().as_millis() as u64;
+
+        metrics::emit("ProbeLatency", latency_ms as f64, Unit::Milliseconds, &[]);
+        metrics::emit(
+            "ProbeSuccess",
+            if sent.is_ok() { 1.0 } else { 0.0 },
+            Unit::Count,
+            &[],
+        );
+        if let Err(e) = sent.as_ref() {
+            println!("ALERT: end-to-end probe failed after {latency_ms}ms: {e}");
+        }
+        ProbeResult {
+            passed: sent.is_ok(),
+            latency_ms,
+            error: sent.err().map(|e| e.to_string()),
+        }
+    }
+
+    /// Probes every `PROBE_INTERVAL_SECS`, for the long-running standalone server.
+    pub fn probe_on_schedule(&self) {
+        let Some(interval) = self.config.probe_interval_secs else {
+            return;
+        };
+        let app = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(interval)).await;
+                app.probe().await;
+            }
+        });
+    }
+}
+
+/// `POST /internal/probe` - sends a probe notification through the public EventSub endpoint
+/// and back, for a schedule (e.g. an EventBridge rule every few minutes).
+#[utoipa::path(post, path = "/internal/probe", tag = "internal",
+    security(("internal_token" = [])),
+    responses(
+        (status = 200, description = "The probe went through", body = Object),
+        (status = 401, description = "Missing or wrong `INTERNAL_API_TOKEN`"),
+        (status = 502, description = "The probe failed", body = Object),
+    ),
+)]
+pub async fn probe_handler(State(state): State<AppState>, headers: HeaderMap) -> Response<Body> {
+    if !auth::internal_request_authorized(&headers, &state.config) {
+        return Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .body(Body::Empty)
+            .unwrap();
+    }
+
+    let result = state.probe().await;
+    Response::builder()
+        .status(match result.passed {
+            true => StatusCode::OK,
+            false => StatusCode::BAD_GATEWAY,
+        })
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(&result).unwrap()))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use chrono::{TimeZone, Utc};
+    use lambda_http::{Body, Response};
+    use pretty_assertions::assert_eq;
+    use reqwest::StatusCode;
+
+    use crate::{
+        config::AppConfig,
+        handler::event_handler::{EventHandler, HandleOutcome},
+        probe,
+        types::twitch::{EventsubHeader, RewardRedeemed},
+    };
+
+    #[test]
+    fn probe_notifications_are_signed_redemptions_of_the_reward() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let config = AppConfig::from_env();
+        let now = Utc.with_ymd_and_hms(2026, 10, 17, 18, 0, 0).unwrap();
+
+        let (headers, body) = probe::notification(&config, now)?;
+
+        EventHandler::verify(body.as_bytes(), &headers, &config)?;
+        let message_id = headers[EventsubHeader::MessageId.as_ref()].to_str()?;
+        assert!(probe::is_probe(message_id));
+        let redemption: RewardRedeemed = serde_json::from_str(&body)?;
+        assert_eq!(redemption.reward_id(), config.feed_mods_rewards_id);
+        Ok(())
+    }
+
+    #[test]
+    fn probes_fail_unless_a_message_was_built() {
+        let processed =
+            |scenario: Option<&str>, failures: Vec<String>| HandleOutcome::NotificationProcessed {
+                scenario: scenario.map(String::from),
+                message_id: "probe-1".into(),
+                failures,
+            };
+        let status = |outcome: HandleOutcome| {
+            let response = Response::builder()
+                .status(StatusCode::NO_CONTENT)
+                .body(Body::Empty)
+                .unwrap();
+            probe::answer(&outcome, response).status()
+        };
+
+        assert_eq!(
+            status(processed(Some("scenario"), vec![])),
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(
+            status(processed(None, vec![])),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+        assert_eq!(
+            status(processed(Some("scenario"), vec!["redemption: down".into()])),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+}
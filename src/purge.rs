@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+use aws_sdk_dynamodb::types::AttributeValue;
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+};
+use chrono::Utc;
+use lambda_http::{Body, Response};
+use reqwest::{StatusCode, header::CONTENT_TYPE};
+use serde::Serialize;
+
+use crate::{
+    AppState,
+    admin_tokens::AdminScope,
+    audit,
+    etag::{self, Dataset},
+    state::StateStore,
+};
+
+/// What was removed for a viewer.
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct PurgeReport {
+    pub redemptions: usize,
+    pub recent_winners: usize,
+    pub audit_entries: usize,
+    pub config_changes: usize,
+}
+
+/// Deletes the viewer's duck redemptions, matched by user id or, for rows written before
+/// user ids were stored, by `login`. Returns how many were deleted along with the names
+/// they were stored under.
+pub async fn purge_redemptions(
+    client: &aws_sdk_dynamodb::Client,
+    table_name: &str,
+    user_id: &str,
+    login: Option<&str>,
+) -> Result<(usize, Vec<String>)> {
+    let mut filter = "user_id = :user_id".to_string();
+    let mut values = HashMap::from([(":user_id".to_string(), AttributeValue::S(user_id.into()))]);
+    if let Some(login) = login {
+        filter.push_str(" OR username = :login");
+        values.insert(":login".into(), AttributeValue::S(login.to_lowercase()));
+    }
+
+    let mut deleted = 0;
+    let mut names = vec![];
+    let mut start_key = None;
+    loop {
+        let output = match client
+            .scan()
+            .table_name(table_name)
+            .filter_expression(&filter)
+            .set_expression_attribute_values(Some(values.clone()))
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await
+        {
+            Ok(o) => o,
+            Err(e) => return Err(anyhow!("Failed to scan {table_name}: {e}")),
+        };
+
+        for item in output.items() {
+            let Some(message_id) = item.get("message_id") else {
+                continue;
+            };
+
+            if let Err(e) = client
+                .delete_item()
+                .table_name(table_name)
+                .key("message_id", message_id.clone())
+                .send()
+                .await
+            {
+                return Err(anyhow!(
+                    "Failed to delete redemption from {table_name}: {e}"
+                ));
+            }
+            deleted += 1;
+
+            for attr in ["username", "display_name"] {
+                if let Some(AttributeValue::S(name)) = item.get(attr)
+                    && !names.contains(name)
+                {
+                    names.push(name.clone());
+                }
+            }
+        }
+
+        start_key = output.last_evaluated_key;
+        if start_key.is_none() {
+            break;
+        }
+    }
+
+    Ok((deleted, names))
+}
+
+/// Removes everything stored about a viewer: their redemptions, their name from the recent
+/// winners, and the audit and config-audit entries of `days` that mention them.
+pub async fn purge_user(
+    client: &aws_sdk_dynamodb::Client,
+    table_name: &str,
+    state: &dyn StateStore,
+    user_id: &str,
+    login: Option<&str>,
+    days: &[String],
+) -> Result<PurgeReport> {
+    let (redemptions, mut names) = purge_redemptions(client, table_name, user_id, login).await?;
+    if let Some(login) = login {
+        names.push(login.to_string());
+    }
+
+    let mut report = PurgeReport {
+        redemptions,
+        recent_winners: state.forget_winners(&names).await?,
+        ..Default::default()
+    };
+    if report.recent_winners > 0 {
+        etag::touch(state, Dataset::RecentWinners).await;
+    }
+    for day in days {
+        report.audit_entries += state.forget_audit(day, &names).await?;
+        report.config_changes += state.forget_config_audit(day, &names).await?;
+    }
+    Ok(report)
+}
+
+/// `DELETE /admin/users/{user_id}/data?login=...` - removes what's stored about a viewer.
+/// `login` also catches data stored before user ids were recorded.
+#[utoipa::path(delete, path = "/admin/users/{user_id}/data", tag = "admin",
+    security(("admin_token" = [])),
+    params(
+        ("user_id" = String, Path, description = "Twitch user id of the viewer"),
+        ("login" = Option<String>, Query, description = "Their login, for data stored before user ids were"),
+    ),
+    responses(
+        (status = 200, description = "What was removed", body = Object),
+        (status = 401, description = "Token missing or unknown"),
+        (status = 403, description = "Token not scoped for this"),
+    ),
+)]
+pub async fn purge_user_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(user_id): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response<Body> {
+    if let Err(status) = state.admin_authorized(&headers, AdminScope::UserData).await {
+        return Response::builder()
+            .status(status)
+            .body(Body::Empty)
+            .unwrap();
+    }
+
+    match purge_user(
+        &state.dynamo_client,
+        &state.config.duck_rewards_table_name,
+        state.state.as_ref(),
+        &user_id,
+        params.get("login").map(String::as_str),
+        &audit::last_days(Utc::now(), state.config.audit_retention_days),
+    )
+    .await
+    {
+        Ok(report) => {
+            println!("Purged data for user {user_id}: {report:?}");
+            Response::builder()
+                .status(StatusCode::OK)
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::to_string(&report).unwrap()))
+                .unwrap()
+        }
+        Err(e) => {
+            println!("Failed to purge data for user {user_id}: {e}");
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::Empty)
+                .unwrap()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use aws_sdk_dynamodb::{
+        Client,
+        operation::{delete_item::DeleteItemOutput, scan::ScanOutput},
+        types::AttributeValue,
+    };
+    use aws_smithy_mocks::{Rule, RuleMode, mock, mock_client};
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        purge::{PurgeReport, purge_user},
+        state::{MemoryStore, StateStore},
+    };
+
+    #[tokio::test]
+    async fn purge_user_deletes_redemptions_and_forgets_winner() -> Result<()> {
+        let scan_rule: Rule = mock!(Client::scan)
+            .match_requests(|r| {
+                r.filter_expression() == Some("user_id = :user_id OR username = :login")
+            })
+            .then_output(|| {
+                ScanOutput::builder()
+                    .items(
+                        [
+                            ("message_id", "m1"),
+                            ("user_id", "42"),
+                            ("username", "clucky"),
+                            ("display_name", "Clucky"),
+                        ]
+                        .into_iter()
+                        .map(|(k, v)| (k.to_string(), AttributeValue::S(v.into())))
+                        .collect(),
+                    )
+                    .build()
+            });
+        let delete_rule: Rule = mock!(Client::delete_item)
+            .match_requests(|r| {
+                r.key().and_then(|k| k.get("message_id")) == Some(&AttributeValue::S("m1".into()))
+            })
+            .then_output(|| DeleteItemOutput::builder().build());
+        let client = mock_client!(
+            aws_sdk_dynamodb,
+            RuleMode::MatchAny,
+            [&scan_rule, &delete_rule]
+        );
+
+        let state = MemoryStore::default();
+        state
+            .push_recent_winners("default", &["Clucky".to_string(), "Jane".to_string()], 5)
+            .await?;
+
+        state
+            .append_audit("2026-10-17", r#"{"message":"clucky won!"}"#)
+            .await?;
+        state
+            .append_audit("2026-10-17", r#"{"message":"Jane won!"}"#)
+            .await?;
+        state
+            .append_config_audit("2026-10-16", r#"{"new":{"mods":["Clucky"]}}"#)
+            .await?;
+
+        let days = vec!["2026-10-17".to_string(), "2026-10-16".to_string()];
+        let report =
+            purge_user(&client, "ducks-table", &state, "42", Some("Clucky"), &days).await?;
+
+        assert_eq!(
+            report,
+            PurgeReport {
+                redemptions: 1,
+                recent_winners: 1,
+                audit_entries: 1,
+                config_changes: 1,
+            }
+        );
+        assert_eq!(delete_rule.num_calls(), 1);
+        assert_eq!(state.recent_winners("default").await?, vec!["Jane"]);
+        assert_eq!(
+            state.audit_entries("2026-10-17").await?,
+            vec![r#"{"message":"Jane won!"}"#]
+        );
+        assert!(state.config_audit_entries("2026-10-16").await?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::cron::CronExpr;
+
+/// A window during which redemptions are acknowledged but nothing is posted to chat. Every
+/// minute matched by `cron` in `timezone` is quiet, e.g. `* 0-7 * * *` for midnight to 8am.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct QuietHours {
+    #[schemars(with = "String")]
+    pub cron: CronExpr,
+    /// IANA name such as `Europe/London`. Defaults to UTC.
+    #[serde(default = "default_timezone")]
+    #[schemars(with = "String")]
+    pub timezone: Tz,
+}
+
+fn default_timezone() -> Tz {
+    Tz::UTC
+}
+
+impl QuietHours {
+    pub fn contains(&self, now: DateTime<Utc>) -> bool {
+        self.cron.matches(&now.with_timezone(&self.timezone))
+    }
+}
+
+pub fn is_quiet(quiet_hours: &[QuietHours], now: DateTime<Utc>) -> bool {
+    quiet_hours.iter().any(|q| q.contains(now))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+
+    use crate::quiet::{QuietHours, is_quiet};
+
+    #[test]
+    fn contains_evaluates_cron_in_timezone() {
+        let quiet: QuietHours =
+            serde_json::from_value(json!({"cron": "* 0-7 * * *", "timezone": "America/New_York"}))
+                .unwrap();
+
+        // 05:30 UTC is 01:30 in New York, 13:00 UTC is 09:00.
+        assert!(quiet.contains(Utc.with_ymd_and_hms(2026, 10, 16, 5, 30, 0).unwrap()));
+        assert!(!quiet.contains(Utc.with_ymd_and_hms(2026, 10, 16, 13, 0, 0).unwrap()));
+    }
+
+    #[test]
+    fn is_quiet_defaults_to_utc_and_checks_every_window() {
+        let quiet: Vec<QuietHours> = serde_json::from_value(json!([
+            {"cron": "* 2 * * *"},
+            {"cron": "* * * * 0"},
+        ]))
+        .unwrap();
+
+        assert!(is_quiet(
+            &quiet,
+            Utc.with_ymd_and_hms(2026, 10, 16, 2, 10, 0).unwrap()
+        ));
+        assert!(is_quiet(
+            &quiet,
+            Utc.with_ymd_and_hms(2026, 10, 18, 15, 0, 0).unwrap()
+        ));
+        assert!(!is_quiet(
+            &quiet,
+            Utc.with_ymd_and_hms(2026, 10, 16, 15, 0, 0).unwrap()
+        ));
+        assert!(!is_quiet(
+            &[],
+            Utc.with_ymd_and_hms(2026, 10, 16, 2, 10, 0).unwrap()
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_timezone() {
+        let result = serde_json::from_value::<QuietHours>(
+            json!({"cron": "* * * * *", "timezone": "Mars/Olympus"}),
+        );
+
+        assert!(result.is_err());
+    }
+}
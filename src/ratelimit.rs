@@ -0,0 +1,220 @@
+use std::{sync::Mutex, time::Duration};
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, TimeZone, Utc};
+use reqwest::{RequestBuilder, Response, StatusCode, header::HeaderMap};
+
+use crate::{
+    metrics::{self, Unit},
+    trace,
+};
+
+/// Longest a request is held back for the rate limit to reset. Anything longer fails right
+/// away, leaving retries to the action queue rather than keeping the invocation waiting.
+const MAX_WAIT: Duration = Duration::from_secs(5);
+
+/// What the last response said about the API's rate limit.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct LimitState {
+    remaining: Option<u64>,
+    reset_at: Option<DateTime<Utc>>,
+}
+
+/// Tracks an API's rate limit from the `Ratelimit-Remaining`, `Ratelimit-Reset` and
+/// `Retry-After` headers of its responses (also with an `X-` prefix, as StreamElements sends
+/// them), holding requests back while the limit is used up.
+pub struct RateLimiter {
+    api: &'static str,
+    state: Mutex<LimitState>,
+}
+
+impl RateLimiter {
+    pub fn new(api: &'static str) -> Self {
+        RateLimiter {
+            api,
+            state: Mutex::new(LimitState::default()),
+        }
+    }
+
+    pub fn api(&self) -> &'static str {
+        self.api
+    }
+
+    /// How long to wait before the next request. Errors when that's longer than [`MAX_WAIT`].
+    fn delay(&self, now: DateTime<Utc>) -> Result<Duration> {
+        let state = self.state.lock().unwrap().clone();
+        let reset_at = match (state.remaining, state.reset_at) {
+            (Some(0), Some(reset_at)) if reset_at > now => reset_at,
+            _ => return Ok(Duration::ZERO),
+        };
+
+        let wait = (reset_at - now).to_std().unwrap_or_default();
+        if wait > MAX_WAIT {
+            return Err(anyhow!(
+                "{} rate limit is used up until {}",
+                self.api,
+                reset_at.to_rfc3339()
+            ));
+        }
+        Ok(wait)
+    }
+
+    fn update(&self, status: StatusCode, headers: &HeaderMap, now: DateTime<Utc>) {
+        let remaining = header_u64(headers, "ratelimit-remaining");
+        let reset_at = match status {
+            StatusCode::TOO_MANY_REQUESTS => header_u64(headers, "retry-after")
+                .map(|secs| now + chrono::Duration::seconds(secs as i64))
+                .or_else(|| reset_header(headers)),
+            _ => reset_header(headers),
+        };
+
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            metrics::count("RateLimited", &[("Api", self.api)]);
+        }
+        if let Some(remaining) = remaining {
+            metrics::emit(
+                "RateLimitRemaining",
+                remaining as f64,
+                Unit::Count,
+                &[("Api", self.api)],
+            );
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.remaining = match status {
+            StatusCode::TOO_MANY_REQUESTS => Some(0),
+            _ => remaining.or(state.remaining),
+        };
+        state.reset_at = reset_at.or(state.reset_at);
+    }
+
+    /// Sends `request` once the limit allows it. A 429 is retried once after its
+    /// `Retry-After`, if that's short enough and the request can be cloned.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        let retry = request.try_clone();
+        let resp = self.send_once(request).await?;
+        if resp.status() != StatusCode::TOO_MANY_REQUESTS {
+            return Ok(resp);
+        }
+
+        match retry {
+            Some(retry) if self.delay(Utc::now()).is_ok() => {
+                println!("{} API rate limited the request, retrying", self.api);
+                self.send_once(retry).await
+            }
+            _ => Ok(resp),
+        }
+    }
+
+    async fn send_once(&self, request: RequestBuilder) -> Result<Response> {
+        let wait = self.delay(Utc::now())?;
+        if !wait.is_zero() {
+            println!(
+                "Waiting {}ms for the {} rate limit",
+                wait.as_millis(),
+                self.api
+            );
+            tokio::time::sleep(wait).await;
+        }
+
+        let resp = trace::tag(request).send().await.map_err(|e| {
+            anyhow!(
+                "Failed to make request to {} API: {}",
+                self.api,
+                e.without_url()
+            )
+        })?;
+        self.update(resp.status(), resp.headers(), Utc::now());
+        Ok(resp)
+    }
+}
+
+/// A header by name, with or without the `X-` prefix.
+fn header_u64(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers
+        .get(name)
+        .or_else(|| headers.get(format!("x-{name}")))
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.trim().parse().ok())
+}
+
+/// `Ratelimit-Reset` is a Unix timestamp, in seconds from Twitch and milliseconds from
+/// StreamElements.
+fn reset_header(headers: &HeaderMap) -> Option<DateTime<Utc>> {
+    let reset = header_u64(headers, "ratelimit-reset")? as i64;
+    match reset > 100_000_000_000 {
+        true => Utc.timestamp_millis_opt(reset).single(),
+        false => Utc.timestamp_opt(reset, 0).single(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{TimeZone, Utc};
+    use reqwest::{StatusCode, header::HeaderMap};
+
+    use crate::ratelimit::RateLimiter;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (k, v) in pairs {
+            headers.insert(*k, v.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn waits_for_reset_only_once_limit_is_used_up() {
+        let limiter = RateLimiter::new("Helix");
+        let now = Utc.with_ymd_and_hms(2026, 10, 16, 12, 0, 0).unwrap();
+        let reset = (now.timestamp() + 3).to_string();
+
+        limiter.update(
+            StatusCode::OK,
+            &headers(&[("ratelimit-remaining", "1"), ("ratelimit-reset", &reset)]),
+            now,
+        );
+        assert_eq!(limiter.delay(now).unwrap(), Duration::ZERO);
+
+        limiter.update(
+            StatusCode::OK,
+            &headers(&[("ratelimit-remaining", "0"), ("ratelimit-reset", &reset)]),
+            now,
+        );
+        assert_eq!(limiter.delay(now).unwrap(), Duration::from_secs(3));
+        assert_eq!(
+            limiter.delay(now + chrono::Duration::seconds(4)).unwrap(),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn too_many_requests_uses_retry_after_and_fails_fast_when_long() {
+        let limiter = RateLimiter::new("StreamElements");
+        let now = Utc.with_ymd_and_hms(2026, 10, 16, 12, 0, 0).unwrap();
+
+        limiter.update(
+            StatusCode::TOO_MANY_REQUESTS,
+            &headers(&[("retry-after", "2")]),
+            now,
+        );
+        assert_eq!(limiter.delay(now).unwrap(), Duration::from_secs(2));
+
+        let reset_ms = ((now.timestamp() + 60) * 1000).to_string();
+        limiter.update(
+            StatusCode::OK,
+            &headers(&[
+                ("x-ratelimit-remaining", "0"),
+                ("x-ratelimit-reset", &reset_ms),
+            ]),
+            now,
+        );
+        let err = limiter.delay(now).unwrap_err().to_string();
+        assert!(
+            err.starts_with("StreamElements rate limit is used up"),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,541 @@
+use anyhow::{Result, anyhow};
+use axum::{extract::State, http::HeaderMap};
+use chrono::{DateTime, Datelike, Days, NaiveDate, Timelike, Utc};
+use chrono_tz::Tz;
+use fastrand::Rng;
+use lambda_http::{Body, Response};
+use reqwest::StatusCode;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    AppState, auth,
+    client::StreamelementsCaller,
+    cron::CronExpr,
+    locale::Locale,
+    reward::mod_feeder::load_message_components,
+    robochick::twitch::{MessageComponents, TemplateContext},
+    state::StateStore,
+};
+
+/// Placeholders of the recap `message`: the month's name, e.g. `October`, how many times the
+/// mods were fed, the mod who won most often with their wins, and the most common scenario.
+pub const MONTH: &str = "month";
+pub const COUNT: &str = "count";
+pub const TOP_MOD: &str = "top_mod";
+pub const TOP_MOD_WINS: &str = "top_mod_wins";
+pub const TOP_SCENARIO: &str = "top_scenario";
+
+/// A summary of a month's feedings, posted on a schedule.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct RecapConfig {
+    /// When to post, e.g. `0 18 1 * *` for 6pm on the 1st. Checked every minute by the
+    /// standalone server, a Lambda deployment calls `/internal/monthly-recap` on the same
+    /// schedule instead.
+    #[schemars(with = "String")]
+    pub cron: CronExpr,
+    /// IANA name such as `Europe/London`. Defaults to UTC.
+    #[serde(default = "default_timezone")]
+    #[schemars(with = "String")]
+    pub timezone: Tz,
+    /// Which month is recapped. The previous one by default, for schedules early in the month.
+    #[serde(default)]
+    pub month: RecapMonth,
+    /// e.g. `In {month} the mods were fed {count} times. Top victim: {top_mod}!`
+    pub message: String,
+    /// Where the recap goes. Chat by default.
+    #[serde(default = "default_post_to")]
+    pub post_to: Vec<RecapTarget>,
+}
+
+fn default_timezone() -> Tz {
+    Tz::UTC
+}
+
+fn default_post_to() -> Vec<RecapTarget> {
+    vec![RecapTarget::Chat]
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecapMonth {
+    #[default]
+    Previous,
+    Current,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecapTarget {
+    Chat,
+    /// The channel of the webhook at `DISCORD_WEBHOOK_URL`.
+    Discord,
+}
+
+/// What happened during a month, from the monthly stats buckets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recap {
+    pub month: NaiveDate,
+    pub feedings: u64,
+    pub top_mod: Option<(String, u64)>,
+    pub top_scenario: Option<String>,
+}
+
+impl RecapConfig {
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        self.cron.matches(&now.with_timezone(&self.timezone))
+    }
+
+    /// First day of the month recapped when posting at `now`.
+    pub fn month_at(&self, now: DateTime<Utc>) -> NaiveDate {
+        let today = now.with_timezone(&self.timezone).date_naive();
+        let first = today.with_day(1).unwrap_or(today);
+        match self.month {
+            RecapMonth::Current => first,
+            RecapMonth::Previous => first
+                .checked_sub_days(Days::new(1))
+                .and_then(|d| d.with_day(1))
+                .unwrap_or(first),
+        }
+    }
+}
+
+impl Recap {
+    pub fn context(&self, locale: Locale) -> TemplateContext {
+        let mut context = TemplateContext::default();
+        context.set_locale(locale);
+        context.insert(MONTH, locale.month_name(self.month.month()));
+        context.insert(COUNT, self.feedings.to_string());
+        let (top_mod, wins) = self.top_mod.clone().unwrap_or(("nobody".into(), 0));
+        context.insert(TOP_MOD, top_mod);
+        context.insert(TOP_MOD_WINS, wins.to_string());
+        context.insert(TOP_SCENARIO, self.top_scenario.clone().unwrap_or_default());
+        context
+    }
+}
+
+/// Reads the recap of the month starting on `month`. Ties go to the name that sorts first.
+pub async fn build(state: &dyn StateStore, month: NaiveDate) -> Result<Recap> {
+    let key = month.format("%Y-%m").to_string();
+    let scenarios = state.scenario_counts(&key).await?;
+    let wins = state.month_win_counts(&key).await?;
+
+    let top = |counts: Vec<(String, u64)>| {
+        counts
+            .into_iter()
+            .filter(|(_, n)| *n > 0)
+            .max_by(|(a, an), (b, bn)| an.cmp(bn).then(b.cmp(a)))
+    };
+    Ok(Recap {
+        month,
+        feedings: scenarios.values().sum(),
+        top_mod: top(wins.into_iter().collect()),
+        top_scenario: top(scenarios.into_iter().collect()).map(|(s, _)| s),
+    })
+}
+
+/// Posts `content` to a Discord channel through its webhook.
+#[cfg(feature = "discord")]
+pub async fn post_discord(
+    client: &reqwest::Client,
+    webhook_url: &str,
+    content: &str,
+) -> Result<()> {
+    let response = client
+        .post(webhook_url)
+        .json(&json!({ "content": content }))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Discord webhook returned {}", response.status()));
+    }
+    Ok(())
+}
+
+impl RecapTarget {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RecapTarget::Chat => "chat",
+            RecapTarget::Discord => "discord",
+        }
+    }
+}
+
+/// Builds the recap `recap` asks for at `now` and posts it everywhere it should go. Returns
+/// the message, or `None` when nothing happened that month.
+///
+/// With `delivered`, each target is marked as seen under it before posting and released again
+/// when posting there fails, so a retry only posts where it didn't get through.
+pub async fn post(
+    app: &AppState,
+    components: &MessageComponents,
+    recap: &RecapConfig,
+    now: DateTime<Utc>,
+    delivered: Option<&str>,
+) -> Result<Option<String>> {
+    let mut built = build(app.state.as_ref(), recap.month_at(now)).await?;
+    if built.feedings == 0 {
+        println!("Nothing to recap for {}", built.month.format("%Y-%m"));
+        return Ok(None);
+    }
+    if components.shows_display_names()
+        && let Some((name, _)) = built.top_mod.as_mut()
+    {
+        let names = app
+            .users
+            .display_names(app.caller.as_ref(), std::slice::from_ref(name), &app.config)
+            .await;
+        if let Some(shown) = names.get(name) {
+            *name = shown.clone();
+        }
+    }
+
+    let mut context = built.context(components.get_locale());
+    components.resolve_variables(&mut context, &mut Rng::new())?;
+    let message = context.format(&recap.message)?;
+
+    let mut failed = vec![];
+    for target in &recap.post_to {
+        let seen = delivered.map(|d| format!("{d}#{}", target.as_str()));
+        if let Some(seen) = &seen {
+            match app.state.mark_seen(seen).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    println!("Recap {seen} already posted");
+                    continue;
+                }
+                Err(e) => println!("Failed to claim the recap, posting anyway: {e}"),
+            }
+        }
+
+        let result = match target {
+            RecapTarget::Chat => app.caller.say(&message, &app.config).await.map(|_| ()),
+            #[cfg(feature = "discord")]
+            RecapTarget::Discord => match &app.config.discord_webhook_url {
+                Some(url) => post_discord(app.web_client.http(), url, &message).await,
+                None => Err(anyhow!("DISCORD_WEBHOOK_URL not set")),
+            },
+            #[cfg(not(feature = "discord"))]
+            RecapTarget::Discord => Err(anyhow!("Posting to discord needs the discord feature")),
+        };
+        if let Err(e) = result {
+            failed.push(format!("{target:?}: {e}"));
+            if let Some(seen) = &seen
+                && let Err(e) = app.state.forget_seen(seen).await
+            {
+                println!("Failed to release the recap for a retry: {e}");
+            }
+        }
+    }
+    match failed.is_empty() {
+        true => Ok(Some(message)),
+        false => Err(anyhow!("Failed to post recap to {}", failed.join(", "))),
+    }
+}
+
+/// Posts the recap whenever its cron matches, for the long-running standalone server.
+/// Instances sharing a state store post it once.
+pub fn spawn_schedule(app: AppState) {
+    tokio::spawn(async move {
+        loop {
+            let now = Utc::now();
+            let next_minute = 60 - u64::from(now.second());
+            tokio::time::sleep(std::time::Duration::from_secs(next_minute)).await;
+
+            let now = Utc::now();
+            let components =
+                match load_message_components(app.state.as_ref(), &app.config_file).await {
+                    Ok(c) => c,
+                    Err(e) => {
+                        println!("Failed to load message config for the recap: {e}");
+                        continue;
+                    }
+                };
+            let Some(recap) = components.get_recap() else {
+                continue;
+            };
+            if !recap.is_due(now) {
+                continue;
+            }
+
+            let slot = format!("recap#{}", now.format("%Y-%m-%dT%H:%M"));
+            match app.state.start_cooldown(&slot, 120).await {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => println!("Failed to claim the recap, posting anyway: {e}"),
+            }
+            if let Err(e) = post(&app, &components, recap, now, None).await {
+                println!("{e}");
+            }
+        }
+    });
+}
+
+/// `POST /internal/monthly-recap` - posts the recap configured under `recap` straight away.
+/// For a schedule (e.g. an EventBridge rule) using the recap's cron. Each month is posted once
+/// to each target within the seen retention, so a retried call only posts where the last one
+/// failed.
+#[utoipa::path(post, path = "/internal/monthly-recap", tag = "internal",
+    security(("internal_token" = [])),
+    responses(
+        (status = 204, description = "Posted, or nothing to recap"),
+        (status = 401, description = "Missing or wrong `INTERNAL_API_TOKEN`"),
+        (status = 404, description = "No `recap` in the config"),
+        (status = 502, description = "Posting failed"),
+    ),
+)]
+pub async fn recap_handler(State(app): State<AppState>, headers: HeaderMap) -> Response<Body> {
+    if !auth::internal_request_authorized(&headers, &app.config) {
+        return empty_response(StatusCode::UNAUTHORIZED);
+    }
+
+    let components = match load_message_components(app.state.as_ref(), &app.config_file).await {
+        Ok(c) => c,
+        Err(e) => {
+            println!("Failed to load message config for the recap: {e}");
+            return empty_response(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    let Some(recap) = components.get_recap() else {
+        println!("No recap configured");
+        return empty_response(StatusCode::NOT_FOUND);
+    };
+
+    let now = Utc::now();
+    let posted = format!("recap#{}", recap.month_at(now).format("%Y-%m"));
+    match post(&app, &components, recap, now, Some(&posted)).await {
+        Ok(_) => empty_response(StatusCode::NO_CONTENT),
+        Err(e) => {
+            println!("{e}");
+            empty_response(StatusCode::BAD_GATEWAY)
+        }
+    }
+}
+
+fn empty_response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::Empty)
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use mockito::{Matcher, Server};
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use std::sync::Arc;
+
+    use axum::{extract::State, http::HeaderMap};
+    use reqwest::StatusCode;
+
+    use crate::{
+        app::App,
+        client::WebClient,
+        config::AppConfig,
+        locale::Locale,
+        recap::{RecapConfig, build, recap_handler},
+        state::{MemoryStore, StateStore},
+        stats::month_key,
+    };
+
+    fn recap(month: &str) -> RecapConfig {
+        serde_json::from_value(json!({
+            "cron": "0 18 1 * *",
+            "timezone": "America/New_York",
+            "month": month,
+            "message": "In {month} the mods were fed {count} times. Top victim: {top_mod}!"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn month_at_uses_the_local_date() {
+        // 02:00 UTC on November 1st is still October 31st in New York.
+        let now = Utc.with_ymd_and_hms(2026, 11, 1, 2, 0, 0).unwrap();
+
+        assert_eq!(
+            recap("previous").month_at(now),
+            NaiveDate::from_ymd_opt(2026, 9, 1).unwrap()
+        );
+        assert_eq!(
+            recap("current").month_at(now),
+            NaiveDate::from_ymd_opt(2026, 10, 1).unwrap()
+        );
+        assert!(recap("previous").is_due(Utc.with_ymd_and_hms(2026, 11, 1, 23, 0, 0).unwrap()));
+    }
+
+    #[tokio::test]
+    async fn build_counts_feedings_and_picks_top_victim() -> Result<()> {
+        let store = MemoryStore::default();
+        for scenario in ["cracker-trip", "cracker-trip", "guard-duty"] {
+            store.record_scenario("2026-10", scenario).await?;
+        }
+        store.record_scenario("2026-09", "guard-duty").await?;
+        store
+            .record_month_wins("2026-10", &["Jane".into(), "John".into()])
+            .await?;
+        store.record_month_wins("2026-10", &["Jane".into()]).await?;
+
+        let built = build(&store, NaiveDate::from_ymd_opt(2026, 10, 1).unwrap()).await?;
+        let message = built
+            .context(Locale::En)
+            .format("In {month} the mods were fed {count} times. Top victim: {top_mod} ({top_mod_wins}x), mostly {top_scenario}.")?;
+
+        assert_eq!(
+            message,
+            "In October the mods were fed 3 times. Top victim: Jane (2x), mostly cracker-trip."
+        );
+        assert_eq!(
+            built
+                .context(Locale::De)
+                .format("Im {month} gab es {number(count)} Fütterungen")?,
+            "Im Oktober gab es 3 Fütterungen"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn recap_handler_posts_each_month_once() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut server = Server::new_async().await;
+        let config = AppConfig {
+            internal_api_token: Some("internal-token".into()),
+            ..AppConfig::from_env().with_se_api_host(server.url())
+        };
+        let say = server
+            .mock("POST", "/kappa/v2/bot/test_channel_id/say")
+            .with_body("{}")
+            .expect(1)
+            .create_async()
+            .await;
+        let store = Arc::new(MemoryStore::default());
+        let config_body = json!({
+            "scenarios": [],
+            "mods": ["John"],
+            "recap": {"cron": "0 18 1 * *", "month": "current", "message": "Fed {count} times"}
+        });
+        store.put_config(&config_body.to_string(), 0).await?;
+        store
+            .record_scenario(&month_key(Utc::now()), "cracker-trip")
+            .await?;
+        let app = App::builder(config)
+            .with_aws(
+                aws_config::SdkConfig::builder()
+                    .behavior_version(aws_config::BehaviorVersion::latest())
+                    .build(),
+            )
+            .with_state(store)
+            .with_web_client(WebClient::new(reqwest::Client::new()))
+            .build()
+            .await;
+        let mut headers = HeaderMap::new();
+        headers.insert("Authorization", "Bearer internal-token".parse()?);
+
+        for _ in 0..2 {
+            let response = recap_handler(State(app.state().clone()), headers.clone()).await;
+            assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        }
+
+        say.assert_async().await;
+        Ok(())
+    }
+
+    #[cfg(feature = "discord")]
+    #[tokio::test]
+    async fn recap_handler_retries_only_the_targets_that_failed() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut server = Server::new_async().await;
+        let config = AppConfig {
+            internal_api_token: Some("internal-token".into()),
+            discord_webhook_url: Some(format!("{}/api/webhooks/1/token", server.url())),
+            ..AppConfig::from_env().with_se_api_host(server.url())
+        };
+        let say = server
+            .mock("POST", "/kappa/v2/bot/test_channel_id/say")
+            .with_body("{}")
+            .expect(1)
+            .create_async()
+            .await;
+        let down = server
+            .mock("POST", "/api/webhooks/1/token")
+            .with_status(500)
+            .expect(1)
+            .create_async()
+            .await;
+        let store = Arc::new(MemoryStore::default());
+        let config_body = json!({
+            "scenarios": [],
+            "mods": ["John"],
+            "recap": {
+                "cron": "0 18 1 * *",
+                "month": "current",
+                "message": "Fed {count} times",
+                "post_to": ["chat", "discord"]
+            }
+        });
+        store.put_config(&config_body.to_string(), 0).await?;
+        store
+            .record_scenario(&month_key(Utc::now()), "cracker-trip")
+            .await?;
+        let app = App::builder(config)
+            .with_aws(
+                aws_config::SdkConfig::builder()
+                    .behavior_version(aws_config::BehaviorVersion::latest())
+                    .build(),
+            )
+            .with_state(store)
+            .with_web_client(WebClient::new(reqwest::Client::new()))
+            .build()
+            .await;
+        let mut headers = HeaderMap::new();
+        headers.insert("Authorization", "Bearer internal-token".parse()?);
+
+        let response = recap_handler(State(app.state().clone()), headers.clone()).await;
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        down.assert_async().await;
+        down.remove_async().await;
+
+        let up = server
+            .mock("POST", "/api/webhooks/1/token")
+            .with_status(204)
+            .expect(1)
+            .create_async()
+            .await;
+        let response = recap_handler(State(app.state().clone()), headers).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        say.assert_async().await;
+        up.assert_async().await;
+        Ok(())
+    }
+
+    #[cfg(feature = "discord")]
+    #[tokio::test]
+    async fn post_discord_sends_webhook_content() -> Result<()> {
+        use crate::recap::post_discord;
+
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/webhooks/1/token")
+            .match_body(Matcher::Json(json!({"content": "Recap!"})))
+            .with_status(204)
+            .expect(1)
+            .create_async()
+            .await;
+
+        post_discord(
+            &reqwest::Client::new(),
+            &format!("{}/api/webhooks/1/token", server.url()),
+            "Recap!",
+        )
+        .await?;
+
+        mock.assert_async().await;
+        Ok(())
+    }
+}
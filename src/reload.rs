@@ -0,0 +1,211 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::{Result, anyhow};
+use axum::{extract::State, http::HeaderMap};
+use chrono::Utc;
+use lambda_http::{Body, Response};
+use reqwest::StatusCode;
+use serde_json::Value;
+
+use crate::{
+    AppState,
+    admin_tokens::AdminScope,
+    config_audit::{self, ChangeSource, ConfigAuditEntry},
+    migrate,
+    reward::mod_feeder::read_config,
+    robochick::twitch::MessageComponents,
+    state::StoredConfig,
+};
+
+/// Actor recorded for reloads triggered by SIGHUP.
+const SIGNAL_ACTOR: &str = "signal";
+
+/// The message config file, parsed once and kept in memory until it's reloaded. A reload only
+/// swaps the config in once the new file has parsed, so a broken edit leaves the last good
+/// config in place. The config saved through the admin API is kept parsed next to it, by
+/// version.
+pub struct ConfigFile {
+    path: PathBuf,
+    current: RwLock<Option<Arc<MessageComponents>>>,
+    saved: RwLock<Option<(u64, Arc<MessageComponents>)>>,
+}
+
+impl ConfigFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        ConfigFile {
+            path: path.into(),
+            current: RwLock::new(None),
+            saved: RwLock::new(None),
+        }
+    }
+
+    /// The saved config `stored`, only parsed when its version isn't the one parsed last.
+    pub fn parse_saved(&self, stored: &StoredConfig) -> Result<Arc<MessageComponents>> {
+        if let Some((version, saved)) = self.saved.read().unwrap().as_ref()
+            && *version == stored.version
+        {
+            return Ok(saved.clone());
+        }
+
+        let components = migrate::parse_config_lenient(&stored.body)
+            .map(|(components, _)| Arc::new(components))
+            .map_err(|e| anyhow!("Failed to deserialize stored message config: {e}"))?;
+        *self.saved.write().unwrap() = Some((stored.version, components.clone()));
+        Ok(components)
+    }
+
+    /// The config in memory, reading the file the first time.
+    pub fn get(&self) -> Result<Arc<MessageComponents>> {
+        if let Some(current) = self.current.read().unwrap().as_ref() {
+            return Ok(current.clone());
+        }
+
+        self.reload()
+    }
+
+    /// The config in memory as JSON, `null` before the file has been read.
+    pub fn loaded_json(&self) -> Value {
+        self.current
+            .read()
+            .unwrap()
+            .as_ref()
+            .and_then(|c| serde_json::to_value(c.as_ref()).ok())
+            .unwrap_or(Value::Null)
+    }
+
+    /// Reads the file again and swaps it in.
+    pub fn reload(&self) -> Result<Arc<MessageComponents>> {
+        let components = Arc::new(read_config(&self.path)?);
+        *self.current.write().unwrap() = Some(components.clone());
+        Ok(components)
+    }
+}
+
+/// `POST /admin/reload` - reloads the message config file. A config saved through
+/// `PUT /admin/config` still takes priority over the file.
+#[utoipa::path(post, path = "/admin/reload", tag = "admin",
+    security(("admin_token" = [])),
+    responses(
+        (status = 204, description = "Reloaded"),
+        (status = 400, description = "The file isn't a valid config"),
+        (status = 401, description = "Token missing or unknown"),
+        (status = 403, description = "Token not scoped for this"),
+    ),
+)]
+pub async fn reload_handler(State(state): State<AppState>, headers: HeaderMap) -> Response<Body> {
+    let actor = match state.admin_actor(&headers, AdminScope::ConfigWrite).await {
+        Ok(actor) => actor,
+        Err(status) => {
+            return Response::builder()
+                .status(status)
+                .body(Body::Empty)
+                .unwrap();
+        }
+    };
+
+    let before = state.config_file.loaded_json();
+    match state.config_file.reload() {
+        Ok(_) => {
+            println!("Reloaded message config file");
+            let after = state.config_file.loaded_json();
+            let entry = ConfigAuditEntry::new(
+                &actor,
+                ChangeSource::Reload,
+                None,
+                &before,
+                &after,
+                Utc::now(),
+            );
+            config_audit::record_change(&state, entry).await;
+            Response::builder()
+                .status(StatusCode::NO_CONTENT)
+                .body(Body::Empty)
+                .unwrap()
+        }
+        Err(e) => {
+            println!("Keeping previous message config, reload failed: {e}");
+            Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(e.to_string()))
+                .unwrap()
+        }
+    }
+}
+
+/// Reloads the config file whenever the process gets SIGHUP, for the standalone server.
+#[cfg(unix)]
+pub fn reload_on_sighup(state: AppState) -> Result<()> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut hangups = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            let before = state.config_file.loaded_json();
+            match state.config_file.reload() {
+                Ok(_) => {
+                    println!("Reloaded message config file on SIGHUP");
+                    let after = state.config_file.loaded_json();
+                    let entry = ConfigAuditEntry::new(
+                        SIGNAL_ACTOR,
+                        ChangeSource::Sighup,
+                        None,
+                        &before,
+                        &after,
+                        Utc::now(),
+                    );
+                    config_audit::record_change(&state, entry).await;
+                }
+                Err(e) => println!("Keeping previous message config, reload failed: {e}"),
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use anyhow::Result;
+
+    use crate::{reload::ConfigFile, state::StoredConfig};
+
+    #[test]
+    fn reload_swaps_config_only_when_file_parses() -> Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("robochick-reload-{}.json", fastrand::u64(..)));
+        std::fs::write(&path, r#"{"scenarios":[],"mods":["John"]}"#)?;
+        let file = ConfigFile::new(&path);
+
+        let first = file.get()?;
+        assert_eq!(first.get_mods(), ["John"]);
+
+        std::fs::write(&path, r#"{"scenarios":[],"mods":["Jane"]}"#)?;
+        assert!(Arc::ptr_eq(&first, &file.get()?));
+        assert_eq!(file.reload()?.get_mods(), ["Jane"]);
+
+        std::fs::write(&path, r#"{"scenarios":[],"mods":"#)?;
+        assert!(file.reload().is_err());
+        assert_eq!(file.get()?.get_mods(), ["Jane"]);
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn saved_config_is_parsed_once_per_version() -> Result<()> {
+        let file = ConfigFile::new("resources/tests/message_components.json");
+        let saved = |version, mods| StoredConfig {
+            version,
+            body: format!(r#"{{"scenarios":[],"mods":["{mods}"]}}"#),
+        };
+
+        let first = file.parse_saved(&saved(1, "John"))?;
+        assert!(Arc::ptr_eq(&first, &file.parse_saved(&saved(1, "John"))?));
+        assert_eq!(file.parse_saved(&saved(2, "Jane"))?.get_mods(), ["Jane"]);
+        Ok(())
+    }
+}
@@ -1,6 +1,8 @@
 use anyhow::Result;
 use async_trait::async_trait;
 
+use serde_json::Value;
+
 use crate::{config::AppConfig, types::twitch::RewardRedeemed};
 
 pub mod ducks;
@@ -8,10 +10,18 @@ pub mod mod_feeder;
 
 #[async_trait]
 pub trait RewardHandler: Send + Sync {
+    /// Acts on the redemption, returning the scenario posted to chat, if any.
     async fn handle(
         &self,
         msg_id: String,
         redeem: &RewardRedeemed,
         config: &AppConfig,
-    ) -> Result<()>;
+    ) -> Result<Option<String>>;
+}
+
+/// Runs the configured pipeline rules for EventSub notifications.
+#[async_trait]
+pub trait EventPipeline: Send + Sync {
+    /// Runs every rule matching the event, returning how many did.
+    async fn run(&self, event_type: &str, event: &Value, config: &AppConfig) -> Result<usize>;
 }
@@ -14,10 +14,10 @@ impl RewardHandler for DuckRedeemed {
         msg_id: String,
         redeem: &RewardRedeemed,
         config: &AppConfig,
-    ) -> Result<()> {
-        let username = redeem.event.username();
-        let display_name = redeem.event.display_name();
-        let redemption_ts = redeem.event.redeemed_at();
+    ) -> Result<Option<String>> {
+        let username = redeem.user_login();
+        let display_name = redeem.user_name();
+        let redemption_ts = redeem.redeemed_at();
         let now_ts = chrono::Utc::now().to_rfc3339();
 
         match self
@@ -25,6 +25,7 @@ impl RewardHandler for DuckRedeemed {
             .put_item()
             .table_name(config.duck_rewards_table_name.clone())
             .item("message_id", AttributeValue::S(msg_id.clone()))
+            .item("user_id", AttributeValue::S(redeem.user_id().to_string()))
             .item("username", AttributeValue::S(username.to_string()))
             .item("display_name", AttributeValue::S(display_name.to_string()))
             .item("redeemed_at", AttributeValue::S(redemption_ts.to_string()))
@@ -33,13 +34,13 @@ impl RewardHandler for DuckRedeemed {
             .send()
             .await
         {
-            Ok(_) => Ok(()),
+            Ok(_) => Ok(None),
             Err(SdkError::ServiceError(e)) if e.err().is_conditional_check_failed_exception() => {
                 println!(
                     "A record with this message-id {} already exists, ignoring.",
                     msg_id
                 );
-                Ok(())
+                Ok(None)
             }
             Err(_) => Err(anyhow!("Failed to insert duck redeem to table")),
         }
@@ -71,9 +72,9 @@ mod tests {
         payload_path.push("resources/tests/reward_redemption_event.json");
         let payload = std::fs::read_to_string(payload_path)?;
         let redemption: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
-        let expected_username = redemption.event.username().to_string();
-        let expected_display_name = redemption.event.display_name().to_string();
-        let expected_redeemed_at = redemption.event.redeemed_at().to_string();
+        let expected_username = redemption.user_login().to_string();
+        let expected_display_name = redemption.user_name().to_string();
+        let expected_redeemed_at = redemption.redeemed_at().to_string();
 
         let mut expected_attrs: HashMap<String, AttributeValue> = HashMap::new();
         let msg_id = String::from("Message-Id");
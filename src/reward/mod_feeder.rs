@@ -1,126 +1,2756 @@
-use super::RewardHandler;
+use super::{EventPipeline, RewardHandler};
 use crate::{
-    client::StreamelementsCaller,
+    action::{ActionScheduler, PollOutcome, PollResolution, QueuedAction, RewardPause},
+    budget::{Budget, BudgetCheck, BudgetWindow},
+    burst::{BurstCheck, BurstGuard},
+    cache::TtlCache,
+    channel,
+    client::{HelixCaller, StreamelementsCaller},
+    commands::{ChatMessage, CommandConfig, Commands, Dispatch, parse},
     config::AppConfig,
-    robochick::twitch::{MessageBuilder, MessageComponents, Robochick},
-    types::twitch::RewardRedeemed,
+    deadline::OutboxEntry,
+    decline::{self, DECLINE_COOLDOWN_SECS, DECLINES_GROUP, DeclineReason},
+    donations::Donation,
+    etag::{self, Dataset},
+    experiment, metrics, migrate,
+    offline::{self, OfflineBehavior, QueuedRedemption},
+    permissions::RoleGate,
+    pipeline::{Step, event_context},
+    probe, quiet,
+    reload::ConfigFile,
+    robochick::twitch::{
+        AMOUNT, AntiRepeatPicker, BuiltMessage, BuiltVote, DONATIONS_GROUP, DONOR, FairPicker,
+        MessageBuilder, MessageComponents, ModPicker, NEXT_STREAM, NEXT_STREAM_TITLE,
+        RANDOM_VIEWER, REWARD_COST, REWARD_PROMPT, REWARD_TITLE, RandomPicker, Robochick,
+        RoundRobinPicker, ScenarioGroup, Selection, TemplateContext, USER_LOGIN, USER_NAME,
+        pick_random,
+    },
+    schedule,
+    state::StateStore,
+    stats::{self, month_key, week_key},
+    types::twitch::{RewardRedeemed, SubscriptionType},
+    users::UserCache,
 };
 use anyhow::{Result, anyhow};
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use fastrand::Rng;
-use std::path::PathBuf;
+use serde_json::Value;
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
-pub struct ModFeed<C: StreamelementsCaller> {
+pub struct ModFeed<C: StreamelementsCaller + HelixCaller> {
     pub client: C,
+    pub chatters: Arc<TtlCache<String, Vec<String>>>,
+    /// Whether the broadcaster is live, keyed by broadcaster id.
+    pub live: Arc<TtlCache<String, bool>>,
+    pub users: Arc<UserCache>,
+    pub actions: Arc<dyn ActionScheduler>,
+    pub state: Arc<dyn StateStore>,
+    pub config_file: Arc<ConfigFile>,
+}
+
+/// Who a post is for: the viewer chat messages are credited to when it shows an experiment
+/// variant, if there is one, and the name `{random_viewer}` falls back to.
+#[derive(Clone, Copy)]
+struct Viewer<'a> {
+    id: Option<&'a str>,
+    name: &'a str,
+}
+
+/// How a round-robin group's rotation moves on once its message is posted.
+struct RoundRobinMove {
+    /// Mod it was at when the message was built.
+    from: Option<String>,
+    to: String,
+}
+
+/// Extra time given to Twitch to close a poll before its results are read.
+const POLL_RESOLUTION_GRACE_SECS: u64 = 5;
+
+/// `{next_stream}` when nothing is on the schedule.
+const NO_NEXT_STREAM: &str = "not scheduled yet";
+
+impl<C: StreamelementsCaller + HelixCaller> ModFeed<C> {
+    /// Category the stream is in, looked up only when a group depends on it.
+    async fn current_game(
+        &self,
+        components: &MessageComponents,
+        config: &AppConfig,
+    ) -> Option<String> {
+        if !components.has_game_groups() {
+            return None;
+        }
+        channel::current_category(self.state.as_ref(), &self.client, config).await
+    }
+
+    /// Display names of the mods, when the config shows them.
+    async fn mod_display_names(
+        &self,
+        components: &MessageComponents,
+        config: &AppConfig,
+    ) -> HashMap<String, String> {
+        match components.shows_display_names() {
+            true => {
+                self.users
+                    .display_names(&self.client, components.get_mods(), config)
+                    .await
+            }
+            false => HashMap::new(),
+        }
+    }
+
+    /// The configured mods less the ones who opted out from chat. All of them when the opt-outs
+    /// can't be read or would leave nobody to pick.
+    async fn participating_mods(&self, mods: &[String]) -> Vec<String> {
+        let opted_out = match self.state.opted_out_mods().await {
+            Ok(o) => o,
+            Err(e) => {
+                println!("Failed to read opted out mods, picking from all of them: {e}");
+                return mods.to_vec();
+            }
+        };
+        let participating: Vec<String> = mods
+            .iter()
+            .filter(|m| !opted_out.contains(&m.to_lowercase()))
+            .cloned()
+            .collect();
+        if participating.is_empty() && !mods.is_empty() {
+            println!("Every mod opted out, picking from all of them");
+            return mods.to_vec();
+        }
+        participating
+    }
+
+    /// Picks a random chatter, falling back to `fallback` when the chatters list is unavailable.
+    async fn random_viewer(&self, fallback: &str, config: &AppConfig, rng: &mut Rng) -> String {
+        let chatters = match self.chatters.get(&config.broadcaster_user_id) {
+            Some(c) => c,
+            None => match self.client.get_chatters(config).await {
+                Ok(c) => {
+                    self.chatters
+                        .insert(config.broadcaster_user_id.clone(), c.clone());
+                    c
+                }
+                Err(e) => {
+                    println!("Failed to fetch chatters: {e}");
+                    vec![]
+                }
+            },
+        };
+
+        pick_random(&chatters, 1, rng)
+            .pop()
+            .unwrap_or(fallback.to_string())
+    }
+
+    /// Whether the broadcaster is live, cached for `STREAM_STATE_CACHE_TTL_SECS`. When Twitch
+    /// can't say, a stream being on the schedule right now counts, and failing that the stream
+    /// is assumed to be live.
+    async fn is_live(&self, config: &AppConfig) -> bool {
+        if let Some(live) = self.live.get(&config.broadcaster_user_id) {
+            return live;
+        }
+
+        let live = self.check_live(config).await;
+        self.live.insert(config.broadcaster_user_id.clone(), live);
+        live
+    }
+
+    async fn check_live(&self, config: &AppConfig) -> bool {
+        let e = match self.client.get_stream_id(config).await {
+            Ok(stream) => return stream.is_some(),
+            Err(e) => e,
+        };
+        println!("Failed to fetch stream, checking the schedule: {e}");
+        match self.client.get_schedule(config).await {
+            Ok(segments) => schedule::scheduled_at(&segments, Utc::now()),
+            Err(e) => {
+                println!("Failed to fetch schedule: {e}");
+                true
+            }
+        }
+    }
+
+    /// Fills `{next_stream}` and `{next_stream_title}` from the broadcaster's schedule.
+    async fn fill_next_stream(
+        &self,
+        context: &mut TemplateContext,
+        message_components: &MessageComponents,
+        config: &AppConfig,
+    ) {
+        let segments = match self.client.get_schedule(config).await {
+            Ok(s) => s,
+            Err(e) => {
+                println!("Failed to fetch schedule: {e}");
+                vec![]
+            }
+        };
+        match schedule::next_stream(&segments, Utc::now()) {
+            Some(next) => {
+                context.insert(
+                    NEXT_STREAM,
+                    next.describe(message_components.get_schedule_timezone()),
+                );
+                context.insert(NEXT_STREAM_TITLE, &next.title);
+            }
+            None => {
+                context.insert(NEXT_STREAM, NO_NEXT_STREAM);
+                context.insert(NEXT_STREAM_TITLE, "");
+            }
+        }
+    }
+}
+
+impl<C: StreamelementsCaller + HelixCaller> ModFeed<C> {
+    /// Builds a message from the group using its selection strategy. With round-robin, also
+    /// returns where its rotation moves on to once the message is posted.
+    async fn build_message(
+        &self,
+        group: &ScenarioGroup,
+        mods: &[String],
+        anti_repeat_window: usize,
+        context: &TemplateContext,
+        rng: &mut Rng,
+    ) -> Result<(BuiltMessage, Option<RoundRobinMove>)> {
+        let selection = group.get_selection();
+        let recent = if anti_repeat_window > 0 {
+            match self.state.recent_winners(group.get_name()).await {
+                Ok(r) => r,
+                Err(e) => {
+                    println!("Failed to read recent winners: {e}");
+                    vec![]
+                }
+            }
+        } else {
+            vec![]
+        };
+
+        let mut round_robin = RoundRobinPicker {
+            last: None,
+            recent: vec![],
+        };
+        if selection == Selection::RoundRobin {
+            round_robin.last = match self.state.last_round_robin(group.get_name()).await {
+                Ok(last) => last,
+                Err(e) => {
+                    println!("Failed to read round-robin, starting over: {e}");
+                    None
+                }
+            };
+        }
+        let from = round_robin.last.clone();
+
+        let mut fair = FairPicker {
+            wins: HashMap::new(),
+        };
+        if selection == Selection::Fair {
+            match stats::fair_picker(self.state.as_ref(), Utc::now()).await {
+                Ok(picker) => fair = picker,
+                Err(e) => println!("Failed to read mod wins, picking evenly: {e}"),
+            }
+        }
+
+        let built = match selection {
+            // Round-robin passes over recent winners itself, keeping the rotation in order.
+            Selection::RoundRobin => {
+                round_robin.recent = recent;
+                Robochick::build_from_group(group, mods, &mut round_robin, context, rng)?
+            }
+            Selection::Random => {
+                let mut picker = AntiRepeatPicker {
+                    inner: &mut RandomPicker,
+                    recent,
+                };
+                Robochick::build_from_group(group, mods, &mut picker, context, rng)?
+            }
+            Selection::Fair => {
+                let mut picker = AntiRepeatPicker {
+                    inner: &mut fair,
+                    recent,
+                };
+                Robochick::build_from_group(group, mods, &mut picker, context, rng)?
+            }
+        };
+
+        let moved = match round_robin.last {
+            Some(to) if selection == Selection::RoundRobin && from.as_ref() != Some(&to) => {
+                Some(RoundRobinMove { from, to })
+            }
+            _ => None,
+        };
+
+        if probe::active() {
+            return Ok((built, moved));
+        }
+        if anti_repeat_window > 0 {
+            match self
+                .state
+                .push_recent_winners(group.get_name(), &built.winners, anti_repeat_window)
+                .await
+            {
+                Ok(()) => etag::touch(self.state.as_ref(), Dataset::RecentWinners).await,
+                Err(e) => println!("Failed to store recent winners: {e}"),
+            }
+        }
+        Ok((built, moved))
+    }
+
+    /// Counts the redemption against the budget's current window. Counter failures, and
+    /// per-stream budgets without a live stream, let the redemption through rather than
+    /// silently dropping it.
+    async fn spend_budget(
+        &self,
+        budget: &Budget,
+        reward_id: &str,
+        config: &AppConfig,
+    ) -> BudgetCheck {
+        let stream_id = match budget.per {
+            BudgetWindow::Stream => match self.client.get_stream_id(config).await {
+                Ok(id) => id,
+                Err(e) => {
+                    println!("Failed to look up the live stream for the budget: {e}");
+                    None
+                }
+            },
+            BudgetWindow::Hour => None,
+        };
+
+        let Some(bucket) = budget.bucket(Utc::now(), stream_id.as_deref()) else {
+            return BudgetCheck::Within;
+        };
+        let counter = format!("budget#{reward_id}#{bucket}");
+        match self
+            .state
+            .increment_counter(&counter, Some(budget.bucket_ttl_secs()))
+            .await
+        {
+            Ok(count) => budget.check(count),
+            Err(e) => {
+                println!("Failed to count redemption against the budget: {e}");
+                BudgetCheck::Within
+            }
+        }
+    }
+
+    /// Announces that the budget is used up and pauses the reward until it resumes.
+    async fn close_reward(&self, budget: &Budget, reward_id: &str, config: &AppConfig) {
+        if let Err(e) = self.client.say(&budget.full_message, config).await {
+            println!("Failed to post budget message: {e}");
+        }
+
+        self.pause_reward(reward_id, budget.resume_delay_secs(Utc::now()), config)
+            .await;
+    }
+
+    /// Counts the redemption towards the viewer's and chat's bursts. Counter failures let the
+    /// redemption through. The counters expire once their window is over.
+    async fn check_burst(
+        &self,
+        guard: &BurstGuard,
+        redeem: &RewardRedeemed,
+        config: &AppConfig,
+    ) -> BurstCheck {
+        let (user_counter, total_counter) =
+            guard.counters(redeem.reward_id(), redeem.user_id(), Utc::now());
+        let ttl_secs = Some(guard.window_secs.max(1));
+        let counts = tokio::try_join!(
+            self.state.increment_counter(&user_counter, ttl_secs),
+            self.state.increment_counter(&total_counter, ttl_secs)
+        );
+        let check = match counts {
+            Ok((user_count, total_count)) => guard.check(user_count, total_count),
+            Err(e) => {
+                println!("Failed to count redemption towards a burst: {e}");
+                return BurstCheck::Within;
+            }
+        };
+
+        if let BurstCheck::Started { count } = check {
+            let mut context = TemplateContext::default();
+            context.insert(USER_NAME, redeem.user_name());
+            context.insert(offline::COUNT, count.to_string());
+            match context.format(&guard.message) {
+                Ok(message) => {
+                    if let Err(e) = self.client.say(&message, config).await {
+                        println!("Failed to post burst message: {e}");
+                    }
+                }
+                Err(e) => println!("Failed to fill in burst message: {e}"),
+            }
+            if let Some(secs) = guard.pause_secs {
+                self.pause_reward(redeem.reward_id(), Some(secs), config)
+                    .await;
+            }
+        }
+        check
+    }
+
+    /// Refunds a redemption ignored during a burst. The decline is posted for the first of
+    /// these in each window only, so a burst across chat doesn't flood it with declines.
+    async fn decline_burst(
+        &self,
+        guard: &BurstGuard,
+        message_components: &MessageComponents,
+        redeem: &RewardRedeemed,
+        config: &AppConfig,
+    ) {
+        let (_, window) = guard.counters(redeem.reward_id(), redeem.user_id(), Utc::now());
+        let decline = async {
+            match self
+                .state
+                .start_cooldown(&format!("decline#{window}"), guard.window_secs.max(1))
+                .await
+            {
+                Ok(true) => {
+                    self.post_decline(
+                        message_components,
+                        DeclineReason::Burst,
+                        redeem.user_id(),
+                        redeem.user_name(),
+                        redeem.user_login(),
+                        config,
+                    )
+                    .await
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    println!("Failed to check the burst decline cooldown, not posting one: {e}")
+                }
+            }
+        };
+        let (_, refunded) = tokio::join!(
+            decline,
+            self.client
+                .cancel_redemption(redeem.reward_id(), redeem.redemption_id(), config)
+        );
+        if let Err(e) = refunded {
+            println!(
+                "Failed to refund redemption {}: {e}",
+                redeem.redemption_id()
+            );
+        }
+    }
+
+    /// Pauses the reward, unpausing it after `resume_after_secs` when set.
+    async fn pause_reward(
+        &self,
+        reward_id: &str,
+        resume_after_secs: Option<u64>,
+        config: &AppConfig,
+    ) {
+        if let Err(e) = self.client.set_reward_paused(reward_id, true, config).await {
+            println!("Failed to pause reward {reward_id}: {e}");
+            return;
+        }
+
+        if let Some(delay) = resume_after_secs
+            && let Err(e) = self
+                .actions
+                .schedule(
+                    QueuedAction::SetRewardPaused(RewardPause {
+                        reward_id: reward_id.to_string(),
+                        paused: false,
+                    }),
+                    delay,
+                    config,
+                )
+                .await
+        {
+            println!("Failed to schedule unpausing reward {reward_id}: {e}");
+        }
+    }
+
+    /// Checks the redeemer's roles, always allowing the broadcaster. A failed lookup is an
+    /// error rather than a decision either way, so the gate stays closed without refunding
+    /// someone who may well be allowed, and Twitch's retry checks again.
+    async fn redeemer_allowed(
+        &self,
+        gate: &RoleGate,
+        redeem: &RewardRedeemed,
+        config: &AppConfig,
+    ) -> Result<bool> {
+        let user_id = redeem.user_id();
+        if user_id == redeem.broadcaster_user_id() {
+            return Ok(true);
+        }
+
+        match self.client.get_user_roles(user_id, config).await {
+            Ok(roles) => Ok(gate.allows(&roles)),
+            Err(e) => Err(anyhow!("Failed to look up roles of {user_id}: {e}")),
+        }
+    }
+
+    /// Tells the redeemer they can't use the reward and refunds their points, both at once.
+    async fn decline(&self, gate: &RoleGate, redeem: &RewardRedeemed, config: &AppConfig) {
+        let message = gate.decline_message_for(redeem.user_name());
+        let (said, cancelled) = tokio::join!(
+            self.client.say(&message, config),
+            self.client
+                .cancel_redemption(redeem.reward_id(), redeem.redemption_id(), config)
+        );
+
+        if let Err(e) = said {
+            println!("Failed to post decline message: {e}");
+        }
+        if let Err(e) = cancelled {
+            println!(
+                "Failed to refund redemption {}: {e}",
+                redeem.redemption_id()
+            );
+        }
+    }
+
+    /// Handles a redemption that isn't a retry of one already handled.
+    async fn handle_unseen(
+        &self,
+        redeem: &RewardRedeemed,
+        config: &AppConfig,
+    ) -> Result<Option<String>> {
+        let message_components =
+            match load_message_components(self.state.as_ref(), &self.config_file).await {
+                Ok(m) => m,
+                Err(e) => {
+                    println!("Error reading message configuration file: {e}");
+                    return Ok(None);
+                }
+            };
+
+        // Probes skip everything that would stop or count a real redemption.
+        let probing = probe::active();
+        if !probing && quiet::is_quiet(message_components.get_quiet_hours(), Utc::now()) {
+            println!("Quiet hours, not posting for {}", redeem.reward_id());
+            return Ok(None);
+        }
+
+        let game = self.current_game(&message_components, config).await;
+        let group =
+            message_components.group_for_reward(redeem.reward_id(), Utc::now(), game.as_deref());
+        if let Some(gate) = group.get_requires().filter(|_| !probing)
+            && !self.redeemer_allowed(gate, redeem, config).await?
+        {
+            self.decline(gate, redeem, config).await;
+            return Ok(None);
+        }
+
+        if let Some(budget) = group.get_budget().filter(|_| !probing) {
+            match self.spend_budget(budget, redeem.reward_id(), config).await {
+                BudgetCheck::Within => {}
+                BudgetCheck::JustFull => {
+                    self.close_reward(budget, redeem.reward_id(), config).await;
+                    return Ok(None);
+                }
+                BudgetCheck::AlreadyFull => {
+                    println!("Budget for {} is used up, ignoring", redeem.reward_id());
+                    self.post_decline(
+                        &message_components,
+                        DeclineReason::Budget,
+                        redeem.user_id(),
+                        redeem.user_name(),
+                        redeem.user_login(),
+                        config,
+                    )
+                    .await;
+                    return Ok(None);
+                }
+            }
+        }
+
+        if let Some(guard) = message_components.get_burst().filter(|_| !probing) {
+            let check = self.check_burst(guard, redeem, config).await;
+            if check == BurstCheck::Ongoing {
+                self.decline_burst(guard, &message_components, redeem, config)
+                    .await;
+            }
+            if check != BurstCheck::Within {
+                println!(
+                    "Redemption of {} by {} is part of a burst, not posting",
+                    redeem.reward_id(),
+                    redeem.user_login()
+                );
+                return Ok(None);
+            }
+        }
+
+        match message_components.get_when_offline() {
+            OfflineBehavior::Post => {}
+            _ if probing || self.is_live(config).await => {}
+            OfflineBehavior::Drop => {
+                println!("Stream is offline, not posting for {}", redeem.reward_id());
+                return Ok(None);
+            }
+            OfflineBehavior::Queue => {
+                let queued = QueuedRedemption::from_redemption(redeem, Utc::now());
+                // Failing lets go of the redemption, for Twitch's retry to queue it.
+                self.state
+                    .queue_offline(&serde_json::to_string(&queued)?)
+                    .await?;
+                println!(
+                    "Stream is offline, queued {} until live",
+                    redeem.reward_id()
+                );
+                return Ok(None);
+            }
+        }
+
+        let mut context = TemplateContext::default();
+        context.insert(REWARD_TITLE, redeem.reward_title());
+        context.insert(REWARD_COST, redeem.reward_cost().to_string());
+        context.insert(REWARD_PROMPT, redeem.reward_prompt());
+        context.insert(USER_NAME, redeem.user_name());
+        context.insert(USER_LOGIN, redeem.user_login());
+
+        self.post(
+            &group.for_viewer(Some(redeem.user_id())),
+            &message_components,
+            context,
+            Viewer {
+                id: Some(redeem.user_id()),
+                name: redeem.user_name(),
+            },
+            redeem.sent_at,
+            config,
+        )
+        .await
+    }
+
+    /// Starts a poll for the vote and schedules its outcome to be announced once it closes.
+    async fn start_vote(&self, vote: BuiltVote, config: &AppConfig) -> Result<()> {
+        let poll_id = self
+            .client
+            .create_poll(&vote.question, &vote.choices, vote.duration_secs, config)
+            .await?;
+
+        let outcomes = vote
+            .choices
+            .into_iter()
+            .zip(vote.outcomes)
+            .map(|(choice, message)| PollOutcome { choice, message })
+            .collect();
+
+        self.actions
+            .schedule(
+                QueuedAction::ResolvePoll(PollResolution { poll_id, outcomes }),
+                vote.duration_secs + POLL_RESOLUTION_GRACE_SECS,
+                config,
+            )
+            .await
+    }
 }
 
 #[async_trait]
-impl<C: StreamelementsCaller> RewardHandler for ModFeed<C> {
+impl<C: StreamelementsCaller + HelixCaller> RewardHandler for ModFeed<C> {
     async fn handle(
         &self,
-        _msg_id: String,
+        msg_id: String,
         redeem: &RewardRedeemed,
         config: &AppConfig,
-    ) -> Result<()> {
-        let msg_config_path = PathBuf::from(config.message_components_config_path.clone());
-        let message_components: MessageComponents = match read_config(&msg_config_path) {
+    ) -> Result<Option<String>> {
+        let seen = format!("message#{msg_id}");
+        match self.state.mark_seen(&seen).await {
+            Ok(true) => {}
+            Ok(false) => {
+                println!("Message {msg_id} was already handled, ignoring the retry");
+                return Ok(None);
+            }
+            Err(e) => println!("Failed to check for a retried message, handling it: {e}"),
+        }
+
+        let handled = self.handle_unseen(redeem, config).await;
+        // Let go of a redemption that failed, so Twitch's retry of it is handled.
+        if handled.is_err()
+            && let Err(e) = self.state.forget_seen(&seen).await
+        {
+            println!("Failed to release {msg_id} for a retry: {e}");
+        }
+        handled
+    }
+}
+
+#[async_trait]
+impl<C: StreamelementsCaller + HelixCaller> EventPipeline for ModFeed<C> {
+    async fn run(&self, event_type: &str, event: &Value, config: &AppConfig) -> Result<usize> {
+        // Chat messages are subscribed to for experiments and commands, the stream going live
+        // for the offline queue and channel updates to remember the category, so they count as
+        // handled.
+        let chat = event_type == SubscriptionType::ChatMessage.as_ref();
+        let message_components =
+            load_message_components(self.state.as_ref(), &self.config_file).await?;
+        // Without any experiment there's nothing to credit, so no state read per message.
+        if chat
+            && message_components
+                .all_scenarios()
+                .any(|s| s.get_experiment().is_some())
+        {
+            self.record_engagement(event, config).await;
+        }
+        if chat && let Some(commands) = message_components.get_commands() {
+            self.run_command(commands, &message_components, event, config)
+                .await;
+        }
+        let online = event_type == SubscriptionType::StreamOnline.as_ref();
+        if online {
+            self.live.insert(config.broadcaster_user_id.clone(), true);
+            // Posting everything queued could take longer than Twitch waits for an answer, so
+            // it's left to the outbox replay.
+            let flush = OutboxEntry::OfflineQueue {
+                queued_at: Utc::now(),
+                attempts: 0,
+            };
+            if let Err(e) = self
+                .state
+                .push_outbox(&serde_json::to_string(&flush)?)
+                .await
+            {
+                println!("Failed to queue posting the offline queue: {e}");
+            }
+        }
+        let update = event_type == SubscriptionType::ChannelUpdate.as_ref();
+        let tracked;
+        let event = match update {
+            true => {
+                tracked = channel::track_update(self.state.as_ref(), event).await;
+                &tracked
+            }
+            false => event,
+        };
+
+        let rules: Vec<_> = message_components
+            .get_pipeline()
+            .iter()
+            .filter(|r| r.matches(event_type, event))
+            .collect();
+        if rules.is_empty() {
+            return Ok(usize::from(chat || online || update));
+        }
+
+        if quiet::is_quiet(message_components.get_quiet_hours(), Utc::now()) {
+            println!("Quiet hours, not running pipeline for {event_type}");
+            return Ok(rules.len());
+        }
+
+        let context = event_context(event);
+        let viewer_id = event.get("user_id").and_then(Value::as_str);
+        let viewer = Viewer {
+            id: viewer_id,
+            name: event
+                .get("user_name")
+                .and_then(Value::as_str)
+                .unwrap_or(&config.broadcaster_user_id),
+        };
+        for step in rules.iter().flat_map(|r| r.steps.iter()) {
+            match step {
+                Step::Post { group } => match message_components.group_named(group) {
+                    Some(g) => {
+                        if let Err(e) = self
+                            .post(
+                                &g.for_viewer(viewer_id),
+                                &message_components,
+                                context.clone(),
+                                viewer,
+                                None,
+                                config,
+                            )
+                            .await
+                        {
+                            println!("{e}");
+                        }
+                    }
+                    None => println!("Pipeline step {step} names an unknown scenario group"),
+                },
+                Step::Say { text } => {
+                    let mut context = context.clone();
+                    let msg = match message_components
+                        .resolve_variables(&mut context, &mut Rng::new())
+                        .and_then(|_| context.format(text))
+                    {
+                        Ok(m) => m,
+                        Err(e) => {
+                            println!("Failed to fill in pipeline step {step}: {e}");
+                            continue;
+                        }
+                    };
+                    if let Err(e) = self.client.say(&msg, config).await {
+                        println!("Streamelements API request failed: {e}");
+                    }
+                }
+                Step::AwardPoints { amount } => {
+                    match event.get("user_login").and_then(Value::as_str) {
+                        Some(user) => {
+                            if let Err(e) = self.client.award_points(user, *amount, config).await {
+                                println!("{e}");
+                            }
+                        }
+                        None => println!("Pipeline step {step} has no viewer to award points to"),
+                    }
+                }
+            }
+        }
+
+        Ok(rules.len())
+    }
+}
+
+impl<C: StreamelementsCaller + HelixCaller> ModFeed<C> {
+    /// Posts a message from the group that `hook` is configured for. Returns false when no
+    /// group handles the hook, and errors when the message couldn't be posted.
+    pub async fn handle_hook(
+        &self,
+        hook: &str,
+        context: TemplateContext,
+        config: &AppConfig,
+    ) -> Result<bool> {
+        let message_components =
+            load_message_components(self.state.as_ref(), &self.config_file).await?;
+        let game = self.current_game(&message_components, config).await;
+        let group = match message_components.group_for_hook(hook, Utc::now(), game.as_deref()) {
+            Some(g) => g,
+            None => return Ok(false),
+        };
+        if group.is_only_when_live() && !self.is_live(config).await {
+            println!("Stream is offline, not posting for {}", group.get_name());
+            return Ok(true);
+        }
+
+        self.post_unless_quiet(&group, &message_components, context, hook, config)
+            .await?;
+        Ok(true)
+    }
+
+    /// Posts the redemptions queued while the stream was offline, or the `offline_summary` of
+    /// them when there is one. Each leaves the queue once it's been posted, and the ones that
+    /// failed are kept for the next flush.
+    pub(crate) async fn flush_offline_queue(&self, config: &AppConfig) -> Result<()> {
+        let message_components =
+            load_message_components(self.state.as_ref(), &self.config_file).await?;
+        let mut queued: Vec<(String, QueuedRedemption)> = vec![];
+        for entry in self.state.offline_queue().await? {
+            match serde_json::from_str(&entry.entry) {
+                Ok(q) => queued.push((entry.key, q)),
+                Err(e) => {
+                    println!("Dropping unreadable queued redemption: {e}");
+                    self.state.remove_offline(&entry.key).await?;
+                }
+            }
+        }
+        if queued.is_empty() {
+            return Ok(());
+        }
+        println!(
+            "Stream is live, posting {} queued redemptions",
+            queued.len()
+        );
+
+        if let Some(summary) = message_components.get_offline_summary() {
+            let redemptions: Vec<QueuedRedemption> =
+                queued.iter().map(|(_, q)| q.clone()).collect();
+            let mut context = offline::summary_context(&redemptions);
+            let msg = message_components
+                .resolve_variables(&mut context, &mut Rng::new())
+                .and_then(|_| context.format(summary))
+                .map_err(|e| anyhow!("Failed to fill in offline summary: {e}"))?;
+            self.client
+                .say(&msg, config)
+                .await
+                .map_err(|e| anyhow!("Streamelements API request failed: {e}"))?;
+            for (key, _) in &queued {
+                self.state.remove_offline(key).await?;
+            }
+            return Ok(());
+        }
+
+        let game = self.current_game(&message_components, config).await;
+        let mut failed = 0;
+        for (key, q) in queued {
+            let group =
+                message_components.group_for_reward(&q.reward_id, Utc::now(), game.as_deref());
+            match self
+                .post(
+                    &group.for_viewer(Some(&q.user_id)),
+                    &message_components,
+                    q.context(),
+                    Viewer {
+                        id: Some(&q.user_id),
+                        name: &q.user_name,
+                    },
+                    None,
+                    config,
+                )
+                .await
+            {
+                Ok(_) => self.state.remove_offline(&key).await?,
+                Err(e) => {
+                    println!("{e}");
+                    failed += 1;
+                }
+            }
+        }
+        match failed {
+            0 => Ok(()),
+            n => Err(anyhow!(
+                "{n} queued redemptions failed to post, keeping them"
+            )),
+        }
+    }
+
+    /// Credits a chat message to the experiment variant last posted for its sender. The bot's
+    /// own messages aren't engagement.
+    async fn record_engagement(&self, event: &Value, config: &AppConfig) {
+        let Ok(message) = ChatMessage::from_event(event) else {
+            return;
+        };
+        if message.is_from_bot(config) {
+            return;
+        }
+        if let Err(e) = experiment::record_chat(self.state.as_ref(), &message.chatter_user_id).await
+        {
+            println!("Failed to record chat engagement: {e}");
+        }
+    }
+
+    /// Runs the command in a chat message, if it's one, and posts its reply.
+    async fn run_command(
+        &self,
+        commands: &CommandConfig,
+        message_components: &MessageComponents,
+        event: &Value,
+        config: &AppConfig,
+    ) {
+        let message = match ChatMessage::from_event(event) {
             Ok(m) => m,
             Err(e) => {
-                println!("Error reading message configuration file: {e}");
-                return Ok(());
+                println!("Failed to read chat message: {e}");
+                return;
+            }
+        };
+        if message.is_from_bot(config) || parse(&commands.prefix, &message.message.text).is_none() {
+            return;
+        }
+
+        let registry = |display_names| {
+            let mut registry = Commands::built_in(
+                commands.clone(),
+                self.state.clone(),
+                message_components.get_mods(),
+                display_names,
+            );
+            registry.add_custom(Arc::new(message_components.clone()));
+            registry
+        };
+        // Only commands are marked seen, most chat lines aren't worth a write, and the mods'
+        // display names are only looked up for them.
+        if !registry(HashMap::new()).is_command(&message.message.text) {
+            return;
+        }
+        match self
+            .state
+            .mark_seen(&format!("chat#{}", message.message_id))
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => println!("Failed to check for a retried chat message, reading it: {e}"),
+        }
+
+        let registry = registry(self.mod_display_names(message_components, config).await);
+        let reply = match registry.dispatch(&message, config).await {
+            Ok(Dispatch::Ran { reply, .. }) => reply,
+            Ok(Dispatch::NotAllowed(name)) => {
+                println!("{} isn't allowed to use {name}", message.chatter_user_login);
+                self.post_decline(
+                    message_components,
+                    DeclineReason::NotAllowed,
+                    &message.chatter_user_id,
+                    &message.chatter_user_name,
+                    &message.chatter_user_login,
+                    config,
+                )
+                .await;
+                None
+            }
+            Ok(Dispatch::CoolingDown(name)) => {
+                println!("Command {name} is cooling down");
+                self.post_decline(
+                    message_components,
+                    DeclineReason::Cooldown,
+                    &message.chatter_user_id,
+                    &message.chatter_user_name,
+                    &message.chatter_user_login,
+                    config,
+                )
+                .await;
+                None
+            }
+            Ok(Dispatch::NotACommand | Dispatch::Unknown(_)) => None,
+            Err(e) => {
+                println!("Failed to run chat command: {e}");
+                None
+            }
+        };
+        let Some(reply) = reply else {
+            return;
+        };
+
+        if quiet::is_quiet(message_components.get_quiet_hours(), Utc::now()) {
+            println!("Quiet hours, not replying to a chat command");
+            return;
+        }
+        if let Err(e) = self.client.say(&reply, config).await {
+            println!("Streamelements API request failed: {e}");
+        }
+    }
+
+    /// Tells the viewer why nothing happened with a message from the `declines` group, if
+    /// there is one. Each viewer gets at most one every [`DECLINE_COOLDOWN_SECS`].
+    async fn post_decline(
+        &self,
+        message_components: &MessageComponents,
+        reason: DeclineReason,
+        user_id: &str,
+        user_name: &str,
+        user_login: &str,
+        config: &AppConfig,
+    ) {
+        let Some(group) = message_components.group_named(DECLINES_GROUP) else {
+            return;
+        };
+        match self
+            .state
+            .start_cooldown(&format!("decline#{user_id}"), DECLINE_COOLDOWN_SECS)
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => {
+                println!("Failed to check the decline cooldown, not posting one: {e}");
+                return;
             }
+        }
+
+        if let Err(e) = self
+            .post_unless_quiet(
+                &group,
+                message_components,
+                decline::context(reason, user_name, user_login),
+                user_name,
+                config,
+            )
+            .await
+        {
+            println!("{e}");
+        }
+    }
+
+    /// Announces a donation from the `donations` group. Errors when there's no such group.
+    pub async fn handle_donation(&self, donation: &Donation, config: &AppConfig) -> Result<()> {
+        let message_components =
+            load_message_components(self.state.as_ref(), &self.config_file).await?;
+        let group = match message_components.group_named(DONATIONS_GROUP) {
+            Some(g) => g,
+            None => return Err(anyhow!("No {DONATIONS_GROUP} scenario group configured")),
+        };
+
+        let mut context = TemplateContext::default();
+        context.insert(DONOR, &donation.donor);
+        context.insert(AMOUNT, &donation.amount);
+        self.post_unless_quiet(
+            &group,
+            &message_components,
+            context,
+            &donation.donor,
+            config,
+        )
+        .await
+    }
+
+    async fn post_unless_quiet(
+        &self,
+        group: &ScenarioGroup,
+        message_components: &MessageComponents,
+        context: TemplateContext,
+        fallback_viewer: &str,
+        config: &AppConfig,
+    ) -> Result<()> {
+        if quiet::is_quiet(message_components.get_quiet_hours(), Utc::now()) {
+            println!("Quiet hours, not posting for {}", group.get_name());
+            return Ok(());
+        }
+
+        self.post(
+            group,
+            message_components,
+            context,
+            Viewer {
+                id: None,
+                name: fallback_viewer,
+            },
+            None,
+            config,
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Builds a message from the group and posts it, then records its stats and starts any
+    /// follow-up or vote concurrently. Failures are logged, the trigger has already been
+    /// accepted. Returns the scenario posted, or an error when chat didn't take the message.
+    async fn post(
+        &self,
+        group: &ScenarioGroup,
+        message_components: &MessageComponents,
+        mut context: TemplateContext,
+        viewer: Viewer<'_>,
+        sent_at: Option<DateTime<Utc>>,
+        config: &AppConfig,
+    ) -> Result<Option<String>> {
+        let mut rng: Rng = Rng::new();
+        context.set_pronouns(message_components.get_pronouns().clone());
+        context.set_locale(message_components.get_locale());
+        context.set_display_names(self.mod_display_names(message_components, config).await);
+        if let Err(e) = message_components.resolve_variables(&mut context, &mut rng) {
+            println!("Failed to fill in variables: {e}");
+            return Ok(None);
+        }
+        if group
+            .get_scenarios()
+            .iter()
+            .any(|s| s.uses_placeholder(RANDOM_VIEWER))
+        {
+            let random = self.random_viewer(viewer.name, config, &mut rng).await;
+            context.insert(RANDOM_VIEWER, random);
+        }
+        if group
+            .get_scenarios()
+            .iter()
+            .any(|s| s.uses_placeholder(NEXT_STREAM) || s.uses_placeholder(NEXT_STREAM_TITLE))
+        {
+            self.fill_next_stream(&mut context, message_components, config)
+                .await;
+        }
+
+        let group = &group.active_at(Utc::now());
+        let mods = self.participating_mods(message_components.get_mods()).await;
+        let (built, round_robin) = match self
+            .build_message(
+                group,
+                &mods,
+                message_components.get_anti_repeat_window(),
+                &context,
+                &mut rng,
+            )
+            .await
+        {
+            Ok(m) => m,
+            Err(e) => {
+                println!("Failed to build message: {e}");
+                return Ok(None);
+            }
+        };
+
+        println!("Message built: {}", &built.message);
+        if probe::active() {
+            println!("Probe, not posting the message");
+            return Ok(Some(built.scenario));
+        }
+        let bot_config = match group.get_bot().map(|bot| bot.apply(config)).transpose() {
+            Ok(c) => c,
+            Err(e) => {
+                println!("Failed to post as the group's bot: {e}");
+                return Ok(None);
+            }
+        };
+        match self
+            .client
+            .say(&built.message, bot_config.as_ref().unwrap_or(config))
+            .await
+        {
+            Ok(resp) => {
+                println!("Successfully posted message in chat!");
+                if let Some(sent_at) = sent_at {
+                    metrics::record_chat_latency(sent_at, Utc::now(), config.latency_budget_ms);
+                }
+            }
+            Err(e) => return Err(anyhow!("Streamelements API request failed: {e}")),
+        };
+
+        let now = Utc::now();
+        let (week, month) = (week_key(now), month_key(now));
+        let recorded = async {
+            tokio::try_join!(
+                self.state.record_scenario(&week, &built.scenario),
+                self.state.record_scenario(&month, &built.scenario),
+            )?;
+            etag::touch(self.state.as_ref(), Dataset::Scenarios).await;
+            Ok::<_, anyhow::Error>(())
+        };
+        let follow_up = async {
+            match built.follow_up {
+                Some(follow_up) => {
+                    let delay_secs = follow_up.delay_secs;
+                    self.actions
+                        .schedule(QueuedAction::FollowUp(follow_up), delay_secs, config)
+                        .await
+                }
+                None => Ok(()),
+            }
+        };
+        let vote = async {
+            match built.vote {
+                Some(vote) => self.start_vote(vote, config).await,
+                None => Ok(()),
+            }
+        };
+        let variant = async {
+            match &built.experiment {
+                Some(variant) => {
+                    experiment::record_shown(self.state.as_ref(), variant, viewer.id).await
+                }
+                None => Ok(()),
+            }
+        };
+        // Wins are counted once the message is out, so the writes don't hold it back.
+        let wins = async {
+            tokio::try_join!(
+                self.state.record_wins(&built.winners),
+                self.state.record_month_wins(&month, &built.winners),
+            )?;
+            etag::touch(self.state.as_ref(), Dataset::Wins).await;
+            Ok::<_, anyhow::Error>(())
+        };
+        // The rotation only moves on once the winner's message is out, and not at all when
+        // another post moved it first.
+        let rotated = async {
+            match &round_robin {
+                Some(m) => {
+                    self.state
+                        .advance_round_robin(group.get_name(), m.from.as_deref(), &m.to)
+                        .await
+                }
+                None => Ok(true),
+            }
+        };
+        let (recorded, won, shown, followed_up, voted, rotated) =
+            tokio::join!(recorded, wins, variant, follow_up, vote, rotated);
+
+        if let Err(e) = recorded {
+            println!("Failed to record scenario stats: {e}");
+        }
+        if let Err(e) = won {
+            println!("{e}");
+        }
+        if let Err(e) = shown {
+            println!("Failed to record experiment stats: {e}");
+        }
+        if let Err(e) = followed_up {
+            println!("Failed to schedule follow-up message: {e}");
+        }
+        if let Err(e) = voted {
+            println!("Failed to start chat vote: {e}");
+        }
+        match rotated {
+            Ok(true) => {}
+            Ok(false) => println!(
+                "Round-robin for {} moved on while posting, keeping that",
+                group.get_name()
+            ),
+            Err(e) => println!("{e}"),
+        }
+        Ok(Some(built.scenario))
+    }
+}
+
+/// Message components saved through the admin API, falling back to the config file until
+/// anything has been saved. A saved config is parsed once per version.
+pub(crate) async fn load_message_components(
+    state: &dyn StateStore,
+    file: &ConfigFile,
+) -> Result<Arc<MessageComponents>> {
+    match state.get_config().await? {
+        Some(stored) => file.parse_saved(&stored),
+        None => file.get(),
+    }
+}
+
+pub(crate) fn read_config(path: &PathBuf) -> Result<MessageComponents> {
+    let config_str = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(_) => {
+            return Err(anyhow!(
+                "Failed to read configuration file for building messages"
+            ));
+        }
+    };
+
+    migrate::parse_config_lenient(&config_str)
+        .map(|(components, _)| components)
+        .map_err(|e| anyhow!("Failed to deserialize message config: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::action::{ActionScheduler, PollOutcome, PollResolution, QueuedAction, RewardPause};
+    use crate::cache::TtlCache;
+    use crate::channel::ChannelInfo;
+    use crate::client::{HelixCaller, StreamelementsCaller};
+    use crate::config::AppConfig;
+    use crate::donations::Donation;
+    use crate::hooks::hook_context;
+    use crate::permissions::UserRoles;
+    use crate::reload::ConfigFile;
+    use crate::reward::mod_feeder::ModFeed;
+    use crate::reward::{EventPipeline, RewardHandler};
+    use crate::robochick::twitch::FollowUpMessage;
+    use crate::schedule::ScheduleSegment;
+    use crate::state::{MemoryStore, StateStore};
+    use crate::stats::{month_key, week_key};
+    use crate::types::twitch::{self, RewardRedeemed, Subscription, SubscriptionRequest};
+    use crate::users::{TwitchUser, UserCache};
+    use crate::{deadline::OutboxEntry, offline::QueuedRedemption};
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use axum::http::HeaderMap;
+    use chrono::Utc;
+    use lambda_http::{Body, Response};
+    use mockall::{Sequence, mock, predicate};
+    use reqwest::StatusCode;
+    use std::{path::PathBuf, sync::Arc, time::Duration};
+
+    mock! {
+        pub Caller {}
+
+        #[async_trait]
+        impl StreamelementsCaller for Caller {
+            async fn say(&self, msg: &str, config: &AppConfig) -> Result<String>;
+            async fn award_points(&self, user_login: &str, amount: i64, config: &AppConfig) -> Result<()>;
+        }
+
+        #[async_trait]
+        impl HelixCaller for Caller {
+            async fn get_chatters(&self, config: &AppConfig) -> Result<Vec<String>>;
+            async fn create_poll(
+                &self,
+                title: &str,
+                choices: &[String],
+                duration_secs: u64,
+                config: &AppConfig,
+            ) -> Result<String>;
+            async fn get_poll_results(
+                &self,
+                poll_id: &str,
+                config: &AppConfig,
+            ) -> Result<Vec<(String, u64)>>;
+            async fn get_stream_id(&self, config: &AppConfig) -> Result<Option<String>>;
+            async fn get_channel_info(&self, config: &AppConfig) -> Result<ChannelInfo>;
+            async fn get_schedule(&self, config: &AppConfig) -> Result<Vec<ScheduleSegment>>;
+            async fn set_reward_paused(
+                &self,
+                reward_id: &str,
+                paused: bool,
+                config: &AppConfig,
+            ) -> Result<()>;
+            async fn get_user_roles(&self, user_id: &str, config: &AppConfig) -> Result<UserRoles>;
+            async fn get_users(&self, logins: &[String], config: &AppConfig) -> Result<Vec<TwitchUser>>;
+            async fn cancel_redemption(
+                &self,
+                reward_id: &str,
+                redemption_id: &str,
+                config: &AppConfig,
+            ) -> Result<()>;
+            async fn create_eventsub_subscription(
+                &self,
+                request: &SubscriptionRequest,
+                config: &AppConfig,
+            ) -> Result<()>;
+            async fn list_eventsub_subscriptions(
+                &self,
+                config: &AppConfig,
+            ) -> Result<Vec<Subscription>>;
+            async fn delete_eventsub_subscription(&self, id: &str, config: &AppConfig) -> Result<()>;
+        }
+    }
+
+    mock! {
+        pub Scheduler {}
+
+        #[async_trait]
+        impl ActionScheduler for Scheduler {
+            async fn schedule(
+                &self,
+                action: QueuedAction,
+                delay_secs: u64,
+                config: &AppConfig,
+            ) -> Result<()>;
+        }
+    }
+
+    /// A feed posting through `client`, with a fresh in-memory state and caches, no scheduled
+    /// actions and the message config at `config`'s path. Tests override the parts they check.
+    fn test_feed(client: MockCaller, config: &AppConfig) -> ModFeed<MockCaller> {
+        ModFeed {
+            client,
+            chatters: Arc::new(TtlCache::new(Duration::from_secs(60))),
+            live: Arc::new(TtlCache::new(Duration::from_secs(60))),
+            users: Arc::new(UserCache::new(Duration::from_secs(60))),
+            actions: Arc::new(MockScheduler::new()),
+            state: Arc::new(MemoryStore::default()),
+            config_file: Arc::new(ConfigFile::new(&config.message_components_config_path)),
+        }
+    }
+
+    #[tokio::test]
+    async fn builds_scenario_and_calls_streamelements_api() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let config = AppConfig::from_env();
+
+        let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        payload_path.push("resources/tests/reward_redemption_event.json");
+        let payload = std::fs::read_to_string(payload_path)?;
+        let event: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
+
+        let msg_id = String::from("Message-Id");
+        let expected_message =
+            "Anna's feeling benevolent this time, all the mods got a dry cracker each!";
+
+        let mut mock_caller = MockCaller::new();
+        let se_mock = mock_caller
+            .expect_say()
+            .with(
+                predicate::eq(expected_message.to_string()),
+                predicate::eq(config.clone()),
+            )
+            .return_once(|_, _| Ok("result".to_string()))
+            .once();
+
+        mock_caller.expect_get_chatters().never();
+
+        let handler = test_feed(mock_caller, &config);
+
+        let scenario = handler.handle(msg_id, &event, &config).await?;
+
+        assert!(scenario.is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn retried_messages_are_only_posted_once() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let config = AppConfig::from_env();
+
+        let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        payload_path.push("resources/tests/reward_redemption_event.json");
+        let payload = std::fs::read_to_string(payload_path)?;
+        let event: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
+
+        let mut mock_caller = MockCaller::new();
+        mock_caller
+            .expect_say()
+            .returning(|_, _| Ok("result".to_string()))
+            .once();
+
+        let handler = test_feed(mock_caller, &config);
+
+        let first = handler
+            .handle(String::from("Message-Id"), &event, &config)
+            .await?;
+        let retry = handler
+            .handle(String::from("Message-Id"), &event, &config)
+            .await?;
+
+        assert!(first.is_some());
+        assert_eq!(retry, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn retries_of_messages_that_failed_are_posted() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let config = AppConfig::from_env();
+
+        let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        payload_path.push("resources/tests/reward_redemption_event.json");
+        let payload = std::fs::read_to_string(payload_path)?;
+        let event: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
+
+        let calls = std::sync::atomic::AtomicUsize::new(0);
+        let mut mock_caller = MockCaller::new();
+        mock_caller
+            .expect_say()
+            .returning(
+                move |_, _| match calls.fetch_add(1, std::sync::atomic::Ordering::Relaxed) {
+                    0 => Err(anyhow::anyhow!("Chat is down")),
+                    _ => Ok("result".to_string()),
+                },
+            )
+            .times(2);
+
+        let handler = test_feed(mock_caller, &config);
+
+        let failed = handler
+            .handle(String::from("Message-Id"), &event, &config)
+            .await;
+        let retry = handler
+            .handle(String::from("Message-Id"), &event, &config)
+            .await?;
+
+        assert!(failed.is_err());
+        assert!(retry.is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn random_viewer_uses_cached_chatters() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let config = AppConfig::from_env();
+
+        let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        payload_path.push("resources/tests/reward_redemption_event.json");
+        let payload = std::fs::read_to_string(payload_path)?;
+        let event: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
+
+        let mut mock_caller = MockCaller::new();
+        mock_caller
+            .expect_get_chatters()
+            .return_once(|_| Ok(vec!["smittysmithers".to_string()]))
+            .once();
+
+        let handler = test_feed(mock_caller, &config);
+
+        let mut rng = fastrand::Rng::with_seed(1);
+        let first = handler
+            .random_viewer(event.user_name(), &config, &mut rng)
+            .await;
+        let second = handler
+            .random_viewer(event.user_name(), &config, &mut rng)
+            .await;
+
+        assert_eq!(first, "smittysmithers");
+        assert_eq!(second, "smittysmithers");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn random_viewer_falls_back_to_redeemer_when_chatters_unavailable() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let config = AppConfig::from_env();
+
+        let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        payload_path.push("resources/tests/reward_redemption_event.json");
+        let payload = std::fs::read_to_string(payload_path)?;
+        let event: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
+
+        let mut mock_caller = MockCaller::new();
+        mock_caller
+            .expect_get_chatters()
+            .return_once(|_| Err(anyhow::anyhow!("401 Unauthorized")));
+
+        let handler = test_feed(mock_caller, &config);
+
+        let mut rng = fastrand::Rng::with_seed(1);
+        let viewer = handler
+            .random_viewer(event.user_name(), &config, &mut rng)
+            .await;
+
+        assert_eq!(viewer, "Cooler_User");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn schedules_follow_up_after_posting_message() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_follow_up.json".into();
+
+        let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        payload_path.push("resources/tests/reward_redemption_event.json");
+        let payload = std::fs::read_to_string(payload_path)?;
+        let event: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
+
+        let mut mock_caller = MockCaller::new();
+        mock_caller
+            .expect_say()
+            .with(
+                predicate::eq("The chicken is still deciding who gets the cracker...".to_string()),
+                predicate::always(),
+            )
+            .return_once(|_, _| Ok("result".to_string()))
+            .once();
+
+        let mut mock_scheduler = MockScheduler::new();
+        mock_scheduler
+            .expect_schedule()
+            .with(
+                predicate::eq(QueuedAction::FollowUp(FollowUpMessage {
+                    message: "...and the chicken ate it.".into(),
+                    delay_secs: 30,
+                })),
+                predicate::eq(30),
+                predicate::always(),
+            )
+            .return_once(|_, _, _| Ok(()))
+            .once();
+
+        let handler = ModFeed {
+            actions: Arc::new(mock_scheduler),
+            ..test_feed(mock_caller, &config)
+        };
+
+        let response = handler
+            .handle(String::from("Message-Id"), &event, &config)
+            .await;
+
+        assert!(response.is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn starts_poll_and_schedules_its_resolution_for_vote_scenarios() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_vote.json".into();
+
+        let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        payload_path.push("resources/tests/reward_redemption_event.json");
+        let payload = std::fs::read_to_string(payload_path)?;
+        let event: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
+
+        let mut mock_caller = MockCaller::new();
+        mock_caller
+            .expect_say()
+            .return_once(|_, _| Ok("result".to_string()))
+            .once();
+        mock_caller
+            .expect_create_poll()
+            .withf(|title, choices, duration, _| {
+                title == "What should John do with the cracker?"
+                    && choices == ["Eat it".to_string(), "Share it".to_string()]
+                    && *duration == 60
+            })
+            .return_once(|_, _, _, _| Ok("poll-1".to_string()))
+            .once();
+
+        let mut mock_scheduler = MockScheduler::new();
+        mock_scheduler
+            .expect_schedule()
+            .with(
+                predicate::eq(QueuedAction::ResolvePoll(PollResolution {
+                    poll_id: "poll-1".into(),
+                    outcomes: vec![
+                        PollOutcome {
+                            choice: "Eat it".into(),
+                            message: "John ate the cracker in one bite.".into(),
+                        },
+                        PollOutcome {
+                            choice: "Share it".into(),
+                            message: "John shared the cracker with everyone.".into(),
+                        },
+                    ],
+                })),
+                predicate::eq(65),
+                predicate::always(),
+            )
+            .return_once(|_, _, _| Ok(()))
+            .once();
+
+        let handler = ModFeed {
+            actions: Arc::new(mock_scheduler),
+            ..test_feed(mock_caller, &config)
+        };
+
+        let response = handler
+            .handle(String::from("Message-Id"), &event, &config)
+            .await;
+
+        assert!(response.is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn round_robin_group_feeds_mods_in_turn_and_remembers_the_last_pick() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_round_robin.json".into();
+
+        let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        payload_path.push("resources/tests/reward_redemption_event.json");
+        let payload = std::fs::read_to_string(payload_path)?;
+        let event: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
+
+        let mut mock_caller = MockCaller::new();
+        let mut seq = mockall::Sequence::new();
+        for name in ["Jane", "Alex"] {
+            mock_caller
+                .expect_say()
+                .with(
+                    predicate::eq(format!("{name} gets the cracker this time.")),
+                    predicate::always(),
+                )
+                .return_once(|_, _| Ok("result".to_string()))
+                .once()
+                .in_sequence(&mut seq);
+        }
+
+        let state = Arc::new(MemoryStore::default());
+        state.advance_round_robin("fair", None, "John").await?;
+
+        let handler = ModFeed {
+            state: state.clone(),
+            ..test_feed(mock_caller, &config)
+        };
+
+        handler
+            .handle(String::from("Message-Id-1"), &event, &config)
+            .await?;
+        handler
+            .handle(String::from("Message-Id-2"), &event, &config)
+            .await?;
+
+        assert_eq!(
+            state.last_round_robin("fair").await?.as_deref(),
+            Some("Alex")
+        );
+        let counts = state.scenario_counts(&week_key(chrono::Utc::now())).await?;
+        assert_eq!(counts.get("{win_1} gets the cracker this time."), Some(&2));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn round_robin_stays_on_the_last_pick_when_posting_fails() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_round_robin.json".into();
+
+        let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        payload_path.push("resources/tests/reward_redemption_event.json");
+        let payload = std::fs::read_to_string(payload_path)?;
+        let event: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
+
+        let mut mock_caller = MockCaller::new();
+        mock_caller
+            .expect_say()
+            .return_once(|_, _| Err(anyhow::anyhow!("chat is down")))
+            .once();
+
+        let state = Arc::new(MemoryStore::default());
+        state.advance_round_robin("fair", None, "John").await?;
+
+        let handler = ModFeed {
+            state: state.clone(),
+            ..test_feed(mock_caller, &config)
+        };
+
+        let _ = handler
+            .handle(String::from("Message-Id"), &event, &config)
+            .await;
+
+        assert_eq!(
+            state.last_round_robin("fair").await?.as_deref(),
+            Some("John")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn anti_repeat_window_keeps_recent_winners_out() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_anti_repeat.json".into();
+
+        let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        payload_path.push("resources/tests/reward_redemption_event.json");
+        let payload = std::fs::read_to_string(payload_path)?;
+        let event: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
+
+        let mut mock_caller = MockCaller::new();
+        mock_caller
+            .expect_say()
+            .with(
+                predicate::eq("Alex gets the cracker this time.".to_string()),
+                predicate::always(),
+            )
+            .return_once(|_, _| Ok("result".to_string()))
+            .once();
+
+        let state = Arc::new(MemoryStore::default());
+        state
+            .push_recent_winners("default", &["John".to_string(), "Jane".to_string()], 2)
+            .await?;
+
+        let handler = ModFeed {
+            state: state.clone(),
+            ..test_feed(mock_caller, &config)
+        };
+
+        handler
+            .handle(String::from("Message-Id"), &event, &config)
+            .await?;
+
+        assert_eq!(state.recent_winners("default").await?, vec!["Alex", "John"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn opted_out_mods_are_left_out_of_scenarios() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_anti_repeat.json".into();
+
+        let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        payload_path.push("resources/tests/reward_redemption_event.json");
+        let payload = std::fs::read_to_string(payload_path)?;
+        let event: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
+
+        let mut mock_caller = MockCaller::new();
+        mock_caller
+            .expect_say()
+            .with(
+                predicate::eq("Jane gets the cracker this time.".to_string()),
+                predicate::always(),
+            )
+            .return_once(|_, _| Ok("result".to_string()))
+            .once();
+
+        let state = Arc::new(MemoryStore::default());
+        state.set_opted_out("john", true).await?;
+        state.set_opted_out("alex", true).await?;
+
+        let handler = ModFeed {
+            state: state.clone(),
+            ..test_feed(mock_caller, &config)
+        };
+
+        handler
+            .handle(String::from("Message-Id"), &event, &config)
+            .await?;
+
+        let mods = ["John".to_string(), "Alex".to_string()];
+        assert_eq!(handler.participating_mods(&mods).await, mods);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn budget_pauses_reward_once_used_up() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_budget.json".into();
+
+        let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        payload_path.push("resources/tests/reward_redemption_event.json");
+        let payload = std::fs::read_to_string(payload_path)?;
+        let event: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
+
+        let mut mock_caller = MockCaller::new();
+        mock_caller
+            .expect_get_stream_id()
+            .returning(|_| Ok(Some("40952121085".into())))
+            .times(3);
+        let mut seq = mockall::Sequence::new();
+        for message in ["John gets the cracker this time.", "The chicken is full!"] {
+            mock_caller
+                .expect_say()
+                .with(predicate::eq(message.to_string()), predicate::always())
+                .return_once(|_, _| Ok("result".to_string()))
+                .once()
+                .in_sequence(&mut seq);
+        }
+        mock_caller
+            .expect_set_reward_paused()
+            .with(
+                predicate::eq("92af127c-7326-4483-a52b-b0da0be61c01"),
+                predicate::eq(true),
+                predicate::always(),
+            )
+            .returning(|_, _, _| Ok(()))
+            .once();
+
+        let mut mock_scheduler = MockScheduler::new();
+        mock_scheduler
+            .expect_schedule()
+            .with(
+                predicate::eq(QueuedAction::SetRewardPaused(RewardPause {
+                    reward_id: "92af127c-7326-4483-a52b-b0da0be61c01".into(),
+                    paused: false,
+                })),
+                predicate::eq(3600),
+                predicate::always(),
+            )
+            .returning(|_, _, _| Ok(()))
+            .once();
+
+        let handler = ModFeed {
+            actions: Arc::new(mock_scheduler),
+            ..test_feed(mock_caller, &config)
+        };
+
+        for id in ["Message-Id-1", "Message-Id-2", "Message-Id-3"] {
+            handler.handle(id.to_string(), &event, &config).await?;
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bursts_post_one_message_and_pause_the_reward() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_burst.json".into();
+
+        let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        payload_path.push("resources/tests/reward_redemption_event.json");
+        let payload = std::fs::read_to_string(payload_path)?;
+        let event: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
+
+        let mut mock_caller = MockCaller::new();
+        let mut seq = mockall::Sequence::new();
+        for message in [
+            "John gets the cracker this time.",
+            "John gets the cracker this time.",
+            "Cooler_User x3 feedings!",
+        ] {
+            mock_caller
+                .expect_say()
+                .with(predicate::eq(message.to_string()), predicate::always())
+                .return_once(|_, _| Ok("result".to_string()))
+                .once()
+                .in_sequence(&mut seq);
+        }
+        mock_caller
+            .expect_set_reward_paused()
+            .with(
+                predicate::eq("92af127c-7326-4483-a52b-b0da0be61c01"),
+                predicate::eq(true),
+                predicate::always(),
+            )
+            .returning(|_, _, _| Ok(()))
+            .once();
+        // The two redemptions after the burst message are refunded.
+        mock_caller
+            .expect_cancel_redemption()
+            .with(
+                predicate::eq("92af127c-7326-4483-a52b-b0da0be61c01"),
+                predicate::eq("17fa2df1-ad76-4804-bfa5-a40ef63efe63"),
+                predicate::always(),
+            )
+            .returning(|_, _, _| Ok(()))
+            .times(2);
+
+        let mut mock_scheduler = MockScheduler::new();
+        mock_scheduler
+            .expect_schedule()
+            .with(
+                predicate::eq(QueuedAction::SetRewardPaused(RewardPause {
+                    reward_id: "92af127c-7326-4483-a52b-b0da0be61c01".into(),
+                    paused: false,
+                })),
+                predicate::eq(120),
+                predicate::always(),
+            )
+            .returning(|_, _, _| Ok(()))
+            .once();
+
+        let handler = ModFeed {
+            actions: Arc::new(mock_scheduler),
+            ..test_feed(mock_caller, &config)
+        };
+
+        for id in 1..=5 {
+            handler
+                .handle(format!("Message-Id-{id}"), &event, &config)
+                .await?;
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn probes_build_a_message_without_posting_or_counting() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_burst.json".into();
+
+        let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        payload_path.push("resources/tests/reward_redemption_event.json");
+        let payload = std::fs::read_to_string(payload_path)?;
+        let event: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
+
+        let state = Arc::new(MemoryStore::default());
+        let handler = ModFeed {
+            state: state.clone(),
+            ..test_feed(MockCaller::new(), &config)
+        };
+
+        for id in 1..=5 {
+            let scenario =
+                crate::probe::scope(true, handler.handle(format!("probe-{id}"), &event, &config))
+                    .await?;
+            assert!(scenario.is_some());
+        }
+        assert!(state.win_counts().await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn declined_redemptions_get_one_message_from_the_declines_group() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_declines.json".into();
+
+        let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        payload_path.push("resources/tests/reward_redemption_event.json");
+        let payload = std::fs::read_to_string(payload_path)?;
+        let event: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
+
+        let mut mock_caller = MockCaller::new();
+        mock_caller
+            .expect_get_stream_id()
+            .returning(|_| Ok(Some("40952121085".into())))
+            .times(4);
+        let mut seq = mockall::Sequence::new();
+        for message in [
+            "John gets the cracker this time.",
+            "The chicken is full!",
+            "Sorry Cooler_User, no crackers left (budget).",
+        ] {
+            mock_caller
+                .expect_say()
+                .with(predicate::eq(message.to_string()), predicate::always())
+                .return_once(|_, _| Ok("result".to_string()))
+                .once()
+                .in_sequence(&mut seq);
+        }
+        mock_caller
+            .expect_set_reward_paused()
+            .returning(|_, _, _| Ok(()))
+            .once();
+
+        let handler = test_feed(mock_caller, &config);
+
+        for id in 1..=4 {
+            handler
+                .handle(format!("Message-Id-{id}"), &event, &config)
+                .await?;
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bursts_across_chat_post_one_decline_per_window() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_burst_declines.json".into();
+
+        let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        payload_path.push("resources/tests/reward_redemption_event.json");
+        let payload: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(payload_path)?)?;
+
+        let mut mock_caller = MockCaller::new();
+        let mut seq = mockall::Sequence::new();
+        for message in [
+            "John gets the cracker this time.",
+            "x2 feedings!",
+            "Sorry Viewer3, no crackers left (burst).",
+        ] {
+            mock_caller
+                .expect_say()
+                .with(predicate::eq(message.to_string()), predicate::always())
+                .return_once(|_, _| Ok("result".to_string()))
+                .once()
+                .in_sequence(&mut seq);
+        }
+        mock_caller
+            .expect_cancel_redemption()
+            .returning(|_, _, _| Ok(()))
+            .times(3);
+
+        let handler = test_feed(mock_caller, &config);
+
+        // Every redemption is by a different viewer, so only the window limits declines.
+        for id in 1..=5 {
+            let mut payload = payload.clone();
+            payload["event"]["user_id"] = id.to_string().into();
+            payload["event"]["user_login"] = format!("viewer{id}").into();
+            payload["event"]["user_name"] = format!("Viewer{id}").into();
+            let event: RewardRedeemed = serde_json::from_value(payload)?;
+            handler
+                .handle(format!("Message-Id-{id}"), &event, &config)
+                .await?;
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn quiet_hours_acknowledge_without_posting() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_quiet.json".into();
+
+        let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        payload_path.push("resources/tests/reward_redemption_event.json");
+        let payload = std::fs::read_to_string(payload_path)?;
+        let event: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
+
+        let mut mock_caller = MockCaller::new();
+        mock_caller.expect_say().never();
+
+        let state = Arc::new(MemoryStore::default());
+        let handler = ModFeed {
+            state: state.clone(),
+            ..test_feed(mock_caller, &config)
+        };
+
+        handler
+            .handle(String::from("Message-Id"), &event, &config)
+            .await?;
+
+        assert!(state.recent_winners("default").await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn offline_redemptions_are_queued_with_cached_stream_state() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_offline.json".into();
+
+        let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        payload_path.push("resources/tests/reward_redemption_event.json");
+        let payload = std::fs::read_to_string(payload_path)?;
+        let event: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
+
+        let mut mock_caller = MockCaller::new();
+        mock_caller
+            .expect_get_stream_id()
+            .return_once(|_| Ok(None))
+            .once();
+        mock_caller.expect_say().never();
+
+        let state = Arc::new(MemoryStore::default());
+        let handler = ModFeed {
+            state: state.clone(),
+            ..test_feed(mock_caller, &config)
+        };
+
+        for msg_id in ["Message-Id-1", "Message-Id-2"] {
+            handler.handle(msg_id.into(), &event, &config).await?;
+        }
+
+        let queued: Vec<QueuedRedemption> = state
+            .offline_queue()
+            .await?
+            .iter()
+            .map(|q| serde_json::from_str(&q.entry))
+            .collect::<serde_json::Result<_>>()?;
+        assert_eq!(queued.len(), 2);
+        assert_eq!(queued[0].user_name, event.user_name());
+        assert_eq!(queued[0].reward_id, event.reward_id());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stream_online_posts_summary_of_queued_redemptions() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_offline.json".into();
+
+        let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        payload_path.push("resources/tests/reward_redemption_event.json");
+        let payload = std::fs::read_to_string(payload_path)?;
+        let event: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
+
+        let mut mock_caller = MockCaller::new();
+        mock_caller
+            .expect_say()
+            .with(
+                predicate::eq(format!(
+                    "While I was asleep, {} fed the mods 2 times.",
+                    event.user_name()
+                )),
+                predicate::always(),
+            )
+            .return_once(|_, _| Ok("result".to_string()))
+            .once();
+
+        let state = Arc::new(MemoryStore::default());
+        let queued = serde_json::to_string(&QueuedRedemption::from_redemption(
+            &event,
+            chrono::Utc::now(),
+        ))?;
+        state.queue_offline(&queued).await?;
+        state.queue_offline(&queued).await?;
+        let handler = ModFeed {
+            state: state.clone(),
+            ..test_feed(mock_caller, &config)
         };
 
-        let mut rng: Rng = Rng::new();
-        let message = match Robochick::build_from_templates(&message_components, &mut rng) {
-            Ok(m) => m,
-            Err(e) => {
-                println!("Failed to build message: {e}");
-                return Ok(());
-            }
+        let online = serde_json::json!({"broadcaster_user_id": "1337", "type": "live"});
+        assert_eq!(handler.run("stream.online", &online, &config).await?, 1);
+        assert_eq!(handler.live.get(&config.broadcaster_user_id), Some(true));
+        let outbox = state.outbox().await?;
+        assert_eq!(outbox.len(), 1);
+        assert!(matches!(
+            serde_json::from_str(&outbox[0].entry)?,
+            OutboxEntry::OfflineQueue { .. }
+        ));
+        assert_eq!(state.offline_queue().await?.len(), 2);
+
+        handler.flush_offline_queue(&config).await?;
+        assert!(state.offline_queue().await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn offline_queue_is_kept_when_its_summary_fails_to_post() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_offline.json".into();
+
+        let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        payload_path.push("resources/tests/reward_redemption_event.json");
+        let payload = std::fs::read_to_string(payload_path)?;
+        let event: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
+
+        let mut mock_caller = MockCaller::new();
+        mock_caller
+            .expect_say()
+            .return_once(|_, _| Err(anyhow::anyhow!("chat is down")))
+            .once();
+
+        let state = Arc::new(MemoryStore::default());
+        let queued = serde_json::to_string(&QueuedRedemption::from_redemption(
+            &event,
+            chrono::Utc::now(),
+        ))?;
+        state.queue_offline(&queued).await?;
+        let handler = ModFeed {
+            state: state.clone(),
+            ..test_feed(mock_caller, &config)
         };
 
-        println!("Message built: {}", &message);
-        return match self.client.say(&message, config).await {
-            Ok(resp) => {
-                println!("Successfully posted message in chat!");
-                Ok(())
-            }
-            Err(e) => {
-                println!("Streamelements API request failed: {e}");
-                Ok(())
-            }
+        assert!(handler.flush_offline_queue(&config).await.is_err());
+        assert_eq!(state.offline_queue().await?.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn role_gate_declines_and_refunds_redeemer_without_role() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_role_gate.json".into();
+
+        let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        payload_path.push("resources/tests/reward_redemption_event.json");
+        let payload = std::fs::read_to_string(payload_path)?;
+        let event: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
+
+        let mut mock_caller = MockCaller::new();
+        mock_caller
+            .expect_get_user_roles()
+            .with(predicate::eq("9001"), predicate::always())
+            .returning(|_, _| {
+                Ok(UserRoles {
+                    sub_tier: Some(1),
+                    ..Default::default()
+                })
+            })
+            .once();
+        mock_caller
+            .expect_say()
+            .with(
+                predicate::eq("Sorry Cooler_User, tier 2 subs and VIPs only!".to_string()),
+                predicate::always(),
+            )
+            .return_once(|_, _| Ok("result".to_string()))
+            .once();
+        mock_caller
+            .expect_cancel_redemption()
+            .with(
+                predicate::eq("92af127c-7326-4483-a52b-b0da0be61c01"),
+                predicate::eq("17fa2df1-ad76-4804-bfa5-a40ef63efe63"),
+                predicate::always(),
+            )
+            .returning(|_, _, _| Ok(()))
+            .once();
+
+        let handler = test_feed(mock_caller, &config);
+
+        handler
+            .handle(String::from("Message-Id"), &event, &config)
+            .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn role_gate_lets_redeemer_with_role_through() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_role_gate.json".into();
+
+        let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        payload_path.push("resources/tests/reward_redemption_event.json");
+        let payload = std::fs::read_to_string(payload_path)?;
+        let event: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
+
+        let mut mock_caller = MockCaller::new();
+        mock_caller
+            .expect_get_user_roles()
+            .returning(|_, _| {
+                Ok(UserRoles {
+                    vip: true,
+                    ..Default::default()
+                })
+            })
+            .once();
+        mock_caller
+            .expect_say()
+            .with(
+                predicate::eq("John gets the cracker this time.".to_string()),
+                predicate::always(),
+            )
+            .return_once(|_, _| Ok("result".to_string()))
+            .once();
+        mock_caller.expect_cancel_redemption().never();
+
+        let handler = test_feed(mock_caller, &config);
+
+        handler
+            .handle(String::from("Message-Id"), &event, &config)
+            .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn role_gate_fails_the_redemption_when_roles_cannot_be_looked_up() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_role_gate.json".into();
+
+        let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        payload_path.push("resources/tests/reward_redemption_event.json");
+        let payload = std::fs::read_to_string(payload_path)?;
+        let event: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
+
+        let mut mock_caller = MockCaller::new();
+        mock_caller
+            .expect_get_user_roles()
+            .returning(|_, _| Err(anyhow::anyhow!("Helix is down")))
+            .once();
+        mock_caller.expect_say().never();
+        mock_caller.expect_cancel_redemption().never();
+
+        let state = Arc::new(MemoryStore::default());
+        let handler = ModFeed {
+            state: state.clone(),
+            ..test_feed(mock_caller, &config)
         };
+
+        assert!(
+            handler
+                .handle(String::from("Message-Id"), &event, &config)
+                .await
+                .is_err()
+        );
+        assert!(state.mark_seen("message#Message-Id").await?);
+        Ok(())
     }
-}
 
-fn read_config(path: &PathBuf) -> Result<MessageComponents> {
-    let config_str = match std::fs::read_to_string(path) {
-        Ok(text) => text,
-        Err(_) => {
-            return Err(anyhow!(
-                "Failed to read configuration file for building messages"
-            ));
-        }
-    };
+    #[tokio::test]
+    async fn game_groups_look_up_the_category_before_the_first_channel_update() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_games.json".into();
 
-    serde_json::from_str::<MessageComponents>(config_str.as_ref())
-        .map_err(|e| anyhow!("Failed to deserialize message config: {e}"))
-}
+        let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        payload_path.push("resources/tests/reward_redemption_event.json");
+        let payload = std::fs::read_to_string(payload_path)?;
+        let event: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
 
-#[cfg(test)]
-mod tests {
-    use crate::client::StreamelementsCaller;
-    use crate::config::AppConfig;
-    use crate::reward::RewardHandler;
-    use crate::reward::mod_feeder::ModFeed;
-    use crate::types::twitch::{self, RewardRedeemed};
-    use anyhow::Result;
-    use axum::http::HeaderMap;
-    use lambda_http::{Body, Response};
-    use mockall::{mock, predicate};
-    use reqwest::StatusCode;
-    use std::path::PathBuf;
+        let mut mock_caller = MockCaller::new();
+        mock_caller
+            .expect_get_channel_info()
+            .returning(|_| {
+                Ok(ChannelInfo {
+                    title: "Chickens!".into(),
+                    category_id: "27471".into(),
+                    category_name: "Minecraft".into(),
+                })
+            })
+            .once();
+        mock_caller
+            .expect_say()
+            .with(
+                predicate::eq("John mined a cracker.".to_string()),
+                predicate::always(),
+            )
+            .returning(|_, _| Ok("result".to_string()))
+            .times(2);
 
-    mock! {
-        pub Caller {}
+        let handler = test_feed(mock_caller, &config);
 
-        impl StreamelementsCaller for Caller {
-            async fn say(&self, msg: &str, config: &AppConfig) -> Result<String>;
+        for id in ["Message-1", "Message-2"] {
+            handler.handle(id.into(), &event, &config).await?;
         }
+        Ok(())
     }
 
     #[tokio::test]
-    async fn builds_scenario_and_calls_streamelements_api() -> Result<()> {
+    async fn templates_can_use_reward_placeholders() -> Result<()> {
         dotenvy::from_filename(".env.test")?;
-        let config = AppConfig::from_env();
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_reward_context.json".into();
 
         let mut payload_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         payload_path.push("resources/tests/reward_redemption_event.json");
         let payload = std::fs::read_to_string(payload_path)?;
         let event: RewardRedeemed = serde_json::from_str::<RewardRedeemed>(&payload)?;
 
-        let msg_id = String::from("Message-Id");
-        let expected_message =
-            "Anna's feeling benevolent this time, all the mods got a dry cracker each!";
+        let mut mock_caller = MockCaller::new();
+        mock_caller
+            .expect_say()
+            .with(
+                predicate::eq("John spent 100 points on title: reward prompt".to_string()),
+                predicate::always(),
+            )
+            .return_once(|_, _| Ok("result".to_string()))
+            .once();
+
+        let handler = test_feed(mock_caller, &config);
+
+        handler
+            .handle(String::from("Message-Id"), &event, &config)
+            .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn hook_posts_from_its_group_with_payload_fields() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_hooks.json".into();
 
         let mut mock_caller = MockCaller::new();
-        let se_mock = mock_caller
+        mock_caller
             .expect_say()
             .with(
-                predicate::eq(expected_message.to_string()),
-                predicate::eq(config.clone()),
+                predicate::eq("Clucky tipped 5.00, so John gets a cracker!".to_string()),
+                predicate::always(),
             )
             .return_once(|_, _| Ok("result".to_string()))
             .once();
 
+        let handler = test_feed(mock_caller, &config);
+
+        let payload = serde_json::json!({"from_name": "Clucky", "amount": "5.00"});
+        assert!(
+            handler
+                .handle_hook("kofi", hook_context(&payload), &config)
+                .await?
+        );
+        assert!(
+            !handler
+                .handle_hook("github", hook_context(&payload), &config)
+                .await?
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn hook_errors_when_chat_does_not_take_the_message() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_hooks.json".into();
+
+        let mut mock_caller = MockCaller::new();
+        mock_caller
+            .expect_say()
+            .return_once(|_, _| Err(anyhow::anyhow!("chat is down")))
+            .once();
+
+        let handler = test_feed(mock_caller, &config);
+
+        let payload = serde_json::json!({"from_name": "Clucky", "amount": "5.00"});
+        assert!(
+            handler
+                .handle_hook("kofi", hook_context(&payload), &config)
+                .await
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn live_only_hook_fills_next_stream_and_skips_offline() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_schedule.json".into();
+
+        let mut mock_caller = MockCaller::new();
+        let mut seq = Sequence::new();
+        mock_caller
+            .expect_get_stream_id()
+            .return_once(|_| Ok(Some("40952121085".into())))
+            .once()
+            .in_sequence(&mut seq);
+        mock_caller
+            .expect_get_schedule()
+            .return_once(|_| {
+                Ok(serde_json::from_value(serde_json::json!([{
+                    "start_time": "2099-07-04T19:00:00Z",
+                    "title": "Egg hunt"
+                }]))?)
+            })
+            .once()
+            .in_sequence(&mut seq);
+        mock_caller
+            .expect_say()
+            .with(
+                predicate::eq(
+                    "John says the next stream is Egg hunt on Sat 4 Jul 20:00 BST.".to_string(),
+                ),
+                predicate::always(),
+            )
+            .return_once(|_, _| Ok("result".to_string()))
+            .once()
+            .in_sequence(&mut seq);
+        mock_caller
+            .expect_get_stream_id()
+            .return_once(|_| Ok(None))
+            .once()
+            .in_sequence(&mut seq);
+
         let handler = ModFeed {
-            client: mock_caller,
+            live: Arc::new(TtlCache::new(Duration::ZERO)),
+            ..test_feed(mock_caller, &config)
         };
 
-        let response: Result<()> = handler.handle(msg_id, &event, &config).await;
+        let payload = serde_json::json!({});
+        handler
+            .handle_hook("timer", hook_context(&payload), &config)
+            .await?;
+        handler
+            .handle_hook("timer", hook_context(&payload), &config)
+            .await?;
+        Ok(())
+    }
 
-        assert!(response.is_ok());
+    #[tokio::test]
+    async fn pipeline_runs_steps_of_matching_rules() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_pipeline.json".into();
+
+        let mut mock_caller = MockCaller::new();
+        let mut seq = Sequence::new();
+        mock_caller
+            .expect_say()
+            .with(
+                predicate::eq("Cool_User cheered 1000 bits, John gets a cracker!".to_string()),
+                predicate::always(),
+            )
+            .return_once(|_, _| Ok("result".to_string()))
+            .once()
+            .in_sequence(&mut seq);
+        mock_caller
+            .expect_say()
+            .with(
+                predicate::eq("Thank you Cool_User!".to_string()),
+                predicate::always(),
+            )
+            .return_once(|_, _| Ok("result".to_string()))
+            .once()
+            .in_sequence(&mut seq);
+        mock_caller
+            .expect_award_points()
+            .with(
+                predicate::eq("cool_user"),
+                predicate::eq(100),
+                predicate::always(),
+            )
+            .return_once(|_, _, _| Ok(()))
+            .once()
+            .in_sequence(&mut seq);
+
+        let handler = test_feed(mock_caller, &config);
+
+        let big =
+            serde_json::json!({"user_login": "cool_user", "user_name": "Cool_User", "bits": 1000});
+        let small = serde_json::json!({"user_name": "Cool_User", "bits": 100});
+        assert_eq!(handler.run("channel.cheer", &big, &config).await?, 1);
+        assert_eq!(handler.run("channel.cheer", &small, &config).await?, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn channel_update_rules_see_the_previous_category() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_pipeline.json".into();
+
+        let mut mock_caller = MockCaller::new();
+        mock_caller
+            .expect_say()
+            .with(
+                predicate::eq("We're switching from Minecraft to Just Chatting".to_string()),
+                predicate::always(),
+            )
+            .return_once(|_, _| Ok("result".to_string()))
+            .once();
+        let handler = test_feed(mock_caller, &config);
+
+        let update = |title: &str, category_id: &str, category_name: &str| {
+            serde_json::json!({
+                "broadcaster_user_id": "1337",
+                "title": title,
+                "category_id": category_id,
+                "category_name": category_name
+            })
+        };
+        let minecraft = update("Building a coop", "27471", "Minecraft");
+        let chatting = update("Building a coop", "509658", "Just Chatting");
+        let retitled = update("Chatting about the coop", "509658", "Just Chatting");
+        assert_eq!(handler.run("channel.update", &minecraft, &config).await?, 1);
+        assert_eq!(handler.run("channel.update", &chatting, &config).await?, 1);
+        assert_eq!(handler.run("channel.update", &retitled, &config).await?, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn chat_commands_reply_once_per_cooldown() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_commands.json".into();
+
+        let mut mock_caller = MockCaller::new();
+        mock_caller
+            .expect_say()
+            .with(
+                predicate::eq("Most fed this month: John (2)".to_string()),
+                predicate::always(),
+            )
+            .return_once(|_, _| Ok("result".to_string()))
+            .once();
+        let state = Arc::new(MemoryStore::default());
+        state
+            .record_month_wins(&month_key(Utc::now()), &["John".into(), "John".into()])
+            .await?;
+        let handler = ModFeed {
+            state: state.clone(),
+            ..test_feed(mock_caller, &config)
+        };
+
+        let chat = |message_id: &str, text: &str| {
+            serde_json::json!({
+                "message_id": message_id,
+                "chatter_user_id": "9001",
+                "chatter_user_login": "clucky",
+                "chatter_user_name": "Clucky",
+                "message": {"text": text},
+                "badges": []
+            })
+        };
+        for event in [
+            chat("m-1", "!fedboard"),
+            chat("m-1", "!fedboard"),
+            chat("m-2", "!fedboard please"),
+            chat("m-3", "hello chat"),
+            chat("m-4", "!lurk"),
+        ] {
+            assert_eq!(
+                handler.run("channel.chat.message", &event, &config).await?,
+                1
+            );
+        }
+
+        // Lines that aren't commands aren't marked seen.
+        assert!(!state.mark_seen("chat#m-2").await?);
+        assert!(state.mark_seen("chat#m-3").await?);
+        assert!(state.mark_seen("chat#m-4").await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn chat_commands_from_the_bot_are_ignored() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_commands.json".into();
+        config.helix_chat_sender_id = Some("4242".into());
+
+        let state = Arc::new(MemoryStore::default());
+        let handler = ModFeed {
+            state: state.clone(),
+            ..test_feed(MockCaller::new(), &config)
+        };
+
+        for (message_id, user_id, login) in [
+            ("m-1", "4242", "robochick"),
+            ("m-2", "100135110", "StreamElements"),
+        ] {
+            let event = serde_json::json!({
+                "message_id": message_id,
+                "chatter_user_id": user_id,
+                "chatter_user_login": login,
+                "chatter_user_name": login,
+                "message": {"text": "!fedboard"},
+                "badges": []
+            });
+            assert_eq!(
+                handler.run("channel.chat.message", &event, &config).await?,
+                1
+            );
+        }
+
+        assert!(state.mark_seen("chat#m-1").await?);
+        assert!(state.mark_seen("chat#m-2").await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn donation_posts_from_donations_group() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.message_components_config_path =
+            "resources/tests/message_components_hooks.json".into();
+
+        let mut mock_caller = MockCaller::new();
+        mock_caller
+            .expect_say()
+            .with(
+                predicate::eq("Thanks Clucky for the 5.00 GBP! John gets a cracker.".to_string()),
+                predicate::always(),
+            )
+            .return_once(|_, _| Ok("result".to_string()))
+            .once();
+
+        let handler = test_feed(mock_caller, &config);
+
+        let donation = Donation {
+            donor: "Clucky".into(),
+            amount: "5.00 GBP".into(),
+        };
+        handler.handle_donation(&donation, &config).await?;
         Ok(())
     }
 }
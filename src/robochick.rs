@@ -1,20 +1,346 @@
 pub mod twitch {
-    use std::{collections::HashMap, error, fmt, iter::zip, vec};
-
+    use std::{
+        collections::{BTreeMap, BTreeSet, HashMap},
+        error, fmt,
+        iter::zip,
+        vec,
+    };
+
+    use chrono::{DateTime, Utc};
+    use chrono_tz::Tz;
     use fastrand::Rng;
+    use schemars::JsonSchema;
+
+    use crate::{
+        bot::SeBot,
+        budget::Budget,
+        burst::BurstGuard,
+        commands::CommandConfig,
+        config_tests::ConfigTest,
+        experiment::{self, Variant},
+        grammar::{Pronouns, apply_helpers, helper_keys},
+        locale::Locale,
+        migrate::SchemaVersion,
+        offline::OfflineBehavior,
+        permissions::RoleGate,
+        pipeline::Rule,
+        quiet::QuietHours,
+        recap::RecapConfig,
+        season::ActiveWindow,
+        text, variables,
+    };
     use serde::{Deserialize, Serialize};
 
-    #[derive(Serialize, Deserialize, Debug, Clone)]
+    #[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
     pub struct MessageComponents {
+        /// Format version of the config. Older files are migrated when they're read.
+        #[serde(default)]
+        pub(crate) schema_version: SchemaVersion,
         pub(crate) scenarios: Vec<Scenario>,
         pub(crate) mods: Vec<String>,
+        #[serde(default)]
+        pub(crate) selection: Selection,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub(crate) groups: Vec<ScenarioGroup>,
+        /// How many of the most recent winners sit out the next redemptions. 0 turns it off.
+        #[serde(default)]
+        pub(crate) anti_repeat_window: usize,
+        /// Pronouns for mods, keyed by name, used by helpers like `{their(win_1)}`.
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        pub(crate) pronouns: HashMap<String, Pronouns>,
+        /// Word lists shared by every template, e.g. `{snack}` from a list of foods. Each
+        /// message gets one word from each list, picked at random.
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        pub(crate) variables: BTreeMap<String, Vec<String>>,
+        /// Budget for the rewards using the top-level scenarios.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub(crate) budget: Option<Budget>,
+        /// Windows during which redemptions are acknowledged without posting anything.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub(crate) quiet_hours: Vec<QuietHours>,
+        /// Rules for EventSub notifications, run whether or not a reward handler took them.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub(crate) pipeline: Vec<Rule>,
+        /// Regression tests for the templates, run by `validate-config`.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub(crate) tests: Vec<ConfigTest>,
+        /// IANA timezone `{next_stream}` is written in. Defaults to UTC.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        #[schemars(with = "Option<String>")]
+        pub(crate) schedule_timezone: Option<Tz>,
+        /// What to do with redemptions while the stream is offline. Posts them by default.
+        #[serde(default, skip_serializing_if = "OfflineBehavior::is_post")]
+        pub(crate) when_offline: OfflineBehavior,
+        /// Posted once the stream is live instead of each queued redemption, e.g. `While I was
+        /// asleep, {users} fed the mods {count} times`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub(crate) offline_summary: Option<String>,
+        /// A summary of the month's feedings, posted on a schedule.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub(crate) recap: Option<RecapConfig>,
+        /// Spotting redemptions in quick succession, answered with one message.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub(crate) burst: Option<BurstGuard>,
+        /// Language of numbers and times written by the template helpers, and of the recap's
+        /// month. English by default.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub(crate) locale: Option<Locale>,
+        /// Chat commands like `!fedboard`. Chat isn't read for commands without it.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub(crate) commands: Option<CommandConfig>,
+        /// Show mods by their Twitch display names, looked up from `mods` as logins, in
+        /// messages, `!fedboard`, the leaderboard and the recap.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        pub(crate) display_names: bool,
+    }
+
+    /// Name of the group made up of the top-level `scenarios`.
+    pub const DEFAULT_GROUP: &str = "default";
+
+    /// A set of scenarios used for specific rewards. Redemptions of rewards not listed by any
+    /// group use the top-level scenarios.
+    #[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+    pub struct ScenarioGroup {
+        pub(crate) name: String,
+        #[serde(default)]
+        pub(crate) reward_ids: Vec<String>,
+        /// Names of the `/hooks/{name}` webhooks that post from this group.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub(crate) hooks: Vec<String>,
+        #[serde(default)]
+        pub(crate) selection: Selection,
+        pub(crate) scenarios: Vec<Scenario>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub(crate) budget: Option<Budget>,
+        /// Roles a redeemer needs for the group's rewards.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub(crate) requires: Option<RoleGate>,
+        /// StreamElements bot the group's messages are posted as, instead of the default one.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub(crate) bot: Option<SeBot>,
+        /// When the group is used. Rewards fall back to the default group outside it.
+        #[serde(flatten)]
+        pub(crate) active: ActiveWindow,
+        /// Skip the group's hook posts while the stream is offline, e.g. for timers.
+        #[serde(default, skip_serializing_if = "is_false")]
+        pub(crate) only_when_live: bool,
+        /// Twitch category ids of the games the group is for. It's only used while the stream
+        /// is in one of them, and before groups that don't list any.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub(crate) games: Vec<String>,
     }
 
-    #[derive(Serialize, Deserialize, Debug, Clone)]
+    fn is_false(b: &bool) -> bool {
+        !b
+    }
+
+    /// How mods are chosen to fill a scenario's winner placeholders.
+    #[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Selection {
+        #[default]
+        Random,
+        /// Winners are taken in turn from the mods list, continuing after the last mod picked.
+        RoundRobin,
+        /// Winners are picked at random, weighted towards mods who've won less often so far.
+        Fair,
+    }
+
+    #[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
     pub struct Scenario {
+        /// Stable name used in stats. The template itself is used when there isn't one.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub(crate) id: Option<String>,
         pub(crate) template: String,
         pub(crate) winners: Vec<String>,
         pub(crate) others: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub(crate) follow_up: Option<FollowUp>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub(crate) vote: Option<Vote>,
+        /// Makes the scenario a variant of an experiment, see [`ScenarioGroup::for_viewer`].
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub(crate) experiment: Option<Variant>,
+        /// Percentage of redemptions a new scenario can be picked for, while it's tried out.
+        /// Remove it to promote the scenario to every redemption.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub(crate) canary: Option<Percent>,
+        /// When the scenario can be picked.
+        #[serde(flatten)]
+        pub(crate) active: ActiveWindow,
+    }
+
+    /// A percentage, from 0 to 100. Larger numbers are rejected when the config is read.
+    #[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq)]
+    #[serde(try_from = "u8", into = "u8")]
+    #[schemars(extend("maximum" = 100))]
+    pub struct Percent(u8);
+
+    impl TryFrom<u8> for Percent {
+        type Error = String;
+
+        fn try_from(percent: u8) -> Result<Self, Self::Error> {
+            match percent {
+                0..=100 => Ok(Percent(percent)),
+                _ => Err(format!("Expected a percentage up to 100, got {percent}")),
+            }
+        }
+    }
+
+    impl From<Percent> for u8 {
+        fn from(percent: Percent) -> Self {
+            percent.0
+        }
+    }
+
+    /// Lets chat decide how the scenario ends. The scenario's message is posted as usual, a poll
+    /// is started with `question` as its title, and the outcome of the winning choice is posted
+    /// once the poll closes.
+    #[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+    pub struct Vote {
+        pub(crate) question: String,
+        pub(crate) choices: Vec<VoteChoice>,
+        #[serde(default = "default_vote_duration_secs")]
+        pub(crate) duration_secs: u64,
+    }
+
+    #[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+    pub struct VoteChoice {
+        pub(crate) title: String,
+        pub(crate) outcome: String,
+    }
+
+    fn default_vote_duration_secs() -> u64 {
+        60
+    }
+
+    /// A rendered vote. `outcomes[i]` is posted when `choices[i]` wins.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct BuiltVote {
+        pub question: String,
+        pub choices: Vec<String>,
+        pub outcomes: Vec<String>,
+        pub duration_secs: u64,
+    }
+
+    /// A second message posted `delay_secs` after the scenario's own message. Its template can
+    /// use the same placeholders as the scenario it belongs to.
+    #[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+    pub struct FollowUp {
+        pub(crate) template: String,
+        pub(crate) delay_secs: u64,
+    }
+
+    /// A rendered follow-up, ready to be scheduled.
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    pub struct FollowUpMessage {
+        pub message: String,
+        pub delay_secs: u64,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct BuiltMessage {
+        pub message: String,
+        pub follow_up: Option<FollowUpMessage>,
+        pub vote: Option<BuiltVote>,
+        pub winners: Vec<String>,
+        /// Key of the scenario that was picked, see [`Scenario::key`].
+        pub scenario: String,
+        pub experiment: Option<Variant>,
+    }
+
+    /// Placeholder filled with a random user from the broadcaster's current chatters.
+    pub const RANDOM_VIEWER: &str = "random_viewer";
+
+    /// Placeholders filled from the redeemed reward, so one group can word its messages for
+    /// each of its rewards.
+    pub const REWARD_TITLE: &str = "reward_title";
+    pub const REWARD_COST: &str = "reward_cost";
+    pub const REWARD_PROMPT: &str = "reward_prompt";
+
+    /// Placeholders filled with the redeemer's display name and login.
+    pub const USER_NAME: &str = "user_name";
+    pub const USER_LOGIN: &str = "user_login";
+
+    /// Placeholders filled from the broadcaster's Twitch schedule: when the next stream starts
+    /// and its title.
+    pub const NEXT_STREAM: &str = "next_stream";
+    pub const NEXT_STREAM_TITLE: &str = "next_stream_title";
+
+    /// Group that donations are announced from, with the placeholders filled from them.
+    pub const DONATIONS_GROUP: &str = "donations";
+    pub const DONOR: &str = "donor";
+    pub const AMOUNT: &str = "amount";
+
+    /// Values for placeholders that don't come from the mods list, e.g. `{random_viewer}`.
+    #[derive(Default, Debug, Clone)]
+    pub struct TemplateContext {
+        values: HashMap<String, String>,
+        pronouns: HashMap<String, Pronouns>,
+        locale: Locale,
+        /// How mods are shown, by name in the mods list.
+        display_names: HashMap<String, String>,
+    }
+
+    impl TemplateContext {
+        /// Sets a placeholder's value, normalized and shortened as it can come from users.
+        pub fn insert(&mut self, key: impl Into<String>, val: impl Into<String>) {
+            self.values.insert(key.into(), text::sanitize(&val.into()));
+        }
+
+        pub fn get(&self, key: &str) -> Option<&str> {
+            self.values.get(key).map(String::as_str)
+        }
+
+        pub fn set_pronouns(&mut self, pronouns: HashMap<String, Pronouns>) {
+            self.pronouns = pronouns;
+        }
+
+        pub fn set_locale(&mut self, locale: Locale) {
+            self.locale = locale;
+        }
+
+        pub fn set_display_names(&mut self, display_names: HashMap<String, String>) {
+            self.display_names = display_names;
+        }
+
+        /// How the mod `name` is shown in messages.
+        pub fn display_name<'a>(&'a self, name: &'a str) -> &'a str {
+            self.display_names.get(name).map_or(name, String::as_str)
+        }
+
+        /// The mods' pronouns, under their display names as well.
+        fn shown_pronouns(&self) -> HashMap<String, Pronouns> {
+            let mut pronouns = self.pronouns.clone();
+            for (name, shown) in &self.display_names {
+                if let Some(p) = self.pronouns.get(name) {
+                    pronouns.insert(shown.clone(), *p);
+                }
+            }
+            pronouns
+        }
+
+        /// Adds each top-level string, number or bool of `payload` as `{<prefix>_<field>}`.
+        pub fn insert_fields(&mut self, prefix: &str, payload: &serde_json::Value) {
+            use serde_json::Value;
+
+            if let Some(fields) = payload.as_object() {
+                for (key, val) in fields {
+                    let key = format!("{prefix}_{key}");
+                    match val {
+                        Value::String(s) => self.insert(key, s),
+                        Value::Number(n) => self.insert(key, n.to_string()),
+                        Value::Bool(b) => self.insert(key, b.to_string()),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        /// Fills `template`'s placeholders from the context alone.
+        pub fn format(&self, template: &str) -> Result<String, ScenarioError> {
+            format_template(template, &self.values, self)
+        }
     }
 
     #[derive(Debug)]
@@ -41,6 +367,24 @@ pub mod twitch {
             &self.template
         }
 
+        /// Identifies the scenario in stats: its `id` if set, otherwise its template.
+        pub fn key(&self) -> &str {
+            self.id.as_deref().unwrap_or(&self.template)
+        }
+
+        pub fn get_experiment(&self) -> Option<&Variant> {
+            self.experiment.as_ref()
+        }
+
+        /// Whether the scenario can be picked for this redemption. Canaries are for their
+        /// percentage of redemptions, everything else for all of them.
+        fn eligible(&self, rng: &mut Rng) -> bool {
+            match self.canary {
+                Some(Percent(percent)) => rng.u8(..100) < percent,
+                None => true,
+            }
+        }
+
         pub fn get_winners(&self) -> &[String] {
             &self.winners
         }
@@ -49,11 +393,97 @@ pub mod twitch {
             &self.others
         }
 
+        pub fn get_follow_up(&self) -> Option<&FollowUp> {
+            self.follow_up.as_ref()
+        }
+
+        pub fn get_vote(&self) -> Option<&Vote> {
+            self.vote.as_ref()
+        }
+
+        pub fn uses_placeholder(&self, name: &str) -> bool {
+            let placeholder = format!("{{{name}}}");
+            self.templates()
+                .any(|t| t.contains(&placeholder) || helper_keys(t).contains(&name))
+        }
+
+        /// Every template string in the scenario, including follow-ups and vote outcomes.
+        fn templates(&self) -> impl Iterator<Item = &str> {
+            let follow_up = self.follow_up.iter().map(|f| f.template.as_str());
+            let vote = self.vote.iter().flat_map(|v| {
+                std::iter::once(v.question.as_str()).chain(
+                    v.choices
+                        .iter()
+                        .flat_map(|c| [c.title.as_str(), c.outcome.as_str()]),
+                )
+            });
+
+            std::iter::once(self.template.as_str())
+                .chain(follow_up)
+                .chain(vote)
+        }
+
         pub fn build(
             &self,
             winners: &[String],
             others: &[String],
+            context: &TemplateContext,
         ) -> Result<String, ScenarioError> {
+            let values = self.values(winners, others, context)?;
+            format_template(&self.template, &values, context)
+        }
+
+        pub fn build_follow_up(
+            &self,
+            winners: &[String],
+            others: &[String],
+            context: &TemplateContext,
+        ) -> Result<Option<FollowUpMessage>, ScenarioError> {
+            let follow_up = match self.follow_up.as_ref() {
+                Some(f) => f,
+                None => return Ok(None),
+            };
+
+            let values = self.values(winners, others, context)?;
+            Ok(Some(FollowUpMessage {
+                message: format_template(&follow_up.template, &values, context)?,
+                delay_secs: follow_up.delay_secs,
+            }))
+        }
+
+        pub fn build_vote(
+            &self,
+            winners: &[String],
+            others: &[String],
+            context: &TemplateContext,
+        ) -> Result<Option<BuiltVote>, ScenarioError> {
+            let vote = match self.vote.as_ref() {
+                Some(v) => v,
+                None => return Ok(None),
+            };
+
+            let values = self.values(winners, others, context)?;
+            let mut choices = vec![];
+            let mut outcomes = vec![];
+            for choice in &vote.choices {
+                choices.push(format_template(&choice.title, &values, context)?);
+                outcomes.push(format_template(&choice.outcome, &values, context)?);
+            }
+
+            Ok(Some(BuiltVote {
+                question: format_template(&vote.question, &values, context)?,
+                choices,
+                outcomes,
+                duration_secs: vote.duration_secs,
+            }))
+        }
+
+        fn values(
+            &self,
+            winners: &[String],
+            others: &[String],
+            context: &TemplateContext,
+        ) -> Result<HashMap<String, String>, ScenarioError> {
             if self.winners.len() != winners.len() {
                 return Err(ScenarioError::NotEnoughPlaceholders(format!(
                     "Expected {} values, found {}",
@@ -70,21 +500,31 @@ pub mod twitch {
                 )));
             }
 
-            let mut values: HashMap<String, String> = HashMap::new();
+            let mut values: HashMap<String, String> = context.values.clone();
             for (k, v) in zip(self.winners.clone(), winners) {
-                values.insert(k, v.to_string());
+                values.insert(k, context.display_name(v).to_string());
             }
 
             for (k, v) in zip(self.others.clone(), others) {
-                values.insert(k, v.to_string());
+                values.insert(k, context.display_name(v).to_string());
             }
 
-            match strfmt::strfmt(&self.template, &values) {
-                Ok(msg) => Ok(msg),
-                Err(e) => Err(ScenarioError::InvalidValue(format!(
-                    "Failed to format string. Original error: {e}"
-                ))),
-            }
+            Ok(values)
+        }
+    }
+
+    fn format_template(
+        template: &str,
+        values: &HashMap<String, String>,
+        context: &TemplateContext,
+    ) -> Result<String, ScenarioError> {
+        let template = apply_helpers(template, values, &context.shown_pronouns(), context.locale)
+            .map_err(ScenarioError::InvalidValue)?;
+        match strfmt::strfmt(&template, values) {
+            Ok(msg) => Ok(msg),
+            Err(e) => Err(ScenarioError::InvalidValue(format!(
+                "Failed to format string. Original error: {e}"
+            ))),
         }
     }
 
@@ -96,13 +536,443 @@ pub mod twitch {
         pub fn get_scenarios(&self) -> &[Scenario] {
             &self.scenarios
         }
+
+        pub fn get_pronouns(&self) -> &HashMap<String, Pronouns> {
+            &self.pronouns
+        }
+
+        pub fn get_schedule_timezone(&self) -> Tz {
+            self.schedule_timezone.unwrap_or(Tz::UTC)
+        }
+
+        pub fn get_when_offline(&self) -> OfflineBehavior {
+            self.when_offline
+        }
+
+        pub fn get_offline_summary(&self) -> Option<&str> {
+            self.offline_summary.as_deref()
+        }
+
+        pub fn get_recap(&self) -> Option<&RecapConfig> {
+            self.recap.as_ref()
+        }
+
+        pub fn get_burst(&self) -> Option<&BurstGuard> {
+            self.burst.as_ref()
+        }
+
+        pub fn get_locale(&self) -> Locale {
+            self.locale.unwrap_or_default()
+        }
+
+        pub fn get_commands(&self) -> Option<&CommandConfig> {
+            self.commands.as_ref()
+        }
+
+        pub fn shows_display_names(&self) -> bool {
+            self.display_names
+        }
+
+        /// Fills each of the `variables` in `context` with a random word from its list,
+        /// leaving values already set alone. Placeholders in the words are expanded too.
+        pub fn resolve_variables(
+            &self,
+            context: &mut TemplateContext,
+            rng: &mut Rng,
+        ) -> Result<(), ScenarioError> {
+            let mut picks = BTreeMap::new();
+            for (name, words) in &self.variables {
+                if context.get(name).is_none()
+                    && let Some(word) = rng.choice(words)
+                {
+                    picks.insert(name.clone(), word.clone());
+                }
+            }
+
+            let expanded =
+                variables::expand_all(&picks, context).map_err(ScenarioError::InvalidValue)?;
+            for (name, value) in expanded {
+                context.insert(name, value);
+            }
+            Ok(())
+        }
+
+        pub fn get_anti_repeat_window(&self) -> usize {
+            self.anti_repeat_window
+        }
+
+        pub fn get_quiet_hours(&self) -> &[QuietHours] {
+            &self.quiet_hours
+        }
+
+        pub fn get_pipeline(&self) -> &[Rule] {
+            &self.pipeline
+        }
+
+        pub fn get_tests(&self) -> &[ConfigTest] {
+            &self.tests
+        }
+
+        pub fn default_group(&self) -> ScenarioGroup {
+            ScenarioGroup {
+                name: DEFAULT_GROUP.into(),
+                reward_ids: vec![],
+                hooks: vec![],
+                selection: self.selection,
+                scenarios: self.scenarios.clone(),
+                budget: self.budget.clone(),
+                requires: None,
+                bot: None,
+                active: ActiveWindow::default(),
+                only_when_live: false,
+                games: vec![],
+            }
+        }
+
+        /// Every configured scenario, across the top-level list and all groups.
+        pub fn all_scenarios(&self) -> impl Iterator<Item = &Scenario> {
+            self.scenarios
+                .iter()
+                .chain(self.groups.iter().flat_map(|g| g.scenarios.iter()))
+        }
+
+        /// Whether any group is limited to particular games, so picking one needs the current
+        /// category.
+        pub fn has_game_groups(&self) -> bool {
+            self.groups.iter().any(|g| !g.games.is_empty())
+        }
+
+        /// The group configured for `reward_id` that's active at `now` while `game` is being
+        /// played, or the default group if there is none.
+        pub fn group_for_reward(
+            &self,
+            reward_id: &str,
+            now: DateTime<Utc>,
+            game: Option<&str>,
+        ) -> ScenarioGroup {
+            self.groups
+                .iter()
+                .filter(|g| g.active.contains(now) && g.plays(game))
+                .filter(|g| g.reward_ids.iter().any(|id| id == reward_id))
+                .min_by_key(|g| g.games.is_empty())
+                .cloned()
+                .unwrap_or_else(|| self.default_group())
+        }
+
+        pub fn group_named(&self, name: &str) -> Option<ScenarioGroup> {
+            self.groups.iter().find(|g| g.name == name).cloned()
+        }
+
+        /// The group configured for the `hook` webhook that's active at `now` while `game` is
+        /// being played. Hooks only post from groups that list them.
+        pub fn group_for_hook(
+            &self,
+            hook: &str,
+            now: DateTime<Utc>,
+            game: Option<&str>,
+        ) -> Option<ScenarioGroup> {
+            self.groups
+                .iter()
+                .filter(|g| g.active.contains(now) && g.plays(game))
+                .filter(|g| g.hooks.iter().any(|h| h == hook))
+                .min_by_key(|g| g.games.is_empty())
+                .cloned()
+        }
+    }
+
+    impl ScenarioGroup {
+        pub fn get_name(&self) -> &str {
+            &self.name
+        }
+
+        /// The group with only the scenarios that can be picked at `now`. None can be when the
+        /// group itself isn't active.
+        pub fn active_at(&self, now: DateTime<Utc>) -> ScenarioGroup {
+            let scenarios = match self.active.contains(now) {
+                true => self
+                    .scenarios
+                    .iter()
+                    .filter(|s| s.active.contains(now))
+                    .cloned()
+                    .collect(),
+                false => vec![],
+            };
+            ScenarioGroup {
+                scenarios,
+                ..self.clone()
+            }
+        }
+
+        /// Narrows each experiment in the group down to the variant `user_id` is assigned.
+        /// Every variant stays in when there's no user to assign.
+        pub fn for_viewer(&self, user_id: Option<&str>) -> ScenarioGroup {
+            let Some(user_id) = user_id else {
+                return self.clone();
+            };
+            let mut experiments: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+            for variant in self.scenarios.iter().filter_map(|s| s.experiment.as_ref()) {
+                experiments
+                    .entry(&variant.experiment)
+                    .or_default()
+                    .insert(&variant.variant);
+            }
+
+            let scenarios = self
+                .scenarios
+                .iter()
+                .filter(|s| match &s.experiment {
+                    Some(v) => {
+                        experiment::assign(
+                            &v.experiment,
+                            &experiments[v.experiment.as_str()],
+                            user_id,
+                        ) == Some(v.variant.as_str())
+                    }
+                    None => true,
+                })
+                .cloned()
+                .collect();
+            ScenarioGroup {
+                scenarios,
+                ..self.clone()
+            }
+        }
+
+        pub fn get_selection(&self) -> Selection {
+            self.selection
+        }
+
+        pub fn get_scenarios(&self) -> &[Scenario] {
+            &self.scenarios
+        }
+
+        pub fn get_budget(&self) -> Option<&Budget> {
+            self.budget.as_ref()
+        }
+
+        pub fn get_requires(&self) -> Option<&RoleGate> {
+            self.requires.as_ref()
+        }
+
+        pub fn get_bot(&self) -> Option<&SeBot> {
+            self.bot.as_ref()
+        }
+
+        pub fn is_only_when_live(&self) -> bool {
+            self.only_when_live
+        }
+
+        /// Whether the group is used while `game`, a category id, is being played. Groups for
+        /// particular games aren't when the game isn't known.
+        fn plays(&self, game: Option<&str>) -> bool {
+            self.games.is_empty() || game.is_some_and(|game| self.games.iter().any(|g| g == game))
+        }
+    }
+
+    /// Chooses the mods that fill a scenario's placeholders.
+    pub trait ModPicker {
+        /// Picks up to `winners + others` distinct mods. The first `winners` picks are the
+        /// winners.
+        fn pick(
+            &mut self,
+            mods: &[String],
+            winners: usize,
+            others: usize,
+            rng: &mut Rng,
+        ) -> Vec<String>;
+    }
+
+    pub struct RandomPicker;
+
+    impl ModPicker for RandomPicker {
+        fn pick(
+            &mut self,
+            mods: &[String],
+            winners: usize,
+            others: usize,
+            rng: &mut Rng,
+        ) -> Vec<String> {
+            pick_random(mods, winners + others, rng)
+        }
+    }
+
+    /// Hands out wins in turn so every mod gets fed roughly equally over time, starting after
+    /// the mod who won last so mods joining or leaving the list don't throw the order off.
+    /// Recent winners are passed over while there are enough others, so it isn't wrapped in an
+    /// [`AntiRepeatPicker`], which would reorder the mods. Others are still picked at random
+    /// from the remaining mods.
+    pub struct RoundRobinPicker {
+        /// Mod who got the last win handed out, moved along as wins are handed out.
+        pub last: Option<String>,
+        /// Most recent winner first.
+        pub recent: Vec<String>,
+    }
+
+    impl ModPicker for RoundRobinPicker {
+        fn pick(
+            &mut self,
+            mods: &[String],
+            winners: usize,
+            others: usize,
+            rng: &mut Rng,
+        ) -> Vec<String> {
+            if mods.is_empty() {
+                return vec![];
+            }
+
+            let start = self
+                .last
+                .as_ref()
+                .and_then(|last| mods.iter().position(|m| m == last))
+                .map_or(0, |i| i + 1);
+            let winner_count = winners.min(mods.len());
+            let mut picks: Vec<String> = (0..mods.len())
+                .map(|i| &mods[(start + i) % mods.len()])
+                .filter(|m| !self.recent.contains(m))
+                .take(winner_count)
+                .cloned()
+                .collect();
+            if let Some(last) = picks.last() {
+                self.last = Some(last.clone());
+            }
+            let backfill: Vec<String> = self
+                .recent
+                .iter()
+                .rev()
+                .filter(|r| mods.contains(r))
+                .take(winner_count - picks.len())
+                .cloned()
+                .collect();
+            picks.extend(backfill);
+
+            let rest: Vec<String> = mods
+                .iter()
+                .filter(|m| !picks.contains(m))
+                .cloned()
+                .collect();
+            picks.extend(pick_random(&rest, others, rng));
+            picks
+        }
+    }
+
+    /// How much a month's wins count relative to the month after it, so a mod who won a lot a
+    /// while ago isn't held back for good.
+    pub const WIN_DECAY: f64 = 0.5;
+
+    /// Picks winners with weights inversely proportional to how often each mod has won lately,
+    /// so under-picked mods catch up over time. Others are still picked at random.
+    pub struct FairPicker {
+        /// Recent wins by mod, older months counting for less. Mods missing from it haven't won
+        /// lately.
+        pub wins: HashMap<String, f64>,
+    }
+
+    impl FairPicker {
+        /// Weighs the wins of recent months, newest first, each month counting [`WIN_DECAY`] as
+        /// much as the one after it.
+        pub fn from_months(months: &[HashMap<String, u64>]) -> Self {
+            let mut wins: HashMap<String, f64> = HashMap::new();
+            for (age, month) in months.iter().enumerate() {
+                let factor = WIN_DECAY.powi(age as i32);
+                for (name, count) in month {
+                    *wins.entry(name.clone()).or_default() += *count as f64 * factor;
+                }
+            }
+            FairPicker { wins }
+        }
+
+        /// Chance of `name` being picked relative to a mod who hasn't won lately.
+        pub fn weight(&self, name: &str) -> f64 {
+            1.0 / (self.wins.get(name).copied().unwrap_or(0.0) + 1.0)
+        }
+    }
+
+    impl ModPicker for FairPicker {
+        fn pick(
+            &mut self,
+            mods: &[String],
+            winners: usize,
+            others: usize,
+            rng: &mut Rng,
+        ) -> Vec<String> {
+            let mut pool: Vec<&String> = mods.iter().collect();
+            let mut picks: Vec<String> = vec![];
+            while picks.len() < winners && !pool.is_empty() {
+                let total: f64 = pool.iter().map(|m| self.weight(m)).sum();
+                let mut target = rng.f64() * total;
+                let mut chosen = pool.len() - 1;
+                for (i, name) in pool.iter().enumerate() {
+                    target -= self.weight(name);
+                    if target < 0.0 {
+                        chosen = i;
+                        break;
+                    }
+                }
+                picks.push(pool.remove(chosen).clone());
+            }
+
+            let rest: Vec<String> = pool.into_iter().cloned().collect();
+            picks.extend(pick_random(&rest, others, rng));
+            picks
+        }
+    }
+
+    /// Keeps recent winners out of the winners pool, falling back to the ones that won longest
+    /// ago when too few mods are left. Others can still be anyone. Only for pickers that don't
+    /// depend on the order of the mods.
+    pub struct AntiRepeatPicker<'a> {
+        pub inner: &'a mut dyn ModPicker,
+        /// Most recent winner first.
+        pub recent: Vec<String>,
+    }
+
+    impl ModPicker for AntiRepeatPicker<'_> {
+        fn pick(
+            &mut self,
+            mods: &[String],
+            winners: usize,
+            others: usize,
+            rng: &mut Rng,
+        ) -> Vec<String> {
+            let mut pool: Vec<String> = mods
+                .iter()
+                .filter(|m| !self.recent.contains(m))
+                .cloned()
+                .collect();
+
+            let backfill = self
+                .recent
+                .iter()
+                .rev()
+                .filter(|r| mods.contains(r))
+                .take(winners.saturating_sub(pool.len()));
+            pool.extend(backfill.cloned());
+
+            let mut picks = self.inner.pick(&pool, winners, 0, rng);
+            let rest: Vec<String> = mods
+                .iter()
+                .filter(|m| !picks.contains(m))
+                .cloned()
+                .collect();
+            picks.extend(pick_random(&rest, others, rng));
+            picks
+        }
     }
 
     pub trait MessageBuilder {
         fn build_from_templates(
             message_components: &MessageComponents,
+            context: &TemplateContext,
+            rng: &mut Rng,
+        ) -> Result<BuiltMessage, ScenarioError>;
+
+        fn build_from_group(
+            group: &ScenarioGroup,
+            mods: &[String],
+            picker: &mut dyn ModPicker,
+            context: &TemplateContext,
             rng: &mut Rng,
-        ) -> Result<String, ScenarioError>;
+        ) -> Result<BuiltMessage, ScenarioError>;
     }
 
     pub struct Robochick {}
@@ -116,16 +986,44 @@ pub mod twitch {
     impl MessageBuilder for Robochick {
         fn build_from_templates(
             message_components: &MessageComponents,
+            context: &TemplateContext,
             rng: &mut Rng,
-        ) -> Result<String, ScenarioError> {
-            let mods: &[String] = message_components.get_mods();
-            let scenarios: &[Scenario] = message_components.get_scenarios();
+        ) -> Result<BuiltMessage, ScenarioError> {
+            let mut context = context.clone();
+            message_components.resolve_variables(&mut context, rng)?;
+            Robochick::build_from_group(
+                &message_components.default_group().active_at(Utc::now()),
+                message_components.get_mods(),
+                &mut RandomPicker,
+                &context,
+                rng,
+            )
+        }
+
+        fn build_from_group(
+            group: &ScenarioGroup,
+            mods: &[String],
+            picker: &mut dyn ModPicker,
+            context: &TemplateContext,
+            rng: &mut Rng,
+        ) -> Result<BuiltMessage, ScenarioError> {
+            let eligible: Vec<Scenario> = group
+                .get_scenarios()
+                .iter()
+                .filter(|s| s.eligible(rng))
+                .cloned()
+                .collect();
+            // A group of nothing but canaries still posts when none of them come up.
+            let scenarios: &[Scenario] = match eligible.is_empty() {
+                true => group.get_scenarios(),
+                false => &eligible,
+            };
 
             if let Some(scenario_pick) = pick_random(scenarios, 1, rng).pop() {
                 let m = scenario_pick.get_winners().len();
                 let n = scenario_pick.get_others().len();
 
-                let picks = pick_random(mods, m + n, rng);
+                let picks = picker.pick(mods, m, n, rng);
 
                 /**
                  * Calling `pick_random()` once for each `m` and `n` had an edge case where
@@ -142,7 +1040,14 @@ pub mod twitch {
                     }
                 };
 
-                scenario_pick.build(winners, others)
+                Ok(BuiltMessage {
+                    message: scenario_pick.build(winners, others, context)?,
+                    follow_up: scenario_pick.build_follow_up(winners, others, context)?,
+                    vote: scenario_pick.build_vote(winners, others, context)?,
+                    winners: winners.to_vec(),
+                    scenario: scenario_pick.key().to_string(),
+                    experiment: scenario_pick.experiment.clone(),
+                })
             } else {
                 Err(ScenarioError::PickFailed(
                     "Failed to select a scenario".into(),
@@ -151,7 +1056,7 @@ pub mod twitch {
         }
     }
 
-    fn pick_random<T: Clone>(haystack: &[T], amount: usize, rng: &mut Rng) -> Vec<T> {
+    pub(crate) fn pick_random<T: Clone>(haystack: &[T], amount: usize, rng: &mut Rng) -> Vec<T> {
         if haystack.is_empty() || amount == 0 {
             return vec![];
         }
@@ -170,12 +1075,21 @@ pub mod twitch {
     }
 
     #[cfg(test)]
+    #[allow(clippy::manual_contains, clippy::useless_vec)]
     mod tests {
+        use std::collections::HashMap;
+
         use anyhow::Result;
+        use chrono::{TimeZone, Utc};
         use fastrand::Rng;
+        use serde_json::json;
 
+        use crate::grammar::Pronouns;
         use crate::robochick::twitch::{
-            MessageBuilder, MessageComponents, Robochick, Scenario, pick_random,
+            AntiRepeatPicker, BuiltVote, DEFAULT_GROUP, FairPicker, FollowUp, FollowUpMessage,
+            MessageBuilder, MessageComponents, ModPicker, Percent, RANDOM_VIEWER, RandomPicker,
+            Robochick, RoundRobinPicker, Scenario, ScenarioGroup, Selection, TemplateContext, Vote,
+            VoteChoice, pick_random,
         };
 
         #[test]
@@ -235,12 +1149,13 @@ pub mod twitch {
                 template: "{placeholder} is the expected {other_placeholder}".into(),
                 winners: vec!["placeholder".into()],
                 others: vec!["other_placeholder".into()],
+                ..Default::default()
             };
 
             let winners: Vec<String> = vec!["This".into()];
             let others: Vec<String> = vec!["sentence.".into()];
 
-            let result = scenario.build(&winners, &others)?;
+            let result = scenario.build(&winners, &others, &TemplateContext::default())?;
 
             assert_eq!("This is the expected sentence.", result);
 
@@ -253,12 +1168,13 @@ pub mod twitch {
                 template: "{placeholder} is the expected {other_placeholder}".into(),
                 winners: vec!["placeholder".into()],
                 others: vec!["other_placeholder".into(), "extra_placeholder".into()],
+                ..Default::default()
             };
 
             let winners: Vec<String> = vec!["This".into()];
             let others: Vec<String> = vec!["sentence.".into()];
 
-            let result = scenario.build(&winners, &others);
+            let result = scenario.build(&winners, &others, &TemplateContext::default());
 
             assert!(result.is_err());
             Ok(())
@@ -270,31 +1186,109 @@ pub mod twitch {
                 template: "{placeholder} is the expected {other_placeholder}".into(),
                 winners: vec!["placeholder".into(), "extra_placeholder".into()],
                 others: vec!["other_placeholder".into()],
+                ..Default::default()
             };
 
             let winners: Vec<String> = vec!["This".into()];
             let others: Vec<String> = vec!["sentence.".into()];
 
-            let result = scenario.build(&winners, &others);
+            let result = scenario.build(&winners, &others, &TemplateContext::default());
 
             assert!(result.is_err());
             Ok(())
         }
 
+        #[test]
+        fn scenario_build_fills_placeholders_from_context() -> Result<()> {
+            let scenario = Scenario {
+                template: "{winner} threw a cracker at {random_viewer}".into(),
+                winners: vec!["winner".into()],
+                others: vec![],
+                ..Default::default()
+            };
+            let mut context = TemplateContext::default();
+            context.insert(RANDOM_VIEWER, "Cooler_User");
+
+            let result = scenario.build(&["John".into()], &[], &context)?;
+
+            assert!(scenario.uses_placeholder(RANDOM_VIEWER));
+            assert_eq!("John threw a cracker at Cooler_User", result);
+            Ok(())
+        }
+
+        #[test]
+        fn scenario_build_applies_grammar_helpers() -> Result<()> {
+            let scenario = Scenario {
+                template: "{possessive(winner)} cracker landed near {random_viewer}, who gave it back to {them(winner)}"
+                    .into(),
+                winners: vec!["winner".into()],
+                others: vec![],
+                ..Default::default()
+            };
+            let mut context = TemplateContext::default();
+            context.insert(RANDOM_VIEWER, "Cooler_User");
+            context.set_pronouns(HashMap::from([("James".into(), Pronouns::HeHim)]));
+
+            let result = scenario.build(&["James".into()], &[], &context)?;
+
+            assert_eq!(
+                "James' cracker landed near Cooler_User, who gave it back to him",
+                result
+            );
+            Ok(())
+        }
+
+        #[test]
+        fn scenario_build_shows_mods_by_display_name() -> Result<()> {
+            let scenario = Scenario {
+                template: "{winner} ate {their(winner)} cracker".into(),
+                winners: vec!["winner".into()],
+                others: vec![],
+                ..Default::default()
+            };
+            let mut context = TemplateContext::default();
+            context.set_pronouns(HashMap::from([("james".into(), Pronouns::HeHim)]));
+            context.set_display_names(HashMap::from([("james".into(), "JamesTheMod".into())]));
+
+            let result = scenario.build(&["james".into()], &[], &context)?;
+
+            assert_eq!("JamesTheMod ate his cracker", result);
+            Ok(())
+        }
+
+        #[test]
+        fn uses_placeholder_sees_helper_arguments() {
+            let scenario = Scenario {
+                template: "{possessive(random_viewer)} turn".into(),
+                ..Default::default()
+            };
+
+            assert!(scenario.uses_placeholder(RANDOM_VIEWER));
+        }
+
         #[test]
         fn build_from_templates_should_return_a_built_scenario_message() -> Result<()> {
             let scenarios: Vec<Scenario> = vec![Scenario {
                 template: "{placeholder} wins by default.".into(),
                 winners: vec!["placeholder".into()],
                 others: vec![],
+                ..Default::default()
             }];
             let mods: Vec<String> = vec!["John".into()];
-            let message_components = MessageComponents { scenarios, mods };
+            let message_components = MessageComponents {
+                scenarios,
+                mods,
+                ..Default::default()
+            };
             let mut rng = Rng::with_seed(1);
 
-            let msg = Robochick::build_from_templates(&message_components, &mut rng)?;
+            let msg = Robochick::build_from_templates(
+                &message_components,
+                &TemplateContext::default(),
+                &mut rng,
+            )?;
 
-            assert_eq!(msg, "John wins by default.");
+            assert_eq!(msg.message, "John wins by default.");
             Ok(())
         }
 
@@ -305,10 +1299,15 @@ pub mod twitch {
             let message_components = MessageComponents {
                 scenarios: vec![],
                 mods,
+                ..Default::default()
             };
             let mut rng = Rng::with_seed(1);
 
-            let result = Robochick::build_from_templates(&message_components, &mut rng);
+            let result = Robochick::build_from_templates(
+                &message_components,
+                &TemplateContext::default(),
+                &mut rng,
+            );
             assert!(result.is_err());
 
             Ok(())
@@ -321,17 +1320,54 @@ pub mod twitch {
                 template: "This sentence has no placeholders as intended.".into(),
                 winners: vec![],
                 others: vec![],
+                ..Default::default()
             };
             let mods: Vec<String> = vec!["Alice".into(), "Bob".into()];
             let message_components = MessageComponents {
                 scenarios: vec![scenario],
                 mods,
+                ..Default::default()
             };
             let mut rng = Rng::with_seed(1);
 
-            let result = Robochick::build_from_templates(&message_components, &mut rng)?;
-            assert_eq!("This sentence has no placeholders as intended.", result);
+            let result = Robochick::build_from_templates(
+                &message_components,
+                &TemplateContext::default(),
+                &mut rng,
+            )?;
+            assert_eq!(
+                "This sentence has no placeholders as intended.",
+                result.message
+            );
+
+            Ok(())
+        }
 
+        #[test]
+        fn build_from_templates_should_fill_variables_unless_context_sets_them() -> Result<()> {
+            let scenario = Scenario {
+                template: "{win_1} shares {snack} with {friend}.".into(),
+                winners: vec!["win_1".into()],
+                others: vec![],
+                ..Default::default()
+            };
+            let message_components = MessageComponents {
+                scenarios: vec![scenario],
+                mods: vec!["John".into()],
+                variables: [
+                    ("snack".to_string(), vec!["corn".to_string()]),
+                    ("friend".to_string(), vec!["the duck".to_string()]),
+                ]
+                .into(),
+                ..Default::default()
+            };
+            let mut context = TemplateContext::default();
+            context.insert("friend", "Clucky");
+
+            let result =
+                Robochick::build_from_templates(&message_components, &context, &mut Rng::new())?;
+
+            assert_eq!(result.message, "John shares corn with Clucky.");
             Ok(())
         }
 
@@ -343,22 +1379,398 @@ pub mod twitch {
                     .into(),
                 winners: vec!["winner".into()],
                 others: vec!["other".into()],
+                ..Default::default()
             };
             let mods: Vec<String> = vec!["John".into(), "Jane".into()];
             let message_components = MessageComponents {
                 scenarios: vec![scenario],
                 mods,
+                ..Default::default()
             };
             let mut rng: Rng = Rng::with_seed(1_000);
 
-            let result = Robochick::build_from_templates(&message_components, &mut rng)?;
+            let result = Robochick::build_from_templates(
+                &message_components,
+                &TemplateContext::default(),
+                &mut rng,
+            )?;
 
             // Expected message for this specific seed `1_000`
             assert_eq!(
                 "John is the winner, and a different person Jane is the loser.",
-                result
+                result.message
+            );
+            Ok(())
+        }
+
+        #[test]
+        fn build_from_templates_should_render_follow_up_with_the_same_picks() -> Result<()> {
+            let scenario = Scenario {
+                template: "{winner} is deciding what to do with the cracker...".into(),
+                winners: vec!["winner".into()],
+                others: vec![],
+                follow_up: Some(FollowUp {
+                    template: "{winner} gave it to the chicken.".into(),
+                    delay_secs: 30,
+                }),
+                ..Default::default()
+            };
+            let message_components = MessageComponents {
+                scenarios: vec![scenario],
+                mods: vec!["John".into()],
+                ..Default::default()
+            };
+            let mut rng = Rng::with_seed(1);
+
+            let result = Robochick::build_from_templates(
+                &message_components,
+                &TemplateContext::default(),
+                &mut rng,
+            )?;
+
+            assert_eq!(
+                "John is deciding what to do with the cracker...",
+                result.message
+            );
+            assert_eq!(
+                Some(FollowUpMessage {
+                    message: "John gave it to the chicken.".into(),
+                    delay_secs: 30,
+                }),
+                result.follow_up
+            );
+            Ok(())
+        }
+
+        #[test]
+        fn build_from_templates_should_render_vote_question_and_outcomes() -> Result<()> {
+            let scenario = Scenario {
+                template: "{winner} found a cracker. Chat decides!".into(),
+                winners: vec!["winner".into()],
+                others: vec![],
+                vote: Some(Vote {
+                    question: "What should {winner} do?".into(),
+                    choices: vec![
+                        VoteChoice {
+                            title: "Eat it".into(),
+                            outcome: "{winner} ate it.".into(),
+                        },
+                        VoteChoice {
+                            title: "Share it".into(),
+                            outcome: "{winner} shared it.".into(),
+                        },
+                    ],
+                    duration_secs: 30,
+                }),
+                ..Default::default()
+            };
+            let message_components = MessageComponents {
+                scenarios: vec![scenario],
+                mods: vec!["John".into()],
+                ..Default::default()
+            };
+            let mut rng = Rng::with_seed(1);
+
+            let result = Robochick::build_from_templates(
+                &message_components,
+                &TemplateContext::default(),
+                &mut rng,
+            )?;
+
+            assert_eq!(
+                Some(BuiltVote {
+                    question: "What should John do?".into(),
+                    choices: vec!["Eat it".into(), "Share it".into()],
+                    outcomes: vec!["John ate it.".into(), "John shared it.".into()],
+                    duration_secs: 30,
+                }),
+                result.vote
+            );
+            Ok(())
+        }
+
+        #[test]
+        fn round_robin_picker_rotates_winners_and_wraps_around() {
+            let mods: Vec<String> = vec!["John".into(), "Jane".into(), "Alex".into()];
+            let mut picker = RoundRobinPicker {
+                last: Some("John".into()),
+                recent: vec![],
+            };
+            let mut rng = Rng::with_seed(1);
+
+            let first = picker.pick(&mods, 1, 0, &mut rng);
+            let second = picker.pick(&mods, 1, 0, &mut rng);
+            let third = picker.pick(&mods, 1, 0, &mut rng);
+
+            assert_eq!(first, vec!["Jane"]);
+            assert_eq!(second, vec!["Alex"]);
+            assert_eq!(third, vec!["John"]);
+            assert_eq!(picker.last.as_deref(), Some("John"));
+        }
+
+        #[test]
+        fn round_robin_picker_follows_the_last_winner_when_mods_change() {
+            let mods: Vec<String> = vec!["Kim".into(), "John".into(), "Alex".into()];
+            let mut picker = RoundRobinPicker {
+                last: Some("John".into()),
+                recent: vec![],
+            };
+            let mut rng = Rng::with_seed(1);
+
+            assert_eq!(picker.pick(&mods, 1, 0, &mut rng), vec!["Alex"]);
+
+            let mut gone = RoundRobinPicker {
+                last: Some("Jane".into()),
+                recent: vec![],
+            };
+            assert_eq!(gone.pick(&mods, 1, 0, &mut rng), vec!["Kim"]);
+        }
+
+        #[test]
+        fn round_robin_picker_passes_over_recent_winners_in_order() {
+            let mods: Vec<String> = vec!["John".into(), "Jane".into(), "Alex".into(), "Kim".into()];
+            let mut picker = RoundRobinPicker {
+                last: Some("John".into()),
+                recent: vec!["Jane".into()],
+            };
+            let mut rng = Rng::with_seed(1);
+
+            assert_eq!(picker.pick(&mods, 2, 0, &mut rng), vec!["Alex", "Kim"]);
+            assert_eq!(picker.last.as_deref(), Some("Kim"));
+
+            let mut everyone_won = RoundRobinPicker {
+                last: None,
+                recent: vec!["Jane".into(), "John".into()],
+            };
+            let two: Vec<String> = vec!["John".into(), "Jane".into()];
+            assert_eq!(everyone_won.pick(&two, 1, 0, &mut rng), vec!["John"]);
+            assert_eq!(everyone_won.last, None);
+        }
+
+        #[test]
+        fn round_robin_picker_never_picks_a_winner_as_other() {
+            let mods: Vec<String> = vec!["John".into(), "Jane".into()];
+            let mut picker = RoundRobinPicker {
+                last: None,
+                recent: vec![],
+            };
+            let mut rng = Rng::with_seed(1_000);
+
+            let picks = picker.pick(&mods, 1, 1, &mut rng);
+
+            assert_eq!(picks, vec!["John", "Jane"]);
+        }
+
+        #[test]
+        fn group_for_reward_falls_back_to_default_group() {
+            let message_components = MessageComponents {
+                scenarios: vec![Scenario {
+                    template: "default".into(),
+                    ..Default::default()
+                }],
+                mods: vec![],
+                selection: Selection::RoundRobin,
+                groups: vec![ScenarioGroup {
+                    name: "ducks".into(),
+                    reward_ids: vec!["duck-reward".into()],
+                    scenarios: vec![],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            };
+
+            let ducks = message_components.group_for_reward("duck-reward", Utc::now(), None);
+            let fallback = message_components.group_for_reward("other-reward", Utc::now(), None);
+
+            assert_eq!(ducks.get_name(), "ducks");
+            assert_eq!(fallback.get_name(), DEFAULT_GROUP);
+            assert_eq!(fallback.get_selection(), Selection::RoundRobin);
+            assert_eq!(fallback.get_scenarios().len(), 1);
+        }
+
+        #[test]
+        fn seasonal_groups_and_scenarios_only_apply_in_their_window() -> Result<()> {
+            let message_components: MessageComponents = serde_json::from_value(json!({
+                "scenarios": [
+                    {"template": "default", "winners": [], "others": []}
+                ],
+                "mods": [],
+                "groups": [{
+                    "name": "halloween",
+                    "reward_ids": ["feed-reward"],
+                    "active_from": "10-01",
+                    "active_until": "10-31",
+                    "scenarios": [
+                        {"template": "spooky", "winners": [], "others": []},
+                        {"template": "very spooky", "winners": [], "others": [], "active_from": "10-31"}
+                    ]
+                }]
+            }))?;
+            let october = Utc.with_ymd_and_hms(2026, 10, 16, 12, 0, 0).unwrap();
+            let november = Utc.with_ymd_and_hms(2026, 11, 1, 12, 0, 0).unwrap();
+
+            let group = message_components.group_for_reward("feed-reward", october, None);
+            let active = group.active_at(october);
+
+            assert_eq!(group.get_name(), "halloween");
+            assert_eq!(active.get_scenarios().len(), 1);
+            assert_eq!(active.get_scenarios()[0].get_template(), "spooky");
+            assert_eq!(
+                message_components
+                    .group_for_reward("feed-reward", november, None)
+                    .get_name(),
+                DEFAULT_GROUP
             );
+            assert!(group.active_at(november).get_scenarios().is_empty());
+            Ok(())
+        }
+
+        #[test]
+        fn game_groups_are_used_while_their_game_is_played() -> Result<()> {
+            let message_components: MessageComponents = serde_json::from_value(json!({
+                "scenarios": [
+                    {"template": "default", "winners": [], "others": []}
+                ],
+                "mods": [],
+                "groups": [
+                    {"name": "any game", "reward_ids": ["feed-reward"], "scenarios": []},
+                    {"name": "minecraft", "reward_ids": ["feed-reward"], "games": ["27471"], "scenarios": []},
+                    {"name": "chatting", "reward_ids": ["chat-reward"], "games": ["509658"], "scenarios": []}
+                ]
+            }))?;
+            let group = |reward: &str, game: Option<&str>| {
+                message_components
+                    .group_for_reward(reward, Utc::now(), game)
+                    .get_name()
+                    .to_string()
+            };
+
+            assert_eq!(group("feed-reward", Some("27471")), "minecraft");
+            assert_eq!(group("feed-reward", Some("509658")), "any game");
+            assert_eq!(group("feed-reward", None), "any game");
+            assert_eq!(group("chat-reward", Some("509658")), "chatting");
+            assert_eq!(group("chat-reward", Some("27471")), DEFAULT_GROUP);
             Ok(())
         }
+
+        #[test]
+        fn anti_repeat_picker_skips_recent_winners() {
+            let mods: Vec<String> = vec!["John".into(), "Jane".into(), "Alex".into()];
+            let mut inner = RandomPicker;
+            let mut picker = AntiRepeatPicker {
+                inner: &mut inner,
+                recent: vec!["John".into(), "Jane".into()],
+            };
+            let mut rng = Rng::with_seed(1);
+
+            let picks = picker.pick(&mods, 1, 0, &mut rng);
+
+            assert_eq!(picks, vec!["Alex"]);
+        }
+
+        #[test]
+        fn anti_repeat_picker_backfills_with_least_recent_winners() {
+            let mods: Vec<String> = vec!["John".into(), "Jane".into(), "Alex".into()];
+            let mut inner = FairPicker {
+                wins: HashMap::new(),
+            };
+            let mut picker = AntiRepeatPicker {
+                inner: &mut inner,
+                recent: vec!["John".into(), "Jane".into(), "Alex".into()],
+            };
+            let mut rng = Rng::with_seed(1);
+
+            let mut picks = picker.pick(&mods, 2, 1, &mut rng);
+            let other = picks.pop();
+            picks.sort();
+
+            assert_eq!(picks, vec!["Alex", "Jane"]);
+            assert_eq!(other.as_deref(), Some("John"));
+        }
+
+        #[test]
+        fn canary_scenarios_are_only_picked_for_their_share() -> Result<()> {
+            let scenario = |template: &str, canary: Option<u8>| Scenario {
+                template: template.into(),
+                canary: canary.map(|c| Percent::try_from(c).unwrap()),
+                ..Default::default()
+            };
+            let group = ScenarioGroup {
+                scenarios: vec![scenario("old", None), scenario("new", Some(10))],
+                ..Default::default()
+            };
+            let mut rng = Rng::with_seed(7);
+
+            let mut new = 0;
+            for _ in 0..2000 {
+                let built = Robochick::build_from_group(
+                    &group,
+                    &[],
+                    &mut RandomPicker,
+                    &TemplateContext::default(),
+                    &mut rng,
+                )?;
+                new += usize::from(built.scenario == "new");
+            }
+
+            // Eligible for 10% of redemptions, then picked for half of those.
+            assert!((50..150).contains(&new), "{new}");
+            Ok(())
+        }
+
+        #[test]
+        fn canary_above_100_is_rejected() {
+            let parsed = serde_json::from_str::<Scenario>(
+                r#"{"template": "bok", "winners": [], "others": [], "canary": 101}"#,
+            );
+
+            assert!(parsed.unwrap_err().to_string().contains("up to 100"));
+        }
+
+        #[test]
+        fn fair_picker_favours_mods_who_won_less() {
+            let mods: Vec<String> = vec!["John".into(), "Jane".into()];
+            let mut picker = FairPicker {
+                wins: HashMap::from([("John".to_string(), 9.0)]),
+            };
+            let mut rng = Rng::with_seed(3);
+
+            let jane = (0..1000)
+                .filter(|_| picker.pick(&mods, 1, 1, &mut rng)[0] == "Jane")
+                .count();
+
+            // Jane's weight is 1 against John's 0.1.
+            assert!((860..960).contains(&jane), "{jane}");
+            assert_eq!(picker.pick(&mods, 1, 1, &mut rng).len(), 2);
+        }
+
+        #[test]
+        fn fair_picker_weighs_older_wins_less() {
+            let picker = FairPicker::from_months(&[
+                HashMap::from([("John".to_string(), 1)]),
+                HashMap::from([("John".to_string(), 2), ("Jane".to_string(), 4)]),
+                HashMap::from([("Jane".to_string(), 4)]),
+            ]);
+
+            assert_eq!(picker.weight("John"), 1.0 / 3.0);
+            assert_eq!(picker.weight("Jane"), 1.0 / 4.0);
+            assert_eq!(picker.weight("Alex"), 1.0);
+        }
+
+        #[test]
+        fn scenario_key_prefers_id_over_template() {
+            let with_id = Scenario {
+                id: Some("cracker-trip".into()),
+                template: "{win_1} tripped.".into(),
+                ..Default::default()
+            };
+            let without_id = Scenario {
+                template: "{win_1} tripped.".into(),
+                ..Default::default()
+            };
+
+            assert_eq!(with_id.key(), "cracker-trip");
+            assert_eq!(without_id.key(), "{win_1} tripped.");
+        }
     }
 }
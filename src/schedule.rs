@@ -0,0 +1,135 @@
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+/// How long a segment without an end time is taken to last.
+const DEFAULT_SEGMENT_LENGTH: Duration = Duration::hours(4);
+
+/// How `{next_stream}` is written, e.g. `Sat 17 Oct 20:00 BST`.
+const NEXT_STREAM_FORMAT: &str = "%a %-d %b %H:%M %Z";
+
+/// A stream on the broadcaster's Twitch schedule.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScheduleSegment {
+    pub start_time: DateTime<Utc>,
+    #[serde(default)]
+    pub end_time: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub title: String,
+    /// Set when the broadcaster called this one off.
+    #[serde(default)]
+    pub canceled_until: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub category: Option<ScheduleCategory>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScheduleCategory {
+    pub name: String,
+}
+
+impl ScheduleSegment {
+    fn canceled(&self) -> bool {
+        self.canceled_until.is_some()
+    }
+
+    fn end(&self) -> DateTime<Utc> {
+        self.end_time
+            .unwrap_or(self.start_time + DEFAULT_SEGMENT_LENGTH)
+    }
+
+    /// Whether the segment covers `now`.
+    pub fn contains(&self, now: DateTime<Utc>) -> bool {
+        !self.canceled() && self.start_time <= now && now < self.end()
+    }
+
+    /// `{next_stream}` for this segment in `timezone`.
+    pub fn describe(&self, timezone: Tz) -> String {
+        self.start_time
+            .with_timezone(&timezone)
+            .format(NEXT_STREAM_FORMAT)
+            .to_string()
+    }
+}
+
+/// The first stream starting after `now` that isn't canceled.
+pub fn next_stream(segments: &[ScheduleSegment], now: DateTime<Utc>) -> Option<&ScheduleSegment> {
+    segments
+        .iter()
+        .filter(|s| !s.canceled() && s.start_time > now)
+        .min_by_key(|s| s.start_time)
+}
+
+/// Whether a stream is scheduled to be running at `now`.
+pub fn scheduled_at(segments: &[ScheduleSegment], now: DateTime<Utc>) -> bool {
+    segments.iter().any(|s| s.contains(now))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use chrono::{Duration, TimeZone, Utc};
+    use chrono_tz::Tz;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use crate::schedule::{ScheduleSegment, next_stream, scheduled_at};
+
+    fn segments() -> Result<Vec<ScheduleSegment>> {
+        Ok(serde_json::from_value(json!([
+            {
+                "id": "a",
+                "start_time": "2026-10-16T19:00:00Z",
+                "end_time": "2026-10-16T22:00:00Z",
+                "title": "Chicken farming",
+                "canceled_until": null,
+                "category": {"id": "1", "name": "Stardew Valley"},
+                "is_recurring": true
+            },
+            {
+                "id": "b",
+                "start_time": "2026-10-17T19:00:00Z",
+                "end_time": null,
+                "title": "Called off",
+                "canceled_until": "2026-10-17T23:00:00Z",
+                "category": null,
+                "is_recurring": true
+            },
+            {
+                "id": "c",
+                "start_time": "2026-10-18T19:00:00Z",
+                "end_time": null,
+                "title": "Egg hunt",
+                "canceled_until": null,
+                "category": null,
+                "is_recurring": false
+            }
+        ]))?)
+    }
+
+    #[test]
+    fn next_stream_skips_canceled_and_running_segments() -> Result<()> {
+        let segments = segments()?;
+        let now = Utc.with_ymd_and_hms(2026, 10, 16, 20, 0, 0).unwrap();
+
+        let next = next_stream(&segments, now).unwrap();
+
+        assert_eq!(next.title, "Egg hunt");
+        assert_eq!(next.describe(Tz::Europe__London), "Sun 18 Oct 20:00 BST");
+        assert_eq!(next_stream(&segments, now + Duration::days(3)), None);
+        Ok(())
+    }
+
+    #[test]
+    fn scheduled_at_uses_a_default_length_without_an_end_time() -> Result<()> {
+        let segments = segments()?;
+        let at = |d, h| Utc.with_ymd_and_hms(2026, 10, d, h, 0, 0).unwrap();
+
+        assert!(scheduled_at(&segments, at(16, 21)));
+        assert!(!scheduled_at(&segments, at(16, 22)));
+        assert!(!scheduled_at(&segments, at(17, 20)));
+        assert!(scheduled_at(&segments, at(18, 22)));
+        assert!(!scheduled_at(&segments, at(18, 23)));
+        Ok(())
+    }
+}
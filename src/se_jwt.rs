@@ -0,0 +1,154 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result, anyhow};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Deserialize;
+
+use crate::secrets::SecretStore;
+
+/// How long before a JWT expires the broadcaster is warned.
+const EXPIRY_WARNING: TimeDelta = TimeDelta::days(7);
+
+#[derive(Deserialize)]
+struct Claims {
+    exp: i64,
+}
+
+/// When a JWT expires, from its `exp` claim. The signature isn't checked, that's up to
+/// StreamElements.
+pub fn expires_at(jwt: &str) -> Result<DateTime<Utc>> {
+    let payload = jwt
+        .trim()
+        .split('.')
+        .nth(1)
+        .ok_or(anyhow!("JWT has no payload"))?;
+    let claims: Claims = serde_json::from_slice(
+        &URL_SAFE_NO_PAD
+            .decode(payload.trim_end_matches('='))
+            .context("JWT payload isn't base64")?,
+    )
+    .context("JWT has no exp claim")?;
+    DateTime::from_timestamp(claims.exp, 0).ok_or(anyhow!("JWT exp {} is out of range", claims.exp))
+}
+
+/// What to tell the broadcaster about a JWT of `bot` expiring at `expires_at`, or `None` while
+/// it's further off than [`EXPIRY_WARNING`].
+pub fn expiry_warning(bot: &str, expires_at: DateTime<Utc>, now: DateTime<Utc>) -> Option<String> {
+    let left = expires_at - now;
+    if left > EXPIRY_WARNING {
+        return None;
+    }
+    Some(match left <= TimeDelta::zero() {
+        true => format!(
+            "The StreamElements JWT of {bot} expired on {}, chat messages through it will fail",
+            expires_at.format("%Y-%m-%d")
+        ),
+        false => format!(
+            "The StreamElements JWT of {bot} expires on {}, in {} days. Get a new one from the StreamElements dashboard",
+            expires_at.format("%Y-%m-%d"),
+            left.num_days()
+        ),
+    })
+}
+
+/// Looks up the StreamElements JWT for a channel from Secrets Manager, one secret per channel
+/// named `{prefix}{channel id}`, so each channel can speak through its own SE bot account.
+pub struct SeJwtStore {
+    secrets: Arc<SecretStore>,
+    prefix: String,
+}
+
+impl SeJwtStore {
+    pub fn new(secrets: Arc<SecretStore>, prefix: String) -> Self {
+        SeJwtStore { secrets, prefix }
+    }
+
+    pub async fn jwt(&self, channel_id: &str) -> Result<String> {
+        self.secrets
+            .get(&format!("{}{channel_id}", self.prefix))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use anyhow::Result;
+    use aws_sdk_secretsmanager::{Client, operation::get_secret_value::GetSecretValueOutput};
+    use aws_smithy_mocks::{RuleMode, mock, mock_client};
+
+    use chrono::{TimeDelta, TimeZone, Utc};
+
+    use crate::{
+        se_jwt::{SeJwtStore, expires_at, expiry_warning},
+        secrets::SecretStore,
+    };
+
+    #[test]
+    fn expires_at_reads_exp_claim() -> Result<()> {
+        // {"alg":"HS256","typ":"JWT"}.{"channel":"test_channel_id","exp":1792108800}
+        let jwt = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.\
+            eyJjaGFubmVsIjoidGVzdF9jaGFubmVsX2lkIiwiZXhwIjoxNzkyMTA4ODAwfQ.c2lnbmF0dXJl";
+
+        assert_eq!(
+            expires_at(jwt)?,
+            Utc.with_ymd_and_hms(2026, 10, 16, 0, 0, 0).unwrap()
+        );
+        assert!(expires_at("not-a-jwt").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn expiry_warning_starts_a_week_ahead() {
+        let expires = Utc.with_ymd_and_hms(2026, 10, 23, 0, 0, 0).unwrap();
+
+        assert_eq!(
+            expiry_warning("the default bot", expires, expires - TimeDelta::days(8)),
+            None
+        );
+        let warning = expiry_warning("the default bot", expires, expires - TimeDelta::days(3));
+        assert!(
+            warning
+                .unwrap()
+                .contains("expires on 2026-10-23, in 3 days")
+        );
+        let expired = expiry_warning("the default bot", expires, expires + TimeDelta::days(1));
+        assert!(expired.unwrap().contains("expired on 2026-10-23"));
+    }
+
+    #[tokio::test]
+    async fn jwt_reads_channel_secret_once() -> Result<()> {
+        let rule = mock!(Client::get_secret_value)
+            .match_requests(|r| r.secret_id() == Some("robochick/se-jwt/test_channel_id"))
+            .then_output(|| {
+                GetSecretValueOutput::builder()
+                    .secret_string("channel-jwt\n")
+                    .build()
+            });
+        let client = mock_client!(aws_sdk_secretsmanager, RuleMode::MatchAny, [&rule]);
+        let store = SeJwtStore::new(
+            Arc::new(SecretStore::new(client)),
+            "robochick/se-jwt/".into(),
+        );
+
+        assert_eq!(store.jwt("test_channel_id").await?, "channel-jwt");
+        assert_eq!(store.jwt("test_channel_id").await?, "channel-jwt");
+        assert_eq!(rule.num_calls(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn jwt_returns_err_for_empty_secret() {
+        let rule = mock!(Client::get_secret_value)
+            .then_output(|| GetSecretValueOutput::builder().secret_string("").build());
+        let client = mock_client!(aws_sdk_secretsmanager, RuleMode::MatchAny, [&rule]);
+        let store = SeJwtStore::new(
+            Arc::new(SecretStore::new(client)),
+            "robochick/se-jwt/".into(),
+        );
+
+        assert!(store.jwt("test_channel_id").await.is_err());
+    }
+}
@@ -0,0 +1,164 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, Datelike, NaiveDate, Utc, Weekday};
+use chrono_tz::Tz;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// A day bounding an active window, either every year (`MM-DD`) or once (`YYYY-MM-DD`).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub enum SeasonDate {
+    Yearly { month: u32, day: u32 },
+    Once(NaiveDate),
+}
+
+impl SeasonDate {
+    fn on_or_before(self, today: NaiveDate) -> bool {
+        match self {
+            SeasonDate::Yearly { month, day } => (month, day) <= (today.month(), today.day()),
+            SeasonDate::Once(date) => date <= today,
+        }
+    }
+
+    fn on_or_after(self, today: NaiveDate) -> bool {
+        match self {
+            SeasonDate::Yearly { month, day } => (month, day) >= (today.month(), today.day()),
+            SeasonDate::Once(date) => date >= today,
+        }
+    }
+}
+
+impl FromStr for SeasonDate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            return Ok(SeasonDate::Once(date));
+        }
+        // A leap year, so 02-29 is allowed.
+        match NaiveDate::parse_from_str(&format!("2000-{s}"), "%Y-%m-%d") {
+            Ok(date) => Ok(SeasonDate::Yearly {
+                month: date.month(),
+                day: date.day(),
+            }),
+            Err(_) => Err(format!("Expected MM-DD or YYYY-MM-DD, got {s}")),
+        }
+    }
+}
+
+impl TryFrom<String> for SeasonDate {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<SeasonDate> for String {
+    fn from(date: SeasonDate) -> Self {
+        date.to_string()
+    }
+}
+
+impl fmt::Display for SeasonDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SeasonDate::Yearly { month, day } => write!(f, "{month:02}-{day:02}"),
+            SeasonDate::Once(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+        }
+    }
+}
+
+/// When a scenario or group is used, for seasonal templates like Halloween ones. Unset fields
+/// don't restrict anything.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq)]
+pub struct ActiveWindow {
+    /// First day it's used, as `MM-DD` for every year or `YYYY-MM-DD` for one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub active_from: Option<SeasonDate>,
+    /// Last day it's used, inclusive. A yearly window can wrap around the new year, e.g.
+    /// `12-20` to `01-05`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub active_until: Option<SeasonDate>,
+    /// Days of the week it's used on, e.g. `["Sat", "Sun"]`. Every day when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schemars(with = "Vec<String>")]
+    pub active_days: Vec<Weekday>,
+    /// IANA name of the timezone the days are in, such as `Europe/London`. Defaults to UTC.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub active_timezone: Option<Tz>,
+}
+
+impl ActiveWindow {
+    pub fn contains(&self, now: DateTime<Utc>) -> bool {
+        let timezone = self.active_timezone.unwrap_or(Tz::UTC);
+        let today = now.with_timezone(&timezone).date_naive();
+        if !self.active_days.is_empty() && !self.active_days.contains(&today.weekday()) {
+            return false;
+        }
+
+        let after_from = self.active_from.is_none_or(|from| from.on_or_before(today));
+        let before_until = self
+            .active_until
+            .is_none_or(|until| until.on_or_after(today));
+        match (self.active_from, self.active_until) {
+            (
+                Some(SeasonDate::Yearly { month, day }),
+                Some(SeasonDate::Yearly {
+                    month: until_month,
+                    day: until_day,
+                }),
+            ) if (month, day) > (until_month, until_day) => after_from || before_until,
+            _ => after_from && before_until,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+
+    use crate::season::ActiveWindow;
+
+    fn window(value: serde_json::Value) -> ActiveWindow {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn contains_checks_yearly_dates_including_over_new_year() {
+        let halloween = window(json!({"active_from": "10-25", "active_until": "10-31"}));
+        let winter = window(json!({"active_from": "12-20", "active_until": "01-05"}));
+
+        assert!(halloween.contains(Utc.with_ymd_and_hms(2026, 10, 31, 23, 0, 0).unwrap()));
+        assert!(!halloween.contains(Utc.with_ymd_and_hms(2026, 11, 1, 0, 0, 0).unwrap()));
+        assert!(winter.contains(Utc.with_ymd_and_hms(2026, 12, 24, 12, 0, 0).unwrap()));
+        assert!(winter.contains(Utc.with_ymd_and_hms(2027, 1, 2, 12, 0, 0).unwrap()));
+        assert!(!winter.contains(Utc.with_ymd_and_hms(2027, 1, 6, 12, 0, 0).unwrap()));
+        assert!(ActiveWindow::default().contains(Utc::now()));
+    }
+
+    #[test]
+    fn contains_checks_one_off_dates_and_days_in_timezone() {
+        let anniversary =
+            window(json!({"active_from": "2026-10-16", "active_until": "2026-10-16"}));
+        // 02:00 UTC on a Saturday is still Friday evening in New York.
+        let weekends =
+            window(json!({"active_days": ["Sat", "Sun"], "active_timezone": "America/New_York"}));
+
+        assert!(anniversary.contains(Utc.with_ymd_and_hms(2026, 10, 16, 12, 0, 0).unwrap()));
+        assert!(!anniversary.contains(Utc.with_ymd_and_hms(2027, 10, 16, 12, 0, 0).unwrap()));
+        assert!(!weekends.contains(Utc.with_ymd_and_hms(2026, 10, 17, 2, 0, 0).unwrap()));
+        assert!(weekends.contains(Utc.with_ymd_and_hms(2026, 10, 17, 12, 0, 0).unwrap()));
+    }
+
+    #[test]
+    fn rejects_malformed_dates() {
+        assert!(serde_json::from_value::<ActiveWindow>(json!({"active_from": "10-32"})).is_err());
+        assert!(serde_json::from_value::<ActiveWindow>(json!({"active_from": "02-29"})).is_ok());
+    }
+}
@@ -0,0 +1,126 @@
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+
+use crate::{cache::TtlCache, run_detached};
+
+/// How long a secret is used before it's read again, so rotated values get picked up without
+/// a redeploy.
+const SECRET_CACHE_TTL: Duration = Duration::from_secs(900);
+
+/// Reads string secrets from Secrets Manager, caching them for a while.
+pub struct SecretStore {
+    client: aws_sdk_secretsmanager::Client,
+    cache: TtlCache<String, String>,
+}
+
+impl SecretStore {
+    pub fn new(client: aws_sdk_secretsmanager::Client) -> Self {
+        SecretStore {
+            client,
+            cache: TtlCache::new(SECRET_CACHE_TTL),
+        }
+    }
+
+    /// The secret's value, trimmed. Errors when it's missing or empty.
+    pub async fn get(&self, name: &str) -> Result<String> {
+        if let Some(value) = self.cache.get(&name.to_string()) {
+            return Ok(value);
+        }
+
+        let value = run_detached(read_secret(self.client.clone(), name.to_string())).await??;
+        self.cache.insert(name.to_string(), value.clone());
+        Ok(value)
+    }
+
+    /// The secret's value if it's cached, without reading Secrets Manager.
+    pub fn cached(&self, name: &str) -> Option<String> {
+        self.cache.get(&name.to_string())
+    }
+
+    /// Stores `value` as the secret `name`, creating it if it doesn't exist yet.
+    pub async fn put(&self, name: &str, value: &str) -> Result<()> {
+        run_detached(write_secret(
+            self.client.clone(),
+            name.to_string(),
+            value.to_string(),
+        ))
+        .await??;
+        self.cache.insert(name.to_string(), value.to_string());
+        Ok(())
+    }
+
+    /// Deletes the secret `name` straight away, without the usual recovery window.
+    pub async fn delete(&self, name: &str) -> Result<()> {
+        let request = self
+            .client
+            .delete_secret()
+            .secret_id(name)
+            .force_delete_without_recovery(true);
+        run_detached(async move { request.send().await })
+            .await?
+            .map_err(|e| anyhow!("Failed to delete secret {name}: {e}"))?;
+        Ok(())
+    }
+
+    /// A random alphanumeric value of `length` characters, from Secrets Manager.
+    pub async fn random(&self, length: i64) -> Result<String> {
+        let request = self
+            .client
+            .get_random_password()
+            .password_length(length)
+            .exclude_punctuation(true);
+        let output = tokio::spawn(async move { request.send().await })
+            .await?
+            .map_err(|e| anyhow!("Failed to generate a random secret: {e}"))?;
+        output
+            .random_password
+            .ok_or(anyhow!("Secrets Manager returned no random secret"))
+    }
+}
+
+async fn write_secret(
+    client: aws_sdk_secretsmanager::Client,
+    name: String,
+    value: String,
+) -> Result<()> {
+    let created = client
+        .create_secret()
+        .name(&name)
+        .secret_string(&value)
+        .send()
+        .await;
+    match created {
+        Ok(_) => Ok(()),
+        Err(e)
+            if e.as_service_error()
+                .is_some_and(|e| e.is_resource_exists_exception()) =>
+        {
+            client
+                .put_secret_value()
+                .secret_id(&name)
+                .secret_string(&value)
+                .send()
+                .await
+                .map(|_| ())
+                .map_err(|e| anyhow!("Failed to update secret {name}: {e}"))
+        }
+        Err(e) => Err(anyhow!("Failed to create secret {name}: {e}")),
+    }
+}
+
+async fn read_secret(client: aws_sdk_secretsmanager::Client, name: String) -> Result<String> {
+    let secret = client
+        .get_secret_value()
+        .secret_id(&name)
+        .send()
+        .await
+        .map_err(|e| anyhow!("Failed to read secret {name}: {e}"))?;
+
+    secret
+        .secret_string()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .ok_or(anyhow!("Secret {name} is empty"))
+}
@@ -0,0 +1,428 @@
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+use axum::{
+    body::{Body, to_bytes},
+    extract::{OriginalUri, Request, State},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use strum::{AsRefStr, EnumString};
+
+use crate::{auth::ADMIN_ACTOR, config::AppConfig};
+
+/// Unix time the request was signed at.
+pub const TIMESTAMP_HEADER: &str = "x-robochick-timestamp";
+/// `sha256=<hex HMAC-SHA256>` of the timestamp, method, path and body.
+pub const SIGNATURE_HEADER: &str = "x-robochick-signature";
+/// Who a request checked by [`admin_guard`] or [`internal_guard`] comes from. Anything a
+/// caller sends in it is dropped before the check.
+pub(crate) const VERIFIED_ACTOR_HEADER: &str = "x-robochick-verified-actor";
+
+/// How far a signature's timestamp may be from now, limiting how long it can be replayed.
+const MAX_CLOCK_SKEW_SECS: u64 = 300;
+
+/// Largest body read to check its signature, Lambda's own limit.
+const MAX_SIGNED_BODY: usize = 6 * 1024 * 1024;
+
+/// How callers of a group of routes prove who they are.
+#[derive(Debug, Clone, Copy, Default, PartialEq, EnumString, AsRefStr)]
+#[strum(serialize_all = "lowercase")]
+pub enum RequestAuth {
+    /// A bearer token, checked by the handlers.
+    #[default]
+    Bearer,
+    /// An HMAC signature of the request, see [`signature`].
+    Hmac,
+    /// A client certificate, checked by a TLS terminator in front of the bot (an ALB or API
+    /// Gateway with mutual TLS) and passed on in a header. The header is taken on trust, so
+    /// the terminator has to be the only way to reach the bot.
+    Mtls,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RouteGroup {
+    Admin,
+    Internal,
+}
+
+impl RouteGroup {
+    fn auth(self, config: &AppConfig) -> RequestAuth {
+        match self {
+            RouteGroup::Admin => config.admin_auth,
+            RouteGroup::Internal => config.internal_auth,
+        }
+    }
+
+    fn signing_secret(self, config: &AppConfig) -> Option<&str> {
+        match self {
+            RouteGroup::Admin => config.admin_signing_secret.as_deref(),
+            RouteGroup::Internal => config.internal_signing_secret.as_deref(),
+        }
+    }
+
+    fn client_certs(self, config: &AppConfig) -> &HashMap<String, String> {
+        match self {
+            RouteGroup::Admin => &config.admin_client_certs,
+            RouteGroup::Internal => &config.internal_client_certs,
+        }
+    }
+}
+
+/// Signature of a request made at `timestamp`: `sha256=` and the hex HMAC-SHA256 of
+/// `{timestamp}\n{method}\n{path and query}\n{body}`.
+pub fn signature(
+    secret: &str,
+    timestamp: i64,
+    method: &Method,
+    path_and_query: &str,
+    body: &[u8],
+) -> Result<String> {
+    let hmac = mac(secret, timestamp, method, path_and_query, body)?;
+    Ok(format!(
+        "sha256={}",
+        hex::encode(hmac.finalize().into_bytes())
+    ))
+}
+
+fn mac(
+    secret: &str,
+    timestamp: i64,
+    method: &Method,
+    path_and_query: &str,
+    body: &[u8],
+) -> Result<Hmac<Sha256>> {
+    let mut hmac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())?;
+    hmac.update(format!("{timestamp}\n{method}\n{path_and_query}\n").as_bytes());
+    hmac.update(body);
+    Ok(hmac)
+}
+
+/// Checks the [`signature`] of a request made within a few minutes of `now`.
+pub fn verify_signature(
+    headers: &HeaderMap,
+    method: &Method,
+    uri: &Uri,
+    body: &[u8],
+    secret: &str,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|h| h.to_str().ok())
+            .ok_or(anyhow!("Missing {name} header"))
+    };
+    let timestamp: i64 = header(TIMESTAMP_HEADER)?
+        .parse()
+        .map_err(|_| anyhow!("Invalid {TIMESTAMP_HEADER} header"))?;
+    if now.timestamp().abs_diff(timestamp) > MAX_CLOCK_SKEW_SECS {
+        return Err(anyhow!(
+            "Signature timestamp {timestamp} is too far from now"
+        ));
+    }
+    let given = header(SIGNATURE_HEADER)?
+        .strip_prefix("sha256=")
+        .ok_or(anyhow!("Malformed {SIGNATURE_HEADER} header"))?;
+
+    let path = uri.path_and_query().map_or("/", |p| p.as_str());
+    mac(secret, timestamp, method, path, body)?
+        .verify_slice(&hex::decode(given)?)
+        .map_err(|e| anyhow!("Signature verification failed: {e}"))
+}
+
+/// SHA-256 fingerprint, as lowercase hex, of the certificate in a header set by a TLS
+/// terminator: a PEM certificate, URL-encoded like an ALB sends it.
+pub fn cert_fingerprint(header: &str) -> Result<String> {
+    let pem = percent_decode(header)?;
+    let encoded: String = pem
+        .lines()
+        .filter(|l| !l.starts_with("-----"))
+        .flat_map(|l| l.chars())
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    let der = STANDARD
+        .decode(encoded)
+        .map_err(|e| anyhow!("Client certificate isn't valid PEM: {e}"))?;
+    Ok(hex::encode(Sha256::digest(der)))
+}
+
+/// Name of the allowed certificate the request was made with.
+fn verify_client_cert(
+    headers: &HeaderMap,
+    header_name: &str,
+    allowed: &HashMap<String, String>,
+) -> Result<String> {
+    let cert = headers
+        .get(header_name)
+        .and_then(|h| h.to_str().ok())
+        .ok_or(anyhow!("No client certificate in {header_name}"))?;
+    let fingerprint = cert_fingerprint(cert)?;
+    allowed
+        .iter()
+        .find(|(_, f)| normalize_fingerprint(f) == fingerprint)
+        .map(|(name, _)| name.clone())
+        .ok_or(anyhow!("Client certificate {fingerprint} isn't allowed"))
+}
+
+/// Lowercase hex without the colons fingerprints are often written with.
+fn normalize_fingerprint(fingerprint: &str) -> String {
+    fingerprint.replace(':', "").to_lowercase()
+}
+
+fn percent_decode(value: &str) -> Result<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes
+                .get(i + 1..i + 3)
+                .ok_or(anyhow!("Truncated escape in client certificate"))?;
+            decoded.extend(hex::decode(hex)?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Ok(String::from_utf8(decoded)?)
+}
+
+/// Checks requests to `/admin` routes as `ADMIN_AUTH` says. Signed requests are allowed
+/// everything, like `ADMIN_API_TOKEN`.
+pub async fn admin_guard(
+    State(config): State<AppConfig>,
+    request: Request,
+    next: Next,
+) -> Response {
+    guard(RouteGroup::Admin, &config, request, next).await
+}
+
+/// Checks requests to `/internal` routes as `INTERNAL_AUTH` says.
+pub async fn internal_guard(
+    State(config): State<AppConfig>,
+    request: Request,
+    next: Next,
+) -> Response {
+    guard(RouteGroup::Internal, &config, request, next).await
+}
+
+async fn guard(group: RouteGroup, config: &AppConfig, request: Request, next: Next) -> Response {
+    let (mut parts, body) = request.into_parts();
+    parts.headers.remove(VERIFIED_ACTOR_HEADER);
+
+    let verified = match group.auth(config) {
+        RequestAuth::Bearer => return next.run(Request::from_parts(parts, body)).await,
+        RequestAuth::Hmac => {
+            let Ok(bytes) = to_bytes(body, MAX_SIGNED_BODY).await else {
+                return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+            };
+            // Clients sign the path they call, which behind an API Gateway stage still has
+            // the stage in front.
+            let signed_uri = parts
+                .extensions
+                .get::<OriginalUri>()
+                .map_or(&parts.uri, |o| &o.0);
+            let verified = match group.signing_secret(config) {
+                Some(secret) => verify_signature(
+                    &parts.headers,
+                    &parts.method,
+                    signed_uri,
+                    &bytes,
+                    secret,
+                    Utc::now(),
+                )
+                .map(|_| ADMIN_ACTOR.to_string()),
+                None => Err(anyhow!("No signing secret is configured")),
+            };
+            verified.map(|actor| (actor, Body::from(bytes)))
+        }
+        RequestAuth::Mtls => verify_client_cert(
+            &parts.headers,
+            &config.client_cert_header,
+            group.client_certs(config),
+        )
+        .map(|actor| (actor, body)),
+    };
+
+    match verified {
+        Ok((actor, body)) => {
+            if let Ok(value) = HeaderValue::from_str(&actor) {
+                parts.headers.insert(VERIFIED_ACTOR_HEADER, value);
+            }
+            next.run(Request::from_parts(parts, body)).await
+        }
+        Err(e) => {
+            println!("Rejected {group:?} request to {}: {e}", parts.uri.path());
+            StatusCode::UNAUTHORIZED.into_response()
+        }
+    }
+}
+
+/// The actor [`guard`] let through, when the group isn't using bearer tokens.
+pub(crate) fn verified_actor(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(VERIFIED_ACTOR_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use axum::{
+        Router,
+        body::Body,
+        extract::OriginalUri,
+        http::{HeaderMap, Method, Request, StatusCode},
+        middleware,
+        routing::post,
+    };
+    use chrono::{Duration, Utc};
+    use pretty_assertions::assert_eq;
+    use tower::ServiceExt;
+
+    use crate::{
+        config::AppConfig,
+        signing::{
+            RequestAuth, SIGNATURE_HEADER, TIMESTAMP_HEADER, VERIFIED_ACTOR_HEADER, admin_guard,
+            cert_fingerprint, signature, verified_actor,
+        },
+    };
+
+    // Stands in for a PEM certificate, URL-encoded the way an ALB forwards it.
+    const CERT: &str = "-----BEGIN%20CERTIFICATE-----%0AMIIBdzCCAR2gAwIBAgIUQ2hpY2tlbg%3D%3D%0A-----END%20CERTIFICATE-----%0A";
+
+    async fn call(config: AppConfig, request: Request<Body>) -> Result<(StatusCode, String)> {
+        let app = Router::new()
+            .route(
+                "/admin/reload",
+                post(|headers: HeaderMap, body: String| async move {
+                    format!("{} {body}", verified_actor(&headers).unwrap_or_default())
+                }),
+            )
+            .route_layer(middleware::from_fn_with_state(config, admin_guard));
+        let response = app.oneshot(request).await?;
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), 1024).await?;
+        Ok((status, String::from_utf8(body.to_vec())?))
+    }
+
+    #[tokio::test]
+    async fn hmac_guard_lets_signed_requests_through() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.admin_auth = RequestAuth::Hmac;
+        config.admin_signing_secret = Some("signing-secret".into());
+        let now = Utc::now().timestamp();
+        let request = |timestamp: i64, body: &'static str| -> Result<Request<Body>> {
+            let signed = signature(
+                "signing-secret",
+                timestamp,
+                &Method::POST,
+                "/admin/reload?dry=1",
+                b"{}",
+            )?;
+            Ok(Request::post("/admin/reload?dry=1")
+                .header(TIMESTAMP_HEADER, timestamp.to_string())
+                .header(SIGNATURE_HEADER, signed)
+                .header(VERIFIED_ACTOR_HEADER, "mallory")
+                .body(Body::from(body))?)
+        };
+
+        assert_eq!(
+            call(config.clone(), request(now, "{}")?).await?,
+            (StatusCode::OK, "admin {}".into())
+        );
+        assert_eq!(
+            call(config.clone(), request(now, "{\"a\":1}")?).await?.0,
+            StatusCode::UNAUTHORIZED
+        );
+        let stale = now - Duration::minutes(10).num_seconds();
+        assert_eq!(
+            call(config.clone(), request(stale, "{}")?).await?.0,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            call(config, request(i64::MIN, "{}")?).await?.0,
+            StatusCode::UNAUTHORIZED
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn hmac_guard_checks_the_path_with_the_api_gateway_stage() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.admin_auth = RequestAuth::Hmac;
+        config.admin_signing_secret = Some("signing-secret".into());
+        let now = Utc::now().timestamp();
+        let signed = signature(
+            "signing-secret",
+            now,
+            &Method::POST,
+            "/prod/admin/reload",
+            b"{}",
+        )?;
+        let mut request = Request::post("/admin/reload")
+            .header(TIMESTAMP_HEADER, now.to_string())
+            .header(SIGNATURE_HEADER, signed)
+            .body(Body::from("{}"))?;
+        request
+            .extensions_mut()
+            .insert(OriginalUri("/prod/admin/reload".parse()?));
+
+        assert_eq!(call(config, request).await?.0, StatusCode::OK);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn mtls_guard_names_the_allowed_certificate() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut config = AppConfig::from_env();
+        config.admin_auth = RequestAuth::Mtls;
+        let fingerprint = cert_fingerprint(CERT)?;
+        let with_colons = fingerprint
+            .as_bytes()
+            .chunks(2)
+            .map(|c| String::from_utf8_lossy(c).to_uppercase())
+            .collect::<Vec<_>>()
+            .join(":");
+        config.admin_client_certs = [("dashboard".to_string(), with_colons)].into();
+        let request = |cert: Option<&str>| -> Result<Request<Body>> {
+            let mut request = Request::post("/admin/reload");
+            if let Some(cert) = cert {
+                request = request.header("X-Amzn-Mtls-Clientcert", cert);
+            }
+            Ok(request.body(Body::from("{}"))?)
+        };
+
+        assert_eq!(
+            call(config.clone(), request(Some(CERT))?).await?,
+            (StatusCode::OK, "dashboard {}".into())
+        );
+        assert_eq!(
+            call(config, request(None)?).await?.0,
+            StatusCode::UNAUTHORIZED
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bearer_guard_drops_a_spoofed_actor() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let config = AppConfig::from_env();
+        let request = Request::post("/admin/reload")
+            .header(VERIFIED_ACTOR_HEADER, "mallory")
+            .body(Body::from("{}"))?;
+
+        assert_eq!(call(config, request).await?, (StatusCode::OK, " {}".into()));
+        Ok(())
+    }
+}
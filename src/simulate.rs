@@ -0,0 +1,270 @@
+use std::fmt;
+
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+use serde_json::Value;
+
+use crate::{
+    pipeline::{Rule, Step, event_context},
+    robochick::twitch::{
+        MessageComponents, RANDOM_VIEWER, REWARD_COST, REWARD_PROMPT, REWARD_TITLE, ScenarioGroup,
+        TemplateContext, USER_LOGIN, USER_NAME,
+    },
+    types::twitch::SubscriptionType,
+};
+
+/// Stands in for `{random_viewer}`, since simulating doesn't look up the chatters.
+const SIMULATED_VIEWER: &str = "<random viewer>";
+
+/// What the bot would do with a notification, worked out from the config alone.
+#[derive(Debug, PartialEq)]
+pub struct Simulation {
+    pub event_type: String,
+    /// The group a redemption's reward posts from, for reward redemptions.
+    pub reward: Option<SimulatedPost>,
+    pub rules: Vec<SimulatedRule>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SimulatedRule {
+    /// Position of the rule in `pipeline`.
+    pub index: usize,
+    pub rule: Rule,
+    pub steps: Vec<SimulatedStep>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SimulatedStep {
+    Post(SimulatedPost),
+    Say(std::result::Result<String, String>),
+    /// The viewer who'd get the points, if the event has one.
+    AwardPoints {
+        user: Option<String>,
+        amount: i64,
+    },
+    UnknownGroup(String),
+}
+
+/// Every scenario of a group rendered with the first mods in the config, since any of them
+/// could be picked. Variables show as `<name>` for the same reason.
+#[derive(Debug, PartialEq)]
+pub struct SimulatedPost {
+    pub group: String,
+    pub messages: Vec<(String, std::result::Result<String, String>)>,
+}
+
+/// Runs an EventSub notification (as Twitch sends it, or `twitch event trigger` prints it)
+/// through the config without calling anything.
+pub fn simulate(components: &MessageComponents, notification: &Value) -> Result<Simulation> {
+    let event_type = notification["subscription"]["type"]
+        .as_str()
+        .ok_or(anyhow!("Notification has no subscription.type"))?;
+    let event = &notification["event"];
+    if !event.is_object() {
+        return Err(anyhow!("Notification has no event"));
+    }
+
+    let mut context = event_context(event);
+    context.insert(RANDOM_VIEWER, SIMULATED_VIEWER);
+
+    let reward = match event_type.parse::<SubscriptionType>() {
+        Ok(SubscriptionType::CustomRewardRedemption) => {
+            let reward = &event["reward"];
+            let mut context = context.clone();
+            context.insert(REWARD_TITLE, as_text(&reward["title"]));
+            context.insert(REWARD_COST, as_text(&reward["cost"]));
+            context.insert(REWARD_PROMPT, as_text(&reward["prompt"]));
+            context.insert(USER_NAME, as_text(&event["user_name"]));
+            context.insert(USER_LOGIN, as_text(&event["user_login"]));
+            let group = components.group_for_reward(
+                reward["id"].as_str().unwrap_or_default(),
+                Utc::now(),
+                None,
+            );
+            Some(render(&group, components, &context))
+        }
+        _ => None,
+    };
+
+    let rules = components
+        .get_pipeline()
+        .iter()
+        .enumerate()
+        .filter(|(_, r)| r.matches(event_type, event))
+        .map(|(index, rule)| SimulatedRule {
+            index,
+            rule: rule.clone(),
+            steps: rule
+                .steps
+                .iter()
+                .map(|step| match step {
+                    Step::Post { group } => match components.group_named(group) {
+                        Some(g) => SimulatedStep::Post(render(&g, components, &context)),
+                        None => SimulatedStep::UnknownGroup(group.clone()),
+                    },
+                    Step::Say { text } => {
+                        SimulatedStep::Say(context.format(text).map_err(|e| e.to_string()))
+                    }
+                    Step::AwardPoints { amount } => SimulatedStep::AwardPoints {
+                        user: event["user_login"].as_str().map(str::to_string),
+                        amount: *amount,
+                    },
+                })
+                .collect(),
+        })
+        .collect();
+
+    Ok(Simulation {
+        event_type: event_type.to_string(),
+        reward,
+        rules,
+    })
+}
+
+fn render(
+    group: &ScenarioGroup,
+    components: &MessageComponents,
+    context: &TemplateContext,
+) -> SimulatedPost {
+    let mut context = context.clone();
+    context.set_pronouns(components.get_pronouns().clone());
+    context.set_locale(components.get_locale());
+    for name in components.variables.keys() {
+        if context.get(name).is_none() {
+            context.insert(name, format!("<{name}>"));
+        }
+    }
+    let mods = components.get_mods();
+
+    let messages = group
+        .active_at(Utc::now())
+        .get_scenarios()
+        .iter()
+        .map(|scenario| {
+            let winners = scenario.get_winners().len();
+            let others = scenario.get_others().len();
+            let message = match mods.get(..winners + others) {
+                Some(picks) => {
+                    let (w, o) = picks.split_at(winners);
+                    scenario.build(w, o, &context).map_err(|e| e.to_string())
+                }
+                None => Err(format!("Needs {} mods", winners + others)),
+            };
+            (scenario.key().to_string(), message)
+        })
+        .collect();
+
+    SimulatedPost {
+        group: group.get_name().to_string(),
+        messages,
+    }
+}
+
+fn as_text(val: &Value) -> String {
+    match val {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+impl fmt::Display for SimulatedPost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "    posts from group {}:", self.group)?;
+        for (scenario, message) in &self.messages {
+            match message {
+                Ok(m) => writeln!(f, "      [{scenario}] {m}")?,
+                Err(e) => writeln!(f, "      [{scenario}] fails: {e}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Simulation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Event: {}", self.event_type)?;
+        if let Some(reward) = &self.reward {
+            writeln!(f, "  Reward")?;
+            write!(f, "{reward}")?;
+        }
+
+        if self.rules.is_empty() {
+            writeln!(f, "  No pipeline rules matched")?;
+        }
+        for rule in &self.rules {
+            match &rule.rule.condition {
+                Some(c) => writeln!(f, "  Rule {} matched (if {c})", rule.index)?,
+                None => writeln!(f, "  Rule {} matched", rule.index)?,
+            }
+            for step in &rule.steps {
+                match step {
+                    SimulatedStep::Post(post) => write!(f, "{post}")?,
+                    SimulatedStep::Say(Ok(m)) => writeln!(f, "    says: {m}")?,
+                    SimulatedStep::Say(Err(e)) => writeln!(f, "    says nothing: {e}")?,
+                    SimulatedStep::AwardPoints {
+                        user: Some(user),
+                        amount,
+                    } => writeln!(f, "    awards {amount} points to {user}")?,
+                    SimulatedStep::AwardPoints { user: None, .. } => {
+                        writeln!(f, "    awards nothing: the event has no user_login")?
+                    }
+                    SimulatedStep::UnknownGroup(g) => {
+                        writeln!(f, "    posts nothing: no scenario group named {g}")?
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use pretty_assertions::assert_eq;
+
+    use crate::{reward::mod_feeder::read_config, simulate::simulate};
+
+    #[test]
+    fn simulate_renders_matching_rules_without_network() -> Result<()> {
+        let components = read_config(&"resources/tests/message_components_pipeline.json".into())?;
+        let notification: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(
+            "resources/tests/channel_cheer_event.json",
+        )?)?;
+
+        let simulation = simulate(&components, &notification)?;
+
+        assert_eq!(
+            simulation.to_string(),
+            "Event: channel.cheer\n  \
+            Rule 0 matched (if bits >= 500)\n    \
+            posts from group big_cheer:\n      \
+            [{event_user_name} cheered {event_bits} bits, {win_1} gets a cracker!] \
+            Cool_User cheered 1000 bits, John gets a cracker!\n    \
+            says: Thank you Cool_User!\n    \
+            awards 100 points to cool_user\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn simulate_shows_reward_group_for_redemptions() -> Result<()> {
+        let components = read_config(&"resources/tests/message_components_pipeline.json".into())?;
+        let notification: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(
+            "resources/tests/reward_redemption_event.json",
+        )?)?;
+
+        let simulation = simulate(&components, &notification)?;
+
+        assert_eq!(
+            simulation.to_string(),
+            "Event: channel.channel_points_custom_reward_redemption.add\n  \
+            Reward\n    \
+            posts from group default:\n      \
+            [{win_1} gets the cracker this time.] John gets the cracker this time.\n  \
+            No pipeline rules matched\n"
+        );
+        Ok(())
+    }
+}
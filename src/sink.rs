@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// How long a probe result is trusted before the backend is probed again.
+pub const PROBE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Where chat messages can be sent from.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatBackend {
+    StreamElements,
+    /// Helix `chat/messages`, sent as `HELIX_CHAT_SENDER_ID`.
+    Helix,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BackendStatus {
+    pub backend: ChatBackend,
+    pub healthy: bool,
+    pub checked_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The first healthy backend, in order of preference. With none healthy the most preferred
+/// one is still tried, since a failed probe doesn't always mean sending will fail.
+pub fn choose(statuses: &[BackendStatus]) -> Option<ChatBackend> {
+    statuses
+        .iter()
+        .find(|s| s.healthy)
+        .or(statuses.first())
+        .map(|s| s.backend)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use pretty_assertions::assert_eq;
+
+    use crate::sink::{BackendStatus, ChatBackend, choose};
+
+    fn status(backend: ChatBackend, healthy: bool) -> BackendStatus {
+        BackendStatus {
+            backend,
+            healthy,
+            checked_at: Utc::now(),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn choose_prefers_first_healthy_backend() {
+        let statuses = [
+            status(ChatBackend::StreamElements, false),
+            status(ChatBackend::Helix, true),
+        ];
+
+        assert_eq!(choose(&statuses), Some(ChatBackend::Helix));
+    }
+
+    #[test]
+    fn choose_falls_back_to_first_backend_when_none_are_healthy() {
+        let statuses = [
+            status(ChatBackend::StreamElements, false),
+            status(ChatBackend::Helix, false),
+        ];
+
+        assert_eq!(choose(&statuses), Some(ChatBackend::StreamElements));
+        assert_eq!(choose(&[]), None);
+    }
+}
@@ -0,0 +1,2184 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Mutex,
+};
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::{
+    Client,
+    types::{AttributeValue, ReturnValue},
+};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use strum::{AsRefStr, EnumString};
+
+use crate::config::AppConfig;
+
+#[cfg(feature = "redis")]
+mod redis_store;
+
+#[cfg(feature = "sqlite")]
+pub(crate) mod sqlite_store;
+
+#[cfg(feature = "redis")]
+pub use redis_store::RedisStore;
+#[cfg(feature = "sqlite")]
+pub use sqlite_store::SqliteStore;
+
+/// Where the state is kept, from `STATE_BACKEND`.
+#[derive(Debug, Clone, Copy, PartialEq, AsRefStr, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum StateBackend {
+    /// The table named by `STATE_TABLE_NAME`.
+    Dynamodb,
+    /// The Redis server at `REDIS_URL`, needs the `redis` feature.
+    Redis,
+    /// The SQLite file at `SQLITE_PATH`, needs the `sqlite` feature.
+    Sqlite,
+    /// The process's memory, lost on restart.
+    Memory,
+}
+
+impl StateBackend {
+    /// `STATE_BACKEND`, or when it's unset DynamoDB if `STATE_TABLE_NAME` is set and memory
+    /// otherwise.
+    pub fn for_config(config: &AppConfig) -> StateBackend {
+        config
+            .state_backend
+            .unwrap_or(match config.state_table_name {
+                Some(_) => StateBackend::Dynamodb,
+                None => StateBackend::Memory,
+            })
+    }
+}
+
+/// How long records that are only needed for a while are kept before they expire. Cooldowns
+/// expire when they end, so they aren't listed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Retention {
+    /// Ids of handled EventSub messages, long enough to recognize Twitch's retries.
+    pub seen_secs: u64,
+    /// A message or config audit entry. Redis and SQLite keep a day's log together, so there
+    /// it's counted from the day's latest entry.
+    pub audit_secs: u64,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Retention {
+            seen_secs: 24 * 60 * 60,
+            audit_secs: 31 * 24 * 60 * 60,
+        }
+    }
+}
+
+impl Retention {
+    pub fn from_config(config: &AppConfig) -> Retention {
+        Retention {
+            seen_secs: config.seen_message_retention_secs,
+            audit_secs: config.audit_retention_days * 24 * 60 * 60,
+        }
+    }
+}
+
+/// Small pieces of state that need to outlive a single invocation.
+#[async_trait]
+pub trait StateStore: Send + Sync {
+    /// Mod the round-robin picker of a scenario group handed the last win to, if any.
+    async fn last_round_robin(&self, group: &str) -> Result<Option<String>>;
+
+    /// Moves the round-robin of a scenario group on to `next`, unless another post moved it
+    /// away from `expected` first. Returns whether it moved.
+    async fn advance_round_robin(
+        &self,
+        group: &str,
+        expected: Option<&str>,
+        next: &str,
+    ) -> Result<bool>;
+
+    /// Recent winners of a scenario group, most recent first.
+    async fn recent_winners(&self, group: &str) -> Result<Vec<String>>;
+
+    /// Records new winners of a scenario group, keeping only its latest `window` entries.
+    /// Winners pushed at the same time aren't lost to each other.
+    async fn push_recent_winners(
+        &self,
+        group: &str,
+        winners: &[String],
+        window: usize,
+    ) -> Result<()>;
+
+    /// Drops `names` (compared case-insensitively) from every group's recent winners,
+    /// returning how many entries were removed.
+    async fn forget_winners(&self, names: &[String]) -> Result<usize>;
+
+    /// Counts one firing of `scenario` in the stats bucket for `week`, an ISO week such as
+    /// `2026-W42` or a month such as `2026-10`.
+    async fn record_scenario(&self, week: &str, scenario: &str) -> Result<()>;
+
+    /// How often each scenario fired during `week`.
+    async fn scenario_counts(&self, week: &str) -> Result<HashMap<String, u64>>;
+
+    /// Counts one win for each of `mods`, over all time.
+    async fn record_wins(&self, mods: &[String]) -> Result<()>;
+
+    /// How often each mod has won, over all time.
+    async fn win_counts(&self) -> Result<HashMap<String, u64>>;
+
+    /// Counts one win for each of `mods` during `month`, e.g. `2026-10`.
+    async fn record_month_wins(&self, month: &str, mods: &[String]) -> Result<()>;
+
+    /// How often each mod won during `month`.
+    async fn month_win_counts(&self, month: &str) -> Result<HashMap<String, u64>>;
+
+    /// Counts one `metric` (e.g. a post or a chat message) for `variant` of `experiment`.
+    async fn record_variant(&self, experiment: &str, variant: &str, metric: &str) -> Result<()>;
+
+    /// Counts of `experiment`, keyed by `{variant}/{metric}`.
+    async fn variant_counts(&self, experiment: &str) -> Result<HashMap<String, u64>>;
+
+    /// The serialized title and category of the channel as of the last `channel.update`.
+    async fn channel_info(&self) -> Result<Option<String>>;
+
+    async fn set_channel_info(&self, entry: &str) -> Result<()>;
+
+    /// The serialized experiment variant last posted for the viewer `user_id`, until it
+    /// expires.
+    async fn engagement(&self, user_id: &str) -> Result<Option<String>>;
+
+    /// Remembers the serialized variant posted for the viewer `user_id` for `ttl_secs`.
+    async fn set_engagement(&self, user_id: &str, entry: &str, ttl_secs: u64) -> Result<()>;
+
+    /// Lowercase names of the mods who opted out of scenarios from chat.
+    async fn opted_out_mods(&self) -> Result<Vec<String>>;
+
+    /// Opts the mod `name` (lowercase) out of scenarios, or back in.
+    async fn set_opted_out(&self, name: &str, opted_out: bool) -> Result<()>;
+
+    /// Adds one to the named counter, returning the new count. With `ttl_secs` the counter is
+    /// dropped that long after it was created, for counters kept under a key per window.
+    async fn increment_counter(&self, name: &str, ttl_secs: Option<u64>) -> Result<u64>;
+
+    /// The named counter's count, 0 before its first increment.
+    async fn counter(&self, name: &str) -> Result<u64>;
+
+    /// Message components saved through the admin API, if any.
+    async fn get_config(&self) -> Result<Option<StoredConfig>>;
+
+    /// Saves `body` as the next config version, as long as the stored version is still
+    /// `expected_version` (0 when nothing has been saved yet).
+    async fn put_config(&self, body: &str, expected_version: u64) -> Result<ConfigWrite>;
+
+    /// Appends a serialized audit entry to the log for `day`, which expires once it's been
+    /// kept for the audit retention.
+    async fn append_audit(&self, day: &str, entry: &str) -> Result<()>;
+
+    /// Serialized audit entries logged on `day`, in the order they were appended.
+    async fn audit_entries(&self, day: &str) -> Result<Vec<String>>;
+
+    /// Appends a serialized message config change to the log for `day`.
+    async fn append_config_audit(&self, day: &str, entry: &str) -> Result<()>;
+
+    /// Serialized config changes logged on `day`, in the order they were appended.
+    async fn config_audit_entries(&self, day: &str) -> Result<Vec<String>>;
+
+    /// Drops the audit entries logged on `day` that mention one of `names`, returning how many
+    /// were dropped.
+    async fn forget_audit(&self, day: &str, names: &[String]) -> Result<usize>;
+
+    /// Drops the config changes logged on `day` that mention one of `names`, returning how
+    /// many were dropped.
+    async fn forget_config_audit(&self, day: &str, names: &[String]) -> Result<usize>;
+
+    /// Holds a serialized redemption until the stream goes live.
+    async fn queue_offline(&self, entry: &str) -> Result<()>;
+
+    /// The offline queue's entries in the order they were queued. They stay there until
+    /// removed.
+    async fn offline_queue(&self) -> Result<Vec<QueuedEntry>>;
+
+    /// Removes the queued redemption kept under `key`, once it's been posted.
+    async fn remove_offline(&self, key: &str) -> Result<()>;
+
+    /// Records `id` (e.g. an EventSub message id) as seen for the seen retention, returning
+    /// false when it already was.
+    async fn mark_seen(&self, id: &str) -> Result<bool>;
+
+    /// Drops `id` from the seen ids, so a retry of a message whose handling failed isn't
+    /// skipped.
+    async fn forget_seen(&self, id: &str) -> Result<()>;
+
+    /// Starts the cooldown `key` for `secs` unless it's still running, returning whether it
+    /// started.
+    async fn start_cooldown(&self, key: &str, secs: u64) -> Result<bool>;
+
+    /// Holds a serialized piece of work to be retried later.
+    async fn push_outbox(&self, entry: &str) -> Result<()>;
+
+    /// The outbox's entries in the order they were pushed. They stay there until removed.
+    async fn outbox(&self) -> Result<Vec<QueuedEntry>>;
+
+    /// Removes the outbox entry kept under `key`, once it's been dealt with.
+    async fn remove_outbox(&self, key: &str) -> Result<()>;
+}
+
+/// An entry of a queue and the key it's removed by.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueuedEntry {
+    pub key: String,
+    pub entry: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredConfig {
+    pub version: u64,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigWrite {
+    Saved(u64),
+    /// Someone else saved first. Holds what they saved.
+    Conflict(StoredConfig),
+}
+
+fn merge_recent(recent: Vec<String>, winners: &[String], window: usize) -> Vec<String> {
+    winners
+        .iter()
+        .cloned()
+        .chain(recent.into_iter().filter(|r| !winners.contains(r)))
+        .take(window)
+        .collect()
+}
+
+/// How many of `recent` match one of `names`, and the rest.
+fn split_forgotten(recent: Vec<String>, names: &[String]) -> (usize, Vec<String>) {
+    let before = recent.len();
+    let kept: Vec<String> = recent
+        .into_iter()
+        .filter(|r| !names.iter().any(|n| n.eq_ignore_ascii_case(r)))
+        .collect();
+    (before - kept.len(), kept)
+}
+
+/// Whether `entry` mentions one of `names` as a whole word, ignoring ASCII case. Logins can
+/// only hold letters, digits and underscores, so those are what a name can't run into.
+fn mentions_any(entry: &str, names: &[String]) -> bool {
+    let entry = entry.to_ascii_lowercase();
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    names
+        .iter()
+        .map(|n| n.to_ascii_lowercase())
+        .filter(|n| !n.is_empty())
+        .any(|name| {
+            entry.match_indices(&name).any(|(at, _)| {
+                let before = entry[..at].chars().next_back();
+                let after = entry[at + name.len()..].chars().next();
+                !before.is_some_and(is_word) && !after.is_some_and(is_word)
+            })
+        })
+}
+
+/// Drops the entries mentioning one of `names`, returning how many.
+fn forget_entries(entries: Option<&mut Vec<String>>, names: &[String]) -> usize {
+    let Some(entries) = entries else {
+        return 0;
+    };
+    let before = entries.len();
+    entries.retain(|e| !mentions_any(e, names));
+    before - entries.len()
+}
+
+/// Key of the recent winners of every scenario group.
+const RECENT_WINNERS_KEY: &str = "recent_winners";
+
+/// How often a write to the recent winners is retried when another one got in first.
+const RECENT_WINNERS_ATTEMPTS: usize = 5;
+
+/// Log holding the redemptions queued while the stream is offline.
+const OFFLINE_QUEUE_KEY: &str = "offline_queue";
+
+/// Log holding work to be retried later.
+const OUTBOX_KEY: &str = "outbox";
+
+/// Key of the mods who opted out of scenarios.
+const OPTED_OUT_KEY: &str = "opted_out";
+
+/// Epoch seconds after which an item can be deleted, the attribute the table's TTL is set on.
+pub(crate) const EXPIRES_AT: &str = "expires_at";
+
+/// Keeps everything in a DynamoDB table keyed by a string partition key `pk`, apart from the
+/// message and config audit logs, the offline queue and the outbox, which get an item per entry in a table also
+/// sorted by the string `sk`.
+pub struct DynamoStore {
+    pub client: Client,
+    pub table_name: String,
+    pub log_table_name: String,
+    pub retention: Retention,
+}
+
+#[async_trait]
+impl StateStore for DynamoStore {
+    async fn last_round_robin(&self, group: &str) -> Result<Option<String>> {
+        let item = match self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("pk", AttributeValue::S(format!("round_robin#{group}")))
+            .send()
+            .await
+        {
+            Ok(output) => output.item,
+            Err(e) => return Err(anyhow!("Failed to read round-robin for {group}: {e}")),
+        };
+
+        match item.as_ref().and_then(|i| i.get("last_mod")) {
+            Some(AttributeValue::S(name)) => Ok(Some(name.clone())),
+            _ => Ok(None),
+        }
+    }
+
+    async fn advance_round_robin(
+        &self,
+        group: &str,
+        expected: Option<&str>,
+        next: &str,
+    ) -> Result<bool> {
+        let request = self
+            .client
+            .put_item()
+            .table_name(&self.table_name)
+            .item("pk", AttributeValue::S(format!("round_robin#{group}")))
+            .item("last_mod", AttributeValue::S(next.to_string()));
+
+        let request = match expected {
+            None => request.condition_expression("attribute_not_exists(pk)"),
+            Some(expected) => request
+                .condition_expression("#last_mod = :expected")
+                .expression_attribute_names("#last_mod", "last_mod")
+                .expression_attribute_values(":expected", AttributeValue::S(expected.into())),
+        };
+
+        match request.send().await {
+            Ok(_) => Ok(true),
+            Err(e)
+                if e.as_service_error()
+                    .is_some_and(|e| e.is_conditional_check_failed_exception()) =>
+            {
+                Ok(false)
+            }
+            Err(e) => Err(anyhow!("Failed to store round-robin for {group}: {e}")),
+        }
+    }
+
+    async fn recent_winners(&self, group: &str) -> Result<Vec<String>> {
+        let (mut groups, _) = self
+            .read_recent_winners()
+            .await
+            .map_err(|e| anyhow!("Failed to read recent winners: {e}"))?;
+        Ok(groups.remove(group).unwrap_or_default())
+    }
+
+    async fn push_recent_winners(
+        &self,
+        group: &str,
+        winners: &[String],
+        window: usize,
+    ) -> Result<()> {
+        self.update_recent_winners(|groups| {
+            let recent = groups.remove(group).unwrap_or_default();
+            let merged = merge_recent(recent, winners, window);
+            if !merged.is_empty() {
+                groups.insert(group.to_string(), merged);
+            }
+            1
+        })
+        .await
+        .map(|_| ())
+        .map_err(|e| anyhow!("Failed to store recent winners: {e}"))
+    }
+
+    async fn forget_winners(&self, names: &[String]) -> Result<usize> {
+        self.update_recent_winners(|groups| {
+            let mut forgotten = 0;
+            for recent in groups.values_mut() {
+                let (count, kept) = split_forgotten(std::mem::take(recent), names);
+                forgotten += count;
+                *recent = kept;
+            }
+            groups.retain(|_, recent| !recent.is_empty());
+            forgotten
+        })
+        .await
+        .map_err(|e| anyhow!("Failed to forget recent winners: {e}"))
+    }
+
+    async fn record_scenario(&self, week: &str, scenario: &str) -> Result<()> {
+        self.increment_in_map(format!("scenario_stats#{week}"), scenario)
+            .await
+            .map_err(|e| anyhow!("Failed to record scenario for {week}: {e}"))
+    }
+
+    async fn scenario_counts(&self, week: &str) -> Result<HashMap<String, u64>> {
+        self.read_counts(format!("scenario_stats#{week}"))
+            .await
+            .map_err(|e| anyhow!("Failed to read stats for {week}: {e}"))
+    }
+
+    async fn record_wins(&self, mods: &[String]) -> Result<()> {
+        let names: Vec<&str> = mods.iter().map(String::as_str).collect();
+        self.add_to_map("mod_wins".into(), &names)
+            .await
+            .map_err(|e| anyhow!("Failed to record wins: {e}"))
+    }
+
+    async fn win_counts(&self) -> Result<HashMap<String, u64>> {
+        self.read_counts("mod_wins".into())
+            .await
+            .map_err(|e| anyhow!("Failed to read mod wins: {e}"))
+    }
+
+    async fn record_month_wins(&self, month: &str, mods: &[String]) -> Result<()> {
+        let names: Vec<&str> = mods.iter().map(String::as_str).collect();
+        self.add_to_map(format!("mod_wins#{month}"), &names)
+            .await
+            .map_err(|e| anyhow!("Failed to record {month} wins: {e}"))
+    }
+
+    async fn month_win_counts(&self, month: &str) -> Result<HashMap<String, u64>> {
+        self.read_counts(format!("mod_wins#{month}"))
+            .await
+            .map_err(|e| anyhow!("Failed to read mod wins for {month}: {e}"))
+    }
+
+    async fn record_variant(&self, experiment: &str, variant: &str, metric: &str) -> Result<()> {
+        self.increment_in_map(
+            format!("experiment_stats#{experiment}"),
+            &format!("{variant}/{metric}"),
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to record {metric} for {experiment}: {e}"))
+    }
+
+    async fn variant_counts(&self, experiment: &str) -> Result<HashMap<String, u64>> {
+        self.read_counts(format!("experiment_stats#{experiment}"))
+            .await
+            .map_err(|e| anyhow!("Failed to read stats for {experiment}: {e}"))
+    }
+
+    async fn channel_info(&self) -> Result<Option<String>> {
+        let item = match self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("pk", AttributeValue::S("channel_info".into()))
+            .send()
+            .await
+        {
+            Ok(output) => output.item,
+            Err(e) => return Err(anyhow!("Failed to read channel info: {e}")),
+        };
+
+        Ok(item
+            .as_ref()
+            .and_then(|i| i.get("entry"))
+            .and_then(|e| e.as_s().ok())
+            .cloned())
+    }
+
+    async fn set_channel_info(&self, entry: &str) -> Result<()> {
+        match self
+            .client
+            .put_item()
+            .table_name(&self.table_name)
+            .item("pk", AttributeValue::S("channel_info".into()))
+            .item("entry", AttributeValue::S(entry.to_string()))
+            .send()
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow!("Failed to store channel info: {e}")),
+        }
+    }
+
+    async fn engagement(&self, user_id: &str) -> Result<Option<String>> {
+        let item = match self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("pk", AttributeValue::S(format!("engagement#{user_id}")))
+            .send()
+            .await
+        {
+            Ok(output) => output.item,
+            Err(e) => return Err(anyhow!("Failed to read engagement of {user_id}: {e}")),
+        };
+
+        // Expired items linger until DynamoDB's TTL gets to them.
+        let now = Utc::now().timestamp();
+        Ok(item
+            .filter(|i| match i.get(EXPIRES_AT) {
+                Some(AttributeValue::N(n)) => n.parse::<i64>().is_ok_and(|e| e > now),
+                _ => false,
+            })
+            .and_then(|i| i.get("entry").and_then(|e| e.as_s().ok()).cloned()))
+    }
+
+    async fn set_engagement(&self, user_id: &str, entry: &str, ttl_secs: u64) -> Result<()> {
+        let expires_at = Utc::now().timestamp() + ttl_secs as i64;
+        match self
+            .client
+            .put_item()
+            .table_name(&self.table_name)
+            .item("pk", AttributeValue::S(format!("engagement#{user_id}")))
+            .item("entry", AttributeValue::S(entry.to_string()))
+            .item(EXPIRES_AT, AttributeValue::N(expires_at.to_string()))
+            .send()
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow!("Failed to store engagement of {user_id}: {e}")),
+        }
+    }
+
+    async fn opted_out_mods(&self) -> Result<Vec<String>> {
+        let item = match self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("pk", AttributeValue::S(OPTED_OUT_KEY.into()))
+            .send()
+            .await
+        {
+            Ok(output) => output.item,
+            Err(e) => return Err(anyhow!("Failed to read opted out mods: {e}")),
+        };
+
+        let mut names = item
+            .as_ref()
+            .and_then(|i| i.get("names"))
+            .and_then(|n| n.as_ss().ok())
+            .cloned()
+            .unwrap_or_default();
+        names.sort();
+        Ok(names)
+    }
+
+    /// A string set, added to and deleted from in place so two mods toggling at once don't
+    /// overwrite each other. DynamoDB drops the attribute once the set is empty.
+    async fn set_opted_out(&self, name: &str, opted_out: bool) -> Result<()> {
+        let expression = match opted_out {
+            true => "ADD #names :name",
+            false => "DELETE #names :name",
+        };
+        match self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .key("pk", AttributeValue::S(OPTED_OUT_KEY.into()))
+            .update_expression(expression)
+            .expression_attribute_names("#names", "names")
+            .expression_attribute_values(":name", AttributeValue::Ss(vec![name.to_string()]))
+            .send()
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow!("Failed to opt {name} in or out: {e}")),
+        }
+    }
+
+    async fn increment_counter(&self, name: &str, ttl_secs: Option<u64>) -> Result<u64> {
+        let mut request = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .key("pk", AttributeValue::S(format!("counter#{name}")))
+            .update_expression("ADD #count :one")
+            .expression_attribute_names("#count", "count")
+            .expression_attribute_values(":one", AttributeValue::N("1".into()))
+            .return_values(ReturnValue::UpdatedNew);
+        if let Some(ttl_secs) = ttl_secs {
+            let expires_at = Utc::now().timestamp() + ttl_secs as i64;
+            request = request
+                .update_expression(
+                    "ADD #count :one SET #expires_at = if_not_exists(#expires_at, :expires_at)",
+                )
+                .expression_attribute_names("#expires_at", EXPIRES_AT)
+                .expression_attribute_values(
+                    ":expires_at",
+                    AttributeValue::N(expires_at.to_string()),
+                );
+        }
+        let output = match request.send().await {
+            Ok(o) => o,
+            Err(e) => return Err(anyhow!("Failed to increment counter {name}: {e}")),
+        };
+
+        match output.attributes.as_ref().and_then(|a| a.get("count")) {
+            Some(AttributeValue::N(n)) => Ok(n.parse()?),
+            _ => Err(anyhow!("Counter {name} has no count")),
+        }
+    }
+
+    async fn counter(&self, name: &str) -> Result<u64> {
+        let item = match self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("pk", AttributeValue::S(format!("counter#{name}")))
+            .send()
+            .await
+        {
+            Ok(output) => output.item,
+            Err(e) => return Err(anyhow!("Failed to read counter {name}: {e}")),
+        };
+
+        match item.as_ref().and_then(|i| i.get("count")) {
+            Some(AttributeValue::N(n)) => Ok(n.parse()?),
+            _ => Ok(0),
+        }
+    }
+
+    async fn get_config(&self) -> Result<Option<StoredConfig>> {
+        let item = match self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("pk", AttributeValue::S("config".into()))
+            .consistent_read(true)
+            .send()
+            .await
+        {
+            Ok(output) => output.item,
+            Err(e) => return Err(anyhow!("Failed to read config: {e}")),
+        };
+
+        let item = match item {
+            Some(i) => i,
+            None => return Ok(None),
+        };
+
+        match (item.get("version"), item.get("body")) {
+            (Some(AttributeValue::N(version)), Some(AttributeValue::S(body))) => {
+                Ok(Some(StoredConfig {
+                    version: version.parse()?,
+                    body: body.clone(),
+                }))
+            }
+            _ => Err(anyhow!("Stored config is missing its version or body")),
+        }
+    }
+
+    async fn put_config(&self, body: &str, expected_version: u64) -> Result<ConfigWrite> {
+        let version = expected_version + 1;
+        let request = self
+            .client
+            .put_item()
+            .table_name(&self.table_name)
+            .item("pk", AttributeValue::S("config".into()))
+            .item("version", AttributeValue::N(version.to_string()))
+            .item("body", AttributeValue::S(body.to_string()));
+
+        let request = if expected_version == 0 {
+            request.condition_expression("attribute_not_exists(pk)")
+        } else {
+            request
+                .condition_expression("#version = :expected")
+                .expression_attribute_names("#version", "version")
+                .expression_attribute_values(
+                    ":expected",
+                    AttributeValue::N(expected_version.to_string()),
+                )
+        };
+
+        match request.send().await {
+            Ok(_) => Ok(ConfigWrite::Saved(version)),
+            Err(e) => {
+                if !e
+                    .as_service_error()
+                    .is_some_and(|e| e.is_conditional_check_failed_exception())
+                {
+                    return Err(anyhow!("Failed to store config: {e}"));
+                }
+
+                match self.get_config().await? {
+                    Some(current) => Ok(ConfigWrite::Conflict(current)),
+                    None => Err(anyhow!("Config write conflicted but nothing is stored")),
+                }
+            }
+        }
+    }
+
+    async fn append_audit(&self, day: &str, entry: &str) -> Result<()> {
+        self.append_log(
+            format!("audit#{day}"),
+            entry,
+            Some(self.retention.audit_secs),
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to append audit entry for {day}: {e}"))
+    }
+
+    async fn audit_entries(&self, day: &str) -> Result<Vec<String>> {
+        match self.read_log(format!("audit#{day}")).await {
+            Ok(entries) => Ok(entries.into_iter().map(|(_, entry)| entry).collect()),
+            Err(e) => Err(anyhow!("Failed to read audit log for {day}: {e}")),
+        }
+    }
+
+    async fn append_config_audit(&self, day: &str, entry: &str) -> Result<()> {
+        self.append_log(
+            format!("config_audit#{day}"),
+            entry,
+            Some(self.retention.audit_secs),
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to append config change for {day}: {e}"))
+    }
+
+    async fn config_audit_entries(&self, day: &str) -> Result<Vec<String>> {
+        match self.read_log(format!("config_audit#{day}")).await {
+            Ok(entries) => Ok(entries.into_iter().map(|(_, entry)| entry).collect()),
+            Err(e) => Err(anyhow!("Failed to read config changes for {day}: {e}")),
+        }
+    }
+
+    async fn forget_audit(&self, day: &str, names: &[String]) -> Result<usize> {
+        self.forget_in_log(format!("audit#{day}"), names)
+            .await
+            .map_err(|e| anyhow!("Failed to forget audit entries for {day}: {e}"))
+    }
+
+    async fn forget_config_audit(&self, day: &str, names: &[String]) -> Result<usize> {
+        self.forget_in_log(format!("config_audit#{day}"), names)
+            .await
+            .map_err(|e| anyhow!("Failed to forget config changes for {day}: {e}"))
+    }
+
+    async fn queue_offline(&self, entry: &str) -> Result<()> {
+        self.append_log(OFFLINE_QUEUE_KEY.into(), entry, None)
+            .await
+            .map_err(|e| anyhow!("Failed to queue offline redemption: {e}"))
+    }
+
+    async fn offline_queue(&self) -> Result<Vec<QueuedEntry>> {
+        match self.read_log(OFFLINE_QUEUE_KEY.into()).await {
+            Ok(entries) => Ok(entries
+                .into_iter()
+                .map(|(key, entry)| QueuedEntry { key, entry })
+                .collect()),
+            Err(e) => Err(anyhow!("Failed to read offline queue: {e}")),
+        }
+    }
+
+    async fn remove_offline(&self, key: &str) -> Result<()> {
+        self.remove_log(OFFLINE_QUEUE_KEY.into(), key)
+            .await
+            .map_err(|e| anyhow!("Failed to remove {key} from offline queue: {e}"))
+    }
+
+    async fn mark_seen(&self, id: &str) -> Result<bool> {
+        self.claim(format!("seen#{id}"), self.retention.seen_secs)
+            .await
+            .map_err(|e| anyhow!("Failed to mark {id} as seen: {e}"))
+    }
+
+    async fn forget_seen(&self, id: &str) -> Result<()> {
+        match self
+            .client
+            .delete_item()
+            .table_name(&self.table_name)
+            .key("pk", AttributeValue::S(format!("seen#{id}")))
+            .send()
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow!("Failed to forget {id} was seen: {e}")),
+        }
+    }
+
+    async fn start_cooldown(&self, key: &str, secs: u64) -> Result<bool> {
+        self.claim(format!("cooldown#{key}"), secs)
+            .await
+            .map_err(|e| anyhow!("Failed to start cooldown {key}: {e}"))
+    }
+
+    async fn push_outbox(&self, entry: &str) -> Result<()> {
+        self.append_log(OUTBOX_KEY.into(), entry, None)
+            .await
+            .map_err(|e| anyhow!("Failed to push to outbox: {e}"))
+    }
+
+    async fn outbox(&self) -> Result<Vec<QueuedEntry>> {
+        match self.read_log(OUTBOX_KEY.into()).await {
+            Ok(entries) => Ok(entries
+                .into_iter()
+                .map(|(key, entry)| QueuedEntry { key, entry })
+                .collect()),
+            Err(e) => Err(anyhow!("Failed to read outbox: {e}")),
+        }
+    }
+
+    async fn remove_outbox(&self, key: &str) -> Result<()> {
+        self.remove_log(OUTBOX_KEY.into(), key)
+            .await
+            .map_err(|e| anyhow!("Failed to remove {key} from outbox: {e}"))
+    }
+}
+
+impl DynamoStore {
+    /// Recent winners by scenario group, and the version they're stored at.
+    async fn read_recent_winners(&self) -> Result<(HashMap<String, Vec<String>>, Option<u64>)> {
+        let item = self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("pk", AttributeValue::S(RECENT_WINNERS_KEY.into()))
+            .consistent_read(true)
+            .send()
+            .await?
+            .item
+            .unwrap_or_default();
+
+        let version = match item.get("version") {
+            Some(AttributeValue::N(n)) => Some(n.parse()?),
+            _ => None,
+        };
+        let groups = match item.get("groups") {
+            Some(AttributeValue::M(groups)) => groups
+                .iter()
+                .map(|(group, recent)| {
+                    let names = match recent {
+                        AttributeValue::L(names) => names
+                            .iter()
+                            .filter_map(|n| n.as_s().ok().cloned())
+                            .collect(),
+                        _ => vec![],
+                    };
+                    (group.clone(), names)
+                })
+                .collect(),
+            _ => HashMap::new(),
+        };
+        Ok((groups, version))
+    }
+
+    /// Applies `change` to the recent winners and writes them back, as long as nobody wrote
+    /// them in between. Otherwise it's retried on what they wrote. Skips the write when
+    /// `change` returns 0, and returns what it returned.
+    async fn update_recent_winners(
+        &self,
+        change: impl Fn(&mut HashMap<String, Vec<String>>) -> usize,
+    ) -> Result<usize> {
+        for _ in 0..RECENT_WINNERS_ATTEMPTS {
+            let (mut groups, version) = self.read_recent_winners().await?;
+            let changed = change(&mut groups);
+            if changed == 0 {
+                return Ok(0);
+            }
+
+            let groups = groups
+                .into_iter()
+                .map(|(group, recent)| {
+                    let names = recent.into_iter().map(AttributeValue::S).collect();
+                    (group, AttributeValue::L(names))
+                })
+                .collect();
+            let request = self
+                .client
+                .put_item()
+                .table_name(&self.table_name)
+                .item("pk", AttributeValue::S(RECENT_WINNERS_KEY.into()))
+                .item("groups", AttributeValue::M(groups))
+                .item(
+                    "version",
+                    AttributeValue::N((version.unwrap_or(0) + 1).to_string()),
+                )
+                .expression_attribute_names("#version", "version");
+            let request = match version {
+                None => request.condition_expression("attribute_not_exists(#version)"),
+                Some(version) => request
+                    .condition_expression("#version = :expected")
+                    .expression_attribute_values(
+                        ":expected",
+                        AttributeValue::N(version.to_string()),
+                    ),
+            };
+
+            match request.send().await {
+                Ok(_) => return Ok(changed),
+                Err(e)
+                    if e.as_service_error()
+                        .is_some_and(|e| e.is_conditional_check_failed_exception()) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Err(anyhow!(
+            "Kept conflicting with other writes after {RECENT_WINNERS_ATTEMPTS} attempts"
+        ))
+    }
+
+    /// Writes the item `pk` expiring in `ttl_secs`, unless there's one that hasn't expired yet.
+    /// Returns whether it was written. DynamoDB deletes expired items through the table's TTL
+    /// on `expires_at`, though not straight away, so the expiry is checked here too.
+    async fn claim(&self, pk: String, ttl_secs: u64) -> Result<bool> {
+        let now = Utc::now().timestamp();
+        match self
+            .client
+            .put_item()
+            .table_name(&self.table_name)
+            .item("pk", AttributeValue::S(pk))
+            .item(
+                EXPIRES_AT,
+                AttributeValue::N((now + ttl_secs as i64).to_string()),
+            )
+            .condition_expression("attribute_not_exists(pk) OR #expires_at <= :now")
+            .expression_attribute_names("#expires_at", EXPIRES_AT)
+            .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+            .send()
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(e)
+                if e.as_service_error()
+                    .is_some_and(|e| e.is_conditional_check_failed_exception()) =>
+            {
+                Ok(false)
+            }
+            Err(e) => Err(anyhow!("{e}")),
+        }
+    }
+
+    /// Adds `entry` to the log `pk` in the log table, as an item of its own sorted by when it
+    /// was added. With `ttl_secs` the entry expires that long after.
+    async fn append_log(&self, pk: String, entry: &str, ttl_secs: Option<u64>) -> Result<()> {
+        let now = Utc::now();
+        let sk = format!(
+            "{}#{:08x}",
+            now.to_rfc3339_opts(SecondsFormat::Micros, true),
+            fastrand::u32(..)
+        );
+        let mut request = self
+            .client
+            .put_item()
+            .table_name(&self.log_table_name)
+            .item("pk", AttributeValue::S(pk))
+            .item("sk", AttributeValue::S(sk))
+            .item("entry", AttributeValue::S(entry.to_string()));
+        if let Some(ttl_secs) = ttl_secs {
+            request = request.item(
+                EXPIRES_AT,
+                AttributeValue::N((now.timestamp() + ttl_secs as i64).to_string()),
+            );
+        }
+
+        match request.send().await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow!("{e}")),
+        }
+    }
+
+    /// The entries of the log `pk` as `(sk, entry)`, oldest first.
+    async fn read_log(&self, pk: String) -> Result<Vec<(String, String)>> {
+        let mut entries = vec![];
+        let mut start_key = None;
+        loop {
+            let output = match self
+                .client
+                .query()
+                .table_name(&self.log_table_name)
+                .key_condition_expression("pk = :pk")
+                .expression_attribute_values(":pk", AttributeValue::S(pk.clone()))
+                .set_exclusive_start_key(start_key)
+                .send()
+                .await
+            {
+                Ok(output) => output,
+                Err(e) => return Err(anyhow!("{e}")),
+            };
+
+            entries.extend(output.items().iter().filter_map(|item| {
+                let sk = item.get("sk")?.as_s().ok()?;
+                let entry = item.get("entry")?.as_s().ok()?;
+                Some((sk.clone(), entry.clone()))
+            }));
+            start_key = output.last_evaluated_key;
+            if start_key.is_none() {
+                return Ok(entries);
+            }
+        }
+    }
+
+    /// Deletes the entries of the log `pk` that mention one of `names`, returning how many.
+    async fn forget_in_log(&self, pk: String, names: &[String]) -> Result<usize> {
+        let mut forgotten = 0;
+        for (sk, entry) in self.read_log(pk.clone()).await? {
+            if mentions_any(&entry, names) {
+                self.remove_log(pk.clone(), &sk).await?;
+                forgotten += 1;
+            }
+        }
+        Ok(forgotten)
+    }
+
+    /// Deletes the entry `sk` of the log `pk`.
+    async fn remove_log(&self, pk: String, sk: &str) -> Result<()> {
+        match self
+            .client
+            .delete_item()
+            .table_name(&self.log_table_name)
+            .key("pk", AttributeValue::S(pk))
+            .key("sk", AttributeValue::S(sk.to_string()))
+            .send()
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow!("{e}")),
+        }
+    }
+
+    /// Adds one to `field` of the `counts` map on the item `pk`.
+    async fn increment_in_map(&self, pk: String, field: &str) -> Result<()> {
+        self.add_to_map(pk, &[field]).await
+    }
+
+    /// Adds one to each of `fields` of the `counts` map on the item `pk` in a single write,
+    /// counting a field listed twice twice.
+    async fn add_to_map(&self, pk: String, fields: &[&str]) -> Result<()> {
+        let mut tally: Vec<(&str, u64)> = vec![];
+        for field in fields {
+            match tally.iter_mut().find(|(f, _)| f == field) {
+                Some((_, n)) => *n += 1,
+                None => tally.push((field, 1)),
+            }
+        }
+        if tally.is_empty() {
+            return Ok(());
+        }
+        let key = AttributeValue::S(pk);
+
+        // Nested paths can only be updated once their parent map exists.
+        if let Err(e) = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .key("pk", key.clone())
+            .update_expression("SET #counts = if_not_exists(#counts, :empty)")
+            .expression_attribute_names("#counts", "counts")
+            .expression_attribute_values(":empty", AttributeValue::M(HashMap::new()))
+            .send()
+            .await
+        {
+            return Err(anyhow!("{e}"));
+        }
+
+        let assignments: Vec<String> = (0..tally.len())
+            .map(|i| format!("#counts.#f{i} = if_not_exists(#counts.#f{i}, :zero) + :n{i}"))
+            .collect();
+        let mut update = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .key("pk", key)
+            .update_expression(format!("SET {}", assignments.join(", ")))
+            .expression_attribute_names("#counts", "counts")
+            .expression_attribute_values(":zero", AttributeValue::N("0".into()));
+        for (i, (field, n)) in tally.iter().enumerate() {
+            update = update
+                .expression_attribute_names(format!("#f{i}"), *field)
+                .expression_attribute_values(format!(":n{i}"), AttributeValue::N(n.to_string()));
+        }
+        match update.send().await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow!("{e}")),
+        }
+    }
+
+    /// The `counts` map on the item `pk`, empty when there's no such item.
+    async fn read_counts(&self, pk: String) -> Result<HashMap<String, u64>> {
+        let item = match self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("pk", AttributeValue::S(pk))
+            .send()
+            .await
+        {
+            Ok(output) => output.item,
+            Err(e) => return Err(anyhow!("{e}")),
+        };
+
+        match item.as_ref().and_then(|i| i.get("counts")) {
+            Some(AttributeValue::M(counts)) => Ok(counts
+                .iter()
+                .filter_map(|(k, v)| Some((k.clone(), v.as_n().ok()?.parse().ok()?)))
+                .collect()),
+            _ => Ok(HashMap::new()),
+        }
+    }
+}
+
+/// Process-local state, for tests and deployments without a state table. Nothing survives a
+/// restart.
+#[derive(Default)]
+pub struct MemoryStore {
+    round_robin: Mutex<HashMap<String, String>>,
+    recent_winners: Mutex<HashMap<String, Vec<String>>>,
+    scenario_counts: Mutex<HashMap<String, HashMap<String, u64>>>,
+    wins: Mutex<HashMap<String, u64>>,
+    month_wins: Mutex<HashMap<String, HashMap<String, u64>>>,
+    counters: Mutex<HashMap<String, MemoryCounter>>,
+    config: Mutex<Option<StoredConfig>>,
+    audit: Mutex<HashMap<String, Vec<String>>>,
+    config_audit: Mutex<HashMap<String, Vec<String>>>,
+    variant_counts: Mutex<HashMap<String, HashMap<String, u64>>>,
+    channel_info: Mutex<Option<String>>,
+    /// Serialized variants by viewer, and when each expires.
+    engagement: Mutex<HashMap<String, (String, DateTime<Utc>)>>,
+    opted_out: Mutex<BTreeSet<String>>,
+    offline_queue: Mutex<Vec<QueuedEntry>>,
+    /// When each claimed `seen#` and `cooldown#` key expires. Expired ones are dropped by the
+    /// next claim.
+    claims: Mutex<HashMap<String, DateTime<Utc>>>,
+    outbox: Mutex<Vec<QueuedEntry>>,
+    /// Only applied to seen ids, the audit logs are small enough to keep until restart.
+    retention: Retention,
+}
+
+/// A counter of [`MemoryStore`], dropped once `expires_at` has passed.
+struct MemoryCounter {
+    count: u64,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl MemoryStore {
+    pub fn with_retention(self, retention: Retention) -> Self {
+        MemoryStore { retention, ..self }
+    }
+}
+
+#[async_trait]
+impl StateStore for MemoryStore {
+    async fn last_round_robin(&self, group: &str) -> Result<Option<String>> {
+        let round_robin = self.round_robin.lock().map_err(|e| anyhow!("{e}"))?;
+        Ok(round_robin.get(group).cloned())
+    }
+
+    async fn advance_round_robin(
+        &self,
+        group: &str,
+        expected: Option<&str>,
+        next: &str,
+    ) -> Result<bool> {
+        let mut round_robin = self.round_robin.lock().map_err(|e| anyhow!("{e}"))?;
+        if round_robin.get(group).map(String::as_str) != expected {
+            return Ok(false);
+        }
+        round_robin.insert(group.to_string(), next.to_string());
+        Ok(true)
+    }
+
+    async fn recent_winners(&self, group: &str) -> Result<Vec<String>> {
+        let recent = self.recent_winners.lock().map_err(|e| anyhow!("{e}"))?;
+        Ok(recent.get(group).cloned().unwrap_or_default())
+    }
+
+    async fn push_recent_winners(
+        &self,
+        group: &str,
+        winners: &[String],
+        window: usize,
+    ) -> Result<()> {
+        let mut recent = self.recent_winners.lock().map_err(|e| anyhow!("{e}"))?;
+        let group_recent = recent.remove(group).unwrap_or_default();
+        recent.insert(
+            group.to_string(),
+            merge_recent(group_recent, winners, window),
+        );
+        Ok(())
+    }
+
+    async fn forget_winners(&self, names: &[String]) -> Result<usize> {
+        let mut recent = self.recent_winners.lock().map_err(|e| anyhow!("{e}"))?;
+        let mut forgotten = 0;
+        for group_recent in recent.values_mut() {
+            let (count, kept) = split_forgotten(std::mem::take(group_recent), names);
+            forgotten += count;
+            *group_recent = kept;
+        }
+        Ok(forgotten)
+    }
+
+    async fn record_scenario(&self, week: &str, scenario: &str) -> Result<()> {
+        let mut counts = self.scenario_counts.lock().map_err(|e| anyhow!("{e}"))?;
+        *counts
+            .entry(week.to_string())
+            .or_default()
+            .entry(scenario.to_string())
+            .or_default() += 1;
+        Ok(())
+    }
+
+    async fn scenario_counts(&self, week: &str) -> Result<HashMap<String, u64>> {
+        let counts = self.scenario_counts.lock().map_err(|e| anyhow!("{e}"))?;
+        Ok(counts.get(week).cloned().unwrap_or_default())
+    }
+
+    async fn record_wins(&self, mods: &[String]) -> Result<()> {
+        let mut wins = self.wins.lock().map_err(|e| anyhow!("{e}"))?;
+        for name in mods {
+            *wins.entry(name.clone()).or_default() += 1;
+        }
+        Ok(())
+    }
+
+    async fn win_counts(&self) -> Result<HashMap<String, u64>> {
+        let wins = self.wins.lock().map_err(|e| anyhow!("{e}"))?;
+        Ok(wins.clone())
+    }
+
+    async fn record_month_wins(&self, month: &str, mods: &[String]) -> Result<()> {
+        let mut wins = self.month_wins.lock().map_err(|e| anyhow!("{e}"))?;
+        let month = wins.entry(month.to_string()).or_default();
+        for name in mods {
+            *month.entry(name.clone()).or_default() += 1;
+        }
+        Ok(())
+    }
+
+    async fn month_win_counts(&self, month: &str) -> Result<HashMap<String, u64>> {
+        let wins = self.month_wins.lock().map_err(|e| anyhow!("{e}"))?;
+        Ok(wins.get(month).cloned().unwrap_or_default())
+    }
+
+    async fn record_variant(&self, experiment: &str, variant: &str, metric: &str) -> Result<()> {
+        let mut counts = self.variant_counts.lock().map_err(|e| anyhow!("{e}"))?;
+        *counts
+            .entry(experiment.to_string())
+            .or_default()
+            .entry(format!("{variant}/{metric}"))
+            .or_default() += 1;
+        Ok(())
+    }
+
+    async fn variant_counts(&self, experiment: &str) -> Result<HashMap<String, u64>> {
+        let counts = self.variant_counts.lock().map_err(|e| anyhow!("{e}"))?;
+        Ok(counts.get(experiment).cloned().unwrap_or_default())
+    }
+
+    async fn channel_info(&self) -> Result<Option<String>> {
+        let info = self.channel_info.lock().map_err(|e| anyhow!("{e}"))?;
+        Ok(info.clone())
+    }
+
+    async fn set_channel_info(&self, entry: &str) -> Result<()> {
+        let mut info = self.channel_info.lock().map_err(|e| anyhow!("{e}"))?;
+        *info = Some(entry.to_string());
+        Ok(())
+    }
+
+    async fn engagement(&self, user_id: &str) -> Result<Option<String>> {
+        let engagement = self.engagement.lock().map_err(|e| anyhow!("{e}"))?;
+        Ok(engagement
+            .get(user_id)
+            .filter(|(_, expires_at)| *expires_at > Utc::now())
+            .map(|(entry, _)| entry.clone()))
+    }
+
+    async fn set_engagement(&self, user_id: &str, entry: &str, ttl_secs: u64) -> Result<()> {
+        let mut engagement = self.engagement.lock().map_err(|e| anyhow!("{e}"))?;
+        let now = Utc::now();
+        engagement.retain(|_, (_, expires_at)| *expires_at > now);
+        engagement.insert(
+            user_id.to_string(),
+            (entry.to_string(), now + Duration::seconds(ttl_secs as i64)),
+        );
+        Ok(())
+    }
+
+    async fn opted_out_mods(&self) -> Result<Vec<String>> {
+        let opted_out = self.opted_out.lock().map_err(|e| anyhow!("{e}"))?;
+        Ok(opted_out.iter().cloned().collect())
+    }
+
+    async fn set_opted_out(&self, name: &str, opted_out: bool) -> Result<()> {
+        let mut names = self.opted_out.lock().map_err(|e| anyhow!("{e}"))?;
+        match opted_out {
+            true => names.insert(name.to_string()),
+            false => names.remove(name),
+        };
+        Ok(())
+    }
+
+    async fn increment_counter(&self, name: &str, ttl_secs: Option<u64>) -> Result<u64> {
+        let mut counters = self.counters.lock().map_err(|e| anyhow!("{e}"))?;
+        let now = Utc::now();
+        counters.retain(|_, c| c.expires_at.is_none_or(|e| e > now));
+        let counter = counters
+            .entry(name.to_string())
+            .or_insert_with(|| MemoryCounter {
+                count: 0,
+                expires_at: ttl_secs.map(|secs| now + Duration::seconds(secs as i64)),
+            });
+        counter.count += 1;
+        Ok(counter.count)
+    }
+
+    async fn counter(&self, name: &str) -> Result<u64> {
+        let counters = self.counters.lock().map_err(|e| anyhow!("{e}"))?;
+        Ok(counters
+            .get(name)
+            .filter(|c| c.expires_at.is_none_or(|e| e > Utc::now()))
+            .map_or(0, |c| c.count))
+    }
+
+    async fn get_config(&self) -> Result<Option<StoredConfig>> {
+        let config = self.config.lock().map_err(|e| anyhow!("{e}"))?;
+        Ok(config.clone())
+    }
+
+    async fn put_config(&self, body: &str, expected_version: u64) -> Result<ConfigWrite> {
+        let mut config = self.config.lock().map_err(|e| anyhow!("{e}"))?;
+        if let Some(current) = config.as_ref()
+            && current.version != expected_version
+        {
+            return Ok(ConfigWrite::Conflict(current.clone()));
+        }
+        if config.is_none() && expected_version != 0 {
+            return Err(anyhow!("No config stored at version {expected_version}"));
+        }
+
+        let version = expected_version + 1;
+        *config = Some(StoredConfig {
+            version,
+            body: body.to_string(),
+        });
+        Ok(ConfigWrite::Saved(version))
+    }
+
+    async fn append_audit(&self, day: &str, entry: &str) -> Result<()> {
+        let mut audit = self.audit.lock().map_err(|e| anyhow!("{e}"))?;
+        audit
+            .entry(day.to_string())
+            .or_default()
+            .push(entry.to_string());
+        Ok(())
+    }
+
+    async fn audit_entries(&self, day: &str) -> Result<Vec<String>> {
+        let audit = self.audit.lock().map_err(|e| anyhow!("{e}"))?;
+        Ok(audit.get(day).cloned().unwrap_or_default())
+    }
+
+    async fn append_config_audit(&self, day: &str, entry: &str) -> Result<()> {
+        let mut audit = self.config_audit.lock().map_err(|e| anyhow!("{e}"))?;
+        audit
+            .entry(day.to_string())
+            .or_default()
+            .push(entry.to_string());
+        Ok(())
+    }
+
+    async fn config_audit_entries(&self, day: &str) -> Result<Vec<String>> {
+        let audit = self.config_audit.lock().map_err(|e| anyhow!("{e}"))?;
+        Ok(audit.get(day).cloned().unwrap_or_default())
+    }
+
+    async fn forget_audit(&self, day: &str, names: &[String]) -> Result<usize> {
+        let mut audit = self.audit.lock().map_err(|e| anyhow!("{e}"))?;
+        Ok(forget_entries(audit.get_mut(day), names))
+    }
+
+    async fn forget_config_audit(&self, day: &str, names: &[String]) -> Result<usize> {
+        let mut audit = self.config_audit.lock().map_err(|e| anyhow!("{e}"))?;
+        Ok(forget_entries(audit.get_mut(day), names))
+    }
+
+    async fn queue_offline(&self, entry: &str) -> Result<()> {
+        let mut queue = self.offline_queue.lock().map_err(|e| anyhow!("{e}"))?;
+        queue.push(QueuedEntry {
+            key: format!("{:016x}", fastrand::u64(..)),
+            entry: entry.to_string(),
+        });
+        Ok(())
+    }
+
+    async fn offline_queue(&self) -> Result<Vec<QueuedEntry>> {
+        Ok(self
+            .offline_queue
+            .lock()
+            .map_err(|e| anyhow!("{e}"))?
+            .clone())
+    }
+
+    async fn remove_offline(&self, key: &str) -> Result<()> {
+        let mut queue = self.offline_queue.lock().map_err(|e| anyhow!("{e}"))?;
+        queue.retain(|q| q.key != key);
+        Ok(())
+    }
+
+    async fn mark_seen(&self, id: &str) -> Result<bool> {
+        self.claim(format!("seen#{id}"), self.retention.seen_secs)
+    }
+
+    async fn forget_seen(&self, id: &str) -> Result<()> {
+        let mut claims = self.claims.lock().map_err(|e| anyhow!("{e}"))?;
+        claims.remove(&format!("seen#{id}"));
+        Ok(())
+    }
+
+    async fn start_cooldown(&self, key: &str, secs: u64) -> Result<bool> {
+        self.claim(format!("cooldown#{key}"), secs)
+    }
+
+    async fn push_outbox(&self, entry: &str) -> Result<()> {
+        let mut outbox = self.outbox.lock().map_err(|e| anyhow!("{e}"))?;
+        outbox.push(QueuedEntry {
+            key: format!("{:016x}", fastrand::u64(..)),
+            entry: entry.to_string(),
+        });
+        Ok(())
+    }
+
+    async fn outbox(&self) -> Result<Vec<QueuedEntry>> {
+        Ok(self.outbox.lock().map_err(|e| anyhow!("{e}"))?.clone())
+    }
+
+    async fn remove_outbox(&self, key: &str) -> Result<()> {
+        let mut outbox = self.outbox.lock().map_err(|e| anyhow!("{e}"))?;
+        outbox.retain(|q| q.key != key);
+        Ok(())
+    }
+}
+
+impl MemoryStore {
+    fn claim(&self, key: String, ttl_secs: u64) -> Result<bool> {
+        let mut claims = self.claims.lock().map_err(|e| anyhow!("{e}"))?;
+        let now = Utc::now();
+        // Dropping every expired claim here keeps seen ids from piling up until restart.
+        claims.retain(|_, expires| *expires > now);
+        if claims.contains_key(&key) {
+            return Ok(false);
+        }
+        claims.insert(key, now + Duration::seconds(ttl_secs as i64));
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use anyhow::Result;
+    use aws_sdk_dynamodb::{
+        Client,
+        operation::{
+            delete_item::DeleteItemOutput,
+            get_item::GetItemOutput,
+            put_item::{PutItemError, PutItemOutput},
+            query::QueryOutput,
+            update_item::UpdateItemOutput,
+        },
+        types::{AttributeValue, ReturnValue, error::ConditionalCheckFailedException},
+    };
+    use aws_smithy_mocks::{Rule, RuleMode, mock, mock_client};
+
+    use crate::state::{
+        ConfigWrite, DynamoStore, MemoryStore, Retention, StateStore, StoredConfig, mentions_any,
+        merge_recent,
+    };
+
+    #[tokio::test]
+    async fn dynamo_store_reads_last_round_robin_mod() -> Result<()> {
+        let get_rule: Rule = mock!(Client::get_item)
+            .match_requests(|r| {
+                r.table_name() == Some("state-table")
+                    && r.key().and_then(|k| k.get("pk"))
+                        == Some(&AttributeValue::S("round_robin#default".into()))
+            })
+            .then_output(|| {
+                GetItemOutput::builder()
+                    .item("pk", AttributeValue::S("round_robin#default".into()))
+                    .item("last_mod", AttributeValue::S("Jane".into()))
+                    .build()
+            });
+
+        let store = DynamoStore {
+            client: mock_client!(aws_sdk_dynamodb, [&get_rule]),
+            table_name: "state-table".into(),
+            log_table_name: "state-table-log".into(),
+            retention: Retention::default(),
+        };
+
+        assert_eq!(
+            store.last_round_robin("default").await?.as_deref(),
+            Some("Jane")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dynamo_store_advances_round_robin_only_from_the_expected_mod() -> Result<()> {
+        let put_rule: Rule = mock!(Client::put_item)
+            .match_requests(|r| {
+                r.item().and_then(|i| i.get("last_mod")) == Some(&AttributeValue::S("Alex".into()))
+                    && r.condition_expression() == Some("#last_mod = :expected")
+                    && r.expression_attribute_values()
+                        .and_then(|v| v.get(":expected"))
+                        == Some(&AttributeValue::S("Jane".into()))
+            })
+            .then_output(|| PutItemOutput::builder().build());
+        let moved_rule: Rule = mock!(Client::put_item).then_error(|| {
+            PutItemError::ConditionalCheckFailedException(
+                ConditionalCheckFailedException::builder().build(),
+            )
+        });
+
+        let store = DynamoStore {
+            client: mock_client!(
+                aws_sdk_dynamodb,
+                RuleMode::Sequential,
+                [&put_rule, &moved_rule]
+            ),
+            table_name: "state-table".into(),
+            log_table_name: "state-table-log".into(),
+            retention: Retention::default(),
+        };
+
+        assert!(
+            store
+                .advance_round_robin("default", Some("Jane"), "Alex")
+                .await?
+        );
+        assert!(
+            !store
+                .advance_round_robin("default", Some("Jane"), "Alex")
+                .await?
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn memory_store_advances_round_robin_only_from_the_expected_mod() -> Result<()> {
+        let store = MemoryStore::default();
+
+        assert_eq!(store.last_round_robin("default").await?, None);
+        assert!(store.advance_round_robin("default", None, "John").await?);
+        assert!(!store.advance_round_robin("default", None, "Jane").await?);
+        assert!(
+            store
+                .advance_round_robin("default", Some("John"), "Jane")
+                .await?
+        );
+        assert_eq!(
+            store.last_round_robin("default").await?.as_deref(),
+            Some("Jane")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn mentions_any_matches_whole_names_ignoring_case() {
+        let names = vec!["Clucky".to_string()];
+
+        assert!(mentions_any(r#"{"message":"clucky won!"}"#, &names));
+        assert!(!mentions_any(r#"{"message":"clucky_two won!"}"#, &names));
+        assert!(!mentions_any(
+            r#"{"message":"Jane won!"}"#,
+            &[String::new()]
+        ));
+    }
+
+    #[test]
+    fn merge_recent_puts_new_winners_first_and_trims_to_window() {
+        let recent = vec!["Jane".to_string(), "Alex".to_string(), "Krish".to_string()];
+
+        let merged = merge_recent(recent, &["Alex".to_string()], 3);
+
+        assert_eq!(merged, vec!["Alex", "Jane", "Krish"]);
+    }
+
+    fn recent_winners_item(version: u64, names: &[&str]) -> GetItemOutput {
+        let names = names
+            .iter()
+            .map(|n| AttributeValue::S(n.to_string()))
+            .collect();
+        GetItemOutput::builder()
+            .item("version", AttributeValue::N(version.to_string()))
+            .item(
+                "groups",
+                AttributeValue::M(HashMap::from([(
+                    "default".to_string(),
+                    AttributeValue::L(names),
+                )])),
+            )
+            .build()
+    }
+
+    #[tokio::test]
+    async fn dynamo_store_retries_recent_winners_on_what_got_in_first() -> Result<()> {
+        let first_get: Rule =
+            mock!(Client::get_item).then_output(|| recent_winners_item(3, &["Jane"]));
+        let conflict: Rule = mock!(Client::put_item).then_error(|| {
+            PutItemError::ConditionalCheckFailedException(
+                ConditionalCheckFailedException::builder().build(),
+            )
+        });
+        let second_get: Rule =
+            mock!(Client::get_item).then_output(|| recent_winners_item(4, &["Kim"]));
+        let put_rule: Rule = mock!(Client::put_item)
+            .match_requests(|r| {
+                let recent = r
+                    .item()
+                    .and_then(|i| i.get("groups"))
+                    .and_then(|g| g.as_m().ok())
+                    .and_then(|g| g.get("default"));
+                recent
+                    == Some(&AttributeValue::L(vec![
+                        AttributeValue::S("John".into()),
+                        AttributeValue::S("Kim".into()),
+                    ]))
+                    && r.expression_attribute_values()
+                        .and_then(|v| v.get(":expected"))
+                        == Some(&AttributeValue::N("4".into()))
+            })
+            .then_output(|| PutItemOutput::builder().build());
+
+        let store = DynamoStore {
+            client: mock_client!(
+                aws_sdk_dynamodb,
+                RuleMode::Sequential,
+                [&first_get, &conflict, &second_get, &put_rule]
+            ),
+            table_name: "state-table".into(),
+            log_table_name: "state-table-log".into(),
+            retention: Retention::default(),
+        };
+
+        store
+            .push_recent_winners("default", &["John".to_string()], 2)
+            .await?;
+
+        assert_eq!(put_rule.num_calls(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn memory_store_keeps_recent_winners_per_group() -> Result<()> {
+        let store = MemoryStore::default();
+
+        store
+            .push_recent_winners("default", &["John".to_string()], 2)
+            .await?;
+        store
+            .push_recent_winners("cracker", &["Jane".to_string()], 2)
+            .await?;
+
+        assert_eq!(store.recent_winners("default").await?, vec!["John"]);
+        assert_eq!(store.recent_winners("cracker").await?, vec!["Jane"]);
+        assert_eq!(store.forget_winners(&["john".to_string()]).await?, 1);
+        assert!(store.recent_winners("default").await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dynamo_store_reads_scenario_counts() -> Result<()> {
+        let get_rule: Rule = mock!(Client::get_item)
+            .match_requests(|r| {
+                r.key().and_then(|k| k.get("pk"))
+                    == Some(&AttributeValue::S("scenario_stats#2026-W42".into()))
+            })
+            .then_output(|| {
+                GetItemOutput::builder()
+                    .item(
+                        "counts",
+                        AttributeValue::M(
+                            [("cracker-trip".to_string(), AttributeValue::N("4".into()))].into(),
+                        ),
+                    )
+                    .build()
+            });
+
+        let store = DynamoStore {
+            client: mock_client!(aws_sdk_dynamodb, [&get_rule]),
+            table_name: "state-table".into(),
+            log_table_name: "state-table-log".into(),
+            retention: Retention::default(),
+        };
+
+        let counts = store.scenario_counts("2026-W42").await?;
+
+        assert_eq!(counts.get("cracker-trip"), Some(&4));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dynamo_store_records_scenario() -> Result<()> {
+        let update_rule: Rule = mock!(Client::update_item)
+            .match_requests(|r| {
+                r.key().and_then(|k| k.get("pk"))
+                    == Some(&AttributeValue::S("scenario_stats#2026-W42".into()))
+            })
+            .then_output(|| UpdateItemOutput::builder().build());
+
+        let store = DynamoStore {
+            client: mock_client!(aws_sdk_dynamodb, RuleMode::MatchAny, [&update_rule]),
+            table_name: "state-table".into(),
+            log_table_name: "state-table-log".into(),
+            retention: Retention::default(),
+        };
+
+        store.record_scenario("2026-W42", "cracker-trip").await?;
+
+        assert_eq!(update_rule.num_calls(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dynamo_store_records_all_wins_in_one_write() -> Result<()> {
+        let parent_rule: Rule = mock!(Client::update_item)
+            .match_requests(|r| {
+                r.update_expression() == Some("SET #counts = if_not_exists(#counts, :empty)")
+            })
+            .then_output(|| UpdateItemOutput::builder().build());
+        let wins_rule: Rule = mock!(Client::update_item)
+            .match_requests(|r| {
+                let values = r.expression_attribute_values().unwrap();
+                r.key().and_then(|k| k.get("pk")) == Some(&AttributeValue::S("mod_wins".into()))
+                    && r.expression_attribute_names().unwrap()["#f0"] == "John"
+                    && values[":n0"] == AttributeValue::N("2".into())
+                    && values[":n1"] == AttributeValue::N("1".into())
+            })
+            .then_output(|| UpdateItemOutput::builder().build());
+
+        let store = DynamoStore {
+            client: mock_client!(
+                aws_sdk_dynamodb,
+                RuleMode::MatchAny,
+                [&parent_rule, &wins_rule]
+            ),
+            table_name: "state-table".into(),
+            log_table_name: "state-table-log".into(),
+            retention: Retention::default(),
+        };
+
+        store
+            .record_wins(&["John".into(), "Jane".into(), "John".into()])
+            .await?;
+
+        assert_eq!(parent_rule.num_calls(), 1);
+        assert_eq!(wins_rule.num_calls(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn memory_store_counts_scenarios_per_week() -> Result<()> {
+        let store = MemoryStore::default();
+
+        store.record_scenario("2026-W42", "cracker-trip").await?;
+        store.record_scenario("2026-W42", "cracker-trip").await?;
+        store.record_scenario("2026-W43", "cracker-trip").await?;
+
+        let counts = store.scenario_counts("2026-W42").await?;
+        assert_eq!(counts.get("cracker-trip"), Some(&2));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dynamo_store_saves_config_when_version_matches() -> Result<()> {
+        let put_rule: Rule = mock!(Client::put_item)
+            .match_requests(|r| {
+                r.condition_expression() == Some("#version = :expected")
+                    && r.expression_attribute_values()
+                        .and_then(|v| v.get(":expected"))
+                        == Some(&AttributeValue::N("3".into()))
+                    && r.item().and_then(|i| i.get("version"))
+                        == Some(&AttributeValue::N("4".into()))
+            })
+            .then_output(|| PutItemOutput::builder().build());
+
+        let store = DynamoStore {
+            client: mock_client!(aws_sdk_dynamodb, [&put_rule]),
+            table_name: "state-table".into(),
+            log_table_name: "state-table-log".into(),
+            retention: Retention::default(),
+        };
+
+        let result = store.put_config(r#"{"scenarios":[],"mods":[]}"#, 3).await?;
+
+        assert_eq!(result, ConfigWrite::Saved(4));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dynamo_store_returns_current_config_on_conflict() -> Result<()> {
+        let put_rule: Rule = mock!(Client::put_item).then_error(|| {
+            PutItemError::ConditionalCheckFailedException(
+                ConditionalCheckFailedException::builder().build(),
+            )
+        });
+        let get_rule: Rule = mock!(Client::get_item).then_output(|| {
+            GetItemOutput::builder()
+                .item("version", AttributeValue::N("5".into()))
+                .item("body", AttributeValue::S("{}".into()))
+                .build()
+        });
+
+        let store = DynamoStore {
+            client: mock_client!(aws_sdk_dynamodb, RuleMode::MatchAny, [&put_rule, &get_rule]),
+            table_name: "state-table".into(),
+            log_table_name: "state-table-log".into(),
+            retention: Retention::default(),
+        };
+
+        let result = store.put_config("{}", 3).await?;
+
+        assert_eq!(
+            result,
+            ConfigWrite::Conflict(StoredConfig {
+                version: 5,
+                body: "{}".into()
+            })
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn memory_store_rejects_stale_config_writes() -> Result<()> {
+        let store = MemoryStore::default();
+
+        assert_eq!(store.put_config("first", 0).await?, ConfigWrite::Saved(1));
+        assert_eq!(store.put_config("second", 1).await?, ConfigWrite::Saved(2));
+        assert_eq!(
+            store.put_config("stale", 1).await?,
+            ConfigWrite::Conflict(StoredConfig {
+                version: 2,
+                body: "second".into()
+            })
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dynamo_store_increments_counter() -> Result<()> {
+        let update_rule: Rule = mock!(Client::update_item)
+            .match_requests(|r| {
+                r.key().and_then(|k| k.get("pk"))
+                    == Some(&AttributeValue::S("counter#budget#reward-1".into()))
+                    && r.update_expression() == Some("ADD #count :one")
+            })
+            .then_output(|| {
+                UpdateItemOutput::builder()
+                    .attributes("count", AttributeValue::N("7".into()))
+                    .build()
+            });
+
+        let store = DynamoStore {
+            client: mock_client!(aws_sdk_dynamodb, [&update_rule]),
+            table_name: "state-table".into(),
+            log_table_name: "state-table-log".into(),
+            retention: Retention::default(),
+        };
+
+        assert_eq!(store.increment_counter("budget#reward-1", None).await?, 7);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dynamo_store_keeps_opted_out_mods_in_a_set() -> Result<()> {
+        let opt_out_rule: Rule = mock!(Client::update_item)
+            .match_requests(|r| {
+                r.key().and_then(|k| k.get("pk")) == Some(&AttributeValue::S("opted_out".into()))
+                    && r.update_expression() == Some("DELETE #names :name")
+                    && r.expression_attribute_values().and_then(|v| v.get(":name"))
+                        == Some(&AttributeValue::Ss(vec!["john".into()]))
+            })
+            .then_output(|| UpdateItemOutput::builder().build());
+        let get_rule: Rule = mock!(Client::get_item)
+            .match_requests(|r| {
+                r.key().and_then(|k| k.get("pk")) == Some(&AttributeValue::S("opted_out".into()))
+            })
+            .then_output(|| {
+                GetItemOutput::builder()
+                    .item("pk", AttributeValue::S("opted_out".into()))
+                    .item(
+                        "names",
+                        AttributeValue::Ss(vec!["jane".into(), "alex".into()]),
+                    )
+                    .build()
+            });
+
+        let store = DynamoStore {
+            client: mock_client!(
+                aws_sdk_dynamodb,
+                RuleMode::MatchAny,
+                [&opt_out_rule, &get_rule]
+            ),
+            table_name: "state-table".into(),
+            log_table_name: "state-table-log".into(),
+            retention: Retention::default(),
+        };
+
+        store.set_opted_out("john", false).await?;
+        assert_eq!(store.opted_out_mods().await?, vec!["alex", "jane"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dynamo_store_appends_and_reads_audit_entries() -> Result<()> {
+        let append_rule: Rule = mock!(Client::put_item)
+            .match_requests(|r| {
+                r.table_name() == Some("state-table-log")
+                    && r.item().and_then(|i| i.get("pk"))
+                        == Some(&AttributeValue::S("audit#2026-10-16".into()))
+                    && r.item()
+                        .and_then(|i| i.get("sk"))
+                        .and_then(|sk| sk.as_s().ok())
+                        .is_some_and(|sk| sk.contains('#'))
+                    && r.item().and_then(|i| i.get("entry"))
+                        == Some(&AttributeValue::S(r#"{"message":"hi"}"#.into()))
+                    && r.item().and_then(|i| i.get("expires_at")).is_some()
+            })
+            .then_output(|| PutItemOutput::builder().build());
+        let read_rule: Rule = mock!(Client::query)
+            .match_requests(|r| {
+                r.table_name() == Some("state-table-log")
+                    && r.expression_attribute_values().and_then(|v| v.get(":pk"))
+                        == Some(&AttributeValue::S("audit#2026-10-16".into()))
+            })
+            .then_output(|| {
+                let item = |sk: &str, entry: &str| {
+                    HashMap::from([
+                        (
+                            "pk".to_string(),
+                            AttributeValue::S("audit#2026-10-16".into()),
+                        ),
+                        ("sk".to_string(), AttributeValue::S(sk.into())),
+                        ("entry".to_string(), AttributeValue::S(entry.into())),
+                    ])
+                };
+                QueryOutput::builder()
+                    .items(item(
+                        "2026-10-16T18:00:00.000000Z#00000001",
+                        r#"{"message":"hi"}"#,
+                    ))
+                    .items(item(
+                        "2026-10-16T18:00:01.000000Z#00000002",
+                        r#"{"message":"bok"}"#,
+                    ))
+                    .build()
+            });
+
+        let store = DynamoStore {
+            client: mock_client!(
+                aws_sdk_dynamodb,
+                RuleMode::MatchAny,
+                [&append_rule, &read_rule]
+            ),
+            table_name: "state-table".into(),
+            log_table_name: "state-table-log".into(),
+            retention: Retention::default(),
+        };
+
+        store
+            .append_audit("2026-10-16", r#"{"message":"hi"}"#)
+            .await?;
+
+        assert_eq!(
+            store.audit_entries("2026-10-16").await?,
+            vec![
+                r#"{"message":"hi"}"#.to_string(),
+                r#"{"message":"bok"}"#.to_string()
+            ]
+        );
+        assert_eq!(append_rule.num_calls(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dynamo_store_keeps_outbox_entries_until_each_is_removed() -> Result<()> {
+        let push_rule: Rule = mock!(Client::put_item)
+            .match_requests(|r| {
+                r.table_name() == Some("state-table-log")
+                    && r.item().and_then(|i| i.get("pk"))
+                        == Some(&AttributeValue::S("outbox".into()))
+                    && r.item().and_then(|i| i.get("expires_at")).is_none()
+            })
+            .then_output(|| PutItemOutput::builder().build());
+        let read_rule: Rule = mock!(Client::query).then_output(|| {
+            QueryOutput::builder()
+                .items(HashMap::from([
+                    ("pk".to_string(), AttributeValue::S("outbox".into())),
+                    ("sk".to_string(), AttributeValue::S("sk-1".into())),
+                    ("entry".to_string(), AttributeValue::S("retry".into())),
+                ]))
+                .build()
+        });
+        let remove_rule: Rule = mock!(Client::delete_item)
+            .match_requests(|r| {
+                r.table_name() == Some("state-table-log")
+                    && r.key().and_then(|k| k.get("pk"))
+                        == Some(&AttributeValue::S("outbox".into()))
+                    && r.key().and_then(|k| k.get("sk")) == Some(&AttributeValue::S("sk-1".into()))
+            })
+            .then_output(|| DeleteItemOutput::builder().build());
+
+        let store = DynamoStore {
+            client: mock_client!(
+                aws_sdk_dynamodb,
+                RuleMode::MatchAny,
+                [&push_rule, &read_rule, &remove_rule]
+            ),
+            table_name: "state-table".into(),
+            log_table_name: "state-table-log".into(),
+            retention: Retention::default(),
+        };
+
+        store.push_outbox("retry").await?;
+        let outbox = store.outbox().await?;
+        store.remove_outbox(&outbox[0].key).await?;
+
+        assert_eq!(outbox.len(), 1);
+        assert_eq!(outbox[0].entry, "retry");
+        assert_eq!(push_rule.num_calls(), 1);
+        assert_eq!(remove_rule.num_calls(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dynamo_store_keeps_offline_queue_until_each_entry_is_removed() -> Result<()> {
+        let read_rule: Rule = mock!(Client::query)
+            .match_requests(|r| {
+                r.table_name() == Some("state-table-log")
+                    && r.expression_attribute_values().and_then(|v| v.get(":pk"))
+                        == Some(&AttributeValue::S("offline_queue".into()))
+            })
+            .then_output(|| {
+                QueryOutput::builder()
+                    .items(HashMap::from([
+                        ("sk".to_string(), AttributeValue::S("sk-1".into())),
+                        ("entry".to_string(), AttributeValue::S("first".into())),
+                    ]))
+                    .items(HashMap::from([
+                        ("sk".to_string(), AttributeValue::S("sk-2".into())),
+                        ("entry".to_string(), AttributeValue::S("second".into())),
+                    ]))
+                    .build()
+            });
+        let remove_rule: Rule = mock!(Client::delete_item)
+            .match_requests(|r| {
+                r.table_name() == Some("state-table-log")
+                    && r.key().and_then(|k| k.get("pk"))
+                        == Some(&AttributeValue::S("offline_queue".into()))
+                    && r.key().and_then(|k| k.get("sk")) == Some(&AttributeValue::S("sk-1".into()))
+            })
+            .then_output(|| DeleteItemOutput::builder().build());
+
+        let store = DynamoStore {
+            client: mock_client!(
+                aws_sdk_dynamodb,
+                RuleMode::MatchAny,
+                [&read_rule, &remove_rule]
+            ),
+            table_name: "state-table".into(),
+            log_table_name: "state-table-log".into(),
+            retention: Retention::default(),
+        };
+
+        let queued = store.offline_queue().await?;
+        store.remove_offline(&queued[0].key).await?;
+
+        assert_eq!(
+            queued.iter().map(|q| q.entry.as_str()).collect::<Vec<_>>(),
+            vec!["first", "second"]
+        );
+        assert_eq!(remove_rule.num_calls(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dynamo_store_marks_seen_only_once() -> Result<()> {
+        let first_rule: Rule = mock!(Client::put_item)
+            .match_requests(|r| {
+                r.item().and_then(|i| i.get("pk")) == Some(&AttributeValue::S("seen#msg-1".into()))
+                    && r.item().and_then(|i| i.get("expires_at")).is_some()
+            })
+            .then_output(|| PutItemOutput::builder().build());
+        let again_rule: Rule = mock!(Client::put_item).then_error(|| {
+            PutItemError::ConditionalCheckFailedException(
+                ConditionalCheckFailedException::builder().build(),
+            )
+        });
+
+        let store = DynamoStore {
+            client: mock_client!(
+                aws_sdk_dynamodb,
+                RuleMode::Sequential,
+                [&first_rule, &again_rule]
+            ),
+            table_name: "state-table".into(),
+            log_table_name: "state-table-log".into(),
+            retention: Retention::default(),
+        };
+
+        assert!(store.mark_seen("msg-1").await?);
+        assert!(!store.mark_seen("msg-1").await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn memory_store_cooldowns_run_out() -> Result<()> {
+        let store = MemoryStore::default();
+
+        assert!(store.start_cooldown("!fedboard", 60).await?);
+        assert!(!store.start_cooldown("!fedboard", 60).await?);
+        assert!(store.start_cooldown("!quote", 0).await?);
+        assert!(store.start_cooldown("!quote", 0).await?);
+        assert!(store.mark_seen("!fedboard").await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn memory_store_drops_expired_counters() -> Result<()> {
+        let store = MemoryStore::default();
+
+        assert_eq!(
+            store.increment_counter("burst#reward-1#1", Some(0)).await?,
+            1
+        );
+        assert_eq!(store.increment_counter("budget#reward-1", None).await?, 1);
+        assert_eq!(
+            store
+                .increment_counter("burst#reward-1#2", Some(60))
+                .await?,
+            1
+        );
+        assert_eq!(
+            store
+                .increment_counter("burst#reward-1#2", Some(60))
+                .await?,
+            2
+        );
+
+        let counters = store.counters.lock().unwrap();
+        assert!(!counters.contains_key("burst#reward-1#1"));
+        assert_eq!(counters.len(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn memory_store_drops_expired_claims() -> Result<()> {
+        let store = MemoryStore::default().with_retention(Retention {
+            seen_secs: 0,
+            ..Retention::default()
+        });
+
+        assert!(store.mark_seen("msg-1").await?);
+        assert!(store.start_cooldown("!fedboard", 60).await?);
+
+        let claims = store.claims.lock().unwrap();
+        assert!(!claims.contains_key("seen#msg-1"));
+        assert_eq!(claims.len(), 1);
+        Ok(())
+    }
+}
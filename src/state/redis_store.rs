@@ -0,0 +1,680 @@
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use redis::{Cmd, aio::ConnectionManager, cmd};
+use tokio::sync::OnceCell;
+
+use super::{ConfigWrite, QueuedEntry, Retention, StateStore, StoredConfig, mentions_any};
+
+/// Saves `ARGV[2]` as version `ARGV[1] + 1` of the config hash, as long as its version is
+/// still `ARGV[1]`. Returns the stored version on a conflict, nil once saved.
+const PUT_CONFIG_SCRIPT: &str = r#"
+local current = redis.call('HGET', KEYS[1], 'version') or '0'
+if current ~= ARGV[1] then
+    return current
+end
+redis.call('HSET', KEYS[1], 'version', tostring(tonumber(ARGV[1]) + 1), 'body', ARGV[2])
+return false
+"#;
+
+/// Sets `KEYS[1]` to `ARGV[2]` if it's still `ARGV[1]`, an empty `ARGV[1]` standing for unset.
+/// Returns whether it was set.
+const ADVANCE_SCRIPT: &str = r#"
+local current = redis.call('GET', KEYS[1]) or ''
+if current ~= ARGV[1] then
+    return 0
+end
+redis.call('SET', KEYS[1], ARGV[2])
+return 1
+"#;
+
+/// Adds one to the counter `KEYS[1]`, which expires `ARGV[1]` seconds after it was created
+/// when that's set. Returns the new count.
+const INCREMENT_SCRIPT: &str = r#"
+local count = redis.call('INCR', KEYS[1])
+if count == 1 and ARGV[1] ~= '' then
+    redis.call('EXPIRE', KEYS[1], ARGV[1])
+end
+return count
+"#;
+
+/// Moves the winners `ARGV[3..]` to the front of the recent winners of group `ARGV[1]`,
+/// keeping its first `ARGV[2]` entries. Each group's winners are a JSON list in the hash
+/// `KEYS[1]`.
+const PUSH_RECENT_SCRIPT: &str = r#"
+local winners = {}
+local merged = {}
+for i = 3, #ARGV do
+    winners[ARGV[i]] = true
+    table.insert(merged, ARGV[i])
+end
+for _, winner in ipairs(cjson.decode(redis.call('HGET', KEYS[1], ARGV[1]) or '[]')) do
+    if not winners[winner] then
+        table.insert(merged, winner)
+    end
+end
+local kept = {}
+for i = 1, math.min(tonumber(ARGV[2]), #merged) do
+    kept[i] = merged[i]
+end
+if #kept == 0 then
+    redis.call('HDEL', KEYS[1], ARGV[1])
+else
+    redis.call('HSET', KEYS[1], ARGV[1], cjson.encode(kept))
+end
+"#;
+
+/// Drops the recent winners of every group matching one of `ARGV`, ignoring ASCII case.
+/// Returns how many were dropped.
+const FORGET_RECENT_SCRIPT: &str = r#"
+local names = {}
+for i = 1, #ARGV do
+    names[string.lower(ARGV[i])] = true
+end
+local forgotten = 0
+local groups = redis.call('HGETALL', KEYS[1])
+for i = 1, #groups, 2 do
+    local kept = {}
+    local dropped = 0
+    for _, winner in ipairs(cjson.decode(groups[i + 1])) do
+        if names[string.lower(winner)] then
+            dropped = dropped + 1
+        else
+            table.insert(kept, winner)
+        end
+    end
+    if #kept == 0 then
+        redis.call('HDEL', KEYS[1], groups[i])
+    elseif dropped > 0 then
+        redis.call('HSET', KEYS[1], groups[i], cjson.encode(kept))
+    end
+    forgotten = forgotten + dropped
+end
+return forgotten
+"#;
+
+/// Keeps the state in Redis, every key starting with `prefix`. Counters and stats are hashes,
+/// logs and queues are lists, and seen ids and cooldowns are keys that Redis expires itself.
+pub struct RedisStore {
+    client: redis::Client,
+    conn: OnceCell<ConnectionManager>,
+    prefix: String,
+    retention: Retention,
+}
+
+impl RedisStore {
+    /// Connects to `url` (e.g. `redis://cache.example:6379`) on first use.
+    pub fn new(url: &str, prefix: &str) -> Result<RedisStore> {
+        Ok(RedisStore {
+            client: redis::Client::open(url)
+                .map_err(|e| anyhow!("Invalid Redis URL {url}: {e}"))?,
+            conn: OnceCell::new(),
+            prefix: prefix.to_string(),
+            retention: Retention::default(),
+        })
+    }
+
+    pub fn with_retention(self, retention: Retention) -> Self {
+        RedisStore { retention, ..self }
+    }
+
+    fn key(&self, name: &str) -> String {
+        format!("{}{name}", self.prefix)
+    }
+
+    async fn conn(&self) -> Result<ConnectionManager> {
+        self.conn
+            .get_or_try_init(|| self.client.get_connection_manager())
+            .await
+            .cloned()
+            .map_err(|e| anyhow!("Failed to connect to Redis: {e}"))
+    }
+
+    async fn query<T: redis::FromRedisValue>(&self, cmd: &Cmd) -> Result<T> {
+        let mut conn = self.conn().await?;
+        cmd.query_async(&mut conn).await.map_err(|e| anyhow!("{e}"))
+    }
+
+    async fn push(&self, key: &str, entry: &str) -> Result<()> {
+        self.query(cmd("RPUSH").arg(self.key(key)).arg(entry)).await
+    }
+
+    /// Appends `entry` and has Redis delete the whole list `ttl_secs` after this append.
+    async fn push_expiring(&self, key: &str, entry: &str, ttl_secs: u64) -> Result<()> {
+        let key = self.key(key);
+        let mut conn = self.conn().await?;
+        redis::pipe()
+            .atomic()
+            .cmd("RPUSH")
+            .arg(&key)
+            .arg(entry)
+            .ignore()
+            .cmd("EXPIRE")
+            .arg(&key)
+            .arg(ttl_secs.max(1))
+            .ignore()
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| anyhow!("{e}"))
+    }
+
+    async fn list(&self, key: &str) -> Result<Vec<String>> {
+        self.query(cmd("LRANGE").arg(self.key(key)).arg(0).arg(-1))
+            .await
+    }
+
+    /// Removes the entries of the list `key` that mention one of `names`, returning how many.
+    async fn forget_in_list(&self, key: &str, names: &[String]) -> Result<usize> {
+        let mut forgotten = 0;
+        for entry in self.list(key).await? {
+            if mentions_any(&entry, names) {
+                let removed: usize = self
+                    .query(cmd("LREM").arg(self.key(key)).arg(0).arg(&entry))
+                    .await?;
+                forgotten += removed;
+            }
+        }
+        Ok(forgotten)
+    }
+
+    /// The list `key` as entries keyed by themselves. Each queued entry is unique through the
+    /// time it was queued at, so they're removed by value.
+    async fn queued(&self, key: &str) -> Result<Vec<QueuedEntry>> {
+        Ok(self
+            .list(key)
+            .await?
+            .into_iter()
+            .map(|entry| QueuedEntry {
+                key: entry.clone(),
+                entry,
+            })
+            .collect())
+    }
+
+    /// Deletes one occurrence of `entry` from the list `key`.
+    async fn remove(&self, key: &str, entry: &str) -> Result<()> {
+        self.query(cmd("LREM").arg(self.key(key)).arg(1).arg(entry))
+            .await
+    }
+
+    async fn counts(&self, key: &str) -> Result<HashMap<String, u64>> {
+        self.query(cmd("HGETALL").arg(self.key(key))).await
+    }
+
+    async fn increment(&self, key: &str, field: &str) -> Result<()> {
+        self.query(cmd("HINCRBY").arg(self.key(key)).arg(field).arg(1))
+            .await
+    }
+
+    /// Adds one to each of `fields` of the hash `key` in one round trip.
+    async fn increment_all(&self, key: &str, fields: &[String]) -> Result<()> {
+        let key = self.key(key);
+        let mut pipe = redis::pipe();
+        for field in fields {
+            pipe.cmd("HINCRBY").arg(&key).arg(field).arg(1).ignore();
+        }
+        let mut conn = self.conn().await?;
+        pipe.query_async(&mut conn)
+            .await
+            .map_err(|e| anyhow!("{e}"))
+    }
+
+    /// Sets `key` for `ttl_secs` unless it's already set, returning whether it was.
+    async fn claim(&self, key: &str, ttl_secs: u64) -> Result<bool> {
+        // Redis rejects a zero expiry, and a zero-length claim never blocks anything anyway.
+        if ttl_secs == 0 {
+            return Ok(true);
+        }
+        let set: Option<String> = self
+            .query(
+                cmd("SET")
+                    .arg(self.key(key))
+                    .arg(1)
+                    .arg("NX")
+                    .arg("EX")
+                    .arg(ttl_secs),
+            )
+            .await?;
+        Ok(set.is_some())
+    }
+}
+
+#[async_trait]
+impl StateStore for RedisStore {
+    async fn last_round_robin(&self, group: &str) -> Result<Option<String>> {
+        self.query(cmd("GET").arg(self.key(&format!("round_robin:{group}"))))
+            .await
+            .map_err(|e| anyhow!("Failed to read round-robin for {group}: {e}"))
+    }
+
+    async fn advance_round_robin(
+        &self,
+        group: &str,
+        expected: Option<&str>,
+        next: &str,
+    ) -> Result<bool> {
+        self.query(
+            cmd("EVAL")
+                .arg(ADVANCE_SCRIPT)
+                .arg(1)
+                .arg(self.key(&format!("round_robin:{group}")))
+                .arg(expected.unwrap_or_default())
+                .arg(next),
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to store round-robin for {group}: {e}"))
+    }
+
+    async fn recent_winners(&self, group: &str) -> Result<Vec<String>> {
+        let recent: Option<String> = self
+            .query(
+                cmd("HGET")
+                    .arg(self.key("recent_winners_by_group"))
+                    .arg(group),
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to read recent winners: {e}"))?;
+        match recent {
+            Some(recent) => Ok(serde_json::from_str(&recent)?),
+            None => Ok(vec![]),
+        }
+    }
+
+    async fn push_recent_winners(
+        &self,
+        group: &str,
+        winners: &[String],
+        window: usize,
+    ) -> Result<()> {
+        // Done in a script, so winners pushed at the same time aren't lost to each other.
+        self.query::<()>(
+            cmd("EVAL")
+                .arg(PUSH_RECENT_SCRIPT)
+                .arg(1)
+                .arg(self.key("recent_winners_by_group"))
+                .arg(group)
+                .arg(window)
+                .arg(winners),
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to store recent winners: {e}"))
+    }
+
+    async fn forget_winners(&self, names: &[String]) -> Result<usize> {
+        self.query(
+            cmd("EVAL")
+                .arg(FORGET_RECENT_SCRIPT)
+                .arg(1)
+                .arg(self.key("recent_winners_by_group"))
+                .arg(names),
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to forget recent winners: {e}"))
+    }
+
+    async fn record_scenario(&self, week: &str, scenario: &str) -> Result<()> {
+        self.increment(&format!("scenarios:{week}"), scenario)
+            .await
+            .map_err(|e| anyhow!("Failed to record scenario for {week}: {e}"))
+    }
+
+    async fn scenario_counts(&self, week: &str) -> Result<HashMap<String, u64>> {
+        self.counts(&format!("scenarios:{week}"))
+            .await
+            .map_err(|e| anyhow!("Failed to read scenario counts for {week}: {e}"))
+    }
+
+    async fn record_wins(&self, mods: &[String]) -> Result<()> {
+        self.increment_all("wins", mods)
+            .await
+            .map_err(|e| anyhow!("Failed to record wins: {e}"))
+    }
+
+    async fn win_counts(&self) -> Result<HashMap<String, u64>> {
+        self.counts("wins")
+            .await
+            .map_err(|e| anyhow!("Failed to read mod wins: {e}"))
+    }
+
+    async fn record_month_wins(&self, month: &str, mods: &[String]) -> Result<()> {
+        self.increment_all(&format!("wins:{month}"), mods)
+            .await
+            .map_err(|e| anyhow!("Failed to record {month} wins: {e}"))
+    }
+
+    async fn month_win_counts(&self, month: &str) -> Result<HashMap<String, u64>> {
+        self.counts(&format!("wins:{month}"))
+            .await
+            .map_err(|e| anyhow!("Failed to read mod wins for {month}: {e}"))
+    }
+
+    async fn record_variant(&self, experiment: &str, variant: &str, metric: &str) -> Result<()> {
+        self.increment(
+            &format!("variants:{experiment}"),
+            &format!("{variant}/{metric}"),
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to record {metric} for {experiment}: {e}"))
+    }
+
+    async fn variant_counts(&self, experiment: &str) -> Result<HashMap<String, u64>> {
+        self.counts(&format!("variants:{experiment}"))
+            .await
+            .map_err(|e| anyhow!("Failed to read counts for {experiment}: {e}"))
+    }
+
+    async fn channel_info(&self) -> Result<Option<String>> {
+        self.query(cmd("GET").arg(self.key("channel_info")))
+            .await
+            .map_err(|e| anyhow!("Failed to read channel info: {e}"))
+    }
+
+    async fn set_channel_info(&self, entry: &str) -> Result<()> {
+        self.query(cmd("SET").arg(self.key("channel_info")).arg(entry))
+            .await
+            .map_err(|e| anyhow!("Failed to store channel info: {e}"))
+    }
+
+    async fn engagement(&self, user_id: &str) -> Result<Option<String>> {
+        self.query(cmd("GET").arg(self.key(&format!("engagement:{user_id}"))))
+            .await
+            .map_err(|e| anyhow!("Failed to read engagement of {user_id}: {e}"))
+    }
+
+    async fn set_engagement(&self, user_id: &str, entry: &str, ttl_secs: u64) -> Result<()> {
+        self.query(
+            cmd("SET")
+                .arg(self.key(&format!("engagement:{user_id}")))
+                .arg(entry)
+                .arg("EX")
+                .arg(ttl_secs.max(1)),
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to store engagement of {user_id}: {e}"))
+    }
+
+    async fn opted_out_mods(&self) -> Result<Vec<String>> {
+        let mut names: Vec<String> = self
+            .query(cmd("SMEMBERS").arg(self.key("opted_out")))
+            .await
+            .map_err(|e| anyhow!("Failed to read opted out mods: {e}"))?;
+        names.sort();
+        Ok(names)
+    }
+
+    async fn set_opted_out(&self, name: &str, opted_out: bool) -> Result<()> {
+        let command = if opted_out { "SADD" } else { "SREM" };
+        self.query(cmd(command).arg(self.key("opted_out")).arg(name))
+            .await
+            .map_err(|e| anyhow!("Failed to opt {name} in or out: {e}"))
+    }
+
+    async fn increment_counter(&self, name: &str, ttl_secs: Option<u64>) -> Result<u64> {
+        self.query(
+            cmd("EVAL")
+                .arg(INCREMENT_SCRIPT)
+                .arg(1)
+                .arg(self.key(&format!("counter:{name}")))
+                .arg(ttl_secs.map(|secs| secs.to_string()).unwrap_or_default()),
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to increment counter {name}: {e}"))
+    }
+
+    async fn counter(&self, name: &str) -> Result<u64> {
+        let count: Option<u64> = self
+            .query(cmd("GET").arg(self.key(&format!("counter:{name}"))))
+            .await
+            .map_err(|e| anyhow!("Failed to read counter {name}: {e}"))?;
+        Ok(count.unwrap_or(0))
+    }
+
+    async fn get_config(&self) -> Result<Option<StoredConfig>> {
+        let fields: HashMap<String, String> = self
+            .query(cmd("HGETALL").arg(self.key("config")))
+            .await
+            .map_err(|e| anyhow!("Failed to read config: {e}"))?;
+        if fields.is_empty() {
+            return Ok(None);
+        }
+
+        match (fields.get("version"), fields.get("body")) {
+            (Some(version), Some(body)) => Ok(Some(StoredConfig {
+                version: version.parse()?,
+                body: body.clone(),
+            })),
+            _ => Err(anyhow!("Stored config is missing its version or body")),
+        }
+    }
+
+    async fn put_config(&self, body: &str, expected_version: u64) -> Result<ConfigWrite> {
+        let conflict: Option<String> = self
+            .query(
+                cmd("EVAL")
+                    .arg(PUT_CONFIG_SCRIPT)
+                    .arg(1)
+                    .arg(self.key("config"))
+                    .arg(expected_version)
+                    .arg(body),
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to store config: {e}"))?;
+        if conflict.is_none() {
+            return Ok(ConfigWrite::Saved(expected_version + 1));
+        }
+
+        match self.get_config().await? {
+            Some(current) => Ok(ConfigWrite::Conflict(current)),
+            None => Err(anyhow!("No config stored at version {expected_version}")),
+        }
+    }
+
+    async fn append_audit(&self, day: &str, entry: &str) -> Result<()> {
+        self.push_expiring(&format!("audit:{day}"), entry, self.retention.audit_secs)
+            .await
+            .map_err(|e| anyhow!("Failed to append audit entry for {day}: {e}"))
+    }
+
+    async fn audit_entries(&self, day: &str) -> Result<Vec<String>> {
+        self.list(&format!("audit:{day}"))
+            .await
+            .map_err(|e| anyhow!("Failed to read audit log for {day}: {e}"))
+    }
+
+    async fn append_config_audit(&self, day: &str, entry: &str) -> Result<()> {
+        self.push_expiring(
+            &format!("config_audit:{day}"),
+            entry,
+            self.retention.audit_secs,
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to append config change for {day}: {e}"))
+    }
+
+    async fn config_audit_entries(&self, day: &str) -> Result<Vec<String>> {
+        self.list(&format!("config_audit:{day}"))
+            .await
+            .map_err(|e| anyhow!("Failed to read config changes for {day}: {e}"))
+    }
+
+    async fn forget_audit(&self, day: &str, names: &[String]) -> Result<usize> {
+        self.forget_in_list(&format!("audit:{day}"), names)
+            .await
+            .map_err(|e| anyhow!("Failed to forget audit entries for {day}: {e}"))
+    }
+
+    async fn forget_config_audit(&self, day: &str, names: &[String]) -> Result<usize> {
+        self.forget_in_list(&format!("config_audit:{day}"), names)
+            .await
+            .map_err(|e| anyhow!("Failed to forget config changes for {day}: {e}"))
+    }
+
+    async fn queue_offline(&self, entry: &str) -> Result<()> {
+        self.push("offline_queue", entry)
+            .await
+            .map_err(|e| anyhow!("Failed to queue offline redemption: {e}"))
+    }
+
+    async fn offline_queue(&self) -> Result<Vec<QueuedEntry>> {
+        self.queued("offline_queue")
+            .await
+            .map_err(|e| anyhow!("Failed to read offline queue: {e}"))
+    }
+
+    async fn remove_offline(&self, key: &str) -> Result<()> {
+        self.remove("offline_queue", key)
+            .await
+            .map_err(|e| anyhow!("Failed to remove from offline queue: {e}"))
+    }
+
+    async fn mark_seen(&self, id: &str) -> Result<bool> {
+        self.claim(&format!("seen:{id}"), self.retention.seen_secs)
+            .await
+            .map_err(|e| anyhow!("Failed to mark {id} as seen: {e}"))
+    }
+
+    async fn forget_seen(&self, id: &str) -> Result<()> {
+        self.query(cmd("DEL").arg(self.key(&format!("seen:{id}"))))
+            .await
+            .map_err(|e| anyhow!("Failed to forget {id} was seen: {e}"))
+    }
+
+    async fn start_cooldown(&self, key: &str, secs: u64) -> Result<bool> {
+        self.claim(&format!("cooldown:{key}"), secs)
+            .await
+            .map_err(|e| anyhow!("Failed to start cooldown {key}: {e}"))
+    }
+
+    async fn push_outbox(&self, entry: &str) -> Result<()> {
+        self.push("outbox", entry)
+            .await
+            .map_err(|e| anyhow!("Failed to push to outbox: {e}"))
+    }
+
+    async fn outbox(&self) -> Result<Vec<QueuedEntry>> {
+        self.queued("outbox")
+            .await
+            .map_err(|e| anyhow!("Failed to read outbox: {e}"))
+    }
+
+    async fn remove_outbox(&self, key: &str) -> Result<()> {
+        self.remove("outbox", key)
+            .await
+            .map_err(|e| anyhow!("Failed to remove from outbox: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use anyhow::Result;
+
+    use crate::state::{StateStore, redis_store::RedisStore};
+
+    /// A store on the Redis server at `REDIS_TEST_URL`, under a prefix of its own. The tests
+    /// that need a server are ignored unless run with `--ignored`.
+    fn test_store() -> Result<RedisStore> {
+        let url = std::env::var("REDIS_TEST_URL")?;
+        RedisStore::new(&url, &format!("robochick-test:{:08x}:", fastrand::u32(..)))
+    }
+
+    #[test]
+    fn keys_start_with_the_prefix() -> anyhow::Result<()> {
+        let store = RedisStore::new("redis://localhost:6379", "robochick:")?;
+
+        assert_eq!(store.key("cursor:default"), "robochick:cursor:default");
+        Ok(())
+    }
+
+    #[test]
+    fn new_rejects_urls_that_arent_redis() {
+        assert!(RedisStore::new("http://localhost", "robochick:").is_err());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs REDIS_TEST_URL"]
+    async fn recent_winners_move_to_the_front_within_the_window() -> Result<()> {
+        let store = test_store()?;
+
+        store
+            .push_recent_winners("default", &["Ann".into(), "Bob".into()], 3)
+            .await?;
+        store
+            .push_recent_winners("default", &["Cat".into(), "Ann".into()], 3)
+            .await?;
+        store
+            .push_recent_winners("cracker", &["Cat".into()], 3)
+            .await?;
+        assert_eq!(
+            store.recent_winners("default").await?,
+            vec!["Cat", "Ann", "Bob"]
+        );
+
+        store
+            .push_recent_winners("default", &["Dan".into()], 3)
+            .await?;
+        assert_eq!(
+            store.recent_winners("default").await?,
+            vec!["Dan", "Cat", "Ann"]
+        );
+
+        assert_eq!(store.forget_winners(&["cat".into()]).await?, 2);
+        assert_eq!(store.recent_winners("default").await?, vec!["Dan", "Ann"]);
+        assert!(store.recent_winners("cracker").await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore = "needs REDIS_TEST_URL"]
+    async fn concurrent_winners_are_all_kept() -> Result<()> {
+        let store = test_store()?;
+
+        let store = Arc::new(store);
+        let names: Vec<String> = (0..10).map(|i| format!("mod-{i}")).collect();
+        let pushes: Vec<_> = names
+            .iter()
+            .map(|n| {
+                let (store, name) = (store.clone(), n.clone());
+                tokio::spawn(async move { store.push_recent_winners("default", &[name], 20).await })
+            })
+            .collect();
+        for push in pushes {
+            push.await??;
+        }
+
+        let mut recent = store.recent_winners("default").await?;
+        recent.sort();
+        assert_eq!(recent, names);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore = "needs REDIS_TEST_URL"]
+    async fn seen_ids_are_claimed_once_until_forgotten() -> Result<()> {
+        let store = test_store()?;
+
+        assert!(store.mark_seen("msg-1").await?);
+        assert!(!store.mark_seen("msg-1").await?);
+        store.forget_seen("msg-1").await?;
+        assert!(store.mark_seen("msg-1").await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore = "needs REDIS_TEST_URL"]
+    async fn outbox_entries_stay_until_removed() -> Result<()> {
+        let store = test_store()?;
+
+        store.push_outbox("first").await?;
+        store.push_outbox("second").await?;
+        let outbox = store.outbox().await?;
+        store.remove_outbox(&outbox[0].key).await?;
+
+        let left = store.outbox().await?;
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].entry, "second");
+        Ok(())
+    }
+}
@@ -0,0 +1,874 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use chrono::Utc;
+use rusqlite::{Connection, OptionalExtension, TransactionBehavior, params};
+
+use super::{
+    ConfigWrite, QueuedEntry, Retention, StateStore, StoredConfig, mentions_any, merge_recent,
+    split_forgotten,
+};
+
+/// Steps bringing the database from one schema version to the next, the first one creating it
+/// from scratch. The version reached is kept in `PRAGMA user_version`. Add steps, never edit
+/// ones that have shipped.
+///
+/// Single values and claims live in `kv`, counters and stats in `counts` (a plain counter has
+/// an empty `field`), and logs and queues in `lists`. Rows of `kv`, `counts` and `lists` with
+/// an `expires_at` (epoch seconds) in the past are deleted by [`SqliteStore::cleanup`].
+const MIGRATIONS: &[&str] = &[
+    "
+CREATE TABLE kv (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL,
+    expires_at INTEGER
+);
+CREATE TABLE counts (
+    key TEXT NOT NULL,
+    field TEXT NOT NULL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY (key, field)
+);
+CREATE TABLE lists (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    key TEXT NOT NULL,
+    entry TEXT NOT NULL
+);
+CREATE INDEX lists_by_key ON lists (key, id);
+CREATE TABLE config (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    version INTEGER NOT NULL,
+    body TEXT NOT NULL
+);
+",
+    "
+ALTER TABLE lists ADD COLUMN expires_at INTEGER;
+CREATE INDEX kv_by_expiry ON kv (expires_at);
+CREATE INDEX lists_by_expiry ON lists (expires_at);
+",
+    "
+ALTER TABLE counts ADD COLUMN expires_at INTEGER;
+CREATE INDEX counts_by_expiry ON counts (expires_at);
+",
+];
+
+/// Runs the migrations the database hasn't had yet, each in its own transaction. Returns the
+/// schema version before and after. Errors for a database from a newer build.
+pub fn migrate(conn: &mut Connection) -> Result<(usize, usize)> {
+    let from = conn.query_row("PRAGMA user_version", [], |r| r.get::<_, i64>(0))? as usize;
+    if from > MIGRATIONS.len() {
+        return Err(anyhow!(
+            "SQLite schema version {from} is newer than this build's {}",
+            MIGRATIONS.len()
+        ));
+    }
+
+    for (i, step) in MIGRATIONS.iter().enumerate().skip(from) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(step)
+            .map_err(|e| anyhow!("SQLite migration to version {} failed: {e}", i + 1))?;
+        tx.pragma_update(None, "user_version", (i + 1) as i64)?;
+        tx.commit()?;
+    }
+    Ok((from, MIGRATIONS.len()))
+}
+
+/// Keeps the state in a local SQLite file, for the standalone server without any cloud
+/// services. Queries run on the blocking pool, one at a time.
+pub struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+    retention: Retention,
+}
+
+impl SqliteStore {
+    /// Opens the database at `path`, creating it and migrating it to the newest schema when
+    /// needed. `:memory:` keeps it in memory.
+    pub fn open(path: &str) -> Result<SqliteStore> {
+        let mut conn =
+            Connection::open(path).map_err(|e| anyhow!("Failed to open SQLite at {path}: {e}"))?;
+        migrate(&mut conn).map_err(|e| anyhow!("Failed to migrate SQLite at {path}: {e}"))?;
+        Ok(SqliteStore {
+            conn: Arc::new(Mutex::new(conn)),
+            retention: Retention::default(),
+        })
+    }
+
+    pub fn with_retention(self, retention: Retention) -> Self {
+        SqliteStore { retention, ..self }
+    }
+
+    /// Deletes expired claims and log entries, returning how many rows went.
+    pub async fn cleanup(&self) -> Result<usize> {
+        let now = Utc::now().timestamp();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let kv = tx.execute("DELETE FROM kv WHERE expires_at <= ?1", [now])?;
+            let counts = tx.execute("DELETE FROM counts WHERE expires_at <= ?1", [now])?;
+            let lists = tx.execute("DELETE FROM lists WHERE expires_at <= ?1", [now])?;
+            tx.commit()?;
+            Ok(kv + counts + lists)
+        })
+        .await
+    }
+
+    /// Runs [`SqliteStore::cleanup`] every `every` in the background for as long as the store
+    /// is around.
+    pub fn spawn_cleanup(self: &Arc<Self>, every: Duration) {
+        let store = Arc::downgrade(self);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(every).await;
+                let Some(store) = store.upgrade() else {
+                    return;
+                };
+                match store.cleanup().await {
+                    Ok(0) => {}
+                    Ok(deleted) => println!("Deleted {deleted} expired rows from SQLite"),
+                    Err(e) => println!("Failed to clean up SQLite: {e}"),
+                }
+            }
+        });
+    }
+
+    async fn with_conn<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn.lock().map_err(|e| anyhow!("{e}"))?;
+            f(&mut conn).map_err(|e| anyhow!("{e}"))
+        })
+        .await?
+    }
+
+    async fn get(&self, key: String) -> Result<Option<String>> {
+        self.with_conn(move |conn| {
+            conn.query_row("SELECT value FROM kv WHERE key = ?1", [key], |r| r.get(0))
+                .optional()
+        })
+        .await
+    }
+
+    async fn set(&self, key: String, value: String) -> Result<()> {
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO kv (key, value) VALUES (?1, ?2)
+                 ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+                [key, value],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    /// Sets `key` for `ttl_secs` unless it's set and hasn't expired, returning whether it was.
+    async fn claim(&self, key: String, ttl_secs: u64) -> Result<bool> {
+        let now = Utc::now().timestamp();
+        let expires_at = now + ttl_secs as i64;
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO kv (key, value, expires_at) VALUES (?1, '', ?2)
+                 ON CONFLICT (key) DO UPDATE SET expires_at = excluded.expires_at
+                 WHERE kv.expires_at <= ?3",
+                params![key, expires_at, now],
+            )
+            .map(|changed| changed > 0)
+        })
+        .await
+    }
+
+    /// Adds one to `field` of the counts `key`. With `expires_at` a new count is deleted by
+    /// [`SqliteStore::cleanup`] once that's passed.
+    async fn increment(&self, key: String, field: String, expires_at: Option<i64>) -> Result<u64> {
+        self.with_conn(move |conn| {
+            conn.query_row(
+                "INSERT INTO counts (key, field, count, expires_at) VALUES (?1, ?2, 1, ?3)
+                 ON CONFLICT (key, field) DO UPDATE SET count = count + 1
+                 RETURNING count",
+                params![key, field, expires_at],
+                |r| r.get::<_, i64>(0),
+            )
+        })
+        .await
+        .map(|count| count as u64)
+    }
+
+    async fn counts(&self, key: String) -> Result<HashMap<String, u64>> {
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare("SELECT field, count FROM counts WHERE key = ?1")?;
+            let rows = stmt.query_map([key], |r| Ok((r.get(0)?, r.get::<_, i64>(1)? as u64)))?;
+            rows.collect()
+        })
+        .await
+    }
+
+    /// Appends `entry` to the list `key`. With `ttl_secs` every entry of the list expires that
+    /// long after this append, so a day's log goes as a whole.
+    async fn push(&self, key: String, entry: String, ttl_secs: Option<u64>) -> Result<()> {
+        let expires_at = ttl_secs.map(|ttl| Utc::now().timestamp() + ttl as i64);
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "INSERT INTO lists (key, entry, expires_at) VALUES (?1, ?2, ?3)",
+                params![key, entry, expires_at],
+            )?;
+            if expires_at.is_some() {
+                tx.execute(
+                    "UPDATE lists SET expires_at = ?2 WHERE key = ?1",
+                    params![key, expires_at],
+                )?;
+            }
+            tx.commit()
+        })
+        .await
+    }
+
+    async fn list(&self, key: String) -> Result<Vec<String>> {
+        self.with_conn(move |conn| read_list(conn, &key)).await
+    }
+
+    /// The list `key` as entries keyed by their row id.
+    async fn queued(&self, key: String) -> Result<Vec<QueuedEntry>> {
+        self.with_conn(move |conn| {
+            let mut stmt =
+                conn.prepare("SELECT id, entry FROM lists WHERE key = ?1 ORDER BY id")?;
+            let rows = stmt.query_map([key], |r| {
+                Ok(QueuedEntry {
+                    key: r.get::<_, i64>(0)?.to_string(),
+                    entry: r.get(1)?,
+                })
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    /// Deletes the entries of the list `key` that mention one of `names`, returning how many.
+    async fn forget_in_list(&self, key: String, names: &[String]) -> Result<usize> {
+        let names = names.to_vec();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let ids: Vec<i64> = {
+                let mut stmt = tx.prepare("SELECT id, entry FROM lists WHERE key = ?1")?;
+                let rows = stmt.query_map([&key], |r| {
+                    Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?))
+                })?;
+                rows.filter_map(|row| match row {
+                    Ok((id, entry)) if mentions_any(&entry, &names) => Some(Ok(id)),
+                    Ok(_) => None,
+                    Err(e) => Some(Err(e)),
+                })
+                .collect::<rusqlite::Result<_>>()?
+            };
+            for id in &ids {
+                tx.execute("DELETE FROM lists WHERE id = ?1", [id])?;
+            }
+            tx.commit()?;
+            Ok(ids.len())
+        })
+        .await
+    }
+
+    /// Deletes the entry with row id `id` from the list `key`.
+    async fn remove(&self, key: String, id: &str) -> Result<()> {
+        let id: i64 = id.parse()?;
+        self.with_conn(move |conn| {
+            conn.execute(
+                "DELETE FROM lists WHERE key = ?1 AND id = ?2",
+                params![key, id],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+}
+
+fn read_list(conn: &Connection, key: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT entry FROM lists WHERE key = ?1 ORDER BY id")?;
+    let rows = stmt.query_map([key], |r| r.get(0))?;
+    rows.collect()
+}
+
+/// Swaps the list `key` for `entries`.
+fn write_list(conn: &Connection, key: &str, entries: &[String]) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM lists WHERE key = ?1", [key])?;
+    for entry in entries {
+        conn.execute(
+            "INSERT INTO lists (key, entry) VALUES (?1, ?2)",
+            [key, entry],
+        )?;
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl StateStore for SqliteStore {
+    async fn last_round_robin(&self, group: &str) -> Result<Option<String>> {
+        self.get(format!("round_robin#{group}"))
+            .await
+            .map_err(|e| anyhow!("Failed to read round-robin for {group}: {e}"))
+    }
+
+    async fn advance_round_robin(
+        &self,
+        group: &str,
+        expected: Option<&str>,
+        next: &str,
+    ) -> Result<bool> {
+        let key = format!("round_robin#{group}");
+        let expected = expected.map(str::to_string);
+        let next = next.to_string();
+        self.with_conn(move |conn| {
+            let changed = match expected {
+                None => conn.execute(
+                    "INSERT INTO kv (key, value) VALUES (?1, ?2) ON CONFLICT (key) DO NOTHING",
+                    [key, next],
+                )?,
+                Some(expected) => conn.execute(
+                    "UPDATE kv SET value = ?2 WHERE key = ?1 AND value = ?3",
+                    [key, next, expected],
+                )?,
+            };
+            Ok(changed > 0)
+        })
+        .await
+        .map_err(|e| anyhow!("Failed to store round-robin for {group}: {e}"))
+    }
+
+    async fn recent_winners(&self, group: &str) -> Result<Vec<String>> {
+        self.list(format!("recent_winners#{group}"))
+            .await
+            .map_err(|e| anyhow!("Failed to read recent winners: {e}"))
+    }
+
+    async fn push_recent_winners(
+        &self,
+        group: &str,
+        winners: &[String],
+        window: usize,
+    ) -> Result<()> {
+        let key = format!("recent_winners#{group}");
+        let winners = winners.to_vec();
+        // Read and written in one write transaction, so winners pushed at the same time
+        // aren't lost to each other.
+        self.with_conn(move |conn| {
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let recent = merge_recent(read_list(&tx, &key)?, &winners, window);
+            write_list(&tx, &key, &recent)?;
+            tx.commit()
+        })
+        .await
+        .map_err(|e| anyhow!("Failed to store recent winners: {e}"))
+    }
+
+    async fn forget_winners(&self, names: &[String]) -> Result<usize> {
+        let names = names.to_vec();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let keys: Vec<String> = {
+                let mut stmt =
+                    tx.prepare("SELECT DISTINCT key FROM lists WHERE key GLOB 'recent_winners#*'")?;
+                let rows = stmt.query_map([], |r| r.get(0))?;
+                rows.collect::<rusqlite::Result<_>>()?
+            };
+            let mut forgotten = 0;
+            for key in keys {
+                let (count, kept) = split_forgotten(read_list(&tx, &key)?, &names);
+                if count > 0 {
+                    write_list(&tx, &key, &kept)?;
+                    forgotten += count;
+                }
+            }
+            tx.commit()?;
+            Ok(forgotten)
+        })
+        .await
+        .map_err(|e| anyhow!("Failed to forget recent winners: {e}"))
+    }
+
+    async fn record_scenario(&self, week: &str, scenario: &str) -> Result<()> {
+        self.increment(format!("scenarios#{week}"), scenario.to_string(), None)
+            .await
+            .map(|_| ())
+            .map_err(|e| anyhow!("Failed to record scenario for {week}: {e}"))
+    }
+
+    async fn scenario_counts(&self, week: &str) -> Result<HashMap<String, u64>> {
+        self.counts(format!("scenarios#{week}"))
+            .await
+            .map_err(|e| anyhow!("Failed to read scenario counts for {week}: {e}"))
+    }
+
+    async fn record_wins(&self, mods: &[String]) -> Result<()> {
+        for name in mods {
+            self.increment("wins".into(), name.clone(), None)
+                .await
+                .map_err(|e| anyhow!("Failed to record win for {name}: {e}"))?;
+        }
+        Ok(())
+    }
+
+    async fn win_counts(&self) -> Result<HashMap<String, u64>> {
+        self.counts("wins".into())
+            .await
+            .map_err(|e| anyhow!("Failed to read mod wins: {e}"))
+    }
+
+    async fn record_month_wins(&self, month: &str, mods: &[String]) -> Result<()> {
+        for name in mods {
+            self.increment(format!("wins#{month}"), name.clone(), None)
+                .await
+                .map_err(|e| anyhow!("Failed to record {month} win for {name}: {e}"))?;
+        }
+        Ok(())
+    }
+
+    async fn month_win_counts(&self, month: &str) -> Result<HashMap<String, u64>> {
+        self.counts(format!("wins#{month}"))
+            .await
+            .map_err(|e| anyhow!("Failed to read mod wins for {month}: {e}"))
+    }
+
+    async fn record_variant(&self, experiment: &str, variant: &str, metric: &str) -> Result<()> {
+        self.increment(
+            format!("variants#{experiment}"),
+            format!("{variant}/{metric}"),
+            None,
+        )
+        .await
+        .map(|_| ())
+        .map_err(|e| anyhow!("Failed to record {metric} for {experiment}: {e}"))
+    }
+
+    async fn variant_counts(&self, experiment: &str) -> Result<HashMap<String, u64>> {
+        self.counts(format!("variants#{experiment}"))
+            .await
+            .map_err(|e| anyhow!("Failed to read counts for {experiment}: {e}"))
+    }
+
+    async fn channel_info(&self) -> Result<Option<String>> {
+        self.get("channel_info".into())
+            .await
+            .map_err(|e| anyhow!("Failed to read channel info: {e}"))
+    }
+
+    async fn set_channel_info(&self, entry: &str) -> Result<()> {
+        self.set("channel_info".into(), entry.to_string())
+            .await
+            .map_err(|e| anyhow!("Failed to store channel info: {e}"))
+    }
+
+    async fn engagement(&self, user_id: &str) -> Result<Option<String>> {
+        let key = format!("engagement#{user_id}");
+        let now = Utc::now().timestamp();
+        self.with_conn(move |conn| {
+            conn.query_row(
+                "SELECT value FROM kv WHERE key = ?1 AND expires_at > ?2",
+                params![key, now],
+                |r| r.get(0),
+            )
+            .optional()
+        })
+        .await
+        .map_err(|e| anyhow!("Failed to read engagement of {user_id}: {e}"))
+    }
+
+    async fn set_engagement(&self, user_id: &str, entry: &str, ttl_secs: u64) -> Result<()> {
+        let key = format!("engagement#{user_id}");
+        let entry = entry.to_string();
+        let expires_at = Utc::now().timestamp() + ttl_secs as i64;
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO kv (key, value, expires_at) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (key) DO UPDATE
+                 SET value = excluded.value, expires_at = excluded.expires_at",
+                params![key, entry, expires_at],
+            )
+            .map(|_| ())
+        })
+        .await
+        .map_err(|e| anyhow!("Failed to store engagement of {user_id}: {e}"))
+    }
+
+    async fn opted_out_mods(&self) -> Result<Vec<String>> {
+        let mut names = self
+            .list("opted_out".into())
+            .await
+            .map_err(|e| anyhow!("Failed to read opted out mods: {e}"))?;
+        names.sort();
+        Ok(names)
+    }
+
+    async fn set_opted_out(&self, name: &str, opted_out: bool) -> Result<()> {
+        let entry = name.to_string();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "DELETE FROM lists WHERE key = 'opted_out' AND entry = ?1",
+                [&entry],
+            )?;
+            if opted_out {
+                tx.execute(
+                    "INSERT INTO lists (key, entry) VALUES ('opted_out', ?1)",
+                    [&entry],
+                )?;
+            }
+            tx.commit()
+        })
+        .await
+        .map_err(|e| anyhow!("Failed to opt {name} in or out: {e}"))
+    }
+
+    async fn increment_counter(&self, name: &str, ttl_secs: Option<u64>) -> Result<u64> {
+        let expires_at = ttl_secs.map(|secs| Utc::now().timestamp() + secs as i64);
+        self.increment(format!("counter#{name}"), String::new(), expires_at)
+            .await
+            .map_err(|e| anyhow!("Failed to increment counter {name}: {e}"))
+    }
+
+    async fn counter(&self, name: &str) -> Result<u64> {
+        let key = format!("counter#{name}");
+        let now = Utc::now().timestamp();
+        self.with_conn(move |conn| {
+            conn.query_row(
+                "SELECT count FROM counts WHERE key = ?1 AND field = ''
+                 AND (expires_at IS NULL OR expires_at > ?2)",
+                params![key, now],
+                |r| r.get::<_, i64>(0),
+            )
+            .optional()
+        })
+        .await
+        .map(|count| count.unwrap_or(0) as u64)
+        .map_err(|e| anyhow!("Failed to read counter {name}: {e}"))
+    }
+
+    async fn get_config(&self) -> Result<Option<StoredConfig>> {
+        self.with_conn(|conn| {
+            conn.query_row("SELECT version, body FROM config WHERE id = 1", [], |r| {
+                Ok(StoredConfig {
+                    version: r.get::<_, i64>(0)? as u64,
+                    body: r.get(1)?,
+                })
+            })
+            .optional()
+        })
+        .await
+        .map_err(|e| anyhow!("Failed to read config: {e}"))
+    }
+
+    async fn put_config(&self, body: &str, expected_version: u64) -> Result<ConfigWrite> {
+        let body = body.to_string();
+        let saved = self
+            .with_conn(move |conn| {
+                let changed = if expected_version == 0 {
+                    conn.execute(
+                        "INSERT INTO config (id, version, body) VALUES (1, 1, ?1)
+                         ON CONFLICT (id) DO NOTHING",
+                        [&body],
+                    )?
+                } else {
+                    conn.execute(
+                        "UPDATE config SET version = version + 1, body = ?1
+                         WHERE id = 1 AND version = ?2",
+                        params![body, expected_version as i64],
+                    )?
+                };
+                Ok(changed > 0)
+            })
+            .await
+            .map_err(|e| anyhow!("Failed to store config: {e}"))?;
+        if saved {
+            return Ok(ConfigWrite::Saved(expected_version + 1));
+        }
+
+        match self.get_config().await? {
+            Some(current) => Ok(ConfigWrite::Conflict(current)),
+            None => Err(anyhow!("No config stored at version {expected_version}")),
+        }
+    }
+
+    async fn append_audit(&self, day: &str, entry: &str) -> Result<()> {
+        self.push(
+            format!("audit#{day}"),
+            entry.to_string(),
+            Some(self.retention.audit_secs),
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to append audit entry for {day}: {e}"))
+    }
+
+    async fn audit_entries(&self, day: &str) -> Result<Vec<String>> {
+        self.list(format!("audit#{day}"))
+            .await
+            .map_err(|e| anyhow!("Failed to read audit log for {day}: {e}"))
+    }
+
+    async fn append_config_audit(&self, day: &str, entry: &str) -> Result<()> {
+        self.push(
+            format!("config_audit#{day}"),
+            entry.to_string(),
+            Some(self.retention.audit_secs),
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to append config change for {day}: {e}"))
+    }
+
+    async fn config_audit_entries(&self, day: &str) -> Result<Vec<String>> {
+        self.list(format!("config_audit#{day}"))
+            .await
+            .map_err(|e| anyhow!("Failed to read config changes for {day}: {e}"))
+    }
+
+    async fn forget_audit(&self, day: &str, names: &[String]) -> Result<usize> {
+        self.forget_in_list(format!("audit#{day}"), names)
+            .await
+            .map_err(|e| anyhow!("Failed to forget audit entries for {day}: {e}"))
+    }
+
+    async fn forget_config_audit(&self, day: &str, names: &[String]) -> Result<usize> {
+        self.forget_in_list(format!("config_audit#{day}"), names)
+            .await
+            .map_err(|e| anyhow!("Failed to forget config changes for {day}: {e}"))
+    }
+
+    async fn queue_offline(&self, entry: &str) -> Result<()> {
+        self.push("offline_queue".into(), entry.to_string(), None)
+            .await
+            .map_err(|e| anyhow!("Failed to queue offline redemption: {e}"))
+    }
+
+    async fn offline_queue(&self) -> Result<Vec<QueuedEntry>> {
+        self.queued("offline_queue".into())
+            .await
+            .map_err(|e| anyhow!("Failed to read offline queue: {e}"))
+    }
+
+    async fn remove_offline(&self, key: &str) -> Result<()> {
+        self.remove("offline_queue".into(), key)
+            .await
+            .map_err(|e| anyhow!("Failed to remove {key} from offline queue: {e}"))
+    }
+
+    async fn mark_seen(&self, id: &str) -> Result<bool> {
+        self.claim(format!("seen#{id}"), self.retention.seen_secs)
+            .await
+            .map_err(|e| anyhow!("Failed to mark {id} as seen: {e}"))
+    }
+
+    async fn forget_seen(&self, id: &str) -> Result<()> {
+        let key = format!("seen#{id}");
+        self.with_conn(move |conn| {
+            conn.execute("DELETE FROM kv WHERE key = ?1", [key])
+                .map(|_| ())
+        })
+        .await
+        .map_err(|e| anyhow!("Failed to forget {id} was seen: {e}"))
+    }
+
+    async fn start_cooldown(&self, key: &str, secs: u64) -> Result<bool> {
+        self.claim(format!("cooldown#{key}"), secs)
+            .await
+            .map_err(|e| anyhow!("Failed to start cooldown {key}: {e}"))
+    }
+
+    async fn push_outbox(&self, entry: &str) -> Result<()> {
+        self.push("outbox".into(), entry.to_string(), None)
+            .await
+            .map_err(|e| anyhow!("Failed to push to outbox: {e}"))
+    }
+
+    async fn outbox(&self) -> Result<Vec<QueuedEntry>> {
+        self.queued("outbox".into())
+            .await
+            .map_err(|e| anyhow!("Failed to read outbox: {e}"))
+    }
+
+    async fn remove_outbox(&self, key: &str) -> Result<()> {
+        self.remove("outbox".into(), key)
+            .await
+            .map_err(|e| anyhow!("Failed to remove {key} from outbox: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use pretty_assertions::assert_eq;
+
+    use rusqlite::Connection;
+
+    use crate::state::{
+        ConfigWrite, Retention, StateStore, StoredConfig,
+        sqlite_store::{MIGRATIONS, SqliteStore, migrate},
+    };
+
+    #[test]
+    fn migrate_only_runs_new_steps() -> Result<()> {
+        let mut conn = Connection::open_in_memory()?;
+
+        assert_eq!(migrate(&mut conn)?, (0, MIGRATIONS.len()));
+        assert_eq!(migrate(&mut conn)?, (MIGRATIONS.len(), MIGRATIONS.len()));
+
+        conn.pragma_update(None, "user_version", MIGRATIONS.len() as i64 + 1)?;
+        assert!(migrate(&mut conn).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn round_trips_round_robin_counts_and_lists() -> Result<()> {
+        let store = SqliteStore::open(":memory:")?;
+
+        assert!(store.advance_round_robin("default", None, "John").await?);
+        assert!(
+            !store
+                .advance_round_robin("default", Some("Kim"), "Jane")
+                .await?
+        );
+        store.record_scenario("2026-W42", "cracker").await?;
+        store.record_scenario("2026-W42", "cracker").await?;
+        store
+            .push_recent_winners("default", &["John".into()], 2)
+            .await?;
+        store
+            .push_recent_winners("default", &["Jane".into(), "Kim".into()], 2)
+            .await?;
+        store.push_outbox("first").await?;
+        store.push_outbox("second").await?;
+
+        assert_eq!(
+            store.last_round_robin("default").await?.as_deref(),
+            Some("John")
+        );
+        assert_eq!(store.last_round_robin("other").await?, None);
+        assert_eq!(
+            store.scenario_counts("2026-W42").await?.get("cracker"),
+            Some(&2)
+        );
+        assert_eq!(store.increment_counter("budget", None).await?, 1);
+        assert_eq!(store.increment_counter("budget", None).await?, 2);
+        assert_eq!(store.recent_winners("default").await?, vec!["Jane", "Kim"]);
+        assert!(store.recent_winners("other").await?.is_empty());
+        store
+            .push_recent_winners("other", &["Kim".into()], 2)
+            .await?;
+        assert_eq!(store.forget_winners(&["kim".into()]).await?, 2);
+        assert_eq!(store.recent_winners("default").await?, vec!["Jane"]);
+        let outbox = store.outbox().await?;
+        let entries: Vec<&str> = outbox.iter().map(|q| q.entry.as_str()).collect();
+        assert_eq!(entries, vec!["first", "second"]);
+        store.remove_outbox(&outbox[0].key).await?;
+        let outbox = store.outbox().await?;
+        assert_eq!(outbox.len(), 1);
+        assert_eq!(outbox[0].entry, "second");
+
+        store.set_opted_out("john", true).await?;
+        store.set_opted_out("john", true).await?;
+        store.set_opted_out("alex", true).await?;
+        store.set_opted_out("john", false).await?;
+        assert_eq!(store.opted_out_mods().await?, vec!["alex"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_stale_config_writes() -> Result<()> {
+        let store = SqliteStore::open(":memory:")?;
+
+        assert_eq!(store.put_config("first", 0).await?, ConfigWrite::Saved(1));
+        assert_eq!(store.put_config("second", 1).await?, ConfigWrite::Saved(2));
+        assert_eq!(
+            store.put_config("stale", 1).await?,
+            ConfigWrite::Conflict(StoredConfig {
+                version: 2,
+                body: "second".into()
+            })
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn claims_block_until_they_expire() -> Result<()> {
+        let store = SqliteStore::open(":memory:")?;
+
+        assert!(store.mark_seen("msg-1").await?);
+        assert!(!store.mark_seen("msg-1").await?);
+        store.forget_seen("msg-1").await?;
+        assert!(store.mark_seen("msg-1").await?);
+        assert!(store.start_cooldown("!quote", 0).await?);
+        assert!(store.start_cooldown("!quote", 0).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn engagement_is_kept_until_it_expires() -> Result<()> {
+        let store = SqliteStore::open(":memory:")?;
+
+        assert_eq!(store.engagement("42").await?, None);
+        store.set_engagement("42", "short", 300).await?;
+        store.set_engagement("43", "long", 0).await?;
+        assert_eq!(store.engagement("42").await?, Some("short".to_string()));
+        assert_eq!(store.engagement("43").await?, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn forgets_audit_entries_mentioning_a_name() -> Result<()> {
+        let store = SqliteStore::open(":memory:")?;
+        store.append_audit("2026-10-16", "Clucky won").await?;
+        store.append_audit("2026-10-16", "Jane won").await?;
+        store
+            .append_config_audit("2026-10-16", "mods: clucky")
+            .await?;
+
+        let names = vec!["Clucky".to_string()];
+        assert_eq!(store.forget_audit("2026-10-16", &names).await?, 1);
+        assert_eq!(store.forget_config_audit("2026-10-16", &names).await?, 1);
+        assert_eq!(store.audit_entries("2026-10-16").await?, vec!["Jane won"]);
+        assert!(store.config_audit_entries("2026-10-16").await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn cleanup_deletes_expired_claims_counters_and_audit_logs() -> Result<()> {
+        let store = SqliteStore::open(":memory:")?.with_retention(Retention {
+            seen_secs: 0,
+            audit_secs: 0,
+        });
+        store.mark_seen("msg-1").await?;
+        store.start_cooldown("!quote", 600).await?;
+        store.append_audit("2026-10-16", "hi").await?;
+        store.push_outbox("retry").await?;
+        store.increment_counter("burst#reward-1#1", Some(0)).await?;
+        store.increment_counter("budget", None).await?;
+
+        assert_eq!(store.cleanup().await?, 3);
+        assert_eq!(
+            store.audit_entries("2026-10-16").await?,
+            Vec::<String>::new()
+        );
+        assert_eq!(store.outbox().await?[0].entry, "retry");
+        assert!(!store.start_cooldown("!quote", 600).await?);
+        assert_eq!(store.increment_counter("budget", None).await?, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn counter_reads_the_count_without_bumping_it() -> Result<()> {
+        let store = SqliteStore::open(":memory:")?;
+        assert_eq!(store.counter("version#wins").await?, 0);
+
+        store.increment_counter("version#wins", None).await?;
+        store.increment_counter("expired", Some(0)).await?;
+
+        assert_eq!(store.counter("version#wins").await?, 1);
+        assert_eq!(store.counter("version#wins").await?, 1);
+        assert_eq!(store.counter("expired").await?, 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,512 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+};
+use chrono::{DateTime, Days, Months, Utc};
+use lambda_http::{Body, Response};
+use reqwest::{StatusCode, header::CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    AppState, auth,
+    client::{StreamelementsCaller, WebClient},
+    etag::{self, Dataset},
+    experiment,
+    reward::mod_feeder::load_message_components,
+    robochick::twitch::{FairPicker, MessageComponents},
+    state::StateStore,
+    text,
+};
+
+/// Longest scenario name shown in the chat summary before it gets cut short.
+const SUMMARY_NAME_LIMIT: usize = 40;
+
+/// How long browsers may reuse stats before revalidating them with their ETag.
+const STATS_CACHE_CONTROL: &str = "public, max-age=60";
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct ScenarioCount {
+    pub scenario: String,
+    pub count: u64,
+}
+
+/// Stats bucket for the ISO week containing `at`, e.g. `2026-W42`.
+pub fn week_key(at: DateTime<Utc>) -> String {
+    at.format("%G-W%V").to_string()
+}
+
+/// Stats bucket for the month containing `at`, e.g. `2026-10`.
+pub fn month_key(at: DateTime<Utc>) -> String {
+    at.format("%Y-%m").to_string()
+}
+
+/// Keys for the `n` weeks ending with the one containing `now`, newest first.
+pub fn recent_weeks(now: DateTime<Utc>, n: u64) -> Vec<String> {
+    (0..n)
+        .filter_map(|i| now.checked_sub_days(Days::new(i * 7)))
+        .map(week_key)
+        .collect()
+}
+
+/// Months of wins `fair` selection weighs, this one included.
+const FAIR_MONTHS: u32 = 3;
+
+/// Keys for the `n` months ending with the one containing `now`, newest first.
+pub fn recent_months(now: DateTime<Utc>, n: u32) -> Vec<String> {
+    (0..n)
+        .filter_map(|i| now.checked_sub_months(Months::new(i)))
+        .map(month_key)
+        .collect()
+}
+
+/// A [`FairPicker`] weighing the wins of the last [`FAIR_MONTHS`] months. The months are read
+/// at once rather than one after another.
+pub async fn fair_picker(state: &dyn StateStore, now: DateTime<Utc>) -> Result<FairPicker> {
+    let months = recent_months(now, FAIR_MONTHS);
+    let (this, last, before) = tokio::try_join!(
+        state.month_win_counts(&months[0]),
+        state.month_win_counts(&months[1]),
+        state.month_win_counts(&months[2]),
+    )?;
+    Ok(FairPicker::from_months(&[this, last, before]))
+}
+
+/// Sums scenario counts over `weeks`. Configured scenarios that never fired are included with
+/// a count of 0 so stale templates stand out. Most common first.
+pub async fn scenario_counts(
+    state: &dyn StateStore,
+    components: Option<&MessageComponents>,
+    weeks: &[String],
+) -> Result<Vec<ScenarioCount>> {
+    let mut totals: HashMap<String, u64> = HashMap::new();
+    if let Some(components) = components {
+        for scenario in components.all_scenarios() {
+            totals.entry(scenario.key().to_string()).or_default();
+        }
+    }
+
+    for week in weeks {
+        for (scenario, count) in state.scenario_counts(week).await? {
+            *totals.entry(scenario).or_default() += count;
+        }
+    }
+
+    let mut counts: Vec<ScenarioCount> = totals
+        .into_iter()
+        .map(|(scenario, count)| ScenarioCount { scenario, count })
+        .collect();
+    counts.sort_by(|a, b| b.count.cmp(&a.count).then(a.scenario.cmp(&b.scenario)));
+    Ok(counts)
+}
+
+/// Chat message listing the `top` most common scenarios, or `None` if nothing fired.
+pub fn summary_message(counts: &[ScenarioCount], top: usize) -> Option<String> {
+    let entries: Vec<String> = counts
+        .iter()
+        .filter(|c| c.count > 0)
+        .take(top)
+        .enumerate()
+        .map(|(i, c)| {
+            let name = text::shorten(&c.scenario, SUMMARY_NAME_LIMIT);
+            format!("{}. {name} ({}x)", i + 1, c.count)
+        })
+        .collect();
+
+    if entries.is_empty() {
+        return None;
+    }
+
+    Some(format!(
+        "Last week's most common chicken outcomes: {}",
+        entries.join(", ")
+    ))
+}
+
+/// `GET /stats/scenarios?weeks=N` - scenario counts over the last N weeks (default 1).
+#[utoipa::path(get, path = "/stats/scenarios", tag = "stats",
+    params(("weeks" = Option<u64>, Query, description = "Weeks to sum, 1 to 52")),
+    responses(
+        (status = 200, description = "Scenario counts, most common first", body = Vec<ScenarioCount>),
+        (status = 304, description = "Unchanged since the `If-None-Match` ETag"),
+    ),
+)]
+pub async fn scenario_stats_handler(
+    State(state): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    let weeks = params
+        .get("weeks")
+        .and_then(|w| w.parse::<u64>().ok())
+        .unwrap_or(1)
+        .clamp(1, 52);
+
+    let now = Utc::now();
+    let tag = match etag::version_etag(
+        state.state.as_ref(),
+        &state.config_file,
+        &[Dataset::Scenarios],
+        &[&weeks.to_string(), &week_key(now)],
+    )
+    .await
+    {
+        Ok(tag) => tag,
+        Err(e) => return stats_error("scenario", e),
+    };
+    if let Some(response) = etag::revalidate(&headers, &tag, STATS_CACHE_CONTROL) {
+        return response;
+    }
+
+    let components = load_message_components(state.state.as_ref(), &state.config_file)
+        .await
+        .ok();
+    match scenario_counts(
+        state.state.as_ref(),
+        components.as_deref(),
+        &recent_weeks(now, weeks),
+    )
+    .await
+    {
+        Ok(counts) => etag::tagged_response(
+            &tag,
+            "application/json",
+            STATS_CACHE_CONTROL,
+            serde_json::to_string(&counts).unwrap(),
+        ),
+        Err(e) => stats_error("scenario", e),
+    }
+}
+
+fn stats_error(stats: &str, e: anyhow::Error) -> Response<Body> {
+    println!("Failed to read {stats} stats: {e}");
+    Response::builder()
+        .status(StatusCode::INTERNAL_SERVER_ERROR)
+        .body(Body::Empty)
+        .unwrap()
+}
+
+/// `GET /stats/experiments` - posts and chat messages per variant of each experiment in the
+/// config.
+#[utoipa::path(get, path = "/stats/experiments", tag = "stats", responses(
+    (status = 200, description = "Posts and chat messages per variant", body = Object),
+    (status = 304, description = "Unchanged since the `If-None-Match` ETag"),
+))]
+pub async fn experiment_stats_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    let tag = match etag::version_etag(
+        state.state.as_ref(),
+        &state.config_file,
+        &[Dataset::Experiments],
+        &[],
+    )
+    .await
+    {
+        Ok(tag) => tag,
+        Err(e) => return stats_error("experiment", e),
+    };
+    if let Some(response) = etag::revalidate(&headers, &tag, STATS_CACHE_CONTROL) {
+        return response;
+    }
+
+    let report = match load_message_components(state.state.as_ref(), &state.config_file).await {
+        Ok(components) => experiment::report(state.state.as_ref(), &components).await,
+        Err(e) => Err(e),
+    };
+    match report {
+        Ok(report) => etag::tagged_response(
+            &tag,
+            "application/json",
+            STATS_CACHE_CONTROL,
+            serde_json::to_string(&report).unwrap(),
+        ),
+        Err(e) => stats_error("experiment", e),
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct ModFairness {
+    pub name: String,
+    pub wins: u64,
+    /// Fraction of all wins by current mods.
+    pub share: f64,
+    /// Chance of winning under `fair` selection, relative to a mod who's never won.
+    pub weight: f64,
+}
+
+/// All-time wins of each of `mods`, most first, with their weight under `fair` selection as of
+/// `now`.
+pub async fn mod_fairness(
+    state: &dyn StateStore,
+    mods: &[String],
+    now: DateTime<Utc>,
+) -> Result<Vec<ModFairness>> {
+    let (wins, picker) = tokio::try_join!(state.win_counts(), fair_picker(state, now))?;
+    let total: u64 = mods.iter().filter_map(|m| wins.get(m)).sum();
+    let mut fairness: Vec<ModFairness> = mods
+        .iter()
+        .map(|name| {
+            let wins = wins.get(name).copied().unwrap_or(0);
+            ModFairness {
+                name: name.clone(),
+                wins,
+                share: match total {
+                    0 => 0.0,
+                    total => wins as f64 / total as f64,
+                },
+                weight: picker.weight(name),
+            }
+        })
+        .collect();
+    fairness.sort_by(|a, b| b.wins.cmp(&a.wins).then(a.name.cmp(&b.name)));
+    Ok(fairness)
+}
+
+/// `GET /stats/mods` - how often each mod has won.
+#[utoipa::path(get, path = "/stats/mods", tag = "stats", responses(
+    (status = 200, description = "Each mod's wins, most first", body = Vec<ModFairness>),
+    (status = 304, description = "Unchanged since the `If-None-Match` ETag"),
+))]
+pub async fn mod_stats_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    // The weights fade out by month, so the month is part of the version.
+    let now = Utc::now();
+    let tag = match etag::version_etag(
+        state.state.as_ref(),
+        &state.config_file,
+        &[Dataset::Wins],
+        &[&month_key(now)],
+    )
+    .await
+    {
+        Ok(tag) => tag,
+        Err(e) => return stats_error("mod", e),
+    };
+    if let Some(response) = etag::revalidate(&headers, &tag, STATS_CACHE_CONTROL) {
+        return response;
+    }
+
+    let fairness = match load_message_components(state.state.as_ref(), &state.config_file).await {
+        Ok(components) => mod_fairness(state.state.as_ref(), components.get_mods(), now).await,
+        Err(e) => Err(e),
+    };
+    match fairness {
+        Ok(f) => etag::tagged_response(
+            &tag,
+            "application/json",
+            STATS_CACHE_CONTROL,
+            serde_json::to_string(&f).unwrap(),
+        ),
+        Err(e) => stats_error("mod", e),
+    }
+}
+
+/// `POST /internal/scenario-summary` - posts last week's top scenarios in chat. Meant to be
+/// called by a weekly schedule (e.g. an EventBridge rule).
+#[utoipa::path(post, path = "/internal/scenario-summary", tag = "internal",
+    security(("internal_token" = [])),
+    responses(
+        (status = 204, description = "Posted, or nothing fired last week"),
+        (status = 401, description = "Missing or wrong `INTERNAL_API_TOKEN`"),
+        (status = 502, description = "Posting failed"),
+    ),
+)]
+pub async fn scenario_summary_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    if !auth::internal_request_authorized(&headers, &state.config) {
+        return Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .body(Body::Empty)
+            .unwrap();
+    }
+
+    let last_week = match Utc::now().checked_sub_days(Days::new(7)) {
+        Some(at) => week_key(at),
+        None => week_key(Utc::now()),
+    };
+
+    let counts = match scenario_counts(state.state.as_ref(), None, &[last_week]).await {
+        Ok(c) => c,
+        Err(e) => {
+            println!("Failed to read scenario stats: {e}");
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::Empty)
+                .unwrap();
+        }
+    };
+
+    if let Some(message) = summary_message(&counts, 3)
+        && let Err(e) = state.caller.say(&message, &state.config).await
+    {
+        println!("Failed to post scenario summary: {e}");
+        return Response::builder()
+            .status(StatusCode::BAD_GATEWAY)
+            .body(Body::Empty)
+            .unwrap();
+    }
+
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .body(Body::Empty)
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use chrono::{TimeZone, Utc};
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        robochick::twitch::{MessageComponents, Scenario},
+        state::{MemoryStore, StateStore},
+        stats::{
+            ScenarioCount, mod_fairness, recent_weeks, scenario_counts, summary_message, week_key,
+        },
+    };
+
+    #[tokio::test]
+    async fn mod_fairness_lists_current_mods_by_wins() -> Result<()> {
+        let store = MemoryStore::default();
+        store
+            .record_wins(&["John".into(), "John".into(), "Jane".into(), "Gone".into()])
+            .await?;
+        store
+            .record_month_wins("2026-10", &["John".into(), "Jane".into()])
+            .await?;
+        store
+            .record_month_wins("2026-08", &["John".into(), "John".into()])
+            .await?;
+        store.record_month_wins("2026-07", &["Jane".into()]).await?;
+        let mods: Vec<String> = vec!["Alex".into(), "Jane".into(), "John".into()];
+        let now = "2026-10-17T12:00:00Z".parse()?;
+
+        let fairness = mod_fairness(&store, &mods, now).await?;
+
+        let rows: Vec<(&str, u64, String, String)> = fairness
+            .iter()
+            .map(|f| {
+                (
+                    f.name.as_str(),
+                    f.wins,
+                    format!("{:.2}", f.share),
+                    format!("{:.2}", f.weight),
+                )
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("John", 2, "0.67".into(), "0.40".into()),
+                ("Jane", 1, "0.33".into(), "0.50".into()),
+                ("Alex", 0, "0.00".into(), "1.00".into()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn week_key_uses_iso_weeks() {
+        let at = Utc.with_ymd_and_hms(2027, 1, 1, 12, 0, 0).unwrap();
+
+        assert_eq!(week_key(at), "2026-W53");
+    }
+
+    #[test]
+    fn recent_weeks_lists_newest_first() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 16, 12, 0, 0).unwrap();
+
+        assert_eq!(recent_weeks(now, 2), vec!["2026-W42", "2026-W41"]);
+    }
+
+    #[tokio::test]
+    async fn scenario_counts_sums_weeks_and_includes_unused_scenarios() -> Result<()> {
+        let store = MemoryStore::default();
+        store.record_scenario("2026-W42", "cracker-trip").await?;
+        store.record_scenario("2026-W41", "cracker-trip").await?;
+        store.record_scenario("2026-W41", "guard-duty").await?;
+
+        let components = MessageComponents {
+            scenarios: vec![Scenario {
+                id: Some("benevolent".into()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let counts = scenario_counts(
+            &store,
+            Some(&components),
+            &["2026-W42".to_string(), "2026-W41".to_string()],
+        )
+        .await?;
+
+        assert_eq!(
+            counts,
+            vec![
+                ScenarioCount {
+                    scenario: "cracker-trip".into(),
+                    count: 2
+                },
+                ScenarioCount {
+                    scenario: "guard-duty".into(),
+                    count: 1
+                },
+                ScenarioCount {
+                    scenario: "benevolent".into(),
+                    count: 0
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn summary_message_lists_top_scenarios_and_skips_unused_ones() {
+        let counts = vec![
+            ScenarioCount {
+                scenario: "cracker-trip".into(),
+                count: 5,
+            },
+            ScenarioCount {
+                scenario: "{win_1} was on guard duty while the others slept and got the cracker"
+                    .into(),
+                count: 2,
+            },
+            ScenarioCount {
+                scenario: "benevolent".into(),
+                count: 0,
+            },
+        ];
+
+        let message = summary_message(&counts, 3);
+
+        assert_eq!(
+            message,
+            Some(
+                "Last week's most common chicken outcomes: 1. cracker-trip (5x), 2. {win_1} was on guard duty while the o... (2x)"
+                    .into()
+            )
+        );
+    }
+
+    #[test]
+    fn summary_message_is_none_without_any_firings() {
+        let counts = vec![ScenarioCount {
+            scenario: "benevolent".into(),
+            count: 0,
+        }];
+
+        assert_eq!(summary_message(&counts, 3), None);
+    }
+}
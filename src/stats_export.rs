@@ -0,0 +1,387 @@
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+use aws_sdk_dynamodb::types::AttributeValue;
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+};
+use chrono::{DateTime, Duration, Utc};
+use lambda_http::{Body, Response};
+use reqwest::{
+    StatusCode,
+    header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+};
+use serde::Serialize;
+use strum::{AsRefStr, EnumString};
+
+use crate::{
+    AppState,
+    admin_tokens::AdminScope,
+    reward::mod_feeder::load_message_components,
+    robochick::twitch::MessageComponents,
+    state::StateStore,
+    stats::{ModFairness, ScenarioCount, mod_fairness, scenario_counts, week_key},
+};
+
+/// Range exported when none is given.
+pub const DEFAULT_RANGE: &str = "30d";
+
+/// Longest range that can be exported, a year.
+const MAX_RANGE_DAYS: i64 = 366;
+
+#[derive(Debug, Clone, Copy, PartialEq, AsRefStr, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Json => "application/json",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RedemptionCount {
+    pub user: String,
+    pub count: u64,
+}
+
+/// Everything the streamer might want to chart, over a range ending now.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct StatsExport {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    /// Duck redemptions made in the range by each viewer, most first.
+    pub redemptions: Vec<RedemptionCount>,
+    /// Wins of each current mod. These are only counted over all time.
+    pub mods: Vec<ModFairness>,
+    /// Scenario firings in the ISO weeks overlapping the range, stats being kept per week.
+    pub scenarios: Vec<ScenarioCount>,
+}
+
+/// Reads a range like `30d` or `12w`.
+pub fn parse_range(range: &str) -> Result<Duration> {
+    let invalid = || anyhow!("Invalid range {range}, expected e.g. 30d or 12w");
+    let (n, weeks) = match (range.strip_suffix('d'), range.strip_suffix('w')) {
+        (Some(n), _) => (n, false),
+        (_, Some(n)) => (n, true),
+        _ => return Err(invalid()),
+    };
+    let n: i64 = n.parse().map_err(|_| invalid())?;
+    let days = if weeks { n.saturating_mul(7) } else { n };
+    if !(1..=MAX_RANGE_DAYS).contains(&days) {
+        return Err(anyhow!(
+            "Range {range} must be between 1 and {MAX_RANGE_DAYS} days"
+        ));
+    }
+    Ok(Duration::days(days))
+}
+
+/// Keys of the ISO weeks from `from` to `to`, oldest first.
+pub fn weeks_between(from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<String> {
+    let mut weeks: Vec<String> = vec![];
+    let mut at = from;
+    while at <= to {
+        let week = week_key(at);
+        if weeks.last() != Some(&week) {
+            weeks.push(week);
+        }
+        at += Duration::days(1);
+    }
+    if weeks.last() != Some(&week_key(to)) {
+        weeks.push(week_key(to));
+    }
+    weeks
+}
+
+/// Counts duck redemptions made at or after `since` by viewer, keyed by display name.
+pub async fn redemption_counts(
+    client: &aws_sdk_dynamodb::Client,
+    table_name: &str,
+    since: DateTime<Utc>,
+) -> Result<Vec<RedemptionCount>> {
+    // Timestamps are stored as RFC 3339 in UTC, so they compare as strings.
+    let since = since.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    let mut totals: HashMap<String, u64> = HashMap::new();
+    let mut start_key = None;
+    loop {
+        let output = match client
+            .scan()
+            .table_name(table_name)
+            .filter_expression("redeemed_at >= :since")
+            .expression_attribute_values(":since", AttributeValue::S(since.clone()))
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await
+        {
+            Ok(o) => o,
+            Err(e) => return Err(anyhow!("Failed to scan {table_name}: {e}")),
+        };
+
+        for item in output.items() {
+            let name = ["display_name", "username"]
+                .iter()
+                .find_map(|attr| item.get(*attr).and_then(|v| v.as_s().ok()));
+            if let Some(name) = name {
+                *totals.entry(name.clone()).or_default() += 1;
+            }
+        }
+
+        start_key = output.last_evaluated_key;
+        if start_key.is_none() {
+            break;
+        }
+    }
+
+    let mut counts: Vec<RedemptionCount> = totals
+        .into_iter()
+        .map(|(user, count)| RedemptionCount { user, count })
+        .collect();
+    counts.sort_by(|a, b| b.count.cmp(&a.count).then(a.user.cmp(&b.user)));
+    Ok(counts)
+}
+
+pub async fn export(
+    client: &aws_sdk_dynamodb::Client,
+    table_name: &str,
+    state: &dyn StateStore,
+    components: Option<&MessageComponents>,
+    range: Duration,
+    now: DateTime<Utc>,
+) -> Result<StatsExport> {
+    let from = now - range;
+    let mods = match components {
+        Some(components) => mod_fairness(state, components.get_mods(), now).await?,
+        None => vec![],
+    };
+    Ok(StatsExport {
+        from,
+        to: now,
+        redemptions: redemption_counts(client, table_name, from).await?,
+        mods,
+        scenarios: scenario_counts(state, components, &weeks_between(from, now)).await?,
+    })
+}
+
+/// JSON as is, or CSV with one `section,name,count` row per viewer, mod and scenario.
+pub fn render(export: &StatsExport, format: ExportFormat) -> Result<String> {
+    match format {
+        ExportFormat::Json => Ok(serde_json::to_string_pretty(export)?),
+        ExportFormat::Csv => {
+            let mut csv = String::from("section,name,count\n");
+            let rows = export
+                .redemptions
+                .iter()
+                .map(|r| ("redemptions", &r.user, r.count))
+                .chain(export.mods.iter().map(|m| ("mod_wins", &m.name, m.wins)))
+                .chain(
+                    export
+                        .scenarios
+                        .iter()
+                        .map(|s| ("scenarios", &s.scenario, s.count)),
+                );
+            for (section, name, count) in rows {
+                csv.push_str(&format!("{section},{},{count}\n", csv_field(name)));
+            }
+            Ok(csv)
+        }
+    }
+}
+
+/// Quotes a field holding a comma, quote or line break, doubling its quotes.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// `GET /admin/stats/export?format=csv&range=30d` - redemption, mod and scenario stats as a
+/// download, for a token with the `read` scope since it names viewers. `format` is `csv`
+/// (default) or `json`, `range` is days or weeks ending now.
+#[utoipa::path(get, path = "/admin/stats/export", tag = "admin",
+    security(("admin_token" = [])),
+    params(
+        ("format" = Option<String>, Query, description = "`csv` (default) or `json`"),
+        ("range" = Option<String>, Query, description = "Days or weeks ending now, e.g. `30d` or `12w`"),
+    ),
+    responses(
+        (status = 200, description = "The stats as a download", content(
+            (String = "text/csv"),
+            (Object = "application/json"),
+        )),
+        (status = 400, description = "Invalid format or range"),
+        (status = 401, description = "Token missing or unknown"),
+        (status = 403, description = "Token not scoped for this"),
+    ),
+)]
+pub async fn export_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response<Body> {
+    if let Err(status) = state.admin_authorized(&headers, AdminScope::Read).await {
+        return Response::builder()
+            .status(status)
+            .body(Body::Empty)
+            .unwrap();
+    }
+
+    let format = match params.get("format").map(|f| f.parse::<ExportFormat>()) {
+        None => ExportFormat::Csv,
+        Some(Ok(format)) => format,
+        Some(Err(_)) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from("Invalid format, expected csv or json"))
+                .unwrap();
+        }
+    };
+    let range_param = params.get("range").map_or(DEFAULT_RANGE, String::as_str);
+    let range = match parse_range(range_param) {
+        Ok(r) => r,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(e.to_string()))
+                .unwrap();
+        }
+    };
+
+    let components = load_message_components(state.state.as_ref(), &state.config_file)
+        .await
+        .ok();
+    let rendered = match export(
+        &state.dynamo_client,
+        &state.config.duck_rewards_table_name,
+        state.state.as_ref(),
+        components.as_deref(),
+        range,
+        Utc::now(),
+    )
+    .await
+    .and_then(|e| render(&e, format))
+    {
+        Ok(r) => r,
+        Err(e) => {
+            println!("Failed to export stats: {e}");
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::Empty)
+                .unwrap();
+        }
+    };
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, format.content_type())
+        .header(
+            CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"robochick-stats-{range_param}.{}\"",
+                format.as_ref()
+            ),
+        )
+        .body(Body::from(rendered))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use aws_sdk_dynamodb::{Client, operation::scan::ScanOutput, types::AttributeValue};
+    use aws_smithy_mocks::{Rule, mock, mock_client};
+    use chrono::{Duration, TimeZone, Utc};
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        robochick::twitch::MessageComponents,
+        state::{MemoryStore, StateStore},
+        stats_export::{ExportFormat, export, parse_range, render, weeks_between},
+    };
+
+    #[test]
+    fn parse_range_reads_days_and_weeks() -> Result<()> {
+        assert_eq!(parse_range("30d")?, Duration::days(30));
+        assert_eq!(parse_range("12w")?, Duration::days(84));
+        assert!(parse_range("0d").is_err());
+        assert!(parse_range("2y").is_err());
+        assert!(parse_range("d").is_err());
+        assert!(parse_range("1é").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn weeks_between_covers_partial_weeks_at_both_ends() {
+        let to = Utc.with_ymd_and_hms(2026, 10, 16, 12, 0, 0).unwrap();
+
+        assert_eq!(
+            weeks_between(to - Duration::days(10), to),
+            vec!["2026-W41", "2026-W42"]
+        );
+        assert_eq!(
+            weeks_between(to - Duration::days(14), to),
+            vec!["2026-W40", "2026-W41", "2026-W42"]
+        );
+    }
+
+    #[tokio::test]
+    async fn export_renders_each_section_as_csv_rows() -> Result<()> {
+        let now = Utc.with_ymd_and_hms(2026, 10, 16, 12, 0, 0).unwrap();
+        let scan_rule: Rule = mock!(Client::scan)
+            .match_requests(|r| {
+                r.filter_expression() == Some("redeemed_at >= :since")
+                    && r.expression_attribute_values()
+                        .and_then(|v| v.get(":since"))
+                        == Some(&AttributeValue::S("2026-09-16T12:00:00Z".into()))
+            })
+            .then_output(|| {
+                let item = |name: &str| {
+                    [("display_name".to_string(), AttributeValue::S(name.into()))]
+                        .into_iter()
+                        .collect()
+                };
+                ScanOutput::builder()
+                    .items(item("Clucky"))
+                    .items(item("Quackers, Jr."))
+                    .items(item("Clucky"))
+                    .build()
+            });
+        let client = mock_client!(aws_sdk_dynamodb, [&scan_rule]);
+        let store = MemoryStore::default();
+        store.record_wins(&["Jane".into()]).await?;
+        store.record_scenario("2026-W42", "cracker-trip").await?;
+        let components = MessageComponents {
+            mods: vec!["Jane".into(), "John".into()],
+            ..Default::default()
+        };
+
+        let export = export(
+            &client,
+            "ducks",
+            &store,
+            Some(&components),
+            Duration::days(30),
+            now,
+        )
+        .await?;
+
+        assert_eq!(
+            render(&export, ExportFormat::Csv)?,
+            "section,name,count\n\
+            redemptions,Clucky,2\n\
+            redemptions,\"Quackers, Jr.\",1\n\
+            mod_wins,Jane,1\n\
+            mod_wins,John,0\n\
+            scenarios,cracker-trip,1\n"
+        );
+        Ok(())
+    }
+}
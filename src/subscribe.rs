@@ -0,0 +1,663 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+
+use crate::{
+    client::HelixCaller,
+    config::AppConfig,
+    offline::OfflineBehavior,
+    robochick::twitch::MessageComponents,
+    types::twitch::{Subscription, SubscriptionRequest, SubscriptionType},
+};
+
+/// Status of a subscription that's delivering notifications.
+const ENABLED: &str = "enabled";
+
+/// Status of a subscription Twitch hasn't finished verifying, which is fine for a while after
+/// it's created.
+const VERIFICATION_PENDING: &str = "webhook_callback_verification_pending";
+
+/// How long a replacement subscription gets to be verified before giving up on it, keeping
+/// the one it was to replace.
+const REPLACEMENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often Twitch is asked whether a replacement subscription has been verified.
+const REPLACEMENT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Query parameter telling a replacement's callback apart from the one it replaces, since
+/// Twitch won't take two subscriptions that only differ in their secret.
+const ROTATION_PARAM: &str = "rotation";
+
+/// One change needed to bring the subscriptions on Twitch in line with the config.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlanStep {
+    Create(SubscriptionRequest),
+    /// Subscriptions can't be edited, so a changed one is created again, and deleted once its
+    /// replacement is enabled.
+    Replace {
+        id: String,
+        request: SubscriptionRequest,
+        reason: String,
+    },
+    Delete {
+        id: String,
+        r#type: String,
+        reason: String,
+    },
+}
+
+/// What `subscribe` would change, like `terraform plan` for EventSub.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plan {
+    pub steps: Vec<PlanStep>,
+    pub unchanged: usize,
+}
+
+/// The subscriptions the config needs: redemptions of each registered reward, every event
+/// type the pipeline has rules for, chat messages when there are experiments to measure or
+/// chat commands, the stream going live when offline redemptions are queued, and channel
+/// updates when groups are for particular games.
+pub fn desired(
+    config: &AppConfig,
+    components: &MessageComponents,
+    callback: &str,
+) -> Vec<SubscriptionRequest> {
+    let redemption = SubscriptionType::CustomRewardRedemption;
+    let mut desired: Vec<SubscriptionRequest> = vec![];
+    for reward_id in [&config.feed_mods_rewards_id, &config.rubberduck_rewards_id] {
+        let mut condition = redemption.condition(&config.broadcaster_user_id);
+        condition.reward_id = Some(reward_id.clone());
+        desired.push(SubscriptionRequest {
+            r#type: redemption.as_ref().to_string(),
+            version: redemption.version().to_string(),
+            condition,
+            callback: callback.to_string(),
+        });
+    }
+
+    for rule in components.get_pipeline() {
+        let Ok(subscription_type) = rule.on.parse::<SubscriptionType>() else {
+            println!(
+                "No subscription for pipeline event {}, it isn't supported",
+                rule.on
+            );
+            continue;
+        };
+        desired.push(SubscriptionRequest {
+            r#type: rule.on.clone(),
+            version: subscription_type.version().to_string(),
+            condition: subscription_type.condition(&config.broadcaster_user_id),
+            callback: callback.to_string(),
+        });
+    }
+
+    if components.get_commands().is_some()
+        || components
+            .all_scenarios()
+            .any(|s| s.get_experiment().is_some())
+    {
+        let chat = SubscriptionType::ChatMessage;
+        desired.push(SubscriptionRequest {
+            r#type: chat.as_ref().to_string(),
+            version: chat.version().to_string(),
+            condition: chat.condition(&config.broadcaster_user_id),
+            callback: callback.to_string(),
+        });
+    }
+
+    if components.get_when_offline() == OfflineBehavior::Queue {
+        let online = SubscriptionType::StreamOnline;
+        desired.push(SubscriptionRequest {
+            r#type: online.as_ref().to_string(),
+            version: online.version().to_string(),
+            condition: online.condition(&config.broadcaster_user_id),
+            callback: callback.to_string(),
+        });
+    }
+
+    if components.groups.iter().any(|g| !g.games.is_empty()) {
+        let update = SubscriptionType::ChannelUpdate;
+        desired.push(SubscriptionRequest {
+            r#type: update.as_ref().to_string(),
+            version: update.version().to_string(),
+            condition: update.condition(&config.broadcaster_user_id),
+            callback: callback.to_string(),
+        });
+    }
+
+    let mut unique: Vec<SubscriptionRequest> = vec![];
+    for request in desired {
+        if !unique
+            .iter()
+            .any(|u| u.r#type == request.r#type && u.condition == request.condition)
+        {
+            unique.push(request);
+        }
+    }
+    unique
+}
+
+/// Matches the existing subscriptions to the desired ones by type and condition. With
+/// `rotate_secret`, every one that's kept is replaced, so it's created with the current secret.
+pub fn plan(
+    desired: &[SubscriptionRequest],
+    existing: &[Subscription],
+    rotate_secret: bool,
+) -> Plan {
+    let mut steps = vec![];
+    let mut unchanged = 0;
+    let mut matched = vec![false; existing.len()];
+
+    for request in desired {
+        let found = existing.iter().enumerate().find(|(i, e)| {
+            !matched[*i] && e.r#type == request.r#type && e.condition == request.condition
+        });
+        let Some((i, current)) = found else {
+            steps.push(PlanStep::Create(request.clone()));
+            continue;
+        };
+        matched[i] = true;
+
+        let reason = if current.version != request.version {
+            Some(format!(
+                "version {} -> {}",
+                current.version, request.version
+            ))
+        } else if configured_callback(&current.transport.callback) != request.callback {
+            Some(format!(
+                "callback {} -> {}",
+                current.transport.callback, request.callback
+            ))
+        } else if current.status != ENABLED {
+            Some(format!("status is {}", current.status))
+        } else if rotate_secret {
+            Some("rotating the secret".into())
+        } else {
+            None
+        };
+        match reason {
+            Some(reason) => steps.push(PlanStep::Replace {
+                id: current.id.clone(),
+                request: request.clone(),
+                reason,
+            }),
+            None => unchanged += 1,
+        }
+    }
+
+    for (current, _) in existing.iter().zip(&matched).filter(|(_, m)| !**m) {
+        steps.push(PlanStep::Delete {
+            id: current.id.clone(),
+            r#type: current.r#type.clone(),
+            reason: "not in config".into(),
+        });
+    }
+
+    Plan { steps, unchanged }
+}
+
+/// The callback a subscription was created for, without the parameter added to tell a
+/// replacement apart.
+fn configured_callback(callback: &str) -> &str {
+    match callback.rfind(&format!("{ROTATION_PARAM}=")) {
+        Some(i) if i > 0 && matches!(&callback[i - 1..i], "?" | "&") => &callback[..i - 1],
+        _ => callback,
+    }
+}
+
+/// `request` with a callback of its own, so it can be created next to the subscription it
+/// replaces.
+fn replacement(request: &SubscriptionRequest, now: DateTime<Utc>) -> SubscriptionRequest {
+    let separator = match request.callback.contains('?') {
+        true => '&',
+        false => '?',
+    };
+    SubscriptionRequest {
+        callback: format!(
+            "{}{separator}{ROTATION_PARAM}={}",
+            request.callback,
+            now.timestamp()
+        ),
+        ..request.clone()
+    }
+}
+
+/// Waits for Twitch to have verified the subscription created for `request`.
+async fn wait_until_enabled(
+    client: &impl HelixCaller,
+    request: &SubscriptionRequest,
+    config: &AppConfig,
+) -> Result<()> {
+    let deadline = Instant::now() + REPLACEMENT_TIMEOUT;
+    loop {
+        let enabled = client
+            .list_eventsub_subscriptions(config)
+            .await?
+            .iter()
+            .any(|s| {
+                s.r#type == request.r#type
+                    && s.condition == request.condition
+                    && s.transport.callback == request.callback
+                    && s.status == ENABLED
+            });
+        if enabled {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(anyhow!(
+                "Replacement {} subscription ({}) wasn't enabled within {}s, kept the old one",
+                request.r#type,
+                request.condition,
+                REPLACEMENT_TIMEOUT.as_secs()
+            ));
+        }
+        tokio::time::sleep(REPLACEMENT_POLL_INTERVAL).await;
+    }
+}
+
+/// Subscriptions that aren't delivering notifications, like ones whose callback failed
+/// verification or that Twitch disabled. Ones still being verified are left out.
+pub fn unhealthy(existing: &[Subscription]) -> Vec<&Subscription> {
+    existing
+        .iter()
+        .filter(|s| s.status != ENABLED && s.status != VERIFICATION_PENDING)
+        .collect()
+}
+
+/// Works out the plan against Twitch, applying it unless `dry_run`.
+pub async fn subscribe(
+    client: &impl HelixCaller,
+    config: &AppConfig,
+    components: &MessageComponents,
+    dry_run: bool,
+    rotate_secret: bool,
+) -> Result<Plan> {
+    let callback = config.eventsub_callback_url.as_ref().ok_or(anyhow!(
+        "Set EVENTSUB_CALLBACK_URL to the bot's /eventsub URL"
+    ))?;
+    let existing = client.list_eventsub_subscriptions(config).await?;
+    let plan = plan(
+        &desired(config, components, callback),
+        &existing,
+        rotate_secret,
+    );
+    if !dry_run {
+        apply(client, &plan, config).await?;
+    }
+    Ok(plan)
+}
+
+/// Makes the changes in `plan` on Twitch.
+async fn apply(client: &impl HelixCaller, plan: &Plan, config: &AppConfig) -> Result<()> {
+    for step in &plan.steps {
+        match step {
+            PlanStep::Create(request) => {
+                client.create_eventsub_subscription(request, config).await?
+            }
+            // The old subscription keeps delivering until its replacement has been verified.
+            PlanStep::Replace { id, request, .. } => {
+                let replacement = replacement(request, Utc::now());
+                client
+                    .create_eventsub_subscription(&replacement, config)
+                    .await?;
+                wait_until_enabled(client, &replacement, config).await?;
+                client.delete_eventsub_subscription(id, config).await?;
+            }
+            PlanStep::Delete { id, .. } => client.delete_eventsub_subscription(id, config).await?,
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for PlanStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanStep::Create(r) => {
+                write!(f, "+ create {} v{} ({})", r.r#type, r.version, r.condition)
+            }
+            PlanStep::Replace {
+                id,
+                request,
+                reason,
+            } => write!(
+                f,
+                "~ replace {} {id} ({}): {reason}",
+                request.r#type, request.condition
+            ),
+            PlanStep::Delete { id, r#type, reason } => {
+                write!(f, "- delete {} {id}: {reason}", r#type)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in &self.steps {
+            writeln!(f, "{step}")?;
+        }
+        let count = |pred: fn(&PlanStep) -> bool| self.steps.iter().filter(|s| pred(s)).count();
+        writeln!(
+            f,
+            "Plan: {} to create, {} to replace, {} to delete, {} unchanged.",
+            count(|s| matches!(s, PlanStep::Create(_))),
+            count(|s| matches!(s, PlanStep::Replace { .. })),
+            count(|s| matches!(s, PlanStep::Delete { .. })),
+            self.unchanged
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use mockall::{Sequence, mock, predicate};
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use crate::{
+        channel::ChannelInfo,
+        client::HelixCaller,
+        config::AppConfig,
+        permissions::UserRoles,
+        reward::mod_feeder::read_config,
+        robochick::twitch::{MessageComponents, ScenarioGroup},
+        schedule::ScheduleSegment,
+        subscribe::{Plan, PlanStep, apply, desired, plan, unhealthy},
+        types::twitch::{Subscription, SubscriptionRequest},
+        users::TwitchUser,
+    };
+
+    mock! {
+        pub Helix {}
+
+        #[async_trait]
+        impl HelixCaller for Helix {
+            async fn get_chatters(&self, config: &AppConfig) -> Result<Vec<String>>;
+            async fn create_poll(
+                &self,
+                title: &str,
+                choices: &[String],
+                duration_secs: u64,
+                config: &AppConfig,
+            ) -> Result<String>;
+            async fn get_poll_results(
+                &self,
+                poll_id: &str,
+                config: &AppConfig,
+            ) -> Result<Vec<(String, u64)>>;
+            async fn get_stream_id(&self, config: &AppConfig) -> Result<Option<String>>;
+            async fn get_channel_info(&self, config: &AppConfig) -> Result<ChannelInfo>;
+            async fn get_schedule(&self, config: &AppConfig) -> Result<Vec<ScheduleSegment>>;
+            async fn set_reward_paused(
+                &self,
+                reward_id: &str,
+                paused: bool,
+                config: &AppConfig,
+            ) -> Result<()>;
+            async fn get_user_roles(&self, user_id: &str, config: &AppConfig) -> Result<UserRoles>;
+            async fn get_users(&self, logins: &[String], config: &AppConfig) -> Result<Vec<TwitchUser>>;
+            async fn cancel_redemption(
+                &self,
+                reward_id: &str,
+                redemption_id: &str,
+                config: &AppConfig,
+            ) -> Result<()>;
+            async fn create_eventsub_subscription(
+                &self,
+                request: &SubscriptionRequest,
+                config: &AppConfig,
+            ) -> Result<()>;
+            async fn list_eventsub_subscriptions(
+                &self,
+                config: &AppConfig,
+            ) -> Result<Vec<Subscription>>;
+            async fn delete_eventsub_subscription(&self, id: &str, config: &AppConfig) -> Result<()>;
+        }
+    }
+
+    fn existing(
+        id: &str,
+        r#type: &str,
+        version: &str,
+        condition: serde_json::Value,
+    ) -> Subscription {
+        serde_json::from_value(json!({
+            "id": id,
+            "type": r#type,
+            "version": version,
+            "status": "enabled",
+            "cost": 0,
+            "condition": condition,
+            "transport": {"method": "webhook", "callback": "https://bot.example/eventsub"},
+            "created_at": "2026-10-01T00:00:00Z"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn unhealthy_skips_enabled_and_pending_subscriptions() {
+        let with_status = |id: &str, status: &str| {
+            let mut s = existing(
+                id,
+                "channel.cheer",
+                "1",
+                json!({"broadcaster_user_id": "1337"}),
+            );
+            s.status = status.into();
+            s
+        };
+        let subscriptions = [
+            with_status("a", "enabled"),
+            with_status("b", "webhook_callback_verification_pending"),
+            with_status("c", "webhook_callback_verification_failed"),
+            with_status("d", "notification_failures_exceeded"),
+        ];
+
+        let ids: Vec<&str> = unhealthy(&subscriptions).iter().map(|s| s.id()).collect();
+
+        assert_eq!(ids, vec!["c", "d"]);
+    }
+
+    #[test]
+    fn plan_creates_replaces_and_deletes_to_match_config() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let config = AppConfig::from_env();
+        let components = read_config(&"resources/tests/message_components_pipeline.json".into())?;
+        let desired = desired(&config, &components, "https://bot.example/eventsub");
+
+        let plan = plan(
+            &desired,
+            &[
+                existing(
+                    "a",
+                    "channel.channel_points_custom_reward_redemption.add",
+                    "1",
+                    json!({"broadcaster_user_id": "1337", "reward_id": config.feed_mods_rewards_id}),
+                ),
+                existing(
+                    "b",
+                    "channel.cheer",
+                    "beta",
+                    json!({"broadcaster_user_id": "1337"}),
+                ),
+                existing(
+                    "c",
+                    "channel.raid",
+                    "1",
+                    json!({"from_broadcaster_user_id": "", "to_broadcaster_user_id": "1337"}),
+                ),
+            ],
+            false,
+        );
+
+        assert_eq!(
+            plan.to_string(),
+            format!(
+                "+ create channel.channel_points_custom_reward_redemption.add v1 \
+                (broadcaster_user_id=1337, reward_id={})\n\
+                ~ replace channel.cheer b (broadcaster_user_id=1337): version beta -> 1\n\
+                + create channel.follow v2 (broadcaster_user_id=1337, moderator_user_id=1337)\n\
+                + create channel.update v2 (broadcaster_user_id=1337)\n\
+                - delete channel.raid c: not in config\n\
+                Plan: 3 to create, 1 to replace, 1 to delete, 1 unchanged.\n",
+                config.rubberduck_rewards_id
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rotating_the_secret_replaces_subscriptions_that_are_kept() -> Result<()> {
+        let request: SubscriptionRequest = serde_json::from_value(json!({
+            "type": "channel.cheer",
+            "version": "1",
+            "condition": {"broadcaster_user_id": "1337"},
+            "callback": "https://bot.example/eventsub",
+        }))?;
+        let current = [existing(
+            "a",
+            "channel.cheer",
+            "1",
+            json!({"broadcaster_user_id": "1337"}),
+        )];
+
+        let desired = [request.clone()];
+
+        assert_eq!(plan(&desired, &current, false).unchanged, 1);
+        assert_eq!(
+            plan(&desired, &current, true).steps,
+            vec![PlanStep::Replace {
+                id: "a".into(),
+                request,
+                reason: "rotating the secret".into(),
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn queueing_offline_redemptions_subscribes_to_stream_online() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let config = AppConfig::from_env();
+        let components = read_config(&"resources/tests/message_components_offline.json".into())?;
+
+        let desired = desired(&config, &components, "https://bot.example/eventsub");
+
+        let online = desired
+            .iter()
+            .find(|d| d.r#type == "stream.online")
+            .unwrap();
+        assert_eq!(
+            online.condition.broadcaster_user_id.as_deref(),
+            Some("1337")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn game_groups_subscribe_to_channel_updates() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let config = AppConfig::from_env();
+        let mut components =
+            read_config(&"resources/tests/message_components_offline.json".into())?;
+        let updates = |components: &MessageComponents| {
+            desired(&config, components, "https://bot.example/eventsub")
+                .iter()
+                .filter(|d| d.r#type == "channel.update")
+                .count()
+        };
+        assert_eq!(updates(&components), 0);
+
+        components.groups.push(ScenarioGroup {
+            name: "minecraft".into(),
+            games: vec!["27471".into()],
+            ..Default::default()
+        });
+
+        assert_eq!(updates(&components), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replacements_are_enabled_before_the_old_subscription_is_deleted() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let config = AppConfig::from_env();
+        let request: SubscriptionRequest = serde_json::from_value(json!({
+            "type": "channel.cheer",
+            "version": "1",
+            "condition": {"broadcaster_user_id": "1337"},
+            "callback": "https://bot.example/eventsub",
+        }))?;
+        let plan = Plan {
+            steps: vec![PlanStep::Replace {
+                id: "a".into(),
+                request,
+                reason: "rotating the secret".into(),
+            }],
+            unchanged: 0,
+        };
+
+        let mut seq = Sequence::new();
+        let mut client = MockHelix::new();
+        client
+            .expect_create_eventsub_subscription()
+            .withf(|r, _| {
+                r.callback
+                    .starts_with("https://bot.example/eventsub?rotation=")
+            })
+            .return_once(|_, _| Ok(()))
+            .once()
+            .in_sequence(&mut seq);
+        client
+            .expect_list_eventsub_subscriptions()
+            .return_once(|_| {
+                let mut replacement = existing(
+                    "b",
+                    "channel.cheer",
+                    "1",
+                    json!({"broadcaster_user_id": "1337"}),
+                );
+                replacement.transport.callback = format!(
+                    "https://bot.example/eventsub?rotation={}",
+                    chrono::Utc::now().timestamp()
+                );
+                Ok(vec![replacement])
+            })
+            .once()
+            .in_sequence(&mut seq);
+        client
+            .expect_delete_eventsub_subscription()
+            .with(predicate::eq("a"), predicate::always())
+            .return_once(|_, _| Ok(()))
+            .once()
+            .in_sequence(&mut seq);
+
+        apply(&client, &plan, &config).await
+    }
+
+    #[test]
+    fn replacements_match_their_configured_callback() {
+        let request: SubscriptionRequest = serde_json::from_value(json!({
+            "type": "channel.cheer",
+            "version": "1",
+            "condition": {"broadcaster_user_id": "1337"},
+            "callback": "https://bot.example/eventsub",
+        }))
+        .unwrap();
+        let mut current = existing(
+            "a",
+            "channel.cheer",
+            "1",
+            json!({"broadcaster_user_id": "1337"}),
+        );
+        current.transport.callback = "https://bot.example/eventsub?rotation=1790000000".into();
+
+        assert_eq!(plan(&[request], &[current], false).unchanged, 1);
+    }
+}
@@ -0,0 +1,73 @@
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Longest value, in grapheme clusters, a placeholder is filled with, so a long redemption
+/// input can't crowd out the rest of the message.
+pub const MAX_FIELD_LEN: usize = 200;
+
+/// `s` in NFC with control characters dropped, so the same name typed two ways looks and
+/// counts the same.
+pub fn normalize(s: &str) -> String {
+    s.nfc().filter(|c| !c.is_control()).collect()
+}
+
+/// Length of `s` in grapheme clusters, i.e. what a viewer would count as characters. An emoji
+/// with skin tone or a letter with a combining accent is one.
+pub fn len(s: &str) -> usize {
+    s.graphemes(true).count()
+}
+
+/// The first `max` grapheme clusters of `s`, never splitting one.
+pub fn truncate(s: &str, max: usize) -> &str {
+    match s.grapheme_indices(true).nth(max) {
+        Some((end, _)) => &s[..end],
+        None => s,
+    }
+}
+
+/// Marks a value that was cut short.
+const ELLIPSIS: &str = "...";
+
+/// `s` cut down to at most `max` grapheme clusters, the last of them `...` when anything was
+/// cut and there's room for it.
+pub fn shorten(s: &str, max: usize) -> String {
+    if len(s) <= max {
+        return s.to_string();
+    }
+    match max.checked_sub(ELLIPSIS.len()) {
+        Some(keep) if keep > 0 => format!("{}{ELLIPSIS}", truncate(s, keep).trim_end()),
+        _ => truncate(s, max).to_string(),
+    }
+}
+
+/// A user-provided value made safe to put in a chat message: normalized and shortened to
+/// [`MAX_FIELD_LEN`].
+pub fn sanitize(s: &str) -> String {
+    shorten(&normalize(s), MAX_FIELD_LEN)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::text::{len, normalize, sanitize, shorten};
+
+    #[test]
+    fn length_counts_graphemes_after_normalizing() {
+        let decomposed = "Cooler_U\u{308}ser";
+
+        assert_eq!(normalize(decomposed), "Cooler_Üser");
+        assert_eq!(len(decomposed), 11);
+        assert_eq!(len("👍🏽🐔"), 2);
+        assert_eq!(normalize("bok\u{0}\nbok"), "bokbok");
+    }
+
+    #[test]
+    fn shorten_never_splits_a_grapheme() {
+        assert_eq!(shorten("🐔👍🏽🐔🐔🐔🐔", 5), "🐔👍🏽...");
+        assert_eq!(shorten("bok bok", 7), "bok bok");
+        assert_eq!(shorten("bok bok", 6), "bok...");
+        assert_eq!(shorten("bok bok", 2), "bo");
+        assert_eq!(len(&sanitize(&"e\u{301}".repeat(300))), 200);
+    }
+}
@@ -0,0 +1,106 @@
+use axum::{extract::Request, http::HeaderValue, middleware::Next, response::Response};
+use reqwest::RequestBuilder;
+
+/// Header the correlation id travels in, on inbound requests, their responses and every call
+/// made to StreamElements and Helix while handling them.
+pub const CORRELATION_HEADER: &str = "X-Correlation-Id";
+
+tokio::task_local! {
+    /// Id tying together everything done for one inbound event.
+    static CORRELATION_ID: String;
+}
+
+/// Runs `fut` with `id` as the correlation id of everything it does.
+pub async fn with_correlation_id<F: Future>(id: String, fut: F) -> F::Output {
+    CORRELATION_ID.scope(id, fut).await
+}
+
+pub fn current() -> Option<String> {
+    CORRELATION_ID.try_with(String::clone).ok()
+}
+
+/// A random id, 32 hex characters.
+pub fn new_id() -> String {
+    format!("{:016x}{:016x}", fastrand::u64(..), fastrand::u64(..))
+}
+
+/// Adds the correlation id, if there is one, to an outbound request.
+pub fn tag(request: RequestBuilder) -> RequestBuilder {
+    match current() {
+        Some(id) => request.header(CORRELATION_HEADER, id),
+        None => request,
+    }
+}
+
+/// Handles each request under a correlation id: the caller's from [`CORRELATION_HEADER`], or a
+/// new one. The id is logged and sent back in the response.
+pub async fn correlate(request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(CORRELATION_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .filter(|h| !h.is_empty() && h.len() <= 128)
+        .map(str::to_string)
+        .unwrap_or_else(new_id);
+    println!(
+        "{} {} correlation id {id}",
+        request.method(),
+        request.uri().path()
+    );
+
+    let mut response = with_correlation_id(id.clone(), next.run(request)).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(CORRELATION_HEADER, value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, body::Body, http::Request, middleware, routing::get};
+    use pretty_assertions::assert_eq;
+    use tower::ServiceExt;
+
+    use crate::trace::{CORRELATION_HEADER, correlate, current, tag};
+
+    #[tokio::test]
+    async fn requests_keep_callers_correlation_id() {
+        let app = Router::new()
+            .route("/", get(|| async { current().unwrap_or_default() }))
+            .layer(middleware::from_fn(correlate));
+
+        let response = app
+            .oneshot(
+                Request::get("/")
+                    .header(CORRELATION_HEADER, "trace-me")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.headers()[CORRELATION_HEADER], "trace-me");
+        let body = axum::body::to_bytes(response.into_body(), 64)
+            .await
+            .unwrap();
+        assert_eq!(body, "trace-me");
+    }
+
+    #[tokio::test]
+    async fn outbound_requests_are_tagged_inside_scope() {
+        let client = reqwest::Client::new();
+        let tagged = crate::trace::with_correlation_id("abc".into(), async {
+            tag(client.get("http://localhost/")).build().unwrap()
+        })
+        .await;
+
+        assert_eq!(tagged.headers()[CORRELATION_HEADER], "abc");
+        assert!(
+            tag(client.get("http://localhost/"))
+                .build()
+                .unwrap()
+                .headers()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::config::AppConfig;
+
+/// What `twitch event trigger` and `twitch event verify-subscription` put in the subscription's
+/// transport, where Twitch has the callback URL.
+const CLI_CALLBACK: &str = "null";
+
+/// Whether an EventSub message was made up by the Twitch CLI rather than sent by Twitch.
+pub fn is_cli_event(payload: &[u8]) -> bool {
+    serde_json::from_slice::<Value>(payload)
+        .is_ok_and(|p| p["subscription"]["transport"]["callback"] == CLI_CALLBACK)
+}
+
+/// Rewrites the ids the CLI makes up unless they're given on the command line, so its events
+/// read as if they were for this channel: the broadcaster (`--to-user`) becomes
+/// `BROADCASTER_USER_ID`, and a reward nothing handles (`--item-id`) `FEED_MODS_REWARD_ID`.
+pub fn adapt(
+    payload: &str,
+    config: &AppConfig,
+    handles_reward: impl Fn(&str) -> bool,
+) -> Result<String> {
+    let mut payload: Value = serde_json::from_str(payload)?;
+
+    for fields in ["/subscription/condition", "/event"] {
+        for key in ["broadcaster_user_id", "to_broadcaster_user_id"] {
+            if let Some(id) = payload.pointer_mut(&format!("{fields}/{key}")) {
+                *id = config.broadcaster_user_id.clone().into();
+            }
+        }
+    }
+
+    let reward_id = payload["event"]["reward"]["id"]
+        .as_str()
+        .map(str::to_string);
+    if let Some(reward_id) = reward_id
+        && !handles_reward(&reward_id)
+    {
+        println!("Nothing handles Twitch CLI reward {reward_id}, redeeming the mod feed instead");
+        for path in ["/event/reward/id", "/subscription/condition/reward_id"] {
+            if let Some(id) = payload.pointer_mut(path) {
+                *id = config.feed_mods_rewards_id.clone().into();
+            }
+        }
+    }
+
+    Ok(serde_json::to_string(&payload)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+
+    use crate::{
+        config::AppConfig,
+        twitch_cli::{adapt, is_cli_event},
+    };
+
+    #[test]
+    fn adapt_points_cli_events_at_this_channel() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let config = AppConfig::from_env();
+        let payload = std::fs::read_to_string("resources/tests/twitch_cli_redemption.json")?;
+        let raid = r#"{"subscription": {"transport": {"method": "webhook", "callback": "null"}},
+            "event": {"from_broadcaster_user_id": "1234", "to_broadcaster_user_id": "5678"}}"#;
+
+        let redemption: Value = serde_json::from_str(&adapt(&payload, &config, |_| false)?)?;
+        let kept: Value = serde_json::from_str(&adapt(&payload, &config, |_| true)?)?;
+        let raid: Value = serde_json::from_str(&adapt(raid, &config, |_| false)?)?;
+
+        assert!(is_cli_event(payload.as_bytes()));
+        assert_eq!(redemption["event"]["broadcaster_user_id"], "1337");
+        assert_eq!(
+            redemption["subscription"]["condition"]["broadcaster_user_id"],
+            "1337"
+        );
+        assert_eq!(
+            redemption["event"]["reward"]["id"],
+            config.feed_mods_rewards_id.as_str()
+        );
+        assert_eq!(
+            kept["event"]["reward"]["id"],
+            "0a6d7c1b-9f3e-4e2a-b8d5-3c4f1e2a7b90"
+        );
+        assert_eq!(raid["event"]["from_broadcaster_user_id"], "1234");
+        assert_eq!(raid["event"]["to_broadcaster_user_id"], "1337");
+        Ok(())
+    }
+
+    #[test]
+    fn events_from_twitch_arent_cli_events() -> Result<()> {
+        let payload = std::fs::read_to_string(
+            "resources/tests/payloads/channel.channel_points_custom_reward_redemption.add.v1.json",
+        )?;
+
+        assert!(!is_cli_event(payload.as_bytes()));
+        assert!(!is_cli_event(b"not json"));
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 pub mod twitch {
     use std::fmt::Display;
 
+    use chrono::{DateTime, Utc};
     use serde::{Deserialize, Serialize};
     use strum::{AsRefStr, EnumString};
 
@@ -32,16 +33,86 @@ pub mod twitch {
         Revocation,
     }
 
-    #[derive(Debug, AsRefStr, EnumString)]
+    #[derive(Debug, Clone, Copy, PartialEq, AsRefStr, EnumString)]
     pub enum SubscriptionType {
         #[strum(serialize = "channel.channel_points_custom_reward_redemption.add")]
         CustomRewardRedemption,
+        #[strum(serialize = "channel.cheer")]
+        Cheer,
+        #[strum(serialize = "channel.follow")]
+        Follow,
+        #[strum(serialize = "channel.subscribe")]
+        Subscribe,
+        #[strum(serialize = "channel.raid")]
+        Raid,
+        #[strum(serialize = "channel.chat.message")]
+        ChatMessage,
+        #[strum(serialize = "stream.online")]
+        StreamOnline,
+        #[strum(serialize = "channel.update")]
+        ChannelUpdate,
+    }
+
+    impl SubscriptionType {
+        /// Version of the subscription type the bot subscribes to.
+        pub fn version(&self) -> &'static str {
+            match self {
+                SubscriptionType::Follow | SubscriptionType::ChannelUpdate => "2",
+                _ => "1",
+            }
+        }
+
+        /// Condition matching the type's events in `broadcaster_user_id`'s channel.
+        pub fn condition(&self, broadcaster_user_id: &str) -> Condition {
+            let id = Some(broadcaster_user_id.to_string());
+            match self {
+                SubscriptionType::Raid => Condition {
+                    to_broadcaster_user_id: id,
+                    ..Default::default()
+                },
+                SubscriptionType::Follow => Condition {
+                    broadcaster_user_id: id.clone(),
+                    moderator_user_id: id,
+                    ..Default::default()
+                },
+                SubscriptionType::ChatMessage => Condition {
+                    broadcaster_user_id: id.clone(),
+                    user_id: id,
+                    ..Default::default()
+                },
+                _ => Condition {
+                    broadcaster_user_id: id,
+                    ..Default::default()
+                },
+            }
+        }
+    }
+
+    /// Why Twitch revoked a subscription, from its `status`.
+    #[derive(Debug, Clone, Copy, PartialEq, AsRefStr, EnumString)]
+    pub enum RevocationReason {
+        /// The broadcaster withdrew the app's authorization.
+        #[strum(serialize = "authorization_revoked")]
+        AuthorizationRevoked,
+        /// The broadcaster's account no longer exists.
+        #[strum(serialize = "user_removed")]
+        UserRemoved,
+        /// Too many notifications went unanswered.
+        #[strum(serialize = "notification_failures_exceeded")]
+        NotificationFailuresExceeded,
+        /// The subscription type or version is no longer supported.
+        #[strum(serialize = "version_removed")]
+        VersionRemoved,
     }
 
     #[derive(Serialize, Deserialize, Debug)]
     pub struct RewardRedeemed {
         pub(crate) subscription: Subscription,
         pub(crate) event: RewardEvent,
+        /// When Twitch sent the notification, from the `Twitch-Eventsub-Message-Timestamp`
+        /// header.
+        #[serde(skip)]
+        pub(crate) sent_at: Option<DateTime<Utc>>,
     }
 
     impl RewardRedeemed {
@@ -52,18 +123,92 @@ pub mod twitch {
         pub fn reward_id(&self) -> &str {
             &self.event.reward.id
         }
+
+        pub fn reward_title(&self) -> &str {
+            &self.event.reward.title
+        }
+
+        pub fn reward_cost(&self) -> u64 {
+            self.event.reward.cost
+        }
+
+        pub fn reward_prompt(&self) -> &str {
+            self.event.reward.prompt()
+        }
+
+        /// Id of this redemption, needed to fulfil or cancel it.
+        pub fn redemption_id(&self) -> &str {
+            &self.event.id
+        }
+
+        pub fn user_id(&self) -> &str {
+            self.event.user_id()
+        }
+
+        pub fn user_login(&self) -> &str {
+            self.event.user_login()
+        }
+
+        /// Display name of the redeemer.
+        pub fn user_name(&self) -> &str {
+            self.event.user_name()
+        }
+
+        /// What the redeemer typed, empty for rewards without input.
+        pub fn user_input(&self) -> &str {
+            self.event.user_input()
+        }
+
+        pub fn redeemed_at(&self) -> &Timestamp {
+            self.event.redeemed_at()
+        }
+
+        pub fn subscription(&self) -> &Subscription {
+            &self.subscription
+        }
     }
 
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Debug, Clone)]
     pub struct Subscription {
-        id: String,
-        r#type: String,
-        version: String,
-        status: String,
-        cost: u16,
-        condition: Condition,
-        transport: Transport,
-        created_at: String,
+        pub(crate) id: String,
+        pub(crate) r#type: String,
+        pub(crate) version: String,
+        pub(crate) status: String,
+        cost: u64,
+        pub(crate) condition: Condition,
+        pub(crate) transport: Transport,
+        created_at: DateTime<Utc>,
+    }
+
+    impl Subscription {
+        pub fn id(&self) -> &str {
+            &self.id
+        }
+
+        pub fn subscription_type(&self) -> &str {
+            &self.r#type
+        }
+
+        pub fn version(&self) -> &str {
+            &self.version
+        }
+
+        /// `enabled` while notifications are delivered, see [`RevocationReason`] for the rest.
+        pub fn status(&self) -> &str {
+            &self.status
+        }
+
+        pub fn cost(&self) -> u64 {
+            self.cost
+        }
+
+        pub fn condition(&self) -> &Condition {
+            &self.condition
+        }
+
+        pub fn created_at(&self) -> DateTime<Utc> {
+            self.created_at
+        }
     }
 
     #[derive(Serialize, Deserialize, Debug)]
@@ -75,44 +220,186 @@ pub mod twitch {
         user_id: String,
         user_login: String,
         user_name: String,
-        user_input: String,
+        /// Absent or null for rewards that don't ask for input.
+        #[serde(default)]
+        user_input: Option<String>,
         status: String,
         reward: Reward,
-        redeemed_at: String,
+        redeemed_at: Timestamp,
     }
 
     impl RewardEvent {
-        pub(crate) fn username(&self) -> &str {
+        pub fn id(&self) -> &str {
+            &self.id
+        }
+
+        pub fn user_login(&self) -> &str {
             &self.user_login
         }
 
-        pub(crate) fn display_name(&self) -> &str {
+        pub fn user_id(&self) -> &str {
+            &self.user_id
+        }
+
+        pub fn user_name(&self) -> &str {
             &self.user_name
         }
 
-        pub(crate) fn redeemed_at(&self) -> &str {
+        pub fn user_input(&self) -> &str {
+            self.user_input.as_deref().unwrap_or_default()
+        }
+
+        /// `unfulfilled`, `fulfilled` or `canceled`.
+        pub fn status(&self) -> &str {
+            &self.status
+        }
+
+        pub fn reward(&self) -> &Reward {
+            &self.reward
+        }
+
+        pub fn redeemed_at(&self) -> &Timestamp {
             &self.redeemed_at
         }
     }
 
-    #[derive(Serialize, Deserialize, Debug)]
+    /// An RFC 3339 time from Twitch, kept as it was sent as well as parsed, so it's stored
+    /// unchanged.
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    #[serde(try_from = "String", into = "String")]
+    pub struct Timestamp {
+        raw: String,
+        at: DateTime<Utc>,
+    }
+
+    impl Timestamp {
+        pub fn at(&self) -> DateTime<Utc> {
+            self.at
+        }
+    }
+
+    impl Display for Timestamp {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str(&self.raw)
+        }
+    }
+
+    impl TryFrom<String> for Timestamp {
+        type Error = chrono::ParseError;
+
+        fn try_from(raw: String) -> Result<Self, Self::Error> {
+            let at = DateTime::parse_from_rfc3339(&raw)?.with_timezone(&Utc);
+            Ok(Timestamp { raw, at })
+        }
+    }
+
+    impl From<Timestamp> for String {
+        fn from(timestamp: Timestamp) -> Self {
+            timestamp.raw
+        }
+    }
+
+    /// Which events a subscription is for. Twitch lists conditions with every field of the
+    /// type, the unused ones empty, so empty fields read as unset.
+    #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
     pub struct Condition {
-        broadcaster_user_id: String,
-        reward_id: Option<String>,
+        #[serde(
+            default,
+            deserialize_with = "non_empty",
+            skip_serializing_if = "Option::is_none"
+        )]
+        pub(crate) broadcaster_user_id: Option<String>,
+        #[serde(
+            default,
+            deserialize_with = "non_empty",
+            skip_serializing_if = "Option::is_none"
+        )]
+        pub(crate) to_broadcaster_user_id: Option<String>,
+        #[serde(
+            default,
+            deserialize_with = "non_empty",
+            skip_serializing_if = "Option::is_none"
+        )]
+        pub(crate) moderator_user_id: Option<String>,
+        #[serde(
+            default,
+            deserialize_with = "non_empty",
+            skip_serializing_if = "Option::is_none"
+        )]
+        pub(crate) reward_id: Option<String>,
+        /// User chat is read as, for chat events.
+        #[serde(
+            default,
+            deserialize_with = "non_empty",
+            skip_serializing_if = "Option::is_none"
+        )]
+        pub(crate) user_id: Option<String>,
     }
 
-    #[derive(Serialize, Deserialize, Debug)]
+    fn non_empty<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<String>, D::Error> {
+        let value = Option::<String>::deserialize(d)?;
+        Ok(value.filter(|v| !v.is_empty()))
+    }
+
+    impl Display for Condition {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let fields = [
+                ("broadcaster_user_id", &self.broadcaster_user_id),
+                ("to_broadcaster_user_id", &self.to_broadcaster_user_id),
+                ("moderator_user_id", &self.moderator_user_id),
+                ("reward_id", &self.reward_id),
+                ("user_id", &self.user_id),
+            ];
+            let set: Vec<String> = fields
+                .iter()
+                .filter_map(|(k, v)| v.as_ref().map(|v| format!("{k}={v}")))
+                .collect();
+            write!(f, "{}", set.join(", "))
+        }
+    }
+
+    /// What's needed to recreate a webhook subscription. The secret comes from the config
+    /// when it's sent.
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    pub struct SubscriptionRequest {
+        pub(crate) r#type: String,
+        pub(crate) version: String,
+        pub(crate) condition: Condition,
+        pub(crate) callback: String,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone)]
     pub struct Transport {
         method: String,
-        callback: String,
+        #[serde(default)]
+        pub(crate) callback: String,
     }
 
     #[derive(Serialize, Deserialize, Debug)]
     pub struct Reward {
         id: String,
         title: String,
-        cost: u16,
-        prompt: String,
+        cost: u64,
+        #[serde(default)]
+        prompt: Option<String>,
+    }
+
+    impl Reward {
+        pub fn id(&self) -> &str {
+            &self.id
+        }
+
+        pub fn title(&self) -> &str {
+            &self.title
+        }
+
+        pub fn cost(&self) -> u64 {
+            self.cost
+        }
+
+        pub fn prompt(&self) -> &str {
+            self.prompt.as_deref().unwrap_or_default()
+        }
     }
 
     #[derive(Serialize, Deserialize, Debug)]
@@ -134,11 +421,141 @@ pub mod twitch {
         pub fn subscription_status(&self) -> &str {
             &self.subscription.status
         }
+
+        pub fn subscription_version(&self) -> &str {
+            &self.subscription.version
+        }
+
+        /// `None` for statuses this version of the bot doesn't know about.
+        pub fn reason(&self) -> Option<RevocationReason> {
+            self.subscription.status.parse().ok()
+        }
+
+        pub fn resubscribe_request(&self) -> SubscriptionRequest {
+            SubscriptionRequest {
+                r#type: self.subscription.r#type.clone(),
+                version: self.subscription.version.clone(),
+                condition: self.subscription.condition.clone(),
+                callback: self.subscription.transport.callback.clone(),
+            }
+        }
     }
 
     impl VerificationEvent {
         pub fn challenge(&self) -> &str {
             &self.challenge
         }
+
+        pub fn subscription_type(&self) -> &str {
+            &self.subscription.r#type
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+
+    use crate::types::twitch::{Condition, RewardRedeemed, Subscription, SubscriptionType};
+
+    /// The payload corpus, one notification per subscription type and version, named
+    /// `{type}.v{version}.json`.
+    fn payloads() -> Result<Vec<(String, Value)>> {
+        let mut payloads = vec![];
+        for entry in std::fs::read_dir("resources/tests/payloads")? {
+            let path = entry?.path();
+            let name = path.file_name().unwrap().to_string_lossy().to_string();
+            payloads.push((
+                name,
+                serde_json::from_str(&std::fs::read_to_string(&path)?)?,
+            ));
+        }
+        payloads.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(payloads)
+    }
+
+    #[test]
+    fn payloads_match_the_subscriptions_the_bot_makes() -> Result<()> {
+        let payloads = payloads()?;
+        assert_eq!(payloads.len(), 8);
+
+        for (name, payload) in payloads {
+            let subscription: Subscription =
+                serde_json::from_value(payload["subscription"].clone())?;
+            let subscription_type: SubscriptionType = subscription.subscription_type().parse()?;
+
+            assert_eq!(
+                name,
+                format!(
+                    "{}.v{}.json",
+                    subscription_type.as_ref(),
+                    subscription_type.version()
+                )
+            );
+            // Redemptions are subscribed to per reward.
+            let condition = Condition {
+                reward_id: None,
+                ..subscription.condition().clone()
+            };
+            assert_eq!(condition, subscription_type.condition("1337"), "{name}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn redemption_payload_round_trips() -> Result<()> {
+        let payload: Value = serde_json::from_str(&std::fs::read_to_string(
+            "resources/tests/payloads/channel.channel_points_custom_reward_redemption.add.v1.json",
+        )?)?;
+
+        let redemption: RewardRedeemed = serde_json::from_value(payload.clone())?;
+
+        assert_eq!(serde_json::to_value(&redemption)?, payload);
+        Ok(())
+    }
+
+    #[test]
+    fn redeemed_at_keeps_the_time_as_twitch_sent_it() -> Result<()> {
+        let payload = std::fs::read_to_string("resources/tests/reward_redemption_event.json")?;
+
+        let redemption: RewardRedeemed = serde_json::from_str(&payload)?;
+
+        assert_eq!(
+            redemption.redeemed_at().to_string(),
+            "2020-07-15T17:16:03.17106713Z"
+        );
+        assert_eq!(redemption.redeemed_at().at().timestamp(), 1594833363);
+        Ok(())
+    }
+
+    #[test]
+    fn reward_costs_above_u16_are_read() -> Result<()> {
+        let payload = std::fs::read_to_string("resources/tests/reward_redemption_large_cost.json")?;
+
+        let redemption: RewardRedeemed = serde_json::from_str(&payload)?;
+
+        assert_eq!(redemption.reward_cost(), 1_000_000);
+        assert_eq!(redemption.subscription().cost(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn missing_or_null_input_and_prompt_read_as_empty() -> Result<()> {
+        let payload =
+            std::fs::read_to_string("resources/tests/reward_redemption_without_input.json")?;
+        let mut without_input: Value = serde_json::from_str(&payload)?;
+        if let Some(event) = without_input["event"].as_object_mut() {
+            event.remove("user_input");
+        }
+
+        for payload in [serde_json::from_str(&payload)?, without_input] {
+            let redemption: RewardRedeemed = serde_json::from_value(payload)?;
+
+            assert_eq!(redemption.user_input(), "");
+            assert_eq!(redemption.reward_prompt(), "");
+        }
+        Ok(())
     }
 }
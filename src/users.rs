@@ -0,0 +1,150 @@
+use std::{collections::HashMap, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{cache::TtlCache, client::HelixCaller, config::AppConfig};
+
+/// Most logins Helix looks up in one request.
+pub const LOGINS_PER_REQUEST: usize = 100;
+
+/// A Twitch user as Helix describes them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TwitchUser {
+    pub id: String,
+    pub login: String,
+    /// The name as the user writes it, with their capitalization or in their own script.
+    pub display_name: String,
+    #[serde(default)]
+    pub profile_image_url: String,
+}
+
+/// Whether `name` can be a Twitch login: 1 to 25 letters, digits or underscores.
+pub fn is_login(name: &str) -> bool {
+    (1..=25).contains(&name.len()) && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Twitch users by login, so names in the config and the stats can be shown the way their
+/// owners write them. Logins without a user are remembered too, to not look them up on every
+/// message.
+pub struct UserCache {
+    users: TtlCache<String, Option<TwitchUser>>,
+}
+
+impl UserCache {
+    pub fn new(ttl: Duration) -> Self {
+        UserCache {
+            users: TtlCache::new(ttl),
+        }
+    }
+
+    /// The users of those of `names` that are logins, by name as given. Names that aren't
+    /// logins or have no user are left out, as are all that weren't cached when Helix fails.
+    pub async fn lookup(
+        &self,
+        client: &(impl HelixCaller + ?Sized),
+        names: &[String],
+        config: &AppConfig,
+    ) -> HashMap<String, TwitchUser> {
+        let logins: Vec<(&String, String)> = names
+            .iter()
+            .filter(|n| is_login(n))
+            .map(|n| (n, n.to_lowercase()))
+            .collect();
+
+        let mut missing: Vec<String> = logins
+            .iter()
+            .filter(|(_, login)| self.users.get(login).is_none())
+            .map(|(_, login)| login.clone())
+            .collect();
+        missing.sort();
+        missing.dedup();
+        for chunk in missing.chunks(LOGINS_PER_REQUEST) {
+            match client.get_users(chunk, config).await {
+                Ok(found) => {
+                    let mut found: HashMap<String, TwitchUser> =
+                        found.into_iter().map(|u| (u.login.clone(), u)).collect();
+                    for login in chunk {
+                        self.users.insert(login.clone(), found.remove(login));
+                    }
+                }
+                Err(e) => println!("Failed to look up Twitch users, showing logins: {e}"),
+            }
+        }
+
+        logins
+            .into_iter()
+            .filter_map(|(name, login)| Some((name.clone(), self.users.get(&login)??)))
+            .collect()
+    }
+
+    /// Display names of those of `names` that are Twitch logins, by name as given.
+    pub async fn display_names(
+        &self,
+        client: &(impl HelixCaller + ?Sized),
+        names: &[String],
+        config: &AppConfig,
+    ) -> HashMap<String, String> {
+        self.lookup(client, names, config)
+            .await
+            .into_iter()
+            .map(|(name, user)| (name, user.display_name))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use mockito::{Matcher, Server};
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        client::WebClient,
+        config::AppConfig,
+        users::{UserCache, is_login},
+    };
+
+    #[test]
+    fn only_login_shaped_names_are_looked_up() {
+        assert!(is_login("cooler_user"));
+        assert!(is_login("Anna"));
+        assert!(!is_login("Anna the Great"));
+        assert!(!is_login(""));
+    }
+
+    #[tokio::test]
+    async fn display_names_are_cached_along_with_missing_users() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut server = Server::new_async().await;
+        let config = AppConfig::from_env()
+            .with_twitch_api_host(format!("http://{}/helix/", server.host_with_port()))
+            .with_twitch_access_token("access-token".into());
+        let users = server
+            .mock("GET", "/helix/users")
+            .match_query(Matcher::Regex("^login=cooler_user&login=nobody$".into()))
+            .with_body(r#"{"data": [{"id": "9001", "login": "cooler_user", "display_name": "Cooler_User"}]}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let client = WebClient::new(reqwest::Client::new());
+        let cache = UserCache::new(Duration::from_secs(60));
+        let names = [
+            "Cooler_User".to_string(),
+            "nobody".to_string(),
+            "The Chicken".to_string(),
+        ];
+        let first = cache.display_names(&client, &names, &config).await;
+        let second = cache.display_names(&client, &names, &config).await;
+
+        users.assert_async().await;
+        assert_eq!(
+            first,
+            [("Cooler_User".to_string(), "Cooler_User".to_string())].into()
+        );
+        assert_eq!(second, first);
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::BTreeMap;
+
+use crate::robochick::twitch::TemplateContext;
+
+/// Deepest variables may refer to other variables, e.g. 2 for `{snack}` using `{adjective}`.
+pub const MAX_DEPTH: usize = 8;
+
+/// Expands the placeholders in each picked word, which can be other variables or values already
+/// in `context`. Errors on loops, nesting deeper than [`MAX_DEPTH`] and unknown placeholders.
+pub fn expand_all(
+    picks: &BTreeMap<String, String>,
+    context: &TemplateContext,
+) -> Result<BTreeMap<String, String>, String> {
+    picks
+        .keys()
+        .map(|name| Ok((name.clone(), expand(name, picks, context, &mut vec![])?)))
+        .collect()
+}
+
+fn expand(
+    name: &str,
+    picks: &BTreeMap<String, String>,
+    context: &TemplateContext,
+    stack: &mut Vec<String>,
+) -> Result<String, String> {
+    if let Some(start) = stack.iter().position(|n| n == name) {
+        let mut cycle = stack[start..].to_vec();
+        cycle.push(name.to_string());
+        return Err(format!(
+            "Variables refer to each other in a loop: {}",
+            cycle.join(" -> ")
+        ));
+    }
+    if stack.len() == MAX_DEPTH {
+        return Err(format!(
+            "Variable {} nests more than {MAX_DEPTH} levels deep",
+            stack[0]
+        ));
+    }
+
+    stack.push(name.to_string());
+    let mut output = String::new();
+    let mut rest = picks[name].as_str();
+    while let Some(start) = rest.find(['{', '}']) {
+        output.push_str(&rest[..start]);
+        let (brace, after) = rest[start..].split_at(1);
+
+        if let Some(escaped) = after.strip_prefix(brace) {
+            output.push_str(brace);
+            rest = escaped;
+            continue;
+        }
+        let end = match after.find('}') {
+            Some(end) if brace == "{" => end,
+            _ => {
+                output.push_str(brace);
+                rest = after;
+                continue;
+            }
+        };
+
+        let key = &after[..end];
+        if picks.contains_key(key) {
+            output.push_str(&expand(key, picks, context, stack)?);
+        } else if let Some(val) = context.get(key) {
+            output.push_str(val);
+        } else {
+            return Err(format!(
+                "Variable {name} uses {{{key}}}, which isn't a variable or placeholder"
+            ));
+        }
+        rest = &after[end + 1..];
+    }
+    output.push_str(rest);
+    stack.pop();
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        robochick::twitch::TemplateContext,
+        variables::{MAX_DEPTH, expand_all},
+    };
+
+    fn picks(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn expand_all_fills_nested_variables_and_context() {
+        let mut context = TemplateContext::default();
+        context.insert("event_user_name", "Clucky");
+
+        let expanded = expand_all(
+            &picks(&[
+                ("snack", "a {adjective} cracker from {event_user_name}"),
+                ("adjective", "{texture}, {{fresh}}"),
+                ("texture", "crunchy"),
+            ]),
+            &context,
+        )
+        .unwrap();
+
+        assert_eq!(expanded["snack"], "a crunchy, {fresh} cracker from Clucky");
+        assert_eq!(expanded["adjective"], "crunchy, {fresh}");
+    }
+
+    #[test]
+    fn expand_all_rejects_loops_deep_nesting_and_unknown_placeholders() {
+        let context = TemplateContext::default();
+
+        let looped = picks(&[("a", "{b}"), ("b", "x {c}"), ("c", "{b}")]);
+        assert_eq!(
+            expand_all(&looped, &context).unwrap_err(),
+            "Variables refer to each other in a loop: b -> c -> b"
+        );
+
+        let deep: Vec<(String, String)> = (0..=MAX_DEPTH)
+            .map(|i| (format!("v{i:02}"), format!("{{v{:02}}}", i + 1)))
+            .chain([(format!("v{:02}", MAX_DEPTH + 1), "end".to_string())])
+            .collect();
+        let deep: BTreeMap<String, String> = deep.into_iter().collect();
+        let err = expand_all(&deep, &context).unwrap_err();
+        assert!(err.contains("nests more than"), "{err}");
+
+        assert_eq!(
+            expand_all(&picks(&[("snack", "{nope}")]), &context).unwrap_err(),
+            "Variable snack uses {nope}, which isn't a variable or placeholder"
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use std::fmt;
+
+use lambda_http::{Body, Response};
+use reqwest::{StatusCode, header::CONTENT_TYPE};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+mod built_info {
+    include!(concat!(env!("OUT_DIR"), "/built.rs"));
+}
+
+/// Which build is running, as recorded by `build.rs`.
+#[derive(Serialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    /// Commit the build was made from, when it was built from a git checkout.
+    pub git_sha: Option<&'static str>,
+    /// Whether the checkout had uncommitted changes.
+    pub git_dirty: Option<bool>,
+    /// RFC 2822 in UTC.
+    pub built_at: &'static str,
+    pub profile: &'static str,
+    pub features: Vec<&'static str>,
+    pub rustc: &'static str,
+}
+
+pub fn build_info() -> BuildInfo {
+    BuildInfo {
+        version: built_info::PKG_VERSION,
+        git_sha: built_info::GIT_COMMIT_HASH,
+        git_dirty: built_info::GIT_DIRTY,
+        built_at: built_info::BUILT_TIME_UTC,
+        profile: built_info::PROFILE,
+        features: built_info::FEATURES_LOWERCASE
+            .iter()
+            .copied()
+            .filter(|f| *f != "default")
+            .collect(),
+        rustc: built_info::RUSTC_VERSION,
+    }
+}
+
+/// One `key=value` line for the startup log, e.g.
+/// `robochick-rs version=1.0.2 git_sha=2ab79b5 dirty=false built_at="..." profile=release ...`.
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "robochick-rs version={}", self.version)?;
+        if let Some(sha) = self.git_sha {
+            write!(f, " git_sha={}", &sha[..sha.len().min(7)])?;
+        }
+        if let Some(dirty) = self.git_dirty {
+            write!(f, " dirty={dirty}")?;
+        }
+        write!(
+            f,
+            " built_at=\"{}\" profile={} features={}",
+            self.built_at,
+            self.profile,
+            self.features.join(",")
+        )
+    }
+}
+
+/// `GET /version` - the build that's serving requests.
+#[utoipa::path(get, path = "/version", tag = "health", responses(
+    (status = 200, description = "Build info", body = BuildInfo),
+))]
+pub async fn version_handler() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(&build_info()).unwrap()))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::version::build_info;
+
+    #[test]
+    fn build_info_lists_enabled_features() {
+        let info = build_info();
+        let banner = info.to_string();
+
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert!(!info.features.contains(&"default"));
+        #[cfg(feature = "sqlite")]
+        assert!(info.features.contains(&"sqlite"));
+        assert!(banner.starts_with(&format!(
+            "robochick-rs version={}",
+            env!("CARGO_PKG_VERSION")
+        )));
+        assert!(banner.contains(&format!("features={}", info.features.join(","))));
+    }
+}
@@ -0,0 +1,156 @@
+use std::time::Instant;
+
+use anyhow::Result;
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+};
+use lambda_http::{Body, Response};
+use serde::Serialize;
+
+use crate::{AppState, auth, reward::mod_feeder::load_message_components};
+
+/// How long one warm-up step took, and why it failed if it did.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct WarmStep {
+    pub step: &'static str,
+    pub millis: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct WarmReport {
+    pub steps: Vec<WarmStep>,
+}
+
+impl WarmReport {
+    fn record(&mut self, step: &'static str, started: Instant, result: Result<()>) {
+        let error = result.err().map(|e| e.to_string());
+        if let Some(e) = error.as_ref() {
+            println!("Failed to warm up {step}: {e}");
+        }
+        self.steps.push(WarmStep {
+            step,
+            millis: started.elapsed().as_millis() as u64,
+            error,
+        });
+    }
+}
+
+impl AppState {
+    /// Does the slow parts of a first event ahead of it: reads the message config and the
+    /// secrets, and opens pooled connections to StreamElements and Helix. Runs on every cold
+    /// start, and from `POST /internal/warm` to start instances before stream time.
+    pub async fn warm(&self) -> WarmReport {
+        let mut report = WarmReport { steps: vec![] };
+
+        let started = Instant::now();
+        let config = load_message_components(self.state.as_ref(), &self.config_file).await;
+        report.record("config", started, config.map(|_| ()));
+
+        let started = Instant::now();
+        let jwt = self.web_client.se_jwt(&self.config).await;
+        report.record("se_jwt", started, jwt.map(|_| ()));
+
+        if let Some(tokens) = self.admin_tokens.as_ref() {
+            let started = Instant::now();
+            let read = tokens.tokens().await;
+            report.record("admin_tokens", started, read.map(|_| ()));
+        }
+
+        for (step, host) in [
+            ("streamelements", &self.config.se_api_host),
+            ("helix", &self.config.twitch_api_host),
+        ] {
+            let started = Instant::now();
+            let opened = self.web_client.open_connection(host).await;
+            report.record(step, started, opened);
+        }
+
+        let total: u64 = report.steps.iter().map(|s| s.millis).sum();
+        println!("Warmed up in {total}ms");
+        report
+    }
+}
+
+/// `POST /internal/warm` - the cold start preloading, for a schedule (e.g. an EventBridge
+/// rule) to ping shortly before stream time. Responds with how long each step took.
+#[utoipa::path(post, path = "/internal/warm", tag = "internal",
+    security(("internal_token" = [])),
+    responses(
+        (status = 200, description = "Each step and how long it took", body = Object),
+        (status = 401, description = "Missing or wrong `INTERNAL_API_TOKEN`"),
+    ),
+)]
+pub async fn warm_handler(State(state): State<AppState>, headers: HeaderMap) -> Response<Body> {
+    if !auth::internal_request_authorized(&headers, &state.config) {
+        return Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .body(Body::Empty)
+            .unwrap();
+    }
+
+    let report = state.warm().await;
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&report).unwrap()))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use mockito::Server;
+    use pretty_assertions::assert_eq;
+    use std::sync::Arc;
+
+    use crate::{app::App, client::WebClient, config::AppConfig, state::MemoryStore};
+
+    #[tokio::test]
+    async fn warming_reads_config_and_opens_connections() -> Result<()> {
+        dotenvy::from_filename(".env.test")?;
+        let mut server = Server::new_async().await;
+        let host = format!("http://{}", server.host_with_port());
+        let config = AppConfig::from_env()
+            .with_se_api_host(format!("{host}/streamelements/"))
+            .with_twitch_api_host(format!("{host}/helix"));
+        let se = server.mock("HEAD", "/streamelements/").create_async().await;
+        let helix = server
+            .mock("HEAD", "/helix")
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let app = App::builder(config)
+            .with_aws(
+                aws_config::SdkConfig::builder()
+                    .behavior_version(aws_config::BehaviorVersion::latest())
+                    .build(),
+            )
+            .with_state(Arc::new(MemoryStore::default()))
+            .with_web_client(WebClient::new(reqwest::Client::new()))
+            .build()
+            .await;
+        let report = app.state().warm().await;
+
+        se.assert_async().await;
+        helix.assert_async().await;
+        let steps: Vec<(&str, Option<String>)> = report
+            .steps
+            .into_iter()
+            .map(|s| (s.step, s.error))
+            .collect();
+        assert_eq!(
+            steps,
+            vec![
+                ("config", None),
+                ("se_jwt", None),
+                ("streamelements", None),
+                ("helix", None),
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,293 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{Result, anyhow};
+use reqwest::{Client, RequestBuilder, header::CONTENT_TYPE};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{cache::TtlCache, secrets::SecretStore};
+
+/// Google access tokens last an hour, refresh them a little before that.
+const ACCESS_TOKEN_TTL: Duration = Duration::from_secs(50 * 60);
+
+/// How long the live chat of the current broadcast is remembered, so going live is noticed
+/// quickly without looking it up for every message.
+const LIVE_CHAT_TTL: Duration = Duration::from_secs(120);
+
+/// OAuth client and refresh token for the YouTube channel, stored as JSON in a secret.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct YouTubeCredentials {
+    client_id: String,
+    client_secret: String,
+    refresh_token: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct AccessToken {
+    access_token: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ListResponse<T> {
+    #[serde(default = "Vec::new")]
+    items: Vec<T>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LiveBroadcast {
+    snippet: LiveBroadcastSnippet,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LiveBroadcastSnippet {
+    live_chat_id: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LiveChatMessage<'a> {
+    snippet: LiveChatMessageSnippet<'a>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LiveChatMessageSnippet<'a> {
+    live_chat_id: &'a str,
+    r#type: &'static str,
+    text_message_details: TextMessageDetails<'a>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct TextMessageDetails<'a> {
+    message_text: &'a str,
+}
+
+/// Posts chat messages to the live chat of the channel's active YouTube broadcast, for
+/// streamers simulcasting to YouTube.
+pub struct YouTubeChatSink {
+    client: Client,
+    secrets: Arc<SecretStore>,
+    secret_name: String,
+    api_host: String,
+    oauth_host: String,
+    access_token: TtlCache<String, String>,
+    live_chat: TtlCache<String, Option<String>>,
+}
+
+impl YouTubeChatSink {
+    pub fn new(
+        client: Client,
+        secrets: Arc<SecretStore>,
+        secret_name: String,
+        api_host: String,
+        oauth_host: String,
+    ) -> Self {
+        YouTubeChatSink {
+            client,
+            secrets,
+            secret_name,
+            api_host,
+            oauth_host,
+            access_token: TtlCache::new(ACCESS_TOKEN_TTL),
+            live_chat: TtlCache::new(LIVE_CHAT_TTL),
+        }
+    }
+
+    /// Posts `msg` to the live chat. Returns false without posting when the channel isn't
+    /// live on YouTube.
+    pub async fn say(&self, msg: &str) -> Result<bool> {
+        let token = self.access_token().await?;
+        let live_chat_id = match self.live_chat_id(&token).await? {
+            Some(id) => id,
+            None => return Ok(false),
+        };
+
+        let request = self
+            .client
+            .post(format!("{}liveChat/messages?part=snippet", self.api_host))
+            .bearer_auth(&token)
+            .json(&LiveChatMessage {
+                snippet: LiveChatMessageSnippet {
+                    live_chat_id: &live_chat_id,
+                    r#type: "textMessageEvent",
+                    text_message_details: TextMessageDetails { message_text: msg },
+                },
+            });
+
+        send::<serde_json::Value>(request).await?;
+        Ok(true)
+    }
+
+    async fn access_token(&self) -> Result<String> {
+        if let Some(token) = self.access_token.get(&self.secret_name) {
+            return Ok(token);
+        }
+
+        let credentials: YouTubeCredentials =
+            serde_json::from_str(&self.secrets.get(&self.secret_name).await?)
+                .map_err(|e| anyhow!("Invalid YouTube credentials: {e}"))?;
+        let body = serde_urlencoded::to_string([
+            ("client_id", credentials.client_id.as_str()),
+            ("client_secret", credentials.client_secret.as_str()),
+            ("refresh_token", credentials.refresh_token.as_str()),
+            ("grant_type", "refresh_token"),
+        ])?;
+        let request = self
+            .client
+            .post(format!("{}token", self.oauth_host))
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(body);
+
+        let token: AccessToken = send(request).await?;
+        self.access_token
+            .insert(self.secret_name.clone(), token.access_token.clone());
+        Ok(token.access_token)
+    }
+
+    async fn live_chat_id(&self, token: &str) -> Result<Option<String>> {
+        if let Some(id) = self.live_chat.get(&self.secret_name) {
+            return Ok(id);
+        }
+
+        let request = self
+            .client
+            .get(format!(
+                "{}liveBroadcasts?part=snippet&broadcastStatus=active&broadcastType=all",
+                self.api_host
+            ))
+            .bearer_auth(token);
+
+        let broadcasts: ListResponse<LiveBroadcast> = send(request).await?;
+        let id = broadcasts
+            .items
+            .into_iter()
+            .find_map(|b| b.snippet.live_chat_id);
+        self.live_chat.insert(self.secret_name.clone(), id.clone());
+        Ok(id)
+    }
+}
+
+async fn send<T: DeserializeOwned>(request: RequestBuilder) -> Result<T> {
+    match request.timeout(Duration::new(2, 0)).send().await {
+        Ok(resp) if resp.status().is_success() => resp
+            .json::<T>()
+            .await
+            .map_err(|e| anyhow!("Failed to deserialize YouTube API response: {e}")),
+        Ok(resp) => Err(anyhow!(
+            "YouTube API returned error with status: {}",
+            resp.status()
+        )),
+        Err(e) => Err(anyhow!(
+            "Failed to make request to YouTube API: {}",
+            e.without_url()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use anyhow::Result;
+    use aws_sdk_secretsmanager::operation::get_secret_value::GetSecretValueOutput;
+    use aws_smithy_mocks::{RuleMode, mock, mock_client};
+    use mockito::{Matcher, Server};
+    use reqwest::Client;
+
+    use crate::{secrets::SecretStore, youtube::YouTubeChatSink};
+
+    fn sink(server: &Server) -> YouTubeChatSink {
+        let rule = mock!(aws_sdk_secretsmanager::Client::get_secret_value)
+            .match_requests(|r| r.secret_id() == Some("robochick/youtube"))
+            .then_output(|| {
+                GetSecretValueOutput::builder()
+                    .secret_string(
+                        r#"{"client_id":"id","client_secret":"secret","refresh_token":"refresh"}"#,
+                    )
+                    .build()
+            });
+        let secrets = mock_client!(aws_sdk_secretsmanager, RuleMode::MatchAny, [&rule]);
+
+        YouTubeChatSink::new(
+            Client::new(),
+            Arc::new(SecretStore::new(secrets)),
+            "robochick/youtube".into(),
+            format!("http://{}/youtube/v3/", server.host_with_port()),
+            format!("http://{}/", server.host_with_port()),
+        )
+    }
+
+    #[tokio::test]
+    async fn say_posts_to_active_broadcast_chat() -> Result<()> {
+        let mut server = Server::new_async().await;
+        let token = server
+            .mock("POST", "/token")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::Regex("grant_type=refresh_token".into()),
+                Matcher::Regex("refresh_token=refresh".into()),
+            ]))
+            .with_body(r#"{"access_token":"yt-token","expires_in":3599}"#)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/youtube/v3/liveBroadcasts")
+            .match_query(Matcher::UrlEncoded(
+                "broadcastStatus".into(),
+                "active".into(),
+            ))
+            .match_header("Authorization", "Bearer yt-token")
+            .with_body(r#"{"items":[{"snippet":{"liveChatId":"chat-1"}}]}"#)
+            .create_async()
+            .await;
+        let post = server
+            .mock("POST", "/youtube/v3/liveChat/messages")
+            .match_query(Matcher::UrlEncoded("part".into(), "snippet".into()))
+            .match_body(Matcher::Json(serde_json::json!({
+                "snippet": {
+                    "liveChatId": "chat-1",
+                    "type": "textMessageEvent",
+                    "textMessageDetails": {"messageText": "Bok bok"}
+                }
+            })))
+            .with_body("{}")
+            .expect(2)
+            .create_async()
+            .await;
+
+        let sink = sink(&server);
+
+        assert!(sink.say("Bok bok").await?);
+        assert!(sink.say("Bok bok").await?);
+        token.assert_async().await;
+        post.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn say_skips_posting_when_not_live() -> Result<()> {
+        let mut server = Server::new_async().await;
+        server
+            .mock("POST", "/token")
+            .with_body(r#"{"access_token":"yt-token"}"#)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/youtube/v3/liveBroadcasts")
+            .match_query(Matcher::Any)
+            .with_body(r#"{"items":[]}"#)
+            .create_async()
+            .await;
+        let post = server
+            .mock("POST", "/youtube/v3/liveChat/messages")
+            .match_query(Matcher::Any)
+            .expect(0)
+            .create_async()
+            .await;
+
+        assert!(!sink(&server).say("Bok bok").await?);
+        post.assert_async().await;
+        Ok(())
+    }
+}